@@ -0,0 +1,107 @@
+//! Evaluation benchmarks. `cargo bench --bench evaluation` times building a
+//! long list of Ints and folding it, once and through `dup` twice, which
+//! copies the list rather than its elements.
+
+use iv::evaluation::evaluator::Evaluator;
+use iv::evaluation::host::IntOp;
+use iv::evaluation::types::Value;
+use iv::syntax::parse;
+use iv::typing::types::{OpType, Type};
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+const LEN: usize = 100_000;
+const COUNT: usize = 10_000_000;
+/// Dropping a list recurses on the Rust stack, once for every element
+const STACK_SIZE: usize = 1 << 30;
+
+const SOURCE: &str = "
+    data Bool: false, true.
+    data List a: empty, [a, List a] cons.
+    define [Int, List Int] build [List Int]:
+        dup is-zero case { true { pop }, false { dup br-2 cons br-1 1 sub build } }.
+    define [List Int, Int] sum [Int]: case { empty { }, cons { dg-2 add br-1 sum } }.
+    define [Int, Int] count [Int]:
+        dup is-zero case { true { pop }, false { 1 sub br-1 1 add br-1 count } }.
+";
+
+fn median(mut samples: Vec<Duration>) -> Duration {
+    samples.sort();
+    samples[samples.len() / 2]
+}
+
+/// The median of evaluating `main`, which leaves `expected`
+fn bench(main: &str, expected: &[i32]) -> Duration {
+    let ann = vec!["Int"; expected.len()].join(", ");
+    let source = format!("{}\ndefine [] main [{}]: {}.", SOURCE, ann, main);
+    let module = parse(&source).unwrap();
+    let samples = (0..RUNS)
+        .map(|_| {
+            let mut evaluator = Evaluator::new(&module);
+            let int = || Type::Mono("Int".to_owned());
+            let is_zero = OpType {
+                pre: vec![int()],
+                post: vec![Type::Mono("Bool".to_owned())],
+            };
+            evaluator
+                .register("is-zero", is_zero, |[n]| {
+                    let name = if matches!(n, Value::Int(0)) {
+                        "true"
+                    } else {
+                        "false"
+                    };
+                    Ok(vec![Value::constr(name, vec![])])
+                })
+                .unwrap();
+            evaluator.register_int_op("add", IntOp::Add).unwrap();
+            evaluator.register_int_op("sub", IntOp::Sub).unwrap();
+            let start = Instant::now();
+            black_box(evaluator.eval_main()).expect("benchmark module does not evaluate");
+            let elapsed = start.elapsed();
+            let ints: Vec<_> = evaluator
+                .stack
+                .iter()
+                .map(|value| match value {
+                    Value::Int(n) => *n,
+                    value => panic!("benchmark left {}", value),
+                })
+                .collect();
+            assert_eq!(ints, expected);
+            elapsed
+        })
+        .collect();
+    median(samples)
+}
+
+fn main() {
+    let sum = (1..=LEN as i32).fold(0i32, |acc, n| acc.wrapping_add(n));
+    let cases = [
+        (
+            format!("build_fold_{}", LEN),
+            format!("0 empty {} build sum", LEN),
+            vec![sum],
+        ),
+        (
+            format!("build_dup_fold_twice_{}", LEN),
+            format!("empty {} build dup 0 br-1 sum br-1 0 br-1 sum", LEN),
+            vec![sum, sum],
+        ),
+        (
+            format!("count_{}", COUNT),
+            format!("0 {} count", COUNT),
+            vec![COUNT as i32],
+        ),
+    ];
+    let runner = thread::Builder::new().stack_size(STACK_SIZE);
+    runner
+        .spawn(move || {
+            for (name, main, expected) in cases.iter() {
+                println!("{:<32} {:>12?}", name, bench(main, expected));
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,162 @@
+//! Inference benchmarks. `cargo bench` times building `Inference` and
+//! running `typecheck` separately for a set of generated modules and compares
+//! the medians against `benches/baseline.json`.
+//!
+//! - `IV_BENCH_SAVE=1` overwrites the baseline with the current numbers
+//! - `IV_BENCH_STRICT=1` exits with an error when a benchmark regressed
+
+use iv::syntax::ast::Module;
+use iv::syntax::parse;
+use iv::typing::inference::Inference;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 15;
+/// A benchmark regressed when it got slower than the baseline by this factor
+const REGRESSION_FACTOR: f64 = 1.5;
+/// Timings below this are too noisy to count as regressions
+const NOISE_FLOOR_NANOS: u128 = 10_000;
+
+/// N trivially typed ops
+fn many_ops(n: usize) -> String {
+    (0..n)
+        .map(|i| format!("define [a] dup{} [a, a]: dup.\n", i))
+        .collect()
+}
+
+/// One op with a body of M literal and addition pairs
+fn long_body(m: usize) -> String {
+    let body = " 1 nocadd".repeat(m);
+    format!(
+        "define [Int, Int] nocadd [Int]:.\ndefine [Int] sum [Int]:{}.\n",
+        body
+    )
+}
+
+/// Quotes nested D deep
+fn nested_quotes(d: usize) -> String {
+    let ty = (0..d).fold("Int".to_owned(), |t, _| format!("[][{}]", t));
+    let body = format!("{}1{}", "(".repeat(d), ")".repeat(d));
+    format!("define [] nested [{}]: {}.\n", ty, body)
+}
+
+/// A case over a K constructor enum
+fn wide_case(k: usize) -> String {
+    let constrs: Vec<_> = (0..k).map(|i| format!("c{}", i)).collect();
+    let arms: Vec<_> = (0..k).map(|i| format!("c{} {{ {} }}", i, i)).collect();
+    format!(
+        "data E: {}.\ndefine [E] toint [Int]: case {{ {} }}.\n",
+        constrs.join(", "),
+        arms.join(", ")
+    )
+}
+
+/// Ops with many type variables calling each other
+fn polymorphic(n: usize) -> String {
+    let mut src = "define [a, b, c, d] rot0 [b, c, d, a]: br-3.\n".to_owned();
+    for i in 1..n {
+        src.push_str(&format!(
+            "define [a, b, c, d] rot{} [b, c, d, a]: rot{} rot{} rot{} rot{} br-3.\n",
+            i,
+            i - 1,
+            i - 1,
+            i - 1,
+            i - 1
+        ));
+    }
+    src
+}
+
+fn median(mut samples: Vec<Duration>) -> Duration {
+    samples.sort();
+    samples[samples.len() / 2]
+}
+
+/// Medians of building the inference context and of typechecking
+fn bench(module: &Module) -> (Duration, Duration) {
+    let mut new_samples = vec![];
+    let mut check_samples = vec![];
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let inference = black_box(Inference::new(module));
+        new_samples.push(start.elapsed());
+        let start = Instant::now();
+        black_box(inference.typecheck()).expect("benchmark module does not typecheck");
+        check_samples.push(start.elapsed());
+    }
+    (median(new_samples), median(check_samples))
+}
+
+fn load_baseline(path: &Path) -> BTreeMap<String, u128> {
+    let Ok(src) = fs::read_to_string(path) else {
+        return BTreeMap::new();
+    };
+    // the baseline is a flat object of names to nanoseconds
+    src.trim()
+        .trim_start_matches('{')
+        .trim_end_matches('}')
+        .split(',')
+        .filter_map(|entry| {
+            let (name, nanos) = entry.split_once(':')?;
+            let name = name.trim().trim_matches('"').to_owned();
+            Some((name, nanos.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn save_baseline(path: &Path, results: &BTreeMap<String, u128>) {
+    let entries: Vec<_> = results
+        .iter()
+        .map(|(name, nanos)| format!("  \"{}\": {}", name, nanos))
+        .collect();
+    fs::write(path, format!("{{\n{}\n}}\n", entries.join(",\n"))).unwrap();
+}
+
+fn main() {
+    let cases = [
+        ("many_ops_500", many_ops(500)),
+        ("long_body_500", long_body(500)),
+        ("nested_quotes_50", nested_quotes(50)),
+        ("wide_case_200", wide_case(200)),
+        ("polymorphic_50", polymorphic(50)),
+    ];
+    let baseline_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/baseline.json");
+    let baseline = load_baseline(&baseline_path);
+    let mut results = BTreeMap::new();
+    let mut regressions = vec![];
+    for (name, src) in cases.iter() {
+        let module = parse(src).unwrap();
+        let (new_time, check_time) = bench(&module);
+        for (phase, time) in [("new", new_time), ("typecheck", check_time)] {
+            let key = format!("{}/{}", name, phase);
+            let nanos = time.as_nanos();
+            let comparison = match baseline.get(&key) {
+                Some(&base) if base > 0 => {
+                    let ratio = nanos as f64 / base as f64;
+                    if ratio > REGRESSION_FACTOR && nanos > NOISE_FLOOR_NANOS {
+                        regressions.push(key.clone());
+                    }
+                    format!("{:.2}x baseline", ratio)
+                }
+                _ => "no baseline".to_owned(),
+            };
+            println!("{:<32} {:>12?}  {}", key, time, comparison);
+            results.insert(key, nanos);
+        }
+    }
+    if env::var_os("IV_BENCH_SAVE").is_some() {
+        save_baseline(&baseline_path, &results);
+        println!("baseline saved to {}", baseline_path.display());
+    }
+    if !regressions.is_empty() {
+        println!("regressed: {}", regressions.join(", "));
+        if env::var_os("IV_BENCH_STRICT").is_some() {
+            process::exit(1);
+        }
+    }
+}
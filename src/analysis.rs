@@ -0,0 +1,10 @@
+pub mod call_graph;
+pub mod data_params;
+pub mod deprecated;
+pub mod leftovers;
+pub mod literals;
+pub mod mono;
+pub mod output_vars;
+pub mod termination;
+pub mod underflow;
+pub mod unreachable;
@@ -0,0 +1,322 @@
+use crate::syntax::ast::*;
+use crate::typing::prelude_types;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// A call of a user op in the body of another
+#[derive(Debug, Clone)]
+pub struct Call<'m> {
+    pub callee: &'m str,
+    pub span: &'m Span,
+    /// Inside a quote, so only made if the quote is executed
+    pub in_quote: bool,
+    /// Constructors of the case arms the call is in, outermost first, both
+    /// of a `case2` arm's patterns
+    pub arms: Vec<&'m str>,
+}
+
+/// Which user ops call which. Names are resolved the way inference does
+/// without extern ops, so a prelude op or constructor of the same name
+/// hides a user op.
+pub struct CallGraph<'m> {
+    /// In source order
+    ops: Vec<&'m str>,
+    calls: HashMap<&'m str, Vec<Call<'m>>>,
+}
+
+impl<'m> CallGraph<'m> {
+    pub fn new(module: &'m Module) -> Self {
+        let constrs: HashSet<_> = module
+            .data_defs
+            .values()
+            .flat_map(|data_def| data_def.constrs.keys())
+            .map(String::as_str)
+            .collect();
+        let is_user_op = |name: &str| {
+            prelude_types::get(name).is_none()
+                && !constrs.contains(name)
+                && module.op_defs.contains_key(name)
+        };
+        // a use of an overloaded name may run any of its definitions
+        let mut overloads: HashMap<&str, Vec<&str>> = HashMap::new();
+        for key in module.op_defs.keys().filter(|key| key.contains('#')) {
+            overloads.entry(overloaded_name(key)).or_default().push(key);
+        }
+        let mut ops = vec![];
+        let mut calls = HashMap::new();
+        for (name, op_def) in module.op_defs_in_source_order() {
+            let mut out = vec![];
+            collect(&op_def.body, false, &mut vec![], &is_user_op, &mut out);
+            let others: Vec<_> = out
+                .iter()
+                .flat_map(|call| {
+                    let keys = overloads.get(call.callee).map_or(&[][..], Vec::as_slice);
+                    keys.iter().map(|key| Call {
+                        callee: key,
+                        ..call.clone()
+                    })
+                })
+                .collect();
+            out.extend(others);
+            out.sort_by_key(|call| call.span.start);
+            ops.push(name.as_str());
+            calls.insert(name.as_str(), out);
+        }
+        CallGraph { ops, calls }
+    }
+
+    /// The user ops, in source order
+    pub fn ops(&self) -> &[&'m str] {
+        &self.ops
+    }
+
+    /// The calls in the body of `op`, in body order
+    pub fn calls(&self, op: &str) -> &[Call<'m>] {
+        self.calls.get(op).map_or(&[], Vec::as_slice)
+    }
+
+    fn callees<'g>(&'g self, op: &str, through_quotes: bool) -> impl Iterator<Item = &'m str> + 'g {
+        self.calls(op)
+            .iter()
+            .filter(move |call| through_quotes || !call.in_quote)
+            .map(|call| call.callee)
+    }
+
+    /// Strongly connected components, each after the components it calls
+    /// into and its ops in source order
+    pub fn sccs(&self, through_quotes: bool) -> Vec<Vec<&'m str>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            through_quotes,
+            index: HashMap::new(),
+            low: HashMap::new(),
+            stack: vec![],
+            on_stack: HashSet::new(),
+            sccs: vec![],
+        };
+        for op in self.ops.iter() {
+            if !tarjan.index.contains_key(op) {
+                tarjan.visit(op);
+            }
+        }
+        let position: HashMap<_, _> = self
+            .ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| (*op, i))
+            .collect();
+        for scc in tarjan.sccs.iter_mut() {
+            scc.sort_by_key(|op| position[op]);
+        }
+        tarjan.sccs
+    }
+
+    /// Whether the component is a cycle, a single op only if it calls itself
+    pub fn is_recursive(&self, scc: &[&str], through_quotes: bool) -> bool {
+        match scc {
+            [op] => self.callees(op, through_quotes).any(|callee| callee == *op),
+            _ => !scc.is_empty(),
+        }
+    }
+
+    /// The entries and the ops they call, directly or not
+    pub fn reachable(&self, entries: &[&str], through_quotes: bool) -> HashSet<&'m str> {
+        let mut reached = HashSet::new();
+        let mut queue: VecDeque<_> = self
+            .ops
+            .iter()
+            .copied()
+            .filter(|op| entries.contains(op))
+            .collect();
+        while let Some(op) = queue.pop_front() {
+            if reached.insert(op) {
+                queue.extend(self.callees(op, through_quotes));
+            }
+        }
+        reached
+    }
+
+    /// A shortest chain of calls from `from` to `to`, both included
+    pub fn path(&self, from: &'m str, to: &str, through_quotes: bool) -> Option<Vec<&'m str>> {
+        let mut came_from: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(op) = queue.pop_front() {
+            for callee in self.callees(op, through_quotes) {
+                if callee == to {
+                    let mut path = vec![callee, op];
+                    let mut at = op;
+                    while at != from {
+                        at = came_from[at];
+                        path.push(at);
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                if callee != from && !came_from.contains_key(callee) {
+                    came_from.insert(callee, op);
+                    queue.push_back(callee);
+                }
+            }
+        }
+        None
+    }
+}
+
+fn collect<'m>(
+    ops: &'m [Op],
+    in_quote: bool,
+    arms: &mut Vec<&'m str>,
+    is_user_op: &impl Fn(&str) -> bool,
+    out: &mut Vec<Call<'m>>,
+) {
+    for op in ops {
+        match op {
+            Op::Name { value, span } if is_user_op(value) => out.push(Call {
+                callee: value,
+                span,
+                in_quote,
+                arms: arms.clone(),
+            }),
+            Op::Literal { .. } | Op::Name { .. } => (),
+            Op::Quote { value, .. } => collect(value, true, arms, is_user_op, out),
+            Op::Case {
+                head_arm,
+                arms: rest,
+                ..
+            } => {
+                for arm in std::iter::once(head_arm).chain(rest) {
+                    arms.push(&arm.constr);
+                    collect(&arm.body, in_quote, arms, is_user_op, out);
+                    arms.pop();
+                }
+            }
+            Op::Case2 {
+                arms: case_arms, ..
+            } => {
+                for arm in case_arms {
+                    let before = arms.len();
+                    for pattern in arm.patterns.iter() {
+                        if let Pattern::Constr(constr) = pattern {
+                            arms.push(constr);
+                        }
+                    }
+                    collect(&arm.body, in_quote, arms, is_user_op, out);
+                    arms.truncate(before);
+                }
+            }
+            Op::List { items, .. } => {
+                for item in items {
+                    collect(item, in_quote, arms, is_user_op, out);
+                }
+            }
+        }
+    }
+}
+
+struct Tarjan<'g, 'm> {
+    graph: &'g CallGraph<'m>,
+    through_quotes: bool,
+    index: HashMap<&'m str, usize>,
+    low: HashMap<&'m str, usize>,
+    stack: Vec<&'m str>,
+    on_stack: HashSet<&'m str>,
+    sccs: Vec<Vec<&'m str>>,
+}
+
+impl<'m> Tarjan<'_, 'm> {
+    fn visit(&mut self, op: &'m str) {
+        let index = self.index.len();
+        self.index.insert(op, index);
+        self.low.insert(op, index);
+        self.stack.push(op);
+        self.on_stack.insert(op);
+        let callees: Vec<_> = self.graph.callees(op, self.through_quotes).collect();
+        for callee in callees {
+            if !self.index.contains_key(callee) {
+                self.visit(callee);
+                self.low.insert(op, self.low[op].min(self.low[callee]));
+            } else if self.on_stack.contains(callee) {
+                self.low.insert(op, self.low[op].min(self.index[callee]));
+            }
+        }
+        if self.low[op] == self.index[op] {
+            let mut scc = vec![];
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.remove(member);
+                scc.push(member);
+                if member == op {
+                    break;
+                }
+            }
+            self.sccs.push(scc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    const SOURCE: &str = "
+        data Nat: zero, [Nat] suc.
+        define [] a []: b (c) exec-0-0.
+        define [] b []: a.
+        define [] c []: c.
+        define [] d []: a dup suc pop pop.
+        define [] dup []: d.
+        define [Nat] e []: case { zero { d }, suc { case2 { suc _ { e } } } }.
+        define [] suc []: e.
+    ";
+
+    #[test]
+    fn components_and_paths() {
+        let module = parse(SOURCE).unwrap();
+        let graph = CallGraph::new(&module);
+        assert_eq!(graph.ops(), ["a", "b", "c", "d", "dup", "e", "suc"]);
+        assert_eq!(
+            graph.sccs(false),
+            vec![
+                vec!["a", "b"],
+                vec!["c"],
+                vec!["d"],
+                vec!["dup"],
+                vec!["e"],
+                vec!["suc"]
+            ]
+        );
+        assert_eq!(
+            graph.sccs(true),
+            vec![
+                vec!["c"],
+                vec!["a", "b"],
+                vec!["d"],
+                vec!["dup"],
+                vec!["e"],
+                vec!["suc"]
+            ]
+        );
+        assert!(graph.is_recursive(&["c"], false));
+        assert!(!graph.is_recursive(&["d"], false));
+        assert_eq!(graph.path("d", "b", false), Some(vec!["d", "a", "b"]));
+        assert_eq!(graph.path("a", "a", false), Some(vec!["a", "b", "a"]));
+        assert_eq!(graph.path("a", "c", false), None);
+        assert_eq!(graph.path("a", "c", true), Some(vec!["a", "c"]));
+    }
+
+    #[test]
+    fn calls_know_where_they_are() {
+        let module = parse(SOURCE).unwrap();
+        let graph = CallGraph::new(&module);
+        let calls = graph.calls("a");
+        assert_eq!((calls[0].callee, calls[0].in_quote), ("b", false));
+        assert_eq!((calls[1].callee, calls[1].in_quote), ("c", true));
+        // `dup` is the prelude op and `suc` the constructor
+        let calls: Vec<_> = graph.calls("d").iter().map(|call| call.callee).collect();
+        assert_eq!(calls, ["a"]);
+        let calls = graph.calls("e");
+        assert_eq!(calls[0].arms, ["zero"]);
+        assert_eq!(calls[1].arms, ["suc", "suc"]);
+        assert!(calls.iter().all(|call| call.callee != "suc"));
+    }
+}
@@ -0,0 +1,189 @@
+//! How data definitions use their parameters. A parameter no constructor
+//! field mentions is phantom, which is usually a typo and is warned about
+//! unless a `//@ phantom:` pragma acknowledges it. A parameter mentioned
+//! only inside quote types gets a note, its values hold ops taking or
+//! giving one rather than a value of it.
+
+use crate::syntax::ast::*;
+use crate::typing::types::Type;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamUsage {
+    /// No constructor field mentions the parameter
+    Phantom,
+    /// Only quote types in the fields mention the parameter
+    OnlyInQuotes,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamWarning {
+    pub data: String,
+    pub param: String,
+    /// The parameter in the definition's head
+    pub span: Span,
+    pub usage: ParamUsage,
+}
+
+impl ParamWarning {
+    /// Whether this is a note rather than a warning
+    pub fn is_informational(&self) -> bool {
+        self.usage == ParamUsage::OnlyInQuotes
+    }
+}
+
+impl fmt::Display for ParamWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.usage {
+            ParamUsage::Phantom => write!(
+                f,
+                "parameter `{}` of `{}` is not used by any constructor\n\
+                 add `//@ phantom: {}` before the definition if that is intended",
+                self.param, self.data, self.param
+            ),
+            ParamUsage::OnlyInQuotes => write!(
+                f,
+                "parameter `{}` of `{}` is only used inside quote types, its values hold ops \
+                 over `{}` and never a value of it",
+                self.param, self.data, self.param
+            ),
+        }
+    }
+}
+
+/// The type variables of `t`, those outside quote types and those inside
+fn variables<'t>(
+    t: &'t Type,
+    in_quote: bool,
+    outside: &mut HashSet<&'t str>,
+    inside: &mut HashSet<&'t str>,
+) {
+    match t {
+        Type::Poly(name) if in_quote => {
+            inside.insert(name);
+        }
+        Type::Poly(name) => {
+            outside.insert(name);
+        }
+        Type::Mono(_) | Type::Dyn => (),
+        Type::App(f, arg) => {
+            variables(f, in_quote, outside, inside);
+            variables(arg, in_quote, outside, inside);
+        }
+        Type::Op(op_type) => {
+            for t in op_type.pre.iter().chain(&op_type.post) {
+                variables(t, true, outside, inside);
+            }
+        }
+    }
+}
+
+/// The parameters of every data definition that no field uses, or that
+/// only quote types in the fields use, in source order
+pub fn check_data_params(module: &Module) -> Vec<ParamWarning> {
+    let mut warnings = vec![];
+    for (data_name, data_def) in module.data_defs_in_source_order() {
+        let mut outside = HashSet::new();
+        let mut inside = HashSet::new();
+        for constr in data_def.constrs.values() {
+            for t in constr.params.iter() {
+                variables(t, false, &mut outside, &mut inside);
+            }
+        }
+        let acknowledged: HashSet<_> = data_def
+            .pragmas
+            .iter()
+            .flat_map(|pragma| match &pragma.kind {
+                PragmaKind::Phantom(params) => params.as_slice(),
+                _ => &[],
+            })
+            .map(String::as_str)
+            .collect();
+        for (param, span) in data_def.params.iter().zip(&data_def.param_spans) {
+            let usage = if outside.contains(param.as_str()) {
+                continue;
+            } else if inside.contains(param.as_str()) {
+                ParamUsage::OnlyInQuotes
+            } else if acknowledged.contains(param.as_str()) {
+                continue;
+            } else {
+                ParamUsage::Phantom
+            };
+            warnings.push(ParamWarning {
+                data: data_name.clone(),
+                param: param.clone(),
+                span: span.clone(),
+                usage,
+            });
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    fn warnings(source: &str) -> Vec<(String, ParamUsage, String)> {
+        let module = parse(source).unwrap();
+        check_data_params(&module)
+            .into_iter()
+            .map(|w| {
+                let at = source[w.span.start..w.span.end].to_owned();
+                (w.param, w.usage, at)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn phantom_parameters() {
+        assert_eq!(
+            warnings("data Box a b: [a] box."),
+            [("b".to_owned(), ParamUsage::Phantom, "b".to_owned())]
+        );
+        assert_eq!(
+            warnings("data Tagged t: untagged.\ndata Pair a b: [b] left, [a] right."),
+            [("t".to_owned(), ParamUsage::Phantom, "t".to_owned())]
+        );
+        assert!(warnings("data Maybe a: nothing, [a] just.").is_empty());
+        assert!(warnings("data Nested a: [Maybe (Maybe a)] nested.").is_empty());
+    }
+
+    #[test]
+    fn acknowledged_phantoms() {
+        let source = "
+            //@ phantom: unit
+            data Meters unit: [Int] meters.
+            //@ phantom: a
+            data Handler a b: [[b -> Int]] handler.
+            ";
+        // only the phantom parameter is silenced, not the note
+        assert_eq!(
+            warnings(source),
+            [("b".to_owned(), ParamUsage::OnlyInQuotes, "b".to_owned())]
+        );
+        let module = parse(source).unwrap();
+        let pragmas = &module.data_defs["Meters"].pragmas;
+        assert!(
+            matches!(&pragmas[..], [Pragma { kind: PragmaKind::Phantom(params), .. }] if params == &["unit"])
+        );
+        // the pragma belongs before `data`
+        assert!(parse("//@ phantom: a\ndefine [] f []:.").is_err());
+    }
+
+    #[test]
+    fn parameters_only_in_quotes() {
+        let source = "data Cont r a: [[[a -> r] -> r]] cont.";
+        assert_eq!(
+            warnings(source),
+            [
+                ("r".to_owned(), ParamUsage::OnlyInQuotes, "r".to_owned()),
+                ("a".to_owned(), ParamUsage::OnlyInQuotes, "a".to_owned())
+            ]
+        );
+        // a use outside a quote anywhere is enough
+        assert!(warnings("data Both a: [[a -> a]] quoted, [a] plain.").is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+//! Uses of ops and constructors marked `#[deprecated]`, the constructors of
+//! a deprecated data definition being deprecated with it. Calling the op or
+//! constructor and matching on the constructor are uses, an op calling
+//! itself is not. A definition with `#[allow(deprecated)]` uses what it
+//! likes. With strict mode the uses are errors instead, see
+//! `StrictOptions::reject_deprecated`.
+
+use crate::syntax::ast::*;
+use crate::typing::env::{Env, Provenance};
+use crate::typing::inference::InferenceErrorMessage;
+use crate::typing::resolve::Resolution;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::once;
+
+/// What the `#[deprecated]` attribute of a name says
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    /// Usually what to use instead
+    pub message: Option<String>,
+    /// The name in its definition
+    pub declared: Span,
+}
+
+/// The deprecated ops and constructors by name, kept apart since a
+/// constructor can have the name of an op
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Deprecations {
+    ops: HashMap<String, Deprecation>,
+    constrs: HashMap<String, Deprecation>,
+}
+
+impl Deprecations {
+    /// Adds the op if it is deprecated, forgets the op it replaces
+    pub fn insert_op(&mut self, name: &str, op_def: &OpDef) {
+        match deprecation(&op_def.attributes, &op_def.name_span) {
+            Some(deprecation) => self.ops.insert(name.to_owned(), deprecation),
+            None => self.ops.remove(name),
+        };
+    }
+
+    /// Adds the constructor if it or its data definition is deprecated, its
+    /// own attribute saying more than the definition's
+    pub fn insert_constr(&mut self, name: &str, data_def: &DataDef, constr: &DataConstr) {
+        let deprecation = deprecation(&constr.attributes, &constr.name_span)
+            .or_else(|| deprecation(&data_def.attributes, &constr.name_span));
+        if let Some(deprecation) = deprecation {
+            self.constrs.insert(name.to_owned(), deprecation);
+        }
+    }
+
+    /// The uses of deprecated names in the body of the definition `user`,
+    /// in the order they come in the body. Names are looked up in `env`,
+    /// a name that a constant or the prelude shadows is not a use.
+    pub fn uses(&self, env: &Env, user: &str, body: &[Op]) -> Vec<DeprecationWarning> {
+        let mut uses = vec![];
+        if !self.ops.is_empty() || !self.constrs.is_empty() {
+            self.collect_uses(env, user, body, &mut uses);
+        }
+        uses.sort_by_key(|warning| warning.span.start);
+        uses
+    }
+
+    fn collect_uses(&self, env: &Env, user: &str, ops: &[Op], uses: &mut Vec<DeprecationWarning>) {
+        let mut used = |name: &str, span: &Span, deprecation: Option<&Deprecation>| {
+            if let Some(deprecation) = deprecation {
+                uses.push(DeprecationWarning {
+                    name: name.to_owned(),
+                    span: span.clone(),
+                    message: deprecation.message.clone(),
+                    declared: deprecation.declared.clone(),
+                });
+            }
+        };
+        let mut bodies = vec![];
+        for op in ops {
+            match op {
+                Op::Literal { .. } => (),
+                Op::Name { value, span } => {
+                    let deprecation = match env.lookup(value).map(|b| b.provenance) {
+                        Some(Provenance::User) if value != user => self.ops.get(value),
+                        Some(Provenance::Constructor) => self.constrs.get(value),
+                        _ => None,
+                    };
+                    used(value, span, deprecation);
+                }
+                Op::Quote { value, .. } => bodies.push(value),
+                Op::Case { head_arm, arms, .. } => {
+                    for arm in once(head_arm).chain(arms) {
+                        used(&arm.constr, &arm.constr_span, self.constrs.get(&arm.constr));
+                        bodies.push(&arm.body);
+                    }
+                }
+                Op::Case2 { arms, .. } => {
+                    for arm in arms {
+                        for (pattern, span) in arm.patterns.iter().zip(&arm.pattern_spans) {
+                            if let Pattern::Constr(constr) = pattern {
+                                used(constr, span, self.constrs.get(constr));
+                            }
+                        }
+                        bodies.push(&arm.body);
+                    }
+                }
+                Op::List { items, .. } => bodies.extend(items),
+            }
+        }
+        for body in bodies {
+            self.collect_uses(env, user, body, uses);
+        }
+    }
+}
+
+fn deprecation(attributes: &[Attribute], declared: &Span) -> Option<Deprecation> {
+    let attribute = attributes.iter().find(|a| a.name == "deprecated")?;
+    Some(Deprecation {
+        message: attribute.message.clone(),
+        declared: declared.clone(),
+    })
+}
+
+/// Whether the op has `#[allow(deprecated)]`
+pub fn allows_deprecated(op_def: &OpDef) -> bool {
+    op_def
+        .attributes
+        .iter()
+        .any(|a| a.name == "allow" && a.args.iter().any(|arg| arg == "deprecated"))
+}
+
+#[derive(Debug, Clone)]
+pub struct DeprecationWarning {
+    /// The deprecated op or constructor
+    pub name: String,
+    /// The use
+    pub span: Span,
+    pub message: Option<String>,
+    /// The name in its definition
+    pub declared: Span,
+}
+
+impl fmt::Display for DeprecationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let error = InferenceErrorMessage::Deprecated {
+            name: self.name.clone(),
+            message: self.message.clone(),
+        };
+        write!(f, "{}", error)
+    }
+}
+
+/// Every use of a deprecated name by the ops and constants of the module,
+/// in source order
+pub fn check_deprecated(module: &Module) -> Vec<DeprecationWarning> {
+    let resolution = Resolution::new(module);
+    let ops = module
+        .op_defs_in_source_order()
+        .into_iter()
+        .filter(|(_, op_def)| !allows_deprecated(op_def))
+        .map(|(name, op_def)| (name, &op_def.body));
+    let consts = module
+        .const_defs_in_source_order()
+        .into_iter()
+        .map(|(name, const_def)| (name, &const_def.body));
+    let mut warnings: Vec<_> = ops
+        .chain(consts)
+        .flat_map(|(name, body)| resolution.deprecations.uses(&resolution.env, name, body))
+        .collect();
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    fn uses(source: &str) -> Vec<(String, String)> {
+        check_deprecated(&parse(source).unwrap())
+            .into_iter()
+            .map(|w| {
+                let (line, _) = crate::diagnostics::line_col(source, w.span.start);
+                (w.name.clone(), format!("{}: {}", line, w))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_kind_of_use_is_found() {
+        let source = "
+#[deprecated(\"use count\")]
+define [] size [Int]: size pop 1.
+data Res: #[deprecated(\"use fine\")] oops, fine.
+#[deprecated]
+data Old: old.
+define [] calls [Int]: size.
+define [] makes [Res]: oops.
+define [Res] matches [Int]: case { oops { 0 }, fine { 1 } }.
+define [Res, Res] pairs [Int]: case2 { fine fine { 1 }, _ _ { pop pop 0 } }.
+define [Res, Res] both [Int]: case2 { oops _ { pop 0 }, _ _ { pop pop 1 } }.
+define [] quoted [[][Old]]: (old).
+const kept [Res]: oops.
+#[allow(deprecated)]
+define [] allowed [Old, Res]: oops old.
+";
+        let expected = [
+            ("size", "7: `size` is deprecated: use count"),
+            ("oops", "8: `oops` is deprecated: use fine"),
+            ("oops", "9: `oops` is deprecated: use fine"),
+            ("oops", "11: `oops` is deprecated: use fine"),
+            ("old", "12: `old` is deprecated"),
+            ("oops", "13: `oops` is deprecated: use fine"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(name, w)| (name.to_string(), w.to_string()))
+            .collect();
+        assert_eq!(uses(source), expected);
+    }
+
+    #[test]
+    fn shadowed_names_are_not_uses() {
+        let source = "
+#[deprecated(\"use pop\")]
+define [a] dup []: pop.
+#[deprecated]
+define [] gone [Int]: 1.
+const gone [Int]: 2.
+define [a] f [a, a, Int]: dup gone.
+";
+        assert!(uses(source).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+//! `#[unchecked]` ops annotated to take and leave nothing, as `[] name []`
+//! or `name --`, whose bodies leave values on the stack. Checking rejects
+//! such a body of a checked op, the annotation has to match it exactly,
+//! but nothing looks at an unchecked body unless asked.
+
+use crate::syntax::ast::*;
+use crate::typing::inference::Inference;
+use crate::typing::types::Type;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct LeftoverWarning {
+    pub op: String,
+    /// The name of the op in its definition
+    pub span: Span,
+    /// What the body leaves beyond the values it passes through, topmost
+    /// first
+    pub leftover: Vec<Type>,
+}
+
+impl fmt::Display for LeftoverWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let leftover: Vec<_> = self.leftover.iter().map(|t| format!("`{}`", t)).collect();
+        write!(
+            f,
+            "`{}` is annotated to take and leave nothing, but its body leaves {}",
+            self.op,
+            leftover.join(", ")
+        )
+    }
+}
+
+/// The unchecked ops with an empty annotation whose bodies leave values,
+/// in source order. A body that does not infer on its own is left to
+/// whatever runs it.
+pub fn check_leftovers(inference: &Inference) -> Vec<LeftoverWarning> {
+    inference
+        .module()
+        .op_defs_in_source_order()
+        .into_iter()
+        .filter(|(_, op_def)| op_def.ann.is_empty() && op_def.has_attribute("unchecked"))
+        .filter_map(|(op, op_def)| {
+            let inferred = inference.infer_body(op)?.ok()?.unaugmented().canonical();
+            (!inferred.post.is_empty()).then(|| LeftoverWarning {
+                op: op.clone(),
+                span: op_def.name_span.clone(),
+                leftover: inferred.post,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    #[test]
+    fn unchecked_bodies_leaving_values() {
+        let source = "
+            data Bool: true, false.
+            #[unchecked]
+            define [] junk []: 1 true.
+            #[unchecked]
+            define shuffled --: br-1 br-1 1 pop.
+            #[unchecked]
+            define pops --: pop (1).
+            #[unchecked]
+            define [Int] kept [Int, Int]: 1.
+            define [] checked []: 1.
+        ";
+        let module = parse(source).unwrap();
+        let warnings: Vec<_> = check_leftovers(&Inference::new(&module))
+            .iter()
+            .map(|warning| {
+                (
+                    &source[warning.span.start..warning.span.end],
+                    warning.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (
+                    "junk",
+                    "`junk` is annotated to take and leave nothing, but its body leaves \
+                     `Bool`, `Int`"
+                        .to_owned()
+                ),
+                (
+                    "pops",
+                    "`pops` is annotated to take and leave nothing, but its body leaves \
+                     `[][Int]`"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+}
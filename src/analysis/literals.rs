@@ -0,0 +1,85 @@
+//! Integer literals written with leading zeros, like `010`. The language
+//! has no octal literals, `010` is ten, which someone used to C reading it
+//! as eight would not expect. The parser keeps only the value, so this
+//! looks at the tokens of the source.
+
+use crate::syntax::ast::Span;
+use crate::syntax::highlight::{lex, LiteralKind, TokenKind};
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct LeadingZeroWarning {
+    /// The literal as written
+    pub literal: String,
+    pub span: Span,
+}
+
+impl LeadingZeroWarning {
+    /// The code the diagnostic of the warning has
+    pub const CODE: &'static str = "LeadingZeros";
+}
+
+impl fmt::Display for LeadingZeroWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.literal.trim_start_matches(['+', '-']);
+        let sign = &self.literal[..self.literal.len() - digits.len()];
+        let decimal = digits.trim_start_matches('0');
+        write!(
+            f,
+            "`{}` is the decimal `{}{}`, leading zeros do not make a literal octal",
+            self.literal,
+            sign,
+            if decimal.is_empty() { "0" } else { decimal }
+        )
+    }
+}
+
+/// The integer literals of the source that start with a zero followed by
+/// more digits, in source order
+pub fn check_leading_zeros(source: &str) -> Vec<LeadingZeroWarning> {
+    lex(source)
+        .into_iter()
+        .filter(|token| token.kind == TokenKind::Literal(LiteralKind::Int))
+        .filter_map(|token| {
+            let literal = &source[token.span.start..token.span.end];
+            let digits = literal.trim_start_matches(['+', '-']);
+            (digits.len() > 1 && digits.starts_with('0')).then(|| LeadingZeroWarning {
+                literal: literal.to_owned(),
+                span: token.span,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_with_leading_zeros() {
+        let source = "define [] f [Int, Int, Int, Int, Int]: 0 10 010 -007 +00.";
+        let warnings: Vec<_> = check_leading_zeros(source)
+            .iter()
+            .map(|w| (source[w.span.start..w.span.end].to_owned(), w.to_string()))
+            .collect();
+        let expected = [
+            (
+                "010",
+                "`010` is the decimal `10`, leading zeros do not make a literal octal",
+            ),
+            (
+                "-007",
+                "`-007` is the decimal `-7`, leading zeros do not make a literal octal",
+            ),
+            (
+                "+00",
+                "`+00` is the decimal `+0`, leading zeros do not make a literal octal",
+            ),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(literal, w)| (literal.to_string(), w.to_string()))
+            .collect();
+        assert_eq!(warnings, expected);
+    }
+}
@@ -0,0 +1,181 @@
+//! Which instantiations of its polymorphic ops a program uses, so that a
+//! backend can emit a specialized copy of an op for each concrete type it
+//! is called at instead of working on dynamic values. The ops are followed
+//! from the entry, each at the types its caller uses it at. An op called
+//! at too many types, or at a type left with variables, is only run on
+//! dynamic values and followed at its annotation. Following an op again at
+//! a type it was followed at stops recursion, polymorphic recursion at
+//! ever larger types stops at the limit.
+
+use crate::typing::inference::{Inference, InferenceError, Typeable};
+use crate::typing::types::OpType;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MonoPlan {
+    /// The name of the copy of each polymorphic op specialized to each
+    /// concrete type it is called at
+    pub specializations: BTreeMap<(String, OpType), String>,
+    /// The polymorphic ops that also need their dynamic version
+    pub dynamic: BTreeSet<String>,
+}
+
+impl MonoPlan {
+    /// What a call of the op at the type calls, its specialized copy or
+    /// the op itself
+    pub fn name<'p>(&'p self, op: &'p str, at: &OpType) -> &'p str {
+        self.specializations
+            .get(&(op.to_owned(), at.clone()))
+            .map_or(op, String::as_str)
+    }
+}
+
+/// The plan of the ops reachable from `entry`, specializing each op to at
+/// most `limit` types. `None` when no op is named `entry`.
+pub fn plan_mono(
+    inference: &Inference,
+    entry: &str,
+    limit: usize,
+) -> Option<Result<MonoPlan, InferenceError>> {
+    let module = inference.module();
+    let mut plan = MonoPlan::default();
+    let mut queue = VecDeque::from([(entry.to_owned(), module.op_defs.get(entry)?.ann.clone())]);
+    let mut followed = HashSet::new();
+    let mut copies = BTreeMap::<&str, usize>::new();
+    while let Some((op, at)) = queue.pop_front() {
+        let op_def = &module.op_defs[&op];
+        // an unchecked body is not known to have the annotation's type
+        if op_def.has_attribute("unchecked") || !followed.insert((op.clone(), at.clone())) {
+            continue;
+        }
+        let calls = match inference.calls_at(&op, &at)? {
+            Ok(calls) => calls,
+            Err(err) => return Some(Err(err)),
+        };
+        for call in calls {
+            let (name, callee) = module.op_defs.get_key_value(&call.callee)?;
+            if callee.ann.ftv().is_empty() {
+                queue.push_back((call.callee, callee.ann.clone()));
+                continue;
+            }
+            let key = (call.callee.clone(), call.optype.clone());
+            if plan.specializations.contains_key(&key) {
+                continue;
+            }
+            let count = copies.entry(name).or_default();
+            if call.optype.ftv().is_empty() && *count < limit {
+                *count += 1;
+                let mut specialized = format!("{}-mono{}", name, count);
+                while module.op_defs.contains_key(&specialized) {
+                    specialized.push('-');
+                }
+                plan.specializations.insert(key, specialized);
+                queue.push_back((call.callee, call.optype));
+            } else {
+                plan.dynamic.insert(call.callee.clone());
+                queue.push_back((call.callee, callee.ann.clone()));
+            }
+        }
+    }
+    Some(Ok(plan))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+    use crate::syntax::parse_optype;
+
+    const SOURCE: &str = "
+        data Nat: zero, [Nat] suc.
+        data Bool: true, false.
+        data List a: empty, [a, List a] cons.
+        define [[a][b], List a] map [List b]:
+          br-1
+          case { empty { pop empty },
+                 cons { br-2 dg-1 dup br-2 map br-2 exec-1-1 cons },
+               }.
+        define [Nat] is-zero [Bool]: case { zero { true }, suc { pop false } }.
+        define [List a] length [Nat]: case { empty { zero }, cons { pop length suc } }.
+        define [a] single [List a]: empty br-1 cons.
+        define [] nats [List Nat]: zero single zero suc cons.
+        define [] main [Nat, List Bool]:
+          nats (suc) map dup (is-zero) map br-1 length.
+    ";
+
+    fn ty(source: &str) -> OpType {
+        parse_optype(source).unwrap()
+    }
+
+    #[test]
+    fn polymorphic_ops_get_a_copy_per_type() {
+        let module = parse(SOURCE).unwrap();
+        let inference = Inference::new(&module);
+        let plan = plan_mono(&inference, "main", 4).unwrap().unwrap();
+        let specializations: Vec<_> = plan
+            .specializations
+            .iter()
+            .map(|((op, at), name)| (op.as_str(), at.to_string(), name.as_str()))
+            .collect();
+        assert_eq!(
+            specializations,
+            [
+                ("length", "[List Nat][Nat]".to_owned(), "length-mono1"),
+                (
+                    "map",
+                    "[[Nat][Bool], List Nat][List Bool]".to_owned(),
+                    "map-mono2"
+                ),
+                (
+                    "map",
+                    "[[Nat][Nat], List Nat][List Nat]".to_owned(),
+                    "map-mono1"
+                ),
+                ("single", "[Nat][List Nat]".to_owned(), "single-mono1"),
+            ]
+        );
+        assert!(plan.dynamic.is_empty());
+        assert_eq!(
+            plan.name("map", &ty("[[Nat][Bool], List Nat][List Bool]")),
+            "map-mono2"
+        );
+        assert_eq!(plan.name("is-zero", &ty("[Nat][Bool]")), "is-zero");
+
+        // past the limit the second type of `map` is left dynamic
+        let plan = plan_mono(&inference, "main", 1).unwrap().unwrap();
+        assert_eq!(plan.dynamic.iter().collect::<Vec<_>>(), [&"map".to_owned()]);
+        assert_eq!(plan.specializations.len(), 3);
+        assert!(plan_mono(&inference, "nope", 1).is_none());
+    }
+
+    #[test]
+    fn recursion_at_growing_types_stops_at_the_limit() {
+        let source = "
+            data List a: empty, [a, List a] cons.
+            data Bool: true, false.
+            define [a, Bool] nest [Bool]:
+              br-1 case { true { empty br-1 cons false br-1 nest }, false { pop true } }.
+            define [] main [Bool]: true 1 nest.
+        ";
+        let module = parse(source).unwrap();
+        let inference = Inference::new(&module);
+        let plan = plan_mono(&inference, "main", 3).unwrap().unwrap();
+        let types: Vec<_> = plan
+            .specializations
+            .keys()
+            .map(|(_, at)| at.to_string())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                "[Int, Bool][Bool]",
+                "[List Int, Bool][Bool]",
+                "[List (List Int), Bool][Bool]",
+            ]
+        );
+        assert_eq!(
+            plan.dynamic.iter().collect::<Vec<_>>(),
+            [&"nest".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Annotations giving a value of a type variable that they take no value
+//! of, which no op that returns can have. The checker accepts them for
+//! bodies that never return, or that underflow so that the variable gets
+//! tied to a value the op was never given. With strict mode they are
+//! errors instead, see `StrictOptions::reject_output_only_vars`.
+//! Constructors are not checked, the variables of their type come from
+//! their fields.
+
+use crate::syntax::ast::*;
+use crate::typing::inference::InferenceErrorMessage;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct OutputVarWarning {
+    pub op: String,
+    pub var: String,
+    /// The value of the post stack that is the variable
+    pub span: Span,
+}
+
+impl fmt::Display for OutputVarWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let error = InferenceErrorMessage::OutputOnlyVar {
+            op: self.op.clone(),
+            var: self.var.clone(),
+        };
+        write!(f, "{}", error)
+    }
+}
+
+/// The variables of each op annotation that `OpType::output_only_vars`
+/// gives, in source order
+pub fn check_output_vars(module: &Module) -> Vec<OutputVarWarning> {
+    module
+        .op_defs_in_source_order()
+        .into_iter()
+        .flat_map(|(op, op_def)| {
+            op_def
+                .ann
+                .output_only_vars()
+                .into_iter()
+                .map(move |(i, var)| OutputVarWarning {
+                    op: op.to_owned(),
+                    var: var.to_owned(),
+                    span: op_def.post_spans[i].clone(),
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    #[test]
+    fn variables_made_from_nothing() {
+        let source = "
+            data Maybe a: none, [a] some.
+            define [] nothing [Maybe a]: none.
+            define [Int] cast [a, Int, b]: cast.
+            #[unchecked]
+            define [] conjure [a, a]:.
+            define [Maybe a] unwrap [a]: case { none { unwrap }, some { } }.
+        ";
+        let warnings: Vec<_> = check_output_vars(&parse(source).unwrap())
+            .into_iter()
+            .map(|w| {
+                let at = source[w.span.start..].starts_with(&w.var);
+                (w.op, w.var, at)
+            })
+            .collect();
+        let warning = |op: &str, var: &str| (op.to_owned(), var.to_owned(), true);
+        assert_eq!(
+            warnings,
+            [
+                warning("cast", "a"),
+                warning("cast", "b"),
+                warning("conjure", "a")
+            ]
+        );
+    }
+}
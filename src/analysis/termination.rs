@@ -0,0 +1,219 @@
+//! A heuristic check for recursion that never gets closer to an end. A
+//! recursive call is trusted only inside a case arm of a constructor with a
+//! recursive field, as in structural recursion, the arm has taken a layer
+//! off some value. That the call recurses on the smaller value is not
+//! checked. Calls inside quotes are not followed, recursion through
+//! executed quotes or `fix` goes unnoticed.
+
+use super::call_graph::CallGraph;
+use crate::syntax::ast::*;
+use crate::typing::types::Type;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A recursive call not inside an arm taking apart a recursive value
+#[derive(Debug, Clone)]
+pub struct TerminationWarning {
+    /// The op making the call
+    pub op: String,
+    pub span: Span,
+    /// The ops of the cycle, from `op` around back to it
+    pub cycle: Vec<String>,
+}
+
+impl fmt::Display for TerminationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` may not terminate, this call recurses outside of any case arm taking apart \
+             a recursive value\ncycle: {}\n(a heuristic, structural recursion is assumed to be \
+             on the value taken apart)",
+            self.op,
+            self.cycle.join(" -> ")
+        )
+    }
+}
+
+/// Constructors with a field that can hold a value of their own data type
+fn recursive_constructors(module: &Module) -> HashSet<&str> {
+    fn mentioned<'t>(ty: &'t Type, out: &mut Vec<&'t str>) {
+        match ty {
+            Type::Mono(name) => out.push(name),
+            Type::App(f, arg) => {
+                mentioned(f, out);
+                mentioned(arg, out);
+            }
+            Type::Op(op_type) => op_type
+                .pre
+                .iter()
+                .chain(&op_type.post)
+                .for_each(|t| mentioned(t, out)),
+            Type::Poly(_) | Type::Dyn => (),
+        }
+    }
+    // the data types mentioned in the fields of each constructor
+    let mut fields: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut data_edges: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for (data_name, data_def) in module.data_defs.iter() {
+        for (constr_name, constr) in data_def.constrs.iter() {
+            let mut out = vec![];
+            constr.params.iter().for_each(|t| mentioned(t, &mut out));
+            data_edges
+                .entry(data_name)
+                .or_default()
+                .extend(out.iter().copied());
+            fields.insert(constr_name, out);
+        }
+    }
+    let reaches = |from: &str, to: &str| {
+        let mut seen = HashSet::new();
+        let mut todo = vec![from];
+        while let Some(data) = todo.pop() {
+            if data == to {
+                return true;
+            }
+            if seen.insert(data) {
+                todo.extend(data_edges.get(data).into_iter().flatten().copied());
+            }
+        }
+        false
+    };
+    let mut recursive = HashSet::new();
+    for (data_name, data_def) in module.data_defs.iter() {
+        for constr_name in data_def.constrs.keys() {
+            if fields[constr_name.as_str()]
+                .iter()
+                .any(|field| reaches(field, data_name))
+            {
+                recursive.insert(constr_name.as_str());
+            }
+        }
+    }
+    recursive
+}
+
+/// Recursive calls that nothing visibly makes smaller, in source order
+pub fn check_termination(module: &Module, graph: &CallGraph) -> Vec<TerminationWarning> {
+    let recursive = recursive_constructors(module);
+    let mut warnings = vec![];
+    for scc in graph.sccs(false) {
+        if !graph.is_recursive(&scc, false) {
+            continue;
+        }
+        for op in scc.iter() {
+            for call in graph.calls(op) {
+                let guarded = call.arms.iter().any(|constr| recursive.contains(constr));
+                if call.in_quote || guarded || !scc.contains(&call.callee) {
+                    continue;
+                }
+                let path = graph.path(call.callee, op, false).unwrap_or_default();
+                let cycle = std::iter::once(*op)
+                    .chain(if call.callee == *op { vec![*op] } else { path })
+                    .map(str::to_owned)
+                    .collect();
+                warnings.push(TerminationWarning {
+                    op: op.to_string(),
+                    span: call.span.clone(),
+                    cycle,
+                });
+            }
+        }
+    }
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    fn warnings(source: &str) -> Vec<(String, String, Vec<String>)> {
+        let module = parse(source).unwrap();
+        check_termination(&module, &CallGraph::new(&module))
+            .into_iter()
+            .map(|w| (w.op, source[w.span.start..w.span.end].to_owned(), w.cycle))
+            .collect()
+    }
+
+    fn cycle(ops: &[&str]) -> Vec<String> {
+        ops.iter().map(|op| op.to_string()).collect()
+    }
+
+    #[test]
+    fn structural_recursion_is_fine() {
+        let factorial = "
+            data Nat: zero, [Nat] suc.
+            define [Nat, Nat] add [Nat]: case { zero { }, suc { add suc } }.
+            define [Nat, Nat] mul [Nat]:
+                case { zero { pop zero }, suc { br-1 dup dg-2 mul add } }.
+            define [Nat] fact [Nat]:
+                dup case { zero { pop zero suc }, suc { fact mul } }.
+        ";
+        assert!(warnings(factorial).is_empty());
+        let lists = "
+            data List a: empty, [a, List a] cons.
+            define [List a] length [Int]:
+                case2 { cons _ { pop length }, empty _ { 0 } }.
+            data Even: ezero, [Odd] esuc.
+            data Odd: [Even] osuc.
+            define [Even] half [Int]: case { ezero { 0 }, esuc { case { osuc { half } } } }.
+        ";
+        assert!(warnings(lists).is_empty());
+    }
+
+    #[test]
+    fn unconditional_recursion_is_flagged() {
+        let source = "
+            data Nat: zero, [Nat] suc.
+            define [a] loop [a]: dup pop loop.
+            define [] ping []: pong.
+            define [] pong []: 1 pop ping.
+            define [Nat] no-base [Nat]: case { zero { no-base }, suc { no-base suc } }.
+            data Box: [Int] box.
+            define [Box] unbox [Int]: case { box { box unbox } }.
+        ";
+        assert_eq!(
+            warnings(source),
+            vec![
+                (
+                    "loop".to_owned(),
+                    "loop".to_owned(),
+                    cycle(&["loop", "loop"])
+                ),
+                (
+                    "ping".to_owned(),
+                    "pong".to_owned(),
+                    cycle(&["ping", "pong", "ping"])
+                ),
+                (
+                    "pong".to_owned(),
+                    "ping".to_owned(),
+                    cycle(&["pong", "ping", "pong"])
+                ),
+                (
+                    "no-base".to_owned(),
+                    "no-base".to_owned(),
+                    cycle(&["no-base", "no-base"])
+                ),
+                (
+                    "unbox".to_owned(),
+                    "unbox".to_owned(),
+                    cycle(&["unbox", "unbox"])
+                ),
+            ]
+        );
+    }
+
+    /// Quotes may never run, so calls inside them are not followed, not
+    /// even when the quote is executed right away
+    #[test]
+    fn recursion_through_quotes_is_not_seen() {
+        let source = "
+            define [] ping []: (pong) exec-0-0.
+            define [] pong []: (ping) exec-0-0.
+            define [a] forever [a]: (exec-0-1) fix-0-1.
+        ";
+        assert!(warnings(source).is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+//! Whether an op run on an empty stack, the way `main` is, ever pops more
+//! values than are there. A body's type only tells how many values the
+//! whole body needs, chaining lets an op in the middle reach below what the
+//! ops before it pushed. So the depth is followed op by op through the
+//! entry's body and the arms of its cases, each op taking and leaving as
+//! many values as its type says. Calls and executed quotes are trusted to
+//! need no more than their types say, the module is expected to typecheck.
+
+use crate::desugar::lists;
+use crate::syntax::ast::*;
+use crate::syntax::module_wrapper::ModuleConstrMaps;
+use crate::typing::inference::{Inference, InferenceError, InferenceErrorMessage};
+use std::fmt;
+
+/// An op of the entry that pops more values than the stack holds
+#[derive(Debug, Clone)]
+pub struct Underflow {
+    pub entry: String,
+    /// The op as written, `case` or `case2` for cases
+    pub op: String,
+    pub span: Span,
+    /// How many values the op pops
+    pub needs: usize,
+    /// How many values are on the stack when it runs
+    pub depth: usize,
+}
+
+impl fmt::Display for Underflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values = |n| if n == 1 { "value" } else { "values" };
+        write!(
+            f,
+            "`{}` pops {} {} but only {} {} on the stack when `{}` runs from an empty stack",
+            self.op,
+            self.needs,
+            values(self.needs),
+            self.depth,
+            if self.depth == 1 { "is" } else { "are" },
+            self.entry
+        )
+    }
+}
+
+struct Walk<'i, 'm> {
+    inference: &'i Inference<'m>,
+    constr_maps: ModuleConstrMaps<'m>,
+    entry: &'i str,
+    underflows: Vec<Underflow>,
+}
+
+impl Walk<'_, '_> {
+    /// The depth after the ops
+    fn sentence(&mut self, ops: &[Op], depth: usize) -> Result<usize, InferenceError> {
+        let mut depth = depth;
+        let mut i = 0;
+        while i < ops.len() {
+            match ops.get(i + 1) {
+                // with the index it takes
+                Some(op @ Op::Name { value, span }) if self.inference.takes_index(op) => {
+                    let optype = self.inference.infer_ops(&ops[i..i + 2])?;
+                    depth = self.pop(value, span, optype.pre.len(), depth) + optype.post.len();
+                    i += 2;
+                }
+                _ => {
+                    depth = self.op(&ops[i], depth)?;
+                    i += 1;
+                }
+            }
+        }
+        Ok(depth)
+    }
+
+    fn op(&mut self, op: &Op, depth: usize) -> Result<usize, InferenceError> {
+        match op {
+            Op::Literal { .. } | Op::Quote { .. } => Ok(depth + 1),
+            Op::Name { value, span } => {
+                let optype = self.inference.infer_op_alone(op)?;
+                Ok(self.pop(value, span, optype.pre.len(), depth) + optype.post.len())
+            }
+            Op::Case {
+                head_arm,
+                arms,
+                span,
+            } => {
+                let depth = self.pop("case", span, 1, depth);
+                let mut after = None;
+                for arm in std::iter::once(head_arm).chain(arms) {
+                    let fields = self.fields(&arm.constr, &arm.span)?;
+                    let arm_after = self.sentence(&arm.body, depth + fields)?;
+                    after = Some(after.map_or(arm_after, |a: usize| a.min(arm_after)));
+                }
+                Ok(after.unwrap_or(depth))
+            }
+            Op::Case2 { arms, span } => {
+                let depth = self.pop("case2", span, 2, depth);
+                let mut after = None;
+                for arm in arms {
+                    let mut pushed = 0;
+                    for pattern in arm.patterns.iter() {
+                        pushed += match pattern {
+                            Pattern::Constr(constr) => self.fields(constr, &arm.span)?,
+                            Pattern::Wildcard => 1,
+                        };
+                    }
+                    let arm_after = self.sentence(&arm.body, depth + pushed)?;
+                    after = Some(after.map_or(arm_after, |a: usize| a.min(arm_after)));
+                }
+                Ok(after.unwrap_or(depth))
+            }
+            // a module not desugared, walked as it would be once it is
+            Op::List { items, span } => self.sentence(&lists::expand(items.clone(), span), depth),
+        }
+    }
+
+    /// The depth after popping `needs` values, recording an underflow when
+    /// there are fewer. The missing values are taken to have been there so
+    /// that one underflow is not reported again by every op after it.
+    fn pop(&mut self, op: &str, span: &Span, needs: usize, depth: usize) -> usize {
+        if needs <= depth {
+            return depth - needs;
+        }
+        self.underflows.push(Underflow {
+            entry: self.entry.to_owned(),
+            op: op.to_owned(),
+            span: span.clone(),
+            needs,
+            depth,
+        });
+        0
+    }
+
+    fn fields(&self, constr: &str, span: &Span) -> Result<usize, InferenceError> {
+        match self.constr_maps.constr_to_constr_map.get(constr) {
+            Some(constr_def) => Ok(constr_def.params.len()),
+            None => Err(InferenceError {
+                span: span.clone(),
+                error: InferenceErrorMessage::UnknownConstructor {
+                    name: constr.to_owned(),
+                },
+            }),
+        }
+    }
+}
+
+/// The ops of the entry's body, its case arms included, that pop from an
+/// empty stack when the entry runs on one, in source order. `None` when
+/// no op is named `entry`.
+pub fn check_no_underflow(
+    inference: &Inference,
+    entry: &str,
+) -> Option<Result<Vec<Underflow>, InferenceError>> {
+    let op_def = inference.module().op_defs.get(entry)?;
+    let mut walk = Walk {
+        inference,
+        constr_maps: ModuleConstrMaps::new(inference.module()),
+        entry,
+        underflows: vec![],
+    };
+    Some(walk.sentence(&op_def.body, 0).map(|_| {
+        walk.underflows
+            .sort_by_key(|underflow| underflow.span.start);
+        walk.underflows
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    fn underflows(source: &str, entry: &str) -> Vec<(String, usize, usize)> {
+        let module = parse(source).unwrap();
+        let inference = Inference::new(&module);
+        assert!(inference.typecheck().is_ok());
+        check_no_underflow(&inference, entry)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|u| {
+                (
+                    source[u.span.start..u.span.end].to_owned(),
+                    u.needs,
+                    u.depth,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn library_ops_underflow_as_entries() {
+        let source = "
+            data Box: [Int, Int] box.
+            define [a] twice [a, a]: dup.
+            define [Box] unbox [Int]: case { box { pop } }.
+            define [Int, Int] first [Int]: pop.
+            define [a, b] over [b, a, b]: 1 pick.
+            define [] main [Int, Int]: 1 twice 2 3 box unbox first.
+        ";
+        assert!(underflows(source, "main").is_empty());
+        assert_eq!(underflows(source, "twice"), [("dup".to_owned(), 1, 0)]);
+        // the arm's `pop` has the fields to pop, only the case underflows
+        assert_eq!(
+            underflows(source, "unbox"),
+            [("case { box { pop } }".to_owned(), 1, 0)]
+        );
+        assert_eq!(underflows(source, "first"), [("pop".to_owned(), 1, 0)]);
+        // the index is not one of the values `pick` pops
+        assert_eq!(underflows(source, "over"), [("pick".to_owned(), 2, 0)]);
+    }
+
+    #[test]
+    fn unchecked_bodies_are_followed_op_by_op() {
+        let source = "
+            data Pair: [Int, Int] pair.
+            #[unchecked]
+            define [] main [Int]: 1 pop pop 2 case2 { pair _ { pop } }.
+        ";
+        assert_eq!(
+            underflows(source, "main"),
+            [
+                ("pop".to_owned(), 1, 0),
+                ("case2 { pair _ { pop } }".to_owned(), 2, 1)
+            ]
+        );
+        assert!(check_no_underflow(&Inference::new(&parse(source).unwrap()), "nope").is_none());
+    }
+
+    #[test]
+    fn list_literals_are_followed_as_they_expand() {
+        let source = "
+            data List a: empty, [a, List a] cons.
+            define [] main [List Int]: [1, 2].
+            #[unchecked]
+            define [] bad [List Int]: [pop pop 1].
+        ";
+        assert!(underflows(source, "main").is_empty());
+        // the `empty` the literal starts with is all the second `pop` finds,
+        // and the `cons` of the literal is short of a value after it
+        assert_eq!(
+            underflows(source, "bad"),
+            [("[pop pop 1]".to_owned(), 2, 1), ("pop".to_owned(), 1, 0)]
+        );
+    }
+}
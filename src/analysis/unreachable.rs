@@ -0,0 +1,137 @@
+//! Ops that never run: the ones after an op that never returns, in the
+//! same sentence. A `panic` never returns, and neither does a case every
+//! arm of which never returns. Checking does not type these ops, whatever
+//! they are, so nothing else points them out.
+
+use crate::syntax::ast::*;
+use std::fmt;
+use std::iter::once;
+
+#[derive(Debug, Clone)]
+pub struct UnreachableWarning {
+    /// The op or constant whose body has them
+    pub name: String,
+    /// The op that never returns
+    pub after: String,
+    /// From the first of the ops that never run to the end of the sentence
+    pub span: Span,
+}
+
+impl fmt::Display for UnreachableWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` never runs the ops after `{}`, which never returns",
+            self.name, self.after
+        )
+    }
+}
+
+/// Whether running the ops never returns
+fn diverges(ops: &[Op]) -> bool {
+    ops.iter().any(op_diverges)
+}
+
+fn op_diverges(op: &Op) -> bool {
+    match op {
+        Op::Name { value, .. } => value == "panic",
+        Op::Case { head_arm, arms, .. } => {
+            once(head_arm).chain(arms).all(|arm| diverges(&arm.body))
+        }
+        Op::Case2 { arms, .. } => arms.iter().all(|arm| diverges(&arm.body)),
+        Op::Literal { .. } | Op::Quote { .. } | Op::List { .. } => false,
+    }
+}
+
+fn collect(name: &str, ops: &[Op], warnings: &mut Vec<UnreachableWarning>) {
+    if let Some(i) = ops.iter().position(op_diverges) {
+        if let (Some(first), Some(last)) = (ops.get(i + 1), ops.last()) {
+            warnings.push(UnreachableWarning {
+                name: name.to_owned(),
+                after: ops[i].to_string(),
+                span: Span {
+                    start: first.get_span().start,
+                    end: last.get_span().end,
+                    origin: first.get_span().origin.clone(),
+                },
+            });
+        }
+    }
+    for op in ops {
+        match op {
+            Op::Literal { .. } | Op::Name { .. } => (),
+            Op::Quote { value, .. } => collect(name, value, warnings),
+            Op::Case { head_arm, arms, .. } => {
+                for arm in once(head_arm).chain(arms) {
+                    collect(name, &arm.body, warnings);
+                }
+            }
+            Op::Case2 { arms, .. } => {
+                for arm in arms {
+                    collect(name, &arm.body, warnings);
+                }
+            }
+            Op::List { items, .. } => {
+                for item in items {
+                    collect(name, item, warnings);
+                }
+            }
+        }
+    }
+}
+
+/// The ops that never run in the bodies of the ops and constants of the
+/// module, in source order
+pub fn check_unreachable(module: &Module) -> Vec<UnreachableWarning> {
+    let ops = module
+        .op_defs_in_source_order()
+        .into_iter()
+        .map(|(name, op_def)| (name, &op_def.body));
+    let consts = module
+        .const_defs_in_source_order()
+        .into_iter()
+        .map(|(name, const_def)| (name, &const_def.body));
+    let mut warnings = vec![];
+    for (name, body) in ops.chain(consts) {
+        collect(name, body, &mut warnings);
+    }
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    #[test]
+    fn ops_after_what_never_returns() {
+        let source = "
+data Bool: false, true.
+define [] stop [Int]: panic 1 pop dup.
+define [Bool] either [Int]: case { false { panic }, true { panic } } nope.
+define [Bool] one [Int]: case { false { panic }, true { 1 } } 2 pop.
+define [] quoted [[][Int]]: (1 panic pop).
+define [] last [Int]: 1 panic.
+const c [Int]: panic 2.
+";
+        let warnings: Vec<_> = check_unreachable(&parse(source).unwrap())
+            .iter()
+            .map(|w| (source[w.span.start..w.span.end].to_owned(), w.to_string()))
+            .collect();
+        let expected = [
+            ("1 pop dup", "`stop` never runs the ops after `panic`, which never returns"),
+            (
+                "nope",
+                "`either` never runs the ops after `case { false { panic }, true { panic } }`, which never returns",
+            ),
+            ("pop", "`quoted` never runs the ops after `panic`, which never returns"),
+            ("2", "`c` never runs the ops after `panic`, which never returns"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(ops, w)| (ops.to_string(), w.to_string()))
+            .collect();
+        assert_eq!(warnings, expected);
+    }
+}
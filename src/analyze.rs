@@ -0,0 +1,261 @@
+//! Every phase on a source string in one call, for the playground, the CLI
+//! and tests. Parse errors do not stop the other phases: the definitions
+//! that parsed are desugared and typechecked as usual.
+
+use crate::desugar::desugar;
+use crate::diagnostics::Diagnostic;
+use crate::syntax::ast::Module;
+use crate::syntax::attributes::check_attributes;
+use crate::syntax::highlight::{self, SpannedToken};
+use crate::syntax::parse_recovering;
+use crate::typing::cache::TypecheckCache;
+use crate::typing::cancel::CheckOptions;
+use crate::typing::inference::{Inference, InferenceErrorMessage};
+use crate::typing::report::TypecheckReport;
+use crate::typing::strict::StrictOptions;
+use std::time::Duration;
+#[cfg(feature = "os")]
+use std::time::Instant;
+
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzeOptions {
+    /// Keep the desugared module in the result
+    pub keep_ast: bool,
+    pub strict: StrictOptions,
+    pub check: CheckOptions,
+    /// Outcomes of an earlier run to reuse, see `TypecheckCache`
+    pub cache: Option<TypecheckCache>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Lex,
+    Parse,
+    Desugar,
+    /// Building the tables names are looked up in, see `Inference::new`
+    Resolve,
+    Typecheck,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Lex => "lex",
+            Phase::Parse => "parse",
+            Phase::Desugar => "desugar",
+            Phase::Resolve => "resolve",
+            Phase::Typecheck => "typecheck",
+        }
+    }
+}
+
+pub struct AnalysisResult {
+    /// The desugared module with `keep_ast`, never when desugaring failed
+    pub module: Option<Module>,
+    /// `None` when desugaring failed and nothing was typechecked
+    pub report: Option<TypecheckReport>,
+    /// The diagnostics of every phase, ordered by where they start
+    pub diagnostics: Vec<(Phase, Diagnostic)>,
+    /// Every token for highlighting, see `highlight::lex`
+    pub tokens: Vec<SpannedToken>,
+    /// How long each phase took, in the order they ran. Empty without the
+    /// `os` feature, which the clock needs
+    pub timings: Vec<(Phase, Duration)>,
+    /// The cache given in the options, updated with this run's outcomes
+    pub cache: Option<TypecheckCache>,
+}
+
+impl AnalysisResult {
+    /// Whether no phase reported anything
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+/// Runs `f`, adding how long it took to the timings when there is a clock
+fn timed<T>(timings: &mut Vec<(Phase, Duration)>, phase: Phase, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "os")]
+    {
+        let start = Instant::now();
+        let result = f();
+        timings.push((phase, start.elapsed()));
+        result
+    }
+    #[cfg(not(feature = "os"))]
+    {
+        let _ = (timings, phase);
+        f()
+    }
+}
+
+/// Lexes, parses, desugars and typechecks `src`. A definition that does not
+/// parse is left out with a diagnostic, and unknown names it would have
+/// defined get a note saying so. Misplaced or malformed attributes are
+/// parse errors, unknown ones are left to the caller to warn about. A failed desugaring leaves nothing to
+/// typecheck.
+pub fn analyze(src: &str, mut opts: AnalyzeOptions) -> AnalysisResult {
+    let mut timings = vec![];
+    let mut diagnostics = vec![];
+    let tokens = timed(&mut timings, Phase::Lex, || highlight::lex(src));
+    let recovered = timed(&mut timings, Phase::Parse, || parse_recovering(src));
+    for err in recovered.errors.iter() {
+        diagnostics.push((Phase::Parse, Diagnostic::from_parse_error(src, err)));
+    }
+    for problem in check_attributes(&recovered.module) {
+        if problem.is_error() {
+            diagnostics.push((Phase::Parse, Diagnostic::from_attribute_problem(&problem)));
+        }
+    }
+    let unparsed = |name: &String| recovered.unparsed_names.contains(name);
+    let desugared = timed(&mut timings, Phase::Desugar, || desugar(recovered.module));
+    let (module, report) = match desugared {
+        Ok(module) => {
+            let inf = timed(&mut timings, Phase::Resolve, || {
+                Inference::new(&module).with_strict(opts.strict.clone())
+            });
+            let mut report = timed(&mut timings, Phase::Typecheck, || {
+                let report = match opts.cache.as_mut() {
+                    Some(cache) => cache.report(&inf, src, &opts.check),
+                    None => inf.report_with(&opts.check),
+                };
+                for err in report.all_errors() {
+                    let mut diagnostic = Diagnostic::from_inference_error(src, &module, err);
+                    if let InferenceErrorMessage::UnknownOp { name }
+                    | InferenceErrorMessage::UnknownConstructor { name } = &err.error
+                    {
+                        if unparsed(name) {
+                            let note = format!("the definition of `{}` failed to parse", name);
+                            diagnostic = diagnostic.with_note(&note);
+                        }
+                    }
+                    diagnostics.push((Phase::Typecheck, diagnostic));
+                }
+                report
+            });
+            report.timings = timings.clone();
+            (opts.keep_ast.then_some(module), Some(report))
+        }
+        Err(errors) => {
+            diagnostics.extend(errors.into_iter().map(|d| (Phase::Desugar, d)));
+            (None, None)
+        }
+    };
+    diagnostics.sort_by_key(|(_, diagnostic)| diagnostic.span.start);
+    AnalysisResult {
+        module,
+        report,
+        diagnostics,
+        tokens,
+        timings,
+        cache: opts.cache,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::report::OpOutcome;
+
+    fn types(result: &AnalysisResult) -> Vec<(String, String)> {
+        let report = result.report.as_ref().unwrap();
+        report
+            .ops
+            .iter()
+            .map(|op| {
+                let outcome = match &op.outcome {
+                    OpOutcome::Inferred(inferred) => inferred.canonical().to_string(),
+                    OpOutcome::Failed(_) => "failed".to_owned(),
+                    _ => "other".to_owned(),
+                };
+                (op.name.clone(), outcome)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn broken_definitions_leave_the_others_checked() {
+        let src = "
+            data Nat: zero, [Nat] suc.
+            define [Nat] two [Nat]: suc suc.
+            define [Nat] broken [: suc.
+            define [Nat] nope [Nat]: pred.
+            define [] list [List Nat]: [zero].
+            ";
+        let result = analyze(src, AnalyzeOptions::default());
+        let phases: Vec<_> = result.diagnostics.iter().map(|(phase, _)| *phase).collect();
+        assert_eq!(phases, [Phase::Parse, Phase::Desugar]);
+        assert!(result.report.is_none());
+
+        let src = src.replace("define [] list [List Nat]: [zero].", "");
+        let result = analyze(&src, AnalyzeOptions::default());
+        let phases: Vec<_> = result.diagnostics.iter().map(|(phase, _)| *phase).collect();
+        assert_eq!(phases, [Phase::Parse, Phase::Typecheck]);
+        assert_eq!(
+            types(&result),
+            [
+                ("two".to_owned(), "[Nat][Nat]".to_owned()),
+                ("nope".to_owned(), "failed".to_owned())
+            ]
+        );
+        let starts: Vec<_> = result
+            .diagnostics
+            .iter()
+            .map(|(_, d)| d.span.start)
+            .collect();
+        assert!(starts.is_sorted());
+        assert!(result.module.is_none());
+    }
+
+    #[test]
+    fn misplaced_attributes_are_parse_errors() {
+        let src = "
+            #[derive(eq)] #[frobnicate]
+            define [] f []:.
+            ";
+        let result = analyze(src, AnalyzeOptions::default());
+        let messages: Vec<_> = result
+            .diagnostics
+            .iter()
+            .map(|(phase, d)| (*phase, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [(Phase::Parse, "`derive` only goes on data definitions")]
+        );
+        assert!(result.report.is_some_and(|report| report.is_ok()));
+    }
+
+    #[test]
+    fn everything_comes_back() {
+        let src = "define [a] twice [a, a]: dup. // done";
+        let opts = AnalyzeOptions {
+            keep_ast: true,
+            ..AnalyzeOptions::default()
+        };
+        let result = analyze(src, opts);
+        assert!(result.is_ok());
+        assert!(result.module.unwrap().op_defs.contains_key("twice"));
+        assert_eq!(result.tokens.len(), 14);
+        let phases: Vec<_> = result.timings.iter().map(|(phase, _)| *phase).collect();
+        if cfg!(feature = "os") {
+            assert_eq!(
+                phases,
+                [
+                    Phase::Lex,
+                    Phase::Parse,
+                    Phase::Desugar,
+                    Phase::Resolve,
+                    Phase::Typecheck
+                ]
+            );
+            let report = result.report.unwrap();
+            assert_eq!(report.timings, result.timings);
+            assert!(report.ops.iter().all(|op| op.elapsed.is_some()));
+            let slowest: Vec<_> = report.slowest(10).iter().map(|op| &op.name).collect();
+            assert_eq!(slowest, ["twice"]);
+            assert!(report.ops[0].stats.fresh_vars > 0);
+        } else {
+            assert!(phases.is_empty());
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Replacing the annotations that conflict with what the bodies of their
+//! ops infer, for `iv annotate`. An op's body is inferred against the
+//! annotations of the ops it calls, so fixing one annotation can show
+//! another to be wrong: fixing goes on until no annotation changes.
+
+use crate::desugar::desugar;
+use crate::diagnostics::fixes::{annotation_fix, annotation_span};
+use crate::format::{format_ranges_with, FormatOptions};
+use crate::refactor::TextEdit;
+use crate::syntax::ast::*;
+use crate::syntax::parse;
+use crate::typing::inference::{is_unchecked, Inference, InferenceErrorMessage};
+use crate::typing::report::OpOutcome;
+use crate::typing::types::Type;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The annotation given to an op names a type the module does not define,
+/// one that only an extern op or literal has, so the annotation may not
+/// mean the same where the module is used
+#[derive(Debug, Clone)]
+pub struct AnnotationWarning {
+    /// The name of the op
+    pub span: Span,
+    pub op: String,
+    pub type_name: String,
+}
+
+impl fmt::Display for AnnotationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the annotation given to `{}` names `{}`, which the module does not define",
+            self.op, self.type_name
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Annotated {
+    /// The edits to the source, one for each op whose annotation changes
+    pub edits: Vec<TextEdit>,
+    pub warnings: Vec<AnnotationWarning>,
+}
+
+/// The edits giving every checked op whose annotation conflicts with its
+/// body the type its body infers. Ops whose body fails otherwise are left
+/// as they are, as are the unchecked ones. Nothing changes if the source
+/// does not parse.
+pub fn annotate(src: &str) -> Annotated {
+    let Some(original) = parse_module(src) else {
+        return Annotated::default();
+    };
+    // the new annotation of each op, as the text from `define` to the colon
+    let mut heads = BTreeMap::new();
+    let mut current = src.to_owned();
+    let mut last = None;
+    // an annotation changing every round could go on forever
+    for _ in 0..=original.op_defs.len() {
+        let Some(module) = parse_module(&current) else {
+            break;
+        };
+        let inference = Inference::new(&module);
+        let report = inference.report();
+        let fixes: Vec<_> = report
+            .ops
+            .iter()
+            .filter(|op| {
+                let OpOutcome::Failed(err) = &op.outcome else {
+                    return false;
+                };
+                let op_def = &module.op_defs[&op.name];
+                let conflicts = match err.error {
+                    InferenceErrorMessage::AnnInfConflict { .. }
+                    | InferenceErrorMessage::NotEnoughValues { .. } => true,
+                    // a stub is left to be filled in, and the pragmas say
+                    // what the body is, not the annotation
+                    InferenceErrorMessage::EmptyBodyCannotSatisfy { .. }
+                    | InferenceErrorMessage::ExpectTypeMismatch { .. }
+                    | InferenceErrorMessage::ExpectErrorMismatch { .. } => false,
+                    // checking the body against the annotation blamed one
+                    // of its ops, while the body alone is fine
+                    _ => inference.infer_ops(&op_def.body).is_ok(),
+                };
+                conflicts && !is_unchecked(&op.name, op_def)
+            })
+            .filter_map(|op| {
+                Some((
+                    op.name.clone(),
+                    annotation_fix(&current, &module, &op.name)?,
+                ))
+            })
+            .collect();
+        if fixes.is_empty() {
+            last = Some(module);
+            break;
+        }
+        let edits: Vec<_> = fixes
+            .iter()
+            .map(|(_, fix)| TextEdit {
+                span: fix.span.clone(),
+                replacement: fix.replacement.clone(),
+            })
+            .collect();
+        current = TextEdit::apply_all(&current, &edits);
+        for (name, fix) in fixes {
+            heads.insert(name, fix.replacement);
+        }
+    }
+    let defined: BTreeSet<&str> = last
+        .as_ref()
+        .unwrap_or(&original)
+        .data_defs
+        .keys()
+        .map(String::as_str)
+        .chain(["Int"])
+        .collect();
+    let mut annotated = Annotated::default();
+    for (name, head) in heads {
+        let op_def = &original.op_defs[&name];
+        let Some(span) = annotation_span(src, op_def) else {
+            continue;
+        };
+        annotated.edits.push(TextEdit {
+            span,
+            replacement: head,
+        });
+        let Some(module) = &last else {
+            continue;
+        };
+        let ann = &module.op_defs[&name].ann;
+        let mut names = BTreeSet::new();
+        for t in ann.pre.iter().chain(&ann.post) {
+            type_names(t, &mut names);
+        }
+        for type_name in names.into_iter().filter(|n| !defined.contains(n.as_str())) {
+            annotated.warnings.push(AnnotationWarning {
+                span: op_def.name_span.clone(),
+                op: name.clone(),
+                type_name,
+            });
+        }
+    }
+    annotated
+}
+
+/// The edits of `annotate`, each replacing the whole definition of its op
+/// with the definition formatted, so that one whose annotation grows past
+/// the width breaks over lines the way `iv format` breaks it
+pub fn annotate_with(src: &str, options: &FormatOptions) -> Annotated {
+    let annotated = annotate(src);
+    let Ok(original) = parse(src) else {
+        return annotated;
+    };
+    let changed: Vec<_> = original
+        .op_defs
+        .iter()
+        .map(|(name, op_def)| (name, Definition::Op(name, op_def).extent()))
+        .filter(|(_, extent)| {
+            annotated
+                .edits
+                .iter()
+                .any(|edit| extent.start <= edit.span.start && edit.span.end <= extent.end)
+        })
+        .collect();
+    let fixed = TextEdit::apply_all(src, &annotated.edits);
+    let Ok(module) = parse(&fixed) else {
+        return annotated;
+    };
+    let ranges: Vec<_> = changed
+        .iter()
+        .filter_map(|(name, _)| Some(Definition::Op(name, module.op_defs.get(*name)?).extent()))
+        .collect();
+    let formatted = TextEdit::apply_all(
+        &fixed,
+        &format_ranges_with(&fixed, &module, &ranges, options),
+    );
+    let Ok(module) = parse(&formatted) else {
+        return annotated;
+    };
+    let edits = changed
+        .into_iter()
+        .filter_map(|(name, span)| {
+            let extent = Definition::Op(name, module.op_defs.get(name)?).extent();
+            Some(TextEdit {
+                span,
+                replacement: formatted[extent.start..extent.end].to_owned(),
+            })
+        })
+        .collect();
+    Annotated {
+        edits,
+        warnings: annotated.warnings,
+    }
+}
+
+fn parse_module(src: &str) -> Option<Module> {
+    desugar(parse(src).ok()?).ok()
+}
+
+/// The names of the types the type is built from
+fn type_names(t: &Type, names: &mut BTreeSet<String>) {
+    match t {
+        Type::Mono(name) => {
+            names.insert(name.clone());
+        }
+        Type::Poly(_) | Type::Dyn => (),
+        Type::Op(op_type) => {
+            for t in op_type.pre.iter().chain(&op_type.post) {
+                type_names(t, names);
+            }
+        }
+        Type::App(t1, t2) => {
+            type_names(t1, names);
+            type_names(t2, names);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotated(src: &str) -> String {
+        TextEdit::apply_all(src, &annotate(src).edits)
+    }
+
+    #[test]
+    fn fixes_go_on_until_the_annotations_agree() {
+        let src = "data Nat: zero, [Nat] suc.
+define [] two []: zero suc suc.
+define [] three []: two suc.
+define [] rest []: three case { zero { zero }, suc { } }.
+";
+        assert_eq!(
+            annotated(src),
+            "data Nat: zero, [Nat] suc.
+define [] two [Nat]: zero suc suc.
+define [] three [Nat]: two suc.
+define [] rest [Nat]: three case { zero { zero }, suc { } }.
+"
+        );
+    }
+
+    #[test]
+    fn unchecked_ops_and_other_errors_stay() {
+        let src = "#[unchecked]
+define [] trusted []: 1.
+define [] nocount []: 2.
+define [] broken []: 1 nope.
+define [] fine [Int]: 1.
+";
+        assert!(annotate(src).edits.is_empty());
+    }
+
+    #[test]
+    fn annotations_can_be_formatted_with_their_definitions() {
+        let src = "data Pair a b: [a, b] pair.
+define [] mk []: 1 2 pair   1 2 pair pair.
+define [] kept [Int]:   1.
+";
+        let options = FormatOptions {
+            max_width: 40,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            TextEdit::apply_all(src, &annotate_with(src, &options).edits),
+            "data Pair a b: [a, b] pair.
+define [] mk [Pair (Pair Int Int) (Pair Int Int)]:
+    1 2 pair 1 2 pair pair.
+define [] kept [Int]:   1.
+"
+        );
+    }
+
+    #[test]
+    fn warns_about_types_the_module_does_not_define() {
+        let src = "define [Str] name [Str].\ndefine [] greet []: name.\n";
+        let annotated = annotate(src);
+        assert_eq!(
+            TextEdit::apply_all(src, &annotated.edits),
+            "define [Str] name [Str].\ndefine [Str] greet [Str]: name.\n"
+        );
+        assert_eq!(
+            annotated
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["the annotation given to `greet` names `Str`, which the module does not define"]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Benchmarks written in iv, for `iv bench`. An op named `bench-*`, or
+//! marked `#[bench]`, typed `[][]` or `[][Int]`, is run over and over on an
+//! empty stack: first for `BenchConfig::warmup`, which also estimates how
+//! long a run takes, then in samples of as many runs as take
+//! `BenchConfig::target` together. The time of a sample over its runs is
+//! one time per run, the result is their median and median absolute
+//! deviation.
+//!
+//! Each run is an evaluation of its own, so the evaluator's limits apply
+//! to every run rather than to all of them together, the way `steps`
+//! counts. The time comes from a `Clock`, which tests replace with one
+//! that only moves as they say.
+
+use crate::diagnostics::{render, Diagnostic, MAX_FRAMES};
+use crate::evaluation::evaluator::Evaluator;
+use crate::evaluation::types::{EvaluatorError, RuntimeError};
+use crate::syntax::ast::*;
+use crate::typing::types::{OpType, Type};
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::time::Duration;
+#[cfg(feature = "os")]
+use std::time::Instant;
+
+/// Where the time of the runs comes from
+pub trait Clock {
+    /// The time since some fixed point, never less than the time before
+    fn now(&self) -> Duration;
+}
+
+/// The time of the OS, from when the clock was made
+#[cfg(feature = "os")]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+#[cfg(feature = "os")]
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "os")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+#[cfg(feature = "os")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// How long to run before timing, at least once. 100ms by default.
+    pub warmup: Duration,
+    /// How long the timed runs should take together, 1s by default
+    pub target: Duration,
+    /// How many samples the timed runs are split into, 20 by default
+    pub samples: usize,
+    /// The most runs of the warmup and of a sample, for ops too fast for
+    /// the clock to tell. A million by default.
+    pub max_runs: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            warmup: Duration::from_millis(100),
+            target: Duration::from_secs(1),
+            samples: 20,
+            max_runs: 1_000_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub warmup_runs: usize,
+    /// The runs of each sample, the same for all of them
+    pub runs_per_sample: usize,
+    /// The time per run of each sample
+    pub samples: Vec<Duration>,
+    pub median: Duration,
+    /// The median of how far the samples are from `median`
+    pub mad: Duration,
+    /// The ops a run executes
+    pub steps: usize,
+}
+
+#[derive(Debug)]
+pub enum BenchError {
+    UnknownOp,
+    /// Not typed like a benchmark, so never run
+    NotABench(OpType),
+    Runtime(RuntimeError),
+}
+
+#[derive(Debug)]
+pub struct BenchOutcome {
+    pub name: String,
+    /// The name in the op's definition
+    pub span: Span,
+    pub result: Result<BenchResult, BenchError>,
+}
+
+/// The benchmarks run, in source order
+#[derive(Debug, Default)]
+pub struct BenchReport {
+    pub outcomes: Vec<BenchOutcome>,
+}
+
+pub fn is_bench(name: &str, op_def: &OpDef) -> bool {
+    name.starts_with("bench-") || op_def.has_attribute("bench")
+}
+
+fn is_bench_type(ann: &OpType) -> bool {
+    match (&ann.pre[..], &ann.post[..]) {
+        ([], []) => true,
+        ([], [Type::Mono(name)]) => name == "Int",
+        _ => false,
+    }
+}
+
+/// The benchmark ops whose names contain `filter`, in source order
+pub fn discover<'m>(module: &'m Module, filter: &str) -> Vec<(&'m String, &'m OpDef)> {
+    module
+        .op_defs_in_source_order()
+        .into_iter()
+        .filter(|(name, op_def)| is_bench(name, op_def) && name.contains(filter))
+        .collect()
+}
+
+fn run_once(evaluator: &mut Evaluator, name: &str) -> Result<(), BenchError> {
+    evaluator.stack.clear();
+    match evaluator.eval_op_def(name) {
+        Ok(()) => {
+            black_box(&evaluator.stack);
+            Ok(())
+        }
+        Err(EvaluatorError::Runtime(err)) => Err(BenchError::Runtime(err)),
+        Err(err) => unreachable!("the bench op exists: {:?}", err),
+    }
+}
+
+fn per_run(elapsed: Duration, runs: usize) -> Duration {
+    Duration::from_nanos((elapsed.as_nanos() / runs.max(1) as u128) as u64)
+}
+
+fn median_of(samples: &[Duration]) -> Duration {
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    sorted.get(sorted.len() / 2).copied().unwrap_or_default()
+}
+
+/// Times the op, which the module defines and the evaluator evaluates.
+/// The runs of a sample follow from the warmup's time per run and the
+/// config alone.
+pub fn run(
+    module: &Module,
+    evaluator: &mut Evaluator,
+    name: &str,
+    config: &BenchConfig,
+    clock: &dyn Clock,
+) -> Result<BenchResult, BenchError> {
+    let op_def = module.op_defs.get(name).ok_or(BenchError::UnknownOp)?;
+    if !is_bench_type(&op_def.ann) {
+        return Err(BenchError::NotABench(op_def.ann.clone()));
+    }
+    let max_runs = config.max_runs.max(1);
+    let start = clock.now();
+    let mut warmup_runs = 0;
+    let warmup = loop {
+        run_once(evaluator, name)?;
+        warmup_runs += 1;
+        let elapsed = clock.now().saturating_sub(start);
+        if elapsed >= config.warmup || warmup_runs >= max_runs {
+            break elapsed;
+        }
+    };
+    let steps = evaluator.steps();
+    let sample_count = config.samples.max(1);
+    let per_sample = config.target.as_nanos() / sample_count as u128;
+    let estimate = per_run(warmup, warmup_runs).as_nanos().max(1);
+    let runs_per_sample = ((per_sample / estimate) as usize).clamp(1, max_runs);
+    let mut samples = vec![];
+    for _ in 0..sample_count {
+        let start = clock.now();
+        for _ in 0..runs_per_sample {
+            run_once(evaluator, name)?;
+        }
+        samples.push(per_run(clock.now().saturating_sub(start), runs_per_sample));
+    }
+    evaluator.stack.clear();
+    let median = median_of(&samples);
+    let deviations: Vec<_> = samples
+        .iter()
+        .map(|sample| sample.abs_diff(median))
+        .collect();
+    Ok(BenchResult {
+        warmup_runs,
+        runs_per_sample,
+        mad: median_of(&deviations),
+        median,
+        samples,
+        steps,
+    })
+}
+
+/// Times every benchmark op whose name contains `filter`. The module is
+/// expected to typecheck.
+pub fn run_benches(
+    module: &Module,
+    evaluator: &mut Evaluator,
+    filter: &str,
+    config: &BenchConfig,
+    clock: &dyn Clock,
+) -> BenchReport {
+    let outcomes = discover(module, filter)
+        .into_iter()
+        .map(|(name, op_def)| BenchOutcome {
+            name: name.to_owned(),
+            span: op_def.name_span.clone(),
+            result: run(module, evaluator, name, config, clock),
+        })
+        .collect();
+    BenchReport { outcomes }
+}
+
+/// A baseline is a flat JSON object of benchmark names to their median in
+/// nanoseconds, none if the text is not one
+pub fn parse_baseline(text: &str) -> Option<BTreeMap<String, u128>> {
+    let inner = text.trim().strip_prefix('{')?.strip_suffix('}')?.trim();
+    if inner.is_empty() {
+        return Some(BTreeMap::new());
+    }
+    inner
+        .split(',')
+        .map(|entry| {
+            let (name, nanos) = entry.split_once(':')?;
+            let name = name.trim().strip_prefix('"')?.strip_suffix('"')?;
+            Some((name.to_owned(), nanos.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+impl BenchReport {
+    pub fn is_ok(&self) -> bool {
+        self.outcomes.iter().all(|outcome| outcome.result.is_ok())
+    }
+
+    /// The medians of the benchmarks that ran, as a baseline to compare
+    /// later runs with
+    pub fn baseline(&self) -> String {
+        let entries: Vec<_> = self
+            .outcomes
+            .iter()
+            .filter_map(|outcome| {
+                let result = outcome.result.as_ref().ok()?;
+                Some(format!(
+                    "  \"{}\": {}",
+                    outcome.name,
+                    result.median.as_nanos()
+                ))
+            })
+            .collect();
+        format!("{{\n{}\n}}\n", entries.join(",\n"))
+    }
+
+    /// A line per benchmark, compared with the baseline if given, then the
+    /// failures with their source
+    pub fn render(&self, source: &str, baseline: Option<&BTreeMap<String, u128>>) -> String {
+        let mut out = String::new();
+        for outcome in self.outcomes.iter() {
+            let line = match &outcome.result {
+                Ok(result) => {
+                    let comparison = match baseline.map(|b| b.get(&outcome.name)) {
+                        None => String::new(),
+                        Some(Some(&base)) if base > 0 => format!(
+                            ", {:.2}x baseline",
+                            result.median.as_nanos() as f64 / base as f64
+                        ),
+                        Some(_) => ", no baseline".to_owned(),
+                    };
+                    format!(
+                        "{:?}/run ± {:?}, {} samples of {} runs, {} steps{}",
+                        result.median,
+                        result.mad,
+                        result.samples.len(),
+                        result.runs_per_sample,
+                        result.steps,
+                        comparison
+                    )
+                }
+                Err(BenchError::Runtime(_)) => "ERROR".to_owned(),
+                Err(_) => "NOT A BENCH".to_owned(),
+            };
+            out.push_str(&format!("bench {} ... {}\n", outcome.name, line));
+        }
+        for outcome in self.outcomes.iter() {
+            let (span, message) = match &outcome.result {
+                Ok(_) => continue,
+                Err(BenchError::Runtime(err)) => {
+                    let diagnostic = Diagnostic::from_runtime_error(source, err, MAX_FRAMES);
+                    (
+                        &err.span,
+                        format!(
+                            "`{}` hit a runtime error: {}",
+                            outcome.name, diagnostic.message
+                        ),
+                    )
+                }
+                Err(BenchError::NotABench(ann)) => (
+                    &outcome.span,
+                    format!(
+                        "`{}` has type {}, a benchmark has type [][] or [][Int]",
+                        outcome.name, ann
+                    ),
+                ),
+                Err(BenchError::UnknownOp) => unreachable!("benchmarks are found in the module"),
+            };
+            out.push('\n');
+            out.push_str(&render(source, span, &message));
+        }
+        out
+    }
+}
@@ -1,16 +1,78 @@
-use iv::syntax::ast::Span;
 use std::env;
-use std::iter::repeat;
 
 pub enum Mode {
     Typecheck,
     Evaluate,
     Compile,
+    /// `iv search <stack effect> [file]`, the ops matching the stack effect
+    Search(String),
+    /// `iv test [--list] [--filter <substring>] [file]`, runs the `test-*` ops
+    Test {
+        filter: String,
+        list: bool,
+    },
+    /// `iv bench [--filter <substring>] [--baseline <file>]
+    /// [--save-baseline <file>] [file]`, times the `bench-*` and `#[bench]`
+    /// ops, compared with the medians of a baseline or saving them as one
+    Bench {
+        filter: String,
+        baseline: Option<String>,
+        save_baseline: Option<String>,
+    },
+    /// `iv shrink [--error <code> | --differs-strict] [file]`, the smallest
+    /// part of the module still showing the inference bug
+    Shrink(ShrinkBy),
+    /// `iv project [dir]`, checks the project whose manifest is in the
+    /// directory, the current one if not given
+    Project,
+    /// `iv explain <code>`, what the error code means with an example
+    Explain(String),
+    /// `iv format [--ranges <lines>] [file]`, the module with its
+    /// definitions formatted, only the ones on the lines if given, like
+    /// `3-5,12-12`, in the style of the `[fmt]` section of the `iv.toml`
+    /// found from the file's directory up
+    Format {
+        ranges: Option<String>,
+    },
+    /// `iv annotate [--write] [--format] [file]`, the module with the
+    /// annotations that conflict with their op's body replaced by the
+    /// inferred type, written back to the file with `--write`. With
+    /// `--format` the definitions changed are formatted too, the way
+    /// `iv format` does.
+    Annotate {
+        write: bool,
+        format: bool,
+    },
+}
+
+/// What `iv shrink` keeps true of the module
+pub enum ShrinkBy {
+    Panics,
+    Error(String),
+    /// The report differs with every strict check on
+    DiffersStrict,
 }
 
 pub struct CliArgs {
     pub mode: Mode,
     pub file_path: Option<String>,
+    /// Warn about recursion that looks like it never ends, when typechecking
+    pub termination: bool,
+    /// `--entry <op>`, check that the op never pops from an empty stack when
+    /// run on one, when typechecking
+    pub entry: Option<String>,
+    /// Report the ops and case arms that never ran, when evaluating
+    pub coverage: bool,
+    /// Say where the value a runtime error is about was made, when
+    /// evaluating
+    pub origins: bool,
+    /// Where typechecking keeps its results between runs, `.iv-cache` if
+    /// not given
+    pub cache_dir: Option<String>,
+    /// Check everything again and write no cache
+    pub no_cache: bool,
+    /// Say how long each phase and the slowest ops took, when typechecking
+    pub timings: bool,
 }
 
 impl CliArgs {
@@ -18,29 +80,114 @@ impl CliArgs {
         let mut a = CliArgs {
             mode: Mode::Typecheck,
             file_path: None,
+            termination: false,
+            entry: None,
+            coverage: false,
+            origins: false,
+            cache_dir: None,
+            no_cache: false,
+            timings: false,
         };
-        for arg in args.skip(1).rev() {
+        let mut args: Vec<_> = args.skip(1).collect();
+        if args.first().is_some_and(|arg| arg == "search") && args.len() > 1 {
+            a.mode = Mode::Search(args.remove(1));
+            args.remove(0);
+        }
+        if args.first().is_some_and(|arg| arg == "explain") && args.len() > 1 {
+            a.mode = Mode::Explain(args.remove(1));
+            args.remove(0);
+        }
+        if args.first().is_some_and(|arg| arg == "test") {
+            args.remove(0);
+            let filter = match args.iter().position(|arg| arg == "--filter") {
+                Some(i) if i + 1 < args.len() => {
+                    args.remove(i);
+                    args.remove(i)
+                }
+                _ => String::new(),
+            };
+            let list = args.iter().any(|arg| arg == "--list");
+            args.retain(|arg| arg != "--list");
+            a.mode = Mode::Test { filter, list };
+        }
+        if args.first().is_some_and(|arg| arg == "bench") {
+            args.remove(0);
+            let mut value = |flag: &str| match args.iter().position(|arg| arg == flag) {
+                Some(i) if i + 1 < args.len() => {
+                    args.remove(i);
+                    Some(args.remove(i))
+                }
+                _ => None,
+            };
+            a.mode = Mode::Bench {
+                filter: value("--filter").unwrap_or_default(),
+                baseline: value("--baseline"),
+                save_baseline: value("--save-baseline"),
+            };
+        }
+        if args.first().is_some_and(|arg| arg == "shrink") {
+            args.remove(0);
+            let mut by = ShrinkBy::Panics;
+            if let Some(i) = args.iter().position(|arg| arg == "--error") {
+                if i + 1 < args.len() {
+                    args.remove(i);
+                    by = ShrinkBy::Error(args.remove(i));
+                }
+            }
+            if args.iter().any(|arg| arg == "--differs-strict") {
+                args.retain(|arg| arg != "--differs-strict");
+                by = ShrinkBy::DiffersStrict;
+            }
+            args.retain(|arg| arg != "--panics");
+            a.mode = Mode::Shrink(by);
+        }
+        if args.first().is_some_and(|arg| arg == "format") {
+            args.remove(0);
+            let ranges = match args.iter().position(|arg| arg == "--ranges") {
+                Some(i) if i + 1 < args.len() => {
+                    args.remove(i);
+                    Some(args.remove(i))
+                }
+                _ => None,
+            };
+            a.mode = Mode::Format { ranges };
+        }
+        if args.first().is_some_and(|arg| arg == "annotate") {
+            args.remove(0);
+            let write = args.iter().any(|arg| arg == "--write");
+            let format = args.iter().any(|arg| arg == "--format");
+            args.retain(|arg| arg != "--write" && arg != "--format");
+            a.mode = Mode::Annotate { write, format };
+        }
+        if args.first().is_some_and(|arg| arg == "project") {
+            args.remove(0);
+            a.mode = Mode::Project;
+        }
+        if let Some(i) = args.iter().position(|arg| arg == "--cache-dir") {
+            if i + 1 < args.len() {
+                args.remove(i);
+                a.cache_dir = Some(args.remove(i));
+            }
+        }
+        if let Some(i) = args.iter().position(|arg| arg == "--entry") {
+            if i + 1 < args.len() {
+                args.remove(i);
+                a.entry = Some(args.remove(i));
+            }
+        }
+        for arg in args.into_iter().rev() {
             match arg.as_str() {
                 "--typecheck" => a.mode = Mode::Typecheck,
                 "--evaluate" => a.mode = Mode::Evaluate,
                 "--compile" => a.mode = Mode::Compile,
+                "--termination" => a.termination = true,
+                "--coverage" => a.coverage = true,
+                "--origins" => a.origins = true,
+                "--no-cache" => a.no_cache = true,
+                "--timings" => a.timings = true,
                 _ => a.file_path = Some(arg),
             }
         }
         a
     }
 }
-
-pub fn print_span_in_source(source: &str, span: &Span) {
-    for (i, line) in source.lines().enumerate() {
-        let line_start = line.as_ptr() as usize - source.as_ptr() as usize;
-        let line_end = line.as_ptr() as usize + line.len() - source.as_ptr() as usize;
-        if line_start <= span.start && span.start <= line_end {
-            let line_n = i + 1;
-            let col_n = span.start - line_start;
-            println!("line: {}, col: {}", line_n, col_n);
-            println!("{}", line);
-            println!("{}^", repeat(' ').take(col_n).collect::<String>());
-        }
-    }
-}
@@ -0,0 +1,649 @@
+//! Rust source backend. Every op becomes a function over a stack of
+//! dynamically typed values, data definitions become enums and case
+//! expressions `match`es on them. Ops with a concrete annotation additionally
+//! get a typed wrapper.
+
+use crate::desugar::lists;
+use crate::syntax::ast::*;
+use crate::syntax::module_wrapper::ModuleConstrMaps;
+use crate::typing::prelude_types;
+use crate::typing::types::*;
+
+fn parse_parametric<const N: usize>(prefix: &str, s: &str) -> Option<[usize; N]> {
+    let rest = s.strip_prefix(prefix)?;
+    rest.split('-')
+        .map(str::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?
+        .try_into()
+        .ok()
+}
+
+/// Names the generated code itself uses
+const RESERVED: &[&str] = &[
+    "Int", "Quote", "Value", "Stack", "Rc", "Vec", "Box", "Option", "Some", "None", "String",
+    "Self", "Fn",
+];
+
+/// iv identifiers may contain `-` and `+` but never `_`, so escaping them
+/// with `_` can't collide
+/// The index an `Int` literal gives the `pick` or `roll` after it
+fn literal_index(op: &Op) -> Option<usize> {
+    match op {
+        Op::Literal {
+            value: Literal::Int(n),
+            ..
+        } => usize::try_from(*n).ok(),
+        _ => None,
+    }
+}
+
+fn mangle(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        match c {
+            '-' => out.push_str("_m"),
+            '+' => out.push_str("_p"),
+            '#' => out.push_str("_o"),
+            c => out.push(c),
+        }
+    }
+    if RESERVED.contains(&out.as_str()) {
+        out.push('_');
+    }
+    out
+}
+
+fn op_fn(name: &str) -> String {
+    format!("op_{}", mangle(name))
+}
+
+fn const_fn(name: &str) -> String {
+    format!("const_{}", mangle(name))
+}
+
+fn data_ident(name: &str) -> String {
+    mangle(name)
+}
+
+fn variant_ident(constr: &str) -> String {
+    let mut chars = constr.chars();
+    let first = chars.next().map(|c| c.to_ascii_uppercase());
+    mangle(&first.into_iter().chain(chars).collect::<String>())
+}
+
+/// `a0, a1, ..` bindings for the fields of a constructor
+fn field_names(arity: usize) -> Vec<String> {
+    (0..arity).map(|i| format!("a{}", i)).collect()
+}
+
+struct Codegen<'m> {
+    module: &'m Module,
+    constr_maps: ModuleConstrMaps<'m>,
+    out: String,
+    indent: usize,
+}
+
+impl<'m> Codegen<'m> {
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn open(&mut self, line: &str) {
+        self.line(line);
+        self.indent += 1;
+    }
+
+    fn close(&mut self, line: &str) {
+        self.indent -= 1;
+        self.line(line);
+    }
+
+    fn data_def_of(&self, constr: &str) -> Option<(&'m String, &'m DataDef)> {
+        self.constr_maps.constr_to_data_map.get(constr).copied()
+    }
+
+    fn arity(&self, constr: &str) -> usize {
+        self.constr_maps.constr_to_constr_map[constr].params.len()
+    }
+
+    /// The constructor pattern, binding the fields as `a0, a1, ..`
+    fn constr_pattern(&self, data_name: &str, constr: &str) -> String {
+        let fields = field_names(self.arity(constr));
+        let path = format!("{}::{}", data_ident(data_name), variant_ident(constr));
+        match fields.is_empty() {
+            true => path,
+            false => format!("{}({})", path, fields.join(", ")),
+        }
+    }
+
+    /// Pushes bound fields so that the first ends up on top
+    fn push_fields(&mut self, arity: usize) {
+        for field in field_names(arity).iter().rev() {
+            self.line(&format!("s.push({}.clone());", field));
+        }
+    }
+
+    fn header(&mut self) {
+        self.line("// generated by iv, do not edit");
+        self.line("#![allow(dead_code, non_camel_case_types, non_snake_case, unreachable_code)]");
+        self.line("#![allow(unused_mut, unused_variables, unreachable_patterns, clippy::all)]");
+        self.line("");
+        self.line("use std::fmt;");
+        self.line("use std::rc::Rc;");
+        self.line("");
+        self.line("pub type Stack = Vec<Value>;");
+        self.line("");
+        self.line("#[derive(Clone)]");
+        self.open("pub enum Value {");
+        self.line("Int(i32),");
+        self.line("Quote(Rc<dyn Fn(&mut Stack)>),");
+        for (data_name, _) in self.module.data_defs_in_source_order() {
+            let ident = data_ident(data_name);
+            self.line(&format!("{}(Rc<{}>),", ident, ident));
+        }
+        self.close("}");
+        self.line("");
+        self.open("impl Value {");
+        self.open("fn exec(self, s: &mut Stack) {");
+        self.open("match self {");
+        self.line("Value::Quote(q) => q(s),");
+        self.line("_ => unreachable!(\"not a quote\"),");
+        self.close("}");
+        self.close("}");
+        self.line("");
+        self.open("fn fix(self, s: &mut Stack) {");
+        self.line("let again = self.clone();");
+        self.line("s.push(Value::Quote(Rc::new(move |s: &mut Stack| again.clone().fix(s))));");
+        self.line("self.exec(s);");
+        self.close("}");
+        self.close("}");
+        self.line("");
+        self.open("impl fmt::Debug for Value {");
+        self.open("fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {");
+        self.open("match self {");
+        self.line("Value::Int(n) => write!(f, \"{}\", n),");
+        self.line("Value::Quote(_) => write!(f, \"[...]\"),");
+        for (data_name, _) in self.module.data_defs_in_source_order() {
+            let ident = data_ident(data_name);
+            self.line(&format!("Value::{}(d) => write!(f, \"{{:?}}\", d),", ident));
+        }
+        self.close("}");
+        self.close("}");
+        self.close("}");
+    }
+
+    fn data_def(&mut self, data_name: &str, data_def: &DataDef) {
+        let ident = data_ident(data_name);
+        let constrs = data_def.constrs_in_source_order();
+        self.line("");
+        self.open(&format!("pub enum {} {{", ident));
+        for (constr, constr_def) in constrs.iter() {
+            let fields = vec!["Value"; constr_def.params.len()];
+            match fields.is_empty() {
+                true => self.line(&format!("{},", variant_ident(constr))),
+                false => self.line(&format!(
+                    "{}({}),",
+                    variant_ident(constr),
+                    fields.join(", ")
+                )),
+            }
+        }
+        self.close("}");
+        // rendered like the interpreter renders values
+        self.line("");
+        self.open(&format!("impl fmt::Debug for {} {{", ident));
+        self.open("fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {");
+        self.open("match self {");
+        for (constr, _) in constrs.iter() {
+            let fields = field_names(self.arity(constr));
+            let pattern = self.constr_pattern(data_name, constr);
+            if fields.is_empty() {
+                self.line(&format!("{} => write!(f, \"{}\"),", pattern, constr));
+            } else {
+                let format = format!("({}{})", constr, " {:?}".repeat(fields.len()));
+                self.line(&format!(
+                    "{} => write!(f, \"{}\", {}),",
+                    pattern,
+                    format,
+                    fields.join(", ")
+                ));
+            }
+        }
+        self.close("}");
+        self.close("}");
+        self.close("}");
+    }
+
+    /// Emits prelude ops, returns false for other names
+    fn prelude_op(&mut self, name: &str) -> bool {
+        if let Some([n]) = parse_parametric("br-", name) {
+            self.line(&format!(
+                "{{ let v = s.pop().unwrap(); let at = s.len() - {}; s.insert(at, v); }}",
+                n
+            ));
+        } else if let Some([n]) = parse_parametric("dg-", name) {
+            self.line(&format!(
+                "{{ let at = s.len() - 1 - {}; let v = s.remove(at); s.push(v); }}",
+                n
+            ));
+        } else if let Some([_, _, _, _]) = parse_parametric("comp-", name) {
+            self.open("{");
+            self.line("let b = s.pop().unwrap();");
+            self.line("let a = s.pop().unwrap();");
+            self.line(
+                "s.push(Value::Quote(Rc::new(move |s: &mut Stack| { a.clone().exec(s); b.clone().exec(s); })));",
+            );
+            self.close("}");
+        } else if let Some([_, _]) = parse_parametric("exec-", name) {
+            self.line("s.pop().unwrap().exec(s);");
+        } else if let Some([_, _]) = parse_parametric("fix-", name) {
+            self.line("s.pop().unwrap().fix(s);");
+        } else if name == "pick" || name == "roll" {
+            // only an unchecked body gets here, a checked one has the index
+            // baked in by `ops`
+            self.open("{");
+            self.line(
+                "let Some(Value::Int(n @ 0..)) = s.pop() else { panic!(\"not an index\"); };",
+            );
+            self.indexed(name, "n as usize");
+            self.close("}");
+        } else if name == "dup" {
+            self.line("{ let v = s.last().unwrap().clone(); s.push(v); }");
+        } else if name == "pop" {
+            self.line("s.pop();");
+        } else if name == "quote" {
+            self.line(
+                "{ let v = s.pop().unwrap(); s.push(Value::Quote(Rc::new(move |s: &mut Stack| s.push(v.clone())))); }",
+            );
+        } else if name == "assert" {
+            let holds = match self.data_def_of("true") {
+                Some((data_name, _)) => format!(
+                    "matches!(s.pop().unwrap(), Value::{}(d) if matches!(&*d, {}))",
+                    data_ident(data_name),
+                    self.constr_pattern(data_name, "true")
+                ),
+                // without the constructor no value is `true`
+                None => "s.pop().is_none()".to_owned(),
+            };
+            self.line(&format!(
+                "if !{} {{ panic!(\"assertion failed\"); }}",
+                holds
+            ));
+        } else if name == "panic" {
+            self.line("panic!(\"panicked\");");
+        } else if name == "trace" {
+            self.line("println!(\"tracing: {:?}\", s.iter().rev().collect::<Vec<_>>());");
+        } else if let Some(expansion) = prelude_types::expand(name) {
+            for name in expansion {
+                self.prelude_op(&name);
+            }
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Emits `pick` or `roll` of the index `n`, a Rust expression
+    fn indexed(&mut self, name: &str, n: &str) {
+        let take = match name {
+            "pick" => "s[at].clone()",
+            _ => "s.remove(at)",
+        };
+        self.line(&format!(
+            "{{ let at = s.len() - 1 - {}; let v = {}; s.push(v); }}",
+            n, take
+        ));
+    }
+
+    fn name(&mut self, name: &str) {
+        // same lookup order as the evaluator
+        if self.prelude_op(name) {
+        } else if self.module.const_defs.contains_key(name) {
+            self.line(&format!("{}(s);", const_fn(name)));
+        } else if self.module.op_defs.contains_key(name) {
+            self.line(&format!("{}(s);", op_fn(name)));
+        } else if let Some((data_name, _)) = self.data_def_of(name) {
+            let fields = field_names(self.arity(name));
+            let value = format!(
+                "Value::{}(Rc::new({}))",
+                data_ident(data_name),
+                self.constr_pattern(data_name, name)
+            );
+            if fields.is_empty() {
+                self.line(&format!("s.push({});", value));
+            } else {
+                self.open("{");
+                for field in fields.iter() {
+                    self.line(&format!("let {} = s.pop().unwrap();", field));
+                }
+                self.line(&format!("s.push({});", value));
+                self.close("}");
+            }
+        } else {
+            self.line(&format!("unimplemented!(\"unknown op `{}`\");", name));
+        }
+    }
+
+    fn case(&mut self, arms: Vec<(&String, &[Op])>) {
+        let Some((data_name, _)) = self.data_def_of(arms[0].0) else {
+            self.line(&format!(
+                "unimplemented!(\"unknown constructor `{}`\");",
+                arms[0].0
+            ));
+            return;
+        };
+        let ident = data_ident(data_name);
+        self.open("match s.pop().unwrap() {");
+        self.open(&format!("Value::{}(d) => match &*d {{", ident));
+        for (constr, body) in arms {
+            self.open(&format!("{} => {{", self.constr_pattern(data_name, constr)));
+            self.push_fields(self.arity(constr));
+            self.ops(body);
+            self.close("}");
+        }
+        self.close("},");
+        self.line(&format!("_ => unreachable!(\"not a `{}`\"),", data_name));
+        self.close("}");
+    }
+
+    fn pattern_cond(&self, pattern: &Pattern, value: &str) -> String {
+        match pattern {
+            Pattern::Wildcard => "true".to_owned(),
+            Pattern::Constr(constr) => match self.data_def_of(constr) {
+                Some((data_name, _)) => format!(
+                    "matches!(&{}, Value::{}(d) if matches!(**d, {}::{} {{ .. }}))",
+                    value,
+                    data_ident(data_name),
+                    data_ident(data_name),
+                    variant_ident(constr)
+                ),
+                None => "false".to_owned(),
+            },
+        }
+    }
+
+    fn pattern_push(&mut self, pattern: &Pattern, value: &str) {
+        let data_def = match pattern {
+            Pattern::Constr(constr) => self.data_def_of(constr).map(|(d, _)| (constr, d)),
+            Pattern::Wildcard => None,
+        };
+        match data_def {
+            Some((constr, data_name)) => {
+                let arity = self.arity(constr);
+                if arity == 0 {
+                    return;
+                }
+                self.open(&format!(
+                    "if let Value::{}(d) = &{} {{",
+                    data_ident(data_name),
+                    value
+                ));
+                self.open(&format!(
+                    "if let {} = &**d {{",
+                    self.constr_pattern(data_name, constr)
+                ));
+                self.push_fields(arity);
+                self.close("}");
+                self.close("}");
+            }
+            None => self.line(&format!("s.push({}.clone());", value)),
+        }
+    }
+
+    fn case2(&mut self, arms: &[Case2Arm]) {
+        self.open("{");
+        self.line("let v1 = s.pop().unwrap();");
+        self.line("let v2 = s.pop().unwrap();");
+        for (i, arm) in arms.iter().enumerate() {
+            let [p1, p2] = &arm.patterns;
+            let cond = format!(
+                "{} && {}",
+                self.pattern_cond(p1, "v1"),
+                self.pattern_cond(p2, "v2")
+            );
+            match i {
+                0 => self.open(&format!("if {} {{", cond)),
+                _ => {
+                    self.indent -= 1;
+                    self.open(&format!("}} else if {} {{", cond));
+                }
+            }
+            // the lower value first so that the topmost one's fields end up on top
+            self.pattern_push(p2, "v2");
+            self.pattern_push(p1, "v1");
+            self.ops(&arm.body);
+        }
+        if !arms.is_empty() {
+            self.indent -= 1;
+            self.open("} else {");
+            self.line("unreachable!(\"no case2 arm matches\");");
+            self.close("}");
+        }
+        self.close("}");
+    }
+
+    fn op(&mut self, op: &Op) {
+        match op {
+            Op::Literal {
+                value: Literal::Int(n),
+                ..
+            } => self.line(&format!("s.push(Value::Int({}));", n)),
+            Op::Literal {
+                value: Literal::Extern { repr, .. },
+                ..
+            } => self.line(&format!("unimplemented!(\"extern literal `{}`\");", repr)),
+            Op::Name { value, .. } => self.name(value),
+            Op::Quote { value, .. } => {
+                self.open("s.push(Value::Quote(Rc::new(|s: &mut Stack| {");
+                self.ops(value);
+                self.close("})));");
+            }
+            Op::Case { head_arm, arms, .. } => {
+                let arms = std::iter::once(head_arm)
+                    .chain(arms)
+                    .map(|arm| (&arm.constr, &arm.body[..]))
+                    .collect();
+                self.case(arms);
+            }
+            Op::Case2 { arms, .. } => self.case2(arms),
+            // a module not desugared, generated as it would be once it is
+            Op::List { items, span } => self.ops(&lists::expand(items.clone(), span)),
+        }
+    }
+
+    fn ops(&mut self, ops: &[Op]) {
+        let mut ops = ops.iter().peekable();
+        while let Some(op) = ops.next() {
+            // a literal index is baked into the `pick` or `roll` after it
+            if let (Some(n), Some(Op::Name { value, .. })) = (literal_index(op), ops.peek()) {
+                if value == "pick" || value == "roll" {
+                    self.indexed(value, &n.to_string());
+                    ops.next();
+                    continue;
+                }
+            }
+            self.op(op);
+        }
+    }
+
+    /// The Rust type of a value of a concrete, non-generic type
+    fn rust_type(&self, t: &Type) -> Option<(String, String)> {
+        match t {
+            Type::Mono(name) if name == "Int" => Some(("i32".to_owned(), "Int".to_owned())),
+            Type::Mono(name) => match self.module.data_defs.get(name) {
+                Some(data_def) if data_def.params.is_empty() => {
+                    let ident = data_ident(name);
+                    Some((format!("Rc<{}>", ident), ident))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// A wrapper with a Rust signature for ops with a concrete annotation
+    fn typed_wrapper(&mut self, name: &str, ann: &OpType) {
+        let types = |stack: &[Type]| -> Option<Vec<(String, String)>> {
+            stack.iter().map(|t| self.rust_type(t)).collect()
+        };
+        let (Some(pre), Some(post)) = (types(&ann.pre), types(&ann.post)) else {
+            return;
+        };
+        let params: Vec<_> = pre
+            .iter()
+            .enumerate()
+            .map(|(i, (ty, _))| format!("x{}: {}", i, ty))
+            .collect();
+        let results: Vec<_> = post.iter().map(|(ty, _)| format!("{},", ty)).collect();
+        self.line("");
+        self.open(&format!(
+            "pub fn typed_{}({}) -> ({}) {{",
+            mangle(name),
+            params.join(", "),
+            results.join(" ")
+        ));
+        self.line("let mut s = Stack::new();");
+        for (i, (_, variant)) in pre.iter().enumerate().rev() {
+            self.line(&format!("s.push(Value::{}(x{}));", variant, i));
+        }
+        self.line(&format!("{}(&mut s);", op_fn(name)));
+        for (i, (_, variant)) in post.iter().enumerate() {
+            self.line(&format!(
+                "let r{} = match s.pop() {{ Some(Value::{}(v)) => v, _ => unreachable!() }};",
+                i, variant
+            ));
+        }
+        let results: Vec<_> = (0..post.len()).map(|i| format!("r{},", i)).collect();
+        self.line(&format!("({})", results.join(" ")));
+        self.close("}");
+    }
+
+    fn op_def(&mut self, name: &str, op_def: &OpDef) {
+        self.line("");
+        self.line(&format!("/// `{}` {}", name, op_def.ann));
+        self.open(&format!("pub fn {}(s: &mut Stack) {{", op_fn(name)));
+        if op_def.declaration {
+            self.line(&format!(
+                "panic!(\"op `{}` is declared but has no implementation\");",
+                name
+            ));
+        }
+        self.ops(&op_def.body);
+        self.close("}");
+        self.typed_wrapper(name, &op_def.ann);
+    }
+
+    /// Constants are pure, so running the body again on each use gives
+    /// the value the evaluator caches
+    fn const_def(&mut self, name: &str, const_def: &ConstDef) {
+        self.line("");
+        self.line(&format!("/// `{}` [{}]", name, const_def.ty));
+        self.open(&format!("pub fn {}(s: &mut Stack) {{", const_fn(name)));
+        self.ops(&const_def.body);
+        self.close("}");
+    }
+
+    fn module(&mut self) {
+        self.header();
+        for (data_name, data_def) in self.module.data_defs_in_source_order() {
+            self.data_def(data_name, data_def);
+        }
+        for (const_name, const_def) in self.module.const_defs_in_source_order() {
+            self.const_def(const_name, const_def);
+        }
+        for (op_name, op_def) in self.module.op_defs_in_source_order() {
+            self.op_def(op_name, op_def);
+        }
+        if self.module.op_defs.contains_key("main") {
+            self.line("");
+            self.open("pub fn run_main() -> Stack {");
+            self.line("let mut s = Stack::new();");
+            self.line(&format!("{}(&mut s);", op_fn("main")));
+            self.line("s");
+            self.close("}");
+        }
+    }
+}
+
+/// Emits a standalone Rust module implementing the iv module
+pub fn codegen_rust(module: &Module) -> String {
+    let mut codegen = Codegen {
+        module,
+        constr_maps: ModuleConstrMaps::new(module),
+        out: String::new(),
+        indent: 0,
+    };
+    codegen.module();
+    codegen.out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::rust::*;
+    use crate::syntax::parse;
+
+    #[test]
+    fn mangling() {
+        assert_eq!(op_fn("br-1"), "op_br_m1");
+        assert_eq!(op_fn("a+b"), "op_a_pb");
+        assert_eq!(op_fn("loop"), "op_loop");
+        assert_eq!(data_ident("Value"), "Value_");
+        assert_eq!(data_ident("My-List"), "My_mList");
+        assert_eq!(variant_ident("just"), "Just");
+        assert_eq!(variant_ident("self"), "Self_");
+    }
+
+    #[test]
+    fn data_defs_become_enums() {
+        let module = parse("data Maybe a: nothing, [a] just.").unwrap();
+        let out = codegen_rust(&module);
+        assert!(out.contains("pub enum Maybe {\n    Nothing,\n    Just(Value),\n}"));
+        assert!(out.contains("    Maybe(Rc<Maybe>),\n"));
+    }
+
+    #[test]
+    fn cases_become_matches() {
+        let module = parse(
+            "
+            data Bool: true, false.
+            define [Bool] not [Bool]: case { true { false }, false { true } }.
+            ",
+        )
+        .unwrap();
+        let out = codegen_rust(&module);
+        assert!(out.contains("Value::Bool(d) => match &*d {"));
+        assert!(out.contains("Bool::True => {"));
+        assert!(out.contains("pub fn typed_not(x0: Rc<Bool>) -> (Rc<Bool>,) {"));
+    }
+
+    #[test]
+    fn polymorphic_ops_stay_dynamic() {
+        let module = parse("define [a] mydup [a, a]: dup.").unwrap();
+        let out = codegen_rust(&module);
+        assert!(out.contains("pub fn op_mydup(s: &mut Stack) {"));
+        assert!(!out.contains("typed_mydup"));
+    }
+
+    #[test]
+    fn fix_runs_a_quote_that_fixes_it_again() {
+        let module = parse("define [] forever [a]: (exec-0-1) fix-0-1.").unwrap();
+        let out = codegen_rust(&module);
+        assert!(out.contains("    fn fix(self, s: &mut Stack) {\n"));
+        assert!(out.contains("again.clone().fix(s)"));
+        assert!(out.contains("    s.pop().unwrap().fix(s);\n"));
+    }
+
+    #[test]
+    fn literal_indices_are_baked_in() {
+        let module = parse("define [a, b, c] f [c, b, a, c]: 2 pick 1 roll.").unwrap();
+        let out = codegen_rust(&module);
+        assert!(out.contains("{ let at = s.len() - 1 - 2; let v = s[at].clone(); s.push(v); }"));
+        assert!(out.contains("{ let at = s.len() - 1 - 1; let v = s.remove(at); s.push(v); }"));
+        assert!(!out.contains("Value::Int(2)"));
+    }
+}
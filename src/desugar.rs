@@ -0,0 +1,192 @@
+//! AST-to-AST rewrites between parsing and inference. Every pass turns some
+//! surface construct into plain ops, so that later stages never see it.
+//! The nodes a pass produces get spans made by `Span::expanded_from` the
+//! construct they replace, ops moved over unchanged keep their own.
+
+pub mod lists;
+
+use crate::diagnostics::Diagnostic;
+use crate::syntax::ast::{Module, Op};
+
+pub trait Pass {
+    fn run(&self, module: Module) -> Result<Module, Vec<Diagnostic>>;
+}
+
+/// The passes in the order they run, a pass may rely on the constructs of
+/// the passes before it being gone. `outside` is whether the module may use
+/// definitions outside it.
+fn passes(outside: bool) -> Vec<Box<dyn Pass>> {
+    vec![Box::new(lists::ListLiterals {
+        list_type_outside: outside,
+    })]
+}
+
+/// Runs every pass on the parsed module, stopping at the first that fails
+pub fn desugar(module: Module) -> Result<Module, Vec<Diagnostic>> {
+    run(module, passes(false))
+}
+
+/// `desugar` for a module of definitions that use ones outside it, one of
+/// the definitions `parse_definitions` yields say. The types a construct
+/// needs may be defined outside too, what it becomes is left to fail
+/// inference if they are not, so this does not fail.
+pub fn desugar_definitions(module: Module) -> Module {
+    run(module, passes(true)).expect("no pass looks outside the module")
+}
+
+fn run(module: Module, passes: Vec<Box<dyn Pass>>) -> Result<Module, Vec<Diagnostic>> {
+    passes
+        .iter()
+        .try_fold(module, |module, pass| pass.run(module))
+}
+
+/// Rewrites the ops of every op and constant definition bottom-up: the ops
+/// nested in an op are rewritten before `f` replaces the op itself with any
+/// number of ops
+pub fn rewrite_ops(module: &mut Module, f: &mut impl FnMut(Op) -> Vec<Op>) {
+    for op_def in module.op_defs.values_mut() {
+        let body = std::mem::take(&mut op_def.body);
+        op_def.body = rewrite(body, f);
+    }
+    for const_def in module.const_defs.values_mut() {
+        let body = std::mem::take(&mut const_def.body);
+        const_def.body = rewrite(body, f);
+    }
+}
+
+fn rewrite(ops: Vec<Op>, f: &mut impl FnMut(Op) -> Vec<Op>) -> Vec<Op> {
+    let mut out = vec![];
+    for op in ops {
+        let op = match op {
+            Op::Quote { value, span } => Op::Quote {
+                value: rewrite(value, f),
+                span,
+            },
+            Op::Case {
+                mut head_arm,
+                mut arms,
+                span,
+            } => {
+                for arm in std::iter::once(&mut head_arm).chain(arms.iter_mut()) {
+                    arm.body = rewrite(std::mem::take(&mut arm.body), f);
+                }
+                Op::Case {
+                    head_arm,
+                    arms,
+                    span,
+                }
+            }
+            Op::Case2 { mut arms, span } => {
+                for arm in arms.iter_mut() {
+                    arm.body = rewrite(std::mem::take(&mut arm.body), f);
+                }
+                Op::Case2 { arms, span }
+            }
+            Op::List { items, span } => Op::List {
+                items: items.into_iter().map(|item| rewrite(item, f)).collect(),
+                span,
+            },
+            op @ (Op::Literal { .. } | Op::Name { .. }) => op,
+        };
+        out.extend(f(op));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::desugar;
+    use crate::syntax::ast::{Module, Op, Span, SpanOrigin};
+    use crate::syntax::parse;
+    use std::fs;
+    use std::path::Path;
+
+    /// Every span in the ops, nested ops included
+    fn spans<'m>(ops: &'m [Op], out: &mut Vec<(&'m Op, &'m Span)>) {
+        for op in ops {
+            out.push((op, op.get_span()));
+            match op {
+                Op::Quote { value, .. } => spans(value, out),
+                Op::Case { head_arm, arms, .. } => {
+                    for arm in std::iter::once(head_arm).chain(arms) {
+                        spans(&arm.body, out);
+                    }
+                }
+                Op::Case2 { arms, .. } => {
+                    for arm in arms {
+                        spans(&arm.body, out);
+                    }
+                }
+                Op::List { items, .. } => {
+                    for item in items {
+                        spans(item, out);
+                    }
+                }
+                Op::Literal { .. } | Op::Name { .. } => (),
+            }
+        }
+    }
+
+    fn module_spans(module: &Module) -> Vec<(&Op, &Span)> {
+        let mut out = vec![];
+        for op_def in module.op_defs.values() {
+            spans(&op_def.body, &mut out);
+        }
+        for const_def in module.const_defs.values() {
+            spans(&const_def.body, &mut out);
+        }
+        out
+    }
+
+    /// Ops left with a source span were in the source at that span, the
+    /// others point at a construct of the source they were expanded from
+    fn check_spans(source: &str) {
+        let surface = parse(source).unwrap();
+        let surface_spans = module_spans(&surface);
+        let in_surface = |span: &Span, list: bool| {
+            surface_spans.iter().any(|(op, s)| {
+                s.start == span.start && s.end == span.end && matches!(op, Op::List { .. }) == list
+            })
+        };
+        let desugared = desugar(parse(source).unwrap()).unwrap();
+        for (op, span) in module_spans(&desugared) {
+            assert!(!matches!(op, Op::List { .. }), "{}", op);
+            match &span.origin {
+                SpanOrigin::Source => assert!(in_surface(span, false), "{}", op),
+                SpanOrigin::Desugared(expansion) => {
+                    let from = &expansion.from;
+                    assert_eq!(expansion.construct, "list literal");
+                    assert!(matches!(from.origin, SpanOrigin::Source));
+                    assert!(in_surface(from, true), "{}", op);
+                    assert_eq!((span.start, span.end), (from.start, from.end));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn produced_ops_point_at_what_they_replace() {
+        check_spans(
+            "
+            data List a: empty, [a, List a] cons.
+            define [] f [List (List Int)]: [[1, 2], (3) exec-0-1 [] cons, []].
+            define [] g [[][List [][Int]]]: ([(1), (2 dup pop)]).
+            ",
+        );
+    }
+
+    #[test]
+    fn fixtures_keep_their_spans() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for dir in ["examples", "tests/cases"] {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                let source = fs::read_to_string(&path).unwrap();
+                // fixtures of parse errors have nothing to desugar
+                if path.extension().is_some_and(|ext| ext == "iv") && parse(&source).is_ok() {
+                    check_spans(&source);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use super::{rewrite_ops, Pass};
+use crate::diagnostics::Diagnostic;
+use crate::syntax::ast::{Module, Op, Span};
+
+/// `[e1, e2]` becomes `empty e2 cons e1 cons`, so the first item ends up at
+/// the head of the list. Needs a data type with an `empty` constructor and a
+/// `cons` constructor of two fields, their types are left to inference.
+pub struct ListLiterals {
+    /// Whether the data type may be defined outside the module, which is
+    /// then not checked for one
+    pub list_type_outside: bool,
+}
+
+const CONSTRUCT: &str = "list literal";
+
+fn has_list_type(module: &Module) -> bool {
+    module.data_defs.values().any(|data_def| {
+        let fields = |name| data_def.constrs.get(name).map(|c| c.params.len());
+        fields("empty") == Some(0) && fields("cons") == Some(2)
+    })
+}
+
+/// The ops the list literal of the items at `span` becomes
+pub fn expand(items: Vec<Vec<Op>>, span: &Span) -> Vec<Op> {
+    let name = |value: &str, index| Op::Name {
+        value: value.to_owned(),
+        span: Span::expanded_from(span, CONSTRUCT, index),
+    };
+    let mut ops = vec![name("empty", 0)];
+    for (i, item) in items.into_iter().rev().enumerate() {
+        ops.extend(item);
+        ops.push(name("cons", i + 1));
+    }
+    ops
+}
+
+/// The ops with the list literals among them expanded, for the stages
+/// given a module that was not desugared. Literals in quotes and arms are
+/// left for when their ops run.
+pub fn expand_in(ops: &[Op]) -> Vec<Op> {
+    let mut out = vec![];
+    for op in ops {
+        match op {
+            Op::List { items, span } => out.extend(expand_in(&expand(items.clone(), span))),
+            op => out.push(op.clone()),
+        }
+    }
+    out
+}
+
+impl Pass for ListLiterals {
+    fn run(&self, mut module: Module) -> Result<Module, Vec<Diagnostic>> {
+        let has_list_type = self.list_type_outside || has_list_type(&module);
+        let mut diagnostics = vec![];
+        rewrite_ops(&mut module, &mut |op| match op {
+            Op::List { items, span } => {
+                if !has_list_type {
+                    diagnostics.push(Diagnostic {
+                        span: span.clone(),
+                        message: "a list literal needs the constructors `empty` and `cons` \
+                                  of a list type\nlike `data List a: empty, [a, List a] cons.`"
+                            .to_owned(),
+                        suggestions: vec![],
+                        code: None,
+                    });
+                }
+                expand(items, &span)
+            }
+            op => vec![op],
+        });
+        if diagnostics.is_empty() {
+            Ok(module)
+        } else {
+            Err(diagnostics)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::rust::codegen_rust;
+    use crate::desugar::desugar;
+    use crate::evaluation::display::{display_stack, DisplayOptions};
+    use crate::evaluation::evaluator::Evaluator;
+    use crate::syntax::parse;
+    use crate::typing::inference::Inference;
+
+    const LIST: &str = "data List a: empty, [a, List a] cons.";
+
+    #[test]
+    fn first_item_is_the_head() {
+        let source = format!("{} define [] main [List Int]: [1, 2, 3].", LIST);
+        let module = desugar(parse(&source).unwrap()).unwrap();
+        assert_eq!(
+            module.op_defs["main"].body[..]
+                .iter()
+                .map(|op| op.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            "empty 3 cons 2 cons 1 cons"
+        );
+        assert!(Inference::new(&module).typecheck().is_ok());
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        assert_eq!(
+            display_stack(&evaluator.stack, &DisplayOptions::default()),
+            "[(cons 1 (cons 2 (cons 3 empty)))]"
+        );
+    }
+
+    #[test]
+    fn modules_not_desugared_run_as_if_they_were() {
+        let source = format!(
+            "{} define [] main [List (List Int)]: [[1], []] // ( -- List (List Int) )\n.",
+            LIST
+        );
+        let module = parse(&source).unwrap();
+        let report = Inference::new(&module).report();
+        assert!(report.is_ok(), "{:?}", report);
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        assert_eq!(
+            display_stack(&evaluator.stack, &DisplayOptions::default()),
+            "[(cons (cons 1 empty) (cons empty empty))]"
+        );
+        let desugared = desugar(module.clone()).unwrap();
+        assert_eq!(codegen_rust(&module), codegen_rust(&desugared));
+    }
+
+    #[test]
+    fn needs_a_list_type() {
+        let source = "data Nat: zero, [Nat] suc.
+            define [] f []: [] [zero] pop pop.";
+        let diagnostics = desugar(parse(source).unwrap()).unwrap_err();
+        let starts: Vec<_> = diagnostics.iter().map(|d| d.span.start).collect();
+        assert_eq!(
+            starts,
+            vec![
+                source.find("[] [zero]").unwrap(),
+                source.find("[zero]").unwrap()
+            ]
+        );
+        assert!(diagnostics[0]
+            .message
+            .starts_with("a list literal needs the constructors `empty` and `cons`"));
+    }
+}
@@ -0,0 +1,874 @@
+pub mod explanations;
+pub mod fixes;
+pub mod kind;
+
+use crate::analysis::data_params::ParamWarning;
+use crate::analysis::deprecated::DeprecationWarning;
+use crate::analysis::leftovers::LeftoverWarning;
+use crate::analysis::literals::LeadingZeroWarning;
+use crate::analysis::output_vars::OutputVarWarning;
+use crate::analysis::termination::TerminationWarning;
+use crate::analysis::underflow::Underflow;
+use crate::analysis::unreachable::UnreachableWarning;
+use crate::annotate::AnnotationWarning;
+use crate::evaluation::types::RuntimeError;
+use crate::optimize::RejectedRewrite;
+use crate::syntax::ast::{overloaded_name, Module, Span, SpanOrigin};
+use crate::syntax::attributes::AttributeProblem;
+use crate::syntax::checkpoints::CheckpointWarning;
+use crate::syntax::highlight::{self, TokenKind};
+use crate::syntax::{self, LexingError};
+use crate::typing::diff::written_vars;
+use crate::typing::inference::{Inference, InferenceError, InferenceErrorMessage};
+use fixes::Fix;
+use kind::DiagnosticKind;
+use lalrpop_util::ParseError;
+
+/// How many calls of a runtime error's trace are shown by default
+pub const MAX_FRAMES: usize = 16;
+
+/// Line and column of a byte offset, both starting from 1
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, offset - line_start + 1)
+}
+
+/// Renders a message with its position and the source line underlined
+/// from the start of the span. Lines after the first line of the message
+/// go below the source line.
+pub fn render(source: &str, span: &Span, message: &str) -> String {
+    let (line_n, col_n) = line_col(source, span.start);
+    let line = source.lines().nth(line_n - 1).unwrap_or("");
+    let (headline, details) = match message.split_once('\n') {
+        Some((headline, details)) => (headline, format!("{}\n", details)),
+        None => (message, String::new()),
+    };
+    format!(
+        "{}:{}: {}\n{}\n{}^\n{}",
+        line_n,
+        col_n,
+        headline,
+        line,
+        " ".repeat(col_n - 1),
+        details
+    )
+}
+
+pub fn render_inference_error(source: &str, err: &InferenceError) -> String {
+    render(source, &err.span, &err.message())
+}
+
+/// The kind of a parse error, as `iv explain` takes it
+pub fn parse_error_code(err: &syntax::ParseError<'_>) -> &'static str {
+    match err {
+        ParseError::InvalidToken { .. } => "InvalidToken",
+        ParseError::UnrecognizedEof { .. } => "UnrecognizedEof",
+        ParseError::UnrecognizedToken { .. } => "UnrecognizedToken",
+        ParseError::ExtraToken { .. } => "ExtraToken",
+        ParseError::User { error } => match error {
+            LexingError::InvalidInteger => "InvalidInteger",
+            LexingError::UnknownPragma => "UnknownPragma",
+            LexingError::AppliedTypeVariable { .. } => "AppliedTypeVariable",
+            LexingError::UnknownLiteralSuffix { .. } => "UnknownLiteralSuffix",
+            LexingError::InvalidLiteral { .. } => "InvalidLiteral",
+            LexingError::Unexpected => "UnexpectedCharacter",
+        },
+    }
+}
+
+/// A message about a span of the source, whatever stage produced it
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+    pub suggestions: Vec<Fix>,
+    /// The kind of error, as `iv explain` takes it, for errors that have one
+    pub code: Option<String>,
+}
+
+impl Diagnostic {
+    /// The kind of the code, for diagnostics that have one
+    pub fn kind(&self) -> Option<DiagnosticKind> {
+        self.code.as_deref().map(DiagnosticKind::of)
+    }
+
+    pub fn from_parse_error(source: &str, err: &syntax::ParseError<'_>) -> Self {
+        let code = Some(parse_error_code(err).to_owned());
+        let at = Span::new;
+        let (span, message) = match err {
+            ParseError::InvalidToken { location } => {
+                (at(*location, *location), "invalid token".to_owned())
+            }
+            ParseError::UnrecognizedEof { location, expected } => (
+                at(*location, *location),
+                format!("unexpected end of input{}", expected_list(expected)),
+            ),
+            ParseError::UnrecognizedToken {
+                token: (start, _, end),
+                expected,
+            } => (
+                at(*start, *end),
+                format!(
+                    "unexpected `{}`{}",
+                    &source[*start..*end],
+                    expected_list(expected)
+                ),
+            ),
+            ParseError::ExtraToken {
+                token: (start, _, end),
+            } => (
+                at(*start, *end),
+                format!("extra token `{}`", &source[*start..*end]),
+            ),
+            ParseError::User {
+                error: LexingError::AppliedTypeVariable { name, start, end },
+            } => (
+                at(*start, *end),
+                format!(
+                    "type variable `{}` cannot be applied to arguments\n\
+                     only data types take arguments, like `Maybe` in `Maybe a`",
+                    name
+                ),
+            ),
+            ParseError::User {
+                error:
+                    LexingError::UnknownLiteralSuffix {
+                        suffix,
+                        start,
+                        end,
+                        known,
+                    },
+            } => {
+                let note = match &known[..] {
+                    [] => "no literal suffixes are registered".to_owned(),
+                    known => format!("the known suffixes are {}", known.join(", ")),
+                };
+                (
+                    at(*start, *end),
+                    format!("unknown literal suffix `{}`\n{}", suffix, note),
+                )
+            }
+            ParseError::User {
+                error:
+                    LexingError::InvalidLiteral {
+                        message,
+                        start,
+                        end,
+                    },
+            } => (
+                at(*start, *end),
+                format!("invalid literal `{}`: {}", &source[*start..*end], message),
+            ),
+            ParseError::User {
+                error: LexingError::InvalidInteger,
+            } => {
+                let start = syntax::error_location(source, &[], err);
+                let len = source[start..]
+                    .char_indices()
+                    .find(|&(i, c)| !(c.is_ascii_digit() || i == 0 && "+-".contains(c)))
+                    .map_or(source.len() - start, |(i, _)| i);
+                (
+                    at(start, start + len),
+                    format!(
+                        "`{}` does not fit in an Int, which goes from {} to {}",
+                        &source[start..start + len],
+                        i32::MIN,
+                        i32::MAX
+                    ),
+                )
+            }
+            ParseError::User { error } => {
+                let location = syntax::error_location(source, &[], err);
+                (at(location, location), format!("lexing error: {:?}", error))
+            }
+        };
+        // where the parser gave up and what it would have taken there
+        let (upto, expected) = match err {
+            ParseError::UnrecognizedEof { expected, .. } => (source.len(), expected),
+            ParseError::UnrecognizedToken {
+                token: (start, _, _),
+                expected,
+            } => (*start, expected),
+            _ => {
+                return Diagnostic {
+                    span,
+                    message,
+                    suggestions: fixes::suggest_for_parse_error(err),
+                    code,
+                }
+            }
+        };
+        match bracket_note(source, upto) {
+            // the bracket is the mistake, the token the parser stopped at
+            // may be far away
+            Some(BracketNote::Unclosed(opening)) => {
+                let (line, col) = line_col(source, span.start);
+                Diagnostic {
+                    message: format!(
+                        "`{}` is never closed\n{}:{}: {}",
+                        &source[opening.start..opening.end],
+                        line,
+                        col,
+                        message
+                    ),
+                    span: opening,
+                    suggestions: vec![],
+                    code,
+                }
+            }
+            Some(BracketNote::Other(bracket_note)) => Diagnostic {
+                span,
+                message: format!("{}\n{}", message, bracket_note),
+                suggestions: vec![],
+                code,
+            },
+            None => Diagnostic {
+                span,
+                message: format!("{}{}", message, note(arrow_note(source, upto, expected))),
+                suggestions: vec![],
+                code,
+            },
+        }
+    }
+
+    pub fn from_inference_error(source: &str, module: &Module, err: &InferenceError) -> Self {
+        Diagnostic {
+            span: err.span.clone(),
+            message: format!(
+                "{}{}{}{}",
+                err.error,
+                note(expansion_note(source, &err.span)),
+                note(
+                    declaration_note(source, module, &err.span)
+                        .or_else(|| deprecated_op_note(source, module, err))
+                        .or_else(|| overload_note(source, err))
+                ),
+                note(blame_note(source, module, err))
+            ),
+            suggestions: fixes::suggest(source, module, err),
+            code: Some(err.code().to_owned()),
+        }
+    }
+
+    pub fn from_termination_warning(warning: &TerminationWarning) -> Self {
+        Diagnostic {
+            span: warning.span.clone(),
+            message: warning.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    pub fn from_underflow(underflow: &Underflow) -> Self {
+        Diagnostic {
+            span: underflow.span.clone(),
+            message: underflow.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    pub fn from_output_var_warning(warning: &OutputVarWarning) -> Self {
+        Diagnostic {
+            span: warning.span.clone(),
+            message: warning.to_string(),
+            suggestions: vec![],
+            code: Some("OutputOnlyVar".to_owned()),
+        }
+    }
+
+    pub fn from_deprecation_warning(source: &str, warning: &DeprecationWarning) -> Self {
+        let (line, col) = line_col(source, warning.declared.start);
+        Diagnostic {
+            span: warning.span.clone(),
+            message: format!(
+                "{}\n`{}` is declared at {}:{}",
+                warning, warning.name, line, col
+            ),
+            suggestions: vec![],
+            code: Some("Deprecated".to_owned()),
+        }
+    }
+
+    pub fn from_leftover_warning(warning: &LeftoverWarning) -> Self {
+        Diagnostic {
+            span: warning.span.clone(),
+            message: warning.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    pub fn from_unreachable_warning(warning: &UnreachableWarning) -> Self {
+        Diagnostic {
+            span: warning.span.clone(),
+            message: warning.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    pub fn from_leading_zero_warning(warning: &LeadingZeroWarning) -> Self {
+        Diagnostic {
+            span: warning.span.clone(),
+            message: warning.to_string(),
+            suggestions: vec![],
+            code: Some(LeadingZeroWarning::CODE.to_owned()),
+        }
+    }
+
+    pub fn from_param_warning(warning: &ParamWarning) -> Self {
+        Diagnostic {
+            span: warning.span.clone(),
+            message: warning.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    pub fn from_checkpoint_warning(warning: &CheckpointWarning) -> Self {
+        Diagnostic {
+            span: warning.span.clone(),
+            message: warning.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    pub fn from_attribute_problem(problem: &AttributeProblem) -> Self {
+        Diagnostic {
+            span: problem.span.clone(),
+            message: problem.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    pub fn from_annotation_warning(warning: &AnnotationWarning) -> Self {
+        Diagnostic {
+            span: warning.span.clone(),
+            message: warning.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    pub fn from_rejected_rewrite(rejected: &RejectedRewrite) -> Self {
+        Diagnostic {
+            span: rejected.span.clone(),
+            message: rejected.to_string(),
+            suggestions: vec![],
+            code: None,
+        }
+    }
+
+    /// The error, where its value was made if known, followed by the calls
+    /// it happened in, innermost first, at most `max_frames` of them
+    pub fn from_runtime_error(source: &str, err: &RuntimeError, max_frames: usize) -> Self {
+        let mut message = err.message();
+        if let Some(origin) = &err.origin {
+            let (line, col) = line_col(source, origin.start);
+            message.push_str(&format!(
+                "\n  the offending value was created at {}:{}",
+                line, col
+            ));
+        }
+        for frame in err.trace.iter().take(max_frames) {
+            let (line, col) = line_col(source, frame.span.start);
+            match &frame.op {
+                Some(op) => {
+                    message.push_str(&format!("\n  in `{}`, called at {}:{}", op, line, col))
+                }
+                None => message.push_str(&format!("\n  in the quote at {}:{}", line, col)),
+            }
+        }
+        if err.trace.len() > max_frames {
+            message.push_str(&format!(
+                "\n  … {} frames omitted",
+                err.trace.len() - max_frames
+            ));
+        }
+        Diagnostic {
+            span: err.span.clone(),
+            message,
+            suggestions: vec![],
+            code: Some(err.code().to_owned()),
+        }
+    }
+
+    /// Adds a line to the end of the message
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.message.push('\n');
+        self.message.push_str(note);
+        self
+    }
+
+    /// Like `render`, followed by a `help:` line per suggestion
+    pub fn render(&self, source: &str) -> String {
+        let mut out = render(source, &self.span, &self.message);
+        for fix in self.suggestions.iter() {
+            out.push_str(&format!("help: {}\n", fix.title));
+        }
+        out
+    }
+
+    /// The diagnostic as a JSON object, the span both as byte offsets and as
+    /// line and column pairs starting from 1
+    pub fn to_json(&self, source: &str) -> String {
+        let (line, col) = line_col(source, self.span.start);
+        let (end_line, end_col) = line_col(source, self.span.end);
+        let suggestions: Vec<_> = self.suggestions.iter().map(Fix::to_json).collect();
+        let code = self.code.as_deref().map_or("null".to_owned(), json_string);
+        format!(
+            "{{\"message\":{},\"code\":{},\"start\":{},\"end\":{},\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{},\"rendered\":{},\"suggestions\":[{}]}}",
+            json_string(&self.message),
+            code,
+            self.span.start,
+            self.span.end,
+            line,
+            col,
+            end_line,
+            end_col,
+            json_string(&self.render(source)),
+            suggestions.join(",")
+        )
+    }
+}
+
+/// Where the constructor named under the span is declared
+fn declaration_note(source: &str, module: &Module, span: &Span) -> Option<String> {
+    let name = source.get(span.start..span.end)?;
+    let constr = module
+        .data_defs
+        .values()
+        .find_map(|data_def| data_def.constrs.get(name))?;
+    let (line, col) = line_col(source, constr.name_span.start);
+    Some(format!("`{}` is declared at {}:{}", name, line, col))
+}
+
+/// Where the deprecated op a strict mode error is about is declared
+fn deprecated_op_note(source: &str, module: &Module, err: &InferenceError) -> Option<String> {
+    let InferenceErrorMessage::Deprecated { name, .. } = &err.error else {
+        return None;
+    };
+    let (line, col) = line_col(source, module.op_defs.get(name)?.name_span.start);
+    Some(format!("`{}` is declared at {}:{}", name, line, col))
+}
+
+/// Where the definitions of the overloaded name an error lists are
+fn overload_note(source: &str, err: &InferenceError) -> Option<String> {
+    let candidates = match &err.error {
+        InferenceErrorMessage::OverloadsIndistinct { candidates, .. }
+        | InferenceErrorMessage::AmbiguousOverload { candidates, .. }
+        | InferenceErrorMessage::NoOverloadFits { candidates, .. }
+        | InferenceErrorMessage::OverloadNeedsContext { candidates, .. } => candidates,
+        _ => return None,
+    };
+    let places: Vec<_> = candidates
+        .iter()
+        .map(|candidate| {
+            let (line, col) = line_col(source, candidate.span.start);
+            format!("{}:{}", line, col)
+        })
+        .collect();
+    let name = overloaded_name(&candidates.first()?.key);
+    Some(format!("`{}` is defined at {}", name, places.join(", ")))
+}
+
+/// Which op of the body made the first variable of the annotation that
+/// the body does not keep general less general
+fn blame_note(source: &str, module: &Module, err: &InferenceError) -> Option<String> {
+    let InferenceErrorMessage::AnnInfConflict { .. } = err.error else {
+        return None;
+    };
+    let (name, op_def) = module.op_defs.iter().find(|(_, op_def)| {
+        op_def.span.start == err.span.start && op_def.span.end == err.span.end
+    })?;
+    let mut vars = vec![];
+    for t in op_def.ann.pre.iter().chain(&op_def.ann.post) {
+        written_vars(t, &mut vars);
+    }
+    let inference = Inference::new(module);
+    vars.iter().find_map(|var| {
+        let site = inference.blame(name, var).into_iter().next()?;
+        let (line, col) = line_col(source, site.span.start);
+        Some(format!(
+            "`{}` became `{}` because of `{}` at {}:{}",
+            var, site.became, site.op, line, col
+        ))
+    })
+}
+
+/// Which construct the code under a desugared span was expanded from
+fn expansion_note(source: &str, span: &Span) -> Option<String> {
+    match &span.origin {
+        SpanOrigin::Source => None,
+        SpanOrigin::Desugared(expansion) => {
+            let (line, col) = line_col(source, expansion.from.start);
+            Some(format!(
+                "in code expanded from the {} at {}:{}",
+                expansion.construct, line, col
+            ))
+        }
+    }
+}
+
+fn note(note: Option<String>) -> String {
+    note.map_or(String::new(), |note| format!("\n{}", note))
+}
+
+enum BracketNote {
+    /// The opening bracket of the innermost bracket never closed
+    Unclosed(Span),
+    Other(String),
+}
+
+/// What is off about the brackets and arrows of the source before `upto`,
+/// given that the token there, if any, could not be parsed. A `:` or `.`
+/// cannot be inside brackets, so brackets still open there are unclosed.
+fn bracket_note(source: &str, upto: usize) -> Option<BracketNote> {
+    let tokens = highlight::lex(source);
+    let text = |span: &Span| &source[span.start..span.end];
+    let position = |span: &Span| {
+        let (line, col) = line_col(source, span.start);
+        format!("{}:{}", line, col)
+    };
+    // the open brackets, each with whether an arrow followed it already
+    let mut open: Vec<(&Span, bool)> = vec![];
+    let mut top_arrow = false;
+    let closing = |opening: &str| match opening {
+        "[" => "]",
+        "(" => ")",
+        _ => "}",
+    };
+    for token in tokens.iter() {
+        let is_last = token.span.start >= upto;
+        match token.kind {
+            TokenKind::BracketOpen | TokenKind::QuoteOpen | TokenKind::BraceOpen if !is_last => {
+                open.push((&token.span, false))
+            }
+            TokenKind::BracketClose | TokenKind::QuoteClose | TokenKind::BraceClose => {
+                match open.last() {
+                    None if is_last => {
+                        return Some(BracketNote::Other(format!(
+                            "`{}` closes no open bracket",
+                            text(&token.span)
+                        )))
+                    }
+                    Some((opening, _)) if closing(text(opening)) != text(&token.span) => {
+                        return Some(BracketNote::Other(format!(
+                            "`{}` cannot close the `{}` at {}",
+                            text(&token.span),
+                            text(opening),
+                            position(opening)
+                        )))
+                    }
+                    None => (),
+                    Some(_) if is_last => return None,
+                    Some(_) => {
+                        open.pop();
+                    }
+                }
+            }
+            TokenKind::Arrow => {
+                let seen = match open.last_mut() {
+                    Some((_, seen)) => seen,
+                    None => &mut top_arrow,
+                };
+                if *seen && is_last {
+                    return Some(BracketNote::Other(
+                        "an op type has a single `->` between its stacks".to_owned(),
+                    ));
+                }
+                *seen = true;
+            }
+            TokenKind::Terminator | TokenKind::Separator if is_last && text(&token.span) != "," => {
+                return open
+                    .last()
+                    .map(|(opening, _)| BracketNote::Unclosed((*opening).clone()))
+            }
+            TokenKind::Terminator => top_arrow = false,
+            _ => (),
+        }
+        if is_last {
+            return None;
+        }
+    }
+    open.last()
+        .map(|(opening, _)| BracketNote::Unclosed((*opening).clone()))
+}
+
+/// A hint for an op type missing its arrow, from what the parser expected
+/// at `upto`. A quote type written with a single stack, like `[Int]`, wants
+/// a second stack after it.
+fn arrow_note(source: &str, upto: usize, expected: &[String]) -> Option<String> {
+    let before = highlight::lex(&source[..upto])
+        .into_iter()
+        .rfind(|token| token.kind != TokenKind::Comment);
+    let after_bracket = before.is_some_and(|token| token.kind == TokenKind::BracketClose);
+    if expected.iter().any(|e| e == "\"->\"") {
+        Some("did you forget `->`?".to_owned())
+    } else if expected == ["\"[\""] && after_bracket {
+        Some("did you forget `->`? a quote type is written `[a -> b]` or `[a][b]`".to_owned())
+    } else {
+        None
+    }
+}
+
+fn expected_list(expected: &[String]) -> String {
+    if expected.is_empty() {
+        String::new()
+    } else {
+        format!(", expected one of {}", expected.join(", "))
+    }
+}
+
+/// `s` as a quoted and escaped JSON string
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::deprecated::check_deprecated;
+    use crate::evaluation::evaluator::Evaluator;
+    use crate::evaluation::types::{EvaluatorError, ExecLimits};
+    use crate::syntax::{parse, parse_optype};
+    use crate::typing::strict::StrictOptions;
+
+    #[test]
+    fn json_escapes() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+
+    #[test]
+    fn parse_error_points_at_token() {
+        let source = "define [] main []:\n  1 2 ].";
+        let err = parse(source).unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(source, &err);
+        assert_eq!((diagnostic.span.start, diagnostic.span.end), (25, 26));
+        assert!(diagnostic
+            .message
+            .starts_with("unexpected `]`, expected one of"));
+        assert!(diagnostic.render(source).starts_with("2:7: unexpected `]`"));
+    }
+
+    #[test]
+    fn runtime_error_traces() {
+        let source = "define [] down []: down.\ndefine [] main []: down.";
+        let module = parse(source).unwrap();
+        let limits = ExecLimits {
+            max_call_depth: Some(5),
+            ..ExecLimits::default()
+        };
+        let mut evaluator = Evaluator::new(&module)
+            .with_limits(limits)
+            .with_tail_calls(false);
+        let err = match evaluator.eval_main() {
+            Err(EvaluatorError::Runtime(err)) => err,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(
+            Diagnostic::from_runtime_error(source, &err, 2).render(source),
+            "1:20: exceeded the limit on call depth\n\
+             define [] down []: down.\n\
+             \x20                  ^\n\
+             \x20 in `down`, called at 1:20\n\
+             \x20 in `down`, called at 1:20\n\
+             \x20 … 4 frames omitted\n"
+        );
+        let rendered = Diagnostic::from_runtime_error(source, &err, MAX_FRAMES).render(source);
+        assert!(rendered.ends_with("  in `down`, called at 2:20\n"));
+        assert!(!rendered.contains("omitted"));
+    }
+
+    #[test]
+    fn deprecations_point_at_the_declaration() {
+        let source = "#[deprecated(\"use count\")]\ndefine [] size [Int]: 1.\n\
+                      define [] two [Int]: size.";
+        let module = parse(source).unwrap();
+        let warning = &check_deprecated(&module)[0];
+        let diagnostic = Diagnostic::from_deprecation_warning(source, warning);
+        assert_eq!(
+            diagnostic.render(source),
+            "3:22: `size` is deprecated: use count\n\
+             define [] two [Int]: size.\n\
+             \x20                    ^\n\
+             `size` is declared at 2:11\n"
+        );
+        assert!(diagnostic
+            .to_json(source)
+            .starts_with("{\"message\":\"`size` is deprecated: use count\\n`size` is declared at 2:11\",\"code\":\"Deprecated\","));
+
+        let strict = Inference::new(&module).with_strict(StrictOptions::all());
+        let err = strict.typecheck().unwrap_err();
+        assert_eq!(
+            Diagnostic::from_inference_error(source, &module, &err).message,
+            diagnostic.message
+        );
+    }
+
+    #[test]
+    fn diagnostic_json() {
+        let source = "define [] main []:\n  nope.";
+        let diagnostic = Diagnostic {
+            span: Span::new(21, 25),
+            message: "unknown op `nope`".to_owned(),
+            suggestions: vec![Fix {
+                title: "rename to `nop`".to_owned(),
+                span: Span::new(21, 25),
+                replacement: "nop".to_owned(),
+            }],
+            code: Some("UnknownOp".to_owned()),
+        };
+        assert_eq!(
+            diagnostic.to_json(source),
+            "{\"message\":\"unknown op `nope`\",\"code\":\"UnknownOp\",\"start\":21,\"end\":25,\
+             \"line\":2,\"col\":3,\"end_line\":2,\"end_col\":7,\
+             \"rendered\":\"2:3: unknown op `nope`\\n  nope.\\n  ^\\nhelp: rename to `nop`\\n\",\
+             \"suggestions\":[{\"title\":\"rename to `nop`\",\"start\":21,\"end\":25,\"replacement\":\"nop\"}]}"
+        );
+    }
+
+    fn optype_diagnostic(source: &str) -> (&str, String) {
+        let err = parse_optype(source).unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(source, &err);
+        (
+            &source[diagnostic.span.start..diagnostic.span.end],
+            diagnostic.message,
+        )
+    }
+
+    #[test]
+    fn unclosed_brackets_are_pointed_at() {
+        assert_eq!(
+            optype_diagnostic("Int, [a -> b"),
+            (
+                "[",
+                "`[` is never closed\n1:13: unexpected end of input, expected one of \"]\""
+                    .to_owned()
+            )
+        );
+        // a `:` or `.` cannot be inside brackets, the open one is unclosed
+        for (source, at) in [
+            ("define [Int f [Int]: .", 7),
+            ("define [Int] f [[Int][Int]: .", 15),
+            ("define [] f []: (1 .", 16),
+        ] {
+            let err = parse(source).unwrap_err();
+            let diagnostic = Diagnostic::from_parse_error(source, &err);
+            assert_eq!(diagnostic.span.start, at, "{}", source);
+            assert!(
+                diagnostic.message.contains("is never closed\n1:"),
+                "{}",
+                diagnostic.message
+            );
+        }
+    }
+
+    #[test]
+    fn missing_arrows() {
+        let hint = "did you forget `->`?";
+        let (at, message) = optype_diagnostic("Int, Maybe a");
+        assert_eq!(at, "");
+        assert!(message.ends_with(hint), "{}", message);
+        let (at, message) = optype_diagnostic("[Int, Int] -> Int");
+        assert_eq!(at, "->");
+        assert!(message.ends_with("`[a -> b]` or `[a][b]`"), "{}", message);
+        let source = "define [[Int, Int]] f []: .";
+        let err = parse(source).unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(source, &err);
+        assert_eq!(diagnostic.span.start, 18);
+        assert!(diagnostic.message.contains(hint));
+        // other errors after a `]` get no hint
+        let source = "define [] f : .";
+        let err = parse(source).unwrap_err();
+        assert!(!Diagnostic::from_parse_error(source, &err)
+            .message
+            .contains(hint));
+    }
+
+    #[test]
+    fn applied_type_variables() {
+        let (at, message) = optype_diagnostic("Int, f Int -> Int");
+        assert_eq!(at, "f");
+        assert!(message.starts_with("type variable `f` cannot be applied to arguments\n"));
+        let source = "define [Maybe (m a)] f []: pop.";
+        let err = parse(source).unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(source, &err);
+        assert_eq!(&source[diagnostic.span.start..diagnostic.span.end], "m");
+    }
+
+    #[test]
+    fn lexing_errors_are_located() {
+        let source = "define [] f []: 99999999999.";
+        let err = parse(source).unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(source, &err);
+        assert_eq!(diagnostic.span.start, 16);
+    }
+
+    #[test]
+    fn out_of_range_literals() {
+        let literal = |source: &str| {
+            let diagnostic = Diagnostic::from_parse_error(source, &parse(source).unwrap_err());
+            (
+                source[diagnostic.span.start..diagnostic.span.end].to_owned(),
+                diagnostic.message,
+            )
+        };
+        assert_eq!(
+            literal("define [] f [Int]: 2147483648."),
+            (
+                "2147483648".to_owned(),
+                "`2147483648` does not fit in an Int, which goes from -2147483648 to 2147483647"
+                    .to_owned()
+            )
+        );
+        assert_eq!(literal("define [] f [Int]: -2147483649.").0, "-2147483649");
+        for bound in ["2147483647", "-2147483648", "+2147483647"] {
+            assert!(parse(&format!("define [] f [Int]: {}.", bound)).is_ok());
+        }
+    }
+
+    #[test]
+    fn bracket_and_arrow_notes() {
+        let note = |source: &str| {
+            let err = parse_optype(source).unwrap_err();
+            let message = Diagnostic::from_parse_error(source, &err).message;
+            message.split_once('\n').map(|(_, note)| note.to_owned())
+        };
+        assert_eq!(note("Int ]"), Some("`]` closes no open bracket".to_owned()));
+        assert_eq!(
+            note("Maybe (a] -> Int"),
+            Some("`]` cannot close the `(` at 1:7".to_owned())
+        );
+        assert_eq!(
+            note("Int -> Int -> Int"),
+            Some("an op type has a single `->` between its stacks".to_owned())
+        );
+        // arrows in different quotes are fine, the error is elsewhere
+        assert_eq!(note("[a -> b] -> [c -> d] Int,,"), None);
+        let source = "define [] f [(]: .";
+        let err = parse(source).unwrap_err();
+        assert!(Diagnostic::from_parse_error(source, &err)
+            .render(source)
+            .ends_with("`]` cannot close the `(` at 1:14\n"));
+    }
+}
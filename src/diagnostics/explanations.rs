@@ -0,0 +1,691 @@
+//! The long form of each error code, for `iv explain`. Every explanation
+//! comes with a program showing the error and the same program fixed, and
+//! the tests check both, so the examples say what the checker does.
+
+/// What runs the examples of an explanation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Parse,
+    Typecheck,
+    /// Typechecking with every check of `StrictOptions::all`
+    Strict,
+    /// Evaluating `main` without typechecking, with a limit on the call depth
+    Run,
+}
+
+#[derive(Debug)]
+pub struct Explanation {
+    pub code: &'static str,
+    pub stage: Stage,
+    /// What the error means, then how to get rid of it
+    pub text: &'static str,
+    /// A program with the error
+    pub before: &'static str,
+    /// The program fixed
+    pub after: &'static str,
+}
+
+impl Explanation {
+    pub fn render(&self) -> String {
+        format!(
+            "{}\n\n{}\n\nfor example\n\n{}\n\nis fixed by\n\n{}\n",
+            self.code,
+            self.text,
+            indent(self.before),
+            indent(self.after)
+        )
+    }
+}
+
+fn indent(source: &str) -> String {
+    let lines: Vec<_> = source.lines().map(|line| format!("    {}", line)).collect();
+    lines.join("\n")
+}
+
+/// The explanation of the code, `None` for codes only embedders can cause
+/// and the ones of errors the checker does not produce
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    EXPLANATIONS
+        .iter()
+        .find(|explanation| explanation.code == code)
+}
+
+pub const EXPLANATIONS: [Explanation; 53] = [
+    // parse errors
+    Explanation {
+        code: "UnrecognizedEof",
+        stage: Stage::Parse,
+        text: "The source ends in the middle of a definition. Every definition ends with \
+               a `.`, and brackets, quotes and case arms must be closed.",
+        before: "define [] one [Int]: 1",
+        after: "define [] one [Int]: 1.",
+    },
+    Explanation {
+        code: "UnrecognizedToken",
+        stage: Stage::Parse,
+        text: "The parser found a token where it cannot be, the message lists what it \
+               expected there. A missing `.` between definitions often shows up here, at \
+               the start of the next one.",
+        before: "define [] one [Int]: 1\ndefine [] two [Int]: 2.",
+        after: "define [] one [Int]: 1.\ndefine [] two [Int]: 2.",
+    },
+    Explanation {
+        code: "UnexpectedCharacter",
+        stage: Stage::Parse,
+        text: "A character that starts no token. Names are letters, digits, `-` and `+`, \
+               starting with a letter.",
+        before: "define [] one [Int]: 1 $.",
+        after: "define [] one [Int]: 1.",
+    },
+    Explanation {
+        code: "InvalidInteger",
+        stage: Stage::Parse,
+        text: "An integer literal outside the range of `Int`, which is 32 bits.",
+        before: "define [] big [Int]: 9999999999.",
+        after: "define [] big [Int]: 999999999.",
+    },
+    Explanation {
+        code: "UnknownPragma",
+        stage: Stage::Parse,
+        text: "A `//@` comment is a pragma, and only `expect-type`, `expect-error` and \
+               `phantom` are known. A comment starting with `//@` by accident needs a \
+               space, `// @`.",
+        before: "//@ expect-kind: Int\ndefine [] one [Int]: 1.",
+        after: "//@ expect-type: [][Int]\ndefine [] one [Int]: 1.",
+    },
+    Explanation {
+        code: "AppliedTypeVariable",
+        stage: Stage::Parse,
+        text: "A type variable given arguments, like `m a`. Only data types take arguments, \
+               there are no variables standing for them.",
+        before: "data Maybe a: nothing, [a] just.\ndefine [m a] unwrap [a]: pop.",
+        after: "data Maybe a: nothing, [a] just.\n\
+                define [Maybe a] unwrap [a]: case { just { }, nothing { unwrap } }.",
+    },
+    Explanation {
+        code: "UnknownLiteralSuffix",
+        stage: Stage::Parse,
+        text: "An integer directly followed by letters is a literal with a suffix, which \
+               only the suffixes an embedder registers make sense of. Without one, put a \
+               space between the number and the name.",
+        before: "data Bool: true, false.\n\
+                 define [Int] one [Int, Bool]: 1true.",
+        after: "data Bool: true, false.\n\
+                define [Int] one [Int, Bool]: 1 true.",
+    },
+    // type errors
+    Explanation {
+        code: "AnnInfConflict",
+        stage: Stage::Typecheck,
+        text: "The body does not have the type the annotation says. The message shows both \
+               and where they differ. Either the body or the annotation is wrong.",
+        before: "data Foo: foo.\ndefine [] any [a]: foo.",
+        after: "data Foo: foo.\ndefine [] any [Foo]: foo.",
+    },
+    Explanation {
+        code: "EmptyBodyCannotSatisfy",
+        stage: Stage::Typecheck,
+        text: "The op's body is empty, but its annotation says it changes the stack. An \
+               empty body leaves every value where it is, so only an annotation whose \
+               values after are the ones before, like `[a, Int][a, Int]`, agrees with it. \
+               This is usually a stub left to fill in: write the body. To trust the \
+               annotation instead mark the op `#[unchecked]`, and for an op a host \
+               function implements, end the definition after its annotation, leaving \
+               out the `:`.",
+        before: "define [Int] double [Int, Int]:.",
+        after: "define [Int] double [Int, Int]: dup.",
+    },
+    Explanation {
+        code: "UnificationError",
+        stage: Stage::Typecheck,
+        text: "An op takes a value of another type than the one the ops before it leave \
+               there.",
+        before: "data Foo: foo.\ndata Bar: bar.\n\
+                 define [Foo] eat []: pop.\n\
+                 define [] main []: bar eat.",
+        after: "data Foo: foo.\ndata Bar: bar.\n\
+                define [Foo] eat []: pop.\n\
+                define [] main []: foo eat.",
+    },
+    Explanation {
+        code: "UnknownOp",
+        stage: Stage::Typecheck,
+        text: "A name that is no op, constructor or constant of the module and no prelude \
+               op.",
+        before: "define [Int] twice [Int, Int]: dupe.",
+        after: "define [Int] twice [Int, Int]: dup.",
+    },
+    Explanation {
+        code: "UnknownConstructor",
+        stage: Stage::Typecheck,
+        text: "A case arm or pattern names no constructor of the module.",
+        before: "data Maybe a: nothing, [a] just.\n\
+                 define [Maybe Int] get [Int]: case { jsut { }, nothing { 0 } }.",
+        after: "data Maybe a: nothing, [a] just.\n\
+                define [Maybe Int] get [Int]: case { just { }, nothing { 0 } }.",
+    },
+    Explanation {
+        code: "DuplicateConstructor",
+        stage: Stage::Typecheck,
+        text: "Two data types have a constructor of the same name. Constructors are ops, so \
+               their names must be unique in the module.",
+        before: "data Bool: true, false.\ndata Answer: yes, no, false.",
+        after: "data Bool: true, false.\ndata Answer: yes, no, unknown.",
+    },
+    Explanation {
+        code: "NotAllConstructorsCovered",
+        stage: Stage::Typecheck,
+        text: "A case has no arm for some constructors of the data type it matches, every \
+               value must have an arm to go to.",
+        before: "data Color: red, green, blue.\n\
+                 define [Color] next [Color]: case { red { green }, green { blue } }.",
+        after: "data Color: red, green, blue.\n\
+                define [Color] next [Color]: case { red { green }, green { blue }, blue { red } }.",
+    },
+    Explanation {
+        code: "CaseArmMismatch",
+        stage: Stage::Typecheck,
+        text: "The arms of a case leave values of different types. Whichever arm runs, the \
+               ops after the case see the same stack.",
+        before: "data Bool: true, false.\ndata Foo: foo.\n\
+                 define [Bool] pick [Foo]: case { true { foo }, false { 1 } }.",
+        after: "data Bool: true, false.\ndata Foo: foo.\n\
+                define [Bool] pick [Foo]: case { true { foo }, false { foo } }.",
+    },
+    Explanation {
+        code: "ArmsDisagreeOnStackDepth",
+        stage: Stage::Typecheck,
+        text: "One arm of a case changes values below the ones the other arms touch. The \
+               arms must agree on how deep into the stack they reach.",
+        before: "data Bool: true, false.\n\
+                 define [Bool, Bool] pick [Int]:\n\
+                 \x20 case { true { }, false { case { true { 1 }, false { 2 } } } }.",
+        after: "data Bool: true, false.\n\
+                define [Bool, Bool] pick [Int]:\n\
+                \x20 case { true { pop 0 }, false { case { true { 1 }, false { 2 } } } }.",
+    },
+    Explanation {
+        code: "ScrutineeMismatch",
+        stage: Stage::Typecheck,
+        text: "A case matches constructors of one data type, and the value on top of the \
+               stack is of another.",
+        before: "data Bool: true, false.\n\
+                 data Unit: unit.\n\
+                 define [] main [Int]: unit case { true { 1 }, false { 0 } }.",
+        after: "data Bool: true, false.\n\
+                define [] main [Int]: true case { true { 1 }, false { 0 } }.",
+    },
+    Explanation {
+        code: "CannotMatchOnQuote",
+        stage: Stage::Typecheck,
+        text: "A case takes apart a quote. Quotes are opaque: nothing about what a quote \
+               does, not even whether it does anything at all, can be found out other than \
+               by running it with an `exec` op, so a quote has no constructors to match. \
+               To choose between quotes, match on the data value that decides which one to \
+               push and push it in the arm. To know something about a quote, pass a data \
+               value along with it that says so.",
+        before: "data Nat: zero, [Nat] suc.\n\
+                 define [] nop [[][]]: ().\n\
+                 define [] main [Nat]: nop case { empty { zero }, full { zero suc } }.",
+        after: "data Nat: zero, [Nat] suc.\n\
+                define [] nop [[][]]: ().\n\
+                define [] main [Nat]: nop exec-0-0 zero.",
+    },
+    Explanation {
+        code: "CannotMatchBuiltin",
+        stage: Stage::Typecheck,
+        text: "A case takes apart a value of a builtin type like `Int`. Builtin values are \
+               not made of constructors, only values of data types can be matched.",
+        before: "data Bool: true, false.\n\
+                 define [] main [Int]: 1 case { true { 1 }, false { 0 } }.",
+        after: "data Bool: true, false.\n\
+                define [] main [Int]: true case { true { 1 }, false { 0 } }.",
+    },
+    Explanation {
+        code: "MissingCombinations",
+        stage: Stage::Typecheck,
+        text: "A `case2` has no arm for some pairs of constructors. A `_` pattern matches \
+               every constructor.",
+        before: "data Bool: true, false.\n\
+                 define [Bool, Bool] and [Bool]: case2 { true true { true }, false false { false } }.",
+        after: "data Bool: true, false.\n\
+                define [Bool, Bool] and [Bool]: case2 { true true { true }, _ _ { pop pop false } }.",
+    },
+    Explanation {
+        code: "OccursCheck",
+        stage: Stage::Typecheck,
+        text: "A value would have to contain itself in its type, like a list of itself.",
+        before: "data List a: empty, [a, List a] cons.\n\
+                 define [a] selfcons [List a]: dup cons.",
+        after: "data List a: empty, [a, List a] cons.\n\
+                define [a] single [List a]: empty br-1 cons.",
+    },
+    Explanation {
+        code: "TypeTooLarge",
+        stage: Stage::Typecheck,
+        text: "The type of a body got too large to check, usually because every op doubles \
+               it, like pairing a value with itself does. Such a value is better given a \
+               data type of its own.",
+        before: "data Pair a b: [a, b] pair.\n\
+                 define [a] twice [Pair a a]: dup pair.\n\
+                 define [] huge []: 0 twice twice twice twice twice twice twice twice twice twice \
+                 twice twice twice twice pop.",
+        after: "data Pair a b: [a, b] pair.\n\
+                define [a] twice [Pair a a]: dup pair.\n\
+                define [] small []: 0 twice twice pop.",
+    },
+    Explanation {
+        code: "InfiniteFix",
+        stage: Stage::Typecheck,
+        text: "The quote given to `fix-*` returns the quote it receives, which would make \
+               its type contain itself. It can run or drop that quote, not return it.",
+        before: "data Foo: foo.\ndefine [] itself [Foo]: (quote exec-0-1) fix-0-1.",
+        after: "data Foo: foo.\ndefine [] itself [Foo]: (pop foo) fix-0-1.",
+    },
+    Explanation {
+        code: "IndexNotLiteral",
+        stage: Stage::Typecheck,
+        text: "A `pick` or `roll` without an `Int` literal right before it. `n pick` pushes \
+               a copy of the value `n` below the top of the stack and `n roll` moves that \
+               value to the top, so what they leave depends on `n`, which is only known \
+               when it is written out. Write the index as a non-negative literal right \
+               before the op, or use `dup`, `br-*` and `dg-*` instead.",
+        before: "define [Int, Int] second [Int, Int, Int]: 0 dup pick.",
+        after: "define [Int, Int] second [Int, Int, Int]: 1 pick.",
+    },
+    Explanation {
+        code: "ListMGULengthDifferent",
+        stage: Stage::Typecheck,
+        text: "Two quote types with stacks of different lengths where they must be the \
+               same. Quote types are not padded with the values below, so the empty quote \
+               is `[][]` and does not match `[a][a]`.",
+        before: "data Endo: [[Int][Int]] endo.\ndefine [] noop [Endo]: () endo.",
+        after: "data Endo: [[Int][Int]] endo.\ndefine [] noop [Endo]: (dup pop) endo.",
+    },
+    Explanation {
+        code: "QuoteNeedsInputs",
+        stage: Stage::Typecheck,
+        text: "A quote given straight to an op that runs it takes more values than the op \
+               gives it. There are no bindings, a value meant to be captured has to be put \
+               into the quote: `quote` makes a quote of a value and `comp-*` joins it to \
+               the other quote, or the value can be written inside.",
+        before: "#[unchecked]\ndefine [Int, Int] add [Int]:.\n\
+                 define [Int] next [Int]: (add) exec-1-1.",
+        after: "#[unchecked]\ndefine [Int, Int] add [Int]:.\n\
+                define [Int] next [Int]: (1 add) exec-1-1.",
+    },
+    Explanation {
+        code: "NotEnoughValues",
+        stage: Stage::Typecheck,
+        text: "A constructor or prelude op pops more values than there are, counting the \
+               ones the annotation gives and the ones the ops before it leave. Either a \
+               value is missing before it or the annotation takes too few.",
+        before: "data List a: empty, [a, List a] cons.\n\
+                 define [] one [List Int]: 1 cons.",
+        after: "data List a: empty, [a, List a] cons.\n\
+                define [] one [List Int]: empty 1 cons.",
+    },
+    Explanation {
+        code: "OverloadNotMarked",
+        stage: Stage::Typecheck,
+        text: "The name is defined more than once, which only overloads it if every one \
+               of its definitions is marked `#[overload]`. Mark them all, or rename one.",
+        before: "data Bool: false, true.\n\
+                 define [Int] size [Int]:.\n\
+                 #[overload]\ndefine [Bool] size [Int]: pop 1.",
+        after: "data Bool: false, true.\n\
+                #[overload]\ndefine [Int] size [Int]:.\n\
+                #[overload]\ndefine [Bool] size [Int]: pop 1.",
+    },
+    Explanation {
+        code: "OverloadsIndistinct",
+        stage: Stage::Typecheck,
+        text: "Each use of an overloaded name runs the definition that fits the stack, \
+               told apart by a value all of them take: at some depth, each has to take a \
+               type made by a different data type or `Int`. Change what one of them \
+               takes, or rename it.",
+        before: "#[overload]\ndefine [Int] size [Int]:.\n\
+                 #[overload]\ndefine [Int, Int] size [Int]: pop.",
+        after: "#[overload]\ndefine [Int] size [Int]:.\n\
+                define [Int, Int] size2 [Int]: pop.",
+    },
+    Explanation {
+        code: "AmbiguousOverload",
+        stage: Stage::Typecheck,
+        text: "More than one definition of the overloaded name fits the stack where it \
+               is used, the value that tells them apart having a type that could be any. \
+               Make the annotation give a concrete type there.",
+        before: "data Bool: false, true.\n\
+                 #[overload]\ndefine [Int] size [Int]:.\n\
+                 #[overload]\ndefine [Bool] size [Int]: pop 1.\n\
+                 define [a] measure [Int]: size.",
+        after: "data Bool: false, true.\n\
+                #[overload]\ndefine [Int] size [Int]:.\n\
+                #[overload]\ndefine [Bool] size [Int]: pop 1.\n\
+                define [Bool] measure [Int]: size.",
+    },
+    Explanation {
+        code: "NoOverloadFits",
+        stage: Stage::Typecheck,
+        text: "None of the definitions of the overloaded name fit the stack where it is \
+               used. Leave a value one of them takes, or define one for what is there.",
+        before: "data Bool: false, true.\n\
+                 #[overload]\ndefine [Int] size [Int]:.\n\
+                 #[overload]\ndefine [Bool] size [Int]: pop 1.\n\
+                 define [] measure [Int]: (1) size.",
+        after: "data Bool: false, true.\n\
+                #[overload]\ndefine [Int] size [Int]:.\n\
+                #[overload]\ndefine [Bool] size [Int]: pop 1.\n\
+                define [] measure [Int]: true size.",
+    },
+    Explanation {
+        code: "OverloadNeedsContext",
+        stage: Stage::Typecheck,
+        text: "Which definition of an overloaded name a use runs is picked by the value \
+               that tells them apart, which has to be on the stack before the use. In a \
+               quote it is not, as the quote's values only arrive when it runs. Wrap the \
+               use in an op of its own whose annotation gives the value.",
+        before: "data Bool: false, true.\n\
+                 #[overload]\ndefine [Int] size [Int]:.\n\
+                 #[overload]\ndefine [Bool] size [Int]: pop 1.\n\
+                 define [] measure [Int]: true (size) exec-1-1.",
+        after: "data Bool: false, true.\n\
+                #[overload]\ndefine [Int] size [Int]:.\n\
+                #[overload]\ndefine [Bool] size [Int]: pop 1.\n\
+                define [Bool] size-bool [Int]: size.\n\
+                define [] measure [Int]: true (size-bool) exec-1-1.",
+    },
+    Explanation {
+        code: "AnnotationTooSpecific",
+        stage: Stage::Strict,
+        text: "Strict mode: the annotation gives concrete types where the body works for \
+               any. Make the annotation as general as the body, or the body as specific \
+               as the annotation.",
+        before: "data Foo: foo.\ndefine [Foo] idfoo [Foo]:.",
+        after: "data Foo: foo.\ndefine [a] id [a]:.",
+    },
+    Explanation {
+        code: "UncheckedOp",
+        stage: Stage::Strict,
+        text: "Strict mode: `#[unchecked]` ops are not trusted, their body must typecheck \
+               like any other.",
+        before: "#[unchecked]\ndefine [a] forget []:.",
+        after: "define [a] forget []: pop.",
+    },
+    Explanation {
+        code: "ShadowsPrelude",
+        stage: Stage::Strict,
+        text: "Strict mode: an op or constructor has the name of a prelude op, which it \
+               hides from the whole module.",
+        before: "define [a] dup [a, a]: dup.",
+        after: "define [a] twice [a, a]: dup.",
+    },
+    Explanation {
+        code: "OutputOnlyVar",
+        stage: Stage::Strict,
+        text: "Strict mode: the annotation gives a value of a type variable it takes no \
+               value of, a value of every type made from nothing. Only an op that never \
+               returns can do that.",
+        before: "define [Int] anything [Int, a]: pop anything.",
+        after: "define [b, a] same [b, a]:.",
+    },
+    Explanation {
+        code: "UnreachableArm",
+        stage: Stage::Strict,
+        text: "Strict mode: the arms before an arm already match everything it matches, so \
+               it never runs.",
+        before: "data Bool: true, false.\n\
+                 define [Bool, Bool] and [Bool]:\n\
+                 \x20 case2 { _ _ { pop pop false }, true true { true } }.",
+        after: "data Bool: true, false.\n\
+                define [Bool, Bool] and [Bool]:\n\
+                \x20 case2 { true true { true }, _ _ { pop pop false } }.",
+    },
+    Explanation {
+        code: "Deprecated",
+        stage: Stage::Strict,
+        text: "Strict mode: the op or constructor is marked `#[deprecated]`, usually with \
+               what to use instead. Without strict mode the use is a warning. \
+               `#[allow(deprecated)]` on the op using it allows it.",
+        before: "#[deprecated(\"use count\")]\n\
+                 define [] size [Int]: 1.\n\
+                 define [] twice [Int, Int]: size size.",
+        after: "define [] count [Int]: 1.\n\
+                define [] twice [Int, Int]: count count.",
+    },
+    Explanation {
+        code: "ImpureConstant",
+        stage: Stage::Typecheck,
+        text: "A constant's initializer uses an op of the module. Constants are computed \
+               before anything runs, from literals, constructors, other constants and the \
+               pure prelude ops.",
+        before: "data Nat: zero, [Nat] suc.\n\
+                 define [Nat] inc [Nat]: suc.\n\
+                 const one [Nat]: zero inc.",
+        after: "data Nat: zero, [Nat] suc.\n\
+                const one [Nat]: zero suc.",
+    },
+    Explanation {
+        code: "DynInAnnotation",
+        stage: Stage::Typecheck,
+        text: "A `?` in the annotation of an op with a body, of a constant or in a \
+               constructor's fields. A `?` is a value whose type is only known when the \
+               program runs, only ops declared without a body, which the embedder gives \
+               host functions, take or leave one. Ops taking such a value as one of a \
+               type check it when they run.",
+        before: "define [?] show [Int]: pop 0.",
+        after: "define [?] show [Int].",
+    },
+    Explanation {
+        code: "CheckpointMismatch",
+        stage: Stage::Typecheck,
+        text: "A stack effect comment, like `// ( Nat -- Nat, Nat )`, says what the ops \
+               before it in the body do, topmost value first, and they do something else.",
+        before: "data Nat: zero, [Nat] suc.\n\
+                 define [Nat] twice [Nat, Nat]:\n\
+                 \x20   dup // ( Nat -- Nat )\n\
+                 \x20   .",
+        after: "data Nat: zero, [Nat] suc.\n\
+                define [Nat] twice [Nat, Nat]:\n\
+                \x20   dup // ( Nat -- Nat, Nat )\n\
+                \x20   .",
+    },
+    Explanation {
+        code: "ExpectTypeMismatch",
+        stage: Stage::Typecheck,
+        text: "An `expect-type` pragma says another type than the one the op has.",
+        before: "//@ expect-type: [a][a]\ndefine [a] twice [a, a]: dup.",
+        after: "//@ expect-type: [a][a, a]\ndefine [a] twice [a, a]: dup.",
+    },
+    Explanation {
+        code: "ExpectErrorMismatch",
+        stage: Stage::Typecheck,
+        text: "An `expect-error` pragma names an error the op does not have.",
+        before: "//@ expect-error: UnknownOp\ndefine [] one [Int]: 1.",
+        after: "//@ expect-error: UnknownOp\ndefine [] one [Int]: 1 nope.",
+    },
+    // runtime errors, which only unchecked ops and runaway recursion get to
+    Explanation {
+        code: "StackUnderflow",
+        stage: Stage::Run,
+        text: "An op popped from an empty stack. A typechecked body never does, but an \
+               `#[unchecked]` one can.",
+        before: "#[unchecked]\ndefine [] main [Int]: pop 1.",
+        after: "define [] main [Int]: 1.",
+    },
+    Explanation {
+        code: "LimitExceeded",
+        stage: Stage::Run,
+        text: "The evaluation went past a limit set on it, like the call depth `iv test` \
+               sets. Calls in tail position reuse their frame, other recursion needs \
+               a case that ends it.",
+        before: "define [] main [Int]: main 1.",
+        after: "define [] main [Int]: 1.",
+    },
+    Explanation {
+        code: "NotAQuote",
+        stage: Stage::Run,
+        text: "An `exec-*` or `fix-*` ran a value that is not a quote, which only an \
+               `#[unchecked]` body can give it.",
+        before: "#[unchecked]\ndefine [] main [Int]: 1 exec-0-1.",
+        after: "define [] main [Int]: (1) exec-0-1.",
+    },
+    Explanation {
+        code: "NotAConstructor",
+        stage: Stage::Run,
+        text: "A case matched a value that no constructor built, which only an \
+               `#[unchecked]` body can give it.",
+        before: "data Bool: true, false.\n#[unchecked]\n\
+                 define [] main [Int]: 1 case { true { 1 }, false { 0 } }.",
+        after: "data Bool: true, false.\n\
+                define [] main [Int]: true case { true { 1 }, false { 0 } }.",
+    },
+    Explanation {
+        code: "NotAnIndex",
+        stage: Stage::Run,
+        text: "A `pick` or `roll` popped a value that is not an `Int` of at least 0, which \
+               only an `#[unchecked]` body can give it, typechecking wants the index as a \
+               literal.",
+        before: "#[unchecked]\ndefine [] main [Int]: 1 -1 roll.",
+        after: "define [] main [Int]: 1 0 roll.",
+    },
+    Explanation {
+        code: "RuntimeUnknownOp",
+        stage: Stage::Run,
+        text: "An `#[unchecked]` body named an op that does not exist, which typechecking \
+               would have reported as `UnknownOp`.",
+        before: "#[unchecked]\ndefine [] main [Int]: 1 nope.",
+        after: "define [] main [Int]: 1.",
+    },
+    Explanation {
+        code: "NoMatchingArm",
+        stage: Stage::Run,
+        text: "A case of an `#[unchecked]` body had no arm for the value's constructor, \
+               which typechecking would have reported as `NotAllConstructorsCovered`.",
+        before: "data Bool: true, false.\n#[unchecked]\n\
+                 define [] main [Int]: false case { true { 1 } }.",
+        after: "data Bool: true, false.\n\
+                define [] main [Int]: false case { true { 1 }, false { 0 } }.",
+    },
+    Explanation {
+        code: "Assertion",
+        stage: Stage::Run,
+        text: "An `assert` popped `false`, or a `panic` ran. Either stops the whole \
+               evaluation, the trace shows the calls that got there.",
+        before: "data Bool: true, false.\ndefine [] main [Int]: false assert 1.",
+        after: "data Bool: true, false.\ndefine [] main [Int]: true assert 1.",
+    },
+    Explanation {
+        code: "ConstantCycle",
+        stage: Stage::Run,
+        text: "Constants whose initializers need each other, so none of them can be \
+               computed first.",
+        before: "const a [Int]: b.\nconst b [Int]: a.\ndefine [] main [Int]: a.",
+        after: "const a [Int]: 1.\nconst b [Int]: a.\ndefine [] main [Int]: b.",
+    },
+    Explanation {
+        code: "NotImplemented",
+        stage: Stage::Run,
+        text: "A call of an op declared without a body, `define [Int] f [Int].`, that \
+               the embedder registered no host function for. A declaration only gives \
+               the type, give the op a body or register the function.",
+        before: "define [] answer [Int].\ndefine [] main [Int]: answer.",
+        after: "define [] answer [Int]: 42.\ndefine [] main [Int]: answer.",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::{analyze, AnalyzeOptions};
+    use crate::desugar::desugar;
+    use crate::diagnostics::parse_error_code;
+    use crate::evaluation::evaluator::Evaluator;
+    use crate::evaluation::types::{EvaluatorError, ExecLimits};
+    use crate::syntax::parse;
+    use crate::typing::strict::StrictOptions;
+    use std::collections::HashSet;
+
+    /// The codes of the errors the stage reports for the source
+    fn codes(stage: Stage, source: &str) -> Vec<String> {
+        match stage {
+            Stage::Parse => parse(source)
+                .err()
+                .map(|err| parse_error_code(&err).to_owned())
+                .into_iter()
+                .collect(),
+            Stage::Typecheck | Stage::Strict => {
+                let opts = AnalyzeOptions {
+                    strict: match stage {
+                        Stage::Strict => StrictOptions::all(),
+                        _ => StrictOptions::default(),
+                    },
+                    ..AnalyzeOptions::default()
+                };
+                analyze(source, opts)
+                    .diagnostics
+                    .into_iter()
+                    .filter_map(|(_, diagnostic)| diagnostic.code)
+                    .collect()
+            }
+            Stage::Run => {
+                let module = desugar(parse(source).unwrap()).unwrap();
+                let mut evaluator = Evaluator::new(&module).with_limits(ExecLimits {
+                    max_call_depth: Some(50),
+                    ..ExecLimits::default()
+                });
+                match evaluator.eval_main() {
+                    Ok(()) => vec![],
+                    Err(EvaluatorError::Runtime(err)) => vec![err.code().to_owned()],
+                    Err(err) => panic!("{:?}", err),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn examples_show_and_fix_their_errors() {
+        for explanation in EXPLANATIONS.iter() {
+            let code = explanation.code;
+            let before = codes(explanation.stage, explanation.before);
+            assert!(
+                before.iter().any(|c| c == code),
+                "the example of {} gives {:?}",
+                code,
+                before
+            );
+            let after = codes(explanation.stage, explanation.after);
+            assert!(after.is_empty(), "the fix of {} gives {:?}", code, after);
+        }
+    }
+
+    #[test]
+    fn every_code_is_explained() {
+        // the errors embedders and host ops cause, and the ones the checker
+        // does not produce
+        let unexplained = [
+            "InvalidToken",
+            "ExtraToken",
+            "InvalidLiteral",
+            "UnknownLiteralKind",
+            "OpPrePostLenNeq",
+            "Interrupted",
+            "HostTypeMismatch",
+            "DynTypeMismatch",
+            "HostResultArity",
+            "HostError",
+            "UnregisteredLiteral",
+            "LiteralError",
+            "IntOverflow",
+        ];
+        let codes: HashSet<_> = EXPLANATIONS.iter().map(|e| e.code).collect();
+        assert_eq!(codes.len(), EXPLANATIONS.len(), "a code is explained twice");
+        for code in unexplained {
+            assert!(explain(code).is_none(), "{} is explained", code);
+        }
+        assert!(explain("UnknownOp")
+            .unwrap()
+            .render()
+            .contains("    define"));
+    }
+}
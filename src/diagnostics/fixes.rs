@@ -0,0 +1,415 @@
+use super::json_string;
+use crate::syntax::ast::*;
+use crate::typing::inference::{Inference, InferenceError, InferenceErrorMessage};
+use crate::typing::types::{Normalizer, OpType, Type};
+
+/// A textual edit that resolves a diagnostic
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub title: String,
+    /// The source range to replace, empty for insertions
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Fix {
+    pub fn apply(&self, source: &str) -> String {
+        format!(
+            "{}{}{}",
+            &source[..self.span.start],
+            self.replacement,
+            &source[self.span.end..]
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"title\":{},\"start\":{},\"end\":{},\"replacement\":{}}}",
+            json_string(&self.title),
+            self.span.start,
+            self.span.end,
+            json_string(&self.replacement)
+        )
+    }
+}
+
+/// Edits that would make `err` go away, best first
+pub fn suggest(source: &str, module: &Module, err: &InferenceError) -> Vec<Fix> {
+    match &err.error {
+        InferenceErrorMessage::UnknownConstructor { name } => {
+            let candidates: Vec<_> = constrs_in_source_order(module)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            rename(source, &err.span, name, &candidates)
+                .into_iter()
+                .collect()
+        }
+        InferenceErrorMessage::NotAllConstructorsCovered => cover_constructors(source, module, err),
+        InferenceErrorMessage::AnnInfConflict { .. }
+        | InferenceErrorMessage::NotEnoughValues { .. } => replace_annotation(source, module, err)
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Edits that would make the parse error go away, for now renaming an
+/// unknown literal suffix to a registered one
+pub fn suggest_for_parse_error(err: &crate::syntax::ParseError<'_>) -> Vec<Fix> {
+    let lalrpop_util::ParseError::User {
+        error:
+            crate::syntax::LexingError::UnknownLiteralSuffix {
+                suffix,
+                start,
+                end,
+                known,
+            },
+    } = err
+    else {
+        return vec![];
+    };
+    let known: Vec<_> = known.iter().map(String::as_str).collect();
+    near_miss(suffix, &known)
+        .map(|replacement| Fix {
+            title: format!("use the suffix `{}`", replacement),
+            span: Span::new(*start, *end),
+            replacement: replacement.to_owned(),
+        })
+        .into_iter()
+        .collect()
+}
+
+fn constrs_in_source_order(module: &Module) -> Vec<(&str, &DataConstr)> {
+    let mut constrs: Vec<_> = module
+        .data_defs
+        .values()
+        .flat_map(|data_def| data_def.constrs.iter())
+        .map(|(name, constr)| (name.as_str(), constr))
+        .collect();
+    constrs.sort_by_key(|(_, constr)| constr.span.start);
+    constrs
+}
+
+/// Edits between `a` and `b`, swapping adjacent characters counts as one
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<_> = a.chars().collect();
+    let b: Vec<_> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// The closest candidate that is a plausible typo of `name`
+fn near_miss<'c>(name: &str, candidates: &[&'c str]) -> Option<&'c str> {
+    let max_distance = (name.chars().count() / 3).clamp(1, 2);
+    candidates
+        .iter()
+        .map(|c| (edit_distance(name, c), *c))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// Where `name` first occurs as a whole identifier inside `span`
+fn find_ident(source: &str, span: &Span, name: &str) -> Option<Span> {
+    let text = &source[span.start..span.end];
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '+';
+    text.match_indices(name)
+        .find(|(i, _)| {
+            let before = text[..*i].chars().next_back();
+            let after = text[i + name.len()..].chars().next();
+            !before.is_some_and(is_ident) && !after.is_some_and(is_ident)
+        })
+        .map(|(i, _)| Span::new(span.start + i, span.start + i + name.len()))
+}
+
+fn rename(source: &str, within: &Span, name: &str, candidates: &[&str]) -> Option<Fix> {
+    let replacement = near_miss(name, candidates)?;
+    Some(Fix {
+        title: format!("rename to `{}`", replacement),
+        span: find_ident(source, within, name)?,
+        replacement: replacement.to_owned(),
+    })
+}
+
+/// The head arm and the other arms of the case at `span`
+fn find_case<'m>(ops: &'m [Op], span: &Span) -> Option<(&'m CaseArm, &'m [CaseArm])> {
+    ops.iter().find_map(|op| match op {
+        Op::Case {
+            head_arm,
+            arms,
+            span: case_span,
+        } if case_span.start == span.start && case_span.end == span.end => {
+            Some((head_arm, arms.as_slice()))
+        }
+        Op::Case { head_arm, arms, .. } => std::iter::once(head_arm)
+            .chain(arms.iter())
+            .find_map(|arm| find_case(&arm.body, span)),
+        Op::Case2 { arms, .. } => arms.iter().find_map(|arm| find_case(&arm.body, span)),
+        Op::Quote { value, .. } => find_case(value, span),
+        Op::List { items, .. } => items.iter().find_map(|item| find_case(item, span)),
+        Op::Literal { .. } | Op::Name { .. } => None,
+    })
+}
+
+/// Renames arms with unknown constructors to a missing constructor they are a
+/// typo of, otherwise adds stub arms that drop the fields
+fn cover_constructors(source: &str, module: &Module, err: &InferenceError) -> Vec<Fix> {
+    let Some((head_arm, arms)) = module
+        .op_defs
+        .values()
+        .find_map(|op_def| find_case(&op_def.body, &err.span))
+    else {
+        return vec![];
+    };
+    let Some(data_def) = module
+        .data_defs
+        .values()
+        .find(|data_def| data_def.constrs.contains_key(&head_arm.constr))
+    else {
+        return vec![];
+    };
+    let all_arms: Vec<_> = std::iter::once(head_arm).chain(arms.iter()).collect();
+    let covered = |name: &str| all_arms.iter().any(|arm| arm.constr == name);
+    let missing: Vec<_> = data_def
+        .constrs_in_source_order()
+        .into_iter()
+        .filter(|(name, _)| !covered(name))
+        .collect();
+    let missing_names: Vec<_> = missing.iter().map(|(name, _)| name.as_str()).collect();
+
+    let renames: Vec<_> = all_arms
+        .iter()
+        .filter(|arm| !data_def.constrs.contains_key(&arm.constr))
+        .filter_map(|arm| rename(source, &arm.span, &arm.constr, &missing_names))
+        .collect();
+    if !renames.is_empty() || missing.is_empty() {
+        return renames;
+    }
+    let stubs: Vec<_> = missing
+        .iter()
+        .map(|(name, constr)| {
+            let drops = " pop".repeat(constr.params.len());
+            format!("{} {{{} }}", name, drops)
+        })
+        .collect();
+    let names: Vec<_> = missing_names.iter().map(|n| format!("`{}`", n)).collect();
+    let last = all_arms.last().unwrap();
+    vec![Fix {
+        title: format!("add arms for {}", names.join(", ")),
+        span: Span::new(last.span.end, last.span.end),
+        replacement: format!(", {}", stubs.join(", ")),
+    }]
+}
+
+fn fmt_stack(stack: &[Type]) -> String {
+    let types: Vec<_> = stack.iter().map(Type::to_string).collect();
+    format!("[{}]", types.join(", "))
+}
+
+/// Replaces the annotation of the op the error is in with the type its body
+/// infers
+fn replace_annotation(source: &str, module: &Module, err: &InferenceError) -> Option<Fix> {
+    // the error is at the definition or at an op of its body
+    let (name, _) = module.op_defs.iter().find(|(_, op_def)| {
+        op_def.span.start <= err.span.start && err.span.end <= op_def.span.end
+    })?;
+    annotation_fix(source, module, name)
+}
+
+/// Replaces the annotation of the op with the type its body infers. A
+/// variable keeps the name of the annotation's variable at the same place,
+/// the others are named `a`, `b` and so on. `None` for ops without a body
+/// and ops whose body does not infer or infers the annotation.
+pub fn annotation_fix(source: &str, module: &Module, name: &str) -> Option<Fix> {
+    let op_def = module.op_defs.get(name)?;
+    let mut inf = Inference::new(module).infer_body(name)?.ok()?;
+    let ann = &op_def.ann;
+    let effect = |ot: &OpType| ot.pre.len() as isize - ot.post.len() as isize;
+    if effect(&inf) == effect(ann) {
+        // as deep as annotated, the callers take the values it passes
+        for i in inf.pre.len()..ann.pre.len() {
+            inf.augment(Type::Poly(format!("_passed_{}", i)));
+        }
+    }
+    if inf.canonical() == ann.canonical() {
+        return None;
+    }
+    let mut names: Vec<(String, String)> = vec![];
+    let places = inf
+        .pre
+        .iter()
+        .zip(&ann.pre)
+        .chain(inf.post.iter().zip(&ann.post));
+    for (inf_t, ann_t) in places {
+        if let (Type::Poly(var), Type::Poly(ann_var)) = (inf_t, ann_t) {
+            if names.iter().all(|(v, n)| v != var && n != ann_var) {
+                names.push((var.clone(), ann_var.clone()));
+            }
+        }
+    }
+    let inf = Normalizer::naming(names).optype(&inf);
+    let replacement = format!(
+        "define {} {} {}",
+        fmt_stack(&inf.pre),
+        name,
+        fmt_stack(&inf.post)
+    );
+    Some(Fix {
+        title: format!("change the annotation to `{}`", replacement),
+        span: annotation_span(source, op_def)?,
+        replacement,
+    })
+}
+
+/// From `define` to the end of the annotation of an op with a body
+pub(crate) fn annotation_span(source: &str, op_def: &OpDef) -> Option<Span> {
+    let colon = source.get(op_def.span.start..op_def.span.end)?.find(':')?;
+    Some(Span::new(op_def.span.start, op_def.span.start + colon))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+    use std::mem::discriminant;
+
+    fn first_error(source: &str) -> Option<InferenceError> {
+        let module = parse(source).unwrap();
+        let report = Inference::new(&module).report();
+        let err = report.all_errors().next().cloned();
+        err
+    }
+
+    /// Applies the first fix of the first error and returns the new source
+    fn apply_first_fix(source: &str) -> (Fix, String) {
+        let module = parse(source).unwrap();
+        let err = first_error(source).expect("source has no error");
+        let fix = suggest(source, &module, &err)
+            .into_iter()
+            .next()
+            .expect("no fix suggested");
+        let fixed = fix.apply(source);
+        let kind = discriminant(&err.error);
+        let module = parse(&fixed).unwrap();
+        let report = Inference::new(&module).report();
+        assert!(
+            report.all_errors().all(|e| discriminant(&e.error) != kind),
+            "fix did not remove the error:\n{}",
+            fixed
+        );
+        (fix, fixed)
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("jusst", "just"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("nothing", "nohting"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(near_miss("jusst", &["nothing", "just"]), Some("just"));
+        assert_eq!(near_miss("foo", &["true", "false"]), None);
+    }
+
+    #[test]
+    fn ident_search_respects_boundaries() {
+        let source = "justs { } just { }";
+        let span = Span::new(0, 18);
+        let found = find_ident(source, &span, "just").unwrap();
+        assert_eq!((found.start, found.end), (10, 14));
+    }
+
+    #[test]
+    fn renames_unknown_constructor() {
+        let (fix, fixed) = apply_first_fix(
+            "data Maybe a: nothing, [a] just.
+             define [Maybe a] unwrap []: case { jusst { pop }, nothing { } }.",
+        );
+        assert_eq!(fix.title, "rename to `just`");
+        assert!(fixed.contains("case { just { pop }, nothing { } }"));
+        assert!(first_error(&fixed).is_none());
+    }
+
+    #[test]
+    fn renames_in_case2_patterns() {
+        let source = "data Bool: true, false.
+             define [Bool, Bool] and [Bool]: case2 { true treu { true }, _ _ { pop pop false } }.";
+        let module = parse(source).unwrap();
+        let err = first_error(source).unwrap();
+        let fixes = suggest(source, &module, &err);
+        assert_eq!(fixes.len(), 1);
+        assert!(fixes[0].apply(source).contains("true true { true }"));
+    }
+
+    #[test]
+    fn renames_misspelled_non_head_arm() {
+        let (fix, fixed) = apply_first_fix(
+            "data Maybe a: nothing, [a] just.
+             define [Maybe a] unwrap []: case { nothing { }, jst { pop } }.",
+        );
+        assert_eq!(fix.title, "rename to `just`");
+        assert!(first_error(&fixed).is_none());
+    }
+
+    #[test]
+    fn stubs_missing_arms() {
+        let (fix, fixed) = apply_first_fix(
+            "data List a: empty, [a, List a] cons, [a] one.
+             define [List a] drop []: case { empty { }, }.",
+        );
+        assert_eq!(fix.title, "add arms for `cons`, `one`");
+        assert!(fixed.contains("case { empty { }, cons { pop pop }, one { pop }, }"));
+        assert!(first_error(&fixed).is_none());
+    }
+
+    #[test]
+    fn stubs_nested_case() {
+        let (_, fixed) = apply_first_fix(
+            "data Bool: true, false.
+             define [Bool] f []: (case { true { } }) pop pop.",
+        );
+        assert!(fixed.contains("case { true { }, false { } }"));
+    }
+
+    #[test]
+    fn replaces_annotation_of_wrong_arity() {
+        let (fix, fixed) = apply_first_fix("data Foo: foo.\ndefine [] onefoo [Foo]: foo foo.");
+        assert_eq!(
+            fix.title,
+            "change the annotation to `define [] onefoo [Foo, Foo]`"
+        );
+        assert_eq!(
+            fixed,
+            "data Foo: foo.\ndefine [] onefoo [Foo, Foo]: foo foo."
+        );
+        assert!(first_error(&fixed).is_none());
+
+        let (_, fixed) = apply_first_fix("define [a] f [a, a, a]: pop.");
+        assert_eq!(fixed, "define [a] f []: pop.");
+    }
+
+    #[test]
+    fn no_fix_without_near_miss() {
+        let source = "data Bool: true, false.
+             data Foo: foo.
+             define [Bool] not [Bool]: case { true { false }, false { true }, foo { true } }.";
+        let module = parse(source).unwrap();
+        let err = first_error(source).unwrap();
+        assert!(suggest(source, &module, &err).is_empty());
+    }
+}
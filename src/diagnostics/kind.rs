@@ -0,0 +1,134 @@
+//! What tools matching on errors should match on. The error enums are
+//! `#[non_exhaustive]` and their variants change as the messages get
+//! better, their codes and kinds do not:
+//!
+//! - a code names one kind of error for good, it is never reused for
+//!   another once the error it named is gone
+//! - a kind is never removed, and a code keeps its kind, new codes get one
+//!   of the kinds there are or a new kind
+//!
+//! The accessors of `InferenceError` and `RuntimeError`, `code`, `kind`,
+//! `primary_span`, `message` and `related_spans`, stay as they are too.
+
+/// A group of error codes, coarser than the codes themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// The source does not parse
+    Syntax,
+    /// A name nothing defines
+    UnknownName,
+    /// A data, op or constant definition is not allowed as it is
+    Definition,
+    /// Two types that have to be the same are not, or a type cannot be
+    /// built
+    TypeMismatch,
+    /// Ops take other values than the ones there are
+    StackEffect,
+    /// A case does not fit what it matches, or misses constructors
+    Case,
+    /// A use of an overloaded name resolves to no single definition
+    Overload,
+    /// A strict mode check
+    Strict,
+    /// A pragma or stack effect comment says otherwise than checking finds
+    Expectation,
+    /// Checking was stopped before it finished
+    Interrupted,
+    /// Running failed at an op
+    Runtime,
+    /// Running went over one of its limits
+    Limit,
+    /// A host op or an embedder's literal failed
+    Host,
+    /// A code this version does not know, as a cached error of another
+    /// version may have
+    Other,
+}
+
+impl DiagnosticKind {
+    /// The kind of the code, `Other` for codes no error has
+    pub fn of(code: &str) -> Self {
+        match code {
+            "UnrecognizedEof"
+            | "UnrecognizedToken"
+            | "InvalidToken"
+            | "ExtraToken"
+            | "UnexpectedCharacter"
+            | "InvalidInteger"
+            | "UnknownPragma"
+            | "AppliedTypeVariable"
+            | "UnknownLiteralSuffix"
+            | "InvalidLiteral"
+            | "LeadingZeros" => DiagnosticKind::Syntax,
+            "UnknownOp" | "UnknownConstructor" | "UnknownLiteralKind" | "RuntimeUnknownOp" => {
+                DiagnosticKind::UnknownName
+            }
+            "DuplicateConstructor"
+            | "OverloadNotMarked"
+            | "OverloadsIndistinct"
+            | "ImpureConstant"
+            | "DynInAnnotation" => DiagnosticKind::Definition,
+            "AnnInfConflict"
+            | "EmptyBodyCannotSatisfy"
+            | "UnificationError"
+            | "OccursCheck"
+            | "TypeTooLarge"
+            | "InfiniteFix"
+            | "ListMGULengthDifferent"
+            | "OpPrePostLenNeq" => DiagnosticKind::TypeMismatch,
+            "NotEnoughValues"
+            | "QuoteNeedsInputs"
+            | "IndexNotLiteral"
+            | "ArmsDisagreeOnStackDepth" => DiagnosticKind::StackEffect,
+            "NotAllConstructorsCovered"
+            | "CaseArmMismatch"
+            | "ScrutineeMismatch"
+            | "CannotMatchBuiltin"
+            | "CannotMatchOnQuote"
+            | "MissingCombinations" => DiagnosticKind::Case,
+            "AmbiguousOverload" | "NoOverloadFits" | "OverloadNeedsContext" => {
+                DiagnosticKind::Overload
+            }
+            "AnnotationTooSpecific"
+            | "UncheckedOp"
+            | "ShadowsPrelude"
+            | "OutputOnlyVar"
+            | "UnreachableArm"
+            | "Deprecated" => DiagnosticKind::Strict,
+            "CheckpointMismatch" | "ExpectTypeMismatch" | "ExpectErrorMismatch" => {
+                DiagnosticKind::Expectation
+            }
+            "Interrupted" => DiagnosticKind::Interrupted,
+            "StackUnderflow" | "NotAQuote" | "NotAConstructor" | "NotAnIndex" | "NoMatchingArm"
+            | "IntOverflow" | "ConstantCycle" | "Assertion" | "NotImplemented"
+            | "DynTypeMismatch" => DiagnosticKind::Runtime,
+            "LimitExceeded" => DiagnosticKind::Limit,
+            "HostTypeMismatch"
+            | "HostResultArity"
+            | "HostError"
+            | "UnregisteredLiteral"
+            | "LiteralError" => DiagnosticKind::Host,
+            _ => DiagnosticKind::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::explanations::EXPLANATIONS;
+
+    #[test]
+    fn every_explained_code_has_a_kind() {
+        for explanation in EXPLANATIONS.iter() {
+            assert_ne!(
+                DiagnosticKind::of(explanation.code),
+                DiagnosticKind::Other,
+                "{}",
+                explanation.code
+            );
+        }
+        assert_eq!(DiagnosticKind::of("NoSuchCode"), DiagnosticKind::Other);
+    }
+}
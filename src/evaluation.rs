@@ -1,2 +1,9 @@
+pub mod coverage;
+pub mod display;
 pub mod evaluator;
+pub mod host;
+pub mod origins;
+#[cfg(feature = "reference")]
+pub mod reference;
+pub mod serialize;
 pub mod types;
@@ -0,0 +1,200 @@
+//! Which op definitions and case arms an evaluation ran, for finding the
+//! parts of a program its tests never reach
+
+use crate::diagnostics::{json_string, line_col};
+use crate::syntax::ast::*;
+use std::collections::HashSet;
+use std::iter::once;
+
+/// What the evaluator ran, collected with `Evaluator::with_coverage`. Arms
+/// are told apart by their spans.
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    ops: HashSet<String>,
+    arms: HashSet<(usize, usize)>,
+}
+
+/// A definition or case arm that never ran
+#[derive(Debug, Clone)]
+pub enum UncoveredItem<'m> {
+    Op {
+        name: &'m str,
+        span: &'m Span,
+    },
+    Arm {
+        /// The op definition the arm is in
+        op: &'m str,
+        /// The constructor matched, or both patterns of a `case2` arm
+        patterns: String,
+        span: &'m Span,
+    },
+}
+
+impl UncoveredItem<'_> {
+    pub fn span(&self) -> &Span {
+        match self {
+            UncoveredItem::Op { span, .. } | UncoveredItem::Arm { span, .. } => span,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            UncoveredItem::Op { name, .. } => format!("op `{}` never ran", name),
+            UncoveredItem::Arm { op, patterns, .. } => {
+                format!("arm `{}` of `{}` never ran", patterns, op)
+            }
+        }
+    }
+}
+
+/// The parts of a module an evaluation did not reach, in source order
+#[derive(Debug, Clone)]
+pub struct Uncovered<'m> {
+    pub items: Vec<UncoveredItem<'m>>,
+    pub op_count: usize,
+    /// Arms of the ops that ran
+    pub arm_count: usize,
+}
+
+impl CoverageReport {
+    pub(crate) fn record_op(&mut self, name: &str) {
+        if !self.ops.contains(name) {
+            self.ops.insert(name.to_owned());
+        }
+    }
+
+    pub(crate) fn record_arm(&mut self, span: &Span) {
+        self.arms.insert((span.start, span.end));
+    }
+
+    pub fn op_ran(&self, name: &str) -> bool {
+        self.ops.contains(name)
+    }
+
+    pub fn arm_ran(&self, span: &Span) -> bool {
+        self.arms.contains(&(span.start, span.end))
+    }
+
+    /// The op definitions of `module` that never ran and the arms that were
+    /// never taken. The arms of an op that never ran are left out, the op
+    /// is reported instead.
+    pub fn uncovered<'m>(&self, module: &'m Module) -> Uncovered<'m> {
+        let mut uncovered = Uncovered {
+            items: vec![],
+            op_count: 0,
+            arm_count: 0,
+        };
+        for (name, op_def) in module.op_defs_in_source_order() {
+            let mut arms = vec![];
+            collect_arms(&op_def.body, &mut arms);
+            uncovered.op_count += 1;
+            if !self.op_ran(name) {
+                uncovered.items.push(UncoveredItem::Op {
+                    name,
+                    span: &op_def.name_span,
+                });
+                continue;
+            }
+            uncovered.arm_count += arms.len();
+            for (patterns, span) in arms {
+                if !self.arm_ran(span) {
+                    uncovered.items.push(UncoveredItem::Arm {
+                        op: name,
+                        patterns,
+                        span,
+                    });
+                }
+            }
+        }
+        uncovered
+    }
+}
+
+/// The arms in `ops` and the ops nested in them, in source order
+fn collect_arms<'m>(ops: &'m [Op], out: &mut Vec<(String, &'m Span)>) {
+    for op in ops {
+        match op {
+            Op::Quote { value, .. } => collect_arms(value, out),
+            Op::Case { head_arm, arms, .. } => {
+                for arm in once(head_arm).chain(arms) {
+                    out.push((arm.constr.clone(), &arm.span));
+                    collect_arms(&arm.body, out);
+                }
+            }
+            Op::Case2 { arms, .. } => {
+                for arm in arms {
+                    let [p1, p2] = &arm.patterns;
+                    out.push((format!("{} {}", p1, p2), &arm.span));
+                    collect_arms(&arm.body, out);
+                }
+            }
+            Op::List { items, .. } => {
+                for item in items {
+                    collect_arms(item, out);
+                }
+            }
+            Op::Literal { .. } | Op::Name { .. } => (),
+        }
+    }
+}
+
+impl Uncovered<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// A summary line followed by a `line:col: ...` line per item
+    pub fn render(&self, source: &str) -> String {
+        let uncovered_ops = self
+            .items
+            .iter()
+            .filter(|item| matches!(item, UncoveredItem::Op { .. }))
+            .count();
+        let ran_arms = self.arm_count - (self.items.len() - uncovered_ops);
+        let mut out = format!(
+            "coverage: {} of {} ops, {} of {} arms of the ops that ran\n",
+            self.op_count - uncovered_ops,
+            self.op_count,
+            ran_arms,
+            self.arm_count
+        );
+        for item in self.items.iter() {
+            let (line, col) = line_col(source, item.span().start);
+            out.push_str(&format!("{}:{}: {}\n", line, col, item.message()));
+        }
+        out
+    }
+
+    pub fn to_json(&self, source: &str) -> String {
+        let items: Vec<_> = self
+            .items
+            .iter()
+            .map(|item| {
+                let (line, col) = line_col(source, item.span().start);
+                let (kind, name) = match item {
+                    UncoveredItem::Op { name, .. } => ("op", name.to_string()),
+                    UncoveredItem::Arm { patterns, .. } => ("arm", patterns.clone()),
+                };
+                let op = match item {
+                    UncoveredItem::Op { name, .. } | UncoveredItem::Arm { op: name, .. } => name,
+                };
+                format!(
+                    "{{\"kind\":\"{}\",\"name\":{},\"op\":{},\"start\":{},\"end\":{},\"line\":{},\"col\":{}}}",
+                    kind,
+                    json_string(&name),
+                    json_string(op),
+                    item.span().start,
+                    item.span().end,
+                    line,
+                    col
+                )
+            })
+            .collect();
+        format!(
+            "{{\"ops\":{},\"arms\":{},\"uncovered\":[{}]}}",
+            self.op_count,
+            self.arm_count,
+            items.join(",")
+        )
+    }
+}
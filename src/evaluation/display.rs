@@ -0,0 +1,287 @@
+use super::types::*;
+use std::fmt;
+
+/// Controls how runtime values are rendered
+#[derive(Debug, Clone)]
+pub struct DisplayOptions {
+    /// Constructor applications nested deeper than this are elided as `...`
+    pub max_depth: usize,
+    /// Upper bound on the number of rendered values, the rest is elided as `...`
+    pub max_nodes: usize,
+    /// Render the ops of quoted sentences instead of eliding them
+    pub show_quote_ops: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions {
+            max_depth: 32,
+            max_nodes: 256,
+            show_quote_ops: true,
+        }
+    }
+}
+
+struct Renderer<'o> {
+    opts: &'o DisplayOptions,
+    nodes_left: usize,
+}
+
+impl Renderer<'_> {
+    fn value(&mut self, out: &mut String, value: &Value, depth: usize) {
+        if self.nodes_left == 0 {
+            out.push_str("...");
+            return;
+        }
+        self.nodes_left -= 1;
+        match value {
+            Value::Int(n) => out.push_str(&n.to_string()),
+            Value::User { constr_name, args } if args.is_empty() => out.push_str(constr_name),
+            Value::User { .. } if depth >= self.opts.max_depth => out.push_str("..."),
+            Value::User { constr_name, args } => {
+                out.push('(');
+                out.push_str(constr_name);
+                for arg in args {
+                    out.push(' ');
+                    self.value(out, arg, depth + 1);
+                }
+                out.push(')');
+            }
+            Value::Quoted(quoted) => {
+                out.push('[');
+                self.quoted(out, quoted, depth + 1);
+                out.push(']');
+            }
+        }
+    }
+
+    fn quoted(&mut self, out: &mut String, quoted: &Quoted, depth: usize) {
+        match quoted {
+            Quoted::Sentence { ops } if ops.is_empty() => (),
+            Quoted::Sentence { ops } if self.opts.show_quote_ops => {
+                let ops: Vec<_> = ops.iter().map(|op| op.to_string()).collect();
+                out.push_str(&ops.join(" "));
+            }
+            Quoted::Sentence { .. } => out.push_str("..."),
+            Quoted::Value { value } => self.value(out, value, depth),
+            Quoted::Composed { a, b } => {
+                self.quoted(out, a, depth);
+                out.push(' ');
+                self.quoted(out, b, depth);
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Renders the value: ints as-is, constructor applications as
+    /// `(suc zero)`, quotes as `[ops]`
+    pub fn display(&self, opts: &DisplayOptions) -> String {
+        let mut out = String::new();
+        let mut renderer = Renderer {
+            opts,
+            nodes_left: opts.max_nodes,
+        };
+        renderer.value(&mut out, self, 0);
+        out
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(&DisplayOptions::default()))
+    }
+}
+
+impl fmt::Display for RuntimeErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorMessage::StackUnderflow => write!(f, "stack underflow"),
+            RuntimeErrorMessage::LimitExceeded(kind) => {
+                let limit = match kind {
+                    LimitKind::Steps => "steps",
+                    LimitKind::CallDepth => "call depth",
+                    LimitKind::StackLen => "stack length",
+                    LimitKind::ValueNodes => "value size",
+                };
+                write!(f, "exceeded the limit on {}", limit)
+            }
+            RuntimeErrorMessage::NotAQuote { value } => write!(f, "`{}` is not a quote", value),
+            RuntimeErrorMessage::NotAConstructor { value } => {
+                write!(f, "`{}` is not built by a constructor", value)
+            }
+            RuntimeErrorMessage::NotAnIndex { value } => {
+                write!(f, "`{}` is not an index into the stack", value)
+            }
+            RuntimeErrorMessage::UnknownOp { name } => write!(f, "unknown op `{}`", name),
+            RuntimeErrorMessage::UnknownConstructor { name } => {
+                write!(f, "no arm for the constructor `{}`", name)
+            }
+            RuntimeErrorMessage::HostTypeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "host op `{}` passed `{}` where its type says {}",
+                name, found, expected
+            ),
+            RuntimeErrorMessage::DynTypeMismatch { expected, found } => write!(
+                f,
+                "`{}` is not of type {}, which the op takes where its value's type is `?`",
+                found, expected
+            ),
+            RuntimeErrorMessage::HostResultArity {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "host op `{}` returned {} values instead of {}",
+                name, found, expected
+            ),
+            RuntimeErrorMessage::HostError { name, message } => {
+                write!(f, "host op `{}` failed: {}", name, message)
+            }
+            RuntimeErrorMessage::UnknownLiteralKind { tag } => {
+                write!(f, "no value is registered for `{}` literals", tag)
+            }
+            RuntimeErrorMessage::LiteralError { repr, message } => {
+                write!(f, "literal `{}` failed: {}", repr, message)
+            }
+            RuntimeErrorMessage::IntOverflow { name } => write!(f, "`{}` overflowed", name),
+            RuntimeErrorMessage::ConstantCycle { names } => match &names[..] {
+                [name] => write!(f, "constant `{}` depends on itself", name),
+                [name, through @ ..] => {
+                    let through: Vec<_> = through.iter().map(|n| format!("`{}`", n)).collect();
+                    write!(
+                        f,
+                        "constant `{}` depends on itself through {}",
+                        name,
+                        through.join(", ")
+                    )
+                }
+                [] => write!(f, "constants depend on each other"),
+            },
+            RuntimeErrorMessage::Assertion { message } => write!(f, "{}", message),
+            RuntimeErrorMessage::NotImplemented { name } => {
+                write!(f, "op `{}` is declared but has no implementation", name)
+            }
+        }
+    }
+}
+
+/// Renders the stack topmost value first, the same way stacks are annotated
+pub fn display_stack(stack: &[Value], opts: &DisplayOptions) -> String {
+    let values: Vec<_> = stack.iter().rev().map(|v| v.display(opts)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::display::*;
+    use crate::evaluation::evaluator::Evaluator;
+    use crate::syntax::parse;
+
+    fn eval_main(input: &str) -> Vec<Value> {
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        evaluator.stack
+    }
+
+    #[test]
+    fn constructors() {
+        let stack = eval_main(
+            "
+            data Nat: zero, [Nat] suc.
+            data Pair a b: [a, b] pair.
+            define [] main [Pair Nat Nat]: zero zero suc pair.
+            ",
+        );
+        assert_eq!(stack[0].to_string(), "(pair (suc zero) zero)");
+    }
+
+    #[test]
+    fn stack_topmost_first() {
+        let stack = eval_main(
+            "
+            data Foo: foo.
+            data Bar: bar.
+            define [] main [Bar, Foo]: foo bar.
+            ",
+        );
+        assert_eq!(
+            display_stack(&stack, &DisplayOptions::default()),
+            "[bar, foo]"
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        let stack = eval_main(
+            "
+            data Foo: foo.
+            define [] main [[][], [][Foo], [][Foo, Foo]]: (foo dup) foo quote ().
+            ",
+        );
+        let opts = DisplayOptions::default();
+        assert_eq!(display_stack(&stack, &opts), "[[], [foo], [foo dup]]");
+        let opts = DisplayOptions {
+            show_quote_ops: false,
+            ..DisplayOptions::default()
+        };
+        assert_eq!(display_stack(&stack, &opts), "[[], [foo], [...]]");
+    }
+
+    #[test]
+    fn composed_quote() {
+        let stack = eval_main(
+            "
+            data Foo: foo.
+            define [] main [[][Foo, Foo]]: (foo) foo quote comp-0-1-0-1.
+            ",
+        );
+        assert_eq!(stack[0].to_string(), "[foo foo]");
+    }
+
+    #[test]
+    fn depth_elision() {
+        let stack = eval_main(
+            "
+            data Nat: zero, [Nat] suc.
+            define [] main [Nat]: zero suc suc suc suc.
+            ",
+        );
+        let opts = DisplayOptions {
+            max_depth: 2,
+            ..DisplayOptions::default()
+        };
+        assert_eq!(stack[0].display(&opts), "(suc (suc ...))");
+    }
+
+    #[test]
+    fn size_elision() {
+        let stack = eval_main(
+            "
+            data Foo: foo.
+            data Triple: [Foo, Foo, Foo] triple.
+            define [] main [Triple]: foo foo foo triple.
+            ",
+        );
+        let opts = DisplayOptions {
+            max_nodes: 3,
+            ..DisplayOptions::default()
+        };
+        assert_eq!(stack[0].display(&opts), "(triple foo foo ...)");
+    }
+
+    #[test]
+    fn int_round_trip() {
+        for n in [0, 7, -13, i32::MAX, i32::MIN] {
+            let printed = Value::Int(n).to_string();
+            let stack = eval_main(&format!("define [] main [Int]: {}.", printed));
+            assert!(matches!(stack[..], [Value::Int(m)] if m == n));
+        }
+    }
+}
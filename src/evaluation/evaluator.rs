@@ -1,5 +1,14 @@
+use super::coverage::CoverageReport;
+use super::display::{display_stack, DisplayOptions};
+use super::host::*;
+use super::origins::{Origin, Origins};
 use super::types::*;
+use crate::desugar::lists;
 use crate::syntax::{ast::*, module_wrapper::ModuleConstrMaps};
+use crate::typing::{dynamic::DynCheck, prelude_types, types::OpType};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::iter::once;
 
 fn parse_parametric<const N: usize>(prefix: &str, s: &str) -> Option<[usize; N]> {
     let rest = s.strip_prefix(prefix)?;
@@ -11,142 +20,1049 @@ fn parse_parametric<const N: usize>(prefix: &str, s: &str) -> Option<[usize; N]>
         .ok()
 }
 
+/// The constants of the module, each after the ones its initializer names
+fn const_order(module: &Module) -> Result<Vec<&str>, RuntimeError> {
+    fn deps<'m>(module: &Module, ops: &'m [Op], out: &mut Vec<&'m str>) {
+        for op in ops {
+            match op {
+                Op::Name { value, .. } if module.const_defs.contains_key(value) => out.push(value),
+                Op::Quote { value, .. } => deps(module, value, out),
+                Op::Case { head_arm, arms, .. } => {
+                    for arm in once(head_arm).chain(arms) {
+                        deps(module, &arm.body, out);
+                    }
+                }
+                Op::Case2 { arms, .. } => {
+                    for arm in arms {
+                        deps(module, &arm.body, out);
+                    }
+                }
+                Op::List { items, .. } => {
+                    for item in items {
+                        deps(module, item, out);
+                    }
+                }
+                Op::Name { .. } | Op::Literal { .. } => (),
+            }
+        }
+    }
+    // depth first, `path` holds the constants being visited
+    fn visit<'m>(
+        module: &'m Module,
+        name: &'m str,
+        path: &mut Vec<&'m str>,
+        order: &mut Vec<&'m str>,
+    ) -> Result<(), RuntimeError> {
+        if order.contains(&name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|n| *n == name) {
+            let const_def = &module.const_defs[path[start]];
+            return Err(RuntimeError::new(
+                &const_def.name_span,
+                RuntimeErrorMessage::ConstantCycle {
+                    names: path[start..].iter().map(|n| n.to_string()).collect(),
+                },
+            ));
+        }
+        path.push(name);
+        let mut uses = vec![];
+        deps(module, &module.const_defs[name].body, &mut uses);
+        for dep in uses {
+            visit(module, dep, path, order)?;
+        }
+        path.pop();
+        order.push(name);
+        Ok(())
+    }
+    let mut order = vec![];
+    for (name, _) in module.const_defs_in_source_order() {
+        visit(module, name, &mut vec![], &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Ops to run, a definition's or a case arm's borrowed from the module, or a
+/// quote's, owned like the quote
+enum Code<'m> {
+    Module(&'m [Op]),
+    Quote(Vec<Op>),
+}
+
+/// The body of the `i`th arm of a case, the head arm first
+fn arm_body(op: &Op, i: usize) -> &[Op] {
+    match op {
+        Op::Case { head_arm, arms, .. } => &once(head_arm).chain(arms).nth(i).unwrap().body,
+        Op::Case2 { arms, .. } => &arms[i].body,
+        _ => unreachable!("only cases have arms"),
+    }
+}
+
+/// Same as `arm_body`, taking the body out of the case
+fn into_arm_body(op: Op, i: usize) -> Vec<Op> {
+    match op {
+        Op::Case { head_arm, arms, .. } => once(head_arm).chain(arms).nth(i).unwrap().body,
+        Op::Case2 { mut arms, .. } => arms.swap_remove(i).body,
+        _ => unreachable!("only cases have arms"),
+    }
+}
+
+type LiteralFn = Box<dyn Fn(&str) -> Result<Value, String>>;
+
 pub struct Evaluator<'m> {
     module: &'m Module,
     constr_maps: ModuleConstrMaps<'m>,
+    /// The name of every constructor, made once so that building a value
+    /// does not allocate one
+    constr_names: HashMap<&'m str, ConstrName>,
+    host_ops: HashMap<String, HostOp>,
+    /// The value of an extern literal of each tag, from its text
+    literals: HashMap<String, LiteralFn>,
+    limits: ExecLimits,
+    int_semantics: IntSemantics,
+    coverage: Option<CoverageReport>,
+    /// Where the values on the stack were made, with `with_origins`
+    origins: Option<Origins>,
+    steps: usize,
+    /// The calls being evaluated, outermost first
+    frames: Vec<Frame>,
+    /// A call ending a call replaces it in `frames` rather than nesting in
+    /// it, and runs without recursing
+    tail_calls: bool,
+    /// The values of the constants, once computed
+    consts: Option<HashMap<String, Value>>,
+    /// The values to check before each op, by the start of its span
+    dyn_checks: BTreeMap<OpKey, Vec<DynCheck>>,
+    /// Left as it was at the point of failure when the evaluation errors
     pub stack: Vec<Value>,
 }
 
 impl<'m> Evaluator<'m> {
     pub fn new(module: &'m Module) -> Self {
         let constr_maps = ModuleConstrMaps::new(module);
+        let constr_names = constr_maps
+            .constr_to_constr_map
+            .keys()
+            .map(|&name| (name, ConstrName::from(name)))
+            .collect();
         Evaluator {
             module,
             constr_maps,
+            constr_names,
+            host_ops: HashMap::new(),
+            literals: HashMap::new(),
+            limits: ExecLimits::default(),
+            int_semantics: IntSemantics::default(),
+            coverage: None,
+            origins: None,
+            steps: 0,
+            frames: vec![],
+            tail_calls: true,
+            consts: None,
+            dyn_checks: BTreeMap::new(),
             stack: vec![],
         }
     }
 
-    pub fn eval_main(&mut self) {
-        let main_op_def = self.module.op_defs.get("main").expect("no main");
-        self.eval_sentence(&main_op_def.body);
+    pub fn with_limits(mut self, limits: ExecLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Whether calls in tail position reuse the frame of the call they end,
+    /// on by default. Without, every call counts toward `max_call_depth`
+    /// and error traces show every call.
+    pub fn with_tail_calls(mut self, tail_calls: bool) -> Self {
+        self.tail_calls = tail_calls;
+        self
+    }
+
+    /// Checks the values ops take that the ops before leave with a `?` in
+    /// their type, as `TypecheckReport::dyn_checks` records them. Without,
+    /// such values are trusted to be of the type the op takes.
+    pub fn with_dyn_checks(mut self, dyn_checks: BTreeMap<OpKey, Vec<DynCheck>>) -> Self {
+        self.dyn_checks = dyn_checks;
+        self
+    }
+
+    pub fn with_int_semantics(mut self, int_semantics: IntSemantics) -> Self {
+        self.int_semantics = int_semantics;
+        self
+    }
+
+    /// Records which op definitions run and which case arms are taken,
+    /// across evaluations
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = Some(CoverageReport::default());
+        self
+    }
+
+    /// What ran so far, `None` without `with_coverage`
+    pub fn coverage(&self) -> Option<&CoverageReport> {
+        self.coverage.as_ref()
+    }
+
+    /// Records where every value is made, for runtime errors to say where
+    /// the value they are about came from. Costs about as much memory as
+    /// the values themselves.
+    pub fn with_origins(mut self) -> Self {
+        self.origins = Some(Origins::default());
+        self
+    }
+
+    /// Where each value on the stack was made, bottom first, `None` without
+    /// `with_origins`
+    pub fn origins(&self) -> Option<&[Origin]> {
+        self.origins.as_ref().map(|origins| &origins.stack[..])
+    }
+
+    /// Number of ops executed by the last evaluation
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Exposes a rust function as an op. The function receives the popped
+    /// values topmost first and returns the values to push, topmost first.
+    pub fn register<const N: usize, F>(
+        &mut self,
+        name: &str,
+        optype: OpType,
+        f: F,
+    ) -> Result<(), HostRegistrationError>
+    where
+        F: Fn([Value; N]) -> Result<Vec<Value>, String> + 'static,
+    {
+        if optype.pre.len() != N {
+            return Err(HostRegistrationError::ArityMismatch {
+                name: name.to_owned(),
+                declared: N,
+                expected: optype.pre.len(),
+            });
+        }
+        self.insert_host_op(name, HostOp::new(optype, f))
+    }
+
+    /// Exposes Int arithmetic as an op, overflowing as the evaluator's
+    /// `IntSemantics` say
+    pub fn register_int_op(&mut self, name: &str, op: IntOp) -> Result<(), HostRegistrationError> {
+        self.insert_host_op(name, HostOp::int(op))
+    }
+
+    /// Gives the extern literals tagged `tag` the values the function makes
+    /// of their text, see `LiteralTable`
+    pub fn register_literal<F>(&mut self, tag: &str, f: F) -> Result<(), HostRegistrationError>
+    where
+        F: Fn(&str) -> Result<Value, String> + 'static,
+    {
+        if self.literals.contains_key(tag) {
+            return Err(HostRegistrationError::DuplicateName {
+                name: tag.to_owned(),
+            });
+        }
+        self.literals.insert(tag.to_owned(), Box::new(f));
+        Ok(())
+    }
+
+    fn insert_host_op(&mut self, name: &str, host_op: HostOp) -> Result<(), HostRegistrationError> {
+        if self.host_ops.contains_key(name) {
+            return Err(HostRegistrationError::DuplicateName {
+                name: name.to_owned(),
+            });
+        }
+        self.host_ops.insert(name.to_owned(), host_op);
+        Ok(())
+    }
+
+    /// Names and declared op types of the registered host ops, in order to
+    /// feed them to the typechecker as extern ops.
+    pub fn host_optypes(&self) -> impl Iterator<Item = (&str, &OpType)> {
+        self.host_ops
+            .iter()
+            .map(|(name, host_op)| (name.as_str(), &host_op.optype))
+    }
+
+    pub fn eval_main(&mut self) -> Result<(), EvaluatorError> {
+        if !self.module.op_defs.contains_key("main") {
+            return Err(EvaluatorError::NoMain);
+        }
+        self.eval_op_def("main")
+    }
+
+    /// Runs the body of an op definition on the current stack, computing
+    /// the constants first if no evaluation did yet. The steps and calls are
+    /// counted from zero, the constants taking theirs from the same limits.
+    pub fn eval_op_def(&mut self, name: &str) -> Result<(), EvaluatorError> {
+        let op_def = self
+            .module
+            .op_defs
+            .get(name)
+            .ok_or_else(|| EvaluatorError::NoOpDef {
+                name: name.to_owned(),
+            })?;
+        self.steps = 0;
+        self.frames.clear();
+        self.eval_consts()?;
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record_op(name);
+        }
+        self.eval_body(&op_def.body)?;
+        Ok(())
+    }
+
+    /// Computes every constant once, each one after the constants its
+    /// initializer names. The values are kept and pushed by every use.
+    pub fn eval_consts(&mut self) -> Result<(), RuntimeError> {
+        if self.consts.is_some() {
+            return Ok(());
+        }
+        let order = const_order(self.module)?;
+        self.consts = Some(HashMap::new());
+        let stack = std::mem::take(&mut self.stack);
+        let origins = self.origins.as_mut().map(|origins| {
+            origins.align(stack.len());
+            std::mem::take(&mut origins.stack)
+        });
+        for name in order {
+            let const_def = &self.module.const_defs[name];
+            let value = self
+                .eval_body(&const_def.body)
+                .and_then(|()| self.pop_traced(&const_def.span));
+            let (value, origin) = match value {
+                Ok(value) => value,
+                Err(err) => {
+                    // the stack is left as the initializer left it
+                    self.consts = None;
+                    return Err(err);
+                }
+            };
+            self.stack.clear();
+            if let Some(origins) = self.origins.as_mut() {
+                origins.stack.clear();
+                origins.consts.insert(name.to_owned(), origin);
+            }
+            if let Some(consts) = self.consts.as_mut() {
+                consts.insert(name.to_owned(), value);
+            }
+        }
+        self.stack = stack;
+        if let (Some(origins), Some(stack)) = (self.origins.as_mut(), origins) {
+            origins.stack = stack;
+        }
+        Ok(())
+    }
+
+    /// Runs the body of a definition
+    fn eval_body(&mut self, body: &[Op]) -> Result<(), RuntimeError> {
+        if let Some(origins) = self.origins.as_mut() {
+            origins.align(self.stack.len());
+        }
+        // a failing op leaves the frames it was in behind
+        self.eval_sentence(body).map_err(|mut err| {
+            err.trace = self.frames.drain(..).rev().collect();
+            err
+        })
     }
 
-    fn eval_sentence(&mut self, ops: &[Op]) {
+    fn eval_sentence(&mut self, ops: &[Op]) -> Result<(), RuntimeError> {
         for op in ops.iter() {
-            self.eval(op);
+            self.eval(op)?;
         }
+        Ok(())
     }
 
-    fn pop(&mut self) -> Value {
-        self.stack.pop().expect("underflow error")
+    fn pop(&mut self, span: &Span) -> Result<Value, RuntimeError> {
+        self.pop_traced(span).map(|(value, _)| value)
+    }
+
+    /// Same as `pop`, with where the value was made, unknown without
+    /// `with_origins`
+    fn pop_traced(&mut self, span: &Span) -> Result<(Value, Origin), RuntimeError> {
+        let value = self
+            .stack
+            .pop()
+            .ok_or_else(|| RuntimeError::new(span, RuntimeErrorMessage::StackUnderflow))?;
+        let origin = self.origins.as_mut().map(Origins::pop).unwrap_or_default();
+        Ok((value, origin))
+    }
+
+    fn pop_quoted(&mut self, span: &Span) -> Result<(Quoted, Origin), RuntimeError> {
+        match self.pop_traced(span)? {
+            (Value::Quoted(quoted), origin) => Ok((quoted, origin)),
+            (value, origin) => Err(RuntimeError::new(
+                span,
+                RuntimeErrorMessage::NotAQuote { value },
+            )
+            .with_origin(origin.span)),
+        }
+    }
+
+    /// Pops the index of a `pick` or `roll`
+    fn pop_index(&mut self, span: &Span) -> Result<usize, RuntimeError> {
+        match self.pop_traced(span)? {
+            (Value::Int(n), _) if n >= 0 => Ok(n as usize),
+            (value, origin) => Err(RuntimeError::new(
+                span,
+                RuntimeErrorMessage::NotAnIndex { value },
+            )
+            .with_origin(origin.span)),
+        }
+    }
+
+    /// Pushes a value that was made at `origin`
+    fn push_traced(&mut self, value: Value, origin: Origin) {
+        self.stack.push(value);
+        if let Some(origins) = self.origins.as_mut() {
+            origins.stack.push(origin);
+        }
     }
 
-    fn eval_quoted(&mut self, quoted: Quoted) {
-        match quoted {
-            Quoted::Sentence { ops } => self.eval_sentence(&ops),
-            Quoted::Value { value } => self.stack.push(*value),
-            Quoted::Composed { a, b } => {
-                self.eval_quoted(*a);
-                self.eval_quoted(*b);
+    /// Pushes a value the op at `span` made
+    fn push_made(&mut self, value: Value, span: &Span) {
+        self.stack.push(value);
+        if let Some(origins) = self.origins.as_mut() {
+            origins.stack.push(Origin::made(span, vec![]));
+        }
+    }
+
+    fn ensure_depth(&self, depth: usize, span: &Span) -> Result<(), RuntimeError> {
+        if self.stack.len() < depth {
+            return Err(RuntimeError::new(span, RuntimeErrorMessage::StackUnderflow));
+        }
+        Ok(())
+    }
+
+    fn check_limit(
+        &self,
+        value: usize,
+        limit: Option<usize>,
+        kind: LimitKind,
+        span: &Span,
+    ) -> Result<(), RuntimeError> {
+        match limit {
+            Some(max) if value > max => Err(RuntimeError::new(
+                span,
+                RuntimeErrorMessage::LimitExceeded(kind),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the size of a value about to be built from the topmost
+    /// `fields` values. Done before the fields are popped so that the stack
+    /// is left intact on failure.
+    fn check_value_nodes(&self, fields: usize, span: &Span) -> Result<(), RuntimeError> {
+        if self.limits.max_value_nodes.is_none() {
+            return Ok(());
+        }
+        let fields = &self.stack[self.stack.len().saturating_sub(fields)..];
+        let nodes = 1 + fields.iter().map(Value::node_count).sum::<usize>();
+        self.check_limit(
+            nodes,
+            self.limits.max_value_nodes,
+            LimitKind::ValueNodes,
+            span,
+        )
+    }
+
+    /// Enters a call of `op` at `span`, or of a quote when `op` is `None`
+    fn enter_call(
+        &mut self,
+        op: Option<&str>,
+        quoted: Option<&Quoted>,
+        span: &Span,
+    ) -> Result<(), RuntimeError> {
+        let frame_span = quoted
+            .and_then(Quoted::span)
+            .unwrap_or_else(|| span.clone());
+        self.frames.push(Frame {
+            op: op.map(str::to_owned),
+            span: frame_span,
+        });
+        self.check_limit(
+            self.frames.len(),
+            self.limits.max_call_depth,
+            LimitKind::CallDepth,
+            span,
+        )
+    }
+
+    fn leave_call(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Makes the innermost call one of `op` at `span`, or of a quote when
+    /// `op` is `None`, for a call ending it
+    fn replace_call(&mut self, op: Option<&str>, quoted: Option<&Quoted>, span: &Span) {
+        let frame_span = quoted
+            .and_then(Quoted::span)
+            .unwrap_or_else(|| span.clone());
+        if let Some(frame) = self.frames.last_mut() {
+            *frame = Frame {
+                op: op.map(str::to_owned),
+                span: frame_span,
+            };
+        }
+    }
+
+    /// Runs the quote made at `origin`, `tail` as for `eval_code`
+    fn eval_quoted(
+        &mut self,
+        quoted: Quoted,
+        origin: Origin,
+        tail: bool,
+    ) -> Result<(), RuntimeError> {
+        match self.enter_quoted(quoted, origin)? {
+            Some(code) => self.eval_code(code, tail),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs all but the last sentence of the quote, returning that one
+    fn enter_quoted(
+        &mut self,
+        mut quoted: Quoted,
+        mut origin: Origin,
+    ) -> Result<Option<Code<'m>>, RuntimeError> {
+        loop {
+            match quoted {
+                Quoted::Sentence { ops } => return Ok(Some(Code::Quote(ops))),
+                Quoted::Value { value } => {
+                    self.push_traced(*value, origin.field(0));
+                    return Ok(None);
+                }
+                Quoted::Composed { a, b } => {
+                    self.eval_quoted(*a, origin.field(0), false)?;
+                    quoted = *b;
+                    origin = origin.field(1);
+                }
             }
         }
     }
 
-    fn eval(&mut self, op: &Op) {
-        match op {
-            Op::Literal { .. } => unimplemented!("literals"),
-            Op::Name { value: op_name, .. } => {
-                if let Some([n]) = parse_parametric("br-", op_name) {
-                    let buried = self.pop();
-                    self.stack.insert(self.stack.len() - n, buried);
-                } else if let Some([n]) = parse_parametric("dg-", op_name) {
-                    let digged = self.stack.remove(self.stack.len() - n - 1);
-                    self.stack.push(digged);
-                } else if let Some([_, _]) = parse_parametric("exec-", op_name) {
-                    match self.pop() {
-                        Value::Quoted(quoted) => {
-                            self.eval_quoted(quoted);
-                        }
-                        _ => {
-                            panic!("topmost is not an op");
-                        }
-                    }
-                } else if let Some([_, _, _, _]) = parse_parametric("comp-", op_name) {
-                    let Value::Quoted(b) = self.pop() else {
-                        panic!("topmost is not a quoted");
-                    };
-                    let Value::Quoted(a) = self.pop() else {
-                        panic!("topmost is not a quoted");
+    /// Runs the ops, which end the innermost call when `tail` holds. A call
+    /// or case they end with then continues in a loop here instead.
+    fn eval_code(&mut self, mut code: Code<'m>, tail: bool) -> Result<(), RuntimeError> {
+        if !tail || !self.tail_calls {
+            return match &code {
+                Code::Module(ops) => self.eval_sentence(ops),
+                Code::Quote(ops) => self.eval_sentence(ops),
+            };
+        }
+        loop {
+            let last = match code {
+                Code::Module(ops) => {
+                    let Some((last, init)) = ops.split_last() else {
+                        return Ok(());
                     };
-                    let composed = Quoted::Composed {
-                        a: Box::new(a),
-                        b: Box::new(b),
+                    self.eval_sentence(init)?;
+                    Cow::Borrowed(last)
+                }
+                Code::Quote(mut ops) => {
+                    let Some(last) = ops.pop() else {
+                        return Ok(());
                     };
-                    self.stack.push(Value::Quoted(composed));
-                } else if op_name == "dup" {
-                    let value = self.pop();
-                    self.stack.push(value.clone());
-                    self.stack.push(value.clone());
-                } else if op_name == "pop" {
-                    self.pop();
-                } else if op_name == "quote" {
-                    let value = self.pop();
-                    self.stack.push(Value::Quoted(Quoted::Value {
-                        value: Box::new(value),
-                    }))
-                } else if op_name == "trace" {
-                    println!("tracing: {:?}", self.stack);
-                } else if let Some(op_def) = self.module.op_defs.get(op_name) {
-                    self.eval_sentence(&op_def.body);
-                } else if let Some(constr_def) =
-                    self.constr_maps.constr_to_constr_map.get(op_name.as_str())
-                {
-                    let args = constr_def.params.iter().map(|_| self.pop()).collect();
-                    self.stack.push(Value::User {
-                        constr_name: op_name.to_owned(),
-                        args,
-                    });
-                } else {
-                    panic!("unknown operator: {}", op_name);
+                    self.eval_sentence(&ops)?;
+                    Cow::Owned(last)
                 }
+            };
+            match self.eval_tail(last)? {
+                Some(next) => code = next,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Runs an op ending the innermost call like `eval`, except that a call
+    /// or case is only entered, and what it runs returned to be run next
+    fn eval_tail(&mut self, op: Cow<'m, Op>) -> Result<Option<Code<'m>>, RuntimeError> {
+        let span = op.get_span().clone();
+        self.count_step(&span)?;
+        self.check_dyn(&span)?;
+        let next = match &*op {
+            Op::Name { value, span } => self.eval_name(value, span, true)?,
+            Op::Case { .. } | Op::Case2 { .. } => {
+                let arm = self.match_arm(&op)?;
+                Some(match op {
+                    Cow::Borrowed(op) => Code::Module(arm_body(op, arm)),
+                    Cow::Owned(op) => Code::Quote(into_arm_body(op, arm)),
+                })
+            }
+            op => {
+                self.eval_op(op)?;
+                None
+            }
+        };
+        self.check_stack_len(&span)?;
+        Ok(next)
+    }
+
+    fn eval_host(&mut self, name: &str, span: &Span) -> Result<(), RuntimeError> {
+        let host_op = &self.host_ops[name];
+        let arity = host_op.optype.pre.len();
+        if self.stack.len() < arity {
+            return Err(RuntimeError::new(span, RuntimeErrorMessage::StackUnderflow));
+        }
+        let args: Vec<Value> = self.stack.drain(self.stack.len() - arity..).rev().collect();
+        let origins = match self.origins.as_mut() {
+            Some(origins) => origins.take(arity),
+            None => vec![],
+        };
+        let host_error = |error| RuntimeError::new(span, error);
+        for (i, (t, value)) in host_op.optype.pre.iter().zip(args.iter()).enumerate() {
+            check_host_value(name, t, value, &self.constr_maps).map_err(|error| {
+                let origin = origins.get(i).and_then(|origin| origin.span.clone());
+                host_error(error).with_origin(origin)
+            })?;
+        }
+        let results = host_op
+            .call(name, self.int_semantics, args)
+            .map_err(host_error)?;
+        if results.len() != host_op.optype.post.len() {
+            return Err(host_error(RuntimeErrorMessage::HostResultArity {
+                name: name.to_owned(),
+                expected: host_op.optype.post.len(),
+                found: results.len(),
+            }));
+        }
+        for (t, value) in host_op.optype.post.iter().zip(results.iter()) {
+            check_host_value(name, t, value, &self.constr_maps).map_err(host_error)?;
+            if let Some(max) = self.limits.max_value_nodes {
+                self.check_limit(value.node_count(), Some(max), LimitKind::ValueNodes, span)?;
+            }
+        }
+        for value in results.into_iter().rev() {
+            self.push_made(value, span);
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, op: &Op) -> Result<(), RuntimeError> {
+        self.count_step(op.get_span())?;
+        self.check_dyn(op.get_span())?;
+        self.eval_op(op)?;
+        self.check_stack_len(op.get_span())
+    }
+
+    fn count_step(&mut self, span: &Span) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        self.check_limit(self.steps, self.limits.max_steps, LimitKind::Steps, span)
+    }
+
+    /// Whether the values the op at the span takes are of the types it
+    /// takes them as, see `with_dyn_checks`. A value missing is left to
+    /// the op to report.
+    fn check_dyn(&self, span: &Span) -> Result<(), RuntimeError> {
+        let Some(checks) = self.dyn_checks.get(&span.key()) else {
+            return Ok(());
+        };
+        for check in checks {
+            let Some(i) = self.stack.len().checked_sub(check.depth + 1) else {
+                continue;
+            };
+            if !value_has_type(&check.expected, &self.stack[i], &self.constr_maps) {
+                return Err(RuntimeError::new(
+                    span,
+                    RuntimeErrorMessage::DynTypeMismatch {
+                        expected: check.expected.clone(),
+                        found: Box::new(self.stack[i].clone()),
+                    },
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_stack_len(&self, span: &Span) -> Result<(), RuntimeError> {
+        self.check_limit(
+            self.stack.len(),
+            self.limits.max_stack_len,
+            LimitKind::StackLen,
+            span,
+        )
+    }
+
+    /// Evaluates the prelude ops that only shuffle the stack, returns false
+    /// if the name is not one of them
+    fn eval_stack_op(&mut self, op_name: &str, span: &Span) -> Result<bool, RuntimeError> {
+        if let Some([n]) = parse_parametric("br-", op_name) {
+            self.ensure_depth(n + 1, span)?;
+            let (buried, origin) = self.pop_traced(span)?;
+            self.stack.insert(self.stack.len() - n, buried);
+            if let Some(origins) = self.origins.as_mut() {
+                origins.stack.insert(origins.stack.len() - n, origin);
+            }
+        } else if let Some([n]) = parse_parametric("dg-", op_name) {
+            self.ensure_depth(n + 1, span)?;
+            let digged = self.stack.remove(self.stack.len() - n - 1);
+            self.stack.push(digged);
+            if let Some(origins) = self.origins.as_mut() {
+                let digged = origins.stack.remove(origins.stack.len() - n - 1);
+                origins.stack.push(digged);
+            }
+        } else if op_name == "pick" || op_name == "roll" {
+            let n = self.pop_index(span)?;
+            self.ensure_depth(n + 1, span)?;
+            let at = self.stack.len() - n - 1;
+            let value = match op_name {
+                "pick" => self.stack[at].clone(),
+                _ => self.stack.remove(at),
+            };
+            self.stack.push(value);
+            if let Some(origins) = self.origins.as_mut() {
+                let at = origins.stack.len() - n - 1;
+                let origin = match op_name {
+                    "pick" => origins.stack[at].clone(),
+                    _ => origins.stack.remove(at),
+                };
+                origins.stack.push(origin);
+            }
+        } else if let Some([_, _, _, _]) = parse_parametric("comp-", op_name) {
+            self.check_value_nodes(2, span)?;
+            let (b, b_origin) = self.pop_quoted(span)?;
+            let (a, a_origin) = self.pop_quoted(span)?;
+            let composed = Quoted::Composed {
+                a: Box::new(a),
+                b: Box::new(b),
+            };
+            let origin = Origin::made(span, vec![a_origin, b_origin]);
+            self.push_traced(Value::Quoted(composed), origin);
+        } else if op_name == "dup" {
+            let (value, origin) = self.pop_traced(span)?;
+            self.push_traced(value.clone(), origin.clone());
+            self.push_traced(value, origin);
+        } else if op_name == "pop" {
+            self.pop(span)?;
+        } else if op_name == "quote" {
+            self.check_value_nodes(1, span)?;
+            let (value, origin) = self.pop_traced(span)?;
+            let quoted = Quoted::Value {
+                value: Box::new(value),
+            };
+            self.push_traced(Value::Quoted(quoted), Origin::made(span, vec![origin]));
+        } else if op_name == "trace" {
+            println!(
+                "tracing: {}",
+                display_stack(&self.stack, &DisplayOptions::default())
+            );
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Pushes the quote a `fix-*` quote gets, one that pushes the quote and
+    /// fixes it again, instead of a reference to itself
+    fn push_again(
+        &mut self,
+        quoted: &Quoted,
+        origin: &Origin,
+        op_name: &str,
+        span: &Span,
+    ) -> Result<(), RuntimeError> {
+        self.check_value_nodes(2, span)?;
+        let again = Quoted::Composed {
+            a: Box::new(Quoted::Value {
+                value: Box::new(Value::Quoted(quoted.clone())),
+            }),
+            b: Box::new(Quoted::Sentence {
+                ops: vec![Op::Name {
+                    value: op_name.to_owned(),
+                    span: span.clone(),
+                }],
+            }),
+        };
+        let origin = self.origins.is_some().then(|| {
+            Origin::made(
+                span,
+                vec![
+                    Origin::made(span, vec![origin.clone()]),
+                    Origin::made(span, vec![]),
+                ],
+            )
+        });
+        self.push_traced(Value::Quoted(again), origin.unwrap_or_default());
+        Ok(())
+    }
+
+    /// Runs the op of the name. With `tail`, the op ends the innermost call:
+    /// a call it makes replaces that call and what the call runs is
+    /// returned to be run next instead.
+    fn eval_name(
+        &mut self,
+        op_name: &str,
+        span: &Span,
+        tail: bool,
+    ) -> Result<Option<Code<'m>>, RuntimeError> {
+        if self.eval_stack_op(op_name, span)? {
+            return Ok(None);
+        }
+        let module = self.module;
+        let failed = |message: &str| {
+            let message = message.to_owned();
+            RuntimeError::new(span, RuntimeErrorMessage::Assertion { message })
+        };
+        if op_name == "assert" {
+            let (value, origin) = self.pop_traced(span)?;
+            if !matches!(&value, Value::User { constr_name, .. } if constr_name == "true") {
+                return Err(failed("assertion failed").with_origin(origin.span));
+            }
+        } else if op_name == "panic" {
+            return Err(failed("panicked"));
+        } else if let Some([_, _]) = parse_parametric("exec-", op_name) {
+            let (quoted, origin) = self.pop_quoted(span)?;
+            if tail {
+                self.replace_call(None, Some(&quoted), span);
+                return self.enter_quoted(quoted, origin);
+            }
+            self.enter_call(None, Some(&quoted), span)?;
+            self.eval_quoted(quoted, origin, true)?;
+            self.leave_call();
+        } else if let Some([_, _]) = parse_parametric("fix-", op_name) {
+            let (quoted, origin) = self.pop_quoted(span)?;
+            self.push_again(&quoted, &origin, op_name, span)?;
+            if tail {
+                self.replace_call(None, Some(&quoted), span);
+                return self.enter_quoted(quoted, origin);
+            }
+            self.enter_call(None, Some(&quoted), span)?;
+            self.eval_quoted(quoted, origin, true)?;
+            self.leave_call();
+        } else if let Some(expansion) = prelude_types::expand(op_name) {
+            for op_name in expansion {
+                self.eval_name(&op_name, span, false)?;
+            }
+        } else if self.host_ops.contains_key(op_name) {
+            self.eval_host(op_name, span)?;
+        } else if let Some(value) = self.consts.as_ref().and_then(|consts| consts.get(op_name)) {
+            let value = value.clone();
+            let origin = self
+                .origins
+                .as_ref()
+                .and_then(|origins| origins.consts.get(op_name))
+                .cloned()
+                .unwrap_or_default();
+            self.push_traced(value, origin);
+        } else if let Some(op_def) = module.op_defs.get(op_name) {
+            if op_def.declaration {
+                // in the trace, at the declaration
+                self.enter_call(Some(op_name), None, span)?;
+                return Err(RuntimeError::new(
+                    &op_def.span,
+                    RuntimeErrorMessage::NotImplemented {
+                        name: op_name.to_owned(),
+                    },
+                ));
+            }
+            if let Some(coverage) = self.coverage.as_mut() {
+                coverage.record_op(op_name);
+            }
+            if tail {
+                self.replace_call(Some(op_name), None, span);
+                return Ok(Some(Code::Module(&op_def.body)));
+            }
+            self.enter_call(Some(op_name), None, span)?;
+            self.eval_code(Code::Module(&op_def.body), true)?;
+            self.leave_call();
+        } else if let Some(constr_def) = self.constr_maps.constr_to_constr_map.get(op_name) {
+            let arity = constr_def.params.len();
+            self.ensure_depth(arity, span)?;
+            self.check_value_nodes(arity, span)?;
+            let args: Vec<_> = self.stack.drain(self.stack.len() - arity..).rev().collect();
+            self.stack.push(Value::User {
+                constr_name: self.constr_names[op_name].clone(),
+                args: args.into(),
+            });
+            if let Some(origins) = self.origins.as_mut() {
+                let fields = origins.take(arity);
+                origins.stack.push(Origin::made(span, fields));
+            }
+        } else {
+            return Err(RuntimeError::new(
+                span,
+                RuntimeErrorMessage::UnknownOp {
+                    name: op_name.to_owned(),
+                },
+            ));
+        }
+        Ok(None)
+    }
+
+    fn eval_op(&mut self, op: &Op) -> Result<(), RuntimeError> {
+        match op {
+            Op::Literal {
+                value: Literal::Int(n),
+                span,
+            } => self.push_made(Value::Int(*n), span),
+            Op::Literal {
+                value: Literal::Extern { tag, repr },
+                span,
+            } => {
+                let literal = self.literals.get(tag).ok_or_else(|| {
+                    RuntimeError::new(
+                        span,
+                        RuntimeErrorMessage::UnknownLiteralKind { tag: tag.clone() },
+                    )
+                })?;
+                let value = literal(repr).map_err(|message| {
+                    RuntimeError::new(
+                        span,
+                        RuntimeErrorMessage::LiteralError {
+                            repr: repr.clone(),
+                            message,
+                        },
+                    )
+                })?;
+                self.push_made(value, span);
+            }
+            Op::Name {
+                value: op_name,
+                span,
+            } => {
+                self.eval_name(op_name, span, false)?;
             }
+            Op::Case { .. } | Op::Case2 { .. } => {
+                let arm = self.match_arm(op)?;
+                self.eval_sentence(arm_body(op, arm))?;
+            }
+            Op::Quote { value: ops, span } => {
+                self.push_made(Value::Quoted(Quoted::Sentence { ops: ops.clone() }), span)
+            }
+            Op::List { items, span } => return self.eval_list(items, span),
+        }
+        Ok(())
+    }
+
+    /// A list literal of a module not desugared runs as it would once it
+    /// is. Kept out of `eval_op`, whose frame every call nests.
+    #[inline(never)]
+    fn eval_list(&mut self, items: &[Vec<Op>], span: &Span) -> Result<(), RuntimeError> {
+        self.eval_sentence(&lists::expand(items.to_vec(), span))
+    }
+
+    /// Pops the values the case matches on and pushes the fields of the
+    /// arm matching them, returning which arm that is, the head arm first
+    fn match_arm(&mut self, op: &Op) -> Result<usize, RuntimeError> {
+        match op {
             Op::Case {
                 head_arm,
                 arms: rest_arms,
-                ..
+                span,
             } => {
-                let Value::User { constr_name, args } = self.pop() else {
-                    panic!("matching a quote value")
+                let (constr_name, args, origin) = match self.pop_traced(span)? {
+                    (Value::User { constr_name, args }, origin) => (constr_name, args, origin),
+                    (value, origin) => {
+                        return Err(RuntimeError::new(
+                            span,
+                            RuntimeErrorMessage::NotAConstructor { value },
+                        )
+                        .with_origin(origin.span))
+                    }
                 };
-                let matching_arm = vec![head_arm]
-                    .into_iter()
+                let (i, matching_arm) = once(head_arm)
                     .chain(rest_arms.iter())
-                    .find(|arm| arm.constr == constr_name)
-                    .expect(&format!("unknown constructor: {}", &constr_name));
-                self.stack.extend(args.into_iter().rev());
-                self.eval_sentence(&matching_arm.body);
+                    .enumerate()
+                    .find(|(_, arm)| arm.constr == constr_name)
+                    .ok_or_else(|| {
+                        RuntimeError::new(
+                            span,
+                            RuntimeErrorMessage::UnknownConstructor {
+                                name: constr_name.to_string(),
+                            },
+                        )
+                    })?;
+                if let Some(coverage) = self.coverage.as_mut() {
+                    coverage.record_arm(&matching_arm.span);
+                }
+                self.push_fields(args, &origin);
+                Ok(i)
+            }
+            Op::Case2 { arms, span } => {
+                let (v1, o1) = self.pop_traced(span)?;
+                let (v2, o2) = self.pop_traced(span)?;
+                let matches = |pattern: &Pattern, value: &Value| match (pattern, value) {
+                    (Pattern::Wildcard, _) => true,
+                    (Pattern::Constr(constr), Value::User { constr_name, .. }) => {
+                        constr == constr_name
+                    }
+                    (Pattern::Constr(_), _) => false,
+                };
+                let (i, matching_arm) = arms
+                    .iter()
+                    .enumerate()
+                    .find(|(_, arm)| {
+                        matches(&arm.patterns[0], &v1) && matches(&arm.patterns[1], &v2)
+                    })
+                    .ok_or_else(|| match (&v1, &v2) {
+                        (Value::User { constr_name, .. }, Value::User { .. }) => RuntimeError::new(
+                            span,
+                            RuntimeErrorMessage::UnknownConstructor {
+                                name: constr_name.to_string(),
+                            },
+                        ),
+                        (Value::User { .. }, value) | (value, _) => {
+                            let origin = if matches!(v1, Value::User { .. }) {
+                                &o2
+                            } else {
+                                &o1
+                            };
+                            RuntimeError::new(
+                                span,
+                                RuntimeErrorMessage::NotAConstructor {
+                                    value: value.clone(),
+                                },
+                            )
+                            .with_origin(origin.span.clone())
+                        }
+                    })?;
+                if let Some(coverage) = self.coverage.as_mut() {
+                    coverage.record_arm(&matching_arm.span);
+                }
+                // the lower value goes first so that the topmost one's fields end up on top
+                let [p1, p2] = &matching_arm.patterns;
+                for (pattern, value, origin) in [(p2, v2, o2), (p1, v1, o1)] {
+                    match (pattern, value) {
+                        (Pattern::Constr(_), Value::User { args, .. }) => {
+                            self.push_fields(args, &origin)
+                        }
+                        (_, value) => self.push_traced(value, origin),
+                    }
+                }
+                Ok(i)
             }
-            Op::Quote { value: ops, .. } => self
-                .stack
-                .push(Value::Quoted(Quoted::Sentence { ops: ops.clone() })),
+            _ => unreachable!("only cases have arms"),
+        }
+    }
+
+    /// Pushes the fields of a constructor value made at `origin`, the
+    /// topmost field last
+    fn push_fields(&mut self, args: Fields, origin: &Origin) {
+        let Some(origins) = self.origins.as_mut() else {
+            self.stack.extend(args.into_iter().rev());
+            return;
+        };
+        for (i, value) in args.into_iter().enumerate().rev() {
+            self.stack.push(value);
+            origins.stack.push(origin.field(i));
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::diagnostics::{Diagnostic, MAX_FRAMES};
+    use crate::evaluation::display::{display_stack, DisplayOptions};
     use crate::evaluation::evaluator::*;
     use crate::syntax::parse;
+    use crate::typing::types::Type;
 
     #[test]
     fn empty() {
         let input = "
         define [] main []:.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         assert!(matches!(evaluator.stack[..], []))
     }
 
@@ -156,9 +1072,9 @@ mod tests {
         data Foo: foo, bar, baz.
         define [] main [Foo, Foo, Foo]: foo bar baz.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         assert!(matches!(
             evaluator.stack[..],
             [
@@ -177,9 +1093,9 @@ mod tests {
         data Nat: zero, [Nat] suc.
         define [] main [Nat]: zero suc suc suc.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         assert!(matches!(
             &evaluator.stack[..],
             [
@@ -211,9 +1127,9 @@ mod tests {
             case { zero { trace }, suc { trace natadd suc } }.
         define [] main [Nat]: zero suc zero suc suc natadd.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         assert!(matches!(
             &evaluator.stack[..],
             [
@@ -256,9 +1172,9 @@ mod tests {
         data X: [Foo, Foo, Foo] x.
         define [] main [Foo, Foo, Foo]: foo bar baz x case { x {} }.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         assert!(matches!(
             &evaluator.stack[..],
             [
@@ -271,6 +1187,87 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn copies_share_constructor_values() {
+        let input = "
+        data Nat: zero, [Nat] suc.
+        data Pair: [Nat, Nat] pair.
+        define [] main [Nat, Pair, Nat]: zero suc dup dup pair zero suc.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        let [one, pair, built_again] = &evaluator.stack[..] else {
+            panic!("unexpected stack {:?}", evaluator.stack);
+        };
+        let Value::User { args, .. } = pair else {
+            panic!("not a pair: {}", pair);
+        };
+        assert!(one.ptr_eq(&args[0]) && args[0].ptr_eq(&args[1]));
+        assert!(!one.ptr_eq(built_again));
+        assert_eq!(one, built_again);
+        assert_ne!(&args[0], pair);
+        assert_eq!(pair.node_count(), 5);
+    }
+
+    #[test]
+    fn case2_destructuring_order() {
+        let input = "
+        data Foo: foo.
+        data Bar: bar.
+        data P: [Foo] pf.
+        data Q: [Bar] qb.
+        define [] main [Bar, Foo]: foo pf bar qb case2 { qb pf { } }.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        let opts = DisplayOptions::default();
+        assert_eq!(display_stack(&evaluator.stack, &opts), "[bar, foo]");
+    }
+
+    #[test]
+    fn case2_wildcards() {
+        let input = "
+        data Bool: true, false.
+        data Maybe a: nothing, [a] just.
+        define [Maybe Bool, Bool] choose [Bool]:
+            case2 { nothing _ { }, _ true { pop true }, just false { } }.
+        define [] main [Bool, Bool, Bool]:
+            false nothing choose true true just choose false false just choose.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        let opts = DisplayOptions::default();
+        assert_eq!(
+            display_stack(&evaluator.stack, &opts),
+            "[false, true, false]"
+        );
+    }
+
+    #[test]
+    fn pick_and_roll_index_the_stack() {
+        let run = |body: &str| {
+            let module = parse(&format!("#[unchecked]\ndefine [] main []: {}.", body)).unwrap();
+            let mut evaluator = Evaluator::new(&module);
+            match evaluator.eval_main() {
+                Ok(()) => display_stack(&evaluator.stack, &DisplayOptions::default()),
+                Err(EvaluatorError::Runtime(err)) => err.code().to_owned(),
+                Err(err) => panic!("{:?}", err),
+            }
+        };
+        assert_eq!(run("1 2 3 0 pick"), "[3, 3, 2, 1]");
+        assert_eq!(run("1 2 3 2 pick"), "[1, 3, 2, 1]");
+        assert_eq!(run("1 2 3 0 roll"), "[3, 2, 1]");
+        assert_eq!(run("1 2 3 2 roll"), "[1, 3, 2]");
+        // the index can come from anywhere at runtime
+        assert_eq!(run("5 6 1 quote exec-0-1 pick"), "[5, 6, 5]");
+        assert_eq!(run("1 2 3 pick"), "StackUnderflow");
+        assert_eq!(run("1 2 -1 roll"), "NotAnIndex");
+        assert_eq!(run("1 (0) pick"), "NotAnIndex");
+    }
+
     #[test]
     fn bury_test() {
         let input = "
@@ -278,9 +1275,9 @@ mod tests {
         data Bar: bar.
         define [] main [Bar, Bar, Foo]: bar bar foo br-2.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(
             &evaluator.stack[..],
@@ -301,9 +1298,9 @@ mod tests {
         data Bar: bar.
         define [] main [Bar, Foo, Bar]: bar bar foo br-1.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(
             &evaluator.stack[..],
@@ -324,9 +1321,9 @@ mod tests {
         data Bar: bar.
         define [] main [Bar, Bar, Foo]: foo bar bar dg-2.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(
             &evaluator.stack[..],
@@ -347,9 +1344,9 @@ mod tests {
         data Bar: bar.
         define [] main [Bar, Bar, Foo]: bar foo bar dg-1.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(
             &evaluator.stack[..],
@@ -372,9 +1369,9 @@ mod tests {
           case { foo { } } case { bar { } } bar bar bar.
         define [] main [Bar, Bar, Bar]: bar foo (foobar) exec-2-3.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(
             &evaluator.stack[..],
@@ -395,9 +1392,9 @@ mod tests {
         data Bar: bar.
         define [] main []: bar foo pop pop.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(&evaluator.stack[..], []));
     }
@@ -408,9 +1405,9 @@ mod tests {
         data Foo: foo.
         define [] main [Foo]: foo quote exec-0-1.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(
             &evaluator.stack[..],
@@ -426,9 +1423,9 @@ mod tests {
         data Foo: foo.
         define [] main [Foo, Foo]: foo dup.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(
             &evaluator.stack[..],
@@ -446,9 +1443,9 @@ mod tests {
         data Bar: bar.
         define [] main [Bar, Foo]: (foo) (bar) comp-0-1-0-1 exec-0-2.
         ";
-        let module = parse(&input).unwrap();
+        let module = parse(input).unwrap();
         let mut evaluator = Evaluator::new(&module);
-        evaluator.eval_main();
+        evaluator.eval_main().unwrap();
         println!("{:?}", evaluator.stack);
         assert!(matches!(
             &evaluator.stack[..],
@@ -458,4 +1455,449 @@ mod tests {
             ] if name1 == "foo" && args1.is_empty() && name2 == "bar" && args2.is_empty()
         ));
     }
+
+    fn limit_exceeded(result: Result<(), EvaluatorError>) -> Option<(LimitKind, Span)> {
+        match result {
+            Err(EvaluatorError::Runtime(RuntimeError {
+                error: RuntimeErrorMessage::LimitExceeded(kind),
+                span,
+                ..
+            })) => Some((kind, span)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn step_limit_infinite_recursion() {
+        let input = "
+        define [] loop []: loop.
+        define [] main []: loop.
+        ";
+        let module = parse(input).unwrap();
+        let limits = ExecLimits {
+            max_steps: Some(100),
+            ..ExecLimits::default()
+        };
+        let mut evaluator = Evaluator::new(&module).with_limits(limits);
+        let (kind, span) = limit_exceeded(evaluator.eval_main()).unwrap();
+        assert_eq!(kind, LimitKind::Steps);
+        assert_eq!(&input[span.start..span.end], "loop");
+        assert_eq!(evaluator.steps(), 101);
+    }
+
+    #[test]
+    fn step_limit_counts_quotes_and_arms() {
+        let input = "
+        data Foo: foo.
+        define [] main [Foo]: (foo case { foo { foo } }) exec-0-1.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        // the quote, exec, foo, case and the arm's foo
+        assert_eq!(evaluator.steps(), 5);
+        let limits = ExecLimits {
+            max_steps: Some(4),
+            ..ExecLimits::default()
+        };
+        let mut evaluator = Evaluator::new(&module).with_limits(limits);
+        let (kind, _) = limit_exceeded(evaluator.eval_main()).unwrap();
+        assert_eq!(kind, LimitKind::Steps);
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let input = "
+        data Foo: foo.
+        define [] deep [Foo]: (deeper) exec-0-1.
+        define [] deeper [Foo]: foo.
+        define [] main [Foo]: deep.
+        ";
+        let module = parse(input).unwrap();
+        let limits = ExecLimits {
+            max_call_depth: Some(3),
+            ..ExecLimits::default()
+        };
+        let evaluator = || Evaluator::new(&module).with_tail_calls(false);
+        evaluator().with_limits(limits.clone()).eval_main().unwrap();
+        let limits = ExecLimits {
+            max_call_depth: Some(2),
+            ..limits
+        };
+        let mut evaluator = evaluator().with_limits(limits);
+        let (kind, span) = limit_exceeded(evaluator.eval_main()).unwrap();
+        assert_eq!(kind, LimitKind::CallDepth);
+        assert_eq!(&input[span.start..span.end], "deeper");
+    }
+
+    /// An evaluator with `is-zero`, `add` and `sub`, for loops over Ints
+    fn counting(module: &Module) -> Evaluator<'_> {
+        let mut evaluator = Evaluator::new(module);
+        let is_zero = OpType {
+            pre: vec![Type::Mono("Int".to_owned())],
+            post: vec![Type::Mono("Bool".to_owned())],
+        };
+        evaluator
+            .register("is-zero", is_zero, |[n]| {
+                let name = if matches!(n, Value::Int(0)) {
+                    "true"
+                } else {
+                    "false"
+                };
+                Ok(vec![Value::constr(name, vec![])])
+            })
+            .unwrap();
+        evaluator.register_int_op("add", IntOp::Add).unwrap();
+        evaluator.register_int_op("sub", IntOp::Sub).unwrap();
+        evaluator
+    }
+
+    fn ints(evaluator: &Evaluator) -> Vec<i32> {
+        evaluator
+            .stack
+            .iter()
+            .map(|value| match value {
+                Value::Int(n) => *n,
+                value => panic!("expected an Int, got {}", value),
+            })
+            .collect()
+    }
+
+    const LOOPS: &str = "
+        data Bool: false, true.
+        define [Int, Int] count [Int]:
+            dup is-zero case { true { pop }, false { 1 sub br-1 1 add br-1 count } }.
+        define [Int, Int] even [Int]:
+            dup is-zero case { true { pop }, false { 1 sub br-1 1 add br-1 odd } }.
+        define [Int, Int] odd [Int]:
+            dup is-zero case { true { pop }, false { 1 sub (even) exec-2-1 } }.
+        define [Int] up [Int]:
+            dup is-zero case { true { }, false { 1 sub up 2 add } }.
+    ";
+
+    #[test]
+    fn tail_calls_reuse_the_frame() {
+        let module = parse(LOOPS).unwrap();
+        let limits = ExecLimits {
+            max_call_depth: Some(4),
+            ..ExecLimits::default()
+        };
+        let mut evaluator = counting(&module).with_limits(limits.clone());
+        evaluator.stack = vec![Value::Int(0), Value::Int(100_000)];
+        evaluator.eval_op_def("count").unwrap();
+        assert_eq!(ints(&evaluator), [100_000]);
+        // the quote odd runs is the last op of its body
+        let mut evaluator = counting(&module).with_limits(limits);
+        evaluator.stack = vec![Value::Int(0), Value::Int(100_001)];
+        evaluator.eval_op_def("even").unwrap();
+        assert_eq!(ints(&evaluator), [50_001]);
+    }
+
+    #[test]
+    fn tail_calls_do_not_change_results() {
+        let module = parse(LOOPS).unwrap();
+        for op_name in ["count", "even", "up"] {
+            for n in 0..10 {
+                let results: Vec<_> = [true, false]
+                    .into_iter()
+                    .map(|tail_calls| {
+                        let mut evaluator = counting(&module).with_tail_calls(tail_calls);
+                        evaluator.stack = vec![Value::Int(0), Value::Int(n)];
+                        evaluator.eval_op_def(op_name).unwrap();
+                        (ints(&evaluator), evaluator.steps())
+                    })
+                    .collect();
+                assert_eq!(results[0], results[1], "{} of {}", op_name, n);
+            }
+        }
+    }
+
+    #[test]
+    fn calls_not_in_tail_position_grow_the_stack() {
+        let module = parse(LOOPS).unwrap();
+        let limits = ExecLimits {
+            max_call_depth: Some(100),
+            ..ExecLimits::default()
+        };
+        let mut evaluator = counting(&module).with_limits(limits.clone());
+        evaluator.stack = vec![Value::Int(50)];
+        evaluator.eval_op_def("up").unwrap();
+        assert_eq!(ints(&evaluator), [100]);
+        let mut evaluator = counting(&module).with_limits(limits);
+        evaluator.stack = vec![Value::Int(200)];
+        let (kind, span) = limit_exceeded(evaluator.eval_op_def("up")).unwrap();
+        assert_eq!(kind, LimitKind::CallDepth);
+        assert_eq!(&LOOPS[span.start..span.end], "up");
+    }
+
+    fn trace(input: &str, result: Result<(), EvaluatorError>) -> Vec<(Option<String>, &str)> {
+        match result {
+            Err(EvaluatorError::Runtime(err)) => err
+                .trace
+                .into_iter()
+                .map(|frame| (frame.op, &input[frame.span.start..frame.span.end]))
+                .collect(),
+            result => panic!("expected a runtime error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn traces_of_nested_calls() {
+        let input = "
+        define [] inner []: pop.
+        define [] middle []: (inner inner) exec-0-0.
+        define [] outer []: middle.
+        define [] main []: outer.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module).with_tail_calls(false);
+        let name = |n: &str| Some(n.to_owned());
+        assert_eq!(
+            trace(input, evaluator.eval_main()),
+            [
+                (name("inner"), "inner"),
+                (None, "inner inner"),
+                (name("middle"), "middle"),
+                (name("outer"), "outer"),
+            ]
+        );
+        // a new evaluation starts with no frames
+        evaluator.stack.push(Value::Int(1));
+        assert_eq!(
+            trace(input, evaluator.eval_op_def("middle")),
+            [(name("inner"), "inner"), (None, "inner inner")]
+        );
+    }
+
+    #[test]
+    fn assertions_fail_at_the_op_inside_their_calls() {
+        let input = "
+        data Bool: true, false.
+        define [Bool] check []: assert.
+        define [] main [Int]: true check false check 1.
+        define [] give-up [Int]: panic.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module).with_tail_calls(false);
+        let result = evaluator.eval_main();
+        let Err(EvaluatorError::Runtime(err)) = &result else {
+            panic!("expected a runtime error, got {:?}", result);
+        };
+        assert_eq!(err.message(), "assertion failed");
+        assert_eq!(&input[err.span.start..err.span.end], "assert");
+        // the second check, after the first passed
+        assert_eq!(
+            err.trace[0].span.start,
+            input.find("false check").unwrap() + 6
+        );
+        assert_eq!(trace(input, result), [(Some("check".to_owned()), "check")]);
+
+        match evaluator.eval_op_def("give-up") {
+            Err(EvaluatorError::Runtime(err)) => {
+                assert_eq!(err.code(), "Assertion");
+                assert_eq!(err.message(), "panicked");
+                assert_eq!(&input[err.span.start..err.span.end], "panic");
+            }
+            result => panic!("expected a runtime error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn failed_assertions_say_where_their_value_was_made() {
+        let input = "
+        data Bool: true, false.
+        data Nat: zero, [Nat] suc.
+        data Pair a b: [a, b] pair.
+        define [] make [Pair Int Bool]: false 1 pair.
+        define [Bool] check []: quote exec-0-1 assert.
+        define [Nat, Bool] deep []: case { zero { check }, suc { deep } }.
+        define [] main []: make case { pair { pop } } zero suc suc deep.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module).with_origins();
+        let err = match evaluator.eval_main() {
+            Err(EvaluatorError::Runtime(err)) => err,
+            result => panic!("expected a runtime error, got {:?}", result),
+        };
+        assert_eq!(&input[err.span.start..err.span.end], "assert");
+        let origin = err.origin.as_ref().unwrap();
+        assert_eq!(origin.start, input.find("false 1").unwrap());
+        let rendered = Diagnostic::from_runtime_error(input, &err, MAX_FRAMES).render(input);
+        assert!(rendered.contains("the offending value was created at 5:41"));
+        assert_eq!(evaluator.origins().map(<[_]>::len), Some(0));
+
+        let mut evaluator = Evaluator::new(&module);
+        match evaluator.eval_main() {
+            Err(EvaluatorError::Runtime(err)) => assert!(err.origin.is_none()),
+            result => panic!("expected a runtime error, got {:?}", result),
+        }
+        assert!(evaluator.origins().is_none());
+    }
+
+    #[test]
+    fn fields_keep_their_origins() {
+        let input = "
+        data Nat: zero, [Nat] suc.
+        data Pair a b: [a, b] pair.
+        define [] main [Nat, Nat, Int, Pair Int Nat]:
+            zero suc 7 pair dup case { pair { } } br-1 zero.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module).with_origins();
+        evaluator.eval_main().unwrap();
+        let at = |origin: &Origin| {
+            let span = origin.span.as_ref().unwrap();
+            &input[span.start..span.end]
+        };
+        let origins = evaluator.origins().unwrap();
+        let made: Vec<_> = origins.iter().map(at).collect();
+        assert_eq!(made, ["pair", "7", "suc", "zero"]);
+        let fields: Vec<_> = origins[0].fields().iter().map(at).collect();
+        assert_eq!(fields, ["7", "suc"]);
+        assert_eq!(at(&origins[2].fields()[0]), "zero");
+    }
+
+    #[test]
+    fn macro_ops_run_their_expansion() {
+        let input = "
+        data Nat: zero, [Nat] suc.
+        data Bool: true, false.
+        define [Nat] is-zero [Bool]: case { zero { true }, suc { pop false } }.
+        define [] main []:
+            zero suc (is-zero) keep-1-1
+            zero (suc) (is-zero) bi-1-1
+            true zero (suc suc) (is-zero) (suc) tri-1-1-1
+            zero true (is-zero) dip-1-1-1.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        let stack: Vec<_> = evaluator.stack.iter().map(Value::to_string).collect();
+        assert_eq!(
+            stack,
+            [
+                "false",
+                "(suc zero)",
+                "(suc zero)",
+                "true",
+                "true",
+                "(suc (suc zero))",
+                "true",
+                "(suc zero)",
+                "true",
+                "true"
+            ]
+        );
+    }
+
+    #[test]
+    fn constants_are_computed_once_in_dependency_order() {
+        let input = "
+        data Nat: zero, [Nat] suc.
+        const four [Nat]: two suc suc.
+        const two [Nat]: zero suc suc.
+        define [] main [Nat, Nat]: four two.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.eval_main().unwrap();
+        let stack: Vec<_> = evaluator.stack.iter().map(Value::to_string).collect();
+        assert_eq!(stack, ["(suc (suc (suc (suc zero))))", "(suc (suc zero))"]);
+        // the values are kept for later evaluations, the steps to compute
+        // them are not spent again
+        evaluator.stack.clear();
+        evaluator.eval_main().unwrap();
+        assert_eq!(evaluator.steps(), 2);
+    }
+
+    #[test]
+    fn constants_spend_the_steps_of_the_evaluation() {
+        let input = "
+        data Nat: zero, [Nat] suc.
+        const two [Nat]: zero suc suc.
+        const three [Nat]: two suc.
+        define [] main [Nat, Nat]: three zero suc suc.
+        ";
+        let module = parse(input).unwrap();
+        // five steps for the constants and four for main, each within the
+        // limit alone
+        let limits = ExecLimits {
+            max_steps: Some(5),
+            ..ExecLimits::default()
+        };
+        let mut evaluator = Evaluator::new(&module).with_limits(limits);
+        let (kind, span) = limit_exceeded(evaluator.eval_main()).unwrap();
+        assert_eq!(kind, LimitKind::Steps);
+        assert_eq!(&input[span.start..span.end], "three");
+        assert_eq!(evaluator.steps(), 6);
+        // once computed, the constants cost nothing
+        evaluator.stack.clear();
+        evaluator.eval_main().unwrap();
+        assert_eq!(evaluator.steps(), 4);
+    }
+
+    #[test]
+    fn constant_cycles() {
+        let input = "
+        data Nat: zero, [Nat] suc.
+        const a [Nat]: b suc.
+        const b [Nat]: (a) pop c.
+        const c [Nat]: zero.
+        define [] main [Nat]: c.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        let Err(EvaluatorError::Runtime(err)) = evaluator.eval_main() else {
+            panic!("expected a runtime error");
+        };
+        assert!(matches!(
+            &err.error,
+            RuntimeErrorMessage::ConstantCycle { names } if names == &["a", "b"]
+        ));
+        assert_eq!(&input[err.span.start..err.span.end], "a");
+        assert_eq!(err.message(), "constant `a` depends on itself through `b`");
+    }
+
+    #[test]
+    fn stack_len_limit() {
+        let input = "
+        data Foo: foo.
+        define [] main [Foo, Foo, Foo]: foo foo foo.
+        ";
+        let module = parse(input).unwrap();
+        let limits = ExecLimits {
+            max_stack_len: Some(2),
+            ..ExecLimits::default()
+        };
+        let mut evaluator = Evaluator::new(&module).with_limits(limits);
+        let (kind, _) = limit_exceeded(evaluator.eval_main()).unwrap();
+        assert_eq!(kind, LimitKind::StackLen);
+        // the partially evaluated stack is kept for inspection
+        assert_eq!(evaluator.stack.len(), 3);
+    }
+
+    #[test]
+    fn value_nodes_limit_list_builder() {
+        let input = "
+        data Nat: zero, [Nat] suc.
+        data List: empty, [Nat, List] cons.
+        define [List] grow [List]: zero cons grow.
+        define [] main [List]: empty grow.
+        ";
+        let module = parse(input).unwrap();
+        let limits = ExecLimits {
+            max_steps: Some(10_000),
+            max_value_nodes: Some(41),
+            ..ExecLimits::default()
+        };
+        for _ in 0..2 {
+            let mut evaluator = Evaluator::new(&module).with_limits(limits.clone());
+            let (kind, span) = limit_exceeded(evaluator.eval_main()).unwrap();
+            assert_eq!(kind, LimitKind::ValueNodes);
+            assert_eq!(&input[span.start..span.end], "cons");
+            // 20 conses made it, the 21st would be 43 nodes big
+            assert_eq!(evaluator.stack[0].node_count(), 41);
+            assert_eq!(evaluator.steps(), 2 + 3 * 20 + 2);
+        }
+    }
 }
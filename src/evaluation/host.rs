@@ -0,0 +1,630 @@
+use super::types::*;
+use crate::syntax::module_wrapper::ModuleConstrMaps;
+use crate::typing::inference::{Subst, Typeable};
+use crate::typing::types::*;
+use std::iter::zip;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HostRegistrationError {
+    ArityMismatch {
+        name: String,
+        declared: usize,
+        expected: usize,
+    },
+    DuplicateName {
+        name: String,
+    },
+}
+
+type HostFn = Box<dyn Fn(Vec<Value>) -> Result<Vec<Value>, String>>;
+
+/// Int arithmetic the evaluator runs itself, so that overflow follows its
+/// `IntSemantics` and fails at the op
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntOp {
+    Add,
+    Sub,
+    Mul,
+    Neg,
+}
+
+impl IntOp {
+    /// `[Int, Int][Int]`, or `[Int][Int]` for `Neg`
+    pub fn optype(self) -> OpType {
+        let int = || Type::Mono("Int".to_owned());
+        let arity = if self == IntOp::Neg { 1 } else { 2 };
+        OpType {
+            pre: vec![int(); arity],
+            post: vec![int()],
+        }
+    }
+
+    /// The result for the values topmost first, `None` when it overflows
+    /// under `Checked`. `Sub` takes the topmost from the one below it.
+    pub fn apply(self, semantics: IntSemantics, args: &[i32]) -> Option<i32> {
+        let (a, b) = match *args {
+            [n] => (n, 0),
+            [b, a] => (a, b),
+            _ => return None,
+        };
+        match semantics {
+            IntSemantics::Wrapping => Some(match self {
+                IntOp::Add => a.wrapping_add(b),
+                IntOp::Sub => a.wrapping_sub(b),
+                IntOp::Mul => a.wrapping_mul(b),
+                IntOp::Neg => a.wrapping_neg(),
+            }),
+            IntSemantics::Checked => match self {
+                IntOp::Add => a.checked_add(b),
+                IntOp::Sub => a.checked_sub(b),
+                IntOp::Mul => a.checked_mul(b),
+                IntOp::Neg => a.checked_neg(),
+            },
+        }
+    }
+}
+
+enum HostFunc {
+    Rust(HostFn),
+    Int(IntOp),
+}
+
+/// A rust function exposed as an op along with its declared type
+pub struct HostOp {
+    pub optype: OpType,
+    func: HostFunc,
+}
+
+impl HostOp {
+    pub fn new<const N: usize, F>(optype: OpType, f: F) -> Self
+    where
+        F: Fn([Value; N]) -> Result<Vec<Value>, String> + 'static,
+    {
+        let func = Box::new(move |args: Vec<Value>| {
+            let args: [Value; N] = args
+                .try_into()
+                .map_err(|_| "host op called with a wrong number of values".to_owned())?;
+            f(args)
+        });
+        HostOp {
+            optype,
+            func: HostFunc::Rust(func),
+        }
+    }
+
+    pub fn int(op: IntOp) -> Self {
+        HostOp {
+            optype: op.optype(),
+            func: HostFunc::Int(op),
+        }
+    }
+
+    /// Runs the op on the popped values, topmost first. The values are
+    /// expected to match the declared type already.
+    pub fn call(
+        &self,
+        name: &str,
+        semantics: IntSemantics,
+        args: Vec<Value>,
+    ) -> Result<Vec<Value>, RuntimeErrorMessage> {
+        match &self.func {
+            HostFunc::Rust(func) => func(args).map_err(|message| RuntimeErrorMessage::HostError {
+                name: name.to_owned(),
+                message,
+            }),
+            HostFunc::Int(op) => {
+                let ints: Vec<i32> = args
+                    .iter()
+                    .filter_map(|value| match value {
+                        Value::Int(n) => Some(*n),
+                        _ => None,
+                    })
+                    .collect();
+                match op.apply(semantics, &ints) {
+                    Some(n) => Ok(vec![Value::Int(n)]),
+                    None => Err(RuntimeErrorMessage::IntOverflow {
+                        name: name.to_owned(),
+                    }),
+                }
+            }
+        }
+    }
+}
+
+fn value_matches(t: &Type, value: &Value, constr_maps: &ModuleConstrMaps) -> bool {
+    match (t, value) {
+        (Type::Poly(_) | Type::Dyn, _) => true,
+        (Type::Op(_), Value::Quoted(_)) => true,
+        (Type::Mono(name), Value::Int(_)) => name == "Int",
+        (Type::Mono(name), Value::User { constr_name, .. }) => constr_maps
+            .constr_to_data_map
+            .get(constr_name.as_str())
+            .is_some_and(|(data_name, _)| *data_name == name),
+        // only the head of an application is checked, the arguments are
+        // not recoverable from the value alone
+        (Type::App(head, _), _) => value_matches(head, value, constr_maps),
+        (_, _) => false,
+    }
+}
+
+/// Checks a value crossing the host boundary against the declared type
+pub fn check_host_value(
+    name: &str,
+    t: &Type,
+    value: &Value,
+    constr_maps: &ModuleConstrMaps,
+) -> Result<(), RuntimeErrorMessage> {
+    if value_matches(t, value, constr_maps) {
+        Ok(())
+    } else {
+        Err(RuntimeErrorMessage::HostTypeMismatch {
+            name: name.to_owned(),
+            expected: t.clone(),
+            found: Box::new(value.clone()),
+        })
+    }
+}
+
+/// Whether the value is of the type as far as the value tells: unlike a
+/// host value, the fields of a constructor are checked too, against the
+/// arguments of the type. A quote is only checked to be a quote.
+pub(crate) fn value_has_type(t: &Type, value: &Value, constr_maps: &ModuleConstrMaps) -> bool {
+    let mut pending = vec![(t.clone(), value)];
+    while let Some((t, value)) = pending.pop() {
+        if !value_matches(&t, value, constr_maps) {
+            return false;
+        }
+        let Value::User { constr_name, args } = value else {
+            continue;
+        };
+        let mut type_args = vec![];
+        let mut head = &t;
+        while let Type::App(t1, t2) = head {
+            type_args.push((**t2).clone());
+            head = t1;
+        }
+        type_args.reverse();
+        let name = constr_name.as_str();
+        let (Some((_, data_def)), Some(constr)) = (
+            constr_maps.constr_to_data_map.get(name),
+            constr_maps.constr_to_constr_map.get(name),
+        ) else {
+            continue;
+        };
+        let s: Subst = zip(data_def.params.iter().cloned(), type_args).collect();
+        pending.extend(zip(&constr.params, args).map(|(field, value)| (field.apply(&s), value)));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::desugar::desugar;
+    use crate::evaluation::evaluator::*;
+    use crate::evaluation::host::*;
+    use crate::syntax::parse;
+    use crate::typing::inference::*;
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    fn mono(name: &str) -> Type {
+        Type::Mono(name.to_owned())
+    }
+
+    fn maybe_int() -> Type {
+        Type::App(Box::new(mono("Maybe")), Box::new(mono("Int")))
+    }
+
+    #[test]
+    fn fake_clock() {
+        let input = "
+        define [] main [Int, Int]: now now.
+        ";
+        let module = parse(input).unwrap();
+        let clock = Rc::new(Cell::new(100));
+        let mut evaluator = Evaluator::new(&module);
+        let now_type = OpType {
+            pre: vec![],
+            post: vec![mono("Int")],
+        };
+        let c = clock.clone();
+        evaluator
+            .register("now", now_type, move |[]| {
+                c.set(c.get() + 1);
+                Ok(vec![Value::Int(c.get())])
+            })
+            .unwrap();
+
+        let mut inference = Inference::new(&module);
+        for (name, optype) in evaluator.host_optypes() {
+            inference = inference.with_extern_op(name, optype.clone());
+        }
+        assert!(inference.typecheck().is_ok());
+
+        evaluator.eval_main().unwrap();
+        assert!(matches!(
+            evaluator.stack[..],
+            [Value::Int(101), Value::Int(102)]
+        ));
+        assert_eq!(clock.get(), 102);
+    }
+
+    #[test]
+    fn declarations_run_the_host_function_of_their_name() {
+        let input = "
+        define [] now [Int].
+        define [] later [Int]: now.
+        define [] main [Int]: later.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        let err = match evaluator.eval_main() {
+            Err(EvaluatorError::Runtime(err)) => err,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(
+            err.message(),
+            "op `now` is declared but has no implementation"
+        );
+        assert_eq!(&input[err.span.start..err.span.end], "define [] now [Int].");
+        let calls: Vec<_> = err.trace.iter().map(|frame| frame.op.as_deref()).collect();
+        assert_eq!(calls, [Some("now"), Some("later")]);
+
+        let now_type = OpType {
+            pre: vec![],
+            post: vec![mono("Int")],
+        };
+        let mut evaluator = Evaluator::new(&module);
+        evaluator
+            .register("now", now_type, |[]| Ok(vec![Value::Int(7)]))
+            .unwrap();
+        assert!(Inference::new(&module).typecheck().is_ok());
+        evaluator.eval_main().unwrap();
+        assert!(matches!(evaluator.stack[..], [Value::Int(7)]));
+    }
+
+    #[test]
+    fn extern_op_unknown_to_typechecker() {
+        let input = "
+        define [] main [Int]: now.
+        ";
+        let module = parse(input).unwrap();
+        assert!(matches!(
+            Inference::new(&module).typecheck(),
+            Err(InferenceError {
+                error: InferenceErrorMessage::UnknownOp { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn fix_factorial() {
+        let input = "
+        data Bool: true, false.
+        define [Int] fact [Int]:
+            (br-1 dup zero case {
+                true { pop pop 1 },
+                false { dup dec dg-2 exec-1-1 mul }
+            }) fix-1-1.
+        define [] main [Int, Int]: 0 fact 5 fact.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        let int = || mono("Int");
+        let int_op = |pre: usize, post: Type| OpType {
+            pre: vec![int(); pre],
+            post: vec![post],
+        };
+        let bool_value = |b: bool| Value::constr(if b { "true" } else { "false" }, vec![]);
+        evaluator
+            .register("zero", int_op(1, mono("Bool")), move |[n]| match n {
+                Value::Int(n) => Ok(vec![bool_value(n == 0)]),
+                _ => unreachable!(),
+            })
+            .unwrap();
+        evaluator
+            .register("dec", int_op(1, int()), |[n]| match n {
+                Value::Int(n) => Ok(vec![Value::Int(n - 1)]),
+                _ => unreachable!(),
+            })
+            .unwrap();
+        evaluator
+            .register("mul", int_op(2, int()), |[a, b]| match (a, b) {
+                (Value::Int(a), Value::Int(b)) => Ok(vec![Value::Int(a * b)]),
+                _ => unreachable!(),
+            })
+            .unwrap();
+
+        let mut inference = Inference::new(&module);
+        for (name, optype) in evaluator.host_optypes() {
+            inference = inference.with_extern_op(name, optype.clone());
+        }
+        assert!(inference.typecheck().is_ok());
+        let fact = inference.infer_body("fact").unwrap().unwrap();
+        assert_eq!(fact.canonical(), int_op(1, int()));
+
+        evaluator.eval_main().unwrap();
+        assert!(matches!(
+            evaluator.stack[..],
+            [Value::Int(1), Value::Int(120)]
+        ));
+    }
+
+    #[test]
+    fn fake_stdin() {
+        let input = "
+        data Maybe a: nothing, [a] just.
+        define [] main [Maybe Int, Maybe Int]: read-int read-int.
+        ";
+        let module = parse(input).unwrap();
+        let stdin = Rc::new(RefCell::new(VecDeque::from([7])));
+        let mut evaluator = Evaluator::new(&module);
+        let read_type = OpType {
+            pre: vec![],
+            post: vec![maybe_int()],
+        };
+        evaluator
+            .register("read-int", read_type, move |[]| {
+                let value = match stdin.borrow_mut().pop_front() {
+                    Some(n) => Value::constr("just", vec![Value::Int(n)]),
+                    None => Value::constr("nothing", vec![]),
+                };
+                Ok(vec![value])
+            })
+            .unwrap();
+        evaluator.eval_main().unwrap();
+        assert!(matches!(
+            &evaluator.stack[..],
+            [
+                Value::User { constr_name: ref name1, args: ref args1 },
+                Value::User { constr_name: ref name2, args: ref args2 },
+            ] if name1 == "just" && matches!(args1[..], [Value::Int(7)]) &&
+                 name2 == "nothing" && args2.is_empty()
+        ));
+    }
+
+    #[test]
+    fn fallible_host_op() {
+        let input = "define [] main [Int]: read-int-strict.";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        let read_type = OpType {
+            pre: vec![],
+            post: vec![mono("Int")],
+        };
+        evaluator
+            .register("read-int-strict", read_type, |[]| {
+                Err("stdin closed".to_owned())
+            })
+            .unwrap();
+        let Err(EvaluatorError::Runtime(err)) = evaluator.eval_main() else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(&input[err.span.start..err.span.end], "read-int-strict");
+        assert!(matches!(
+            err.error,
+            RuntimeErrorMessage::HostError { ref message, .. } if message == "stdin closed"
+        ));
+    }
+
+    #[test]
+    fn registration_arity_mismatch() {
+        let module = parse("").unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        let inc_type = OpType {
+            pre: vec![mono("Int")],
+            post: vec![mono("Int")],
+        };
+        let result = evaluator.register("inc", inc_type, |[]| Ok(vec![Value::Int(0)]));
+        assert!(matches!(
+            result,
+            Err(HostRegistrationError::ArityMismatch {
+                declared: 0,
+                expected: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn host_type_mismatch() {
+        let input = "
+        data Foo: foo.
+        define [] main [Int]: foo inc.
+        ";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        let inc_type = OpType {
+            pre: vec![mono("Int")],
+            post: vec![mono("Int")],
+        };
+        evaluator
+            .register("inc", inc_type, |[n]| match n {
+                Value::Int(n) => Ok(vec![Value::Int(n + 1)]),
+                _ => unreachable!(),
+            })
+            .unwrap();
+        assert!(matches!(
+            evaluator.eval_main(),
+            Err(EvaluatorError::Runtime(RuntimeError {
+                error: RuntimeErrorMessage::HostTypeMismatch { .. },
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn host_result_arity() {
+        let input = "define [] main [Int]: two.";
+        let module = parse(input).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        let two_type = OpType {
+            pre: vec![],
+            post: vec![mono("Int")],
+        };
+        evaluator
+            .register("two", two_type, |[]| Ok(vec![Value::Int(1), Value::Int(2)]))
+            .unwrap();
+        assert!(matches!(
+            evaluator.eval_main(),
+            Err(EvaluatorError::Runtime(RuntimeError {
+                error: RuntimeErrorMessage::HostResultArity {
+                    expected: 1,
+                    found: 2,
+                    ..
+                },
+                ..
+            }))
+        ));
+    }
+
+    /// Runs `main` of the desugared source with `any` leaving the value as
+    /// a `?`, and `add`
+    fn eval_with_any(input: &str, any: Value) -> Result<Vec<Value>, RuntimeError> {
+        let module = desugar(parse(input).unwrap()).unwrap();
+        let mut evaluator = Evaluator::new(&module);
+        evaluator.register_int_op("add", IntOp::Add).unwrap();
+        let any_type = OpType {
+            pre: vec![],
+            post: vec![Type::Dyn],
+        };
+        (evaluator.register("any", any_type, move |[]| Ok(vec![any.clone()]))).unwrap();
+        let mut inference = Inference::new(&module);
+        for (name, optype) in evaluator.host_optypes() {
+            inference = inference.with_extern_op(name, optype.clone());
+        }
+        let report = inference.report();
+        assert!(report.is_ok(), "{:?}", report);
+        let mut evaluator = evaluator.with_dyn_checks(report.dyn_checks);
+        match evaluator.eval_main() {
+            Ok(()) => Ok(evaluator.stack),
+            Err(EvaluatorError::Runtime(err)) => Err(err),
+            Err(err) => unreachable!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn dyn_values_are_checked_where_ops_take_them() {
+        let input = "
+        data Bool: false, true.
+        define [Int] inc [Int]: 1 add.
+        define [] main [Int, Int]: any 1 add any inc.
+        ";
+        let stack = eval_with_any(input, Value::Int(41)).unwrap();
+        assert!(matches!(stack[..], [Value::Int(42), Value::Int(42)]));
+
+        let err = eval_with_any(input, Value::constr("true", vec![])).unwrap_err();
+        assert_eq!(err.code(), "DynTypeMismatch");
+        assert_eq!(
+            err.message(),
+            "`true` is not of type Int, which the op takes where its value's type is `?`"
+        );
+        // the `add` of `main`, which the value goes to first
+        assert_eq!(err.span.start, input.find("add any").unwrap());
+
+        // only the op taking the value as an Int checks it
+        let input = "
+        data Bool: false, true.
+        define [Int] inc [Int]: 1 add.
+        define [] main [Int]: any dup pop inc.
+        ";
+        let err = eval_with_any(input, Value::constr("true", vec![])).unwrap_err();
+        assert_eq!(&input[err.span.start..err.span.end], "inc");
+
+        // what the op takes may only be known from values below the `?`
+        let input = "
+        data Bool: false, true.
+        define [a, [a][b]] app [b]: br-1 exec-1-1.
+        define [Bool] same [Bool]: .
+        define [] main [Bool]: (same) any app.
+        ";
+        let err = eval_with_any(input, Value::Int(5)).unwrap_err();
+        assert_eq!(err.code(), "DynTypeMismatch");
+        assert_eq!(&input[err.span.start..err.span.end], "app");
+    }
+
+    #[test]
+    fn dyn_checks_of_a_list_literal_are_its_own_ops() {
+        // every op the literal becomes shares its span, only the `cons`
+        // taking the `?` checks it
+        let input = "
+        data List a: empty, [a, List a] cons.
+        data Bool: false, true.
+        define [] main [List Int, Bool, Bool]: true true [1, any].
+        ";
+        let stack = eval_with_any(input, Value::Int(2)).unwrap();
+        assert_eq!(stack.len(), 3);
+        let err = eval_with_any(input, Value::constr("true", vec![])).unwrap_err();
+        assert_eq!(err.code(), "DynTypeMismatch");
+        assert_eq!(&input[err.span.start..err.span.end], "[1, any]");
+    }
+
+    fn int_ops_under(semantics: IntSemantics, body: &str) -> Result<Vec<i32>, RuntimeError> {
+        let input = format!("define [] main [Int, Int]: {}.", body);
+        let module = parse(&input).unwrap();
+        let mut evaluator = Evaluator::new(&module).with_int_semantics(semantics);
+        for (name, op) in [
+            ("add", IntOp::Add),
+            ("sub", IntOp::Sub),
+            ("mul", IntOp::Mul),
+            ("neg", IntOp::Neg),
+        ] {
+            evaluator.register_int_op(name, op).unwrap();
+        }
+        let mut inference = Inference::new(&module);
+        for (name, optype) in evaluator.host_optypes() {
+            inference = inference.with_extern_op(name, optype.clone());
+        }
+        assert!(inference.typecheck().is_ok(), "{}", body);
+        match evaluator.eval_main() {
+            Ok(()) => Ok(evaluator
+                .stack
+                .iter()
+                .map(|value| match value {
+                    Value::Int(n) => *n,
+                    _ => unreachable!(),
+                })
+                .collect()),
+            Err(EvaluatorError::Runtime(err)) => Err(err),
+            Err(err) => unreachable!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn wrapping_int_ops() {
+        let wrapping = |body| int_ops_under(IntSemantics::Wrapping, body).unwrap();
+        assert_eq!(wrapping("5 2 sub 3 neg"), [3, -3]);
+        assert_eq!(wrapping("0 2147483647 1 add"), [0, i32::MIN]);
+        assert_eq!(wrapping("0 -2147483648 1 sub"), [0, i32::MAX]);
+        assert_eq!(wrapping("0 -2147483648 neg"), [0, i32::MIN]);
+        assert_eq!(wrapping("65536 65536 mul 0"), [0, 0]);
+    }
+
+    #[test]
+    fn checked_int_ops() {
+        let checked = |body| int_ops_under(IntSemantics::Checked, body);
+        assert_eq!(
+            checked("2147483646 1 add -2147483647 1 sub").unwrap(),
+            [i32::MAX, i32::MIN]
+        );
+        assert_eq!(checked("46340 46340 mul 0").unwrap(), [2147395600, 0]);
+        for (body, op) in [
+            ("0 2147483647 1 add", "add"),
+            ("0 -2147483648 1 sub", "sub"),
+            ("0 -2147483648 neg", "neg"),
+            ("46341 46341 mul 0", "mul"),
+        ] {
+            let err = checked(body).unwrap_err();
+            assert!(
+                matches!(&err.error, RuntimeErrorMessage::IntOverflow { name } if name == op),
+                "{:?}",
+                err.error
+            );
+            let input = format!("define [] main [Int, Int]: {}.", body);
+            assert_eq!(&input[err.span.start..err.span.end], op);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Where the values of an evaluation were made, for telling which op
+//! produced a value. Kept beside the stack with `Evaluator::with_origins`,
+//! since it takes about as much memory as the values.
+
+use super::types::Shared;
+use crate::syntax::ast::Span;
+use std::collections::HashMap;
+
+/// Where a value was made, and where the values it was built from were
+#[derive(Debug, Clone, Default)]
+pub struct Origin {
+    /// The literal, constructor or host op that made the value, `None` if
+    /// not known, for the values put on the stack before the evaluation
+    pub span: Option<Span>,
+    /// The origins of the fields of a constructor value, topmost first.
+    /// For a quote, of the value it pushes or of the two quotes it is
+    /// composed of.
+    fields: Option<Shared<[Origin]>>,
+}
+
+impl Origin {
+    /// A value made at `span` from values made at `fields`
+    pub(crate) fn made(span: &Span, fields: Vec<Origin>) -> Self {
+        Origin {
+            span: Some(span.clone()),
+            fields: (!fields.is_empty()).then(|| fields.into()),
+        }
+    }
+
+    pub fn fields(&self) -> &[Origin] {
+        self.fields.as_deref().unwrap_or(&[])
+    }
+
+    /// The origin of the `i`th field, unknown if it is not recorded
+    pub(crate) fn field(&self, i: usize) -> Origin {
+        self.fields().get(i).cloned().unwrap_or_default()
+    }
+}
+
+/// The origins of the values on the evaluator's stack and of the
+/// constants' values
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Origins {
+    /// Bottom first, like the stack
+    pub stack: Vec<Origin>,
+    pub consts: HashMap<String, Origin>,
+}
+
+impl Origins {
+    /// Makes as many origins as there are values, those of values put on
+    /// the stack or taken off it from outside are lost
+    pub fn align(&mut self, len: usize) {
+        self.stack.resize_with(len, Origin::default);
+    }
+
+    pub fn pop(&mut self) -> Origin {
+        self.stack.pop().unwrap_or_default()
+    }
+
+    /// Takes the topmost `n` origins, topmost first
+    pub fn take(&mut self, n: usize) -> Vec<Origin> {
+        let start = self.stack.len().saturating_sub(n);
+        self.stack.drain(start..).rev().collect()
+    }
+}
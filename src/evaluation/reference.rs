@@ -0,0 +1,355 @@
+//! A reference evaluator, the semantics of `Evaluator` written as plainly
+//! as they go: every op, call and quote is a recursive call, values are
+//! cloned wherever they are used, and nothing is kept but the constants.
+//! It is slow on purpose and only there to check `Evaluator` against, see
+//! `tests/reference.rs`. Built with the `reference` feature.
+//!
+//! The limits only keep a runaway program from overflowing the Rust stack
+//! or running forever, a program hitting one tells nothing.
+
+use super::types::*;
+use crate::desugar::lists;
+use crate::syntax::ast::*;
+use crate::typing::prelude_types;
+use std::collections::HashMap;
+use std::iter::once;
+
+/// Calls of user ops and runs of quotes nested deeper than this fail
+pub const MAX_DEPTH: usize = 1000;
+/// Evaluations running more ops than this fail
+pub const MAX_STEPS: usize = 100_000;
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+pub struct Reference<'m> {
+    module: &'m Module,
+    /// The values of the constants computed so far
+    consts: HashMap<String, Value>,
+    /// The constants being computed, a cycle if one needs itself
+    computing: Vec<String>,
+    depth: usize,
+    steps: usize,
+    /// Left as it was at the point of failure when the evaluation errors
+    pub stack: Vec<Value>,
+}
+
+impl<'m> Reference<'m> {
+    pub fn new(module: &'m Module) -> Self {
+        Reference {
+            module,
+            consts: HashMap::new(),
+            computing: vec![],
+            depth: 0,
+            steps: 0,
+            stack: vec![],
+        }
+    }
+
+    pub fn eval_main(&mut self) -> std::result::Result<(), EvaluatorError> {
+        if !self.module.op_defs.contains_key("main") {
+            return Err(EvaluatorError::NoMain);
+        }
+        self.eval_op_def("main")
+    }
+
+    /// Runs the body of an op definition on the current stack, computing
+    /// every constant first
+    pub fn eval_op_def(&mut self, name: &str) -> std::result::Result<(), EvaluatorError> {
+        let module = self.module;
+        let op_def = module
+            .op_defs
+            .get(name)
+            .ok_or_else(|| EvaluatorError::NoOpDef {
+                name: name.to_owned(),
+            })?;
+        for (name, _) in module.const_defs_in_source_order() {
+            self.constant(name)?;
+        }
+        self.run(&op_def.body)?;
+        Ok(())
+    }
+
+    /// The value of the constant, computed on a stack of its own the first
+    /// time it is needed
+    fn constant(&mut self, name: &str) -> Result<Value> {
+        if let Some(value) = self.consts.get(name) {
+            return Ok(value.clone());
+        }
+        let const_def = &self.module.const_defs[name];
+        if let Some(start) = self.computing.iter().position(|n| n == name) {
+            let names = self.computing[start..].to_vec();
+            let first = &self.module.const_defs[&names[0]];
+            return Err(error(
+                &first.name_span,
+                RuntimeErrorMessage::ConstantCycle { names },
+            ));
+        }
+        self.computing.push(name.to_owned());
+        let stack = std::mem::take(&mut self.stack);
+        self.run(&const_def.body)?;
+        let value = self.pop(&const_def.span)?;
+        self.stack = stack;
+        self.computing.pop();
+        self.consts.insert(name.to_owned(), value.clone());
+        Ok(value)
+    }
+
+    fn run(&mut self, ops: &[Op]) -> Result<()> {
+        for op in ops {
+            self.op(op)?;
+        }
+        Ok(())
+    }
+
+    fn op(&mut self, op: &Op) -> Result<()> {
+        let span = op.get_span();
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(error(
+                span,
+                RuntimeErrorMessage::LimitExceeded(LimitKind::Steps),
+            ));
+        }
+        match op {
+            Op::Literal {
+                value: Literal::Int(n),
+                ..
+            } => self.stack.push(Value::Int(*n)),
+            Op::Literal {
+                value: Literal::Extern { tag, .. },
+                ..
+            } => {
+                let tag = tag.clone();
+                return Err(error(span, RuntimeErrorMessage::UnknownLiteralKind { tag }));
+            }
+            Op::Name { value, .. } => self.name(value, span)?,
+            Op::Quote { value, .. } => {
+                let ops = value.clone();
+                self.stack.push(Value::Quoted(Quoted::Sentence { ops }));
+            }
+            Op::Case { head_arm, arms, .. } => {
+                let (name, args) = match self.pop(span)? {
+                    Value::User { constr_name, args } => (constr_name, args),
+                    value => {
+                        return Err(error(span, RuntimeErrorMessage::NotAConstructor { value }))
+                    }
+                };
+                let arm = once(head_arm)
+                    .chain(arms)
+                    .find(|arm| arm.constr == name)
+                    .ok_or_else(|| {
+                        let name = name.to_string();
+                        error(span, RuntimeErrorMessage::UnknownConstructor { name })
+                    })?;
+                self.stack.extend(args.iter().rev().cloned());
+                self.run(&arm.body)?;
+            }
+            Op::Case2 { arms, .. } => {
+                let top = self.pop(span)?;
+                let below = self.pop(span)?;
+                let fits = |pattern: &Pattern, value: &Value| match (pattern, value) {
+                    (Pattern::Wildcard, _) => true,
+                    (Pattern::Constr(constr), Value::User { constr_name, .. }) => {
+                        constr == constr_name
+                    }
+                    (Pattern::Constr(_), _) => false,
+                };
+                let Some(arm) = arms
+                    .iter()
+                    .find(|arm| fits(&arm.patterns[0], &top) && fits(&arm.patterns[1], &below))
+                else {
+                    let message = match (top, below) {
+                        (Value::User { constr_name, .. }, Value::User { .. }) => {
+                            let name = constr_name.to_string();
+                            RuntimeErrorMessage::UnknownConstructor { name }
+                        }
+                        (Value::User { .. }, value) | (value, _) => {
+                            RuntimeErrorMessage::NotAConstructor { value }
+                        }
+                    };
+                    return Err(error(span, message));
+                };
+                // a wildcard leaves its value as it is, the topmost one's
+                // fields end up on top
+                for (pattern, value) in [(&arm.patterns[1], below), (&arm.patterns[0], top)] {
+                    match (pattern, value) {
+                        (Pattern::Constr(_), Value::User { args, .. }) => {
+                            self.stack.extend(args.iter().rev().cloned())
+                        }
+                        (_, value) => self.stack.push(value),
+                    }
+                }
+                self.run(&arm.body)?;
+            }
+            Op::List { items, span } => self.run(&lists::expand(items.clone(), span))?,
+        }
+        Ok(())
+    }
+
+    fn name(&mut self, name: &str, span: &Span) -> Result<()> {
+        let module = self.module;
+        let failed = |message: &str| {
+            let message = message.to_owned();
+            error(span, RuntimeErrorMessage::Assertion { message })
+        };
+        if let Some([n]) = parametric("br-", name) {
+            self.underflows(n + 1, span)?;
+            let value = self.pop(span)?;
+            self.stack.insert(self.stack.len() - n, value);
+        } else if let Some([n]) = parametric("dg-", name) {
+            self.underflows(n + 1, span)?;
+            let value = self.stack.remove(self.stack.len() - n - 1);
+            self.stack.push(value);
+        } else if name == "pick" || name == "roll" {
+            let n = match self.pop(span)? {
+                Value::Int(n) if n >= 0 => n as usize,
+                value => return Err(error(span, RuntimeErrorMessage::NotAnIndex { value })),
+            };
+            self.underflows(n + 1, span)?;
+            let at = self.stack.len() - n - 1;
+            let value = match name {
+                "pick" => self.stack[at].clone(),
+                _ => self.stack.remove(at),
+            };
+            self.stack.push(value);
+        } else if let Some([_, _, _, _]) = parametric("comp-", name) {
+            let b = self.pop_quoted(span)?;
+            let a = self.pop_quoted(span)?;
+            let (a, b) = (Box::new(a), Box::new(b));
+            self.stack.push(Value::Quoted(Quoted::Composed { a, b }));
+        } else if name == "dup" {
+            let value = self.pop(span)?;
+            self.stack.push(value.clone());
+            self.stack.push(value);
+        } else if name == "pop" {
+            self.pop(span)?;
+        } else if name == "quote" {
+            let value = Box::new(self.pop(span)?);
+            self.stack.push(Value::Quoted(Quoted::Value { value }));
+        } else if name == "trace" {
+            // prints the stack, which stays as it is
+        } else if name == "assert" {
+            let value = self.pop(span)?;
+            if !matches!(&value, Value::User { constr_name, .. } if constr_name == "true") {
+                return Err(failed("assertion failed"));
+            }
+        } else if name == "panic" {
+            return Err(failed("panicked"));
+        } else if let Some([_, _]) = parametric("exec-", name) {
+            let quoted = self.pop_quoted(span)?;
+            self.call(span, |reference| reference.exec(quoted))?;
+        } else if let Some([_, _]) = parametric("fix-", name) {
+            // the quote gets a quote running it fixed again
+            let quoted = self.pop_quoted(span)?;
+            let again = Quoted::Composed {
+                a: Box::new(Quoted::Value {
+                    value: Box::new(Value::Quoted(quoted.clone())),
+                }),
+                b: Box::new(Quoted::Sentence {
+                    ops: vec![Op::Name {
+                        value: name.to_owned(),
+                        span: span.clone(),
+                    }],
+                }),
+            };
+            self.stack.push(Value::Quoted(again));
+            self.call(span, |reference| reference.exec(quoted))?;
+        } else if let Some(expansion) = prelude_types::expand(name) {
+            for name in expansion {
+                self.name(&name, span)?;
+            }
+        } else if module.const_defs.contains_key(name) {
+            let value = self.constant(name)?;
+            self.stack.push(value);
+        } else if let Some(op_def) = module.op_defs.get(name) {
+            if op_def.declaration {
+                let name = name.to_owned();
+                return Err(error(
+                    &op_def.span,
+                    RuntimeErrorMessage::NotImplemented { name },
+                ));
+            }
+            self.call(span, |reference| reference.run(&op_def.body))?;
+        } else if let Some(constr) = constr(module, name) {
+            let arity = constr.params.len();
+            self.underflows(arity, span)?;
+            let args = self.stack.split_off(self.stack.len() - arity);
+            let args = args.into_iter().rev().collect();
+            self.stack.push(Value::constr(name, args));
+        } else {
+            let name = name.to_owned();
+            return Err(error(span, RuntimeErrorMessage::UnknownOp { name }));
+        }
+        Ok(())
+    }
+
+    /// Runs the ops of the quote, pushes the value it quotes
+    fn exec(&mut self, quoted: Quoted) -> Result<()> {
+        match quoted {
+            Quoted::Sentence { ops } => self.run(&ops),
+            Quoted::Value { value } => {
+                self.stack.push(*value);
+                Ok(())
+            }
+            Quoted::Composed { a, b } => {
+                self.exec(*a)?;
+                self.exec(*b)
+            }
+        }
+    }
+
+    fn call(&mut self, span: &Span, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        if self.depth == MAX_DEPTH {
+            return Err(error(
+                span,
+                RuntimeErrorMessage::LimitExceeded(LimitKind::CallDepth),
+            ));
+        }
+        self.depth += 1;
+        f(self)?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn pop(&mut self, span: &Span) -> Result<Value> {
+        self.stack
+            .pop()
+            .ok_or_else(|| error(span, RuntimeErrorMessage::StackUnderflow))
+    }
+
+    fn pop_quoted(&mut self, span: &Span) -> Result<Quoted> {
+        match self.pop(span)? {
+            Value::Quoted(quoted) => Ok(quoted),
+            value => Err(error(span, RuntimeErrorMessage::NotAQuote { value })),
+        }
+    }
+
+    fn underflows(&self, depth: usize, span: &Span) -> Result<()> {
+        if self.stack.len() < depth {
+            return Err(error(span, RuntimeErrorMessage::StackUnderflow));
+        }
+        Ok(())
+    }
+}
+
+fn error(span: &Span, message: RuntimeErrorMessage) -> RuntimeError {
+    RuntimeError::new(span, message)
+}
+
+fn parametric<const N: usize>(prefix: &str, s: &str) -> Option<[usize; N]> {
+    let numbers: Option<Vec<usize>> = s
+        .strip_prefix(prefix)?
+        .split('-')
+        .map(|n| n.parse().ok())
+        .collect();
+    numbers?.try_into().ok()
+}
+
+/// The constructor of the name, the first of the data definitions defining
+/// it
+fn constr<'m>(module: &'m Module, name: &str) -> Option<&'m DataConstr> {
+    module
+        .data_defs_in_source_order()
+        .into_iter()
+        .find_map(|(_, data_def)| data_def.constrs.get(name))
+}
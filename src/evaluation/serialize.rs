@@ -0,0 +1,760 @@
+//! Values as JSON, for persisting stacks and sending values to other
+//! processes. The encoding of a value is one of
+//!
+//! - `{"int":5}`
+//! - `{"data":"List","constr":"cons","fields":[...]}`
+//! - `{"quote":q}` where `q` is `{"ops":"dup exec-1-1"}`, `{"value":v}` or
+//!   `{"compose":[q,q]}`, matching `Quoted`
+//!
+//! Decoding checks constructors against the module instead of trusting the
+//! input, a value the module could not have built is an error.
+
+use super::types::{Quoted, Value};
+use crate::diagnostics::json_string;
+use crate::syntax::ast::{Module, Op};
+use crate::syntax::module_wrapper::ModuleConstrMaps;
+use crate::syntax::parse_ops;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    /// Reject quotes, for input from untrusted places, since decoded ops run
+    /// when the quote is executed
+    pub forbid_quotes: bool,
+    /// Nesting of values, quotes included, beyond which decoding gives up
+    pub max_depth: usize,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            forbid_quotes: false,
+            max_depth: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// Not JSON, `at` is the byte offset reading stopped at
+    Syntax {
+        at: usize,
+    },
+    /// JSON, but not of the shape of an encoded value
+    Shape {
+        expected: &'static str,
+    },
+    IntOutOfRange {
+        n: String,
+    },
+    UnknownConstructor {
+        name: String,
+    },
+    /// The constructor belongs to a different data type in the module
+    WrongDataType {
+        constr: String,
+        expected: String,
+        found: String,
+    },
+    WrongArity {
+        constr: String,
+        expected: usize,
+        found: usize,
+    },
+    QuotesForbidden,
+    /// The ops of a quote do not parse, or are not plain ops
+    InvalidOps {
+        ops: String,
+    },
+    TooDeep,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Syntax { at } => write!(f, "invalid JSON at byte {}", at),
+            DecodeError::Shape { expected } => write!(f, "expected {}", expected),
+            DecodeError::IntOutOfRange { n } => write!(f, "{} does not fit an Int", n),
+            DecodeError::UnknownConstructor { name } => {
+                write!(f, "no constructor `{}` is defined", name)
+            }
+            DecodeError::WrongDataType {
+                constr,
+                expected,
+                found,
+            } => write!(
+                f,
+                "constructor `{}` is of `{}`, not `{}`",
+                constr, expected, found
+            ),
+            DecodeError::WrongArity {
+                constr,
+                expected,
+                found,
+            } => write!(
+                f,
+                "constructor `{}` takes {} fields, found {}",
+                constr, expected, found
+            ),
+            DecodeError::QuotesForbidden => write!(f, "quotes are not accepted"),
+            DecodeError::InvalidOps { ops } => write!(f, "`{}` are not valid quote ops", ops),
+            DecodeError::TooDeep => write!(f, "value nested too deeply"),
+        }
+    }
+}
+
+/// A stack as a JSON array, in the order of `Evaluator::stack`, topmost
+/// value last
+pub fn stack_to_json(stack: &[Value], module: &Module) -> String {
+    let maps = ModuleConstrMaps::new(module);
+    let values: Vec<_> = stack.iter().map(|value| encode(value, &maps)).collect();
+    format!("[{}]", values.join(","))
+}
+
+pub fn stack_from_json(
+    json: &str,
+    module: &Module,
+    opts: &DecodeOptions,
+) -> Result<Vec<Value>, DecodeError> {
+    let json = read(json, opts.max_depth)?;
+    let decoder = Decoder::new(module, opts);
+    match json {
+        Json::Array(items) => items.iter().map(|item| decoder.value(item, 0)).collect(),
+        _ => Err(DecodeError::Shape {
+            expected: "an array of values",
+        }),
+    }
+}
+
+impl Value {
+    /// The value as JSON. A constructor the module does not define gets a
+    /// `null` data type, which no module decodes.
+    pub fn to_json(&self, module: &Module) -> String {
+        encode(self, &ModuleConstrMaps::new(module))
+    }
+
+    pub fn from_json(
+        json: &str,
+        module: &Module,
+        opts: &DecodeOptions,
+    ) -> Result<Self, DecodeError> {
+        let json = read(json, opts.max_depth)?;
+        Decoder::new(module, opts).value(&json, 0)
+    }
+}
+
+fn encode(value: &Value, maps: &ModuleConstrMaps) -> String {
+    match value {
+        Value::Int(n) => format!("{{\"int\":{}}}", n),
+        Value::User { constr_name, args } => {
+            let data = maps
+                .constr_to_data_map
+                .get(constr_name.as_str())
+                .map_or("null".to_owned(), |(data_name, _)| json_string(data_name));
+            let fields: Vec<_> = args.iter().map(|arg| encode(arg, maps)).collect();
+            format!(
+                "{{\"data\":{},\"constr\":{},\"fields\":[{}]}}",
+                data,
+                json_string(constr_name),
+                fields.join(",")
+            )
+        }
+        Value::Quoted(quoted) => format!("{{\"quote\":{}}}", encode_quoted(quoted, maps)),
+    }
+}
+
+fn encode_quoted(quoted: &Quoted, maps: &ModuleConstrMaps) -> String {
+    match quoted {
+        Quoted::Sentence { ops } => {
+            let ops: Vec<_> = ops.iter().map(|op| op.to_string()).collect();
+            format!("{{\"ops\":{}}}", json_string(&ops.join(" ")))
+        }
+        Quoted::Value { value } => format!("{{\"value\":{}}}", encode(value, maps)),
+        Quoted::Composed { a, b } => format!(
+            "{{\"compose\":[{},{}]}}",
+            encode_quoted(a, maps),
+            encode_quoted(b, maps)
+        ),
+    }
+}
+
+struct Decoder<'m> {
+    maps: ModuleConstrMaps<'m>,
+    opts: &'m DecodeOptions,
+}
+
+impl<'m> Decoder<'m> {
+    fn new(module: &'m Module, opts: &'m DecodeOptions) -> Self {
+        Decoder {
+            maps: ModuleConstrMaps::new(module),
+            opts,
+        }
+    }
+
+    fn value(&self, json: &Json, depth: usize) -> Result<Value, DecodeError> {
+        if depth > self.opts.max_depth {
+            return Err(DecodeError::TooDeep);
+        }
+        let shape = DecodeError::Shape {
+            expected: "an int, constructor or quote object",
+        };
+        let Json::Object(fields) = json else {
+            return Err(shape);
+        };
+        match &fields[..] {
+            [(key, Json::Number(n))] if key == "int" => n
+                .parse()
+                .map(Value::Int)
+                .map_err(|_| DecodeError::IntOutOfRange { n: n.to_owned() }),
+            [(key, quoted)] if key == "quote" => {
+                if self.opts.forbid_quotes {
+                    return Err(DecodeError::QuotesForbidden);
+                }
+                Ok(Value::Quoted(self.quoted(quoted, depth + 1)?))
+            }
+            _ => {
+                let (data, constr, args) = match (
+                    field(fields, "data"),
+                    field(fields, "constr"),
+                    field(fields, "fields"),
+                ) {
+                    (
+                        Some(Json::String(data)),
+                        Some(Json::String(constr)),
+                        Some(Json::Array(args)),
+                    ) if fields.len() == 3 => (data, constr, args),
+                    _ => return Err(shape),
+                };
+                let (data_name, _) = self
+                    .maps
+                    .constr_to_data_map
+                    .get(constr.as_str())
+                    .ok_or_else(|| DecodeError::UnknownConstructor {
+                        name: constr.to_owned(),
+                    })?;
+                if *data_name != data {
+                    return Err(DecodeError::WrongDataType {
+                        constr: constr.to_owned(),
+                        expected: data_name.to_string(),
+                        found: data.to_owned(),
+                    });
+                }
+                let params = &self.maps.constr_to_constr_map[constr.as_str()].params;
+                if params.len() != args.len() {
+                    return Err(DecodeError::WrongArity {
+                        constr: constr.to_owned(),
+                        expected: params.len(),
+                        found: args.len(),
+                    });
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| self.value(arg, depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::constr(constr.as_str(), args))
+            }
+        }
+    }
+
+    fn quoted(&self, json: &Json, depth: usize) -> Result<Quoted, DecodeError> {
+        if depth > self.opts.max_depth {
+            return Err(DecodeError::TooDeep);
+        }
+        let Json::Object(fields) = json else {
+            return Err(DecodeError::Shape {
+                expected: "an ops, value or compose object",
+            });
+        };
+        match &fields[..] {
+            [(key, Json::String(text))] if key == "ops" => {
+                let invalid = || DecodeError::InvalidOps {
+                    ops: text.to_owned(),
+                };
+                let ops = parse_ops(text).map_err(|_| invalid())?;
+                if contains_list(&ops) {
+                    return Err(invalid());
+                }
+                Ok(Quoted::Sentence { ops })
+            }
+            [(key, value)] if key == "value" => Ok(Quoted::Value {
+                value: Box::new(self.value(value, depth + 1)?),
+            }),
+            [(key, Json::Array(parts))] if key == "compose" && parts.len() == 2 => {
+                Ok(Quoted::Composed {
+                    a: Box::new(self.quoted(&parts[0], depth + 1)?),
+                    b: Box::new(self.quoted(&parts[1], depth + 1)?),
+                })
+            }
+            _ => Err(DecodeError::Shape {
+                expected: "an ops, value or compose object",
+            }),
+        }
+    }
+}
+
+fn field<'j>(fields: &'j [(String, Json)], key: &str) -> Option<&'j Json> {
+    fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// List literals are desugared before evaluation, decoded ops never are
+fn contains_list(ops: &[Op]) -> bool {
+    ops.iter().any(|op| match op {
+        Op::List { .. } => true,
+        Op::Quote { value, .. } => contains_list(value),
+        Op::Case { head_arm, arms, .. } => std::iter::once(head_arm)
+            .chain(arms)
+            .any(|arm| contains_list(&arm.body)),
+        Op::Case2 { arms, .. } => arms.iter().any(|arm| contains_list(&arm.body)),
+        Op::Literal { .. } | Op::Name { .. } => false,
+    })
+}
+
+/// Parsed JSON, numbers kept as their text
+#[derive(Debug)]
+enum Json {
+    /// `true`, `false` or `null`, no encoded value has them
+    Keyword,
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn read(src: &str, max_depth: usize) -> Result<Json, DecodeError> {
+    let mut reader = Reader {
+        src: src.as_bytes(),
+        at: 0,
+        max_depth,
+    };
+    let json = reader.value(0)?;
+    reader.skip_whitespace();
+    if reader.at < reader.src.len() {
+        return Err(reader.error());
+    }
+    Ok(json)
+}
+
+struct Reader<'s> {
+    src: &'s [u8],
+    at: usize,
+    max_depth: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self) -> DecodeError {
+        DecodeError::Syntax { at: self.at }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.src.get(self.at), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.src.get(self.at) == Some(&byte) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), DecodeError> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn keyword(&mut self, word: &str) -> Result<Json, DecodeError> {
+        if self.src[self.at..].starts_with(word.as_bytes()) {
+            self.at += word.len();
+            Ok(Json::Keyword)
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, DecodeError> {
+        if depth > self.max_depth {
+            return Err(DecodeError::TooDeep);
+        }
+        self.skip_whitespace();
+        match self.src.get(self.at) {
+            Some(b'{') => {
+                self.at += 1;
+                let mut fields = vec![];
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        fields.push((key, self.value(depth + 1)?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Json::Object(fields))
+            }
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = vec![];
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.keyword("true"),
+            Some(b'f') => self.keyword("false"),
+            Some(b'n') => self.keyword("null"),
+            _ => Err(self.error()),
+        }
+    }
+
+    /// Integers only, encoded values have no fractions
+    fn number(&mut self) -> Result<Json, DecodeError> {
+        let start = self.at;
+        if self.src[self.at] == b'-' {
+            self.at += 1;
+        }
+        let digits = self.at;
+        while matches!(self.src.get(self.at), Some(b'0'..=b'9')) {
+            self.at += 1;
+        }
+        let leading_zero = self.src.get(digits) == Some(&b'0') && self.at - digits > 1;
+        if self.at == digits || leading_zero {
+            return Err(self.error());
+        }
+        if matches!(self.src.get(self.at), Some(b'.' | b'e' | b'E')) {
+            return Err(self.error());
+        }
+        let text = std::str::from_utf8(&self.src[start..self.at]).unwrap();
+        Ok(Json::Number(text.to_owned()))
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        if self.src.get(self.at) != Some(&b'"') {
+            return Err(self.error());
+        }
+        self.at += 1;
+        let mut out = vec![];
+        loop {
+            match self.src.get(self.at) {
+                None => return Err(self.error()),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.at += 1;
+                    let unescaped = match self.src.get(self.at) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error()),
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(unescaped.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(b) if *b < 0x20 => return Err(self.error()),
+                Some(b) => out.push(*b),
+            }
+            self.at += 1;
+        }
+        self.at += 1;
+        // the input is a str and escapes add whole chars
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    /// The char of a `\uXXXX` escape, a surrogate pair taking two escapes,
+    /// leaves `at` on the last hex digit
+    fn unicode_escape(&mut self) -> Result<char, DecodeError> {
+        let hex = |reader: &Self, from: usize| {
+            reader
+                .src
+                .get(from..from + 4)
+                .and_then(|digits| std::str::from_utf8(digits).ok())
+                .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| reader.error())
+        };
+        let high = hex(self, self.at + 1)?;
+        self.at += 4;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error());
+        }
+        if self.src.get(self.at + 1..self.at + 3) != Some(b"\\u") {
+            return Err(self.error());
+        }
+        let low = hex(self, self.at + 3)?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.error());
+        }
+        self.at += 6;
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+            .ok_or_else(|| self.error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desugar::desugar;
+    use crate::evaluation::display::DisplayOptions;
+    use crate::rng::Rng;
+    use crate::syntax::parse;
+    use crate::typing::types::Type;
+    use std::fs;
+    use std::path::Path;
+
+    /// Some value of the data type, ints and quotes standing in for type
+    /// variables and op types. Deep values stick to constructors with the
+    /// fewest fields, `None` if the type has no finite values that way.
+    fn generate(module: &Module, data: &str, depth: usize, rng: &mut Rng) -> Option<Value> {
+        if depth > 16 {
+            return None;
+        }
+        let mut constrs: Vec<_> = module.data_defs[data].constrs.iter().collect();
+        constrs.sort_by_key(|(_, constr)| (constr.params.len(), constr.span.start));
+        let (name, constr) = if depth >= 4 {
+            constrs[0]
+        } else {
+            constrs[rng.below(constrs.len())]
+        };
+        let args = constr
+            .params
+            .iter()
+            .map(|ty| generate_field(module, ty, depth + 1, rng))
+            .collect::<Option<_>>()?;
+        Some(Value::constr(name.as_str(), args))
+    }
+
+    fn generate_field(module: &Module, ty: &Type, depth: usize, rng: &mut Rng) -> Option<Value> {
+        match ty {
+            Type::App(head, _) => generate_field(module, head, depth, rng),
+            Type::Mono(name) if module.data_defs.contains_key(name) => {
+                generate(module, name, depth, rng)
+            }
+            Type::Op(_) => Some(Value::Quoted(generate_quoted(rng, depth))),
+            _ => Some(Value::Int([0, -1, 7, i32::MIN, i32::MAX][rng.below(5)])),
+        }
+    }
+
+    fn generate_quoted(rng: &mut Rng, depth: usize) -> Quoted {
+        let sentences = [
+            "",
+            "dup exec-1-1",
+            "-3 (1 (2)) pop",
+            "case { zero { 1 }, suc { pop } }",
+            "case2 { _ zero { }, suc _ {} }",
+        ];
+        match rng.below(if depth > 6 { 1 } else { 3 }) {
+            0 => Quoted::Sentence {
+                ops: parse_ops(sentences[rng.below(sentences.len())]).unwrap(),
+            },
+            1 => Quoted::Value {
+                value: Box::new(Value::Int(rng.below(100) as i32)),
+            },
+            _ => Quoted::Composed {
+                a: Box::new(generate_quoted(rng, depth + 1)),
+                b: Box::new(generate_quoted(rng, depth + 1)),
+            },
+        }
+    }
+
+    fn decode(json: &str, module: &Module) -> Result<Value, DecodeError> {
+        Value::from_json(json, module, &DecodeOptions::default())
+    }
+
+    fn module(source: &str) -> Module {
+        desugar(parse(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn fixture_values_round_trip() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut rng = Rng(1);
+        let mut checked = 0;
+        for dir in ["examples", "tests/cases", "tests/codegen"] {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|ext| ext != "iv") {
+                    continue;
+                }
+                let Ok(module) = parse(&fs::read_to_string(&path).unwrap()) else {
+                    continue;
+                };
+                let module = desugar(module).unwrap();
+                for data in module.data_defs.keys() {
+                    for _ in 0..20 {
+                        let Some(value) = generate(&module, data, 0, &mut rng) else {
+                            continue;
+                        };
+                        let json = value.to_json(&module);
+                        let decoded = decode(&json, &module)
+                            .unwrap_or_else(|err| panic!("{}: {}: {}", path.display(), json, err));
+                        assert_eq!(decoded.to_json(&module), json);
+                        let opts = DisplayOptions::default();
+                        assert_eq!(decoded.display(&opts), value.display(&opts));
+                        checked += 1;
+                    }
+                }
+            }
+        }
+        assert!(checked > 100);
+    }
+
+    #[test]
+    fn stacks_round_trip() {
+        let module = module("data Nat: zero, [Nat] suc.");
+        let stack = vec![
+            Value::Int(3),
+            Value::constr("suc", vec![Value::constr("zero", vec![])]),
+            Value::Quoted(Quoted::Sentence { ops: vec![] }),
+        ];
+        let json = stack_to_json(&stack, &module);
+        assert_eq!(
+            json,
+            "[{\"int\":3},\
+             {\"data\":\"Nat\",\"constr\":\"suc\",\"fields\":[{\"data\":\"Nat\",\"constr\":\"zero\",\"fields\":[]}]},\
+             {\"quote\":{\"ops\":\"\"}}]"
+        );
+        let decoded = stack_from_json(&json, &module, &DecodeOptions::default()).unwrap();
+        assert_eq!(stack_to_json(&decoded, &module), json);
+        let spaced = " [ { \"int\" : -3 } ,{\"quote\":{\"ops\":\"1 \\u0032\\n\"}} ] ";
+        let decoded = stack_from_json(spaced, &module, &DecodeOptions::default()).unwrap();
+        assert_eq!(
+            stack_to_json(&decoded, &module),
+            "[{\"int\":-3},{\"quote\":{\"ops\":\"1 2\"}}]"
+        );
+    }
+
+    #[test]
+    fn renamed_constructors_do_not_decode() {
+        let before = module("data Nat: zero, [Nat] suc.");
+        let json = Value::constr("suc", vec![Value::constr("zero", vec![])]).to_json(&before);
+        assert_eq!(
+            decode(&json, &module("data Nat: zero, [Nat] succ.")).unwrap_err(),
+            DecodeError::UnknownConstructor {
+                name: "suc".to_owned()
+            }
+        );
+        assert_eq!(
+            decode(&json, &module("data Natural: zero, [Natural] suc.")).unwrap_err(),
+            DecodeError::WrongDataType {
+                constr: "suc".to_owned(),
+                expected: "Natural".to_owned(),
+                found: "Nat".to_owned()
+            }
+        );
+        assert_eq!(
+            decode(&json, &module("data Nat: zero, [Nat, Nat] suc.")).unwrap_err(),
+            DecodeError::WrongArity {
+                constr: "suc".to_owned(),
+                expected: 2,
+                found: 1
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_what_the_module_could_not_build() {
+        let module = module("data Nat: zero, [Nat] suc.");
+        let err = |json: &str| decode(json, &module).unwrap_err();
+        assert_eq!(
+            err("{\"int\":2147483648}"),
+            DecodeError::IntOutOfRange {
+                n: "2147483648".to_owned()
+            }
+        );
+        assert!(matches!(
+            err("{\"int\":1,\"x\":2}"),
+            DecodeError::Shape { .. }
+        ));
+        assert!(matches!(err("[]"), DecodeError::Shape { .. }));
+        assert!(matches!(
+            err("{\"data\":\"Nat\",\"constr\":\"zero\",\"fields\":[],\"extra\":1}"),
+            DecodeError::Shape { .. }
+        ));
+        assert!(matches!(
+            err("{\"quote\":{\"ops\":\"(\"}}"),
+            DecodeError::InvalidOps { .. }
+        ));
+        assert!(matches!(
+            err("{\"quote\":{\"ops\":\"[1]\"}}"),
+            DecodeError::InvalidOps { .. }
+        ));
+        assert!(matches!(
+            err("{\"quote\":{\"compose\":[{\"ops\":\"\"}]}}"),
+            DecodeError::Shape { .. }
+        ));
+        let untrusted = DecodeOptions {
+            forbid_quotes: true,
+            ..DecodeOptions::default()
+        };
+        let quote = "{\"data\":\"Nat\",\"constr\":\"suc\",\"fields\":[{\"quote\":{\"ops\":\"\"}}]}";
+        assert!(decode(quote, &module).is_ok());
+        assert_eq!(
+            Value::from_json(quote, &module, &untrusted).unwrap_err(),
+            DecodeError::QuotesForbidden
+        );
+        let shallow = DecodeOptions {
+            max_depth: 3,
+            ..DecodeOptions::default()
+        };
+        let deep = "{\"quote\":{\"value\":{\"quote\":{\"value\":{\"int\":1}}}}}";
+        assert!(decode(deep, &module).is_ok());
+        assert_eq!(
+            Value::from_json(deep, &module, &shallow).unwrap_err(),
+            DecodeError::TooDeep
+        );
+    }
+
+    #[test]
+    fn malformed_json_is_an_error() {
+        let module = module("data Nat: zero, [Nat] suc.");
+        let json = "{\"data\":\"Nat\",\"constr\":\"suc\",\"fields\":[{\"quote\":{\"compose\":\
+                    [{\"ops\":\"1 \\\"\"},{\"value\":{\"int\":-12}}]}}]}";
+        assert!(decode(json, &module).is_err());
+        for end in 0..json.len() {
+            assert!(decode(&json[..end], &module).is_err(), "{}", &json[..end]);
+        }
+        for bad in [
+            "{\"int\":01}",
+            "{\"int\":1.5}",
+            "{\"int\":-}",
+            "{\"int\":1} x",
+            "{\"int\":1,}",
+            "\"\\ud800\"",
+            "\"\\udc00\"",
+            "\"\\x\"",
+            "nul",
+        ] {
+            assert!(
+                matches!(decode(bad, &module), Err(DecodeError::Syntax { .. })),
+                "{}",
+                bad
+            );
+        }
+        assert_eq!(
+            read("\"\\ud83d\\ude00\\u00e9\"", 8).map(|j| format!("{:?}", j)),
+            Ok("String(\"😀é\")".to_owned())
+        );
+    }
+}
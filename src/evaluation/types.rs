@@ -1,22 +1,472 @@
+use crate::diagnostics::kind::DiagnosticKind;
 use crate::syntax::ast::*;
+use crate::typing::types::Type;
+use std::fmt;
+use std::ops::Deref;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum EvaluatorError {
     NoMain,
+    /// `Evaluator::eval_op_def` of an op the module does not define
+    NoOpDef {
+        name: String,
+    },
+    Runtime(RuntimeError),
+}
+
+impl From<RuntimeError> for EvaluatorError {
+    fn from(err: RuntimeError) -> Self {
+        EvaluatorError::Runtime(err)
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct RuntimeError {
+    /// The op that failed
+    pub span: Span,
+    pub error: RuntimeErrorMessage,
+    /// The calls the failing op was in, innermost first, not counting the
+    /// op definition the evaluation started from
+    pub trace: Vec<Frame>,
+    /// Where the value the op failed on was made, only known with
+    /// `Evaluator::with_origins`. Boxed, it is rarely there.
+    pub origin: Option<Box<Span>>,
+}
+
+impl RuntimeError {
+    pub fn new(span: &Span, error: RuntimeErrorMessage) -> Self {
+        RuntimeError {
+            span: span.clone(),
+            error,
+            trace: vec![],
+            origin: None,
+        }
+    }
+
+    pub fn with_origin(mut self, origin: Option<Span>) -> Self {
+        self.origin = origin.map(Box::new);
+        self
+    }
+
+    /// See `RuntimeErrorMessage::code`
+    pub fn code(&self) -> &'static str {
+        self.error.code()
+    }
+
+    pub fn kind(&self) -> DiagnosticKind {
+        DiagnosticKind::of(self.code())
+    }
+
+    /// The op that failed
+    pub fn primary_span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn message(&self) -> String {
+        self.error.to_string()
+    }
+
+    /// Where the value the op failed on was made, if known, then the calls
+    /// the op was in, innermost first
+    pub fn related_spans(&self) -> Vec<Span> {
+        let origin = self.origin.as_deref().cloned();
+        let calls = self.trace.iter().map(|frame| frame.span.clone());
+        origin.into_iter().chain(calls).collect()
+    }
+}
+
+/// A call of a user op, or a run of a quote by `exec-*` or `fix-*`
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The op called, `None` for a quote
+    pub op: Option<String>,
+    /// Where the op was called, or the quote's ops
+    pub span: Span,
+}
+
+/// Caps on the resources a single evaluation may use, `None` means unlimited
+#[derive(Debug, Clone, Default)]
+pub struct ExecLimits {
+    /// Number of ops executed, including the ones inside quotes and case arms
+    pub max_steps: Option<usize>,
+    /// Nesting of user op calls and executed quotes
+    pub max_call_depth: Option<usize>,
+    pub max_stack_len: Option<usize>,
+    /// Size of a single constructed value, counted in values
+    pub max_value_nodes: Option<usize>,
+}
+
+/// What Int arithmetic does with a result that does not fit in an `i32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntSemantics {
+    /// Wraps around, two's complement
+    #[default]
+    Wrapping,
+    /// Fails with `IntOverflow` at the op
+    Checked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Steps,
+    CallDepth,
+    StackLen,
+    ValueNodes,
+}
+
+/// What running fails with. Matching on the variants of another crate
+/// breaks as they change, see `diagnostics::kind` for what does not.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RuntimeErrorMessage {
+    StackUnderflow,
+    LimitExceeded(LimitKind),
+    #[non_exhaustive]
+    NotAQuote {
+        value: Value,
+    },
+    #[non_exhaustive]
+    NotAConstructor {
+        value: Value,
+    },
+    /// `pick` or `roll` popped a value that is no `Int` of at least 0
+    #[non_exhaustive]
+    NotAnIndex {
+        value: Value,
+    },
+    #[non_exhaustive]
+    UnknownOp {
+        name: String,
+    },
+    #[non_exhaustive]
+    UnknownConstructor {
+        name: String,
+    },
+    #[non_exhaustive]
+    HostTypeMismatch {
+        name: String,
+        expected: Type,
+        found: Box<Value>,
+    },
+    /// A value the ops before leave with a `?` in its type is not of the
+    /// type the op needs, see `Evaluator::with_dyn_checks`
+    #[non_exhaustive]
+    DynTypeMismatch {
+        expected: Type,
+        found: Box<Value>,
+    },
+    #[non_exhaustive]
+    HostResultArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[non_exhaustive]
+    HostError {
+        name: String,
+        message: String,
+    },
+    /// An extern literal whose tag no `Evaluator::register_literal` gave
+    /// values for
+    #[non_exhaustive]
+    UnknownLiteralKind {
+        tag: String,
+    },
+    /// The function registered for the literal's tag rejected it
+    #[non_exhaustive]
+    LiteralError {
+        repr: String,
+        message: String,
+    },
+    /// An Int op's result did not fit under `IntSemantics::Checked`
+    #[non_exhaustive]
+    IntOverflow {
+        name: String,
+    },
+    /// The first constant's initializer needs the second, and so on, the
+    /// last one's needs the first
+    #[non_exhaustive]
+    ConstantCycle {
+        names: Vec<String>,
+    },
+    /// `assert` was given `false`, or `panic` ran. The language has no
+    /// strings, the message is the op's own.
+    #[non_exhaustive]
+    Assertion {
+        message: String,
+    },
+    /// A call of an op declared without a body that no host function was
+    /// registered for, the error is at the declaration
+    #[non_exhaustive]
+    NotImplemented {
+        name: String,
+    },
+}
+
+impl RuntimeErrorMessage {
+    /// Stable name of the kind of error, as `iv explain` takes it. Where a
+    /// type error has the same name the code says it is a runtime one.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeErrorMessage::StackUnderflow => "StackUnderflow",
+            RuntimeErrorMessage::LimitExceeded(_) => "LimitExceeded",
+            RuntimeErrorMessage::NotAQuote { .. } => "NotAQuote",
+            RuntimeErrorMessage::NotAConstructor { .. } => "NotAConstructor",
+            RuntimeErrorMessage::NotAnIndex { .. } => "NotAnIndex",
+            RuntimeErrorMessage::UnknownOp { .. } => "RuntimeUnknownOp",
+            RuntimeErrorMessage::UnknownConstructor { .. } => "NoMatchingArm",
+            RuntimeErrorMessage::HostTypeMismatch { .. } => "HostTypeMismatch",
+            RuntimeErrorMessage::DynTypeMismatch { .. } => "DynTypeMismatch",
+            RuntimeErrorMessage::HostResultArity { .. } => "HostResultArity",
+            RuntimeErrorMessage::HostError { .. } => "HostError",
+            RuntimeErrorMessage::UnknownLiteralKind { .. } => "UnregisteredLiteral",
+            RuntimeErrorMessage::LiteralError { .. } => "LiteralError",
+            RuntimeErrorMessage::IntOverflow { .. } => "IntOverflow",
+            RuntimeErrorMessage::ConstantCycle { .. } => "ConstantCycle",
+            RuntimeErrorMessage::Assertion { .. } => "Assertion",
+            RuntimeErrorMessage::NotImplemented { .. } => "NotImplemented",
+        }
+    }
+}
+
+/// Constructor values are shared between their copies, so that `dup` and
+/// the other shuffles are O(1). `Arc` with the `sync` feature, to move
+/// values between threads.
+#[cfg(not(feature = "sync"))]
+pub(crate) type Shared<T> = std::rc::Rc<T>;
+#[cfg(feature = "sync")]
+pub(crate) type Shared<T> = std::sync::Arc<T>;
+
+/// The name of a constructor value, copied without allocating
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ConstrName(Shared<str>);
+
+impl ConstrName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for ConstrName {
+    fn from(name: &str) -> Self {
+        ConstrName(name.into())
+    }
+}
+
+impl Deref for ConstrName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for ConstrName {
+    fn eq(&self, other: &str) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<ConstrName> for String {
+    fn eq(&self, other: &ConstrName) -> bool {
+        *self == *other.0
+    }
+}
+
+impl fmt::Debug for ConstrName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for ConstrName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug)]
+struct FieldValues {
+    values: Vec<Value>,
+    /// `Value::node_count` of the values together
+    nodes: usize,
+}
+
+/// The fields of a constructor value, read like a slice. No fields take no
+/// allocation, so nullary constructors are as cheap as an `Int`.
+#[derive(Clone, Default)]
+pub struct Fields(Option<Shared<FieldValues>>);
+
+impl Fields {
+    /// Whether both are the same fields, not only equal ones
+    fn ptr_eq(&self, other: &Fields) -> bool {
+        match (&self.0, &other.0) {
+            (None, None) => true,
+            (Some(a), Some(b)) => Shared::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    fn node_count(&self) -> usize {
+        self.0.as_ref().map_or(0, |fields| fields.nodes)
+    }
+}
+
+impl From<Vec<Value>> for Fields {
+    fn from(values: Vec<Value>) -> Self {
+        if values.is_empty() {
+            return Fields(None);
+        }
+        let nodes = values.iter().map(Value::node_count).sum();
+        Fields(Some(Shared::new(FieldValues { values, nodes })))
+    }
+}
+
+impl Deref for Fields {
+    type Target = [Value];
+
+    fn deref(&self) -> &[Value] {
+        self.0.as_ref().map_or(&[], |fields| &fields.values)
+    }
+}
+
+/// The values themselves when these are their only copy, copies of them
+/// otherwise, which is O(1) a field
+impl IntoIterator for Fields {
+    type Item = Value;
+    type IntoIter = std::vec::IntoIter<Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let values = match self.0.map(Shared::try_unwrap) {
+            None => vec![],
+            Some(Ok(fields)) => fields.values,
+            Some(Err(shared)) => shared.values.clone(),
+        };
+        values.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Fields {
+    type Item = &'a Value;
+    type IntoIter = std::slice::Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Debug for Fields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Value {
+    Int(i32),
+    /// Build with `Value::constr`
     User {
-        constr_name: String,
-        args: Vec<Value>,
+        constr_name: ConstrName,
+        args: Fields,
     },
     Quoted(Quoted),
 }
 
+impl Value {
+    /// The value of constructor `name` with the fields `args`, topmost
+    /// first
+    pub fn constr(name: impl Into<ConstrName>, args: Vec<Value>) -> Self {
+        Value::User {
+            constr_name: name.into(),
+            args: args.into(),
+        }
+    }
+
+    /// Number of values this value is built from, itself included. Shared
+    /// fields count once for every place they are in.
+    pub fn node_count(&self) -> usize {
+        match self {
+            Value::Int(_) => 1,
+            Value::User { args, .. } => 1 + args.node_count(),
+            Value::Quoted(quoted) => 1 + quoted.node_count(),
+        }
+    }
+
+    /// Whether both are copies of the same value, which makes them equal
+    /// without looking at their fields. False for quotes.
+    pub fn ptr_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (
+                Value::User {
+                    constr_name: name1,
+                    args: args1,
+                },
+                Value::User {
+                    constr_name: name2,
+                    args: args2,
+                },
+            ) => name1 == name2 && args1.ptr_eq(args2),
+            _ => false,
+        }
+    }
+}
+
+/// Ints and constructor values are equal when they are built the same way.
+/// Quotes are never equal, what they compute cannot be compared.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        if self.ptr_eq(other) {
+            return true;
+        }
+        match (self, other) {
+            (
+                Value::User {
+                    constr_name: name1,
+                    args: args1,
+                },
+                Value::User {
+                    constr_name: name2,
+                    args: args2,
+                },
+            ) => name1 == name2 && args1[..] == args2[..],
+            _ => false,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Quoted {
     Sentence { ops: Vec<Op> },
     Value { value: Box<Value> },
     Composed { a: Box<Quoted>, b: Box<Quoted> },
 }
+
+impl Quoted {
+    /// From the first op of the quote to the last one, `None` for a quote
+    /// with no ops of its own
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Quoted::Sentence { ops } => {
+                let (first, last) = (ops.first()?, ops.last()?);
+                Some(Span::new(first.get_span().start, last.get_span().end))
+            }
+            Quoted::Value { .. } => None,
+            Quoted::Composed { a, b } => match (a.span(), b.span()) {
+                (Some(a), Some(b)) => Some(Span::new(a.start.min(b.start), a.end.max(b.end))),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+
+    fn node_count(&self) -> usize {
+        match self {
+            Quoted::Sentence { .. } => 0,
+            Quoted::Value { value } => value.node_count(),
+            // the two composed quotes are still values of their own
+            Quoted::Composed { a, b } => 2 + a.node_count() + b.node_count(),
+        }
+    }
+}
@@ -0,0 +1,585 @@
+//! Formatting some definitions of a module only, so that changing one
+//! definition does not rewrite the whole file. A definition that fits in
+//! `FormatOptions::max_width` is printed on one line the way the module
+//! printer prints it, a longer one is broken over lines, see
+//! `format_definition`. Every byte outside of the formatted definitions is
+//! left as it is, but for the whitespace ending the file.
+
+use crate::refactor::TextEdit;
+use crate::syntax::ast::{overloaded_name, Definition, Module, Op, Span};
+use crate::syntax::highlight::{self, TokenKind};
+use crate::syntax::parse;
+use crate::typing::types::Type;
+use std::iter::once;
+
+/// How definitions are laid out, the `[fmt]` section of a project's
+/// manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The longest a line may be, in characters, before its definition is
+    /// broken over lines. 100 by default.
+    pub max_width: usize,
+    /// The spaces of one level of indentation, 4 by default
+    pub indent: usize,
+    /// Where the body of a broken op or constant starts, `SameLine` by
+    /// default
+    pub annotation_style: AnnotationStyle,
+    /// How the arms of a case broken a line each line up, `Unaligned` by
+    /// default
+    pub case_arm_alignment: CaseArmAlignment,
+    /// A quote of more ops than this is broken over lines when it does not
+    /// fit, a shorter one stays on one line even then. 4 by default.
+    pub quote_break_threshold: usize,
+    /// Whether a formatted file ends with a newline, rather than with no
+    /// whitespace after its last definition. True by default.
+    pub trailing_newline: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            max_width: 100,
+            indent: 4,
+            annotation_style: AnnotationStyle::SameLine,
+            case_arm_alignment: CaseArmAlignment::Unaligned,
+            quote_break_threshold: 4,
+            trailing_newline: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationStyle {
+    /// The body goes on after the colon ending the annotation, as far as it
+    /// fits
+    SameLine,
+    /// The body starts on the line after the annotation
+    NextLine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseArmAlignment {
+    Unaligned,
+    /// The constructors are padded so that the `{` of the arms are in one
+    /// column
+    Braces,
+}
+
+/// The edits formatting the definitions of the module parsed from `src`
+/// that overlap or touch one of the ranges, with the default options
+pub fn format_ranges(src: &str, module: &Module, ranges: &[Span]) -> Vec<TextEdit> {
+    format_ranges_with(src, module, ranges, &FormatOptions::default())
+}
+
+/// The edits formatting the definitions of the module parsed from `src`
+/// that overlap or touch one of the ranges. A definition with a comment in
+/// it is left as it is, since printing it would drop the comment, and so
+/// is one whose printed form would not parse back to itself. A range
+/// reaching past the last definition also has the end of the file made
+/// what `trailing_newline` says.
+pub fn format_ranges_with(
+    src: &str,
+    module: &Module,
+    ranges: &[Span],
+    options: &FormatOptions,
+) -> Vec<TextEdit> {
+    let comments: Vec<_> = highlight::lex(src)
+        .into_iter()
+        .filter(|token| token.kind == TokenKind::Comment)
+        .map(|token| token.span)
+        .collect();
+    let touched = |extent: &Span| {
+        ranges
+            .iter()
+            .any(|range| range.start <= extent.end && extent.start <= range.end)
+    };
+    let commented = |extent: &Span| {
+        comments
+            .iter()
+            .any(|comment| extent.start <= comment.start && comment.end <= extent.end)
+    };
+    module
+        .definitions_in_source_order()
+        .into_iter()
+        .filter_map(|def| {
+            let extent = def.extent();
+            if !touched(&extent) || commented(&extent) {
+                return None;
+            }
+            let formatted = format_definition(&def, options);
+            if src.get(extent.start..extent.end) == Some(&formatted) || !reparses(&def, &formatted)
+            {
+                return None;
+            }
+            Some(TextEdit {
+                span: extent,
+                replacement: formatted,
+            })
+        })
+        .chain(end_of_file(src, ranges, options))
+        .collect()
+}
+
+/// The edit making the whitespace ending the source what the options say,
+/// if a range reaches into it
+fn end_of_file(src: &str, ranges: &[Span], options: &FormatOptions) -> Option<TextEdit> {
+    let content = src.trim_end().len();
+    if content == 0 || ranges.iter().all(|range| range.end < content) {
+        return None;
+    }
+    let replacement = if options.trailing_newline { "\n" } else { "" };
+    (&src[content..] != replacement).then(|| TextEdit {
+        span: Span::new(content, src.len()),
+        replacement: replacement.to_owned(),
+    })
+}
+
+/// The definition as source, with no newline after the `.`. On one line
+/// if it fits, the way `Definition`'s `Display` prints it, else:
+///
+/// - a data definition has a constructor on each line
+/// - the ops of a body fill lines as far as they fit, a case that does
+///   not fit has an arm on each line, and so does a quote of more ops than
+///   `quote_break_threshold`
+///
+/// The attributes and pragmas are a line each before the definition
+/// either way.
+pub fn format_definition(def: &Definition, options: &FormatOptions) -> String {
+    let flat = def.to_string();
+    if flat.lines().all(|line| width(line) <= options.max_width) {
+        return flat;
+    }
+    let mut printer = Printer {
+        options,
+        out: String::new(),
+        column: 0,
+        line_depth: 0,
+        fresh: true,
+    };
+    let (attributes, pragmas) = match def {
+        Definition::Data(_, d) => (&d.attributes[..], &d.pragmas[..]),
+        Definition::Op(_, o) => (&o.attributes[..], &o.pragmas[..]),
+        Definition::Const(..) => (&[][..], &[][..]),
+    };
+    for marker in attributes
+        .iter()
+        .map(ToString::to_string)
+        .chain(pragmas.iter().map(ToString::to_string))
+    {
+        printer.push(&marker);
+        printer.newline(0);
+    }
+    match def {
+        Definition::Data(name, data_def) => {
+            let params: String = data_def.params.iter().map(|p| format!(" {}", p)).collect();
+            printer.push(&format!("data {}{}:", name, params));
+            let constrs = data_def.constrs_in_source_order();
+            for (i, (constr_name, constr)) in constrs.iter().enumerate() {
+                let mut text = String::new();
+                for attribute in &constr.attributes {
+                    text.push_str(&format!("{} ", attribute));
+                }
+                if !constr.params.is_empty() {
+                    let params: Vec<_> = constr.params.iter().map(Type::to_string).collect();
+                    text.push_str(&format!("[{}] ", params.join(", ")));
+                }
+                text.push_str(constr_name);
+                text.push(if i + 1 == constrs.len() { '.' } else { ',' });
+                printer.newline(1);
+                printer.push(&text);
+            }
+        }
+        Definition::Op(name, op_def) => {
+            let stack = |stack: &[Type]| {
+                let stack: Vec<_> = stack.iter().map(Type::to_string).collect();
+                stack.join(", ")
+            };
+            printer.push(&format!(
+                "define [{}] {} [{}]",
+                stack(&op_def.ann.pre),
+                overloaded_name(name),
+                stack(&op_def.ann.post)
+            ));
+            if op_def.declaration {
+                printer.push(".");
+            } else {
+                printer.body(&op_def.body);
+            }
+        }
+        Definition::Const(name, const_def) => {
+            printer.push(&format!("const {} [{}]", name, const_def.ty));
+            printer.body(&const_def.body);
+        }
+    }
+    printer.out
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+fn flat_ops(ops: &[Op]) -> String {
+    let ops: Vec<_> = ops.iter().map(Op::to_string).collect();
+    ops.join(" ")
+}
+
+struct Printer<'o> {
+    options: &'o FormatOptions,
+    out: String,
+    column: usize,
+    /// The indentation depth of the line being printed
+    line_depth: usize,
+    /// Whether nothing is on the line but its indentation
+    fresh: bool,
+}
+
+impl Printer<'_> {
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.column += width(text);
+        self.fresh = false;
+    }
+
+    fn newline(&mut self, depth: usize) {
+        let indent = depth * self.options.indent;
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(indent));
+        self.column = indent;
+        self.line_depth = depth;
+        self.fresh = true;
+    }
+
+    /// Whether the text fits on the line, with `end` more characters after
+    fn fits(&self, text: &str, end: usize) -> bool {
+        self.column + width(text) + end <= self.options.max_width
+    }
+
+    /// The colon, the ops and the `.` ending an op or a constant
+    fn body(&mut self, body: &[Op]) {
+        self.push(":");
+        if !body.is_empty() {
+            if self.options.annotation_style == AnnotationStyle::NextLine {
+                self.newline(1);
+            }
+            self.ops(body, 1, 1);
+        }
+        self.push(".");
+    }
+
+    /// The ops from where the printer is, lines they go on to indented by
+    /// `depth`, with `end` characters to follow the last one
+    fn ops(&mut self, ops: &[Op], depth: usize, end: usize) {
+        for (i, op) in ops.iter().enumerate() {
+            let end = if i + 1 == ops.len() { end } else { 0 };
+            let flat = op.to_string();
+            let sep = if self.fresh { "" } else { " " };
+            if self.fits(&format!("{}{}", sep, flat), end) {
+                self.push(sep);
+                self.push(&flat);
+                continue;
+            }
+            let fits_alone =
+                depth * self.options.indent + width(&flat) + end <= self.options.max_width;
+            match self.opener(op) {
+                Some(opener) if !fits_alone || self.fresh => {
+                    if self.fits(&format!("{}{}", sep, opener), 0) {
+                        self.push(sep);
+                    } else if !self.fresh {
+                        self.newline(depth);
+                    }
+                    self.broken(op);
+                }
+                _ => {
+                    if !self.fresh {
+                        self.newline(depth);
+                    }
+                    self.push(&flat);
+                }
+            }
+        }
+    }
+
+    /// What starts the op when it is broken over lines, none for an op
+    /// only printed on one line
+    fn opener(&self, op: &Op) -> Option<&'static str> {
+        match op {
+            Op::Quote { value, .. } if value.len() > self.options.quote_break_threshold => {
+                Some("(")
+            }
+            Op::Case { .. } => Some("case {"),
+            Op::Case2 { .. } => Some("case2 {"),
+            _ => None,
+        }
+    }
+
+    /// The op over lines, its parts indented one more than the line it
+    /// starts on and the line ending it like that line
+    fn broken(&mut self, op: &Op) {
+        let depth = self.line_depth;
+        match op {
+            Op::Quote { value, .. } => {
+                self.push("(");
+                self.newline(depth + 1);
+                self.ops(value, depth + 1, 0);
+                self.newline(depth);
+                self.push(")");
+            }
+            Op::Case { head_arm, arms, .. } => {
+                let arms: Vec<_> = once(head_arm)
+                    .chain(arms)
+                    .map(|arm| (arm.constr.clone(), &arm.body[..]))
+                    .collect();
+                self.arms("case {", &arms, depth);
+            }
+            Op::Case2 { arms, .. } => {
+                let arms: Vec<_> = arms
+                    .iter()
+                    .map(|arm| {
+                        let [p1, p2] = &arm.patterns;
+                        (format!("{} {}", p1, p2), &arm.body[..])
+                    })
+                    .collect();
+                self.arms("case2 {", &arms, depth);
+            }
+            _ => self.push(&op.to_string()),
+        }
+    }
+
+    /// The arms of a case, a line each, each given as what it matches and
+    /// its body
+    fn arms(&mut self, opener: &str, arms: &[(String, &[Op])], depth: usize) {
+        self.push(opener);
+        let pad = match self.options.case_arm_alignment {
+            CaseArmAlignment::Unaligned => 0,
+            CaseArmAlignment::Braces => arms
+                .iter()
+                .map(|(label, _)| width(label))
+                .max()
+                .unwrap_or(0),
+        };
+        for (i, (label, body)) in arms.iter().enumerate() {
+            let last = i + 1 == arms.len();
+            let end = if last { 0 } else { 1 };
+            let label = format!("{:<pad$}", label);
+            self.newline(depth + 1);
+            if body.is_empty() {
+                self.push(&format!("{} {{}}", label));
+            } else {
+                let flat = format!("{} {{ {} }}", label, flat_ops(body));
+                if self.fits(&flat, end) {
+                    self.push(&flat);
+                } else {
+                    self.push(&format!("{} {{", label));
+                    self.newline(depth + 2);
+                    self.ops(body, depth + 2, 0);
+                    self.newline(depth + 1);
+                    self.push("}");
+                }
+            }
+            if !last {
+                self.push(",");
+            }
+        }
+        self.newline(depth);
+        self.push("}");
+    }
+}
+
+/// Whether the printed definition parses to a definition printed the same
+fn reparses(def: &Definition, formatted: &str) -> bool {
+    let Ok(module) = parse(formatted) else {
+        return false;
+    };
+    match module.definitions_in_source_order()[..] {
+        [parsed] => parsed.to_string() == def.to_string(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "data   Maybe a :nothing,[a]just .
+
+
+define [Maybe a,a]   or-else [a]:
+    case {   just { br-1   pop },
+       nothing {}
+    }.
+#[unchecked]
+//@ expect-type: [][Int]
+define  []   one [Int]  :   .
+const   none [Maybe Int]:nothing .
+define [] main [Int]: 1   none // keeps the comment
+  or-else.
+";
+
+    fn format(ranges: &[Span]) -> String {
+        let module = parse(SOURCE).unwrap();
+        TextEdit::apply_all(SOURCE, &format_ranges(SOURCE, &module, ranges))
+    }
+
+    /// The span of the lines, numbered from 1
+    fn lines(from: usize, to: usize) -> Span {
+        let starts: Vec<_> = std::iter::once(0)
+            .chain(SOURCE.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Span::new(starts[from - 1], starts[to] - 1)
+    }
+
+    #[test]
+    fn only_the_touched_definitions_change() {
+        // a line inside `or-else`
+        let formatted = format(&[lines(6, 6)]);
+        let or_else = "define [Maybe a, a] or-else [a]: \
+                       case { just { br-1 pop }, nothing {} }.";
+        let start = SOURCE.find("define [Maybe").unwrap();
+        let end = SOURCE.find("}.").unwrap() + 2;
+        assert_eq!(
+            formatted,
+            format!("{}{}{}", &SOURCE[..start], or_else, &SOURCE[end..])
+        );
+
+        // with its markers, the range only touching the attribute
+        let formatted = format(&[lines(8, 8)]);
+        assert_eq!(
+            formatted,
+            SOURCE.replace("define  []   one [Int]  :   .", "define [] one [Int]:.")
+        );
+    }
+
+    #[test]
+    fn whole_files_keep_comments_and_what_is_between_definitions() {
+        let formatted = format(&[Span::new(0, SOURCE.len())]);
+        assert_eq!(
+            formatted,
+            "data Maybe a: nothing, [a] just.
+
+
+define [Maybe a, a] or-else [a]: case { just { br-1 pop }, nothing {} }.
+#[unchecked]
+//@ expect-type: [][Int]
+define [] one [Int]:.
+const none [Maybe Int]: nothing.
+define [] main [Int]: 1   none // keeps the comment
+  or-else.
+"
+        );
+        let module = parse(&formatted).unwrap();
+        assert!(format_ranges(&formatted, &module, &[Span::new(0, formatted.len())]).is_empty());
+        assert_eq!(module.to_string(), parse(SOURCE).unwrap().to_string());
+        assert!(format_ranges(SOURCE, &parse(SOURCE).unwrap(), &[]).is_empty());
+    }
+
+    #[test]
+    fn long_definitions_break_over_lines() {
+        let src = "data Shape: [Int] circle, [Int, Int] rect, point.
+define [Maybe a, a] or-else [a]: case { just { br-1 pop }, nothing {} }.
+define [Int] many [Int, Int]: dup (dup dup pop pop pop) pop (1 2) pop dup dup pop.
+";
+        let module = parse(src).unwrap();
+        let formatted = |options: &FormatOptions| {
+            let defs: Vec<_> = module
+                .definitions_in_source_order()
+                .iter()
+                .map(|def| format_definition(def, options))
+                .collect();
+            defs.join("\n")
+        };
+        assert_eq!(
+            formatted(&FormatOptions {
+                max_width: 40,
+                ..FormatOptions::default()
+            }),
+            "data Shape:
+    [Int] circle,
+    [Int, Int] rect,
+    point.
+define [Maybe a, a] or-else [a]: case {
+    just { br-1 pop },
+    nothing {}
+}.
+define [Int] many [Int, Int]: dup
+    (dup dup pop pop pop) pop (1 2) pop
+    dup dup pop."
+        );
+        assert_eq!(
+            formatted(&FormatOptions {
+                max_width: 30,
+                indent: 2,
+                annotation_style: AnnotationStyle::NextLine,
+                case_arm_alignment: CaseArmAlignment::Braces,
+                quote_break_threshold: 5,
+                ..FormatOptions::default()
+            }),
+            "data Shape:
+  [Int] circle,
+  [Int, Int] rect,
+  point.
+define [Maybe a, a] or-else [a]:
+  case {
+    just    { br-1 pop },
+    nothing {}
+  }.
+define [Int] many [Int, Int]:
+  dup (dup dup pop pop pop)
+  pop (1 2) pop dup dup pop."
+        );
+        let quotes = formatted(&FormatOptions {
+            max_width: 20,
+            quote_break_threshold: 2,
+            ..FormatOptions::default()
+        });
+        assert!(quotes.ends_with(
+            "
+define [Int] many [Int, Int]:
+    dup (
+        dup dup pop
+        pop pop
+    ) pop (1 2) pop
+    dup dup pop."
+        ));
+    }
+
+    #[test]
+    fn the_end_of_the_file_follows_the_options() {
+        let src = "define [] one [Int]:   1.\n\n\n";
+        let module = parse(src).unwrap();
+        let format = |options: &FormatOptions, ranges: &[Span]| {
+            TextEdit::apply_all(src, &format_ranges_with(src, &module, ranges, options))
+        };
+        let whole = [Span::new(0, src.len())];
+        let options = FormatOptions::default();
+        assert_eq!(format(&options, &whole), "define [] one [Int]: 1.\n");
+        // a range ending in the definition leaves the end alone
+        assert_eq!(
+            format(&options, &[Span::new(0, 5)]),
+            "define [] one [Int]: 1.\n\n\n"
+        );
+        let options = FormatOptions {
+            trailing_newline: false,
+            ..options
+        };
+        assert_eq!(format(&options, &whole), "define [] one [Int]: 1.");
+    }
+
+    #[test]
+    fn indices_stay_before_their_ops() {
+        let src = "define [a, b, c] f [c, c, a, b]:   2
+    pick
+  3   roll.
+";
+        let module = parse(src).unwrap();
+        let edits = format_ranges(src, &module, &[Span::new(0, src.len())]);
+        let formatted = TextEdit::apply_all(src, &edits);
+        assert_eq!(
+            formatted,
+            "define [a, b, c] f [c, c, a, b]: 2 pick 3 roll.\n"
+        );
+        let module = parse(&formatted).unwrap();
+        assert!(crate::typing::inference::Inference::new(&module)
+            .typecheck()
+            .is_ok());
+    }
+}
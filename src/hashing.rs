@@ -0,0 +1,438 @@
+//! Hashes of definitions that only change when what they mean does, for
+//! caches and anything else keyed by the content of a definition.
+//!
+//! The scheme, version `HASH_VERSION`: a definition is hashed in its
+//! canonical form, desugared and printed without spans, comments or layout,
+//! type variables renamed in the order they are first seen. The hash of an
+//! op adds, after the scheme and prelude versions and its own form, the
+//! kind, name and form of every definition it depends on, in name order:
+//! the ops it calls, also in quotes, the data definitions of the types and
+//! constructors it names and the constants it pushes, and in turn theirs.
+//! Its own name is not part of it, the names it refers to are. Each part is
+//! fed to 64 bit FNV-1a followed by a separator byte.
+
+use crate::desugar::desugar;
+use crate::syntax::ast::*;
+use crate::typing::cache::{body_names, type_names};
+use crate::typing::prelude_types::{self, PRELUDE_VERSION};
+use crate::typing::types::{Normalizer, Type};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::iter::once;
+
+/// Bumped whenever the hash of some definition changes without the
+/// definition changing
+pub const HASH_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hash(pub u64);
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// 64 bit FNV-1a, stable across runs and Rust versions unlike the std
+/// hashers
+pub(crate) struct Fnv(pub u64);
+
+impl Fnv {
+    pub fn new() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    /// Adds the string and a separator, so that `ab` `c` and `a` `bc`
+    /// hash apart
+    pub fn add(&mut self, s: &str) {
+        for byte in s.bytes().chain(once(0xff)) {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+impl Module {
+    /// The hash of the op and everything it depends on, `None` if there is
+    /// no such op
+    pub fn op_hash(&self, name: &str) -> Option<Hash> {
+        let module = canonical_module(self);
+        let op_def = module.op_defs.get(name)?;
+        let mut hash = versioned("iv-op");
+        hash.add(&op_form(op_def));
+        for def in dependencies(&module, name) {
+            hash.add(def.kind());
+            hash.add(def.name());
+            hash.add(&form(&def));
+        }
+        Some(Hash(hash.0))
+    }
+
+    /// The hash of every definition of the module, whatever their order
+    pub fn module_hash(&self) -> Hash {
+        let module = canonical_module(self);
+        let mut defs = module.definitions_in_source_order();
+        defs.sort_by(|d1, d2| (d1.kind(), d1.name()).cmp(&(d2.kind(), d2.name())));
+        let mut hash = versioned("iv-module");
+        for def in defs {
+            hash.add(def.kind());
+            hash.add(def.name());
+            hash.add(&form(&def));
+        }
+        Hash(hash.0)
+    }
+}
+
+impl Definition<'_> {
+    fn kind(&self) -> &'static str {
+        match self {
+            Definition::Data(..) => "data",
+            Definition::Op(..) => "define",
+            Definition::Const(..) => "const",
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Definition::Data(name, _) | Definition::Op(name, _) | Definition::Const(name, _) => {
+                name
+            }
+        }
+    }
+}
+
+fn versioned(scheme: &str) -> Fnv {
+    let mut hash = Fnv::new();
+    hash.add(scheme);
+    hash.add(&HASH_VERSION.to_string());
+    hash.add(&PRELUDE_VERSION.to_string());
+    hash
+}
+
+/// The module with its surface constructs desugared, as it is if that fails
+/// so that a module with errors still hashes
+fn canonical_module(module: &Module) -> Module {
+    desugar(module.clone()).unwrap_or_else(|_| module.clone())
+}
+
+fn form(def: &Definition) -> String {
+    match def {
+        Definition::Data(_, data_def) => data_form(data_def),
+        Definition::Op(_, op_def) => op_form(op_def),
+        Definition::Const(_, const_def) => {
+            format!(
+                "[{}] {}",
+                const_def.ty.canonical(),
+                body_form(&const_def.body)
+            )
+        }
+    }
+}
+
+/// Parameters renamed by position, constructors in tag order
+fn data_form(data_def: &DataDef) -> String {
+    let mut normalizer = Normalizer::default();
+    for param in data_def.params.iter() {
+        normalizer.ty(&Type::Poly(param.clone()));
+    }
+    let constrs: Vec<_> = data_def
+        .constrs_in_source_order()
+        .into_iter()
+        .map(|(name, constr)| {
+            let params: Vec<_> = constr
+                .params
+                .iter()
+                .map(|t| normalizer.ty(t).to_string())
+                .collect();
+            format!("[{}] {}", params.join(", "), name)
+        })
+        .collect();
+    format!(
+        "{} {}: {}",
+        markers(&data_def.attributes, &data_def.pragmas),
+        data_def.params.len(),
+        constrs.join(", ")
+    )
+}
+
+fn op_form(op_def: &OpDef) -> String {
+    let markers = markers(&op_def.attributes, &op_def.pragmas);
+    if op_def.declaration {
+        return format!("{} {}.", markers, op_def.ann.canonical());
+    }
+    format!(
+        "{} {}: {}",
+        markers,
+        op_def.ann.canonical(),
+        body_form(&op_def.body)
+    )
+}
+
+fn markers(attributes: &[Attribute], pragmas: &[Pragma]) -> String {
+    let markers: Vec<_> = attributes
+        .iter()
+        .map(Attribute::to_string)
+        .chain(pragmas.iter().map(|pragma| match &pragma.kind {
+            PragmaKind::ExpectType(op_type) => {
+                format!("//@ expect-type: {}", op_type.canonical())
+            }
+            _ => pragma.to_string(),
+        }))
+        .collect();
+    markers.join(" ")
+}
+
+/// Printed ops have no spans and a single layout, an integer literal
+/// prints the same however it is written
+fn body_form(body: &[Op]) -> String {
+    let ops: Vec<_> = body.iter().map(Op::to_string).collect();
+    ops.join(" ")
+}
+
+/// The definitions the op depends on, directly or not, in name order. Names
+/// resolve the way inference resolves them without extern ops: to a
+/// constructor, then a prelude op, then a user op or constant.
+fn dependencies<'m>(module: &'m Module, root: &str) -> Vec<Definition<'m>> {
+    let data_of = |name: &str| {
+        module
+            .data_defs
+            .iter()
+            .find(|(_, data_def)| data_def.constrs.contains_key(name))
+            .map(|(data, data_def)| Definition::Data(data, data_def))
+    };
+    let resolve = |name: &str, is_type: bool| -> Option<Definition<'m>> {
+        if is_type {
+            let (data, data_def) = module.data_defs.get_key_value(name)?;
+            return Some(Definition::Data(data, data_def));
+        }
+        if let Some(def) = data_of(name) {
+            return Some(def);
+        }
+        if prelude_types::get(name).is_some() {
+            return None;
+        }
+        if let Some((op, op_def)) = module.op_defs.get_key_value(name) {
+            return Some(Definition::Op(op, op_def));
+        }
+        let (constant, const_def) = module.const_defs.get_key_value(name)?;
+        Some(Definition::Const(constant, const_def))
+    };
+    let mut seen = BTreeSet::new();
+    let mut found = vec![];
+    let mut todo = vec![];
+    if let Some((op, op_def)) = module.op_defs.get_key_value(root) {
+        todo.push(Definition::Op(op, op_def));
+    }
+    while let Some(def) = todo.pop() {
+        let mut types = BTreeSet::new();
+        let mut names = BTreeSet::new();
+        match def {
+            Definition::Data(_, data_def) => {
+                for constr in data_def.constrs.values() {
+                    for t in constr.params.iter() {
+                        type_names(t, &mut types);
+                    }
+                }
+            }
+            Definition::Op(_, op_def) => {
+                for t in op_def.ann.pre.iter().chain(&op_def.ann.post) {
+                    type_names(t, &mut types);
+                }
+                body_names(&op_def.body, &mut names);
+            }
+            Definition::Const(_, const_def) => {
+                type_names(&const_def.ty, &mut types);
+                body_names(&const_def.body, &mut names);
+            }
+        }
+        let referenced = types
+            .into_iter()
+            .filter_map(|name| resolve(name, true))
+            .chain(names.into_iter().filter_map(|name| resolve(name, false)));
+        for dep in referenced {
+            let key = (dep.kind(), dep.name().to_owned());
+            if key != ("define", root.to_owned()) && seen.insert(key) {
+                found.push(dep);
+                todo.push(dep);
+            }
+        }
+    }
+    found.sort_by(|d1, d2| (d1.name(), d1.kind()).cmp(&(d2.name(), d2.kind())));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::call_graph::CallGraph;
+    use crate::syntax::parse;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::Path;
+
+    const BASE: &str = "
+        data Nat: zero, [Nat] succ.
+        data Bool: true, false.
+        define [a] double [a, a]: dup.
+        define [a] four [a, a, a, a]: double br-1 double.
+        define [Nat] is-zero [Bool]: case { zero { true }, succ { pop false } }.
+        define [Bool] not [Bool]: case { true { false }, false { true } }.
+        const two [Int]: 2.
+        define [] big [Int, Int, Int, Int]: two four.
+        define [a] id [a]:.
+        define [Nat] main [Bool, Int]: dup (is-zero) exec-1-1 not br-1 pop big.
+    ";
+
+    fn hashes(source: &str) -> Vec<(String, Hash)> {
+        let module = parse(source).unwrap();
+        let mut hashes: Vec<_> = module
+            .op_defs
+            .keys()
+            .map(|op| (op.clone(), module.op_hash(op).unwrap()))
+            .collect();
+        hashes.sort();
+        hashes
+    }
+
+    #[test]
+    fn layout_comments_and_variable_names_do_not_count() {
+        let reformatted = "
+            // the naturals
+            data   Nat :zero,[Nat]succ.
+            data Bool: true, false.
+            define [b] double [b, b]:
+                dup   .
+            define [a] four [a, a, a, a]: double // twice
+                br-1 double.
+            define [Nat] is-zero [Bool]:
+                case { zero { true },
+                       succ { pop false } }.
+            define [Bool] not [Bool]: case { true { false }, false { true } }.
+            define [] big [Int, Int, Int, Int]: two four.
+            const two [Int]: 2.
+            define [x] id [x]:.
+            define [Nat] main [Bool, Int]: dup ( is-zero ) exec-1-1 not br-1 pop big.
+        ";
+        assert_eq!(hashes(reformatted), hashes(BASE));
+        assert_eq!(
+            parse(reformatted).unwrap().module_hash(),
+            parse(BASE).unwrap().module_hash()
+        );
+        let module = parse(BASE).unwrap();
+        assert_eq!(module.op_hash("zero"), None);
+        assert_ne!(module.op_hash("double"), module.op_hash("four"));
+    }
+
+    #[test]
+    fn list_literals_hash_as_what_they_desugar_to() {
+        let module = parse(
+            "data List a: empty, [List a, a] cons.
+             define [] listed [List Int]: [1, 2].
+             define [] consed [List Int]: empty 2 cons 1 cons.",
+        )
+        .unwrap();
+        assert_eq!(module.op_hash("listed"), module.op_hash("consed"));
+    }
+
+    #[test]
+    fn printing_and_parsing_fixtures_keeps_their_hashes() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for dir in ["examples", "tests/cases"] {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                let source = fs::read_to_string(&path).unwrap();
+                let Some(module) = path
+                    .extension()
+                    .filter(|ext| *ext == "iv")
+                    .and_then(|_| parse(&source).ok())
+                else {
+                    continue;
+                };
+                let reparsed = parse(&module.to_string()).unwrap();
+                assert_eq!(
+                    reparsed.module_hash(),
+                    module.module_hash(),
+                    "{}",
+                    path.display()
+                );
+                for op in module.op_defs.keys() {
+                    assert_eq!(
+                        reparsed.op_hash(op),
+                        module.op_hash(op),
+                        "{}: {}",
+                        path.display(),
+                        op
+                    );
+                }
+            }
+        }
+    }
+
+    /// Each edit replaces some text of `BASE`, and lists the ops it changes
+    /// or whose bodies and annotations use a changed data definition or
+    /// constant directly. Every op calling one of them changes with them.
+    const EDITS: [(&str, &str, &[&str]); 8] = [
+        ("dup.", "dup dup pop.", &["double"]),
+        ("pop false", "pop true", &["is-zero"]),
+        ("true { false }", "true { true }", &["not"]),
+        ("const two [Int]: 2.", "const two [Int]: 3.", &["big"]),
+        ("[Nat] succ.", "[Nat] succ, many.", &["is-zero", "main"]),
+        (
+            "data Bool: true, false.",
+            "data Bool: false, true.",
+            &["is-zero", "not", "main"],
+        ),
+        (
+            "define [a] id [a]:.",
+            "define [a] id [a]: dup pop.",
+            &["id"],
+        ),
+        (
+            "define [] big [Int, Int, Int, Int]: two four.",
+            "#[unchecked]\ndefine [] big [Int, Int, Int, Int]: two four.",
+            &["big"],
+        ),
+    ];
+
+    #[test]
+    fn edits_change_the_edited_ops_and_their_callers_only() {
+        let base = hashes(BASE);
+        let module = parse(BASE).unwrap();
+        let graph = CallGraph::new(&module);
+        for (from, to, touched) in EDITS {
+            assert!(BASE.contains(from), "{}", from);
+            let edited = BASE.replace(from, to);
+            let expected: HashSet<_> = graph
+                .ops()
+                .iter()
+                .filter(|op| {
+                    touched.contains(op)
+                        || graph
+                            .reachable(&[op], true)
+                            .iter()
+                            .any(|callee| touched.contains(callee))
+                })
+                .collect();
+            let changed: HashSet<_> = hashes(&edited)
+                .iter()
+                .zip(&base)
+                .filter(|(after, before)| after != before)
+                .map(|((op, _), _)| graph.ops().iter().find(|o| *o == op).unwrap())
+                .collect();
+            assert_eq!(changed, expected, "{} => {}", from, to);
+            assert_ne!(parse(&edited).unwrap().module_hash(), module.module_hash());
+        }
+    }
+
+    #[test]
+    fn renaming_an_op_changes_its_callers_only() {
+        let renamed = BASE.replace("four", "quadruple");
+        let module = parse(&renamed).unwrap();
+        let base = parse(BASE).unwrap();
+        assert_eq!(module.op_hash("quadruple"), base.op_hash("four"));
+        assert_eq!(module.op_hash("double"), base.op_hash("double"));
+        assert_ne!(module.op_hash("big"), base.op_hash("big"));
+        assert_ne!(module.op_hash("main"), base.op_hash("main"));
+        assert_ne!(module.module_hash(), base.module_hash());
+    }
+}
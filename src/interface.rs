@@ -0,0 +1,417 @@
+//! Interface files, `.ivi`: what other modules need of a module to
+//! typecheck against it, without its source. An interface is iv source
+//! itself, after a header giving its format version:
+//!
+//! ```text
+//! // iv interface 1
+//! data Maybe a: nothing, [a] just.
+//! const none [Maybe Int]:.
+//! define [Maybe a, a] or-else [a]:.
+//! ```
+//!
+//! The data definitions are whole, the constants and ops only have their
+//! types, canonical and without bodies. Definitions are ordered by name so
+//! that the same module always gives the same interface.
+
+use crate::syntax::ast::{DataDef, Module};
+use crate::syntax::{parse, ParseError};
+use crate::typing::inference::Inference;
+use crate::typing::report::{OpOutcome, TypecheckReport};
+use crate::typing::types::{OpType, Type};
+use crate::typing::unify::TypeOrderError;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// The format version `emit` writes and `load` takes
+pub const VERSION: u32 = 1;
+
+const HEADER: &str = "// iv interface ";
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum InterfaceError<'input> {
+    /// The text does not start with the header
+    NoHeader,
+    /// The header gives another version than `VERSION`
+    Version {
+        found: String,
+    },
+    Parse(ParseError<'input>),
+}
+
+impl fmt::Display for InterfaceError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterfaceError::NoHeader => {
+                write!(
+                    f,
+                    "not an interface, the first line is not `{}{}`",
+                    HEADER, VERSION
+                )
+            }
+            InterfaceError::Version { found } => write!(
+                f,
+                "the interface is of format version {}, this iv reads version {}\n\
+                 emit the interface again from the module's source",
+                found, VERSION
+            ),
+            InterfaceError::Parse(err) => write!(f, "the interface does not parse: {:?}", err),
+        }
+    }
+}
+
+/// The definitions of an interface
+#[derive(Debug, Clone)]
+pub struct InterfaceModule {
+    pub data_defs: HashMap<String, DataDef>,
+    pub const_types: BTreeMap<String, Type>,
+    pub op_types: BTreeMap<String, OpType>,
+}
+
+/// How a newer interface of a module breaks a client checked against an
+/// older one
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incompatibility {
+    /// The constant or op is gone
+    Removed { name: String },
+    /// The older type is not an instance of the newer one, a client may use
+    /// the constant or op at a type it no longer has
+    Narrowed { name: String, error: TypeOrderError },
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Incompatibility::Removed { name } => write!(f, "`{}` was removed", name),
+            Incompatibility::Narrowed { name, error } => {
+                write!(f, "`{}` became less general: {}", name, error)
+            }
+        }
+    }
+}
+
+fn stack(types: &[Type]) -> String {
+    let types: Vec<_> = types.iter().map(Type::to_string).collect();
+    types.join(", ")
+}
+
+/// The interface of the module, whose report says which ops have the types
+/// of their annotations. The ops that failed to check, expectedly or not,
+/// are left out.
+pub fn emit(module: &Module, report: &TypecheckReport) -> String {
+    let mut out = format!("{}{}\n", HEADER, VERSION);
+    let mut data_defs: Vec<_> = module.data_defs.iter().collect();
+    data_defs.sort_by_key(|(name, _)| *name);
+    for (name, data_def) in data_defs {
+        out.push_str(&format!("data {}", name));
+        for param in data_def.params.iter() {
+            out.push_str(&format!(" {}", param));
+        }
+        out.push(':');
+        for (i, (constr_name, constr)) in data_def.constrs_in_source_order().into_iter().enumerate()
+        {
+            out.push_str(if i > 0 { ", " } else { " " });
+            if !constr.params.is_empty() {
+                out.push_str(&format!("[{}] ", stack(&constr.params)));
+            }
+            out.push_str(constr_name);
+        }
+        out.push_str(".\n");
+    }
+    let mut const_defs: Vec<_> = module.const_defs.iter().collect();
+    const_defs.sort_by_key(|(name, _)| *name);
+    for (name, const_def) in const_defs {
+        out.push_str(&format!(
+            "const {} [{}]:.\n",
+            name,
+            const_def.ty.canonical()
+        ));
+    }
+    let checked: Vec<_> = report
+        .ops
+        .iter()
+        .filter(|op| {
+            matches!(
+                op.outcome,
+                OpOutcome::Inferred(_) | OpOutcome::Unchecked | OpOutcome::Declared
+            )
+        })
+        .map(|op| &op.name)
+        .collect();
+    let mut op_defs: Vec<_> = module
+        .op_defs
+        .iter()
+        .filter(|(name, _)| checked.contains(name))
+        .collect();
+    op_defs.sort_by_key(|(name, _)| *name);
+    for (name, op_def) in op_defs {
+        let OpType { pre, post } = op_def.ann.canonical();
+        out.push_str(&format!(
+            "define [{}] {} [{}]:.\n",
+            stack(&pre),
+            name,
+            stack(&post)
+        ));
+    }
+    out
+}
+
+/// The interface `emit` wrote. Bodies are ignored, each constant and op
+/// has the type it is written with.
+pub fn load(text: &str) -> Result<InterfaceModule, InterfaceError<'_>> {
+    let first = text.lines().next().unwrap_or("");
+    let Some(version) = first.strip_prefix(HEADER) else {
+        return Err(InterfaceError::NoHeader);
+    };
+    if version.trim() != VERSION.to_string() {
+        return Err(InterfaceError::Version {
+            found: version.trim().to_owned(),
+        });
+    }
+    let module = parse(text).map_err(InterfaceError::Parse)?;
+    Ok(InterfaceModule {
+        data_defs: module.data_defs,
+        const_types: module
+            .const_defs
+            .into_iter()
+            .map(|(name, const_def)| (name, const_def.ty))
+            .collect(),
+        op_types: module
+            .op_defs
+            .into_iter()
+            .map(|(name, op_def)| (name, op_def.ann))
+            .collect(),
+    })
+}
+
+impl InterfaceModule {
+    /// Adds the interface's data definitions to the module, which cases on
+    /// their constructors need. A data type the module defines itself is
+    /// kept.
+    pub fn link(&self, module: &mut Module) {
+        for (name, data_def) in self.data_defs.iter() {
+            module
+                .data_defs
+                .entry(name.clone())
+                .or_insert_with(|| data_def.clone());
+        }
+    }
+
+    /// What a client checked against this interface may rely on that
+    /// `newer` no longer gives, by name, constants before ops. A type may
+    /// become more general, an op may stop reaching values it passed
+    /// through. Data definitions are not compared.
+    pub fn incompatibilities(&self, newer: &InterfaceModule) -> Vec<Incompatibility> {
+        let removed = |name: &String| Incompatibility::Removed { name: name.clone() };
+        let narrowed = |name: &String, error| Incompatibility::Narrowed {
+            name: name.clone(),
+            error,
+        };
+        let consts = self.const_types.iter().filter_map(|(name, ty)| {
+            let Some(newer_ty) = newer.const_types.get(name) else {
+                return Some(removed(name));
+            };
+            Type::instance_of(newer_ty, ty)
+                .err()
+                .map(|error| narrowed(name, error))
+        });
+        let ops = self.op_types.iter().filter_map(|(name, optype)| {
+            let Some(newer_optype) = newer.op_types.get(name) else {
+                return Some(removed(name));
+            };
+            OpType::instance_of(&passing_through(newer_optype, optype), optype)
+                .err()
+                .map(|error| narrowed(name, error))
+        });
+        consts.chain(ops).collect()
+    }
+
+    /// Declares the interface's constants and ops as extern ops of the
+    /// inference, of the module `link` was given
+    pub fn declare<'m>(&self, inference: Inference<'m>) -> Inference<'m> {
+        let consts = self.const_types.iter().map(|(name, ty)| {
+            let optype = OpType {
+                pre: vec![],
+                post: vec![ty.clone()],
+            };
+            (name, optype)
+        });
+        let ops = self
+            .op_types
+            .iter()
+            .map(|(name, optype)| (name, optype.clone()));
+        consts
+            .chain(ops)
+            .fold(inference, |inference, (name, optype)| {
+                inference.with_extern_op(name, optype)
+            })
+    }
+}
+
+/// The op type with values added below both stacks until one of them is
+/// as deep as the other type's, values an op that does not reach them
+/// passes through
+fn passing_through(optype: &OpType, other: &OpType) -> OpType {
+    let pre = other.pre.len().saturating_sub(optype.pre.len());
+    let post = other.post.len().saturating_sub(optype.post.len());
+    let mut padded = optype.clone();
+    for i in 0..pre.min(post) {
+        let passed = Type::Poly(format!("_passed_{}", i));
+        padded.pre.push(passed.clone());
+        padded.post.push(passed);
+    }
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::report::OpReport;
+
+    const LIBRARY: &str = "
+        data Maybe a: nothing, [a] just.
+        data Pair a b: [a, b] pair.
+        const none [Maybe Int]: nothing.
+        define [Maybe x, x] or-else [x]:
+            case { just { br-1 pop }, nothing { } }.
+        define [a, b] swap [b, a]: br-1.
+        #[unchecked]
+        define [Int, Int] add [Int]:.
+        define [Int] negate [Int].
+        define [a] broken [Maybe a]: 1.
+    ";
+
+    const CLIENT: &str = "
+        define [] main [Int]: 1 none or-else.
+        define [Maybe Int] get [Int]: case { just { negate 1 add }, nothing { 0 } }.
+        define [a, b] mk [Pair b a]: swap pair.
+        define [Int] wrong [Maybe Int]: none swap.
+    ";
+
+    /// The outcome of each op of the client, without spans
+    fn outcomes(report: &TypecheckReport, client: &Module) -> Vec<(String, String)> {
+        let outcome = |op: &OpReport| match &op.outcome {
+            OpOutcome::Inferred(optype) => optype.canonical().to_string(),
+            OpOutcome::Failed(err) => err.code().to_owned(),
+            outcome => format!("{:?}", outcome),
+        };
+        let mut outcomes: Vec<_> = report
+            .ops
+            .iter()
+            .filter(|op| client.op_defs.contains_key(&op.name))
+            .map(|op| (op.name.clone(), outcome(op)))
+            .collect();
+        outcomes.sort();
+        outcomes
+    }
+
+    #[test]
+    fn clients_check_the_same_against_interfaces() {
+        let library = parse(LIBRARY).unwrap();
+        let text = emit(&library, &Inference::new(&library).report());
+        assert_eq!(
+            text,
+            "// iv interface 1\n\
+             data Maybe a: nothing, [a] just.\n\
+             data Pair a b: [a, b] pair.\n\
+             const none [Maybe Int]:.\n\
+             define [Int, Int] add [Int]:.\n\
+             define [Int] negate [Int]:.\n\
+             define [Maybe a, a] or-else [a]:.\n\
+             define [a, b] swap [b, a]:.\n"
+        );
+        let interface = load(&text).unwrap();
+        for (name, optype) in interface.op_types.iter() {
+            assert_eq!(&library.op_defs[name].ann.canonical(), optype);
+        }
+        for (name, ty) in interface.const_types.iter() {
+            assert_eq!(&library.const_defs[name].ty, ty);
+        }
+        let constrs = |data_def: &DataDef| {
+            let constrs = data_def.constrs_in_source_order().into_iter();
+            constrs
+                .map(|(name, constr)| (name.clone(), constr.params.clone()))
+                .collect::<Vec<_>>()
+        };
+        for (name, data_def) in interface.data_defs.iter() {
+            assert_eq!(constrs(&library.data_defs[name]), constrs(data_def));
+        }
+
+        let client = parse(CLIENT).unwrap();
+        let mut linked = client.clone();
+        interface.link(&mut linked);
+        let against_interface = interface.declare(Inference::new(&linked)).report();
+        let whole = parse(&format!("{}{}", LIBRARY, CLIENT)).unwrap();
+        let against_source = Inference::new(&whole).report();
+        let expected = outcomes(&against_source, &client);
+        assert_eq!(outcomes(&against_interface, &client), expected);
+        assert_eq!(
+            expected,
+            [
+                ("get".to_owned(), "[Maybe Int][Int]".to_owned()),
+                ("main".to_owned(), "[][Int]".to_owned()),
+                ("mk".to_owned(), "[a, b][Pair b a]".to_owned()),
+                ("wrong".to_owned(), "AnnInfConflict".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn newer_interfaces_may_only_generalize() {
+        let interface = |source: &str| {
+            let module = parse(source).unwrap();
+            load(&emit(&module, &Inference::new(&module).report())).unwrap()
+        };
+        let older = interface(
+            "data Maybe a: nothing, [a] just.
+             const none [Maybe Int]: nothing.
+             #[unchecked]
+             define [Int, Int] add [Int]:.
+             define [a, b] swap [b, a]: br-1.
+             define [Int] keep [Int, Int]: dup.
+             define [a] gone [a]:.",
+        );
+        let newer = interface(
+            "data Maybe a: nothing, [a] just.
+             const none [Maybe a]: nothing.
+             #[unchecked]
+             define [a, a] add [a]:.
+             define [a] swap [a]:.
+             #[unchecked]
+             define [a] keep [a, Bool]:.",
+        );
+        let found: Vec<_> = older
+            .incompatibilities(&newer)
+            .iter()
+            .map(Incompatibility::to_string)
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "`gone` was removed",
+                "`keep` became less general: Bool is not more general than Int, \
+                 at post value 1",
+                "`swap` became less general: a is not more general than b, at post value 0",
+            ]
+        );
+        assert!(newer.incompatibilities(&newer).is_empty());
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let err = load("// iv interface 2\ndata Foo: foo.\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the interface is of format version 2, this iv reads version 1\n\
+             emit the interface again from the module's source"
+        );
+        assert!(matches!(
+            load("data Foo: foo."),
+            Err(InterfaceError::NoHeader)
+        ));
+        assert!(matches!(
+            load("// iv interface 1\ndata Foo foo."),
+            Err(InterfaceError::Parse(_))
+        ));
+    }
+}
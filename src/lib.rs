@@ -1,3 +1,23 @@
+pub mod analysis;
+pub mod analyze;
+pub mod annotate;
+pub mod bench;
+pub mod codegen;
+pub mod desugar;
+pub mod diagnostics;
 pub mod evaluation;
+pub mod format;
+pub mod hashing;
+pub mod interface;
+pub mod optimize;
+#[cfg(feature = "os")]
+pub mod project;
+pub mod refactor;
+#[cfg(test)]
+mod rng;
+pub mod shrink;
 pub mod syntax;
+pub mod testing;
 pub mod typing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,60 +1,557 @@
 mod cli;
 
+use iv::analysis::call_graph::CallGraph;
+use iv::analysis::data_params::check_data_params;
+use iv::analysis::deprecated::check_deprecated;
+use iv::analysis::leftovers::check_leftovers;
+use iv::analysis::literals::check_leading_zeros;
+use iv::analysis::output_vars::check_output_vars;
+use iv::analysis::termination::check_termination;
+use iv::analysis::underflow::check_no_underflow;
+use iv::analysis::unreachable::check_unreachable;
+use iv::analyze::{analyze, AnalyzeOptions};
+use iv::annotate::{annotate, annotate_with};
+use iv::bench::{parse_baseline, run_benches, BenchConfig, SystemClock};
+use iv::codegen::rust::codegen_rust;
+use iv::desugar::desugar;
+use iv::diagnostics::explanations::explain;
+use iv::diagnostics::{Diagnostic, MAX_FRAMES};
+use iv::evaluation::display::{display_stack, DisplayOptions};
 use iv::evaluation::evaluator::Evaluator;
-use iv::syntax::ast::Span;
-use iv::syntax::parse;
+use iv::evaluation::types::{EvaluatorError, ExecLimits};
+use iv::format::{format_ranges_with, FormatOptions};
+use iv::project::{Project, ProjectError, ProjectOptions, MANIFEST};
+use iv::refactor::TextEdit;
+use iv::shrink::{self, Predicate};
+use iv::syntax::ast::{Module, Span};
+use iv::syntax::attributes::check_attributes;
+use iv::syntax::{checkpoints, parse, parse_optype};
+use iv::testing::{discover, run_tests};
+use iv::typing::cache::TypecheckCache;
 use iv::typing::inference::Inference;
+use iv::typing::overload::{dispatch_overloads, has_overloads};
+use iv::typing::report::TypecheckReport;
+use iv::typing::strict::StrictOptions;
 use std::env;
 use std::fs;
 use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
 
-use lalrpop_util::ParseError;
+/// The diagnostic rendered, pointing at `iv explain` when its code has an
+/// explanation
+fn render(diagnostic: &Diagnostic, input: &str) -> String {
+    let mut out = diagnostic.render(input);
+    if let Some(code) = diagnostic
+        .code
+        .as_deref()
+        .filter(|code| explain(code).is_some())
+    {
+        out.push_str(&format!("run `iv explain {}` for more\n", code));
+    }
+    out
+}
 
 fn main() {
     let cli_args = cli::CliArgs::new(env::args());
-    let input = match cli_args.file_path {
+    if let cli::Mode::Explain(code) = &cli_args.mode {
+        match explain(code) {
+            Some(explanation) => print!("{}", explanation.render()),
+            None => {
+                eprintln!("no explanation of `{}`", code);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if let cli::Mode::Project = cli_args.mode {
+        project(Path::new(cli_args.file_path.as_deref().unwrap_or(".")));
+        return;
+    }
+    let input = match &cli_args.file_path {
         Some(file_path) => fs::read_to_string(file_path).expect("file read error"),
+        // searching the prelude alone needs no module
+        None if matches!(cli_args.mode, cli::Mode::Search(_)) => String::new(),
         None => io::read_to_string(io::stdin()).expect("stdin read error"),
     };
+    if let cli::Mode::Typecheck = cli_args.mode {
+        // only files are cached, a module from stdin has no path to key by
+        let cache = match (&cli_args.file_path, cli_args.no_cache) {
+            (Some(file_path), false) => {
+                let dir = cli_args.cache_dir.as_deref().unwrap_or(".iv-cache");
+                Some(TypecheckCache::path_in(
+                    Path::new(dir),
+                    Path::new(file_path),
+                ))
+            }
+            _ => None,
+        };
+        typecheck(
+            &input,
+            cli_args.termination,
+            cli_args.entry.as_deref(),
+            cache.as_deref(),
+            cli_args.timings,
+        );
+        return;
+    }
     let module = match parse(&input) {
         Ok(module) => module,
-        Err(
-            err @ ParseError::UnrecognizedToken {
-                token: (loc, _, _), ..
-            },
-        )
-        | Err(err @ ParseError::InvalidToken { location: loc, .. })
-        | Err(err @ ParseError::UnrecognizedEof { location: loc, .. })
-        | Err(err @ ParseError::ExtraToken { token: (loc, _, _) }) => {
-            let span = Span {
-                start: loc,
-                end: loc,
-            };
-            cli::print_span_in_source(&input, &span);
-            panic!("parsing error {:?}", err)
-        }
         Err(err) => {
-            panic!("parsing error {:?}", err)
+            eprint!(
+                "{}",
+                render(&Diagnostic::from_parse_error(&input, &err), &input)
+            );
+            process::exit(1);
+        }
+    };
+    // shrinking prints the module as written, list literals included
+    if let cli::Mode::Shrink(by) = &cli_args.mode {
+        shrink(&module, by);
+        return;
+    }
+    if let cli::Mode::Format { ranges } = &cli_args.mode {
+        let options = format_options(cli_args.file_path.as_deref());
+        format(&input, &module, ranges.as_deref(), &options);
+        return;
+    }
+    if let cli::Mode::Annotate { write, format } = cli_args.mode {
+        let options = format.then(|| format_options(cli_args.file_path.as_deref()));
+        annotate_file(
+            &input,
+            cli_args.file_path.as_deref(),
+            write,
+            options.as_ref(),
+        );
+        return;
+    }
+    let module = match desugar(module) {
+        Ok(module) => module,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprint!("{}", render(&diagnostic, &input));
+            }
+            process::exit(1);
         }
     };
+    // which definition of an overloaded name a use runs is up to checking
+    let module = if has_overloads(&module) {
+        dispatch_overloads(&module, &Inference::new(&module).report().overloads)
+    } else {
+        module
+    };
     match cli_args.mode {
-        cli::Mode::Typecheck => {
-            let inf = Inference::new(&module);
-            match inf.typecheck() {
-                Ok(_) => {
-                    println!("success!")
+        cli::Mode::Typecheck => unreachable!("typechecking needs no complete module"),
+        cli::Mode::Shrink(_) => unreachable!("shrinking needs no desugared module"),
+        cli::Mode::Project => unreachable!("a project is read from its directory"),
+        cli::Mode::Explain(_) => unreachable!("explaining reads no module"),
+        cli::Mode::Format { .. } => unreachable!("formatting needs no desugared module"),
+        cli::Mode::Annotate { .. } => unreachable!("annotating reads the module again"),
+        cli::Mode::Evaluate => {
+            let mut evaluator = Evaluator::new(&module);
+            if cli_args.coverage {
+                evaluator = evaluator.with_coverage();
+            }
+            if cli_args.origins {
+                evaluator = evaluator.with_origins();
+            }
+            match evaluator.eval_main() {
+                Ok(_) => (),
+                Err(EvaluatorError::Runtime(err)) => {
+                    let diagnostic = Diagnostic::from_runtime_error(&input, &err, MAX_FRAMES);
+                    eprint!("runtime error: {}", render(&diagnostic, &input));
+                    process::exit(1);
                 }
+                Err(err) => panic!("evaluation error {:?}", err),
+            }
+            println!(
+                "{}",
+                display_stack(&evaluator.stack, &DisplayOptions::default())
+            );
+            if let Some(coverage) = evaluator.coverage() {
+                print!("{}", coverage.uncovered(&module).render(&input));
+            }
+        }
+        cli::Mode::Compile => print!("{}", codegen_rust(&module)),
+        cli::Mode::Test { filter, list } => test(&input, &module, &filter, list),
+        cli::Mode::Bench {
+            filter,
+            baseline,
+            save_baseline,
+        } => bench(
+            &input,
+            &module,
+            &filter,
+            baseline.as_deref(),
+            save_baseline.as_deref(),
+        ),
+        cli::Mode::Search(query) => {
+            let query = match parse_optype(&query) {
+                Ok(query) => query,
                 Err(err) => {
-                    cli::print_span_in_source(&input, &err.span);
-                    panic!("typechecker error {:?}", err.error)
+                    eprint!(
+                        "{}",
+                        Diagnostic::from_parse_error(&query, &err).render(&query)
+                    );
+                    process::exit(1);
                 }
+            };
+            let hits = Inference::new(&module).search(&query);
+            let width = hits.iter().map(|hit| hit.name.len()).max().unwrap_or(0);
+            for hit in hits {
+                println!("{:width$}  {}", hit.name, hit.op_type);
             }
         }
-        cli::Mode::Evaluate => {
-            let mut evaluator = Evaluator::new(&module);
-            evaluator.eval_main();
-            println!("{:?}", evaluator.stack);
+    }
+}
+
+/// Checks every file of the project, each diagnostic prefixed with its file
+fn project(path: &Path) {
+    let project = match Project::load(path) {
+        Ok(project) => project,
+        Err(err) => {
+            eprintln!("cannot load the project: {}", err);
+            process::exit(1);
+        }
+    };
+    let report = project.check();
+    eprint!("{}", report.render(&project));
+    if report.is_ok() {
+        println!("success! {} files checked", project.files.len())
+    } else {
+        process::exit(1);
+    }
+}
+
+/// Prints the smallest part of the module the predicate still holds of
+fn shrink(module: &Module, by: &cli::ShrinkBy) {
+    let predicate = match by {
+        cli::ShrinkBy::Panics => Predicate::Panics,
+        cli::ShrinkBy::Error(code) => Predicate::ErrorCode(code.clone()),
+        cli::ShrinkBy::DiffersStrict => Predicate::ReportsDiffer(
+            Box::new(|inference| inference),
+            Box::new(|inference| inference.with_strict(StrictOptions::all())),
+        ),
+    };
+    // the panics looked for are expected, each candidate would report one
+    panic::set_hook(Box::new(|_| ()));
+    match shrink::shrink(module, |module| predicate.holds(module)) {
+        Ok(shrunk) => {
+            print!("{}", shrunk.source);
+            eprintln!(
+                "kept {} of {} candidate removals",
+                shrunk.removals, shrunk.candidates
+            );
+        }
+        Err(err) => {
+            eprintln!("cannot shrink: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// The `[fmt]` section of the first manifest in the file's directory or
+/// one above it, the current directory for stdin, the defaults if there is
+/// none
+fn format_options(file_path: Option<&str>) -> FormatOptions {
+    let start = match file_path {
+        Some(file_path) => Path::new(file_path)
+            .parent()
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+        None => PathBuf::from("."),
+    };
+    let start = fs::canonicalize(&start).unwrap_or(start);
+    let Some(manifest) = start
+        .ancestors()
+        .map(|dir| dir.join(MANIFEST))
+        .find(|path| path.is_file())
+    else {
+        return FormatOptions::default();
+    };
+    let parsed = fs::read_to_string(&manifest)
+        .map_err(|error| ProjectError::Io {
+            path: manifest.clone(),
+            error,
+        })
+        .and_then(|text| ProjectOptions::parse(&text));
+    match parsed {
+        Ok(options) => options.fmt,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Prints the module with its conflicting annotations replaced, or writes
+/// it back to the file with `write`, the definitions changed formatted if
+/// given options
+fn annotate_file(
+    input: &str,
+    file_path: Option<&str>,
+    write: bool,
+    options: Option<&FormatOptions>,
+) {
+    let annotated = match options {
+        Some(options) => annotate_with(input, options),
+        None => annotate(input),
+    };
+    for warning in &annotated.warnings {
+        let diagnostic = Diagnostic::from_annotation_warning(warning);
+        eprint!("warning: {}", diagnostic.render(input));
+    }
+    let output = TextEdit::apply_all(input, &annotated.edits);
+    if !write {
+        print!("{}", output);
+        return;
+    }
+    let Some(file_path) = file_path else {
+        eprintln!("--write needs a file to write to");
+        process::exit(1);
+    };
+    if let Err(err) = fs::write(file_path, output) {
+        eprintln!("cannot write {}: {}", file_path, err);
+        process::exit(1);
+    }
+}
+
+/// Prints the module with the definitions on the lines of the ranges
+/// formatted, all of them without ranges
+fn format(input: &str, module: &Module, ranges: Option<&str>, options: &FormatOptions) {
+    let line_starts: Vec<_> = std::iter::once(0)
+        .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    // from the start of the first line to the end of the last one
+    let span = |range: &str| {
+        let (from, to) = range.split_once('-')?;
+        let (from, to): (usize, usize) = (from.parse().ok()?, to.parse().ok()?);
+        let start = *line_starts.get(from.checked_sub(1)?)?;
+        let end = line_starts.get(to).map_or(input.len(), |next| next - 1);
+        (from <= to).then(|| Span::new(start, end))
+    };
+    let spans = match ranges {
+        None => Some(vec![Span::new(0, input.len())]),
+        Some(ranges) => ranges.split(',').map(span).collect(),
+    };
+    let Some(spans) = spans else {
+        eprintln!(
+            "cannot read the ranges `{}`, give them as lines like `3-5,12-12`",
+            ranges.unwrap_or_default()
+        );
+        process::exit(1);
+    };
+    print!(
+        "{}",
+        TextEdit::apply_all(input, &format_ranges_with(input, module, &spans, options))
+    );
+}
+
+/// Runs the test ops of a module that typechecks, or lists them
+fn test(input: &str, module: &Module, filter: &str, list: bool) {
+    if list {
+        for (name, _) in discover(module, filter) {
+            println!("{}", name);
+        }
+        return;
+    }
+    if let Err(err) = Inference::new(module).typecheck() {
+        let diagnostic = Diagnostic::from_inference_error(input, module, &err);
+        eprint!("{}", render(&diagnostic, input));
+        process::exit(1);
+    }
+    // a test that recurses forever fails instead of overflowing the stack
+    let mut evaluator = Evaluator::new(module).with_limits(ExecLimits {
+        max_call_depth: Some(500),
+        ..ExecLimits::default()
+    });
+    let report = run_tests(module, &mut evaluator, filter);
+    print!("{}", report.render(input));
+    if !report.is_ok() {
+        process::exit(1);
+    }
+}
+
+/// Times the benchmark ops of a module that typechecks, against the
+/// baseline if given
+fn bench(
+    input: &str,
+    module: &Module,
+    filter: &str,
+    baseline: Option<&str>,
+    save_baseline: Option<&str>,
+) {
+    let baseline = baseline.map(|path| {
+        let text = fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("cannot read {}: {}", path, err);
+            process::exit(1);
+        });
+        parse_baseline(&text).unwrap_or_else(|| {
+            eprintln!(
+                "{} is not a baseline, an object of names to nanoseconds",
+                path
+            );
+            process::exit(1);
+        })
+    });
+    if let Err(err) = Inference::new(module).typecheck() {
+        let diagnostic = Diagnostic::from_inference_error(input, module, &err);
+        eprint!("{}", render(&diagnostic, input));
+        process::exit(1);
+    }
+    // each run is checked against the limits on its own
+    let mut evaluator = Evaluator::new(module).with_limits(ExecLimits {
+        max_call_depth: Some(500),
+        ..ExecLimits::default()
+    });
+    let report = run_benches(
+        module,
+        &mut evaluator,
+        filter,
+        &BenchConfig::default(),
+        &SystemClock::new(),
+    );
+    print!("{}", report.render(input, baseline.as_ref()));
+    if let Some(path) = save_baseline {
+        if let Err(err) = fs::write(path, report.baseline()) {
+            eprintln!("cannot write {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+    if !report.is_ok() {
+        process::exit(1);
+    }
+}
+
+/// How long each phase took, then the ten ops that took longest to check
+/// and what checking them did most
+fn print_timings(report: &TypecheckReport) {
+    let millis = |d: &Duration| format!("{:.3}ms", d.as_secs_f64() * 1000.0);
+    eprintln!("phases:");
+    for (phase, duration) in report.timings.iter() {
+        eprintln!("  {:10} {}", phase.name(), millis(duration));
+    }
+    let slowest = report.slowest(10);
+    let width = slowest.iter().map(|op| op.name.len()).max().unwrap_or(0);
+    eprintln!("slowest definitions:");
+    for op in slowest {
+        let stats = &op.stats;
+        eprintln!(
+            "  {:width$} {}  mostly {}: {} unifications, {} instantiations, {} fresh variables, substitutions of up to {}",
+            op.name,
+            op.elapsed.as_ref().map_or(String::new(), millis),
+            stats.dominant_cost(),
+            stats.unifications,
+            stats.instantiations,
+            stats.fresh_vars,
+            stats.max_subst_len,
+        );
+    }
+}
+
+/// Reports every parse, desugaring and type error, the definitions that
+/// parse are checked even when others do not. Ops unchanged since the run
+/// that wrote the cache file are not checked again.
+fn typecheck(
+    input: &str,
+    termination: bool,
+    entry: Option<&str>,
+    cache: Option<&Path>,
+    timings: bool,
+) {
+    let opts = AnalyzeOptions {
+        keep_ast: true,
+        cache: cache.map(TypecheckCache::load),
+        ..AnalyzeOptions::default()
+    };
+    let result = analyze(input, opts);
+    if let (true, Some(report)) = (timings, &result.report) {
+        print_timings(report);
+    }
+    if let (Some(path), Some(updated)) = (cache, &result.cache) {
+        if let Err(err) = updated.save(path) {
+            eprintln!(
+                "warning: cannot write the cache {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+    for warning in checkpoints::scan(input).1 {
+        let diagnostic = Diagnostic::from_checkpoint_warning(&warning);
+        eprint!("warning: {}", diagnostic.render(input));
+    }
+    if let Some(module) = &result.module {
+        for warning in check_leading_zeros(input) {
+            let diagnostic = Diagnostic::from_leading_zero_warning(&warning);
+            eprint!("warning: {}", diagnostic.render(input));
+        }
+        for problem in check_attributes(module) {
+            if !problem.is_error() {
+                let diagnostic = Diagnostic::from_attribute_problem(&problem);
+                eprint!("warning: {}", diagnostic.render(input));
+            }
+        }
+        for warning in check_output_vars(module) {
+            let diagnostic = Diagnostic::from_output_var_warning(&warning);
+            eprint!("warning: {}", render(&diagnostic, input));
+        }
+        for warning in check_deprecated(module) {
+            let diagnostic = Diagnostic::from_deprecation_warning(input, &warning);
+            eprint!("warning: {}", diagnostic.render(input));
+        }
+        for warning in check_unreachable(module) {
+            let diagnostic = Diagnostic::from_unreachable_warning(&warning);
+            eprint!("warning: {}", diagnostic.render(input));
+        }
+        for warning in check_leftovers(&Inference::new(module)) {
+            let diagnostic = Diagnostic::from_leftover_warning(&warning);
+            eprint!("warning: {}", diagnostic.render(input));
+        }
+        for warning in check_data_params(module) {
+            let diagnostic = Diagnostic::from_param_warning(&warning);
+            let level = if warning.is_informational() {
+                "note"
+            } else {
+                "warning"
+            };
+            eprint!("{}: {}", level, diagnostic.render(input));
+        }
+        if termination {
+            let graph = CallGraph::new(module);
+            for warning in check_termination(module, &graph) {
+                let diagnostic = Diagnostic::from_termination_warning(&warning);
+                eprint!("warning: {}", diagnostic.render(input));
+            }
+        }
+    }
+    for (_, diagnostic) in result.diagnostics.iter() {
+        eprint!("{}", render(diagnostic, input));
+    }
+    if !result.is_ok() {
+        process::exit(1);
+    }
+    if let (Some(entry), Some(module)) = (entry, &result.module) {
+        let underflows = match check_no_underflow(&Inference::new(module), entry) {
+            Some(Ok(underflows)) => underflows,
+            // an unchecked entry may use names that do not exist
+            Some(Err(err)) => {
+                let diagnostic = Diagnostic::from_inference_error(input, module, &err);
+                eprint!("{}", render(&diagnostic, input));
+                process::exit(1);
+            }
+            None => {
+                eprintln!("no op named `{}` to check as the entry", entry);
+                process::exit(1);
+            }
+        };
+        for underflow in underflows.iter() {
+            eprint!("{}", Diagnostic::from_underflow(underflow).render(input));
+        }
+        if !underflows.is_empty() {
+            process::exit(1);
         }
-        cli::Mode::Compile => unimplemented!("compilation"),
     }
+    println!("success!")
 }
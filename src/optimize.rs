@@ -0,0 +1,927 @@
+//! Rewriting bodies with rules like `dup pop =>`, a pattern of ops and the
+//! ops replacing it wherever the pattern matches. A rule's variables stand
+//! for any one literal or quote, so `x pop =>` drops whatever value is
+//! pushed right before a `pop`. Each rewrite is checked by inferring the
+//! rewritten body again, one that would change the body's type, up to the
+//! values it passes through, is not made, whatever the rule claims.
+
+use crate::diagnostics::parse_error_code;
+use crate::syntax::ast::*;
+use crate::syntax::parse_ops;
+use crate::typing::env::Provenance;
+use crate::typing::inference::Inference;
+use crate::typing::prelude_types;
+use crate::typing::types::OpType;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::iter::once;
+
+const CONSTRUCT: &str = "rewrite rule";
+
+/// The rewrites made in a body before it is left as it is
+const DEFAULT_BUDGET: usize = 1000;
+
+/// The name rewrites removing dead sequences go by
+const DEAD_SEQUENCE: &str = "dead-sequence";
+
+/// The name rewrites running the ops of a quote in place of the op running
+/// it go by
+const DEVIRTUALIZE: &str = "devirtualize";
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    /// The names in `pattern` and `replacement` that are variables
+    vars: Vec<String>,
+    pattern: Vec<Op>,
+    replacement: Vec<Op>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RuleError {
+    /// A side of the rule does not parse as ops
+    Unparsable {
+        side: &'static str,
+        code: &'static str,
+    },
+    /// A pattern without ops, which would match everywhere
+    EmptyPattern,
+    /// A case or list literal in the rule, rules only have literals, names
+    /// and quotes
+    Unsupported { construct: &'static str },
+    /// A variable of the replacement that the pattern does not bind
+    UnboundVar(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Unparsable { side, code } => {
+                write!(f, "the {} does not parse: {}", side, code)
+            }
+            RuleError::EmptyPattern => write!(f, "the pattern has no ops"),
+            RuleError::Unsupported { construct } => {
+                write!(f, "a rule cannot have a {} in it", construct)
+            }
+            RuleError::UnboundVar(var) => {
+                write!(
+                    f,
+                    "variable `{}` of the replacement is not in the pattern",
+                    var
+                )
+            }
+        }
+    }
+}
+
+type Bindings = HashMap<String, Op>;
+
+impl Rule {
+    /// A rule replacing the ops of `pattern` with those of `replacement`,
+    /// both written as in a body, the names in `vars` being variables
+    pub fn new(
+        name: &str,
+        vars: &[&str],
+        pattern: &str,
+        replacement: &str,
+    ) -> Result<Self, RuleError> {
+        let parse = |side, source| {
+            parse_ops(source).map_err(|err| RuleError::Unparsable {
+                side,
+                code: parse_error_code(&err),
+            })
+        };
+        let rule = Rule {
+            name: name.to_owned(),
+            vars: vars.iter().map(|var| (*var).to_owned()).collect(),
+            pattern: parse("pattern", pattern)?,
+            replacement: parse("replacement", replacement)?,
+        };
+        if rule.pattern.is_empty() {
+            return Err(RuleError::EmptyPattern);
+        }
+        supported(&rule.pattern)?;
+        supported(&rule.replacement)?;
+        let mut bound = HashSet::new();
+        rule.names(&rule.pattern, &mut bound);
+        let mut used = HashSet::new();
+        rule.names(&rule.replacement, &mut used);
+        match used.difference(&bound).min() {
+            Some(var) => Err(RuleError::UnboundVar(var.clone())),
+            None => Ok(rule),
+        }
+    }
+
+    /// The variables among the names of the ops
+    fn names(&self, ops: &[Op], out: &mut HashSet<String>) {
+        for op in ops {
+            match op {
+                Op::Name { value, .. } if self.vars.contains(value) => {
+                    out.insert(value.clone());
+                }
+                Op::Quote { value, .. } => self.names(value, out),
+                _ => {}
+            }
+        }
+    }
+
+    /// What the variables stand for when the pattern matches the ops from
+    /// the first one on
+    fn matches(&self, ops: &[Op]) -> Option<Bindings> {
+        let ops = ops.get(..self.pattern.len())?;
+        let mut bindings = HashMap::new();
+        self.match_ops(&self.pattern, ops, &mut bindings)
+            .then_some(bindings)
+    }
+
+    fn match_ops(&self, pattern: &[Op], ops: &[Op], bindings: &mut Bindings) -> bool {
+        pattern.len() == ops.len()
+            && pattern
+                .iter()
+                .zip(ops)
+                .all(|(pattern, op)| self.match_op(pattern, op, bindings))
+    }
+
+    fn match_op(&self, pattern: &Op, op: &Op, bindings: &mut Bindings) -> bool {
+        match (pattern, op) {
+            (Op::Name { value: var, .. }, Op::Literal { .. } | Op::Quote { .. })
+                if self.vars.contains(var) =>
+            {
+                match bindings.get(var) {
+                    // ops print the same exactly when they only differ in
+                    // their spans
+                    Some(bound) => bound.to_string() == op.to_string(),
+                    None => {
+                        bindings.insert(var.clone(), op.clone());
+                        true
+                    }
+                }
+            }
+            (Op::Name { value: name, .. }, Op::Name { value, .. }) => {
+                !self.vars.contains(name) && name == value
+            }
+            (Op::Literal { value: literal, .. }, Op::Literal { value, .. }) => {
+                literal.to_string() == value.to_string()
+            }
+            (Op::Quote { value: quoted, .. }, Op::Quote { value, .. }) => {
+                self.match_ops(quoted, value, bindings)
+            }
+            _ => false,
+        }
+    }
+
+    /// The replacement of the ops under `span`. The ops a variable stands
+    /// for keep their spans, the rule's own get spans expanded from `span`.
+    fn replace(&self, ops: &[Op], bindings: &Bindings, span: &Span) -> Vec<Op> {
+        self.replace_from(ops, bindings, span, &Cell::new(0))
+    }
+
+    /// `replace`, numbering the rule's ops from `next` on
+    fn replace_from(
+        &self,
+        ops: &[Op],
+        bindings: &Bindings,
+        span: &Span,
+        next: &Cell<usize>,
+    ) -> Vec<Op> {
+        let expanded = || Span::expanded_from(span, CONSTRUCT, next.replace(next.get() + 1));
+        ops.iter()
+            .map(|op| match op {
+                Op::Name { value, .. } if bindings.contains_key(value) => bindings[value].clone(),
+                Op::Name { value, .. } => Op::Name {
+                    value: value.clone(),
+                    span: expanded(),
+                },
+                Op::Literal { value, .. } => Op::Literal {
+                    value: value.clone(),
+                    span: expanded(),
+                },
+                Op::Quote { value, .. } => Op::Quote {
+                    span: expanded(),
+                    value: self.replace_from(value, bindings, span, next),
+                },
+                // `new` refuses rules with any other op
+                op => op.clone(),
+            })
+            .collect()
+    }
+}
+
+fn supported(ops: &[Op]) -> Result<(), RuleError> {
+    ops.iter().try_for_each(|op| match op {
+        Op::Case { .. } | Op::Case2 { .. } => Err(RuleError::Unsupported { construct: "case" }),
+        Op::List { .. } => Err(RuleError::Unsupported {
+            construct: "list literal",
+        }),
+        Op::Quote { value, .. } => supported(value),
+        Op::Literal { .. } | Op::Name { .. } => Ok(()),
+    })
+}
+
+/// `pattern => replacement` with the variables as names
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ops = |ops: &[Op]| {
+            let ops: Vec<_> = ops.iter().map(Op::to_string).collect();
+            ops.join(" ")
+        };
+        write!(f, "{} =>", ops(&self.pattern))?;
+        if !self.replacement.is_empty() {
+            write!(f, " {}", ops(&self.replacement))?;
+        }
+        Ok(())
+    }
+}
+
+/// The identities of the stack shufflers of the prelude, and of pushing a
+/// value right before one
+pub fn builtin_rules() -> Vec<Rule> {
+    let mut rules = vec![
+        ("swap-swap", &[][..], "br-1 br-1", ""),
+        ("dup-pop", &[], "dup pop", ""),
+        ("dup-swap", &[], "dup br-1", "dup"),
+        ("push-pop", &["x"], "x pop", ""),
+        ("push-dup", &["x"], "x dup", "x x"),
+        ("push-swap", &["x", "y"], "x y br-1", "y x"),
+    ];
+    let inverses: Vec<_> = (1..=3)
+        .flat_map(|n| {
+            [
+                (format!("bury-dig-{}", n), format!("br-{} dg-{}", n, n)),
+                (format!("dig-bury-{}", n), format!("dg-{} br-{}", n, n)),
+            ]
+        })
+        .collect();
+    rules.extend(
+        inverses
+            .iter()
+            .map(|(name, pattern)| (name.as_str(), &[][..], pattern.as_str(), "")),
+    );
+    rules
+        .into_iter()
+        .map(|(name, vars, pattern, replacement)| {
+            Rule::new(name, vars, pattern, replacement).expect("the built-in rules are valid")
+        })
+        .collect()
+}
+
+/// Applies rules to the bodies of a module until none matches. At each op
+/// of a body, the ops inside it are rewritten first, then the first rule
+/// matching from the op on, so of overlapping matches the leftmost and,
+/// at the same op, the earliest rule wins.
+#[derive(Debug, Clone)]
+pub struct Optimizer {
+    rules: Vec<Rule>,
+    budget: usize,
+    dead_sequences: bool,
+    devirtualize: bool,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Optimizer {
+            rules: vec![],
+            budget: DEFAULT_BUDGET,
+            dead_sequences: false,
+            devirtualize: false,
+        }
+    }
+}
+
+/// A rewrite made
+#[derive(Debug, Clone)]
+pub struct Rewrite {
+    pub rule: String,
+    /// The definition of the body
+    pub def: String,
+    /// The ops the pattern matched
+    pub span: Span,
+}
+
+/// A rewrite not made, since it would change the body's type
+#[derive(Debug, Clone)]
+pub struct RejectedRewrite {
+    pub rule: String,
+    pub def: String,
+    pub span: Span,
+    pub before: OpType,
+    /// `None` when the rewritten body does not typecheck
+    pub after: Option<OpType>,
+}
+
+impl fmt::Display for RejectedRewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.after {
+            Some(after) => write!(
+                f,
+                "rule `{}` would change the type of `{}` from {} to {}, so it is not applied here",
+                self.rule, self.def, self.before, after
+            ),
+            None => write!(
+                f,
+                "rule `{}` would make `{}` fail to typecheck, so it is not applied here",
+                self.rule, self.def
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Optimized {
+    pub module: Module,
+    /// In the order they were made
+    pub rewrites: Vec<Rewrite>,
+    pub rejected: Vec<RejectedRewrite>,
+    /// The definitions whose rewriting stopped at the budget
+    pub exhausted: Vec<String>,
+}
+
+impl Optimized {
+    /// The calls of quotes replaced by the quotes' ops, see
+    /// `Optimizer::with_devirtualization`
+    pub fn devirtualized(&self) -> usize {
+        self.rewrites
+            .iter()
+            .filter(|rewrite| rewrite.rule == DEVIRTUALIZE)
+            .count()
+    }
+}
+
+/// Where a rule matches
+struct Site {
+    rule: String,
+    /// The op and the sequence inside it at each level, see `nested`
+    path: Vec<(usize, usize)>,
+    start: usize,
+    /// The number of ops matched
+    len: usize,
+    replacement: Vec<Op>,
+    span: Span,
+}
+
+impl Optimizer {
+    pub fn with_rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn with_builtin_rules(self) -> Self {
+        builtin_rules().into_iter().fold(self, Self::with_rule)
+    }
+
+    /// Also removes the runs of pure ops that leave every stack as they
+    /// find it, like `1 pop` or `br-1 br-1`, the longest run first. These
+    /// rewrites go by the rule name `dead-sequence`.
+    pub fn with_dead_sequences(mut self) -> Self {
+        self.dead_sequences = true;
+        self
+    }
+
+    /// Also runs the ops of a quote in place of an `exec-*`, `keep-*` or
+    /// `dip-*` that runs it, when the quote run can only be the one a quote
+    /// literal earlier in the same sequence pushes. The literal is left for
+    /// the other rules to remove, `exec-1-1` becomes `pop` and the quote's
+    /// ops. These rewrites go by the rule name `devirtualize`.
+    pub fn with_devirtualization(mut self) -> Self {
+        self.devirtualize = true;
+        self
+    }
+
+    /// At most `budget` rewrites are made in each body
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// The module with the bodies of its op and constant definitions
+    /// rewritten. Bodies that do not typecheck and `#[unchecked]` ones are
+    /// left as they are, as rewrites of them could not be checked.
+    pub fn optimize(&self, module: &Module) -> Optimized {
+        let inference = Inference::new(module);
+        let mut optimized = Optimized {
+            module: module.clone(),
+            rewrites: vec![],
+            rejected: vec![],
+            exhausted: vec![],
+        };
+        let ops = module
+            .op_defs_in_source_order()
+            .into_iter()
+            .filter(|(_, op_def)| !op_def.has_attribute("unchecked"))
+            .map(|(name, op_def)| (name, &op_def.body));
+        let consts = module
+            .const_defs_in_source_order()
+            .into_iter()
+            .map(|(name, const_def)| (name, &const_def.body));
+        let mut bodies = HashMap::new();
+        for (name, body) in ops.chain(consts) {
+            let mut body = body.clone();
+            self.optimize_body(&inference, name, &mut body, &mut optimized);
+            bodies.insert(name.clone(), body);
+        }
+        for (name, op_def) in optimized.module.op_defs.iter_mut() {
+            if let Some(body) = bodies.remove(name) {
+                op_def.body = body;
+            }
+        }
+        for (name, const_def) in optimized.module.const_defs.iter_mut() {
+            if let Some(body) = bodies.remove(name) {
+                const_def.body = body;
+            }
+        }
+        optimized
+    }
+
+    fn optimize_body(
+        &self,
+        inference: &Inference,
+        def: &str,
+        body: &mut Vec<Op>,
+        optimized: &mut Optimized,
+    ) {
+        let normalized =
+            |ops: &[Op]| (inference.infer_ops(ops)).map(|optype| optype.unaugmented().canonical());
+        let Ok(before) = normalized(body) else {
+            return;
+        };
+        let mut rejected = HashSet::new();
+        let mut made = 0;
+        while let Some(site) = self.find(inference, body, &mut vec![], &rejected) {
+            if made == self.budget {
+                optimized.exhausted.push(def.to_owned());
+                return;
+            }
+            let mut rewritten = body.clone();
+            let ops = site.path.iter().fold(&mut rewritten, |ops, &(i, child)| {
+                nested_mut(&mut ops[i], child)
+            });
+            ops.splice(site.start..site.start + site.len, site.replacement);
+            match normalized(&rewritten) {
+                Ok(after) if after == before => {
+                    *body = rewritten;
+                    made += 1;
+                    optimized.rewrites.push(Rewrite {
+                        rule: site.rule,
+                        def: def.to_owned(),
+                        span: site.span,
+                    });
+                }
+                after => {
+                    rejected.insert((site.rule.clone(), site.span.start, site.span.end));
+                    optimized.rejected.push(RejectedRewrite {
+                        rule: site.rule,
+                        def: def.to_owned(),
+                        span: site.span,
+                        before: before.clone(),
+                        after: after.ok(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// The first site a rule matches at in the ops, skipping the rules
+    /// already rejected at the same span
+    fn find(
+        &self,
+        inference: &Inference,
+        ops: &[Op],
+        path: &mut Vec<(usize, usize)>,
+        rejected: &HashSet<(String, usize, usize)>,
+    ) -> Option<Site> {
+        let site = |path: &[(usize, usize)],
+                    rule: &str,
+                    start: usize,
+                    len: usize,
+                    replacement: &dyn Fn(&Span) -> Vec<Op>| {
+            let matched = &ops[start..start + len];
+            let span = Span::new(matched[0].get_span().start, matched[len - 1].get_span().end);
+            let key = (rule.to_owned(), span.start, span.end);
+            (!rejected.contains(&key)).then(|| Site {
+                rule: rule.to_owned(),
+                path: path.to_vec(),
+                start,
+                len,
+                replacement: replacement(&span),
+                span,
+            })
+        };
+        let quotes = match self.devirtualize {
+            true => quote_sources(inference, ops),
+            false => vec![],
+        };
+        for start in 0..ops.len() {
+            for (child, nested) in nested(&ops[start]).into_iter().enumerate() {
+                path.push((start, child));
+                if let Some(site) = self.find(inference, nested, path, rejected) {
+                    return Some(site);
+                }
+                path.pop();
+            }
+            for rule in self.rules.iter() {
+                let Some(bindings) = rule.matches(&ops[start..]) else {
+                    continue;
+                };
+                let replace = |span: &Span| rule.replace(&rule.replacement, &bindings, span);
+                if let Some(site) = site(path, &rule.name, start, rule.pattern.len(), &replace) {
+                    return Some(site);
+                }
+            }
+            let run = quotes.get(start).copied().flatten().and_then(|quote| {
+                let (Op::Name { value, .. }, Op::Quote { value: body, .. }) =
+                    (&ops[start], &ops[quote])
+                else {
+                    return None;
+                };
+                let inline = |span: &Span| inlined(value, body, span);
+                runs_top_quote(value)
+                    .then(|| site(path, DEVIRTUALIZE, start, 1, &inline))
+                    .flatten()
+            });
+            if let Some(site) = run {
+                return Some(site);
+            }
+            if !self.dead_sequences {
+                continue;
+            }
+            let dead = (1..=ops.len() - start).rev().find(|len| {
+                let run = &ops[start..start + len];
+                inference.is_pure(run)
+                    && inference
+                        .infer_ops(run)
+                        .is_ok_and(|optype| optype.is_identity())
+            });
+            if let Some(site) =
+                dead.and_then(|len| site(path, DEAD_SEQUENCE, start, len, &|_| vec![]))
+            {
+                return Some(site);
+            }
+        }
+        None
+    }
+}
+
+/// Where the value on top of the stack comes from before each of the ops,
+/// when it is the quote the op at the position pushed. Values keep where
+/// they come from only through the prelude ops that leave values they take
+/// as they are, see `prelude_types::moves`, the other ops take and leave
+/// values as their type says, the values they leave coming from nowhere known.
+fn quote_sources(inference: &Inference, ops: &[Op]) -> Vec<Option<usize>> {
+    // topmost last, the values below the ones the ops pushed are not known
+    let mut stack: Vec<Option<usize>> = vec![];
+    let mut tops = vec![];
+    for (i, op) in ops.iter().enumerate() {
+        tops.push(stack.last().copied().flatten());
+        let moves = match op {
+            Op::Name { value, .. }
+                if inference
+                    .env()
+                    .lookup(value)
+                    .is_some_and(|binding| binding.provenance == Provenance::Prelude) =>
+            {
+                prelude_types::moves(value)
+            }
+            _ => None,
+        };
+        let (taken, left) = match (op, moves) {
+            (Op::Quote { .. }, _) => (0, vec![Some(i)]),
+            (Op::Literal { .. }, _) => (0, vec![None]),
+            (Op::Name { value, .. }, Some(moves)) => {
+                let taken = prelude_types::get(value).map_or(0, |optype| optype.pre.len());
+                let taken_at = |k: usize| stack.len().checked_sub(k + 1).and_then(|j| stack[j]);
+                let left = moves
+                    .into_iter()
+                    .map(|from| from.and_then(taken_at))
+                    .collect();
+                (taken, left)
+            }
+            _ => match inference.infer_op_alone(op) {
+                Ok(optype) => (optype.pre.len(), vec![None; optype.post.len()]),
+                Err(_) => {
+                    stack.clear();
+                    continue;
+                }
+            },
+        };
+        stack.truncate(stack.len().saturating_sub(taken));
+        stack.extend(left.into_iter().rev());
+    }
+    tops
+}
+
+/// Whether the prelude op runs the quote on top of the stack and no other
+fn runs_top_quote(name: &str) -> bool {
+    ["exec-", "keep-", "dip-"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+        && prelude_types::get(name).is_some()
+}
+
+/// The ops running `body` in place of the prelude op `name`, one that
+/// `runs_top_quote`, the quote it runs dropped
+fn inlined(name: &str, body: &[Op], span: &Span) -> Vec<Op> {
+    let next = Cell::new(0);
+    let op = |value: &str| Op::Name {
+        value: value.to_owned(),
+        span: Span::expanded_from(span, CONSTRUCT, next.replace(next.get() + 1)),
+    };
+    let run = || once(op("pop")).chain(body.iter().cloned());
+    let expansion = prelude_types::expand(name).unwrap_or_else(|| vec![name.to_owned()]);
+    expansion
+        .iter()
+        .flat_map(|value| match value.starts_with("exec-") {
+            true => run().collect::<Vec<_>>(),
+            false => vec![op(value)],
+        })
+        .collect()
+}
+
+/// The op sequences directly inside the op: a quote's, one for each case
+/// arm, one for each item of a list literal
+fn nested(op: &Op) -> Vec<&Vec<Op>> {
+    match op {
+        Op::Quote { value, .. } => vec![value],
+        Op::Case { head_arm, arms, .. } => std::iter::once(head_arm)
+            .chain(arms)
+            .map(|arm| &arm.body)
+            .collect(),
+        Op::Case2 { arms, .. } => arms.iter().map(|arm| &arm.body).collect(),
+        Op::List { items, .. } => items.iter().collect(),
+        Op::Literal { .. } | Op::Name { .. } => vec![],
+    }
+}
+
+/// The sequence `nested` gives at `child`
+fn nested_mut(op: &mut Op, child: usize) -> &mut Vec<Op> {
+    match op {
+        Op::Quote { value, .. } => value,
+        Op::Case { head_arm, arms, .. } => match child {
+            0 => &mut head_arm.body,
+            _ => &mut arms[child - 1].body,
+        },
+        Op::Case2 { arms, .. } => &mut arms[child].body,
+        Op::List { items, .. } => &mut items[child],
+        Op::Literal { .. } | Op::Name { .. } => unreachable!("no ops inside"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    /// The bodies of the optimized module, in source order
+    fn bodies(optimized: &Optimized) -> Vec<String> {
+        optimized
+            .module
+            .definitions_in_source_order()
+            .into_iter()
+            .filter_map(|def| match def {
+                Definition::Op(..) | Definition::Const(..) => {
+                    let def = def.to_string();
+                    Some(def[def.find("]:").unwrap() + 2..].trim().to_owned())
+                }
+                Definition::Data(..) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shuffles_go_inside_quotes_and_case_arms() {
+        let source = "
+            data Bool: true, false.
+            define [a, b] swap [b, a]: br-1 br-1 br-1.
+            define [Bool, Int] pick [Int]:
+              case { true { dup pop pop 1 2 br-1 pop }, false { pop (3 dup) exec-0-2 pop } }.
+            const one [Int]: 1 2 dg-1 br-1 pop.
+            #[unchecked]
+            define [] loose [Int]: 1 dup pop.
+        ";
+        let module = parse(source).unwrap();
+        let optimized = Optimizer::default().with_builtin_rules().optimize(&module);
+        assert_eq!(
+            bodies(&optimized),
+            [
+                "br-1.",
+                "case { true { pop 2 }, false { pop (3 3) exec-0-2 pop } }.",
+                "1.",
+                "1 dup pop.",
+            ]
+        );
+        let rules: Vec<_> = optimized
+            .rewrites
+            .iter()
+            .map(|rewrite| (rewrite.def.as_str(), rewrite.rule.as_str()))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                ("swap", "swap-swap"),
+                ("pick", "dup-pop"),
+                ("pick", "push-swap"),
+                ("pick", "push-pop"),
+                ("pick", "push-dup"),
+                ("one", "dig-bury-1"),
+                ("one", "push-pop"),
+            ]
+        );
+        assert!(optimized.rejected.is_empty() && optimized.exhausted.is_empty());
+    }
+
+    #[test]
+    fn overlapping_rules_take_the_leftmost_match_then_the_earliest_rule() {
+        let source = "define [a] f []: dup pop pop.\ndefine [] g [Int]: 1 dup pop.";
+        let module = parse(source).unwrap();
+        let short = Rule::new("short", &[], "dup pop", "").unwrap();
+        let long = Rule::new("long", &[], "dup pop pop", "pop").unwrap();
+        let push = Rule::new("push", &["x"], "x dup", "x x").unwrap();
+        let optimize = |rules: &[&Rule]| {
+            let optimizer = rules.iter().fold(Optimizer::default(), |optimizer, rule| {
+                optimizer.with_rule((*rule).clone())
+            });
+            let optimized = optimizer.optimize(&module);
+            let rules: Vec<_> = optimized
+                .rewrites
+                .iter()
+                .map(|rewrite| rewrite.rule.clone())
+                .collect();
+            (bodies(&optimized), rules)
+        };
+        // `1 dup` starts left of `dup pop`
+        assert_eq!(
+            optimize(&[&short, &long, &push]),
+            (
+                vec!["pop.".to_owned(), "1 1 pop.".to_owned()],
+                vec!["short".to_owned(), "push".to_owned()]
+            )
+        );
+        assert_eq!(
+            optimize(&[&long, &short]),
+            (
+                vec!["pop.".to_owned(), "1.".to_owned()],
+                vec!["long".to_owned(), "short".to_owned()]
+            )
+        );
+        assert_eq!(short.to_string(), "dup pop =>");
+        assert_eq!(push.to_string(), "x dup => x x");
+    }
+
+    #[test]
+    fn type_changing_rewrites_are_refused() {
+        let source = "
+            define [Int] twice [Int, Int]: dup.
+            define [] quoted [[][Int, Int]]: (1 dup).
+        ";
+        let module = parse(source).unwrap();
+        let optimized = Optimizer::default()
+            .with_rule(Rule::new("forget-dup", &[], "dup", "").unwrap())
+            .optimize(&module);
+        assert_eq!(bodies(&optimized), ["dup.", "(1 dup)."]);
+        let rejected: Vec<_> = optimized
+            .rejected
+            .iter()
+            .map(|rejected| (rejected.def.as_str(), rejected.to_string()))
+            .collect();
+        assert_eq!(
+            rejected,
+            [
+                (
+                    "twice",
+                    "rule `forget-dup` would change the type of `twice` from [a][a, a] to \
+                     [][], so it is not applied here"
+                        .to_owned()
+                ),
+                (
+                    "quoted",
+                    "rule `forget-dup` would change the type of `quoted` from [][[][Int, Int]] \
+                     to [][[][Int]], so it is not applied here"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn dead_sequences_go_unless_they_can_fail() {
+        let source = "
+            data Bool: true, false.
+            define [a, b] same [a, b]: br-1 br-1.
+            define [Bool] check [Bool]: dup 1 pop pop false assert.
+            define [] inner []: (1 pop) exec-0-0.
+            define [] kept [Int]: 1.
+        ";
+        let module = parse(source).unwrap();
+        let optimized = Optimizer::default().with_dead_sequences().optimize(&module);
+        assert_eq!(
+            bodies(&optimized),
+            [".", "false assert.", "() exec-0-0.", "1."]
+        );
+        assert!(optimized
+            .rewrites
+            .iter()
+            .all(|rewrite| rewrite.rule == DEAD_SEQUENCE));
+        assert!(Inference::new(&optimized.module).typecheck().is_ok());
+
+        // what a body passes through does not count as part of its type
+        let optimized = Optimizer::default().with_builtin_rules().optimize(&module);
+        assert_eq!(bodies(&optimized)[0], ".");
+        assert!(optimized.rejected.is_empty());
+    }
+
+    #[test]
+    fn calls_of_known_quotes_run_the_quotes_ops() {
+        let source = "
+            define [Int] direct [Int]: (pop 2) exec-1-1.
+            define [Int] shuffled [Int]: (pop 2) br-1 dg-1 exec-1-1.
+            define [Int, Int] under [Int, Int]: (pop 3) dip-1-1-1.
+        ";
+        let module = parse(source).unwrap();
+        let optimized = Optimizer::default()
+            .with_devirtualization()
+            .optimize(&module);
+        assert_eq!(
+            bodies(&optimized),
+            [
+                "(pop 2) pop pop 2.",
+                "(pop 2) br-1 dg-1 pop pop 2.",
+                "(pop 3) br-1 br-2 pop pop 3 dg-1.",
+            ]
+        );
+        assert_eq!(optimized.devirtualized(), 3);
+        assert!(optimized.rejected.is_empty());
+        assert!(Inference::new(&optimized.module).typecheck().is_ok());
+
+        // the other rules clean up after
+        let optimized = Optimizer::default()
+            .with_builtin_rules()
+            .with_devirtualization()
+            .optimize(&module);
+        assert_eq!(
+            bodies(&optimized),
+            ["pop 2.", "pop 2.", "(pop 3) br-1 br-2 pop pop 3 dg-1."]
+        );
+    }
+
+    #[test]
+    fn calls_of_quotes_from_more_than_one_place_stay() {
+        let source = "
+            data Bool: true, false.
+            define [Bool, Int] pick [Int]:
+              case { true { (pop 1) }, false { (pop 2) } } exec-1-1.
+            define [[Int -> Int], Int] given [Int]: exec-1-1.
+            define [[Int -> Int]] same [[Int -> Int]]:.
+            define [] passed [Int]: 3 (pop 1) same exec-1-1.
+        ";
+        let module = parse(source).unwrap();
+        let optimized = Optimizer::default()
+            .with_devirtualization()
+            .optimize(&module);
+        assert_eq!(optimized.devirtualized(), 0);
+        assert_eq!(
+            bodies(&optimized),
+            [
+                "case { true { (pop 1) }, false { (pop 2) } } exec-1-1.",
+                "exec-1-1.",
+                ".",
+                "3 (pop 1) same exec-1-1.",
+            ]
+        );
+    }
+
+    #[test]
+    fn rewriting_stops_at_the_budget() {
+        let module = parse("define [a, b] f [b, a]: br-1.").unwrap();
+        let optimized = Optimizer::default()
+            .with_rule(Rule::new("grow", &[], "br-1", "br-1 br-1 br-1").unwrap())
+            .with_budget(3)
+            .optimize(&module);
+        assert_eq!(optimized.rewrites.len(), 3);
+        assert_eq!(optimized.exhausted, ["f"]);
+        assert_eq!(bodies(&optimized), ["br-1 br-1 br-1 br-1 br-1 br-1 br-1."]);
+    }
+
+    #[test]
+    fn malformed_rules_are_refused() {
+        let err = |vars: &[&str], pattern, replacement| {
+            Rule::new("r", vars, pattern, replacement).unwrap_err()
+        };
+        assert_eq!(err(&[], "", "dup"), RuleError::EmptyPattern);
+        assert_eq!(
+            err(&["x", "y"], "x pop", "y x"),
+            RuleError::UnboundVar("y".to_owned())
+        );
+        assert_eq!(
+            err(&[], "case { true { } }", ""),
+            RuleError::Unsupported { construct: "case" }
+        );
+        assert!(matches!(
+            err(&[], "dup )", ""),
+            RuleError::Unparsable {
+                side: "pattern",
+                ..
+            }
+        ));
+    }
+}
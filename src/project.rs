@@ -0,0 +1,460 @@
+//! Projects of several source files, described by an `iv.toml` manifest in
+//! the project's directory:
+//!
+//! ```toml
+//! # where the .iv files are, searched recursively
+//! roots = ["src"]
+//! # the file with the op the program starts from, and that op
+//! entry = "src/main.iv"
+//! entry_op = "main"
+//! # every check of `StrictOptions::all`
+//! strict = true
+//!
+//! # how `iv format` lays out definitions, see `FormatOptions`
+//! [fmt]
+//! max_width = 80
+//! indent = 2
+//! annotation_style = "next-line"   # or "same-line"
+//! case_arm_alignment = "braces"    # or "unaligned"
+//! quote_break_threshold = 3
+//! trailing_newline = true
+//! ```
+//!
+//! The manifest is a small part of TOML: lines of `key = value` and
+//! `[section]` headers, the values strings, lists of strings, numbers or
+//! booleans. There are no imports, so each file
+//! is a module of its own and is checked alone. The entry op is also
+//! checked to never pop from an empty stack, see `check_no_underflow`.
+
+use crate::analysis::underflow::check_no_underflow;
+use crate::analyze::{analyze, AnalysisResult, AnalyzeOptions, Phase};
+use crate::diagnostics::Diagnostic;
+use crate::format::{AnnotationStyle, CaseArmAlignment, FormatOptions};
+use crate::syntax::ast::Span;
+use crate::typing::inference::Inference;
+use crate::typing::strict::StrictOptions;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// The name of the manifest file
+pub const MANIFEST: &str = "iv.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectOptions {
+    /// The directories searched for `.iv` files, relative to the project
+    pub roots: Vec<PathBuf>,
+    /// The file defining the entry op, none for a library
+    pub entry: Option<PathBuf>,
+    pub entry_op: String,
+    pub strict: bool,
+    /// The `[fmt]` section
+    pub fmt: FormatOptions,
+}
+
+impl Default for ProjectOptions {
+    fn default() -> Self {
+        ProjectOptions {
+            roots: vec![PathBuf::from(".")],
+            entry: None,
+            entry_op: "main".to_owned(),
+            strict: false,
+            fmt: FormatOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ProjectError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A manifest line that does not parse or sets no known option
+    Manifest {
+        line: usize,
+        message: String,
+    },
+    /// The entry is not one of the files under the roots
+    EntryNotFound {
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ProjectError::Manifest { line, message } => {
+                write!(f, "{}:{}: {}", MANIFEST, line, message)
+            }
+            ProjectError::EntryNotFound { path } => write!(
+                f,
+                "the entry {} is not a .iv file under the roots",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// A string, `"..."` without escapes
+fn parse_string(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    (!inner.contains('"')).then(|| inner.to_owned())
+}
+
+fn parse_strings(value: &str) -> Option<Vec<String>> {
+    let inner = value.strip_prefix('[')?.strip_suffix(']')?.trim();
+    if inner.is_empty() {
+        return Some(vec![]);
+    }
+    let inner = inner.strip_suffix(',').unwrap_or(inner);
+    inner
+        .split(',')
+        .map(|item| parse_string(item.trim()))
+        .collect()
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_number(value: &str) -> Option<usize> {
+    value.parse().ok()
+}
+
+impl ProjectOptions {
+    /// The options the manifest's text sets, the defaults for the others
+    pub fn parse(text: &str) -> Result<Self, ProjectError> {
+        let mut options = ProjectOptions::default();
+        // the section the lines are in, none before the first header
+        let mut section = None;
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| ProjectError::Manifest {
+                line: i + 1,
+                message,
+            };
+            // a `#` in a string is taken as part of it
+            let line = match line.split_once('#') {
+                Some((before, _)) if before.matches('"').count() % 2 == 0 => before,
+                _ => line,
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                match name.trim() {
+                    "fmt" => section = Some("fmt"),
+                    name => return Err(error(format!("unknown section `{}`", name))),
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("expected `key = value`, found `{}`", line)));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let bad_value = |expected: &str| error(format!("`{}` takes {}", key, expected));
+            if section == Some("fmt") {
+                let fmt = &mut options.fmt;
+                match key {
+                    "max_width" => {
+                        fmt.max_width = parse_number(value).ok_or_else(|| bad_value("a number"))?;
+                    }
+                    "indent" => {
+                        fmt.indent = parse_number(value).ok_or_else(|| bad_value("a number"))?;
+                    }
+                    "annotation_style" => {
+                        fmt.annotation_style = match parse_string(value).as_deref() {
+                            Some("same-line") => AnnotationStyle::SameLine,
+                            Some("next-line") => AnnotationStyle::NextLine,
+                            _ => return Err(bad_value("\"same-line\" or \"next-line\"")),
+                        };
+                    }
+                    "case_arm_alignment" => {
+                        fmt.case_arm_alignment = match parse_string(value).as_deref() {
+                            Some("unaligned") => CaseArmAlignment::Unaligned,
+                            Some("braces") => CaseArmAlignment::Braces,
+                            _ => return Err(bad_value("\"unaligned\" or \"braces\"")),
+                        };
+                    }
+                    "quote_break_threshold" => {
+                        fmt.quote_break_threshold =
+                            parse_number(value).ok_or_else(|| bad_value("a number"))?;
+                    }
+                    "trailing_newline" => {
+                        fmt.trailing_newline =
+                            parse_bool(value).ok_or_else(|| bad_value("true or false"))?;
+                    }
+                    _ => return Err(error(format!("unknown option `{}` in [fmt]", key))),
+                }
+                continue;
+            }
+            match key {
+                "roots" => {
+                    let roots = parse_strings(value).ok_or_else(|| bad_value("a list of paths"))?;
+                    options.roots = roots.into_iter().map(PathBuf::from).collect();
+                }
+                "entry" => {
+                    let entry = parse_string(value).ok_or_else(|| bad_value("a path"))?;
+                    options.entry = Some(PathBuf::from(entry));
+                }
+                "entry_op" => {
+                    options.entry_op = parse_string(value).ok_or_else(|| bad_value("a name"))?;
+                }
+                "strict" => {
+                    options.strict = parse_bool(value).ok_or_else(|| bad_value("true or false"))?;
+                }
+                _ => return Err(error(format!("unknown option `{}`", key))),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// A source file of a project
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// Relative to the project's directory
+    pub path: PathBuf,
+    pub source: String,
+}
+
+#[derive(Debug)]
+pub struct Project {
+    /// The directory holding the manifest
+    pub dir: PathBuf,
+    pub options: ProjectOptions,
+    /// Every `.iv` file under the roots, ordered by path
+    pub files: Vec<SourceFile>,
+}
+
+/// The path without `.` components, so that paths written differently
+/// compare the same
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// The `.iv` files under the directory, relative to `base`
+fn discover(base: &Path, dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), ProjectError> {
+    let io_error = |error| ProjectError::Io {
+        path: base.join(dir),
+        error,
+    };
+    for entry in fs::read_dir(base.join(dir)).map_err(io_error)? {
+        let path = dir.join(entry.map_err(io_error)?.file_name());
+        if base.join(&path).is_dir() {
+            discover(base, &path, out)?;
+        } else if path.extension().is_some_and(|ext| ext == "iv") {
+            out.push(normalize(&path));
+        }
+    }
+    Ok(())
+}
+
+impl Project {
+    /// Reads the manifest in the directory and the source files under its
+    /// roots. `path` may also be the manifest itself.
+    pub fn load(path: &Path) -> Result<Project, ProjectError> {
+        let (dir, manifest) = if path.is_dir() {
+            (path.to_owned(), path.join(MANIFEST))
+        } else {
+            let dir = path.parent().unwrap_or(Path::new(".")).to_owned();
+            (dir, path.to_owned())
+        };
+        let read = |path: &Path| {
+            fs::read_to_string(path).map_err(|error| ProjectError::Io {
+                path: path.to_owned(),
+                error,
+            })
+        };
+        let options = ProjectOptions::parse(&read(&manifest)?)?;
+        let mut paths = vec![];
+        for root in options.roots.iter() {
+            discover(&dir, root, &mut paths)?;
+        }
+        paths.sort();
+        paths.dedup();
+        if let Some(entry) = &options.entry {
+            if !paths.contains(&normalize(entry)) {
+                return Err(ProjectError::EntryNotFound {
+                    path: entry.clone(),
+                });
+            }
+        }
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let source = read(&dir.join(&path))?;
+                Ok(SourceFile { path, source })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Project {
+            dir,
+            options,
+            files,
+        })
+    }
+
+    /// Checks every file, the entry's entry op included once the entry
+    /// typechecks
+    pub fn check(&self) -> ProjectReport {
+        let strict = if self.options.strict {
+            StrictOptions::all()
+        } else {
+            StrictOptions::default()
+        };
+        let entry = self.options.entry.as_deref().map(normalize);
+        let files = self
+            .files
+            .iter()
+            .map(|file| {
+                let opts = AnalyzeOptions {
+                    keep_ast: true,
+                    strict: strict.clone(),
+                    ..AnalyzeOptions::default()
+                };
+                let mut result = analyze(&file.source, opts);
+                if entry.as_ref() == Some(&file.path) && result.is_ok() {
+                    let diagnostics = self.check_entry(&file.source, &result);
+                    result
+                        .diagnostics
+                        .extend(diagnostics.into_iter().map(|d| (Phase::Typecheck, d)));
+                }
+                FileReport {
+                    path: file.path.clone(),
+                    result,
+                }
+            })
+            .collect();
+        ProjectReport { files }
+    }
+
+    fn check_entry(&self, source: &str, result: &AnalysisResult) -> Vec<Diagnostic> {
+        let Some(module) = &result.module else {
+            return vec![];
+        };
+        let entry_op = &self.options.entry_op;
+        match check_no_underflow(&Inference::new(module), entry_op) {
+            Some(Ok(underflows)) => underflows.iter().map(Diagnostic::from_underflow).collect(),
+            Some(Err(err)) => vec![Diagnostic::from_inference_error(source, module, &err)],
+            None => vec![Diagnostic {
+                span: Span::new(0, 0),
+                message: format!("the entry defines no `{}`", entry_op),
+                suggestions: vec![],
+                code: None,
+            }],
+        }
+    }
+}
+
+pub struct FileReport {
+    /// Relative to the project's directory
+    pub path: PathBuf,
+    pub result: AnalysisResult,
+}
+
+/// The outcome of checking every file of a project, the files ordered by
+/// path
+pub struct ProjectReport {
+    pub files: Vec<FileReport>,
+}
+
+impl ProjectReport {
+    pub fn is_ok(&self) -> bool {
+        self.files.iter().all(|file| file.result.is_ok())
+    }
+
+    /// Every diagnostic, each prefixed with the path of its file
+    pub fn render(&self, project: &Project) -> String {
+        let mut out = String::new();
+        for (file, source) in self.files.iter().zip(project.files.iter()) {
+            for (_, diagnostic) in file.result.diagnostics.iter() {
+                let rendered = diagnostic.render(&source.source);
+                out.push_str(&format!("{}:{}", file.path.display(), rendered));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifests() {
+        let text = "
+            # a comment
+            roots = [\"src\", \"lib#1\",]
+            entry = \"src/main.iv\" # the main file
+            strict = true
+        ";
+        assert_eq!(
+            ProjectOptions::parse(text).unwrap(),
+            ProjectOptions {
+                roots: vec![PathBuf::from("src"), PathBuf::from("lib#1")],
+                entry: Some(PathBuf::from("src/main.iv")),
+                entry_op: "main".to_owned(),
+                strict: true,
+                fmt: FormatOptions::default(),
+            }
+        );
+        let error = |text| ProjectOptions::parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("strict = yes"),
+            "iv.toml:1: `strict` takes true or false"
+        );
+        assert_eq!(
+            error("\nroots = src"),
+            "iv.toml:2: `roots` takes a list of paths"
+        );
+        assert_eq!(error("opt = \"x\""), "iv.toml:1: unknown option `opt`");
+        assert_eq!(
+            error("strict"),
+            "iv.toml:1: expected `key = value`, found `strict`"
+        );
+    }
+
+    #[test]
+    fn format_sections() {
+        let text = "
+            strict = true
+            [fmt]
+            max_width = 80
+            annotation_style = \"next-line\" # the body below the annotation
+            case_arm_alignment = \"braces\"
+            trailing_newline = false
+        ";
+        let options = ProjectOptions::parse(text).unwrap();
+        assert!(options.strict);
+        assert_eq!(
+            options.fmt,
+            FormatOptions {
+                max_width: 80,
+                annotation_style: AnnotationStyle::NextLine,
+                case_arm_alignment: CaseArmAlignment::Braces,
+                trailing_newline: false,
+                ..FormatOptions::default()
+            }
+        );
+        let error = |text| ProjectOptions::parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("[fmt]\nindent = -1"),
+            "iv.toml:2: `indent` takes a number"
+        );
+        assert_eq!(
+            error("[fmt]\nstrict = true"),
+            "iv.toml:2: unknown option `strict` in [fmt]"
+        );
+        assert_eq!(error("[lint]"), "iv.toml:1: unknown section `lint`");
+    }
+}
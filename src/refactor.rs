@@ -0,0 +1,538 @@
+use crate::syntax::ast::*;
+use crate::syntax::highlight::{self, TokenKind};
+use crate::typing::inference::is_unchecked;
+use crate::typing::prelude_types;
+use std::fmt;
+
+/// Replacement of a source range, edits returned together never overlap
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    /// Applies edits that do not overlap, in any order
+    pub fn apply_all(source: &str, edits: &[TextEdit]) -> String {
+        let mut edits: Vec<_> = edits.iter().collect();
+        edits.sort_by_key(|edit| edit.span.start);
+        let mut out = String::new();
+        let mut at = 0;
+        for edit in edits {
+            out.push_str(&source[at..edit.span.start]);
+            out.push_str(&edit.replacement);
+            at = edit.span.end;
+        }
+        out.push_str(&source[at..]);
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RenameError {
+    UnknownOp {
+        name: String,
+    },
+    UnknownConstructor {
+        name: String,
+    },
+    /// Not a lowercase name, or a keyword
+    InvalidName {
+        name: String,
+    },
+    CollidesWithPrelude {
+        name: String,
+    },
+    CollidesWithOp {
+        name: String,
+    },
+    CollidesWithConstant {
+        name: String,
+    },
+    CollidesWithConstructor {
+        name: String,
+        data: String,
+    },
+    /// Renaming adds or removes the `noc` prefix of an op without
+    /// `#[unchecked]`
+    ChangesChecking {
+        name: String,
+    },
+    /// List literals of the module desugar to the constructor
+    UsedByListLiterals {
+        name: String,
+    },
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenameError::UnknownOp { name } => write!(f, "no op `{}` is defined", name),
+            RenameError::UnknownConstructor { name } => {
+                write!(f, "no constructor `{}` is defined", name)
+            }
+            RenameError::InvalidName { name } => {
+                write!(f, "`{}` is not a valid op or constructor name", name)
+            }
+            RenameError::CollidesWithPrelude { name } => {
+                write!(f, "`{}` is a prelude op", name)
+            }
+            RenameError::CollidesWithOp { name } => write!(f, "op `{}` is already defined", name),
+            RenameError::CollidesWithConstant { name } => {
+                write!(f, "constant `{}` is already defined", name)
+            }
+            RenameError::CollidesWithConstructor { name, data } => {
+                write!(f, "`{}` is already a constructor of `{}`", name, data)
+            }
+            RenameError::ChangesChecking { name } => write!(
+                f,
+                "renaming to `{}` changes whether the op is checked, the `noc` prefix marks \
+                 unchecked ops",
+                name
+            ),
+            RenameError::UsedByListLiterals { name } => {
+                write!(f, "the module's list literals are built with `{}`", name)
+            }
+        }
+    }
+}
+
+fn check_new_name(module: &Module, new: &str) -> Result<(), RenameError> {
+    let name = || new.to_owned();
+    match highlight::lex(new).as_slice() {
+        [token] if token.kind == TokenKind::Name && token.span.end == new.len() => (),
+        _ => return Err(RenameError::InvalidName { name: name() }),
+    }
+    if prelude_types::get(new).is_some() {
+        return Err(RenameError::CollidesWithPrelude { name: name() });
+    }
+    if let Some(data) = data_of(module, new) {
+        return Err(RenameError::CollidesWithConstructor {
+            name: name(),
+            data: data.to_owned(),
+        });
+    }
+    if module.op_defs.contains_key(new) {
+        return Err(RenameError::CollidesWithOp { name: name() });
+    }
+    if module.const_defs.contains_key(new) {
+        return Err(RenameError::CollidesWithConstant { name: name() });
+    }
+    Ok(())
+}
+
+/// The data type defining the constructor
+fn data_of<'m>(module: &'m Module, constr: &str) -> Option<&'m str> {
+    module
+        .data_defs_in_source_order()
+        .into_iter()
+        .find(|(_, data_def)| data_def.constrs.contains_key(constr))
+        .map(|(data_name, _)| data_name.as_str())
+}
+
+/// What a use of a name in a body refers to, looked up the way inference
+/// does
+#[derive(PartialEq)]
+enum Referent {
+    Prelude,
+    Constructor,
+    Constant,
+    Op,
+}
+
+fn referent(module: &Module, name: &str) -> Option<Referent> {
+    if prelude_types::get(name).is_some() {
+        Some(Referent::Prelude)
+    } else if data_of(module, name).is_some() {
+        Some(Referent::Constructor)
+    } else if module.const_defs.contains_key(name) {
+        Some(Referent::Constant)
+    } else if module.op_defs.contains_key(name) {
+        Some(Referent::Op)
+    } else {
+        None
+    }
+}
+
+/// Spans of the names the closure picks out in the ops, nested ops included
+fn collect(ops: &[Op], spans: &mut Vec<Span>, pick: &impl Fn(Name) -> bool) {
+    for op in ops {
+        match op {
+            Op::Literal { .. } => (),
+            Op::Name { value, span } => {
+                if pick(Name::Op(value)) {
+                    spans.push(span.clone());
+                }
+            }
+            Op::Quote { value, .. } => collect(value, spans, pick),
+            Op::Case { head_arm, arms, .. } => {
+                for arm in std::iter::once(head_arm).chain(arms) {
+                    if pick(Name::Arm(&arm.constr)) {
+                        spans.push(arm.constr_span.clone());
+                    }
+                    collect(&arm.body, spans, pick);
+                }
+            }
+            Op::Case2 { arms, .. } => {
+                for arm in arms {
+                    for (pattern, span) in arm.patterns.iter().zip(&arm.pattern_spans) {
+                        if matches!(pattern, Pattern::Constr(c) if pick(Name::Arm(c))) {
+                            spans.push(span.clone());
+                        }
+                    }
+                    collect(&arm.body, spans, pick);
+                }
+            }
+            Op::List { items, span } => {
+                if pick(Name::List) {
+                    spans.push(span.clone());
+                }
+                for item in items {
+                    collect(item, spans, pick);
+                }
+            }
+        }
+    }
+}
+
+/// A name in a body, either an op call or a constructor of a case arm, or
+/// a list literal naming `empty` and `cons` without spelling them out
+enum Name<'a> {
+    Op(&'a str),
+    Arm(&'a str),
+    List,
+}
+
+fn edits(
+    module: &Module,
+    mut spans: Vec<Span>,
+    pick: impl Fn(Name) -> bool,
+    new: &str,
+) -> Vec<TextEdit> {
+    for (_, op_def) in module.op_defs_in_source_order() {
+        collect(&op_def.body, &mut spans, &pick);
+    }
+    for (_, const_def) in module.const_defs_in_source_order() {
+        collect(&const_def.body, &mut spans, &pick);
+    }
+    spans
+        .into_iter()
+        .map(|span| TextEdit {
+            span,
+            replacement: new.to_owned(),
+        })
+        .collect()
+}
+
+/// Edits renaming the op `old` to `new`, at its definitions and every use
+pub fn rename_op(module: &Module, old: &str, new: &str) -> Result<Vec<TextEdit>, RenameError> {
+    // every definition of an overloaded name, the first one being its key
+    let definitions: Vec<_> = module
+        .op_defs_in_source_order()
+        .into_iter()
+        .filter(|(key, _)| overloaded_name(key) == old)
+        .map(|(_, op_def)| op_def.name_span.clone())
+        .collect();
+    let op_def = (module.op_defs.get(old))
+        .filter(|_| !old.contains('#'))
+        .ok_or_else(|| RenameError::UnknownOp {
+            name: old.to_owned(),
+        })?;
+    if old == new {
+        return Ok(vec![]);
+    }
+    check_new_name(module, new)?;
+    if is_unchecked(old, op_def) != is_unchecked(new, op_def) {
+        return Err(RenameError::ChangesChecking {
+            name: new.to_owned(),
+        });
+    }
+    // uses of a name shadowed by the prelude or a constructor are not uses
+    // of the op
+    let used = referent(module, old) == Some(Referent::Op);
+    let pick = |name: Name| matches!(name, Name::Op(n) if used && n == old);
+    Ok(edits(module, definitions, pick, new))
+}
+
+/// Edits renaming the constructor `old` to `new`, at its definition, where
+/// it is called and in case arms. The new name may not be taken by any
+/// constructor, including the other ones of the same data type, since
+/// constructors share a namespace with ops.
+pub fn rename_constructor(
+    module: &Module,
+    old: &str,
+    new: &str,
+) -> Result<Vec<TextEdit>, RenameError> {
+    let definitions: Vec<_> = module
+        .data_defs_in_source_order()
+        .into_iter()
+        .filter_map(|(_, data_def)| data_def.constrs.get(old))
+        .map(|constr| constr.name_span.clone())
+        .collect();
+    if definitions.is_empty() {
+        return Err(RenameError::UnknownConstructor {
+            name: old.to_owned(),
+        });
+    }
+    if old == new {
+        return Ok(vec![]);
+    }
+    check_new_name(module, new)?;
+    let mut lists = vec![];
+    let bodies = module.op_defs.values().map(|op_def| &op_def.body);
+    for body in bodies.chain(module.const_defs.values().map(|c| &c.body)) {
+        collect(body, &mut lists, &|name| matches!(name, Name::List));
+    }
+    if ["empty", "cons"].contains(&old) && !lists.is_empty() {
+        return Err(RenameError::UsedByListLiterals {
+            name: old.to_owned(),
+        });
+    }
+    let called = referent(module, old) == Some(Referent::Constructor);
+    let pick = |name: Name| match name {
+        Name::Op(n) => called && n == old,
+        Name::Arm(n) => n == old,
+        Name::List => false,
+    };
+    Ok(edits(module, definitions, pick, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desugar::desugar;
+    use crate::syntax::parse;
+    use crate::typing::inference::Inference;
+    use crate::typing::report::OpOutcome;
+    use std::fs;
+    use std::path::Path;
+
+    const SOURCE: &str = "
+        data Maybe a: nothing, [a] just.
+        data Bool: true, false.
+        const none [Maybe Bool]: nothing.
+        define [Maybe a] is-just [Bool]: case { just { pop true }, nothing { false } }.
+        define [Maybe a, Maybe b] both [Bool]:
+            case2 { just just { pop pop true }, _ _ { pop pop false } }.
+        define [] main [Bool, Bool, [][Bool]]:
+            nothing is-just (nothing is-just) 1 just is-just.
+        ";
+
+    /// The outcome of every op, without spans since renaming moves them
+    fn report(source: &str) -> Vec<String> {
+        let module = desugar(parse(source).unwrap()).unwrap();
+        let report = Inference::new(&module).report();
+        let errors = report.errors.iter().map(|err| err.message());
+        report
+            .ops
+            .iter()
+            .map(|op| match &op.outcome {
+                OpOutcome::Inferred(t) => format!("{}: {}", op.name, t.canonical()),
+                OpOutcome::Failed(err) | OpOutcome::ExpectedError(err) => {
+                    format!("{}: {}", op.name, err.error)
+                }
+                outcome => format!("{}: {:?}", op.name, outcome),
+            })
+            .chain(errors)
+            .collect()
+    }
+
+    fn renamed(
+        rename: fn(&Module, &str, &str) -> Result<Vec<TextEdit>, RenameError>,
+        old: &str,
+        new: &str,
+    ) -> Result<String, RenameError> {
+        let module = parse(SOURCE).unwrap();
+        let edits = rename(&module, old, new)?;
+        Ok(TextEdit::apply_all(SOURCE, &edits))
+    }
+
+    #[test]
+    fn renames_an_op_everywhere() {
+        let source = renamed(rename_op, "is-just", "present").unwrap();
+        assert!(!source.contains("is-just"));
+        assert_eq!(source.matches("present").count(), 4);
+        let expected: Vec<_> = report(SOURCE)
+            .iter()
+            .map(|line| line.replace("is-just", "present"))
+            .collect();
+        assert_eq!(report(&source), expected);
+    }
+
+    #[test]
+    fn renames_a_constructor_everywhere() {
+        let source = renamed(rename_constructor, "just", "some").unwrap();
+        let words: Vec<_> = source
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .collect();
+        assert!(!words.contains(&"just"));
+        // definition, call, case arm and both case2 patterns
+        assert_eq!(source.matches("some").count(), 5);
+        assert!(source.contains("is-just"));
+        assert_eq!(report(&source), report(SOURCE));
+    }
+
+    #[test]
+    fn rejects_collisions() {
+        let err = |rename, old, new| renamed(rename, old, new).unwrap_err();
+        assert_eq!(
+            err(rename_op, "both", "dup"),
+            RenameError::CollidesWithPrelude {
+                name: "dup".to_owned()
+            }
+        );
+        assert_eq!(
+            err(rename_op, "both", "main"),
+            RenameError::CollidesWithOp {
+                name: "main".to_owned()
+            }
+        );
+        assert_eq!(
+            err(rename_op, "both", "none"),
+            RenameError::CollidesWithConstant {
+                name: "none".to_owned()
+            }
+        );
+        assert_eq!(
+            err(rename_op, "both", "true"),
+            RenameError::CollidesWithConstructor {
+                name: "true".to_owned(),
+                data: "Bool".to_owned()
+            }
+        );
+        assert_eq!(
+            err(rename_constructor, "just", "nothing"),
+            RenameError::CollidesWithConstructor {
+                name: "nothing".to_owned(),
+                data: "Maybe".to_owned()
+            }
+        );
+        assert_eq!(
+            err(rename_constructor, "just", "both"),
+            RenameError::CollidesWithOp {
+                name: "both".to_owned()
+            }
+        );
+        for invalid in ["Just", "case", "a b", "", "1"] {
+            assert_eq!(
+                err(rename_op, "both", invalid),
+                RenameError::InvalidName {
+                    name: invalid.to_owned()
+                }
+            );
+        }
+        assert_eq!(
+            err(rename_op, "both", "nocboth"),
+            RenameError::ChangesChecking {
+                name: "nocboth".to_owned()
+            }
+        );
+        let source = "#[unchecked] define [] both []:.";
+        let module = parse(source).unwrap();
+        assert!(rename_op(&module, "both", "nocboth").is_ok());
+        assert!(matches!(
+            err(rename_op, "just", "x"),
+            RenameError::UnknownOp { .. }
+        ));
+        assert!(matches!(
+            err(rename_constructor, "main", "x"),
+            RenameError::UnknownConstructor { .. }
+        ));
+    }
+
+    #[test]
+    fn list_literals_keep_their_constructors() {
+        let source = "data List a: empty, [a, List a] cons.
+            define [] f [List Int]: [1, 2].
+            define [] g [List Int]: empty.";
+        let module = parse(source).unwrap();
+        assert_eq!(
+            rename_constructor(&module, "cons", "push").unwrap_err(),
+            RenameError::UsedByListLiterals {
+                name: "cons".to_owned()
+            }
+        );
+        let edits = rename_op(&module, "f", "h").unwrap();
+        assert_eq!(edits.len(), 1);
+        let module =
+            parse("data List a: empty, [a, List a] cons. define [] g [List Int]: empty.").unwrap();
+        assert!(rename_constructor(&module, "empty", "nil").is_ok());
+    }
+
+    #[test]
+    fn shadowed_ops_only_rename_the_definition() {
+        // calls of `just` construct, they do not call the op
+        let source = "
+            data Maybe a: nothing, [a] just.
+            define [a] just [a]:.
+            define [] main [Maybe Int]: 1 just.
+            ";
+        let module = parse(source).unwrap();
+        let edits = rename_op(&module, "just", "other").unwrap();
+        assert_eq!(edits.len(), 1);
+        assert!(TextEdit::apply_all(source, &edits).contains("1 just."));
+    }
+
+    #[test]
+    fn renaming_to_itself_changes_nothing() {
+        assert!(renamed(rename_op, "main", "main").unwrap() == SOURCE);
+        assert!(renamed(rename_constructor, "true", "true").unwrap() == SOURCE);
+    }
+
+    /// The report after the rename, ops and names in messages renamed
+    fn renamed_report(before: &[String], old: &str, new: &str, op: bool) -> Vec<String> {
+        before
+            .iter()
+            .map(|line| match line.split_once(": ") {
+                Some((name, rest)) if op && overloaded_name(name) == old => {
+                    format!("{}{}: {}", new, &name[old.len()..], rest)
+                }
+                _ => line.to_owned(),
+            })
+            .map(|line| line.replace(&format!("`{}`", old), &format!("`{}`", new)))
+            // the definitions an overload error lists
+            .map(|line| line.replace(&format!("] {} [", old), &format!("] {} [", new)))
+            .collect()
+    }
+
+    #[test]
+    fn renaming_in_fixtures_keeps_the_report() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for dir in ["examples", "tests/cases"] {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|ext| ext != "iv") {
+                    continue;
+                }
+                let source = fs::read_to_string(&path).unwrap();
+                let Ok(module) = parse(&source) else {
+                    continue;
+                };
+                let before = report(&source);
+                for old in module.op_defs.keys().filter(|key| !key.contains('#')) {
+                    let new = if is_unchecked(old, &module.op_defs[old]) {
+                        "nocrenamed-op"
+                    } else {
+                        "renamed-op"
+                    };
+                    let edits = rename_op(&module, old, new).unwrap();
+                    let renamed = TextEdit::apply_all(&source, &edits);
+                    let expected = renamed_report(&before, old, new, true);
+                    assert_eq!(report(&renamed), expected, "{} in {}", old, path.display());
+                }
+                let constrs = module.data_defs.values().flat_map(|d| d.constrs.keys());
+                for old in constrs {
+                    let new = "renamed-constr";
+                    let edits = match rename_constructor(&module, old, new) {
+                        Err(RenameError::UsedByListLiterals { .. }) => continue,
+                        edits => edits.unwrap(),
+                    };
+                    let renamed = TextEdit::apply_all(&source, &edits);
+                    let expected = renamed_report(&before, old, new, false);
+                    assert_eq!(report(&renamed), expected, "{} in {}", old, path.display());
+                }
+            }
+        }
+    }
+}
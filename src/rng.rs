@@ -0,0 +1,41 @@
+//! The seeded generator of the tests that build their inputs at random, so
+//! that a failure reproduces from its seed
+
+use crate::typing::types::{OpType, Type};
+
+pub(crate) struct Rng(pub u64);
+
+impl Rng {
+    /// A number less than `n`
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize % n
+    }
+
+    /// A type of a few variables, `Int`, `Maybe` and quotes
+    pub fn ty(&mut self, vars: &[&str], depth: usize) -> Type {
+        match self.below(if depth == 0 { 2 } else { 4 }) {
+            0 => Type::Poly(vars[self.below(vars.len())].to_owned()),
+            1 => Type::Mono("Int".to_owned()),
+            2 => Type::App(
+                Box::new(Type::Mono("Maybe".to_owned())),
+                Box::new(self.ty(vars, depth - 1)),
+            ),
+            _ => Type::Op(self.optype(vars, depth - 1)),
+        }
+    }
+
+    /// An op type of up to three values on each side
+    pub fn optype(&mut self, vars: &[&str], depth: usize) -> OpType {
+        let stack = |rng: &mut Rng| -> Vec<Type> {
+            (0..rng.below(4)).map(|_| rng.ty(vars, depth)).collect()
+        };
+        OpType {
+            pre: stack(self),
+            post: stack(self),
+        }
+    }
+}
@@ -0,0 +1,522 @@
+//! Shrinking a module that shows an inference bug to a small reproducer.
+//! Definitions, constructors, pragmas, case arms and body ops are removed
+//! one at a time, each candidate printed and parsed back, and kept when
+//! the predicate still holds of it. Working on printed source keeps every
+//! candidate parseable and the reproducer ready to paste.
+
+use crate::desugar::desugar;
+use crate::syntax::ast::*;
+use crate::syntax::parse;
+use crate::typing::inference::Inference;
+use crate::typing::report::{OpOutcome, TypecheckReport};
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::once;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Sets up one of the inference configurations a predicate compares
+pub type Configure = Box<dyn for<'m> Fn(Inference<'m>) -> Inference<'m>>;
+
+/// What a reproducer has to keep doing while it is shrunk
+pub enum Predicate {
+    /// Checking the module panics
+    Panics,
+    /// Checking the module reports an error of the code
+    ErrorCode(String),
+    /// Checking the module gives different reports under two configurations
+    ReportsDiffer(Configure, Configure),
+}
+
+impl Predicate {
+    /// Whether the predicate holds of the module as parsed, desugaring and
+    /// checking it. A module that does not desugar shows no inference bug.
+    pub fn holds(&self, module: &Module) -> bool {
+        let Ok(module) = desugar(module.clone()) else {
+            return false;
+        };
+        let check = |configure: &dyn Fn(Inference) -> Inference| {
+            catch_unwind(AssertUnwindSafe(|| {
+                summary(&configure(Inference::new(&module)).report())
+            }))
+        };
+        match self {
+            Predicate::Panics => check(&|inference| inference).is_err(),
+            Predicate::ErrorCode(code) => {
+                let error = format!("error: {}", code);
+                check(&|inference| inference)
+                    .is_ok_and(|summary| summary.iter().any(|line| line.ends_with(&error)))
+            }
+            Predicate::ReportsDiffer(a, b) => match (check(a), check(b)) {
+                (Ok(a), Ok(b)) => a != b,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// A report as lines comparable across configurations, the error codes
+/// and the canonical types without spans or fresh variable names
+fn summary(report: &TypecheckReport) -> Vec<String> {
+    let errors = report
+        .errors
+        .iter()
+        .map(|err| format!("error: {}", err.code()));
+    let ops = report.ops.iter().map(|op| match &op.outcome {
+        OpOutcome::Inferred(optype) => format!("{}: {}", op.name, optype.canonical()),
+        OpOutcome::Failed(err) => format!("{}: error: {}", op.name, err.code()),
+        OpOutcome::ExpectedError(err) => format!("{}: expected: {}", op.name, err.code()),
+        OpOutcome::Unchecked => format!("{}: unchecked", op.name),
+        OpOutcome::Cancelled => format!("{}: cancelled", op.name),
+        OpOutcome::TimedOut => format!("{}: timed out", op.name),
+        OpOutcome::Skipped(_) => format!("{}: skipped", op.name),
+        OpOutcome::Declared => format!("{}: declared", op.name),
+    });
+    errors.chain(ops).collect()
+}
+
+/// The kinds of removal, tried in this order so that big pieces go first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Removal {
+    OpDef,
+    ConstDef,
+    DataDef,
+    Constructor,
+    Pragma,
+    Attribute,
+    CaseArm,
+    BodyOp,
+}
+
+const REMOVALS: [Removal; 8] = [
+    Removal::OpDef,
+    Removal::ConstDef,
+    Removal::DataDef,
+    Removal::Constructor,
+    Removal::Pragma,
+    Removal::Attribute,
+    Removal::CaseArm,
+    Removal::BodyOp,
+];
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ShrinkError {
+    /// The predicate does not hold of the module to begin with
+    DoesNotHold,
+    /// The module printed does not parse, `printed` is what it printed as
+    Unprintable { printed: String },
+    /// The predicate holds of the module but not of it printed, the printed
+    /// module differs in something the predicate depends on
+    LostInPrinting { printed: String },
+}
+
+impl fmt::Display for ShrinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShrinkError::DoesNotHold => write!(f, "the predicate does not hold of the module"),
+            ShrinkError::Unprintable { .. } => {
+                write!(f, "the module does not parse back once printed")
+            }
+            ShrinkError::LostInPrinting { .. } => {
+                write!(f, "the predicate holds of the module but not of it printed")
+            }
+        }
+    }
+}
+
+/// The smallest module found, as printed source
+#[derive(Debug)]
+pub struct Shrunk {
+    pub source: String,
+    pub module: Module,
+    /// How many removals were kept
+    pub removals: usize,
+    /// How many candidates were checked against the predicate
+    pub candidates: usize,
+}
+
+/// Removes parts of the module while `holds` stays true of it, until no
+/// single removal keeps it true
+pub fn shrink(
+    module: &Module,
+    mut holds: impl FnMut(&Module) -> bool,
+) -> Result<Shrunk, ShrinkError> {
+    if !holds(module) {
+        return Err(ShrinkError::DoesNotHold);
+    }
+    let printed = module.to_string();
+    let Ok(current) = parse(&printed) else {
+        return Err(ShrinkError::Unprintable { printed });
+    };
+    if !holds(&current) {
+        return Err(ShrinkError::LostInPrinting { printed });
+    }
+    let mut shrunk = Shrunk {
+        source: printed,
+        module: current,
+        removals: 0,
+        candidates: 0,
+    };
+    loop {
+        let before = shrunk.removals;
+        for removal in REMOVALS {
+            let mut n = 0;
+            loop {
+                let mut candidate = shrunk.module.clone();
+                if !remove(&mut candidate, removal, n) {
+                    break;
+                }
+                let printed = candidate.to_string();
+                shrunk.candidates += 1;
+                match parse(&printed) {
+                    Ok(candidate) if holds(&candidate) => {
+                        shrunk.source = printed;
+                        shrunk.module = candidate;
+                        shrunk.removals += 1;
+                    }
+                    _ => n += 1,
+                }
+            }
+        }
+        if shrunk.removals == before {
+            return Ok(shrunk);
+        }
+    }
+}
+
+/// Removes the `n`th thing of the kind, counted in source order, false
+/// when there are no more than `n`
+fn remove(module: &mut Module, removal: Removal, n: usize) -> bool {
+    match removal {
+        Removal::OpDef => remove_def(&mut module.op_defs, n, |op_def| &op_def.span),
+        Removal::ConstDef => remove_def(&mut module.const_defs, n, |const_def| &const_def.span),
+        Removal::DataDef => remove_def(&mut module.data_defs, n, |data_def| &data_def.span),
+        Removal::Constructor => {
+            let mut n = n;
+            for data_def in defs_mut(&mut module.data_defs, |data_def| &data_def.span) {
+                if n < data_def.constrs.len() {
+                    return remove_def(&mut data_def.constrs, n, |constr| &constr.span);
+                }
+                n -= data_def.constrs.len();
+            }
+            false
+        }
+        Removal::Pragma => {
+            let pragmas = module
+                .data_defs
+                .values_mut()
+                .map(|data_def| (data_def.span.start, &mut data_def.pragmas))
+                .chain(
+                    module
+                        .op_defs
+                        .values_mut()
+                        .map(|op_def| (op_def.span.start, &mut op_def.pragmas)),
+                )
+                .collect();
+            remove_marker(pragmas, n)
+        }
+        Removal::Attribute => {
+            let attributes = module
+                .data_defs
+                .values_mut()
+                .map(|data_def| (data_def.span.start, &mut data_def.attributes))
+                .chain(
+                    module
+                        .op_defs
+                        .values_mut()
+                        .map(|op_def| (op_def.span.start, &mut op_def.attributes)),
+                )
+                .collect();
+            remove_marker(attributes, n)
+        }
+        Removal::CaseArm | Removal::BodyOp => {
+            let mut n = Some(n);
+            for body in bodies_mut(module) {
+                if removal == Removal::CaseArm {
+                    remove_arm(body, &mut n);
+                } else {
+                    remove_op(body, &mut n);
+                }
+                if n.is_none() {
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+/// Removes the `n`th of the pragmas or attributes of definitions, the
+/// lists keyed by where their definition starts
+fn remove_marker<T>(mut lists: Vec<(usize, &mut Vec<T>)>, mut n: usize) -> bool {
+    lists.sort_by_key(|(start, _)| *start);
+    for (_, list) in lists {
+        if n < list.len() {
+            list.remove(n);
+            return true;
+        }
+        n -= list.len();
+    }
+    false
+}
+
+fn defs_mut<T>(defs: &mut HashMap<String, T>, span: impl Fn(&T) -> &Span) -> Vec<&mut T> {
+    let mut defs: Vec<_> = defs.values_mut().collect();
+    defs.sort_by_key(|def| span(def).start);
+    defs
+}
+
+fn remove_def<T>(defs: &mut HashMap<String, T>, n: usize, span: impl Fn(&T) -> &Span) -> bool {
+    let mut names: Vec<_> = defs
+        .iter()
+        .map(|(name, def)| (span(def).start, name))
+        .collect();
+    names.sort();
+    let Some((_, name)) = names.get(n) else {
+        return false;
+    };
+    let name = (*name).clone();
+    defs.remove(&name);
+    true
+}
+
+/// The bodies of the op and constant definitions, in source order
+fn bodies_mut(module: &mut Module) -> Vec<&mut Vec<Op>> {
+    let mut bodies: Vec<_> = module
+        .op_defs
+        .values_mut()
+        .map(|op_def| (op_def.span.start, &mut op_def.body))
+        .chain(
+            module
+                .const_defs
+                .values_mut()
+                .map(|const_def| (const_def.span.start, &mut const_def.body)),
+        )
+        .collect();
+    bodies.sort_by_key(|(start, _)| *start);
+    bodies.into_iter().map(|(_, body)| body).collect()
+}
+
+/// The sequences of ops nested in the op, in source order
+fn nested_mut(op: &mut Op) -> Vec<&mut Vec<Op>> {
+    match op {
+        Op::Quote { value, .. } => vec![value],
+        Op::Case { head_arm, arms, .. } => once(head_arm)
+            .chain(arms)
+            .map(|arm| &mut arm.body)
+            .collect(),
+        Op::Case2 { arms, .. } => arms.iter_mut().map(|arm| &mut arm.body).collect(),
+        Op::List { items, .. } => items.iter_mut().collect(),
+        Op::Literal { .. } | Op::Name { .. } => vec![],
+    }
+}
+
+/// Removes the `n`th op of the sequence and the ones nested in it,
+/// counting down `n` and setting it to `None` once removed
+fn remove_op(ops: &mut Vec<Op>, n: &mut Option<usize>) {
+    for i in 0..ops.len() {
+        match n {
+            Some(0) => {
+                ops.remove(i);
+                *n = None;
+                return;
+            }
+            Some(k) => *k -= 1,
+            None => return,
+        }
+        for nested in nested_mut(&mut ops[i]) {
+            remove_op(nested, n);
+        }
+    }
+}
+
+/// Removes the `n`th case arm of the sequence and the ones nested in it,
+/// leaving every case at least one arm
+fn remove_arm(ops: &mut [Op], n: &mut Option<usize>) {
+    for op in ops.iter_mut() {
+        let removable = match op {
+            Op::Case { arms, .. } if !arms.is_empty() => arms.len() + 1,
+            Op::Case2 { arms, .. } if arms.len() > 1 => arms.len(),
+            _ => 0,
+        };
+        match n {
+            Some(k) if *k < removable => {
+                match op {
+                    Op::Case { head_arm, arms, .. } if *k == 0 => *head_arm = arms.remove(0),
+                    Op::Case { arms, .. } => {
+                        arms.remove(*k - 1);
+                    }
+                    Op::Case2 { arms, .. } => {
+                        arms.remove(*k);
+                    }
+                    _ => unreachable!("only cases have arms"),
+                }
+                *n = None;
+                return;
+            }
+            Some(k) => *k -= removable,
+            None => return,
+        }
+        for nested in nested_mut(op) {
+            remove_arm(nested, n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use crate::typing::strict::StrictOptions;
+
+    /// Fifty definitions that check, but for `paint` whose case misses a
+    /// constructor of `Color`, the planted bug
+    fn seeded_module(seed: u64) -> String {
+        let mut rng = Rng(seed);
+        let mut defs = vec![
+            "data Nat: zero, [Nat] suc.".to_owned(),
+            "data Bool: true, false.".to_owned(),
+            "//@ phantom: a\ndata Tag a: tag.".to_owned(),
+            "const one [Nat]: zero suc.".to_owned(),
+            "const two [Nat]: one suc.".to_owned(),
+        ];
+        let mut nats = vec!["suc".to_owned()];
+        for i in 0..43 {
+            let name = format!("op-{}", i);
+            let call = &nats[rng.below(nats.len())];
+            let def = match rng.below(5) {
+                0 => format!("define [Nat] {} [Nat]: {} suc.", name, call),
+                1 => format!(
+                    "define [Nat] {} [Nat]: case {{ zero {{ two }}, suc {{ {} }} }}.",
+                    name, call
+                ),
+                2 => format!("define [Nat] {} [Nat]: ({}) exec-1-1.", name, call),
+                3 => format!(
+                    "//@ expect-type: [Nat][Nat]\ndefine [Nat] {} [Nat]: dup pop {}.",
+                    name, call
+                ),
+                _ => format!(
+                    "define [Nat, Nat] {} [Nat]: case2 {{ zero _ {{ }}, suc _ {{ br-1 pop {} }} }}.",
+                    name, call
+                ),
+            };
+            if !def.contains("[Nat, Nat]") {
+                nats.push(name);
+            }
+            defs.push(def);
+        }
+        let at = 5 + rng.below(40);
+        defs.insert(at, "data Color: red, green, blue.".to_owned());
+        defs.insert(
+            at + 1 + rng.below(4),
+            "define [Color] paint [Nat]: case { red { zero }, green { one } }.".to_owned(),
+        );
+        defs.join("\n")
+    }
+
+    fn names<T>(defs: &HashMap<String, T>) -> Vec<&str> {
+        let mut names: Vec<_> = defs.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn shrinks_to_the_core_of_a_planted_bug() {
+        let predicate = Predicate::ErrorCode("NotAllConstructorsCovered".to_owned());
+        for seed in [1, 7, 42] {
+            let source = seeded_module(seed);
+            let module = parse(&source).unwrap();
+            let count = module.op_defs.len() + module.data_defs.len() + module.const_defs.len();
+            assert_eq!(count, 50);
+            let report = Inference::new(&desugar(module.clone()).unwrap()).report();
+            assert_eq!(report.all_errors().count(), 1, "{}", source);
+            let shrunk = shrink(&module, |module| predicate.holds(module)).unwrap();
+            assert_eq!(
+                names(&shrunk.module.data_defs),
+                ["Color"],
+                "{}",
+                shrunk.source
+            );
+            assert_eq!(
+                names(&shrunk.module.op_defs),
+                ["paint"],
+                "{}",
+                shrunk.source
+            );
+            assert!(shrunk.module.const_defs.is_empty());
+            // the result is a fixpoint, shrinking it again removes nothing
+            let again = shrink(&shrunk.module, |module| predicate.holds(module)).unwrap();
+            assert_eq!(again.removals, 0);
+            assert_eq!(again.source, shrunk.source);
+        }
+    }
+
+    #[test]
+    fn shrinks_reports_that_differ() {
+        let source = "
+            data Nat: zero, [Nat] suc.
+            define [Nat] twice [Nat]: suc suc.
+            define [a] nocforget [Nat]: pop zero.
+            define [] main [Nat]: zero twice nocforget twice.
+            ";
+        let module = parse(source).unwrap();
+        let predicate = Predicate::ReportsDiffer(
+            Box::new(|inference| inference),
+            Box::new(|inference| inference.with_strict(StrictOptions::all())),
+        );
+        let shrunk = shrink(&module, |module| predicate.holds(module)).unwrap();
+        assert_eq!(shrunk.source, "define [a] nocforget [Nat]:.\n");
+        assert!(matches!(
+            shrink(&module, |_| false),
+            Err(ShrinkError::DoesNotHold)
+        ));
+    }
+
+    #[test]
+    fn removals_in_bodies() {
+        let source = "
+            data Nat: zero, [Nat] suc.
+            define [Nat] f [Nat]: (suc) case { zero { zero (suc) }, suc { } } pop.
+            ";
+        let module = parse(source).unwrap();
+        let removed = |removal, n| {
+            let mut module = module.clone();
+            remove(&mut module, removal, n).then(|| module.op_defs["f"].body.clone())
+        };
+        let body = |ops: Option<Vec<Op>>| {
+            let ops: Vec<_> = ops.unwrap().iter().map(Op::to_string).collect();
+            ops.join(" ")
+        };
+        // ops count in source order, each before the ones nested in it
+        assert_eq!(
+            body(removed(Removal::BodyOp, 0)),
+            "case { zero { zero (suc) }, suc {} } pop"
+        );
+        assert_eq!(
+            body(removed(Removal::BodyOp, 1)),
+            "() case { zero { zero (suc) }, suc {} } pop"
+        );
+        assert_eq!(
+            body(removed(Removal::BodyOp, 3)),
+            "(suc) case { zero { (suc) }, suc {} } pop"
+        );
+        assert_eq!(
+            body(removed(Removal::BodyOp, 5)),
+            "(suc) case { zero { zero () }, suc {} } pop"
+        );
+        assert_eq!(
+            body(removed(Removal::BodyOp, 6)),
+            "(suc) case { zero { zero (suc) }, suc {} }"
+        );
+        assert!(removed(Removal::BodyOp, 7).is_none());
+        assert_eq!(
+            body(removed(Removal::CaseArm, 0)),
+            "(suc) case { suc {} } pop"
+        );
+        assert_eq!(
+            body(removed(Removal::CaseArm, 1)),
+            "(suc) case { zero { zero (suc) } } pop"
+        );
+        assert!(removed(Removal::CaseArm, 2).is_none());
+    }
+}
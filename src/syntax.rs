@@ -1,8 +1,13 @@
 use lalrpop_util::lalrpop_mod;
 
 pub mod ast;
+pub mod attributes;
+pub mod checkpoints;
+pub mod highlight;
 mod lexer;
+pub mod literals;
 pub mod module_wrapper;
+mod stream;
 mod tokens;
 
 lalrpop_mod!(
@@ -12,14 +17,817 @@ lalrpop_mod!(
     "/syntax/parser.rs"
 );
 
+use crate::typing::types::{OpType, Type};
 use ast::Module;
+use ast::Op;
 use lexer::Lexer;
-use parser::IVParser;
+use literals::{LiteralTable, NO_LITERALS};
+use logos::Logos;
+use parser::{IVParser, OpTypeParser, OpsParser, TypeParser};
+use std::collections::HashMap;
+use std::ops::Range;
+pub use stream::{parse_definitions, Definitions, StreamError};
+pub use tokens::LexingError;
+use tokens::Token;
 
-pub fn parse(
-    input: &str,
-) -> Result<Module, lalrpop_util::ParseError<usize, tokens::Token<'_>, tokens::LexingError>> {
+pub type ParseError<'input> =
+    lalrpop_util::ParseError<usize, tokens::Token<'input>, tokens::LexingError>;
+
+pub fn parse(input: &str) -> Result<Module, ParseError<'_>> {
+    parse_with_literals(input, &NO_LITERALS)
+}
+
+/// Like `parse`, taking the suffixed literals of the table
+pub fn parse_with_literals<'input>(
+    input: &'input str,
+    literals: &LiteralTable,
+) -> Result<Module, ParseError<'input>> {
     let lexer = Lexer::new(input);
     let parser = IVParser::new();
-    parser.parse(input, lexer)
+    let module = parser.parse(input, literals, lexer)?;
+    Ok(module.with_checkpoints(checkpoints::scan(input).0))
+}
+
+/// What `parse_recovering` makes of a source
+pub struct Recovered<'input> {
+    /// The definitions that parsed, with their spans into the source
+    pub module: Module,
+    /// The errors of the definitions left out, in source order
+    pub errors: Vec<ParseError<'input>>,
+    /// The names the definitions left out would have defined, as far as
+    /// their tokens tell: the op of a `define`, the constructors of a `data`,
+    /// the constant of a `const`
+    pub unparsed_names: Vec<String>,
+}
+
+/// Like `parse`, but a definition that does not parse is left out and
+/// parsing goes on at the next top-level `define`, `data`, `const`, attribute or pragma. Every
+/// retry leaves out another definition, so this takes at most one parse
+/// per definition.
+pub fn parse_recovering(input: &str) -> Recovered<'_> {
+    let starts = definition_starts(input);
+    let parser = IVParser::new();
+    let mut skipped: Vec<Range<usize>> = vec![];
+    let mut errors = vec![];
+    let module = loop {
+        let err = match parser.parse(input, &NO_LITERALS, Lexer::skipping(input, skipped.clone())) {
+            Ok(module) => break module,
+            Err(err) => err,
+        };
+        let location = error_location(input, &skipped, &err);
+        // a definition running into the next one fails at the next one's
+        // start, other errors are in the definition where they are
+        let runs_into_next = matches!(
+            err,
+            lalrpop_util::ParseError::UnrecognizedToken { .. }
+                | lalrpop_util::ParseError::ExtraToken { .. }
+        );
+        let blamed = match starts.binary_search(&location) {
+            Ok(i) if runs_into_next && i > 0 => Some(i - 1),
+            Ok(i) => Some(i),
+            Err(i) => i.checked_sub(1),
+        };
+        let definition = match blamed {
+            Some(i) => starts[i]..starts.get(i + 1).copied().unwrap_or(input.len()),
+            None => 0..starts.first().copied().unwrap_or(input.len()),
+        };
+        errors.push((location, err));
+        if skipped.contains(&definition) {
+            // the parser does not see skipped tokens, so this is not expected
+            break Module::new(HashMap::new(), HashMap::new());
+        }
+        skipped.push(definition);
+    };
+    errors.sort_by_key(|(location, _)| *location);
+    skipped.sort_by_key(|range| range.start);
+    Recovered {
+        module: module.with_checkpoints(checkpoints::scan(input).0),
+        errors: errors.into_iter().map(|(_, err)| err).collect(),
+        unparsed_names: skipped
+            .into_iter()
+            .flat_map(|range| defined_names(input, range))
+            .collect(),
+    }
+}
+
+/// The names a definition in `range` would define, see `Recovered`
+fn defined_names(input: &str, range: Range<usize>) -> Vec<String> {
+    let tokens: Vec<_> = Token::lexer(&input[range])
+        .filter_map(Result::ok)
+        .filter(|token| token != &Token::Comment)
+        .collect();
+    let depth_changes = |token: &Token| match token {
+        Token::BracketOpen | Token::ParenOpen => 1,
+        Token::BracketClose | Token::ParenClose => -1,
+        _ => 0,
+    };
+    let Some(keyword) = tokens
+        .iter()
+        .position(|token| matches!(token, Token::Define | Token::Data | Token::Const))
+    else {
+        return vec![];
+    };
+    let mut depth = 0;
+    let mut names = vec![];
+    let mut after_colon = false;
+    for (i, token) in tokens.iter().enumerate().skip(keyword + 1) {
+        depth += depth_changes(token);
+        match (&tokens[keyword], token) {
+            // the name follows the closing bracket of the pre stack
+            (Token::Define, Token::LIdent(name)) if depth == 0 => {
+                if tokens[i - 1] == Token::BracketClose {
+                    names.push(name.to_string());
+                }
+                break;
+            }
+            (Token::Const, Token::LIdent(name)) => {
+                names.push(name.to_string());
+                break;
+            }
+            (Token::Data, Token::Colon) if depth == 0 => after_colon = true,
+            (Token::Data, Token::LIdent(name)) if depth == 0 && after_colon => {
+                names.push(name.to_string())
+            }
+            _ => (),
+        }
+    }
+    names
+}
+
+/// Offsets of the tokens a top-level definition starts with, attributes
+/// and pragmas belong to the definition after them
+fn definition_starts(input: &str) -> Vec<usize> {
+    let mut starts = DefinitionStarts::default();
+    starts.scan(input, 0);
+    starts.starts
+}
+
+/// The offsets `definition_starts` finds, for a source scanned a piece at a
+/// time. No token spans lines, so any whole lines make a piece.
+#[derive(Debug, Default)]
+pub(crate) struct DefinitionStarts {
+    pub starts: Vec<usize>,
+    in_pragmas: bool,
+}
+
+impl DefinitionStarts {
+    /// Scans the piece of the source starting at `offset`
+    pub fn scan(&mut self, piece: &str, offset: usize) {
+        for (token, span) in Token::lexer(piece).spanned() {
+            match token {
+                Ok(Token::PragmaStart | Token::Hash) => {
+                    if !self.in_pragmas {
+                        self.starts.push(offset + span.start);
+                    }
+                    self.in_pragmas = true;
+                }
+                Ok(Token::Define | Token::Data | Token::Const) => {
+                    if !self.in_pragmas {
+                        self.starts.push(offset + span.start);
+                    }
+                    self.in_pragmas = false;
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Where parsing failed, for lexing errors the start of the first token
+/// the lexer did not recognize
+pub(crate) fn error_location(input: &str, skipped: &[Range<usize>], err: &ParseError<'_>) -> usize {
+    match err {
+        lalrpop_util::ParseError::InvalidToken { location }
+        | lalrpop_util::ParseError::UnrecognizedEof { location, .. } => *location,
+        lalrpop_util::ParseError::UnrecognizedToken {
+            token: (start, _, _),
+            ..
+        }
+        | lalrpop_util::ParseError::ExtraToken {
+            token: (start, _, _),
+        } => *start,
+        lalrpop_util::ParseError::User {
+            error: LexingError::UnknownPragma,
+        } => unknown_pragma_location(input, skipped),
+        lalrpop_util::ParseError::User {
+            error:
+                LexingError::AppliedTypeVariable { start, .. }
+                | LexingError::UnknownLiteralSuffix { start, .. }
+                | LexingError::InvalidLiteral { start, .. },
+        } => *start,
+        lalrpop_util::ParseError::User { .. } => Token::lexer(input)
+            .spanned()
+            .find(|(token, span)| {
+                token.is_err() && !skipped.iter().any(|r| r.contains(&span.start))
+            })
+            .map_or(0, |(_, span)| span.start),
+    }
+}
+
+/// The start of the first pragma the parser rejects, which does not say
+/// where it is
+fn unknown_pragma_location(input: &str, skipped: &[Range<usize>]) -> usize {
+    let tokens: Vec<_> = Lexer::skipping(input, skipped.to_vec())
+        .map_while(Result::ok)
+        .collect();
+    tokens
+        .windows(4)
+        .find_map(|window| match window {
+            [(start, Token::PragmaStart, _), (_, Token::LIdent(name), _), _, (_, arg, _)] => {
+                let known = matches!(
+                    (*name, arg),
+                    ("expect-type", Token::BracketOpen)
+                        | ("expect-error", Token::UIdent(_))
+                        | ("phantom", Token::LIdent(_))
+                );
+                (!known).then_some(*start)
+            }
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// An op type on its own, either `[pre][post]` as in annotations or like
+/// `Int, Int -> Bool`, topmost value first on either side
+pub fn parse_optype(input: &str) -> Result<OpType, ParseError<'_>> {
+    let lexer = Lexer::new(input);
+    let parser = OpTypeParser::new();
+    parser.parse(input, &NO_LITERALS, lexer)
+}
+
+/// Ops on their own, as in a body, spans are offsets into `input`
+pub fn parse_ops(input: &str) -> Result<Vec<Op>, ParseError<'_>> {
+    let lexer = Lexer::new(input);
+    let parser = OpsParser::new();
+    parser.parse(input, &NO_LITERALS, lexer)
+}
+
+/// A type on its own, as written in a stack of an annotation
+pub fn parse_type(input: &str) -> Result<Type, ParseError<'_>> {
+    let lexer = Lexer::new(input);
+    let parser = TypeParser::new();
+    parser.parse(input, &NO_LITERALS, lexer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ast::*;
+    use super::tokens::Token;
+    use super::{parse, parse_optype, parse_recovering, parse_type, Recovered};
+    use crate::rng::Rng;
+    use crate::typing::types::{OpType, Type};
+    use logos::Logos;
+    use std::fs;
+    use std::iter::zip;
+    use std::path::Path;
+
+    /// Tokens of `src`, comments and trailing commas dropped since they are not
+    /// printed
+    fn tokens(src: &str) -> Vec<Token<'_>> {
+        let all: Vec<_> = Token::lexer(src)
+            .map(|t| t.unwrap())
+            .filter(|t| t != &Token::Comment)
+            .collect();
+        let trailing_comma = |i: usize| {
+            all[i] == Token::Comma
+                && matches!(
+                    all.get(i + 1),
+                    Some(Token::BraceClose | Token::BracketClose)
+                )
+        };
+        (0..all.len())
+            .filter(|&i| !trailing_comma(i))
+            .map(|i| all[i].clone())
+            .collect()
+    }
+
+    /// The source text under a span lexes the same as the pretty-printed node
+    fn assert_covers(source: &str, span: &Span, printed: &str) {
+        let slice = &source[span.start..span.end];
+        assert_eq!(tokens(slice), tokens(printed), "span of `{}`", printed);
+    }
+
+    fn slice<'s>(source: &'s str, span: &Span) -> &'s str {
+        &source[span.start..span.end]
+    }
+
+    fn check_ops(source: &str, ops: &[Op]) {
+        for op in ops {
+            assert_covers(source, op.get_span(), &op.to_string());
+            match op {
+                Op::Quote { value, .. } => check_ops(source, value),
+                Op::Case { head_arm, arms, .. } => {
+                    for arm in std::iter::once(head_arm).chain(arms) {
+                        assert_covers(source, &arm.span, &arm.to_string());
+                        assert_eq!(slice(source, &arm.constr_span), arm.constr);
+                        check_ops(source, &arm.body);
+                    }
+                }
+                Op::Case2 { arms, .. } => {
+                    for arm in arms {
+                        assert_covers(source, &arm.span, &arm.to_string());
+                        for (pattern, span) in zip(&arm.patterns, &arm.pattern_spans) {
+                            assert_eq!(slice(source, span), pattern.to_string());
+                        }
+                        check_ops(source, &arm.body);
+                    }
+                }
+                Op::List { items, .. } => {
+                    for item in items {
+                        check_ops(source, item);
+                    }
+                }
+                Op::Literal { .. } | Op::Name { .. } => (),
+            }
+        }
+    }
+
+    fn check_source(source: &str) {
+        let module = parse(source).unwrap();
+        for (name, op_def) in module.op_defs.iter() {
+            let text = slice(source, &op_def.span);
+            assert!(text.starts_with("define") && text.ends_with('.'));
+            assert_eq!(slice(source, &op_def.name_span), overloaded_name(name));
+            check_ops(source, &op_def.body);
+        }
+        for (name, const_def) in module.const_defs.iter() {
+            let text = slice(source, &const_def.span);
+            assert!(text.starts_with("const") && text.ends_with('.'));
+            assert_eq!(slice(source, &const_def.name_span), name);
+            check_ops(source, &const_def.body);
+        }
+        for data_def in module.data_defs.values() {
+            let text = slice(source, &data_def.span);
+            assert!(text.starts_with("data") && text.ends_with('.'));
+            for (name, constr) in data_def.constrs.iter() {
+                assert_eq!(slice(source, &constr.name_span), name);
+            }
+        }
+    }
+
+    #[test]
+    fn spans_cover_their_source() {
+        check_source(
+            "
+            data Maybe a: nothing, [a] just.
+            define [Maybe Int] f [Int]: case {
+                just { +5 pop ( ( 1 )  dup ) pop },
+                nothing {
+                    // comment inside a span
+                    -3
+                },
+            }.
+            define [] g [[][]]: ().
+            define [] h [List Int]: [ 1, (2) exec-0-1 ,[3],].
+            ",
+        );
+    }
+
+    #[test]
+    fn spans_cover_fixtures() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for dir in ["examples", "tests/cases"] {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                let source = fs::read_to_string(&path).unwrap();
+                if path.extension().is_some_and(|ext| ext == "iv") && parse(&source).is_ok() {
+                    check_source(&source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn printed_modules_parse_back() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for dir in ["examples", "tests/cases"] {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                let source = fs::read_to_string(&path).unwrap();
+                let Some(module) = path
+                    .extension()
+                    .filter(|ext| *ext == "iv")
+                    .and_then(|_| parse(&source).ok())
+                else {
+                    continue;
+                };
+                let printed = module.to_string();
+                let reparsed = parse(&printed)
+                    .unwrap_or_else(|err| panic!("{}: {:?}\n{}", path.display(), err, printed));
+                assert_eq!(reparsed.to_string(), printed, "{}", path.display());
+                let names = |m: &Module| {
+                    let mut names: Vec<_> = m
+                        .op_defs
+                        .keys()
+                        .chain(m.data_defs.keys())
+                        .chain(m.const_defs.keys())
+                        .cloned()
+                        .collect();
+                    names.sort();
+                    names
+                };
+                assert_eq!(names(&reparsed), names(&module), "{}", path.display());
+                check_source(&printed);
+            }
+        }
+    }
+
+    #[test]
+    fn pragmas_attach_to_the_next_definition() {
+        let source = "
+            define [] f []:.
+            //@ expect-error: UnknownOp
+            //comment
+            //@expect-type:[a][]
+            define [a] g []: pop.
+            ";
+        let module = parse(source).unwrap();
+        assert!(module.op_defs["f"].pragmas.is_empty());
+        let pragmas = &module.op_defs["g"].pragmas;
+        assert!(matches!(&pragmas[0].kind, PragmaKind::ExpectError(code) if code == "UnknownOp"));
+        assert!(matches!(&pragmas[1].kind, PragmaKind::ExpectType(_)));
+        let slice = &source[pragmas[1].span.start..pragmas[1].span.end];
+        assert_eq!(slice, "//@expect-type:[a][]");
+    }
+
+    #[test]
+    fn attributes_come_before_pragmas() {
+        let source = "
+            #[unchecked] #[derive(eq,show)]
+            //@ expect-type: [][]
+            define [] f []:.
+            #[phantom(a)]
+            data Tag a: tag.
+            ";
+        let module = parse(source).unwrap();
+        let op_def = &module.op_defs["f"];
+        let attributes: Vec<_> = op_def.attributes.iter().map(|a| a.to_string()).collect();
+        assert_eq!(attributes, ["#[unchecked]", "#[derive(eq, show)]"]);
+        assert_eq!(op_def.pragmas.len(), 1);
+        assert!(op_def.has_attribute("unchecked"));
+        let attribute = &module.data_defs["Tag"].attributes[0];
+        assert_eq!(attribute.args, ["a"]);
+        assert_eq!(slice(source, &attribute.span), "#[phantom(a)]");
+        assert!(parse("//@ expect-type: [][]\n#[unchecked] define [] f []:.").is_err());
+        assert!(parse("#[unchecked] const c [Int]: 1.").is_err());
+        let printed = module.to_string();
+        assert_eq!(parse(&printed).unwrap().to_string(), printed);
+        let (names, errors) = recovered("#[unchecked(] define [] f []:. define [] g []:.");
+        assert_eq!((names.as_str(), errors), ("g", 1));
+    }
+
+    #[test]
+    fn unknown_pragmas_are_rejected() {
+        assert!(parse(
+            "//@ expect-kind: Int
+define [] f []:."
+        )
+        .is_err());
+        assert!(parse(
+            "//@ expect-error: UnknownOp
+data Foo: foo."
+        )
+        .is_err());
+        assert!(parse(
+            "//
+// @ not a pragma
+define [] f []:."
+        )
+        .is_ok());
+    }
+
+    /// The names of the definitions that parsed, sorted, and the error count
+    fn recovered(source: &str) -> (String, usize) {
+        let Recovered { module, errors, .. } = parse_recovering(source);
+        let mut names: Vec<_> = module
+            .op_defs
+            .keys()
+            .chain(module.data_defs.keys())
+            .chain(module.const_defs.keys())
+            .collect();
+        names.sort();
+        let names: Vec<_> = names.into_iter().map(String::as_str).collect();
+        (names.join(" "), errors.len())
+    }
+
+    #[test]
+    fn recovery_skips_broken_definitions() {
+        let source = "
+            data Foo: foo.
+            define [] f [Foo]: foo ( .
+            define [] g [Foo]: foo.
+            define [] h: .
+            //@ expect-error: UnknownOp
+            define [] i []: nope.
+            ";
+        assert_eq!(recovered(source), ("Foo g i".to_owned(), 2));
+        // a definition running into the next one is left out, not the next
+        assert_eq!(
+            recovered("define [] f []: define [] g []: ."),
+            ("g".to_owned(), 1)
+        );
+        assert_eq!(
+            recovered("define [] f [] define [] g []: ."),
+            ("g".to_owned(), 1)
+        );
+        // junk before the first definition, at the end and unknown tokens
+        assert_eq!(
+            recovered("junk define [] f []: . define"),
+            ("f".to_owned(), 2)
+        );
+        assert_eq!(
+            recovered("define [] f []: % . define [] g []: ."),
+            ("g".to_owned(), 1)
+        );
+        assert_eq!(recovered(""), (String::new(), 0));
+        // constants start definitions too
+        assert_eq!(
+            recovered("const n [Int] 1. const m [Int]: 2. define [] f []: ."),
+            ("f m".to_owned(), 1)
+        );
+    }
+
+    #[test]
+    fn recovery_keeps_spans_and_order() {
+        let source = "data Foo: foo. define [] f x. define [] g [Foo]: foo. data Bar.";
+        let Recovered { module, errors, .. } = parse_recovering(source);
+        let g = &module.op_defs["g"];
+        assert_eq!(&source[g.span.start..g.span.end], "define [] g [Foo]: foo.");
+        let locations: Vec<_> = errors
+            .iter()
+            .map(|err| match err {
+                lalrpop_util::ParseError::UnrecognizedToken { token, .. } => token.0,
+                err => panic!("{:?}", err),
+            })
+            .collect();
+        assert_eq!(
+            locations,
+            [source.find("x").unwrap(), source.rfind('.').unwrap()]
+        );
+        assert_eq!(
+            recovered("data Foo: foo. define [] g [Foo]: foo."),
+            ("Foo g".to_owned(), 0)
+        );
+    }
+
+    #[test]
+    fn unparsed_names() {
+        let source = "
+            define [Foo] f [: . define [] g [Foo]: foo .
+            //@ expect-error: UnknownOp
+            define [] h [Foo] foo..
+            data Foo: foo, [Foo] bar, [[][Foo]] Foo baz.
+            data Bar: [Int] bar
+            define [] (oops) [] .
+            const size [Int] 3.
+            ";
+        let Recovered {
+            module,
+            errors,
+            unparsed_names,
+        } = parse_recovering(source);
+        assert_eq!(
+            unparsed_names,
+            ["f", "h", "foo", "bar", "baz", "bar", "size"]
+        );
+        assert_eq!(errors.len(), 6);
+        assert_eq!(module.op_defs.len(), 1);
+    }
+
+    #[test]
+    fn recovery_from_unknown_pragmas() {
+        let source = "
+            define [] f []: .
+            //@ expect-type: Foo
+            define [] g []: .
+            //@ expect-error: [a][]
+            define [] h []: .
+            //@ expect-kind: Int
+            data Foo: foo.
+            define [] i []: .
+            ";
+        assert_eq!(recovered(source), ("f i".to_owned(), 3));
+    }
+
+    /// Token soup that is mostly made of definitions, every definition that
+    /// comes back has to parse on its own from its span
+    #[test]
+    fn recovery_fuzz() {
+        let words = [
+            "define",
+            "const",
+            "data",
+            "[",
+            "]",
+            "(",
+            ")",
+            "{",
+            "}",
+            ":",
+            ",",
+            ".",
+            "->",
+            "a",
+            "f",
+            "Int",
+            "case",
+            "case2",
+            "_",
+            "1",
+            "//@",
+            "expect-error",
+            "expect-type",
+            "%",
+            "// c\n",
+            "\n",
+        ];
+        let pieces = [
+            "define [a] f [a, a]: dup.",
+            "data Foo: foo, [Foo] bar.",
+            "//@ expect-error: UnknownOp\n",
+            "define [] g []: case { foo { }, bar { pop } }.",
+        ];
+        let mut rng = Rng(7);
+        for _ in 0..2000 {
+            let mut source = String::new();
+            for _ in 0..rng.below(24) {
+                if rng.below(3) == 0 {
+                    source.push_str(words[rng.below(words.len())]);
+                } else {
+                    source.push_str(pieces[rng.below(pieces.len())]);
+                }
+                source.push(' ');
+            }
+            let Recovered { module, errors, .. } = parse_recovering(&source);
+            assert!(errors.len() <= source.len(), "{}", source);
+            let spans = module
+                .op_defs
+                .values()
+                .map(|op_def| &op_def.span)
+                .chain(module.data_defs.values().map(|data_def| &data_def.span));
+            for span in spans {
+                let text = &source[span.start..span.end];
+                assert!(parse(text).is_ok(), "`{}` in {}", text, source);
+            }
+            if errors.is_empty() {
+                assert!(parse(&source).is_ok(), "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn stack_effects() {
+        let effect = parse_optype("Int, Maybe a -> [a][Bool]").unwrap();
+        assert_eq!(effect.to_string(), "[Int, Maybe a][[a][Bool]]");
+        assert_eq!(parse_optype("->").unwrap().to_string(), "[][]");
+        assert!(parse_optype("Int").is_err());
+        assert!(parse_optype("Int -> Int -> Int").is_err());
+    }
+
+    /// Every type built from a few leaves, applications and op types up to
+    /// the depth
+    fn sample_types(depth: usize) -> Vec<Type> {
+        let mut types = vec![
+            Type::Poly("a".to_owned()),
+            Type::Poly("b".to_owned()),
+            Type::Mono("Int".to_owned()),
+        ];
+        if depth == 0 {
+            return types;
+        }
+        let smaller = sample_types(depth - 1);
+        for t in smaller.iter() {
+            let app = |f: &str, t: &Type| {
+                Type::App(Box::new(Type::Mono(f.to_owned())), Box::new(t.clone()))
+            };
+            types.push(app("Maybe", t));
+            types.push(Type::App(Box::new(app("Either", t)), Box::new(t.clone())));
+            // type variables cannot be applied
+            if !matches!(t, Type::Poly(_)) {
+                types.push(Type::App(
+                    Box::new(t.clone()),
+                    Box::new(Type::Poly("c".to_owned())),
+                ));
+            }
+            types.push(Type::Op(OpType {
+                pre: vec![t.clone()],
+                post: vec![],
+            }));
+            for u in smaller.iter().take(4) {
+                types.push(Type::Op(OpType {
+                    pre: vec![u.clone(), t.clone()],
+                    post: vec![t.clone()],
+                }));
+            }
+        }
+        types
+    }
+
+    #[test]
+    fn printed_types_parse_back() {
+        for t in sample_types(2) {
+            let printed = t.to_string();
+            let parsed =
+                parse_type(&printed).unwrap_or_else(|err| panic!("{}: {:?}", printed, err));
+            assert_eq!(parsed.canonical(), t.canonical(), "{}", printed);
+            let op = OpType {
+                pre: vec![t.clone(), Type::Poly("z".to_owned())],
+                post: vec![t.clone()],
+            };
+            let printed = op.to_string();
+            let parsed =
+                parse_optype(&printed).unwrap_or_else(|err| panic!("{}: {:?}", printed, err));
+            assert_eq!(parsed.canonical(), op.canonical(), "{}", printed);
+        }
+    }
+
+    #[test]
+    fn type_syntax() {
+        let same = |a: &str, b: &str| {
+            assert_eq!(
+                parse_type(a).unwrap().canonical(),
+                parse_type(b).unwrap().canonical(),
+                "{} vs {}",
+                a,
+                b
+            )
+        };
+        same("[a -> b]", "[a][b]");
+        same("[a, Int -> ]", "[a, Int][]");
+        same("[->]", "[][]");
+        same("Either a b", "(Either a) b");
+        same("Maybe (Maybe a)", "(Maybe ((Maybe a)))");
+        same("[Maybe a -> [b -> c]]", "[Maybe a][[b][c]]");
+        assert!(parse_type("Maybe Maybe a").unwrap().to_string() == "Maybe Maybe a");
+        for bad in [
+            "",
+            "a, b",
+            "a -> b",
+            "[a",
+            "a]",
+            "(a",
+            "[[a][b]",
+            "[a -> b -> c]",
+            "[a][b][c]",
+        ] {
+            assert!(parse_type(bad).is_err(), "{}", bad);
+        }
+        assert!(parse_optype("[a][b][c]").is_err());
+    }
+
+    #[test]
+    fn empty_op_types() {
+        for empty in ["", "  ", "// nothing", "->", "[][]", "--"] {
+            assert_eq!(parse_optype(empty).unwrap(), OpType::empty(), "{:?}", empty);
+        }
+        assert!(parse_type("").is_err());
+        assert_eq!(parse_type("[--]").unwrap(), Type::Op(OpType::empty()));
+        assert!(parse_optype("Int --").is_err());
+
+        // `name --` is `[] name []`, printed as such
+        let module = parse(
+            "define nop --: 1 pop.
+define [] quoted [[--]]: ().",
+        )
+        .unwrap();
+        assert!(module.op_defs["nop"].ann.is_empty());
+        assert!(module.op_defs["nop"].post_spans.is_empty());
+        let name_span = &module.op_defs["nop"].name_span;
+        assert_eq!((name_span.start, name_span.end), (7, 10));
+        assert_eq!(
+            module.to_string(),
+            "define [] nop []: 1 pop.\ndefine [] quoted [[][]]: ().\n"
+        );
+        assert!(parse("define nop -- []:.").is_err());
+    }
+
+    #[test]
+    fn declarations_have_no_body() {
+        let module = parse(
+            "define [Int] succ [Int].
+define nothing --.
+define [a] same [a]:.",
+        )
+        .unwrap();
+        assert!(module.op_defs["succ"].declaration);
+        assert!(module.op_defs["nothing"].declaration);
+        assert!(!module.op_defs["same"].declaration);
+        assert!(module.op_defs["succ"].body.is_empty());
+        assert_eq!(
+            module.to_string(),
+            "define [Int] succ [Int].\ndefine [] nothing [].\ndefine [a] same [a]:.\n"
+        );
+        assert!(parse("define [Int] succ [Int] 1.").is_err());
+        assert!(parse("const one [Int].").is_err());
+    }
+
+    #[test]
+    fn type_variables_take_no_arguments() {
+        for bad in ["a Int", "Maybe (f a)", "[f a -> a]", "(a b) c"] {
+            assert!(parse_type(bad).is_err(), "{}", bad);
+        }
+        assert!(parse("define [f a] g []: pop.").is_err());
+        assert!(parse_type("Either a b").is_ok());
+        assert!(parse_type("[a][b] c").is_ok());
+    }
 }
@@ -1,10 +1,78 @@
 use crate::typing::types::*;
 use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
+    pub origin: SpanOrigin,
+}
+
+/// Where the node under a span comes from
+#[derive(Debug, Clone)]
+pub enum SpanOrigin {
+    /// Written in the source as it is
+    Source,
+    /// Produced by desugaring a construct, the span itself covers the same
+    /// source as the construct
+    Desugared(Box<Expansion>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub from: Span,
+    /// What was desugared, like "list literal"
+    pub construct: &'static str,
+    /// Which of the nodes made from the construct this is, they all share
+    /// its span
+    pub index: usize,
+}
+
+/// What tells an op apart from the others of its module, see `Span::key`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OpKey {
+    pub start: usize,
+    /// Which of the ops desugaring made at `start` it is, 0 for an op in
+    /// the source and from 1 for the ones made
+    pub expansion: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span {
+            start,
+            end,
+            origin: SpanOrigin::Source,
+        }
+    }
+
+    /// A span for the `index`th node that desugaring the `construct` at
+    /// `from` produced
+    pub fn expanded_from(from: &Span, construct: &'static str, index: usize) -> Self {
+        Span {
+            start: from.start,
+            end: from.end,
+            origin: SpanOrigin::Desugared(Box::new(Expansion {
+                from: from.clone(),
+                construct,
+                index,
+            })),
+        }
+    }
+
+    /// The key of the op at the span. Ops made from one construct share
+    /// its start, the index of their expansion tells them apart.
+    pub fn key(&self) -> OpKey {
+        let expansion = match &self.origin {
+            SpanOrigin::Source => 0,
+            SpanOrigin::Desugared(expansion) => expansion.index + 1,
+        };
+        OpKey {
+            start: self.start,
+            expansion,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -13,36 +81,237 @@ pub struct ConstrInfo {
     pub op_type: OpType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Module {
     pub data_defs: HashMap<String, DataDef>,
     pub op_defs: HashMap<String, OpDef>,
+    pub const_defs: HashMap<String, ConstDef>,
+    /// The stack effect comments of the source, in source order
+    pub checkpoints: Vec<Checkpoint>,
 }
 
 impl Module {
     pub fn new(data_defs: HashMap<String, DataDef>, op_defs: HashMap<String, OpDef>) -> Self {
-        Module { data_defs, op_defs }
+        Module {
+            data_defs,
+            op_defs,
+            const_defs: HashMap::new(),
+            checkpoints: vec![],
+        }
+    }
+
+    pub fn with_const_defs(mut self, const_defs: HashMap<String, ConstDef>) -> Self {
+        self.const_defs = const_defs;
+        self
+    }
+
+    pub fn with_checkpoints(mut self, checkpoints: Vec<Checkpoint>) -> Self {
+        self.checkpoints = checkpoints;
+        self
+    }
+
+    /// The stack effect comments right after a token ending at `end`
+    pub fn checkpoints_after(&self, end: usize) -> &[Checkpoint] {
+        let from = self.checkpoints.partition_point(|c| c.after < end);
+        let to = self.checkpoints.partition_point(|c| c.after <= end);
+        &self.checkpoints[from..to]
+    }
+
+    /// Op definitions in the order they appear in the source
+    pub fn op_defs_in_source_order(&self) -> Vec<(&String, &OpDef)> {
+        let mut op_defs: Vec<_> = self.op_defs.iter().collect();
+        op_defs.sort_by_key(|(_, op_def)| op_def.span.start);
+        op_defs
+    }
+
+    /// Constant definitions in the order they appear in the source
+    pub fn const_defs_in_source_order(&self) -> Vec<(&String, &ConstDef)> {
+        let mut const_defs: Vec<_> = self.const_defs.iter().collect();
+        const_defs.sort_by_key(|(_, const_def)| const_def.span.start);
+        const_defs
+    }
+
+    /// Data definitions in the order they appear in the source
+    pub fn data_defs_in_source_order(&self) -> Vec<(&String, &DataDef)> {
+        let mut data_defs: Vec<_> = self.data_defs.iter().collect();
+        data_defs.sort_by_key(|(_, data_def)| data_def.span.start);
+        data_defs
+    }
+
+    /// Every definition in the order they appear in the source
+    pub fn definitions_in_source_order(&self) -> Vec<Definition<'_>> {
+        let mut defs: Vec<_> = self
+            .data_defs
+            .iter()
+            .map(|(name, d)| (d.span.start, Definition::Data(name, d)))
+            .chain(
+                self.op_defs
+                    .iter()
+                    .map(|(name, o)| (o.span.start, Definition::Op(name, o))),
+            )
+            .chain(
+                self.const_defs
+                    .iter()
+                    .map(|(name, c)| (c.span.start, Definition::Const(name, c))),
+            )
+            .collect();
+        defs.sort_by_key(|(start, _)| *start);
+        defs.into_iter().map(|(_, def)| def).collect()
     }
 }
 
-#[derive(Debug)]
+/// A comment like `// ( Int, a -- a )`, the type the ops before it in
+/// their body are asserted to have. Its type variables are its own, not
+/// those of the annotation.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub effect: OpType,
+    /// The comment
+    pub span: Span,
+    /// Where the token before the comment ends, the op ending there is the
+    /// last one the effect covers
+    pub after: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct DataDef {
     pub params: Vec<String>,
+    /// The span of each parameter in `params`
+    pub param_spans: Vec<Span>,
     pub constrs: HashMap<String, DataConstr>,
     pub span: Span,
+    /// Attributes on the lines before the pragmas
+    pub attributes: Vec<Attribute>,
+    /// Pragmas on the lines before `data`
+    pub pragmas: Vec<Pragma>,
 }
 
-#[derive(Debug)]
+impl DataDef {
+    /// Constructors in the order they appear in the source
+    pub fn constrs_in_source_order(&self) -> Vec<(&String, &DataConstr)> {
+        let mut constrs: Vec<_> = self.constrs.iter().collect();
+        constrs.sort_by_key(|(_, constr)| constr.span.start);
+        constrs
+    }
+
+    /// The constructor names by tag. A constructor's tag is its position
+    /// among the definition's constructors in the source, so unlike the
+    /// order of `constrs` it is the same on every run. Whatever lists,
+    /// numbers or dispatches on the constructors goes by this order.
+    pub fn constr_order(&self) -> Vec<&str> {
+        self.constrs_in_source_order()
+            .into_iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// The tag of the constructor, see `constr_order`
+    pub fn constr_tag(&self, name: &str) -> Option<usize> {
+        self.constr_order()
+            .iter()
+            .position(|constr| *constr == name)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DataConstr {
     pub params: Vec<Type>,
+    /// From the fields to the name, without the attributes
     pub span: Span,
+    pub name_span: Span,
+    /// Attributes before the fields
+    pub attributes: Vec<Attribute>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OpDef {
     pub ann: OpType,
+    /// The span of each type in `ann.post`
+    pub post_spans: Vec<Span>,
     pub body: Vec<Op>,
+    /// Written without a body, `define [Int] f [Int].`, for an op a host
+    /// function implements. The body is empty.
+    pub declaration: bool,
     pub span: Span,
+    pub name_span: Span,
+    /// Attributes on the lines before the pragmas
+    pub attributes: Vec<Attribute>,
+    /// Pragmas on the lines before `define`
+    pub pragmas: Vec<Pragma>,
+}
+
+impl OpDef {
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes
+            .iter()
+            .any(|attribute| attribute.name == name)
+    }
+}
+
+/// Adds the op definition, replacing the one of the same name unless one of
+/// the two is marked `#[overload]`: then both are kept, the new one under a
+/// key of its own, the name and `#` and how many definitions of the name
+/// there are with it. No name has a `#`, so no key is the name of an op.
+pub fn insert_op_def(op_defs: &mut HashMap<String, OpDef>, name: String, op_def: OpDef) {
+    let overloads = |op_def: &OpDef| op_def.has_attribute("overload");
+    match op_defs.get(&name) {
+        Some(first) if overloads(first) || overloads(&op_def) => {
+            let key = (2..)
+                .map(|n| format!("{}#{}", name, n))
+                .find(|key| !op_defs.contains_key(key))
+                .expect("some key is free");
+            op_defs.insert(key, op_def);
+        }
+        _ => {
+            op_defs.insert(name, op_def);
+        }
+    }
+}
+
+/// The name an op definition is written with, its key in `Module::op_defs`
+/// without what `insert_op_def` added
+pub fn overloaded_name(key: &str) -> &str {
+    key.split_once('#').map_or(key, |(name, _)| name)
+}
+
+/// `const name [T]: body.`, a value computed once by running the body,
+/// which has to have type `[][T]` and only use pure ops
+#[derive(Debug, Clone)]
+pub struct ConstDef {
+    pub ty: Type,
+    pub body: Vec<Op>,
+    pub span: Span,
+    pub name_span: Span,
+}
+
+/// `#[name]`, `#[name(arg, ...)]` or `#[name("message")]`, a marker on the
+/// definition or constructor that follows it, see `attributes` for the
+/// known ones
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+    /// The text in quotes, without them, never given with `args`
+    pub message: Option<String>,
+    pub span: Span,
+}
+
+/// An in-source assertion about the definition that follows it
+#[derive(Debug, Clone)]
+pub struct Pragma {
+    pub kind: PragmaKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum PragmaKind {
+    /// `//@ expect-type: [pre][post]`, the inferred type up to renaming
+    ExpectType(OpType),
+    /// `//@ expect-error: Code`, checking fails with the error of that code
+    ExpectError(String),
+    /// `//@ phantom: b`, before a data definition, the parameters no
+    /// constructor uses on purpose
+    Phantom(Vec<String>),
 }
 
 #[derive(Debug, Clone)]
@@ -64,6 +333,18 @@ pub enum Op {
         arms: Vec<CaseArm>,
         span: Span,
     },
+    /// Matches the top two values at once, the first pattern of an arm
+    /// matches the topmost value
+    Case2 {
+        arms: Vec<Case2Arm>,
+        span: Span,
+    },
+    /// `[e1, e2]`, each item the ops pushing one element. Only exists
+    /// until desugaring, later stages never see it.
+    List {
+        items: Vec<Vec<Op>>,
+        span: Span,
+    },
 }
 
 impl Op {
@@ -74,6 +355,8 @@ impl Op {
             Op::Name { span, .. } => span,
             Op::Quote { span, .. } => span,
             Op::Case { span, .. } => span,
+            Op::Case2 { span, .. } => span,
+            Op::List { span, .. } => span,
         }
     }
 }
@@ -81,6 +364,13 @@ impl Op {
 #[derive(Debug, Clone)]
 pub enum Literal {
     Int(i32),
+    /// A literal of a kind the embedder adds, see `LiteralTable`
+    Extern {
+        /// Names the kind, for its type and its values
+        tag: String,
+        /// The literal as written, printed back as is
+        repr: String,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -88,4 +378,315 @@ pub struct CaseArm {
     pub constr: String,
     pub body: Vec<Op>,
     pub span: Span,
+    pub constr_span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Constr(String),
+    /// Matches any value and leaves it on the stack
+    Wildcard,
+}
+
+#[derive(Debug, Clone)]
+pub struct Case2Arm {
+    pub patterns: [Pattern; 2],
+    pub body: Vec<Op>,
+    pub span: Span,
+    pub pattern_spans: [Span; 2],
+}
+
+impl fmt::Display for Pragma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            PragmaKind::ExpectType(optype) => write!(f, "//@ expect-type: {}", optype),
+            PragmaKind::ExpectError(code) => write!(f, "//@ expect-error: {}", code),
+            PragmaKind::Phantom(params) => write!(f, "//@ phantom: {}", params.join(" ")),
+        }
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#[{}", self.name)?;
+        if !self.args.is_empty() {
+            write!(f, "({})", self.args.join(", "))?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, "(\"{}\")", message)?;
+        }
+        write!(f, "]")
+    }
+}
+
+fn fmt_markers(
+    attributes: &[Attribute],
+    pragmas: &[Pragma],
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    for attribute in attributes {
+        writeln!(f, "{}", attribute)?;
+    }
+    for pragma in pragmas {
+        writeln!(f, "{}", pragma)?;
+    }
+    Ok(())
+}
+
+fn fmt_data_def(name: &str, data_def: &DataDef, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt_markers(&data_def.attributes, &data_def.pragmas, f)?;
+    write!(f, "data {}", name)?;
+    for param in data_def.params.iter() {
+        write!(f, " {}", param)?;
+    }
+    write!(f, ":")?;
+    for (i, (constr_name, constr)) in data_def.constrs_in_source_order().into_iter().enumerate() {
+        write!(f, "{}", if i > 0 { ", " } else { " " })?;
+        for attribute in constr.attributes.iter() {
+            write!(f, "{} ", attribute)?;
+        }
+        if !constr.params.is_empty() {
+            let params: Vec<_> = constr.params.iter().map(Type::to_string).collect();
+            write!(f, "[{}] ", params.join(", "))?;
+        }
+        write!(f, "{}", constr_name)?;
+    }
+    write!(f, ".")
+}
+
+fn fmt_body(body: &[Op], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, ":")?;
+    if !body.is_empty() {
+        write!(f, " ")?;
+        fmt_ops(body, f)?;
+    }
+    write!(f, ".")
+}
+
+/// A definition of a module, of any kind
+#[derive(Debug, Clone, Copy)]
+pub enum Definition<'m> {
+    Data(&'m str, &'m DataDef),
+    Op(&'m str, &'m OpDef),
+    Const(&'m str, &'m ConstDef),
+}
+
+impl Definition<'_> {
+    /// The source of the definition, from its first attribute or pragma to
+    /// the `.` ending it. The span of the definition itself starts at the
+    /// keyword.
+    pub fn extent(&self) -> Span {
+        let (span, attributes, pragmas) = match self {
+            Definition::Data(_, d) => (&d.span, &d.attributes[..], &d.pragmas[..]),
+            Definition::Op(_, o) => (&o.span, &o.attributes[..], &o.pragmas[..]),
+            Definition::Const(_, c) => (&c.span, &[][..], &[][..]),
+        };
+        let start = attributes
+            .iter()
+            .map(|attribute| attribute.span.start)
+            .chain(pragmas.iter().map(|pragma| pragma.span.start))
+            .fold(span.start, usize::min);
+        Span::new(start, span.end)
+    }
+}
+
+/// A definition on its own rather than in a module, as
+/// `parse_definitions` yields them
+#[derive(Debug, Clone)]
+pub enum TopLevel {
+    Data(String, DataDef),
+    Op(String, OpDef),
+    Const(String, ConstDef),
+}
+
+impl TopLevel {
+    pub fn name(&self) -> &str {
+        match self {
+            TopLevel::Data(name, _) | TopLevel::Op(name, _) | TopLevel::Const(name, _) => name,
+        }
+    }
+
+    pub fn as_definition(&self) -> Definition<'_> {
+        match self {
+            TopLevel::Data(name, data_def) => Definition::Data(name, data_def),
+            TopLevel::Op(name, op_def) => Definition::Op(name, op_def),
+            TopLevel::Const(name, const_def) => Definition::Const(name, const_def),
+        }
+    }
+
+    /// A module of just this definition
+    pub fn into_module(self) -> Module {
+        let mut module = Module::new(HashMap::new(), HashMap::new());
+        match self {
+            TopLevel::Data(name, data_def) => {
+                module.data_defs.insert(name, data_def);
+            }
+            TopLevel::Op(name, op_def) => {
+                module.op_defs.insert(name, op_def);
+            }
+            TopLevel::Const(name, const_def) => {
+                module.const_defs.insert(name, const_def);
+            }
+        }
+        module
+    }
+}
+
+/// A definition `parse_definitions` yields, with the stack effect comments
+/// of its source
+#[derive(Debug, Clone)]
+pub struct ParsedDefinition {
+    pub def: TopLevel,
+    /// In source order, with spans into the whole source
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl ParsedDefinition {
+    pub fn name(&self) -> &str {
+        self.def.name()
+    }
+
+    /// A module of just this definition and its checkpoints
+    pub fn into_module(self) -> Module {
+        self.def.into_module().with_checkpoints(self.checkpoints)
+    }
+}
+
+impl From<TopLevel> for ParsedDefinition {
+    fn from(def: TopLevel) -> Self {
+        ParsedDefinition {
+            def,
+            checkpoints: vec![],
+        }
+    }
+}
+
+/// The definition as source, its attributes and pragmas a line each before
+/// it, with no newline after the `.`
+impl fmt::Display for Definition<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Definition::Data(name, data_def) => fmt_data_def(name, data_def, f),
+            Definition::Op(name, op_def) => {
+                fmt_markers(&op_def.attributes, &op_def.pragmas, f)?;
+                let OpType { pre, post } = &op_def.ann;
+                let ann = |stack: &[Type]| {
+                    let stack: Vec<_> = stack.iter().map(Type::to_string).collect();
+                    stack.join(", ")
+                };
+                write!(
+                    f,
+                    "define [{}] {} [{}]",
+                    ann(pre),
+                    overloaded_name(name),
+                    ann(post)
+                )?;
+                if op_def.declaration {
+                    return write!(f, ".");
+                }
+                fmt_body(&op_def.body, f)
+            }
+            Definition::Const(name, const_def) => {
+                write!(f, "const {} [{}]", name, const_def.ty)?;
+                fmt_body(&const_def.body, f)
+            }
+        }
+    }
+}
+
+/// The module as source, definitions in source order a line each. It
+/// parses back to the same definitions, with spans into the printed text.
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for def in self.definitions_in_source_order() {
+            writeln!(f, "{}", def)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Int(n) => write!(f, "{}", n),
+            Literal::Extern { repr, .. } => write!(f, "{}", repr),
+        }
+    }
+}
+
+fn fmt_ops(ops: &[Op], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, op) in ops.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", op)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for CaseArm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.body.is_empty() {
+            return write!(f, "{} {{}}", self.constr);
+        }
+        write!(f, "{} {{ ", self.constr)?;
+        fmt_ops(&self.body, f)?;
+        write!(f, " }}")
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Constr(constr) => write!(f, "{}", constr),
+            Pattern::Wildcard => write!(f, "_"),
+        }
+    }
+}
+
+impl fmt::Display for Case2Arm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [p1, p2] = &self.patterns;
+        if self.body.is_empty() {
+            return write!(f, "{} {} {{}}", p1, p2);
+        }
+        write!(f, "{} {} {{ ", p1, p2)?;
+        fmt_ops(&self.body, f)?;
+        write!(f, " }}")
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Literal { value, .. } => write!(f, "{}", value),
+            Op::Name { value, .. } => write!(f, "{}", value),
+            Op::Quote { value, .. } => {
+                write!(f, "(")?;
+                fmt_ops(value, f)?;
+                write!(f, ")")
+            }
+            Op::Case { head_arm, arms, .. } => {
+                write!(f, "case {{ {}", head_arm)?;
+                for arm in arms {
+                    write!(f, ", {}", arm)?;
+                }
+                write!(f, " }}")
+            }
+            Op::Case2 { arms, .. } => {
+                let arms: Vec<_> = arms.iter().map(|arm| arm.to_string()).collect();
+                write!(f, "case2 {{ {} }}", arms.join(", "))
+            }
+            Op::List { items, .. } => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    fmt_ops(item, f)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
 }
@@ -0,0 +1,342 @@
+//! The attributes a definition or constructor can have, what each goes on
+//! and what arguments it takes. An attribute not listed here is warned
+//! about and otherwise ignored, a listed one in the wrong place or with the
+//! wrong arguments is an error.
+
+use super::ast::{Attribute, Module, Span};
+use std::fmt;
+
+/// What attributes go on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Op,
+    Data,
+    /// A constructor of a data definition
+    Constr,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Op => write!(f, "op definitions"),
+            Target::Data => write!(f, "data definitions"),
+            Target::Constr => write!(f, "constructors"),
+        }
+    }
+}
+
+/// What goes in the parentheses of an attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Args {
+    /// No parentheses
+    None,
+    /// One or more of the names
+    OneOf(&'static [&'static str]),
+    /// One or more parameters of the data type
+    Params,
+    /// A message in quotes or nothing
+    Message,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AttributeSpec {
+    pub name: &'static str,
+    pub targets: &'static [Target],
+    pub args: Args,
+    pub doc: &'static str,
+}
+
+/// Every known attribute. Only `unchecked`, `deprecated`, `allow`,
+/// `overload` and `bench` have an effect so far, the others are checked
+/// but wait for their feature to read them.
+pub const KNOWN: [AttributeSpec; 9] = [
+    AttributeSpec {
+        name: "unchecked",
+        targets: &[Target::Op],
+        args: Args::None,
+        doc: "the body is not typechecked, the annotation is trusted",
+    },
+    AttributeSpec {
+        name: "test",
+        targets: &[Target::Op],
+        args: Args::None,
+        doc: "the op is a test",
+    },
+    AttributeSpec {
+        name: "inline",
+        targets: &[Target::Op],
+        args: Args::None,
+        doc: "uses of the op are replaced by its body",
+    },
+    AttributeSpec {
+        name: "phantom",
+        targets: &[Target::Data],
+        args: Args::Params,
+        doc: "the parameters no constructor uses on purpose",
+    },
+    AttributeSpec {
+        name: "derive",
+        targets: &[Target::Data],
+        args: Args::OneOf(&["eq", "show"]),
+        doc: "ops on the data type written by the compiler",
+    },
+    AttributeSpec {
+        name: "deprecated",
+        targets: &[Target::Op, Target::Data, Target::Constr],
+        args: Args::Message,
+        doc: "uses are warned about, with the message, say what to use instead",
+    },
+    AttributeSpec {
+        name: "allow",
+        targets: &[Target::Op],
+        args: Args::OneOf(&["deprecated"]),
+        doc: "the body may use deprecated ops and constructors without a warning",
+    },
+    AttributeSpec {
+        name: "overload",
+        targets: &[Target::Op],
+        args: Args::None,
+        doc: "other ops of the name may be defined, each use runs the one its stack fits",
+    },
+    AttributeSpec {
+        name: "bench",
+        targets: &[Target::Op],
+        args: Args::None,
+        doc: "the op is timed by `iv bench`, as ops named `bench-*` are",
+    },
+];
+
+pub fn spec(name: &str) -> Option<&'static AttributeSpec> {
+    KNOWN.iter().find(|spec| spec.name == name)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeProblemKind {
+    Unknown,
+    /// On what it does not go on, with what it goes on
+    Misplaced {
+        targets: &'static [Target],
+    },
+    /// Arguments to one that takes none
+    UnexpectedArgs,
+    /// No arguments to one that needs some
+    MissingArgs,
+    /// An argument it does not take, with the ones it does
+    BadArg {
+        arg: String,
+        expected: Vec<String>,
+    },
+}
+
+/// An attribute written wrong, see `check_attributes`
+#[derive(Debug, Clone)]
+pub struct AttributeProblem {
+    pub span: Span,
+    pub name: String,
+    pub kind: AttributeProblemKind,
+}
+
+impl AttributeProblem {
+    /// Unknown attributes only get a warning, in case they are newer than
+    /// this checker
+    pub fn is_error(&self) -> bool {
+        self.kind != AttributeProblemKind::Unknown
+    }
+}
+
+impl fmt::Display for AttributeProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = &self.name;
+        match &self.kind {
+            AttributeProblemKind::Unknown => {
+                let known: Vec<_> = KNOWN.iter().map(|spec| spec.name).collect();
+                write!(
+                    f,
+                    "unknown attribute `{}`, it is ignored\nthe known attributes are {}",
+                    name,
+                    known.join(", ")
+                )
+            }
+            AttributeProblemKind::Misplaced { targets } => {
+                let targets: Vec<_> = targets.iter().map(Target::to_string).collect();
+                let targets = match targets.split_last() {
+                    Some((last, rest)) if !rest.is_empty() => {
+                        format!("{} and {}", rest.join(", "), last)
+                    }
+                    _ => targets.join(""),
+                };
+                write!(f, "`{}` only goes on {}", name, targets)
+            }
+            AttributeProblemKind::UnexpectedArgs => {
+                write!(f, "`{}` takes no arguments", name)
+            }
+            AttributeProblemKind::MissingArgs => {
+                write!(f, "`{}` needs arguments, like `#[{}(...)]`", name, name)
+            }
+            AttributeProblemKind::BadArg { arg, expected } => write!(
+                f,
+                "`{}` does not take `{}`, it takes {}",
+                name,
+                arg,
+                expected.join(", ")
+            ),
+        }
+    }
+}
+
+/// What is wrong with the attribute on the target, `params` being the data
+/// type's parameters
+fn check(attribute: &Attribute, target: Target, params: &[String]) -> Option<AttributeProblemKind> {
+    let Some(spec) = spec(&attribute.name) else {
+        return Some(AttributeProblemKind::Unknown);
+    };
+    if !spec.targets.contains(&target) {
+        return Some(AttributeProblemKind::Misplaced {
+            targets: spec.targets,
+        });
+    }
+    let has_args = !attribute.args.is_empty() || attribute.message.is_some();
+    let expected: Vec<String> = match spec.args {
+        Args::None if !has_args => return None,
+        Args::None => return Some(AttributeProblemKind::UnexpectedArgs),
+        Args::OneOf(names) => names.iter().map(|name| name.to_string()).collect(),
+        Args::Params => params.to_vec(),
+        Args::Message => vec!["a message in quotes".to_owned()],
+    };
+    if let Some(message) = &attribute.message {
+        return match spec.args {
+            Args::Message => None,
+            _ => Some(AttributeProblemKind::BadArg {
+                arg: format!("\"{}\"", message),
+                expected,
+            }),
+        };
+    }
+    if attribute.args.is_empty() {
+        return match spec.args {
+            Args::Message => None,
+            _ => Some(AttributeProblemKind::MissingArgs),
+        };
+    }
+    let arg = attribute.args.iter().find(|arg| !expected.contains(arg))?;
+    Some(AttributeProblemKind::BadArg {
+        arg: arg.clone(),
+        expected,
+    })
+}
+
+/// The problems with the attributes of the module's definitions and
+/// constructors, in source order
+pub fn check_attributes(module: &Module) -> Vec<AttributeProblem> {
+    let ops = module
+        .op_defs
+        .values()
+        .flat_map(|op_def| op_def.attributes.iter().map(|a| (a, Target::Op, &[][..])));
+    let data = module.data_defs.values().flat_map(|data_def| {
+        let params = &data_def.params[..];
+        data_def
+            .attributes
+            .iter()
+            .map(move |a| (a, Target::Data, params))
+    });
+    let constrs = module
+        .data_defs
+        .values()
+        .flat_map(|data_def| data_def.constrs.values())
+        .flat_map(|constr| {
+            constr
+                .attributes
+                .iter()
+                .map(|a| (a, Target::Constr, &[][..]))
+        });
+    let mut problems: Vec<_> = ops
+        .chain(data)
+        .chain(constrs)
+        .filter_map(|(attribute, target, params)| {
+            Some(AttributeProblem {
+                span: attribute.span.clone(),
+                name: attribute.name.clone(),
+                kind: check(attribute, target, params)?,
+            })
+        })
+        .collect();
+    problems.sort_by_key(|problem| problem.span.start);
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    #[test]
+    fn attributes_are_checked_against_the_registry() {
+        let source = "#[unchecked] #[inline]
+            define [] a []:.
+            #[derive]
+            define [] b []:.
+            #[unchecked(fast)] #[frobnicate]
+            define [] c []:.
+            #[derive(eq, show)] #[phantom(a)]
+            data Tag a: tag.
+            #[derive(ord)] #[phantom(b)] #[phantom] #[test]
+            data Other a: other.";
+        let module = parse(source).unwrap();
+        let problems: Vec<_> = check_attributes(&module)
+            .into_iter()
+            .map(|p| (p.is_error(), p.name.clone(), p.kind))
+            .collect();
+        let error = |name: &str, kind| (true, name.to_owned(), kind);
+        let bad_arg = |arg: &str, expected: &[&str]| AttributeProblemKind::BadArg {
+            arg: arg.to_owned(),
+            expected: expected.iter().map(|e| e.to_string()).collect(),
+        };
+        let misplaced = |name: &str| AttributeProblemKind::Misplaced {
+            targets: spec(name).unwrap().targets,
+        };
+        assert_eq!(
+            problems,
+            [
+                error("derive", misplaced("derive")),
+                error("unchecked", AttributeProblemKind::UnexpectedArgs),
+                (
+                    false,
+                    "frobnicate".to_owned(),
+                    AttributeProblemKind::Unknown
+                ),
+                error("derive", bad_arg("ord", &["eq", "show"])),
+                error("phantom", bad_arg("b", &["a"])),
+                error("phantom", AttributeProblemKind::MissingArgs),
+                error("test", misplaced("test")),
+            ]
+        );
+    }
+
+    #[test]
+    fn deprecations_take_a_message() {
+        let source = "#[deprecated(\"use g\")] #[allow(deprecated)]
+            define [] f []:.
+            #[deprecated] #[allow(warnings)]
+            define [] g []:.
+            #[deprecated(soon)] #[derive(\"eq\")] #[allow(deprecated)]
+            data Tag: #[deprecated(\"use new\")] old, #[inline] new.";
+        let module = parse(source).unwrap();
+        let messages: Vec<_> = check_attributes(&module)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "`allow` does not take `warnings`, it takes deprecated",
+                "`deprecated` does not take `soon`, it takes a message in quotes",
+                "`derive` does not take `\"eq\"`, it takes eq, show",
+                "`allow` only goes on op definitions",
+                "`inline` only goes on op definitions",
+            ]
+        );
+        let old = &module.data_defs["Tag"].constrs["old"];
+        assert_eq!(old.attributes[0].message.as_deref(), Some("use new"));
+        assert_eq!(old.attributes[0].to_string(), "#[deprecated(\"use new\")]");
+    }
+}
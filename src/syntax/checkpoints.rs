@@ -0,0 +1,102 @@
+//! Stack effect comments: a comment holding nothing but `( pre -- post )`,
+//! each side a stack written as in annotations, topmost value first.
+//! Inference checks the ops before one in its body against it, any other
+//! comment is prose.
+
+use super::ast::{Checkpoint, Span};
+use super::parse_optype;
+use super::tokens::Token;
+use logos::Logos;
+use std::fmt;
+
+/// A comment shaped like a stack effect whose stacks do not parse, so it is
+/// not checked
+#[derive(Debug, Clone)]
+pub struct CheckpointWarning {
+    pub span: Span,
+    /// The comment without its `//`
+    pub text: String,
+}
+
+impl fmt::Display for CheckpointWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` looks like a stack effect but does not parse, so it is not checked\n\
+             write the stacks like `( Int, a -- a )`, topmost value first",
+            self.text
+        )
+    }
+}
+
+/// The sides of a comment shaped like `( pre -- post )`
+fn sides(text: &str) -> Option<(&str, &str)> {
+    let inner = text.strip_prefix('(')?.strip_suffix(')')?;
+    inner.split_once("--")
+}
+
+/// The stack effect comments of the source and the comments shaped like one
+/// that do not parse, both in source order
+pub fn scan(input: &str) -> (Vec<Checkpoint>, Vec<CheckpointWarning>) {
+    let mut checkpoints = vec![];
+    let mut warnings = vec![];
+    let mut after = 0;
+    for (token, range) in Token::lexer(input).spanned() {
+        if token != Ok(Token::Comment) {
+            after = range.end;
+            continue;
+        }
+        let text = input[range.start + 2..range.end].trim();
+        let Some((pre, post)) = sides(text) else {
+            continue;
+        };
+        let span = Span::new(range.start, range.end);
+        match parse_optype(&format!("{} -> {}", pre, post)) {
+            Ok(effect) => checkpoints.push(Checkpoint {
+                effect,
+                span,
+                after,
+            }),
+            Err(_) => warnings.push(CheckpointWarning {
+                span,
+                text: text.to_owned(),
+            }),
+        }
+    }
+    (checkpoints, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_stack_effects_are_checkpoints() {
+        let source = "define [Int] f [Int, Int]: // takes (an int) to two
+            dup // ( Int -- Int, Int )
+            // (Maybe a, [-> a] --)
+            swap // ( Int -- ) ( oops
+            drop //( Int -- Int -> Bool )
+            .";
+        let (checkpoints, warnings) = scan(source);
+        let effects: Vec<_> = checkpoints
+            .iter()
+            .map(|c| (c.effect.to_string(), &source[..c.after]))
+            .collect();
+        let dup = source.find("dup").unwrap() + 3;
+        assert_eq!(
+            effects,
+            [
+                ("[Int][Int, Int]".to_owned(), &source[..dup]),
+                ("[Maybe a, [][a]][]".to_owned(), &source[..dup]),
+            ]
+        );
+        let checkpoint = &checkpoints[0];
+        assert_eq!(
+            &source[checkpoint.span.start..checkpoint.span.end],
+            "// ( Int -- Int, Int )"
+        );
+        let warnings: Vec<_> = warnings.iter().map(|w| w.text.as_str()).collect();
+        assert_eq!(warnings, ["( Int -- Int -> Bool )"]);
+    }
+}
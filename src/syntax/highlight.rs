@@ -0,0 +1,244 @@
+use super::ast::Span;
+use super::tokens::{LexingError, Token};
+use logos::Logos;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralKind {
+    Int,
+    /// An integer with a suffix, see `LiteralTable`
+    Suffixed,
+    /// Text in double quotes, in attributes
+    Str,
+}
+
+/// What a token is, as far as can be told without parsing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A lowercase name: an op, a constructor or a type variable
+    Name,
+    /// An uppercase name: a data type
+    TypeName,
+    Literal(LiteralKind),
+    QuoteOpen,
+    QuoteClose,
+    /// `[` and `]` of stack types, constructor fields and list literals
+    BracketOpen,
+    BracketClose,
+    /// `{` and `}` of case arms
+    BraceOpen,
+    BraceClose,
+    /// `define` and `data`
+    DefinitionKeyword,
+    /// `case` and `case2`
+    CaseKeyword,
+    /// `_` in `case2` patterns
+    Wildcard,
+    /// `->` and `--`
+    Arrow,
+    /// `:` and `,`
+    Separator,
+    /// The `.` ending a definition
+    Terminator,
+    Comment,
+    /// The `//@` starting a pragma
+    Pragma,
+    /// The `#` starting an attribute
+    Attribute,
+    /// Text that is not a token, including integers out of range
+    Error,
+}
+
+impl TokenKind {
+    /// TextMate scope of the token kind
+    pub fn scope(&self) -> &'static str {
+        match self {
+            TokenKind::Name => "entity.name.function.iv",
+            TokenKind::TypeName => "entity.name.type.iv",
+            TokenKind::Literal(LiteralKind::Int) => "constant.numeric.integer.iv",
+            TokenKind::Literal(LiteralKind::Suffixed) => "constant.numeric.suffixed.iv",
+            TokenKind::Literal(LiteralKind::Str) => "string.quoted.double.iv",
+            TokenKind::QuoteOpen | TokenKind::QuoteClose => "punctuation.section.quote.iv",
+            TokenKind::BracketOpen | TokenKind::BracketClose => "punctuation.section.brackets.iv",
+            TokenKind::BraceOpen | TokenKind::BraceClose => "punctuation.section.braces.iv",
+            TokenKind::DefinitionKeyword => "keyword.other.definition.iv",
+            TokenKind::CaseKeyword => "keyword.control.case.iv",
+            TokenKind::Wildcard => "variable.language.wildcard.iv",
+            TokenKind::Arrow => "keyword.operator.arrow.iv",
+            TokenKind::Separator => "punctuation.separator.iv",
+            TokenKind::Terminator => "punctuation.terminator.iv",
+            TokenKind::Comment => "comment.line.double-slash.iv",
+            TokenKind::Pragma => "meta.preprocessor.pragma.iv",
+            TokenKind::Attribute => "meta.attribute.iv",
+            TokenKind::Error => "invalid.illegal.iv",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SpannedToken {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+fn kind(token: Result<Token<'_>, LexingError>) -> TokenKind {
+    match token {
+        Ok(Token::Comment) => TokenKind::Comment,
+        Ok(Token::End) => TokenKind::Terminator,
+        Ok(Token::Number(_)) => TokenKind::Literal(LiteralKind::Int),
+        Ok(Token::Suffixed(_)) => TokenKind::Literal(LiteralKind::Suffixed),
+        Ok(Token::Str(_)) => TokenKind::Literal(LiteralKind::Str),
+        Ok(Token::LIdent(_)) => TokenKind::Name,
+        Ok(Token::UIdent(_) | Token::Question) => TokenKind::TypeName,
+        Ok(Token::Define | Token::Data | Token::Const) => TokenKind::DefinitionKeyword,
+        Ok(Token::Case | Token::Case2) => TokenKind::CaseKeyword,
+        Ok(Token::Underscore) => TokenKind::Wildcard,
+        Ok(Token::PragmaStart) => TokenKind::Pragma,
+        Ok(Token::Hash) => TokenKind::Attribute,
+        Ok(Token::Colon | Token::Comma) => TokenKind::Separator,
+        Ok(Token::Arrow | Token::Dashes) => TokenKind::Arrow,
+        Ok(Token::BracketOpen) => TokenKind::BracketOpen,
+        Ok(Token::BracketClose) => TokenKind::BracketClose,
+        Ok(Token::ParenOpen) => TokenKind::QuoteOpen,
+        Ok(Token::ParenClose) => TokenKind::QuoteClose,
+        Ok(Token::BraceOpen) => TokenKind::BraceOpen,
+        Ok(Token::BraceClose) => TokenKind::BraceClose,
+        Err(_) => TokenKind::Error,
+    }
+}
+
+/// Every token of `src` including comments, in order. Never fails, text the
+/// lexer does not recognize becomes `Error` tokens. Apart from comments and
+/// errors these are exactly the tokens the parser sees.
+pub fn lex(src: &str) -> Vec<SpannedToken> {
+    Token::lexer(src)
+        .spanned()
+        .map(|(token, span)| SpannedToken {
+            kind: kind(token),
+            span: Span::new(span.start, span.end),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lexer::Lexer;
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn kinds(src: &str) -> Vec<(TokenKind, &str)> {
+        lex(src)
+            .into_iter()
+            .map(|t| (t.kind, &src[t.span.start..t.span.end]))
+            .collect()
+    }
+
+    #[test]
+    fn classifies_a_definition() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("define [Maybe a] f [[][Int]]: case { just { (1) }, nothing { } }. // done"),
+            vec![
+                (DefinitionKeyword, "define"),
+                (BracketOpen, "["),
+                (TypeName, "Maybe"),
+                (Name, "a"),
+                (BracketClose, "]"),
+                (Name, "f"),
+                (BracketOpen, "["),
+                (BracketOpen, "["),
+                (BracketClose, "]"),
+                (BracketOpen, "["),
+                (TypeName, "Int"),
+                (BracketClose, "]"),
+                (BracketClose, "]"),
+                (Separator, ":"),
+                (CaseKeyword, "case"),
+                (BraceOpen, "{"),
+                (Name, "just"),
+                (BraceOpen, "{"),
+                (QuoteOpen, "("),
+                (Literal(LiteralKind::Int), "1"),
+                (QuoteClose, ")"),
+                (BraceClose, "}"),
+                (Separator, ","),
+                (Name, "nothing"),
+                (BraceOpen, "{"),
+                (BraceClose, "}"),
+                (BraceClose, "}"),
+                (Terminator, "."),
+                (Comment, "// done"),
+            ]
+        );
+    }
+
+    #[test]
+    fn signs_and_symbols() {
+        use TokenKind::*;
+        let int = Literal(LiteralKind::Int);
+        // a sign directly before digits is part of the literal
+        assert_eq!(kinds("-3 +4"), vec![(int, "-3"), (int, "+4")]);
+        // a lone sign is not an op
+        assert_eq!(kinds("- 3"), vec![(Error, "-"), (int, "3")]);
+        // names may contain signs and digits after the first letter
+        assert_eq!(
+            kinds("exec-1-1 a+b br-2"),
+            vec![(Name, "exec-1-1"), (Name, "a+b"), (Name, "br-2")]
+        );
+        assert_eq!(kinds("x -1"), vec![(Name, "x"), (int, "-1")]);
+    }
+
+    #[test]
+    fn never_fails() {
+        use TokenKind::*;
+        // there are no string literals, an unterminated one is an error
+        // token and lexing carries on after it
+        assert_eq!(
+            kinds("\"abc 1"),
+            vec![
+                (Error, "\""),
+                (Name, "abc"),
+                (Literal(LiteralKind::Int), "1")
+            ]
+        );
+        assert_eq!(kinds("99999999999"), vec![(Error, "99999999999")]);
+        assert_eq!(kinds("//@ nope"), vec![(Pragma, "//@"), (Name, "nope")]);
+        assert_eq!(kinds("// at the end"), vec![(Comment, "// at the end")]);
+        assert_eq!(kinds("//"), vec![(Comment, "//")]);
+        assert_eq!(
+            kinds("//@ expect-error: UnknownOp"),
+            vec![
+                (Pragma, "//@"),
+                (Name, "expect-error"),
+                (Separator, ":"),
+                (TypeName, "UnknownOp")
+            ]
+        );
+        assert!(lex("").is_empty());
+    }
+
+    #[test]
+    fn agrees_with_the_parser() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for dir in ["examples", "tests/cases", "tests/codegen"] {
+            for entry in fs::read_dir(root.join(dir)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_some_and(|ext| ext == "iv") {
+                    let src = fs::read_to_string(&path).unwrap();
+                    let ours: Vec<_> = lex(&src)
+                        .into_iter()
+                        .filter(|t| t.kind != TokenKind::Comment)
+                        .map(|t| (t.span.start, t.span.end))
+                        .collect();
+                    let parsers: Vec<_> = Lexer::new(&src)
+                        .map(|t| {
+                            let (start, _, end) = t.unwrap();
+                            (start, end)
+                        })
+                        .collect();
+                    assert_eq!(ours, parsers, "{}", path.display());
+                }
+            }
+        }
+    }
+}
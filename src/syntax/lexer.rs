@@ -1,16 +1,36 @@
 use super::tokens::{LexingError, Token};
 use logos::{Logos, SpannedIter};
+use std::ops::Range;
 
 pub type Spanned<Tok, Loc, Err> = Result<(Loc, Tok, Loc), Err>;
 
 pub struct Lexer<'input> {
     token_stream: SpannedIter<'input, Token<'input>>,
+    /// Tokens starting in these ranges are dropped like comments
+    skipped: Vec<Range<usize>>,
+    /// Added to every location, for input that is a piece of a larger
+    /// source
+    offset: usize,
 }
 
 impl<'input> Lexer<'input> {
     pub fn new(input: &'input str) -> Self {
+        Self::skipping(input, vec![])
+    }
+
+    pub fn skipping(input: &'input str, skipped: Vec<Range<usize>>) -> Self {
         Self {
             token_stream: Token::lexer(input).spanned(),
+            skipped,
+            offset: 0,
+        }
+    }
+
+    /// Locations as if the input started at `offset` of a larger source
+    pub fn at(input: &'input str, offset: usize) -> Self {
+        Self {
+            offset,
+            ..Self::new(input)
         }
     }
 }
@@ -19,8 +39,12 @@ impl<'input> Iterator for Lexer<'input> {
     type Item = Spanned<Token<'input>, usize, LexingError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let skipped = &self.skipped;
+        let offset = self.offset;
         self.token_stream
-            .next()
-            .map(|(token, span)| Ok((span.start, token?, span.end)))
+            .find(|(token, span)| {
+                token != &Ok(Token::Comment) && !skipped.iter().any(|r| r.contains(&span.start))
+            })
+            .map(|(token, span)| Ok((span.start + offset, token?, span.end + offset)))
     }
 }
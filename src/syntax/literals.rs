@@ -0,0 +1,164 @@
+//! Literals an embedder adds to the language, written as an integer with a
+//! suffix like `5s` or `4kb`. The parser hands every suffixed literal to
+//! the handler registered for its suffix. Whatever the handler makes of it
+//! goes on as usual: an `Int` is an `Int`, an `Extern` literal gets the type
+//! declared for its tag with `Inference::with_extern_literal` and its value
+//! from the function given to `Evaluator::register_literal`.
+
+use super::ast::{Literal, Span};
+use super::tokens::LexingError;
+use std::collections::BTreeMap;
+
+/// Makes the literal of its text, the suffix included, at the span
+pub type LiteralHandler = fn(&str, Span) -> Result<Literal, LexingError>;
+
+/// The suffixes the parser takes and their handlers, none by default
+#[derive(Debug, Clone, Default)]
+pub struct LiteralTable {
+    handlers: BTreeMap<String, LiteralHandler>,
+}
+
+impl LiteralTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the handler for literals ending in `suffix`, replacing any
+    /// handler it had. The suffix is a lowercase letter followed by
+    /// lowercase letters and digits.
+    pub fn with_suffix(mut self, suffix: &str, handler: LiteralHandler) -> Self {
+        self.handlers.insert(suffix.to_owned(), handler);
+        self
+    }
+
+    /// The registered suffixes, ordered
+    pub fn suffixes(&self) -> impl Iterator<Item = &str> {
+        self.handlers.keys().map(String::as_str)
+    }
+
+    /// The literal of `text`, an integer immediately followed by a suffix
+    pub(crate) fn literal(&self, text: &str, span: Span) -> Result<Literal, LexingError> {
+        let digits = text
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || i == 0 && "+-".contains(c)))
+            .map_or(text.len(), |(i, _)| i);
+        let suffix = &text[digits..];
+        match self.handlers.get(suffix) {
+            Some(handler) => handler(text, span),
+            None => Err(LexingError::UnknownLiteralSuffix {
+                suffix: suffix.to_owned(),
+                start: span.start + digits,
+                end: span.end,
+                known: self.suffixes().map(str::to_owned).collect(),
+            }),
+        }
+    }
+}
+
+/// The table `parse` and the other parsers without one use
+pub(crate) static NO_LITERALS: LiteralTable = LiteralTable {
+    handlers: BTreeMap::new(),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostic;
+    use crate::evaluation::evaluator::Evaluator;
+    use crate::evaluation::types::Value;
+    use crate::syntax::parse_with_literals;
+    use crate::typing::inference::{Inference, InferenceErrorMessage};
+    use crate::typing::types::{OpType, Type};
+
+    fn seconds(text: &str, span: Span) -> Result<Literal, LexingError> {
+        let n: i32 = text.trim_end_matches('s').parse().unwrap();
+        if n < 0 {
+            return Err(LexingError::InvalidLiteral {
+                message: "durations are not negative".to_owned(),
+                start: span.start,
+                end: span.end,
+            });
+        }
+        Ok(Literal::Extern {
+            tag: "duration".to_owned(),
+            repr: text.to_owned(),
+        })
+    }
+
+    fn kilobytes(text: &str, _: Span) -> Result<Literal, LexingError> {
+        Ok(Literal::Int(
+            text.trim_end_matches("kb").parse::<i32>().unwrap() * 1024,
+        ))
+    }
+
+    #[test]
+    fn durations_end_to_end() {
+        let literals = LiteralTable::new()
+            .with_suffix("s", seconds)
+            .with_suffix("kb", kilobytes);
+        let source = "
+            define [Duration, Duration] longer [Duration]: pop.
+            define [] main [Int, Duration]: 5s 10s longer 4kb.
+        ";
+        let module = parse_with_literals(source, &literals).unwrap();
+        let duration = Type::Mono("Duration".to_owned());
+        let inference = Inference::new(&module).with_extern_literal("duration", duration.clone());
+        assert!(inference.typecheck().is_ok());
+        assert_eq!(
+            module.op_defs["main"].ann,
+            OpType {
+                pre: vec![],
+                post: vec![Type::Mono("Int".to_owned()), duration],
+            }
+        );
+        // without the declared type the literal is an error at inference
+        let err = Inference::new(&module).typecheck().unwrap_err();
+        assert!(matches!(
+            err.error,
+            InferenceErrorMessage::UnknownLiteralKind { ref tag } if tag == "duration"
+        ));
+
+        let mut evaluator = Evaluator::new(&module);
+        evaluator
+            .register_literal("duration", |text| {
+                let n: i32 = text.trim_end_matches('s').parse().unwrap();
+                Ok(Value::Int(n * 1000))
+            })
+            .unwrap();
+        evaluator.eval_main().unwrap();
+        assert_eq!(evaluator.stack, [Value::Int(5000), Value::Int(4096)]);
+    }
+
+    #[test]
+    fn unknown_suffixes_suggest_registered_ones() {
+        let literals = LiteralTable::new()
+            .with_suffix("s", seconds)
+            .with_suffix("kb", kilobytes);
+        let source = "define [] main [Int]: 4kv.";
+        let err = parse_with_literals(source, &literals).unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(source, &err);
+        assert_eq!(
+            diagnostic.message,
+            "unknown literal suffix `kv`\nthe known suffixes are kb, s"
+        );
+        assert_eq!(&source[diagnostic.span.start..diagnostic.span.end], "kv");
+        assert_eq!(
+            diagnostic.suggestions[0].apply(source),
+            "define [] main [Int]: 4kb."
+        );
+
+        let source = "define [] main [Duration]: -5s.";
+        let err = parse_with_literals(source, &literals).unwrap_err();
+        assert_eq!(
+            Diagnostic::from_parse_error(source, &err).message,
+            "invalid literal `-5s`: durations are not negative"
+        );
+        // without a table every suffix is unknown
+        let err = crate::syntax::parse("define [] main [Int]: 5s.").unwrap_err();
+        assert!(
+            Diagnostic::from_parse_error("define [] main [Int]: 5s.", &err)
+                .message
+                .ends_with("no literal suffixes are registered")
+        );
+    }
+}
@@ -3,25 +3,74 @@ use std::collections::HashMap;
 
 /// This struct's sole purpose is to avoid the O(n) constructor
 /// information lookup by generating maps associating the constructor
-/// name with the data def and the constructor info
+/// name with the data def and the constructor info. A constructor defined
+/// more than once maps to its first definition in the source.
 pub struct ModuleConstrMaps<'m> {
     pub constr_to_data_map: HashMap<&'m str, (&'m String, &'m DataDef)>,
     pub constr_to_constr_map: HashMap<&'m str, &'m DataConstr>,
+    /// The tag of each constructor, as `DataDef::constr_tag` gives it
+    pub constr_tags: HashMap<&'m str, usize>,
 }
 
 impl<'m> ModuleConstrMaps<'m> {
     pub fn new(module: &'m Module) -> Self {
         let mut constr_to_data_map = HashMap::new();
         let mut constr_to_constr_map = HashMap::new();
-        for data_pair @ (_data_name, data_def) in module.data_defs.iter() {
-            for (constr_name, constr_def) in data_def.constrs.iter() {
-                constr_to_data_map.insert(constr_name.as_str(), data_pair);
-                constr_to_constr_map.insert(constr_name.as_str(), constr_def);
+        let mut constr_tags = HashMap::new();
+        for data_pair @ (_data_name, data_def) in module.data_defs_in_source_order() {
+            for (tag, (constr_name, constr_def)) in
+                data_def.constrs_in_source_order().into_iter().enumerate()
+            {
+                constr_tags.entry(constr_name.as_str()).or_insert(tag);
+                constr_to_data_map
+                    .entry(constr_name.as_str())
+                    .or_insert(data_pair);
+                constr_to_constr_map
+                    .entry(constr_name.as_str())
+                    .or_insert(constr_def);
             }
         }
         ModuleConstrMaps {
             constr_to_data_map,
             constr_to_constr_map,
+            constr_tags,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    /// The maps number constructors on their own, they must agree with the
+    /// data definitions
+    #[test]
+    fn tags_follow_the_constructor_order() {
+        let source = "
+            data Shape: [Int] circle, [Int, Int] rect, point, [Int, Int, Int] tri.
+            data Tree a: leaf, [Tree a, a, Tree a] node.
+            data Clash: point, [Int] other.
+        ";
+        let module = parse(source).unwrap();
+        let maps = ModuleConstrMaps::new(&module);
+        assert_eq!(
+            module.data_defs["Shape"].constr_order(),
+            ["circle", "rect", "point", "tri"]
+        );
+        for (constr, (data_name, data_def)) in maps.constr_to_data_map.iter() {
+            let tag = data_def.constr_tag(constr);
+            assert_eq!(
+                Some(maps.constr_tags[constr]),
+                tag,
+                "{} of {}",
+                constr,
+                data_name
+            );
+            assert_eq!(data_def.constr_order()[tag.unwrap()], *constr);
+        }
+        assert_eq!(maps.constr_tags.len(), maps.constr_to_data_map.len());
+        assert_eq!(module.data_defs["Clash"].constr_tag("point"), Some(0));
+        assert_eq!(maps.constr_tags["point"], 2);
+    }
+}
@@ -0,0 +1,307 @@
+//! Parsing a source a definition at a time, for modules too large to keep
+//! in memory whole. The source is read a line at a time until the next
+//! definition starts, so only the definition being parsed is held.
+
+use super::ast::*;
+use super::lexer::Lexer;
+use super::literals::NO_LITERALS;
+use super::parser::IVParser;
+use super::{checkpoints, parse, DefinitionStarts, ParseError};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Why `parse_definitions` could not yield a definition
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StreamError {
+    Io(io::Error),
+    /// A definition that does not parse. Parsing goes on at the next one.
+    Parse {
+        /// The source of the definition, with whatever came before it
+        /// since the previous definition
+        source: String,
+        /// Where `source` starts in the stream
+        offset: usize,
+        /// The line of the stream `source` starts on, from 1
+        line: usize,
+    },
+}
+
+impl StreamError {
+    /// The parse error, with locations into the error's `source` rather than
+    /// the stream
+    pub fn parse_error(&self) -> Option<ParseError<'_>> {
+        match self {
+            StreamError::Io(_) => None,
+            StreamError::Parse { source, .. } => parse(source).err(),
+        }
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(err) => write!(f, "reading the source failed: {}", err),
+            StreamError::Parse { line, .. } => {
+                write!(f, "the definition from line {} does not parse", line)
+            }
+        }
+    }
+}
+
+/// The definitions of a source read from `reader`, in source order, with
+/// their spans offsets into the whole source and the checkpoints of each.
+/// A definition that does not parse is an error, the ones after it still
+/// come.
+pub fn parse_definitions<R: BufRead>(reader: R) -> Definitions<R> {
+    Definitions {
+        reader,
+        buffer: String::new(),
+        offset: 0,
+        line: 1,
+        starts: DefinitionStarts::default(),
+        parsed: VecDeque::new(),
+        done: false,
+    }
+}
+
+/// The iterator `parse_definitions` returns
+pub struct Definitions<R> {
+    reader: R,
+    /// The source read since the last definition parsed
+    buffer: String,
+    /// Where `buffer` starts in the source
+    offset: usize,
+    /// The line `buffer` starts on
+    line: usize,
+    /// The definition starts in `buffer`, as offsets into the source
+    starts: DefinitionStarts,
+    /// Definitions parsed and not yielded yet
+    parsed: VecDeque<ParsedDefinition>,
+    done: bool,
+}
+
+impl<R: BufRead> Definitions<R> {
+    /// Parses the first `len` bytes of the buffer and drops them
+    fn parse_front(&mut self, len: usize) -> Result<(), StreamError> {
+        let rest = self.buffer.split_off(len);
+        let source = std::mem::replace(&mut self.buffer, rest);
+        let offset = self.offset;
+        let line = self.line;
+        self.offset += len;
+        self.line += source.matches('\n').count();
+        self.starts.starts.retain(|&start| start >= self.offset);
+        if source.trim().is_empty() {
+            return Ok(());
+        }
+        match IVParser::new().parse(&source, &NO_LITERALS, Lexer::at(&source, offset)) {
+            Ok(module) => {
+                let checkpoints = checkpoints::scan(&source)
+                    .0
+                    .into_iter()
+                    .map(|c| Checkpoint {
+                        span: Span::new(c.span.start + offset, c.span.end + offset),
+                        after: c.after + offset,
+                        ..c
+                    });
+                self.parsed
+                    .extend(with_checkpoints(definitions(module), checkpoints.collect()));
+                Ok(())
+            }
+            Err(_) => Err(StreamError::Parse {
+                source,
+                offset,
+                line,
+            }),
+        }
+    }
+}
+
+/// The definitions of a module in source order
+fn definitions(module: Module) -> Vec<TopLevel> {
+    let mut defs: Vec<_> = module
+        .data_defs
+        .into_iter()
+        .map(|(name, d)| (d.span.start, TopLevel::Data(name, d)))
+        .chain(
+            module
+                .op_defs
+                .into_iter()
+                .map(|(name, o)| (o.span.start, TopLevel::Op(name, o))),
+        )
+        .chain(
+            module
+                .const_defs
+                .into_iter()
+                .map(|(name, c)| (c.span.start, TopLevel::Const(name, c))),
+        )
+        .collect();
+    defs.sort_by_key(|(start, _)| *start);
+    defs.into_iter().map(|(_, def)| def).collect()
+}
+
+/// The definitions, each with the checkpoints before the next one
+fn with_checkpoints(defs: Vec<TopLevel>, checkpoints: Vec<Checkpoint>) -> Vec<ParsedDefinition> {
+    let mut checkpoints = checkpoints.into_iter().peekable();
+    let mut parsed = vec![];
+    let mut defs = defs.into_iter().peekable();
+    while let Some(def) = defs.next() {
+        let next = defs.peek().map(|def| def.as_definition().extent().start);
+        let mut own = vec![];
+        while let Some(c) = checkpoints.next_if(|c| next.is_none_or(|next| c.span.start < next)) {
+            own.push(c);
+        }
+        parsed.push(ParsedDefinition {
+            def,
+            checkpoints: own,
+        });
+    }
+    parsed
+}
+
+impl<R: BufRead> Iterator for Definitions<R> {
+    type Item = Result<ParsedDefinition, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(def) = self.parsed.pop_front() {
+                return Some(Ok(def));
+            }
+            // the definition before the second start is complete, whatever
+            // comes before the first one is parsed with it
+            if let Some(&next) = self.starts.starts.get(1) {
+                if let Err(err) = self.parse_front(next - self.offset) {
+                    return Some(Err(err));
+                }
+                continue;
+            }
+            if self.done {
+                if self.buffer.is_empty() {
+                    return None;
+                }
+                if let Err(err) = self.parse_front(self.buffer.len()) {
+                    return Some(Err(err));
+                }
+                continue;
+            }
+            let end = self.buffer.len();
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => self.done = true,
+                Ok(_) => self.starts.scan(&self.buffer[end..], self.offset + end),
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(StreamError::Io(err)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(source: &str) -> Vec<Result<String, usize>> {
+        parse_definitions(source.as_bytes())
+            .map(|def| match def {
+                Ok(def) => Ok(def.name().to_owned()),
+                Err(StreamError::Parse { line, .. }) => Err(line),
+                Err(err) => panic!("{}", err),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn definitions_come_in_source_order() {
+        let source = "
+data Maybe a: nothing, [a] just.
+define [] f [Maybe Int]: 1 just. define [] g []: f pop.
+//@ expect-error: UnknownOp
+define [] h []:
+    nope.
+const size [Int]: 3.
+";
+        assert_eq!(
+            names(source),
+            [
+                Ok("Maybe".to_owned()),
+                Ok("f".to_owned()),
+                Ok("g".to_owned()),
+                Ok("h".to_owned()),
+                Ok("size".to_owned())
+            ]
+        );
+        assert!(names("").is_empty());
+        assert!(names("\n  // nothing\n").is_empty());
+    }
+
+    #[test]
+    fn spans_are_into_the_whole_source() {
+        let source = "define [] f []: .\n\n#[unchecked]\ndefine [Int] g [Int]: 1 pop.\n";
+        let module = parse(source).unwrap();
+        for def in parse_definitions(source.as_bytes()) {
+            let TopLevel::Op(name, op_def) = def.unwrap().def else {
+                panic!()
+            };
+            let whole = &module.op_defs[&name];
+            assert_eq!(
+                (op_def.span.start, op_def.span.end),
+                (whole.span.start, whole.span.end)
+            );
+            assert_eq!(
+                (op_def.name_span.start, op_def.name_span.end),
+                (whole.name_span.start, whole.name_span.end)
+            );
+            let extent = Definition::Op(&name, &op_def).extent();
+            let whole_extent = Definition::Op(&name, whole).extent();
+            assert_eq!(
+                &source[extent.start..extent.end],
+                &source[whole_extent.start..whole_extent.end]
+            );
+        }
+    }
+
+    #[test]
+    fn checkpoints_go_with_their_definition() {
+        let source = "// ( -- )\ndefine [] f [Int]: 1 // ( -- Int )\n.\ndefine [Int] g []: pop // ( Int -- )\n.\n";
+        let whole = parse(source).unwrap();
+        let spans = |checkpoints: &[Checkpoint]| -> Vec<(usize, usize, usize)> {
+            (checkpoints.iter())
+                .map(|c| (c.span.start, c.span.end, c.after))
+                .collect()
+        };
+        let streamed: Vec<_> = parse_definitions(source.as_bytes())
+            .map(|def| spans(&def.unwrap().checkpoints))
+            .collect();
+        assert_eq!(streamed.len(), 2);
+        assert_eq!(streamed[0], spans(&whole.checkpoints[..2]));
+        assert_eq!(streamed[1], spans(&whole.checkpoints[2..]));
+    }
+
+    #[test]
+    fn broken_definitions_are_errors() {
+        let source = "define [] f []: .\ndefine [] g [: .\n\ndefine [] h []: .\n";
+        assert_eq!(
+            names(source),
+            [Ok("f".to_owned()), Err(2), Ok("h".to_owned())]
+        );
+        // junk after the last definition is taken as part of it
+        assert_eq!(names("define [] f []: . junk"), [Err(1)]);
+        let err = parse_definitions(source.as_bytes())
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        let StreamError::Parse {
+            source: text,
+            offset,
+            ..
+        } = &err
+        else {
+            panic!()
+        };
+        assert_eq!(text, "define [] g [: .\n\n");
+        assert_eq!(*offset, source.find(text.as_str()).unwrap());
+        assert!(err.parse_error().is_some());
+    }
+}
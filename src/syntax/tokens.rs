@@ -2,8 +2,30 @@ use logos::Logos;
 use std::num::ParseIntError;
 
 #[derive(Default, Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum LexingError {
     InvalidInteger,
+    UnknownPragma,
+    /// A type variable with arguments, which only data types can have
+    AppliedTypeVariable {
+        name: String,
+        start: usize,
+        end: usize,
+    },
+    /// A literal with a suffix the `LiteralTable` has no handler for, with
+    /// the suffixes it has
+    UnknownLiteralSuffix {
+        suffix: String,
+        start: usize,
+        end: usize,
+        known: Vec<String>,
+    },
+    /// A literal its handler rejects
+    InvalidLiteral {
+        message: String,
+        start: usize,
+        end: usize,
+    },
     #[default]
     Unexpected,
 }
@@ -14,19 +36,47 @@ impl From<ParseIntError> for LexingError {
     }
 }
 
+/// The text up to the closing quote, which has to be on the same line. An
+/// unclosed quote is an error on its own, the text after it is lexed as
+/// usual.
+fn string<'s>(lex: &mut logos::Lexer<'s, Token<'s>>) -> Option<&'s str> {
+    let rest = lex.remainder();
+    let end = rest.find(['"', '\n'])?;
+    if !rest[end..].starts_with('"') {
+        return None;
+    }
+    lex.bump(end + 1);
+    Some(&rest[..end])
+}
+
 #[derive(Debug, Logos, PartialEq, Clone)]
 #[logos(error = LexingError)]
 #[logos(skip r"[ \t\n\r]+")]
 pub enum Token<'source> {
+    /// `//@` starts a pragma instead of a comment. Comments are tokens for
+    /// highlighting, the parser's lexer drops them.
+    #[regex(r"//[^@\n][^\n]*")]
+    #[token("//")]
+    Comment,
+
     #[token(".")]
     End,
 
     #[regex(r"[+-]?\d+", |lex| lex.slice().parse())]
     Number(i32),
 
+    /// An integer with a suffix, made a literal by the `LiteralTable`
+    #[regex(r"[+-]?\d+[a-z][a-z0-9]*", |lex| lex.slice())]
+    Suffixed(&'source str),
+
     #[regex(r"[a-z][A-Za-z0-9\-\+]*", |lex| lex.slice())]
     LIdent(&'source str),
 
+    /// Text in double quotes on one line, without the quotes. Only
+    /// attributes take it, as in `#[deprecated("use g")]`.
+    #[token("\"", string)]
+    Str(&'source str),
+
     #[regex(r"[A-Z][A-Za-z0-9\-\+]*", |lex| lex.slice())]
     UIdent(&'source str),
 
@@ -34,8 +84,25 @@ pub enum Token<'source> {
     Define,
     #[token("data")]
     Data,
+    #[token("const")]
+    Const,
     #[token("case")]
     Case,
+    #[token("case2")]
+    Case2,
+    #[token("_")]
+    Underscore,
+    /// The type of values only known when the program runs
+    #[token("?")]
+    Question,
+
+    /// Followed by the pragma's name, a colon and its argument
+    #[token("//@")]
+    PragmaStart,
+
+    /// Followed by the attribute in brackets, like `#[derive(eq)]`
+    #[token("#")]
+    Hash,
 
     #[token(":")]
     Colon,
@@ -44,6 +111,10 @@ pub enum Token<'source> {
 
     #[token("->")]
     Arrow,
+    /// The whole annotation of an op leaving the stack as it is, as in
+    /// `define name --: ...`
+    #[token("--")]
+    Dashes,
 
     #[token("[")]
     BracketOpen,
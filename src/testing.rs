@@ -0,0 +1,170 @@
+//! Tests written in iv itself. An op named `test-*` typed `[][Bool]` passes
+//! when it leaves `true`, one typed `[][]` passes when it runs without a
+//! runtime error.
+
+use crate::diagnostics::{render, Diagnostic, MAX_FRAMES};
+use crate::evaluation::evaluator::Evaluator;
+use crate::evaluation::types::{EvaluatorError, RuntimeError, Value};
+use crate::syntax::ast::*;
+use crate::typing::types::{OpType, Type};
+
+/// What a test op has to do to pass, from its annotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestKind {
+    /// `[][Bool]`, leave `true`
+    Bool,
+    /// `[][]`, not hit a runtime error
+    Runs,
+}
+
+#[derive(Debug)]
+pub enum TestOutcome {
+    Passed,
+    /// Left something other than `true` on top, if anything
+    Failed(Option<Value>),
+    Errored(RuntimeError),
+    /// Named like a test but not typed like one, so never run
+    NotATest(OpType),
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    /// The name in the op's definition
+    pub span: Span,
+    pub outcome: TestOutcome,
+}
+
+/// The tests run, in source order
+#[derive(Debug, Default)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+pub fn is_test_name(name: &str) -> bool {
+    name.starts_with("test-")
+}
+
+fn test_kind(ann: &OpType) -> Option<TestKind> {
+    match (&ann.pre[..], &ann.post[..]) {
+        ([], []) => Some(TestKind::Runs),
+        ([], [Type::Mono(name)]) if name == "Bool" => Some(TestKind::Bool),
+        _ => None,
+    }
+}
+
+/// The test ops whose names contain `filter`, in source order
+pub fn discover<'m>(module: &'m Module, filter: &str) -> Vec<(&'m String, &'m OpDef)> {
+    module
+        .op_defs_in_source_order()
+        .into_iter()
+        .filter(|(name, _)| is_test_name(name) && name.contains(filter))
+        .collect()
+}
+
+/// Runs every test op whose name contains `filter` on an empty stack, the
+/// evaluator's limits and host ops apply to each. The module is expected
+/// to typecheck.
+pub fn run_tests(module: &Module, evaluator: &mut Evaluator, filter: &str) -> TestReport {
+    let mut report = TestReport::default();
+    for (name, op_def) in discover(module, filter) {
+        let outcome = match test_kind(&op_def.ann) {
+            None => TestOutcome::NotATest(op_def.ann.clone()),
+            Some(kind) => {
+                evaluator.stack.clear();
+                match (evaluator.eval_op_def(name), kind) {
+                    (Err(EvaluatorError::Runtime(err)), _) => TestOutcome::Errored(err),
+                    (Err(err), _) => unreachable!("the test op exists: {:?}", err),
+                    (Ok(()), TestKind::Runs) => TestOutcome::Passed,
+                    (Ok(()), TestKind::Bool) => match evaluator.stack.last() {
+                        Some(Value::User { constr_name, args })
+                            if constr_name == "true" && args.is_empty() =>
+                        {
+                            TestOutcome::Passed
+                        }
+                        top => TestOutcome::Failed(top.cloned()),
+                    },
+                }
+            }
+        };
+        report.results.push(TestResult {
+            name: name.to_owned(),
+            span: op_def.name_span.clone(),
+            outcome,
+        });
+    }
+    evaluator.stack.clear();
+    report
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, TestOutcome::Passed))
+    }
+
+    /// Failed, errored or not typed like a test
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failed() == 0
+    }
+
+    fn count(&self, f: impl Fn(&TestOutcome) -> bool) -> usize {
+        self.results.iter().filter(|r| f(&r.outcome)).count()
+    }
+
+    /// A line per test, then the failures with their source, then the counts
+    pub fn render(&self, source: &str) -> String {
+        let mut out = String::new();
+        for result in self.results.iter() {
+            let status = match result.outcome {
+                TestOutcome::Passed => "ok",
+                TestOutcome::Failed(_) => "FAILED",
+                TestOutcome::Errored(_) => "ERROR",
+                TestOutcome::NotATest(_) => "NOT A TEST",
+            };
+            out.push_str(&format!("test {} ... {}\n", result.name, status));
+        }
+        for result in self.results.iter() {
+            let (span, message) = match &result.outcome {
+                TestOutcome::Passed => continue,
+                TestOutcome::Failed(top) => {
+                    let left = top
+                        .as_ref()
+                        .map_or("nothing".to_owned(), |value| format!("`{}`", value));
+                    (
+                        &result.span,
+                        format!("`{}` left {} instead of `true`", result.name, left),
+                    )
+                }
+                TestOutcome::Errored(err) => {
+                    let diagnostic = Diagnostic::from_runtime_error(source, err, MAX_FRAMES);
+                    (
+                        &err.span,
+                        format!(
+                            "`{}` hit a runtime error: {}",
+                            result.name, diagnostic.message
+                        ),
+                    )
+                }
+                TestOutcome::NotATest(ann) => (
+                    &result.span,
+                    format!(
+                        "`{}` has type {}, a test has type [][Bool] or [][]",
+                        result.name, ann
+                    ),
+                ),
+            };
+            out.push('\n');
+            out.push_str(&render(source, span, &message));
+        }
+        out.push_str(&format!(
+            "\n{} passed, {} failed\n",
+            self.passed(),
+            self.failed()
+        ));
+        out
+    }
+}
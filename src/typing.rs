@@ -1,5 +1,20 @@
+pub mod cache;
+pub mod cancel;
+pub mod diff;
+pub mod dynamic;
+pub mod env;
+pub mod explain;
+pub mod incremental;
 pub mod inference;
 #[cfg(test)]
 mod inference_tests;
+pub mod overload;
 pub mod prelude_types;
+pub mod report;
+pub(crate) mod resolve;
+pub mod stack_algebra;
+pub mod strict;
+pub mod trace;
+pub mod type_env;
 pub mod types;
+pub mod unify;
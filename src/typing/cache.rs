@@ -0,0 +1,537 @@
+//! Typecheck results kept between runs. Each op definition is keyed by a
+//! hash of its source text, the bindings of the names it uses, the data
+//! definitions it mentions and the same facts of every op it can reach in
+//! the call graph. An op whose key is unchanged gets its earlier outcome
+//! instead of being inferred again. Keys are conservative: editing a
+//! data definition changes the key of every op naming its constructors or
+//! its type, and a cache written by another iv version or schema is thrown
+//! away as a whole.
+
+use super::cancel::CheckOptions;
+use super::inference::{Inference, InferenceError, InferenceErrorMessage};
+use super::report::{OpOutcome, TypecheckReport};
+use super::types::{OpType, Type};
+use crate::analysis::call_graph::CallGraph;
+use crate::diagnostics::fixes;
+use crate::hashing::Fnv;
+use crate::syntax::ast::*;
+use crate::syntax::parse_optype;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::iter::once;
+#[cfg(feature = "os")]
+use std::{fs, io, path::Path, path::PathBuf};
+
+/// Bumped whenever keys or the file format change meaning
+pub const SCHEMA_VERSION: u32 = 1;
+
+fn header() -> String {
+    format!(
+        "iv-typecheck-cache {} {}",
+        SCHEMA_VERSION,
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// An error as stored, its span relative to the start of the definition
+#[derive(Debug, Clone, PartialEq)]
+struct CachedError {
+    code: String,
+    message: String,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Entry {
+    /// The canonical inferred type
+    Inferred(OpType),
+    Unchecked,
+    Failed(CachedError),
+    ExpectedError(CachedError),
+}
+
+/// The source of an op definition, its pragmas included
+fn extent(op_def: &OpDef) -> (usize, usize) {
+    let start = op_def
+        .attributes
+        .iter()
+        .map(|attribute| attribute.span.start)
+        .chain(op_def.pragmas.iter().map(|pragma| pragma.span.start))
+        .chain(once(op_def.span.start))
+        .min()
+        .unwrap_or(op_def.span.start);
+    (start, op_def.span.end)
+}
+
+impl CachedError {
+    /// Errors at desugared code or outside the definition are not kept,
+    /// their spans would not survive edits elsewhere. Neither are errors
+    /// with suggested fixes, which are worked out from the error itself.
+    fn new(err: &InferenceError, (start, end): (usize, usize), fixes: bool) -> Option<Self> {
+        let in_def = start <= err.span.start && err.span.end <= end;
+        let keep = in_def && !fixes && matches!(err.span.origin, SpanOrigin::Source);
+        keep.then(|| CachedError {
+            code: err.code().to_owned(),
+            message: err.message(),
+            start: err.span.start - start,
+            end: err.span.end - start,
+        })
+    }
+
+    fn error(&self, start: usize) -> InferenceError {
+        InferenceError {
+            span: Span::new(start + self.start, start + self.end),
+            error: InferenceErrorMessage::Cached {
+                code: self.code.clone(),
+                message: self.message.clone(),
+            },
+        }
+    }
+}
+
+impl Entry {
+    fn new(
+        outcome: &OpOutcome,
+        extent: (usize, usize),
+        source: &str,
+        module: &Module,
+    ) -> Option<Self> {
+        let error = |err| {
+            let fixes = !fixes::suggest(source, module, err).is_empty();
+            CachedError::new(err, extent, fixes)
+        };
+        match outcome {
+            OpOutcome::Inferred(optype) => Some(Entry::Inferred(optype.canonical())),
+            OpOutcome::Unchecked => Some(Entry::Unchecked),
+            OpOutcome::Failed(err) => error(err).map(Entry::Failed),
+            OpOutcome::ExpectedError(err) => error(err).map(Entry::ExpectedError),
+            // an interrupted op says nothing about the next run
+            OpOutcome::Cancelled | OpOutcome::TimedOut => None,
+            // nor does one that was not looked at
+            OpOutcome::Skipped(_) => None,
+            // a declaration has nothing to check
+            OpOutcome::Declared => None,
+        }
+    }
+
+    fn outcome(&self, start: usize) -> OpOutcome {
+        match self {
+            Entry::Inferred(optype) => OpOutcome::Inferred(optype.clone()),
+            Entry::Unchecked => OpOutcome::Unchecked,
+            Entry::Failed(err) => OpOutcome::Failed(err.error(start)),
+            Entry::ExpectedError(err) => OpOutcome::ExpectedError(err.error(start)),
+        }
+    }
+}
+
+/// The outcomes of earlier runs by key, see `Display` for the file format
+#[derive(Debug, Clone, Default)]
+pub struct TypecheckCache {
+    entries: HashMap<u64, Entry>,
+}
+
+pub(crate) fn type_names<'t>(t: &'t Type, out: &mut BTreeSet<&'t str>) {
+    match t {
+        Type::Mono(name) => {
+            out.insert(name);
+        }
+        Type::Poly(_) | Type::Dyn => (),
+        Type::App(t1, t2) => {
+            type_names(t1, out);
+            type_names(t2, out);
+        }
+        Type::Op(optype) => {
+            for t in optype.pre.iter().chain(&optype.post) {
+                type_names(t, out);
+            }
+        }
+    }
+}
+
+/// The names an op body calls and the constructors its arms match
+pub(crate) fn body_names<'o>(ops: &'o [Op], out: &mut BTreeSet<&'o str>) {
+    for op in ops {
+        match op {
+            Op::Literal { .. } => (),
+            Op::Name { value, .. } => {
+                out.insert(value);
+            }
+            Op::Quote { value, .. } => body_names(value, out),
+            Op::Case { head_arm, arms, .. } => {
+                for arm in once(head_arm).chain(arms) {
+                    out.insert(&arm.constr);
+                    body_names(&arm.body, out);
+                }
+            }
+            Op::Case2 { arms, .. } => {
+                for arm in arms {
+                    for pattern in arm.patterns.iter() {
+                        if let Pattern::Constr(constr) = pattern {
+                            out.insert(constr);
+                        }
+                    }
+                    body_names(&arm.body, out);
+                }
+            }
+            Op::List { items, .. } => {
+                for item in items {
+                    body_names(item, out);
+                }
+            }
+        }
+    }
+}
+
+/// What checking the op alone depends on, as text: its source, every
+/// binding of each name it uses and the data definitions whose
+/// constructors or types it mentions
+fn local_facts(inference: &Inference, source: &str, name: &str, op_def: &OpDef) -> String {
+    let module = inference.module();
+    let (start, end) = extent(op_def);
+    let mut facts = source[start..end].to_owned();
+    let mut names = BTreeSet::from([name]);
+    body_names(&op_def.body, &mut names);
+    let mut types = BTreeSet::new();
+    for t in op_def.ann.pre.iter().chain(&op_def.ann.post) {
+        type_names(t, &mut types);
+    }
+    for name in names {
+        facts.push_str(&format!("\nname {}:", name));
+        for binding in inference.env().bindings(name) {
+            facts.push_str(&format!(" {} {}", binding.provenance, binding.scheme));
+        }
+        for (data_name, data_def) in module.data_defs_in_source_order() {
+            if data_def.constrs.contains_key(name) {
+                types.insert(data_name);
+            }
+        }
+    }
+    for type_name in types {
+        facts.push_str(&format!("\ntype {}:", type_name));
+        if let Some(data_def) = module.data_defs.get(type_name) {
+            facts.push_str(&source[data_def.span.start..data_def.span.end]);
+        }
+    }
+    facts
+}
+
+/// The key of every op definition of the inference's module
+fn op_keys(inference: &Inference, source: &str) -> HashMap<String, u64> {
+    let module = inference.module();
+    let facts: HashMap<_, _> = module
+        .op_defs
+        .iter()
+        .map(|(name, op_def)| (name.as_str(), local_facts(inference, source, name, op_def)))
+        .collect();
+    let graph = CallGraph::new(module);
+    module
+        .op_defs
+        .keys()
+        .map(|name| {
+            let mut reachable = BTreeSet::new();
+            let mut todo = vec![name.as_str()];
+            while let Some(op) = todo.pop() {
+                for call in graph.calls(op) {
+                    if reachable.insert(call.callee) {
+                        todo.push(call.callee);
+                    }
+                }
+            }
+            let mut hash = Fnv::new();
+            hash.add(&header());
+            hash.add(&format!("{:?}", inference.strict()));
+            hash.add(&facts[name.as_str()]);
+            for op in reachable {
+                hash.add(op);
+                hash.add(&facts[op]);
+            }
+            (name.clone(), hash.0)
+        })
+        .collect()
+}
+
+impl TypecheckCache {
+    pub fn new() -> Self {
+        TypecheckCache::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Checks the module like `Inference::report_with`, reusing the
+    /// outcomes of ops whose keys are in the cache. `source` is what the
+    /// module was parsed from. Afterwards the cache holds the outcomes of
+    /// this run only.
+    pub fn report(
+        &mut self,
+        inference: &Inference,
+        source: &str,
+        opts: &CheckOptions,
+    ) -> TypecheckReport {
+        let keys = op_keys(inference, source);
+        let report = inference.report_reusing(opts, &|name, op_def| {
+            let entry = self.entries.get(keys.get(name)?)?;
+            Some(entry.outcome(extent(op_def).0))
+        });
+        let module = inference.module();
+        self.entries = report
+            .ops
+            .iter()
+            .filter_map(|op| {
+                let key = keys[&op.name];
+                let entry = if op.from_cache {
+                    self.entries.get(&key).cloned()
+                } else {
+                    let op_def = &module.op_defs[&op.name];
+                    Entry::new(&op.outcome, extent(op_def), source, module)
+                };
+                Some((key, entry?))
+            })
+            .collect();
+        report
+    }
+
+    /// Reads a cache written by `to_string`, an empty cache when it was
+    /// written by another version or is not a cache at all
+    pub fn parse(text: &str) -> Self {
+        let mut lines = text.lines();
+        if lines.next() != Some(header().as_str()) {
+            return TypecheckCache::new();
+        }
+        let entries: Option<HashMap<_, _>> = lines.map(parse_entry).collect();
+        TypecheckCache {
+            entries: entries.unwrap_or_default(),
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(c) => out.push(c),
+            None => (),
+        }
+    }
+    out
+}
+
+fn parse_entry(line: &str) -> Option<(u64, Entry)> {
+    let fields: Vec<_> = line.split('\t').collect();
+    let key = u64::from_str_radix(fields.first()?, 16).ok()?;
+    let error = |fields: &[&str]| match fields {
+        [code, start, end, message] => Some(CachedError {
+            code: code.to_string(),
+            message: unescape(message),
+            start: start.parse().ok()?,
+            end: end.parse().ok()?,
+        }),
+        _ => None,
+    };
+    let entry = match &fields[1..] {
+        ["inferred", optype] => Entry::Inferred(parse_optype(optype).ok()?),
+        ["unchecked"] => Entry::Unchecked,
+        ["failed", rest @ ..] => Entry::Failed(error(rest)?),
+        ["expected", rest @ ..] => Entry::ExpectedError(error(rest)?),
+        _ => return None,
+    };
+    Some((key, entry))
+}
+
+/// A header line with the schema and iv versions, then a line per entry:
+/// the key in hex and the outcome, fields separated by tabs
+impl fmt::Display for TypecheckCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", header())?;
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|(key, _)| **key);
+        for (key, entry) in entries {
+            write!(f, "{:016x}\t", key)?;
+            let (kind, err) = match entry {
+                Entry::Inferred(optype) => {
+                    writeln!(f, "inferred\t{}", optype)?;
+                    continue;
+                }
+                Entry::Unchecked => {
+                    writeln!(f, "unchecked")?;
+                    continue;
+                }
+                Entry::Failed(err) => ("failed", err),
+                Entry::ExpectedError(err) => ("expected", err),
+            };
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}",
+                kind,
+                err.code,
+                err.start,
+                err.end,
+                escape(&err.message)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "os")]
+impl TypecheckCache {
+    /// Where the cache of a source file goes in the cache directory, named
+    /// after a hash of the file's path
+    pub fn path_in(dir: &Path, source_path: &Path) -> PathBuf {
+        let source_path = fs::canonicalize(source_path).unwrap_or(source_path.to_owned());
+        let mut hash = Fnv::new();
+        hash.add(&source_path.to_string_lossy());
+        dir.join(format!("{:016x}.cache", hash.0))
+    }
+
+    /// Reads the cache file, an empty cache when there is none yet
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path).map_or_else(|_| TypecheckCache::new(), |text| Self::parse(&text))
+    }
+
+    /// Writes the cache file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desugar::desugar;
+    use crate::syntax::parse;
+    use crate::typing::strict::StrictOptions;
+
+    const SOURCE: &str = "
+        data Nat: zero, [Nat] suc.
+        data Bool: true, false.
+        define [Nat] two [Nat]: suc suc.
+        define [Nat] four [Nat]: two two.
+        define [Nat] is-zero [Bool]: case { zero { true }, suc { pop false } }.
+        define [Bool] flip [Bool]: case { true { false }, false { true } }.
+        define [] broken [Nat]: zero flip.
+        ";
+
+    fn check(source: &str, cache: &mut TypecheckCache, strict: StrictOptions) -> TypecheckReport {
+        let module = desugar(parse(source).unwrap()).unwrap();
+        let inference = Inference::new(&module).with_strict(strict);
+        cache.report(&inference, source, &CheckOptions::default())
+    }
+
+    /// The ops taken from the cache
+    fn cached(source: &str, cache: &mut TypecheckCache) -> Vec<String> {
+        check(source, cache, StrictOptions::default())
+            .ops
+            .into_iter()
+            .filter(|op| op.from_cache)
+            .map(|op| op.name)
+            .collect()
+    }
+
+    fn warm() -> TypecheckCache {
+        let mut cache = TypecheckCache::new();
+        assert!(cached(SOURCE, &mut cache).is_empty());
+        cache
+    }
+
+    #[test]
+    fn unchanged_ops_come_from_the_cache() {
+        let mut cache = warm();
+        assert_eq!(cache.len(), 5);
+        let report = check(SOURCE, &mut cache, StrictOptions::default());
+        assert!(report.ops.iter().all(|op| op.from_cache));
+        // the cached outcomes are the checked ones, errors at the same place
+        let fresh = Inference::new(&desugar(parse(SOURCE).unwrap()).unwrap()).report();
+        for (cached, fresh) in report.ops.iter().zip(fresh.ops.iter()) {
+            match (&cached.outcome, &fresh.outcome) {
+                (OpOutcome::Inferred(a), OpOutcome::Inferred(b)) => {
+                    assert_eq!(a.to_string(), b.canonical().to_string())
+                }
+                (OpOutcome::Failed(a), OpOutcome::Failed(b)) => {
+                    assert_eq!(a.code(), b.code());
+                    assert_eq!(a.message(), b.message());
+                    assert_eq!((a.span.start, a.span.end), (b.span.start, b.span.end));
+                }
+                outcomes => panic!("{}: {:?}", cached.name, outcomes),
+            }
+        }
+        // moving every definition keeps the keys, and the errors move along
+        let moved = format!("\n\n  {}", SOURCE);
+        let report = check(&moved, &mut cache, StrictOptions::default());
+        assert!(report.ops.iter().all(|op| op.from_cache));
+        let err = report.all_errors().next().unwrap();
+        assert_eq!(&moved[err.span.start..err.span.end], "flip");
+    }
+
+    #[test]
+    fn edits_recheck_what_they_reach() {
+        // callers of an edited op are checked again, the others are not
+        let mut cache = warm();
+        let edited = SOURCE.replace("suc suc.", "suc suc suc.");
+        assert_eq!(cached(&edited, &mut cache), ["is-zero", "flip", "broken"]);
+        // the cache now holds the edited module only
+        assert_eq!(cached(SOURCE, &mut cache), ["is-zero", "flip", "broken"]);
+        // a data definition invalidates the ops naming its constructors or
+        // its type
+        let mut cache = warm();
+        let edited = SOURCE.replace("true, false.", "true, false, maybe.");
+        assert_eq!(cached(&edited, &mut cache), ["two", "four"]);
+        // a comment between definitions changes nothing
+        let mut cache = warm();
+        let edited = SOURCE.replace("define [] broken", "// soon\n define [] broken");
+        assert_eq!(cached(&edited, &mut cache).len(), 5);
+        // a new definition a name now refers to counts as a change
+        let mut cache = warm();
+        let edited = format!("{}\ndefine [Nat] pop [Nat]:.", SOURCE);
+        assert_eq!(
+            cached(&edited, &mut cache),
+            ["two", "four", "flip", "broken"]
+        );
+    }
+
+    #[test]
+    fn other_options_recheck_everything() {
+        let mut cache = warm();
+        let report = check(SOURCE, &mut cache, StrictOptions::all());
+        assert!(report.ops.iter().all(|op| !op.from_cache));
+    }
+
+    #[test]
+    fn cache_files_round_trip() {
+        let cache = warm();
+        let text = cache.to_string();
+        assert!(text.starts_with(&header()));
+        let mut read = TypecheckCache::parse(&text);
+        assert_eq!(read.entries, cache.entries);
+        assert_eq!(cached(SOURCE, &mut read).len(), 5);
+        // another schema or a damaged entry throws everything away
+        let other = text.replacen(&SCHEMA_VERSION.to_string(), "0", 1);
+        assert!(TypecheckCache::parse(&other).is_empty());
+        assert!(TypecheckCache::parse(&format!("{}junk\n", text)).is_empty());
+        assert!(TypecheckCache::parse("").is_empty());
+        let message = "two\tlines\nand a \\";
+        assert_eq!(unescape(&escape(message)), message);
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "os")]
+use std::time::Duration;
+
+/// Shared flag to stop a running typecheck from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether a cancellable typecheck ran to the end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    Finished,
+    Cancelled,
+}
+
+/// Why inference stopped before reaching a result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Cancelled,
+    /// The op definition ran over its budget
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    pub cancel: Option<CancelToken>,
+    /// Wall clock budget for checking a single op definition, needs a clock
+    /// and so the `os` feature
+    #[cfg(feature = "os")]
+    pub op_budget: Option<Duration>,
+}
@@ -0,0 +1,388 @@
+use super::types::*;
+use std::collections::HashMap;
+use std::iter::zip;
+
+/// How one stack slot of two op types compares
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlotDiff {
+    /// Equal up to renaming of type variables
+    Same(Type),
+    Differs(Type, Type),
+    /// Past the end of the other stack
+    OnlyLeft(Type),
+    OnlyRight(Type),
+}
+
+/// Slot by slot comparison of two op types, topmost slot first. Variables of
+/// the right side that correspond to variables of the left side are renamed
+/// to them, all variables are then named `a`, `b`, ... in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpTypeDiff {
+    pub pre: Vec<SlotDiff>,
+    pub post: Vec<SlotDiff>,
+}
+
+/// A one to one correspondence of left and right variables, built up while
+/// comparing slots
+#[derive(Default, Clone)]
+struct Renaming {
+    left_to_right: HashMap<String, String>,
+    right_to_left: HashMap<String, String>,
+}
+
+impl Renaming {
+    fn unify(&mut self, l: &Type, r: &Type) -> bool {
+        match (l, r) {
+            (Type::Mono(a), Type::Mono(b)) => a == b,
+            (Type::Poly(a), Type::Poly(b)) => {
+                match (self.left_to_right.get(a), self.right_to_left.get(b)) {
+                    (None, None) => {
+                        self.left_to_right.insert(a.to_owned(), b.to_owned());
+                        self.right_to_left.insert(b.to_owned(), a.to_owned());
+                        true
+                    }
+                    (Some(mapped), Some(_)) => mapped == b,
+                    _ => false,
+                }
+            }
+            (Type::Op(o1), Type::Op(o2)) => {
+                o1.pre.len() == o2.pre.len()
+                    && o1.post.len() == o2.post.len()
+                    && zip(o1.pre.iter().chain(&o1.post), o2.pre.iter().chain(&o2.post))
+                        .all(|(l, r)| self.unify(l, r))
+            }
+            (Type::App(l1, l2), Type::App(r1, r2)) => self.unify(l1, r1) && self.unify(l2, r2),
+            _ => false,
+        }
+    }
+
+    /// Whether the slots are equal up to renaming, only keeps the variables
+    /// paired up on the way when they are
+    fn slot(&mut self, l: &Type, r: &Type) -> bool {
+        let mut attempt = self.clone();
+        let same = attempt.unify(l, r);
+        if same {
+            *self = attempt;
+        }
+        same
+    }
+
+    /// The right type in terms of the left variables, unpaired variables get
+    /// names no left variable can have
+    fn rename_right(&self, t: &Type) -> Type {
+        match t {
+            Type::Mono(_) | Type::Dyn => t.clone(),
+            Type::Poly(v) => match self.right_to_left.get(v) {
+                Some(l) => Type::Poly(l.to_owned()),
+                None => Type::Poly(format!("{}'", v)),
+            },
+            Type::Op(o) => Type::Op(OpType {
+                pre: o.pre.iter().map(|t| self.rename_right(t)).collect(),
+                post: o.post.iter().map(|t| self.rename_right(t)).collect(),
+            }),
+            Type::App(t1, t2) => Type::App(
+                Box::new(self.rename_right(t1)),
+                Box::new(self.rename_right(t2)),
+            ),
+        }
+    }
+}
+
+fn diff_stack(
+    left: &[Type],
+    right: &[Type],
+    renaming: &Renaming,
+    n: &mut Normalizer,
+) -> Vec<SlotDiff> {
+    (0..left.len().max(right.len()))
+        .map(|i| match (left.get(i), right.get(i)) {
+            (Some(l), Some(r)) => {
+                let l = n.ty(l);
+                let r = n.ty(&renaming.rename_right(r));
+                if l == r {
+                    SlotDiff::Same(l)
+                } else {
+                    SlotDiff::Differs(l, r)
+                }
+            }
+            (Some(l), None) => SlotDiff::OnlyLeft(n.ty(l)),
+            (None, Some(r)) => SlotDiff::OnlyRight(n.ty(&renaming.rename_right(r))),
+            (None, None) => unreachable!(),
+        })
+        .collect()
+}
+
+/// The variables of the type in order of appearance, leaving out the ones
+/// the checker made up, whose names start with `_`
+pub(crate) fn written_vars(t: &Type, out: &mut Vec<String>) {
+    match t {
+        Type::Mono(_) | Type::Dyn => (),
+        Type::Poly(v) if v.starts_with('_') || out.contains(v) => (),
+        Type::Poly(v) => out.push(v.clone()),
+        Type::Op(o) => o
+            .pre
+            .iter()
+            .chain(&o.post)
+            .for_each(|t| written_vars(t, out)),
+        Type::App(t1, t2) => {
+            written_vars(t1, out);
+            written_vars(t2, out);
+        }
+    }
+}
+
+impl OpType {
+    pub fn diff(&self, other: &OpType) -> OpTypeDiff {
+        self.diff_with(other, Normalizer::default())
+    }
+
+    fn renaming(&self, other: &OpType) -> Renaming {
+        let mut renaming = Renaming::default();
+        for (l, r) in zip(&self.pre, &other.pre).chain(zip(&self.post, &other.post)) {
+            renaming.slot(l, r);
+        }
+        renaming
+    }
+
+    fn diff_with(&self, other: &OpType, mut n: Normalizer) -> OpTypeDiff {
+        let renaming = self.renaming(other);
+        let pre = diff_stack(&self.pre, &other.pre, &renaming, &mut n);
+        let post = diff_stack(&self.post, &other.post, &renaming, &mut n);
+        OpTypeDiff { pre, post }
+    }
+
+    /// Names for showing the type next to `other`, an annotation or the
+    /// like: the variables written in `other` keep their names, and the
+    /// variables in the same slots here take them
+    pub fn naming_after(&self, other: &OpType) -> Normalizer {
+        let renaming = self.renaming(other);
+        let mut written = vec![];
+        other
+            .pre
+            .iter()
+            .chain(&other.post)
+            .for_each(|t| written_vars(t, &mut written));
+        let names = written.into_iter().flat_map(|v| {
+            // unpaired variables come out of `rename_right` primed
+            let here = match renaming.right_to_left.get(&v) {
+                Some(l) => l.clone(),
+                None => format!("{}'", v),
+            };
+            [(v.clone(), v.clone()), (here, v)]
+        });
+        Normalizer::naming(names)
+    }
+
+    /// Like `diff`, with the variables named as `naming_after` names them
+    pub fn diff_naming_after(&self, other: &OpType) -> OpTypeDiff {
+        self.diff_with(other, self.naming_after(other))
+    }
+}
+
+fn values(n: usize) -> String {
+    match n {
+        1 => "1 value".to_owned(),
+        n => format!("{} values", n),
+    }
+}
+
+impl OpTypeDiff {
+    pub fn is_same(&self) -> bool {
+        self.pre
+            .iter()
+            .chain(&self.post)
+            .all(|slot| matches!(slot, SlotDiff::Same(_)))
+    }
+
+    /// A table of the slots, differing ones marked, followed by a line per
+    /// stack of different lengths. `left` and `right` name the two sides.
+    pub fn render(&self, left: &str, right: &str) -> String {
+        let mut rows = vec![([String::new(), left.to_owned(), right.to_owned()], "")];
+        for (stack, slots) in [("pre", &self.pre), ("post", &self.post)] {
+            for (i, slot) in slots.iter().enumerate() {
+                let (l, r, mark) = match slot {
+                    SlotDiff::Same(t) => (t.to_string(), t.to_string(), ""),
+                    SlotDiff::Differs(l, r) => (l.to_string(), r.to_string(), "differs"),
+                    SlotDiff::OnlyLeft(l) => (l.to_string(), String::new(), "extra"),
+                    SlotDiff::OnlyRight(r) => (String::new(), r.to_string(), "extra"),
+                };
+                rows.push(([format!("{} {}", stack, i), l, r], mark));
+            }
+        }
+        let width = |col: usize| rows.iter().map(|(cells, _)| cells[col].len()).max();
+        let (w0, w1, w2) = (width(0).unwrap(), width(1).unwrap(), width(2).unwrap());
+        let mut lines: Vec<_> = rows
+            .iter()
+            .map(|([label, l, r], mark)| {
+                let line = format!("  {:w0$}  {:w1$}  {:w2$}  {}", label, l, r, mark);
+                line.trim_end().to_owned()
+            })
+            .collect();
+        for (verb, slots) in [("takes", &self.pre), ("produces", &self.post)] {
+            let extra_left: Vec<_> = slots
+                .iter()
+                .filter_map(|slot| match slot {
+                    SlotDiff::OnlyLeft(t) => Some(format!("`{}`", t)),
+                    _ => None,
+                })
+                .collect();
+            let extra_right: Vec<_> = slots
+                .iter()
+                .filter_map(|slot| match slot {
+                    SlotDiff::OnlyRight(t) => Some(format!("`{}`", t)),
+                    _ => None,
+                })
+                .collect();
+            let (longer, shorter, extra) = if !extra_left.is_empty() {
+                (left, right, extra_left)
+            } else if !extra_right.is_empty() {
+                (right, left, extra_right)
+            } else {
+                continue;
+            };
+            lines.push(format!(
+                "  the {} {} {} but the {} {}, extra: {}",
+                longer,
+                verb,
+                values(slots.len()),
+                shorter,
+                slots.len() - extra.len(),
+                extra.join(", ")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poly(v: &str) -> Type {
+        Type::Poly(v.to_owned())
+    }
+
+    fn mono(v: &str) -> Type {
+        Type::Mono(v.to_owned())
+    }
+
+    fn op(pre: Vec<Type>, post: Vec<Type>) -> OpType {
+        OpType { pre, post }
+    }
+
+    #[test]
+    fn renamed_variables_are_the_same() {
+        let diff = op(vec![poly("x"), poly("y")], vec![poly("y"), poly("x")])
+            .diff(&op(vec![poly("p"), poly("q")], vec![poly("q"), poly("p")]));
+        assert!(diff.is_same());
+        assert_eq!(
+            diff.pre,
+            vec![SlotDiff::Same(poly("a")), SlotDiff::Same(poly("b"))]
+        );
+    }
+
+    #[test]
+    fn concrete_against_variable_differs() {
+        let diff =
+            op(vec![poly("a")], vec![mono("Int")]).diff(&op(vec![poly("b")], vec![poly("b")]));
+        assert_eq!(diff.pre, vec![SlotDiff::Same(poly("a"))]);
+        assert_eq!(diff.post, vec![SlotDiff::Differs(mono("Int"), poly("a"))]);
+        assert_eq!(
+            diff.render("inferred type", "annotation"),
+            "          inferred type  annotation\n  \
+             pre 0   a              a\n  \
+             post 0  Int            a           differs"
+        );
+    }
+
+    #[test]
+    fn inconsistent_renaming_differs() {
+        // x maps to p in the first slot, so p in the second slot is not y
+        let diff =
+            op(vec![poly("x"), poly("y")], vec![]).diff(&op(vec![poly("p"), poly("p")], vec![]));
+        assert_eq!(
+            diff.pre,
+            vec![
+                SlotDiff::Same(poly("a")),
+                SlotDiff::Differs(poly("b"), poly("a"))
+            ]
+        );
+    }
+
+    #[test]
+    fn unpaired_variables_do_not_collide() {
+        let diff = op(vec![mono("Int")], vec![]).diff(&op(vec![poly("a")], vec![poly("b")]));
+        assert_eq!(diff.pre, vec![SlotDiff::Differs(mono("Int"), poly("a"))]);
+        assert_eq!(diff.post, vec![SlotDiff::OnlyRight(poly("b"))]);
+    }
+
+    #[test]
+    fn written_names_are_kept() {
+        // `_x` pairs with `b` and `_y` with `a`, the made up name of `_z`
+        // avoids the written ones
+        let inf = op(vec![poly("_x"), poly("_y")], vec![poly("_y"), poly("_z")]);
+        let ann = op(vec![poly("b"), mono("Int")], vec![poly("a")]);
+        let diff = inf.diff_naming_after(&ann);
+        assert_eq!(
+            diff.pre,
+            vec![
+                SlotDiff::Same(poly("b")),
+                SlotDiff::Differs(poly("a"), mono("Int"))
+            ]
+        );
+        assert_eq!(
+            diff.post,
+            vec![SlotDiff::Same(poly("a")), SlotDiff::OnlyLeft(poly("c"))]
+        );
+        let mut n = inf.naming_after(&ann);
+        assert_eq!(n.optype(&inf).to_string(), "[b, a][a, c]");
+        assert_eq!(n.optype(&ann).to_string(), "[b, Int][a]");
+    }
+
+    #[test]
+    fn length_differences() {
+        let diff = op(vec![], vec![mono("Foo"), mono("Int")])
+            .diff(&op(vec![mono("Bool")], vec![mono("Foo")]));
+        assert_eq!(diff.pre, vec![SlotDiff::OnlyRight(mono("Bool"))]);
+        assert_eq!(
+            diff.post,
+            vec![SlotDiff::Same(mono("Foo")), SlotDiff::OnlyLeft(mono("Int"))]
+        );
+        assert_eq!(
+            diff.render("body", "annotation"),
+            "          body  annotation\n  \
+             pre 0         Bool        extra\n  \
+             post 0  Foo   Foo\n  \
+             post 1  Int               extra\n  \
+             the annotation takes 1 value but the body 0, extra: `Bool`\n  \
+             the body produces 2 values but the annotation 1, extra: `Int`"
+        );
+    }
+
+    #[test]
+    fn nested_quotes_compare_as_a_whole() {
+        let quote = |t| Type::Op(op(vec![], vec![t]));
+        let diff = op(vec![], vec![quote(poly("a")), poly("a")])
+            .diff(&op(vec![], vec![quote(poly("b")), mono("Int")]));
+        assert_eq!(
+            diff.post,
+            vec![
+                SlotDiff::Same(quote(poly("a"))),
+                SlotDiff::Differs(poly("a"), mono("Int"))
+            ]
+        );
+        // the failed first slot leaves b free to pair up with a
+        let diff = op(vec![], vec![quote(mono("Int")), poly("a")]).diff(&op(
+            vec![],
+            vec![Type::Op(op(vec![poly("b")], vec![poly("b")])), poly("b")],
+        ));
+        assert_eq!(
+            diff.post[0],
+            SlotDiff::Differs(
+                quote(mono("Int")),
+                Type::Op(op(vec![poly("a")], vec![poly("a")]))
+            )
+        );
+        assert_eq!(diff.post[1], SlotDiff::Same(poly("a")));
+    }
+}
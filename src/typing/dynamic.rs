@@ -0,0 +1,113 @@
+//! Values of type `?`, which host ops and ops declared without a body may
+//! take and leave when their types are only known as the program runs. A
+//! `?` fits every type and binds no variable, the variables of what it
+//! fits become `?` too. Where an op takes a value of a type more specific
+//! than the `?` the ops before it leave, checking records a `DynCheck`,
+//! and the evaluator checks the value before running the op, see
+//! `Evaluator::with_dyn_checks`.
+//!
+//! Nothing else lets a `?` become a type: an op with a body cannot have
+//! one in its annotation, so its body leaving one where the annotation
+//! says a type is an `AnnInfConflict`, and joined case arms leave a `?`
+//! where either arm does. A quote is only checked to be a quote, what its
+//! ops take and leave is checked where they run.
+
+use super::types::{NameSupply, OpType, Type};
+use std::iter::zip;
+
+/// A value an op takes that has to be checked to have the type, since the
+/// ops before leave it with a `?` in its type
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynCheck {
+    /// The position of the value, 0 is the topmost
+    pub depth: usize,
+    pub expected: Type,
+}
+
+/// Whether `expected` says more than `found`, somewhere `found` has a `?`
+pub fn narrows(found: &Type, expected: &Type) -> bool {
+    match (found, expected) {
+        (Type::Dyn, Type::Dyn | Type::Poly(_)) => false,
+        (Type::Dyn, _) => true,
+        (Type::App(f1, a1), Type::App(f2, a2)) => narrows(f1, f2) || narrows(a1, a2),
+        (Type::Op(o1), Type::Op(o2)) => zip(&o1.pre, &o2.pre)
+            .chain(zip(&o1.post, &o2.post))
+            .any(|(t1, t2)| narrows(t1, t2)),
+        _ => false,
+    }
+}
+
+/// `t` with a fresh variable for each `?`. Unifying it finds what the `?`s
+/// are taken as, where unifying a `?` itself would make what it meets a
+/// `?` and lose what the values below it say.
+pub fn open_dyn(t: &Type, supply: &NameSupply) -> Type {
+    match t {
+        Type::Dyn => Type::Poly(supply.fresh()),
+        Type::App(f, a) => Type::App(Box::new(open_dyn(f, supply)), Box::new(open_dyn(a, supply))),
+        Type::Op(o) => Type::Op(OpType {
+            pre: o.pre.iter().map(|t| open_dyn(t, supply)).collect(),
+            post: o.post.iter().map(|t| open_dyn(t, supply)).collect(),
+        }),
+        Type::Mono(_) | Type::Poly(_) => t.clone(),
+    }
+}
+
+/// The type of two case arms that unified to the same type but where one
+/// of them has a `?`: a `?` where a value is left, the other type where
+/// one is taken, so that either arm's values are checked where needed
+pub fn join_dyn(arms: &OpType, arm: &OpType) -> OpType {
+    join_optype(arms, arm, true)
+}
+
+fn join_optype(o1: &OpType, o2: &OpType, leaves: bool) -> OpType {
+    let pre = zip(&o1.pre, &o2.pre)
+        .map(|(t1, t2)| join_type(t1, t2, !leaves))
+        .collect();
+    let post = zip(&o1.post, &o2.post)
+        .map(|(t1, t2)| join_type(t1, t2, leaves))
+        .collect();
+    OpType { pre, post }
+}
+
+fn join_type(t1: &Type, t2: &Type, leaves: bool) -> Type {
+    match (t1, t2) {
+        (Type::Dyn, _) | (_, Type::Dyn) if leaves => Type::Dyn,
+        (Type::Dyn, t) | (t, Type::Dyn) => t.clone(),
+        (Type::App(f1, a1), Type::App(f2, a2)) => Type::App(
+            Box::new(join_type(f1, f2, leaves)),
+            Box::new(join_type(a1, a2, leaves)),
+        ),
+        (Type::Op(o1), Type::Op(o2)) => Type::Op(join_optype(o1, o2, leaves)),
+        _ => t1.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{parse_optype, parse_type};
+
+    #[test]
+    fn what_narrows_a_dyn() {
+        let narrowing = |found: &str, expected: &str| {
+            narrows(&parse_type(found).unwrap(), &parse_type(expected).unwrap())
+        };
+        assert!(narrowing("?", "Int"));
+        assert!(narrowing("?", "Maybe a"));
+        assert!(narrowing("Maybe ?", "Maybe Int"));
+        assert!(narrowing("[?][]", "[Int][]"));
+        assert!(!narrowing("?", "a"));
+        assert!(!narrowing("?", "?"));
+        assert!(!narrowing("Int", "Int"));
+        assert!(!narrowing("Maybe ?", "Maybe a"));
+    }
+
+    #[test]
+    fn joined_arms_keep_the_dyn_they_leave() {
+        let joined = join_dyn(
+            &parse_optype("[?, Int][Int, Maybe ?]").unwrap(),
+            &parse_optype("[Int, ?][?, Maybe Int]").unwrap(),
+        );
+        assert_eq!(joined, parse_optype("[Int, Int][?, Maybe ?]").unwrap());
+    }
+}
@@ -0,0 +1,269 @@
+//! Where the names in op bodies get their types: a stack of scopes, each
+//! binding names to type schemes, the innermost scope shadowing the ones below
+//! it. A module's base environment has, from the outermost, its op
+//! definitions, its constants, its constructors, the extern ops and the
+//! prelude, so that the prelude wins over everything else.
+
+use super::prelude_types::Prelude;
+use super::types::Scheme;
+use std::collections::HashMap;
+use std::fmt;
+
+/// What kind of definition a name is bound by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provenance {
+    Prelude,
+    Extern,
+    Constructor,
+    User,
+    Constant,
+    Local,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Provenance::Prelude => "prelude op",
+            Provenance::Extern => "extern op",
+            Provenance::Constructor => "constructor",
+            Provenance::User => "op defined in the module",
+            Provenance::Constant => "constant defined in the module",
+            Provenance::Local => "local binding",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The scheme a name is bound to, and what bound it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub scheme: Scheme,
+    pub provenance: Provenance,
+}
+
+#[derive(Debug, Clone)]
+enum Names {
+    /// Parametric names like `br-2` are made up on lookup, so the prelude
+    /// is asked rather than listed
+    Prelude(Prelude),
+    Map(HashMap<String, Scheme>),
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    provenance: Provenance,
+    names: Names,
+}
+
+impl Scope {
+    fn get(&self, name: &str) -> Option<Scheme> {
+        match &self.names {
+            Names::Prelude(prelude) => prelude.get(name).map(Scheme::generalize),
+            Names::Map(names) => names.get(name).cloned(),
+        }
+    }
+}
+
+/// Scopes of names, outermost first
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    scopes: Vec<Scope>,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Env::default()
+    }
+
+    /// The environment of a module with the given op annotations, constant
+    /// and constructor schemes, no extern ops and the prelude on top
+    pub fn base(
+        user: HashMap<String, Scheme>,
+        consts: HashMap<String, Scheme>,
+        constrs: HashMap<String, Scheme>,
+        prelude: Prelude,
+    ) -> Self {
+        let mut env = Env::new();
+        env.push_scope(Provenance::User, user);
+        env.push_scope(Provenance::Constant, consts);
+        env.push_scope(Provenance::Constructor, constrs);
+        env.push_scope(Provenance::Extern, HashMap::new());
+        env.scopes.push(Scope {
+            provenance: Provenance::Prelude,
+            names: Names::Prelude(prelude),
+        });
+        env
+    }
+
+    /// Adds a scope shadowing every scope so far
+    pub fn push_scope(&mut self, provenance: Provenance, names: HashMap<String, Scheme>) {
+        self.scopes.push(Scope {
+            provenance,
+            names: Names::Map(names),
+        });
+    }
+
+    /// Removes the innermost scope, returning what kind of names it had
+    pub fn pop_scope(&mut self) -> Option<Provenance> {
+        self.scopes.pop().map(|scope| scope.provenance)
+    }
+
+    /// The binding a use of the name refers to
+    pub fn lookup(&self, name: &str) -> Option<Binding> {
+        self.bindings(name).next()
+    }
+
+    /// Every binding of the name, the one it refers to first and then the
+    /// ones that binding shadows
+    pub fn bindings<'e>(&'e self, name: &'e str) -> impl Iterator<Item = Binding> + 'e {
+        self.scopes.iter().rev().filter_map(move |scope| {
+            Some(Binding {
+                scheme: scope.get(name)?,
+                provenance: scope.provenance,
+            })
+        })
+    }
+
+    /// Says what the name refers to when that hides another binding, like
+    /// "`map` here refers to the local binding, shadowing the prelude op"
+    pub fn shadowing_note(&self, name: &str) -> Option<String> {
+        let mut bindings = self.bindings(name);
+        let visible = bindings.next()?;
+        let shadowed = bindings.next()?;
+        Some(format!(
+            "`{}` here refers to the {}, shadowing the {}",
+            name, visible.provenance, shadowed.provenance
+        ))
+    }
+
+    /// The innermost scope of the provenance, to add names to
+    pub fn scope_mut(&mut self, provenance: Provenance) -> Option<&mut HashMap<String, Scheme>> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .filter(|scope| scope.provenance == provenance)
+            .find_map(|scope| match &mut scope.names {
+                Names::Map(names) => Some(names),
+                Names::Prelude(_) => None,
+            })
+    }
+
+    /// The names the innermost scope of the provenance binds, none for the
+    /// prelude, whose names are made up on lookup
+    pub fn names(&self, provenance: Provenance) -> impl Iterator<Item = (&String, &Scheme)> {
+        self.scopes
+            .iter()
+            .rev()
+            .filter(|scope| scope.provenance == provenance)
+            .find_map(|scope| match &scope.names {
+                Names::Map(names) => Some(names),
+                Names::Prelude(_) => None,
+            })
+            .into_iter()
+            .flatten()
+    }
+
+    pub fn prelude(&self) -> Option<&Prelude> {
+        self.scopes.iter().find_map(|scope| match &scope.names {
+            Names::Prelude(prelude) => Some(prelude),
+            Names::Map(_) => None,
+        })
+    }
+
+    pub fn prelude_mut(&mut self) -> Option<&mut Prelude> {
+        self.scopes
+            .iter_mut()
+            .find_map(|scope| match &mut scope.names {
+                Names::Prelude(prelude) => Some(prelude),
+                Names::Map(_) => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse_optype;
+
+    fn scheme(t: &str) -> Scheme {
+        Scheme::generalize(parse_optype(t).unwrap())
+    }
+
+    fn names(pairs: &[(&str, &str)]) -> HashMap<String, Scheme> {
+        pairs
+            .iter()
+            .map(|(name, t)| (name.to_string(), scheme(t)))
+            .collect()
+    }
+
+    fn base() -> Env {
+        Env::base(
+            names(&[
+                ("twice", "Int -> Int"),
+                ("dup", "Int -> Int"),
+                ("just", "->"),
+                ("size", "Int -> Int"),
+            ]),
+            names(&[("size", "-> Int")]),
+            names(&[("just", "a -> Maybe a"), ("nothing", "-> Maybe a")]),
+            Prelude::default(),
+        )
+    }
+
+    fn provenance(env: &Env, name: &str) -> Option<Provenance> {
+        env.lookup(name).map(|binding| binding.provenance)
+    }
+
+    #[test]
+    fn base_scopes_shadow_in_order() {
+        let mut env = base();
+        assert_eq!(provenance(&env, "twice"), Some(Provenance::User));
+        assert_eq!(provenance(&env, "just"), Some(Provenance::Constructor));
+        assert_eq!(provenance(&env, "size"), Some(Provenance::Constant));
+        assert_eq!(provenance(&env, "dup"), Some(Provenance::Prelude));
+        assert_eq!(provenance(&env, "br-3"), Some(Provenance::Prelude));
+        assert_eq!(provenance(&env, "nope"), None);
+        env.scope_mut(Provenance::Extern)
+            .unwrap()
+            .insert("twice".to_owned(), scheme("->"));
+        assert_eq!(provenance(&env, "twice"), Some(Provenance::Extern));
+        env.prelude_mut().unwrap().remove("dup");
+        assert_eq!(provenance(&env, "dup"), Some(Provenance::User));
+    }
+
+    #[test]
+    fn local_scopes_shadow_until_popped() {
+        let mut env = base();
+        env.push_scope(Provenance::Local, names(&[("dup", "a -> a")]));
+        let binding = env.lookup("dup").unwrap();
+        assert_eq!(binding.provenance, Provenance::Local);
+        assert_eq!(binding.scheme, scheme("a -> a"));
+        let shadowed: Vec<_> = env.bindings("dup").map(|b| b.provenance).collect();
+        assert_eq!(
+            shadowed,
+            [Provenance::Local, Provenance::Prelude, Provenance::User]
+        );
+        env.push_scope(Provenance::Local, names(&[("x", "-> Int")]));
+        assert_eq!(provenance(&env, "dup"), Some(Provenance::Local));
+        assert_eq!(env.pop_scope(), Some(Provenance::Local));
+        assert_eq!(provenance(&env, "x"), None);
+        assert_eq!(env.pop_scope(), Some(Provenance::Local));
+        assert_eq!(provenance(&env, "dup"), Some(Provenance::Prelude));
+    }
+
+    #[test]
+    fn shadowing_notes() {
+        let mut env = base();
+        assert_eq!(
+            env.shadowing_note("dup").unwrap(),
+            "`dup` here refers to the prelude op, shadowing the op defined in the module"
+        );
+        assert_eq!(env.shadowing_note("twice"), None);
+        assert_eq!(env.shadowing_note("nope"), None);
+        env.push_scope(Provenance::Local, names(&[("br-1", "->")]));
+        assert_eq!(
+            env.shadowing_note("br-1").unwrap(),
+            "`br-1` here refers to the local binding, shadowing the prelude op"
+        );
+    }
+}
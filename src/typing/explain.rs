@@ -0,0 +1,383 @@
+use super::inference::{InferenceError, InferenceErrorMessage};
+use super::types::*;
+use crate::syntax::ast::Span;
+use std::collections::HashMap;
+use std::fmt;
+
+/// One side of a unification
+#[derive(Debug, Clone)]
+pub enum Term {
+    Type(Type),
+    Stack(Vec<Type>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnifyRule {
+    /// Both sides are the same type or the same variable
+    Equal,
+    /// The variable is bound to the other side
+    Bind {
+        var: String,
+    },
+    /// Applications unify head with head, then argument with argument
+    App,
+    /// Op types unify their pre stacks, then their post stacks
+    Op,
+    /// Stacks unify slot by slot, topmost first
+    Stack,
+    /// A `?` fits the other side, binding nothing
+    Dyn,
+    Mismatch,
+    Occurs,
+    /// Stacks of different lengths
+    Length,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnifyStep {
+    pub lhs: Term,
+    pub rhs: Term,
+    pub rule: UnifyRule,
+    pub ok: bool,
+    pub children: Vec<UnifyStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObligationKind {
+    /// The ops before `subject` have to produce what it consumes
+    Chain,
+    /// The fields of constructor `subject` feed its case arm body
+    CaseArm,
+    /// The arm of `subject` has to agree with the arms before it
+    CaseArms,
+    /// The inferred body type has to match the annotation
+    Annotation,
+    /// The ops before a stack effect comment have to match it
+    Checkpoint,
+}
+
+/// Why the checker had to unify two things
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub kind: ObligationKind,
+    pub span: Span,
+    pub subject: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Obligation {
+    pub origin: Origin,
+    pub step: UnifyStep,
+}
+
+/// Every unification performed while checking one op, in order
+#[derive(Debug)]
+pub struct UnifyTrace {
+    pub op: String,
+    pub obligations: Vec<Obligation>,
+    pub error: Option<InferenceError>,
+    /// The names written in the annotation and stack effect comments for
+    /// the variables generated in their place
+    pub names: HashMap<String, String>,
+}
+
+/// Records unification steps while the checker runs
+#[derive(Default)]
+pub(crate) struct Collector {
+    frames: Vec<UnifyStep>,
+    finished: Option<UnifyStep>,
+    pub(crate) obligations: Vec<Obligation>,
+    pub(crate) names: HashMap<String, String>,
+}
+
+impl Collector {
+    pub(crate) fn enter(&mut self, lhs: Term, rhs: Term, rule: UnifyRule) {
+        self.frames.push(UnifyStep {
+            lhs,
+            rhs,
+            rule,
+            ok: true,
+            children: vec![],
+        });
+    }
+
+    pub(crate) fn leave(&mut self, ok: bool) {
+        let mut step = self.frames.pop().expect("unbalanced unify trace");
+        step.ok = ok;
+        match self.frames.last_mut() {
+            Some(parent) => parent.children.push(step),
+            None => self.finished = Some(step),
+        }
+    }
+
+    /// Attributes the last finished unification to `origin`
+    pub(crate) fn obligation(&mut self, origin: Origin) {
+        if let Some(step) = self.finished.take() {
+            self.obligations.push(Obligation { origin, step });
+        }
+    }
+}
+
+fn child_label(parent: &UnifyRule, i: usize) -> String {
+    match (parent, i) {
+        (UnifyRule::Stack, i) => format!("stack slot {}", i),
+        (UnifyRule::Op, 0) => "the pre stack".to_owned(),
+        (UnifyRule::Op, _) => "the post stack".to_owned(),
+        (UnifyRule::App, 0) => "the type head".to_owned(),
+        (UnifyRule::App, _) => "the type argument".to_owned(),
+        _ => String::new(),
+    }
+}
+
+fn term(n: &mut Normalizer, t: &Term) -> String {
+    match t {
+        Term::Type(t) => n.ty(t).to_string(),
+        Term::Stack(ts) => {
+            let ts: Vec<_> = ts.iter().map(|t| n.ty(t).to_string()).collect();
+            format!("[{}]", ts.join(", "))
+        }
+    }
+}
+
+impl UnifyStep {
+    /// The steps from this one to the pair that failed to unify
+    pub fn failure_path(&self) -> Vec<(String, &UnifyStep)> {
+        let mut path = vec![(String::new(), self)];
+        let mut step = self;
+        while let Some((i, child)) = step.children.iter().enumerate().find(|(_, c)| !c.ok) {
+            path.push((child_label(&step.rule, i), child));
+            step = child;
+        }
+        path
+    }
+
+    fn fmt_tree(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        n: &mut Normalizer,
+        label: &str,
+        indent: usize,
+    ) -> fmt::Result {
+        let label = if label.is_empty() {
+            String::new()
+        } else {
+            format!("{}: ", label)
+        };
+        let rule = match &self.rule {
+            UnifyRule::Bind { var } => format!("bind {}", n.ty(&Type::Poly(var.to_owned()))),
+            rule => format!("{:?}", rule).to_lowercase(),
+        };
+        let lhs = term(n, &self.lhs);
+        let rhs = term(n, &self.rhs);
+        let failed = if self.ok { "" } else { " (failed)" };
+        writeln!(
+            f,
+            "{}{}{} ~ {}, {}{}",
+            "  ".repeat(indent),
+            label,
+            lhs,
+            rhs,
+            rule,
+            failed
+        )?;
+        for (i, child) in self.children.iter().enumerate() {
+            child.fmt_tree(f, n, &child_label(&self.rule, i), indent + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl UnifyTrace {
+    /// The obligation that failed, if the op failed to unify
+    pub fn failed_obligation(&self) -> Option<&Obligation> {
+        self.obligations.iter().find(|o| !o.step.ok)
+    }
+
+    /// Names variables as they were written where they were
+    fn normalizer(&self) -> Normalizer {
+        Normalizer::naming(self.names.clone())
+    }
+
+    /// A one paragraph account of why checking the op failed
+    pub fn explain(&self) -> Option<String> {
+        if let Some(obligation) = self.failed_obligation() {
+            return Some(explain_failure(obligation, self.normalizer()));
+        }
+        match self.error.as_ref().map(|err| &err.error) {
+            Some(InferenceErrorMessage::AnnInfConflict { .. }) => self.explain_ann_conflict(),
+            _ => None,
+        }
+    }
+
+    /// The annotation's variables that unification bound to concrete types
+    fn explain_ann_conflict(&self) -> Option<String> {
+        let obligation = self
+            .obligations
+            .iter()
+            .rev()
+            .find(|o| o.origin.kind == ObligationKind::Annotation)?;
+        let mut n = self.normalizer();
+        let ann = term(&mut n, &obligation.step.rhs);
+        let mut bindings = vec![];
+        collect_ann_bindings(&obligation.step, &mut n, &mut bindings);
+        Some(format!(
+            "the annotation {} of `{}` is too general, {}",
+            ann,
+            self.op,
+            bindings.join(", ")
+        ))
+    }
+}
+
+fn collect_ann_bindings(step: &UnifyStep, n: &mut Normalizer, out: &mut Vec<String>) {
+    if let (Term::Type(inf), Term::Type(Type::Poly(v))) = (&step.lhs, &step.rhs) {
+        if !matches!(inf, Type::Poly(_)) {
+            let v = n.ty(&Type::Poly(v.to_owned()));
+            out.push(format!("the body needs {} = {}", v, n.ty(inf)));
+        }
+    }
+    for child in step.children.iter() {
+        collect_ann_bindings(child, n, out);
+    }
+}
+
+fn explain_failure(obligation: &Obligation, mut n: Normalizer) -> String {
+    let path = obligation.step.failure_path();
+    let root = &obligation.step;
+    let lhs = term(&mut n, &root.lhs);
+    let rhs = term(&mut n, &root.rhs);
+    let subject = &obligation.origin.subject;
+    let mut out = match obligation.origin.kind {
+        ObligationKind::Chain => format!(
+            "`{}` requires {} but the ops before it produce {}",
+            subject, rhs, lhs
+        ),
+        ObligationKind::CaseArm => format!(
+            "the body of the `{}` arm requires {} but the fields of `{}` are {}",
+            subject, rhs, subject, lhs
+        ),
+        ObligationKind::CaseArms => format!(
+            "the `{}` arm has type {} but the arms before it have type {}",
+            subject, rhs, lhs
+        ),
+        ObligationKind::Annotation => {
+            format!("the body has type {} but is annotated {}", lhs, rhs)
+        }
+        ObligationKind::Checkpoint => format!(
+            "the ops before the stack effect comment have type {} but it says {}",
+            lhs, rhs
+        ),
+    };
+    for (label, step) in path.iter().skip(1) {
+        let lhs = term(&mut n, &step.lhs);
+        let rhs = term(&mut n, &step.rhs);
+        out.push_str(&format!(", because {} has {} against {}", label, lhs, rhs));
+    }
+    let (_, leaf) = path.last().unwrap();
+    let reason = match &leaf.rule {
+        UnifyRule::Occurs => "a variable cannot contain itself",
+        UnifyRule::Length => "the stacks have different lengths",
+        _ => "the types do not unify",
+    };
+    out.push_str(&format!(", and {}", reason));
+    out
+}
+
+impl fmt::Display for UnifyTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for obligation in self.obligations.iter() {
+            let mut n = self.normalizer();
+            let origin = &obligation.origin;
+            writeln!(
+                f,
+                "{:?} `{}` at {}..{}",
+                origin.kind, origin.subject, origin.span.start, origin.span.end
+            )?;
+            obligation.step.fmt_tree(f, &mut n, "", 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::syntax::parse;
+    use crate::typing::inference::Inference;
+
+    fn explain(input: &str, op: &str) -> Option<String> {
+        let module = parse(input).unwrap();
+        Inference::new(&module).explain_op(op).explain()
+    }
+
+    #[test]
+    fn chain_mismatch() {
+        let input = "
+            data Foo: foo.
+            define [Foo] usefoo []: pop.
+            define [] main []: 1 usefoo.
+            ";
+        assert_eq!(
+            explain(input, "main").unwrap(),
+            "`usefoo` requires [Foo] but the ops before it produce [Int], \
+             because stack slot 0 has Int against Foo, and the types do not unify"
+        );
+    }
+
+    #[test]
+    fn nested_quote_path() {
+        assert_eq!(
+            explain("define [] main [[][]]: (1).", "main").unwrap(),
+            "the body has type [][[][Int]] but is annotated [][[][]], \
+             because the post stack has [[][Int]] against [[][]], \
+             because stack slot 0 has [][Int] against [][], \
+             because the post stack has [Int] against [], \
+             and the stacks have different lengths"
+        );
+    }
+
+    #[test]
+    fn occurs_check() {
+        assert_eq!(
+            explain("define [a] f [a]: dup exec-1-1.", "f").unwrap(),
+            "`exec-1-1` requires [[b][c], b] but the ops before it produce [a, a], \
+             because stack slot 1 has [b][c] against b, and a variable cannot contain itself"
+        );
+    }
+
+    #[test]
+    fn annotation_too_general() {
+        assert_eq!(
+            explain("define [a] f [a]: pop 1.", "f").unwrap(),
+            "the annotation [a][a] of `f` is too general, the body needs a = Int"
+        );
+        assert_eq!(
+            explain("define [elem, b] f [elem, b]: pop 1.", "f").unwrap(),
+            "the annotation [elem, b][elem, b] of `f` is too general, the body needs elem = Int"
+        );
+    }
+
+    #[test]
+    fn records_successful_checks() {
+        let module = parse("data Foo: foo. define [] main [Foo]: foo.").unwrap();
+        let inference = Inference::new(&module);
+        let trace = inference.explain_op("main");
+        assert!(trace.error.is_none());
+        assert!(trace.explain().is_none());
+        assert_eq!(
+            trace.to_string(),
+            "Chain `foo` at 37..40\n  [] ~ [], stack\n\
+             Annotation `annotation` at 15..41\n  [][Foo] ~ [][Foo], op\n    \
+             the pre stack: [] ~ [], stack\n    \
+             the post stack: [Foo] ~ [Foo], stack\n      \
+             stack slot 0: Foo ~ Foo, equal\n"
+        );
+        // tracing is switched off again afterwards
+        assert!(inference.typecheck().is_ok());
+    }
+
+    #[test]
+    fn unknown_op_has_no_explanation() {
+        assert!(explain("define [] main []: nope.", "main").is_none());
+        assert!(explain("define [] main []:.", "missing").is_none());
+    }
+}
@@ -0,0 +1,385 @@
+//! Checking a module as its definitions arrive, say from
+//! `parse_definitions`, instead of once it is whole. Ops are checked
+//! against the annotations of the ops they call, so a definition can be
+//! checked as soon as every name it uses is defined, and it is not kept
+//! after that. Only the definitions waiting on names defined further on
+//! are held, with the tables of names.
+//!
+//! Definitions of a name marked `#[overload]` get keys of their own, as
+//! `insert_op_def` gives them, so each is added to the name's overload set.
+
+use super::dynamic::DynCheck;
+use super::inference::{Inference, InferenceError, InferenceErrorMessage};
+use super::report::{OpReport, TypecheckReport};
+use super::resolve::Resolution;
+use crate::desugar::desugar_definitions;
+use crate::syntax::ast::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::iter::once;
+
+/// A definition waiting on names, and how many of them are still not
+/// defined
+struct Waiting {
+    def: ParsedDefinition,
+    missing: usize,
+}
+
+/// Takes definitions one at a time and checks each once everything it
+/// uses is defined. A name is looked up when the definition using it is
+/// checked, a definition that comes later and shadows the name, a
+/// constant shadowing an op say, does not change what it referred to.
+pub struct IncrementalCheck {
+    resolution: Option<Resolution>,
+    /// Definitions not checked yet, by when they came
+    waiting: Vec<Option<Waiting>>,
+    /// The definitions waiting on each name
+    waiters: HashMap<String, Vec<usize>>,
+    /// The first definition of each op name, without its body, and how
+    /// many definitions of the name there are
+    firsts: HashMap<String, (OpDef, usize)>,
+    errors: Vec<InferenceError>,
+    ops: Vec<OpReport>,
+    prelude_used: BTreeSet<String>,
+    overloads: BTreeMap<usize, String>,
+    dyn_checks: BTreeMap<OpKey, Vec<DynCheck>>,
+}
+
+impl Default for IncrementalCheck {
+    fn default() -> Self {
+        IncrementalCheck::new()
+    }
+}
+
+impl IncrementalCheck {
+    pub fn new() -> Self {
+        IncrementalCheck {
+            resolution: Some(Resolution::empty()),
+            waiting: vec![],
+            waiters: HashMap::new(),
+            firsts: HashMap::new(),
+            errors: vec![],
+            ops: vec![],
+            prelude_used: BTreeSet::new(),
+            overloads: BTreeMap::new(),
+            dyn_checks: BTreeMap::new(),
+        }
+    }
+
+    fn resolution(&self) -> &Resolution {
+        self.resolution
+            .as_ref()
+            .expect("the tables are only taken while checking")
+    }
+
+    /// Adds the definition, checking it and the definitions waiting on the
+    /// names it defines once they have all they use. Returns the reports of
+    /// the ops this checked. An overload is only seen by the definitions
+    /// checked after it.
+    pub fn insert(&mut self, def: impl Into<ParsedDefinition>) -> &[OpReport] {
+        let def = self.keyed(def.into());
+        let checked = self.ops.len();
+        let defined = self.define(&def.def);
+        let missing = self.undefined_names(&def.def);
+        if missing.is_empty() {
+            self.check(def);
+        } else {
+            let id = self.waiting.len();
+            for name in &missing {
+                self.waiters.entry(name.clone()).or_default().push(id);
+            }
+            self.waiting.push(Some(Waiting {
+                def,
+                missing: missing.len(),
+            }));
+        }
+        for name in defined {
+            for id in self.waiters.remove(&name).unwrap_or_default() {
+                let Some(waiting) = self.waiting[id].as_mut() else {
+                    continue;
+                };
+                waiting.missing -= 1;
+                if waiting.missing == 0 {
+                    let waiting = self.waiting[id].take().expect("is waiting");
+                    self.check(waiting.def);
+                }
+            }
+        }
+        &self.ops[checked..]
+    }
+
+    /// Checks the definitions still waiting, which fail on the names never
+    /// defined, and reports on everything, ops in source order
+    pub fn finish(mut self) -> TypecheckReport {
+        for waiting in std::mem::take(&mut self.waiting).into_iter().flatten() {
+            self.check(waiting.def);
+        }
+        self.ops.sort_by_key(|op| op.span.start);
+        TypecheckReport {
+            errors: self.errors,
+            ops: self.ops,
+            prelude_used: self.prelude_used.into_iter().collect(),
+            overloads: self.overloads,
+            dyn_checks: self.dyn_checks,
+            timings: vec![],
+        }
+    }
+
+    /// The definition under its key in `Module::op_defs`, see
+    /// `insert_op_def`. The first definition of a name defined again joins
+    /// the name's overload set, marked or not, as in `Resolution::new`.
+    fn keyed(&mut self, mut def: ParsedDefinition) -> ParsedDefinition {
+        let TopLevel::Op(name, op_def) = &mut def.def else {
+            return def;
+        };
+        let overloads = |op_def: &OpDef| op_def.has_attribute("overload");
+        let bodiless = || OpDef {
+            body: vec![],
+            ..op_def.clone()
+        };
+        match self.firsts.get_mut(name.as_str()) {
+            Some((first, count)) if overloads(first) || overloads(op_def) => {
+                *count += 1;
+                let resolution = self
+                    .resolution
+                    .as_mut()
+                    .expect("the tables are only taken while checking");
+                let set = resolution.overloads.entry(name.clone()).or_default();
+                set.insert(name, first);
+                *name = format!("{}#{}", name, count);
+            }
+            _ => {
+                self.firsts.insert(name.clone(), (bodiless(), 1));
+            }
+        }
+        def
+    }
+
+    /// Adds the names the definition defines to the tables, returns them
+    fn define(&mut self, def: &TopLevel) -> Vec<String> {
+        let resolution = self
+            .resolution
+            .as_mut()
+            .expect("the tables are only taken while checking");
+        match def {
+            TopLevel::Data(name, data_def) => {
+                let constrs: Vec<_> = data_def
+                    .constrs_in_source_order()
+                    .into_iter()
+                    .map(|(constr_name, _)| constr_name.to_owned())
+                    .collect();
+                if let Some(clash) = constrs
+                    .iter()
+                    .find(|constr| resolution.constr_optypes.contains_key(*constr))
+                {
+                    self.errors.push(InferenceError {
+                        error: InferenceErrorMessage::DuplicateConstructor {
+                            name: clash.to_owned(),
+                        },
+                        span: data_def.span.clone(),
+                    });
+                }
+                resolution.insert_data(name, data_def);
+                constrs
+            }
+            TopLevel::Op(name, op_def) => {
+                resolution.insert_op(name, op_def);
+                vec![name.to_owned()]
+            }
+            TopLevel::Const(name, const_def) => {
+                resolution.insert_const(name, const_def);
+                vec![name.to_owned()]
+            }
+        }
+    }
+
+    /// Checks the definition on its own against the tables so far
+    fn check(&mut self, def: ParsedDefinition) {
+        let module = desugar_definitions(def.into_module());
+        let resolution = self
+            .resolution
+            .take()
+            .expect("the tables are only taken while checking");
+        let inference = Inference::with_resolution(&module, resolution);
+        let report = inference.report();
+        self.resolution = Some(inference.into_resolution());
+        self.errors.extend(report.errors);
+        self.ops.extend(report.ops);
+        self.prelude_used.extend(report.prelude_used);
+        self.overloads.extend(report.overloads);
+        self.dyn_checks.extend(report.dyn_checks);
+    }
+
+    /// The names the definition uses that are not defined yet, each once
+    fn undefined_names(&self, def: &TopLevel) -> Vec<String> {
+        let body = match def {
+            TopLevel::Data(..) => return vec![],
+            TopLevel::Op(_, op_def) => &op_def.body,
+            TopLevel::Const(_, const_def) => &const_def.body,
+        };
+        let mut seen = HashSet::new();
+        let mut missing = vec![];
+        self.collect_undefined(body, &mut seen, &mut missing);
+        missing
+    }
+
+    fn collect_undefined<'o>(
+        &self,
+        ops: &'o [Op],
+        seen: &mut HashSet<&'o str>,
+        missing: &mut Vec<String>,
+    ) {
+        let resolution = self.resolution();
+        let mut name = |name: &'o str, defined: bool| {
+            if !defined && seen.insert(name) {
+                missing.push(name.to_owned());
+            }
+        };
+        let mut bodies = vec![];
+        for op in ops {
+            match op {
+                Op::Literal { .. } => (),
+                Op::Name { value, .. } => name(value, resolution.is_defined(value)),
+                Op::Quote { value, .. } => bodies.push(value),
+                Op::Case { head_arm, arms, .. } => {
+                    for arm in once(head_arm).chain(arms) {
+                        let constr = &arm.constr;
+                        name(constr, resolution.constr_optypes.contains_key(constr));
+                        bodies.push(&arm.body);
+                    }
+                }
+                Op::Case2 { arms, .. } => {
+                    for arm in arms {
+                        for pattern in &arm.patterns {
+                            if let Pattern::Constr(constr) = pattern {
+                                name(constr, resolution.constr_optypes.contains_key(constr));
+                            }
+                        }
+                        bodies.push(&arm.body);
+                    }
+                }
+                Op::List { items, .. } => {
+                    // what the literal becomes
+                    for constr in ["empty", "cons"] {
+                        name(constr, resolution.constr_optypes.contains_key(constr));
+                    }
+                    bodies.extend(items)
+                }
+            }
+        }
+        for body in bodies {
+            self.collect_undefined(body, seen, missing);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desugar::desugar;
+    use crate::syntax::{parse, parse_definitions};
+    use crate::typing::report::OpOutcome;
+
+    fn check(source: &str) -> TypecheckReport {
+        let mut check = IncrementalCheck::new();
+        for def in parse_definitions(source.as_bytes()) {
+            check.insert(def.unwrap());
+        }
+        check.finish()
+    }
+
+    fn outcomes(report: &TypecheckReport) -> Vec<(String, String)> {
+        report
+            .ops
+            .iter()
+            .map(|op| {
+                let outcome = match &op.outcome {
+                    OpOutcome::Inferred(_) => "ok".to_owned(),
+                    OpOutcome::Failed(err) => err.code().to_owned(),
+                    outcome => format!("{:?}", outcome),
+                };
+                (op.name.clone(), outcome)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn agrees_with_checking_the_whole_module() {
+        let source = "
+define [Int] twice [Int, Int]: dup.
+define [Maybe Int] get [Int]: case { just { }, nothing { 0 } }.
+data Maybe a: nothing, [a] just.
+define [Nat] odd [Maybe Nat]: case { zero { nothing }, succ { even } }.
+define [Nat] even [Maybe Nat]: case { zero { zero just }, succ { odd } }.
+data Nat: zero, [Nat] succ.
+define [] wrong [Int]: size zero.
+const size [Int]: 3.
+define [] lost []: nope.
+define [Int] declared [Int].
+define [] digits [List Int]: [1, 2].
+data List a: empty, [a, List a] cons.
+define [Int] checked [Bool]: dup // ( Int -- Bool )
+    pop pop true.
+data Bool: false, true.
+";
+        let whole = Inference::new(&desugar(parse(source).unwrap()).unwrap()).report();
+        let streamed = check(source);
+        assert_eq!(outcomes(&streamed), outcomes(&whole));
+        for outcome in [("digits", "ok"), ("checked", "CheckpointMismatch")] {
+            assert!(outcomes(&streamed).contains(&(outcome.0.to_owned(), outcome.1.to_owned())));
+        }
+        assert_eq!(streamed.prelude_used, whole.prelude_used);
+        assert!(streamed.errors.is_empty());
+    }
+
+    #[test]
+    fn definitions_are_checked_once_what_they_use_is_defined() {
+        let mut check = IncrementalCheck::new();
+        let mut insert = |source: &str| -> Vec<String> {
+            let def = parse_definitions(source.as_bytes())
+                .next()
+                .unwrap()
+                .unwrap();
+            check.insert(def).iter().map(|op| op.name.clone()).collect()
+        };
+        assert_eq!(insert("define [] f [Int]: g one pop."), [] as [String; 0]);
+        assert_eq!(insert("define [] two [Int]: 2."), ["two"]);
+        assert_eq!(insert("define [] g [Int]: f pop 1."), ["g"]);
+        assert_eq!(insert("const one [Int]: 1."), ["f"]);
+        let report = check.finish();
+        assert!(outcomes(&report).contains(&("f".to_owned(), "ok".to_owned())));
+    }
+
+    #[test]
+    fn overloads_are_resolved_as_in_the_whole_module() {
+        let source = "
+data Bool: false, true.
+#[overload]
+define [Int] size [Int]:.
+#[overload]
+define [Bool] size [Int]: pop 1.
+define [] main [Int, Int]: 5 size true size.
+";
+        let whole = Inference::new(&parse(source).unwrap()).report();
+        let streamed = check(source);
+        assert_eq!(outcomes(&streamed), outcomes(&whole));
+        assert!(outcomes(&streamed)
+            .iter()
+            .all(|(_, outcome)| outcome == "ok"));
+        assert_eq!(streamed.overloads, whole.overloads);
+        assert_eq!(
+            streamed.overloads.values().collect::<Vec<_>>(),
+            ["size", "size#2"]
+        );
+    }
+
+    #[test]
+    fn duplicate_constructors_across_definitions() {
+        let report = check("data A: a, b.\ndata B: [Int] b.\ndefine [] f [A]: b.");
+        assert_eq!(report.errors.len(), 1);
+        assert!(matches!(
+            &report.errors[0].error,
+            InferenceErrorMessage::DuplicateConstructor { name } if name == "b"
+        ));
+        assert_eq!(outcomes(&report), [("f".to_owned(), "ok".to_owned())]);
+    }
+}
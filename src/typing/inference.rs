@@ -1,53 +1,881 @@
-use super::prelude_types;
+use super::prelude_types::{self, Prelude, SpecialForm, UnknownPreludeOp};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
 use std::iter::once;
 use std::iter::zip;
-use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+#[cfg(feature = "os")]
+use std::time::Instant;
 
+use super::cancel::*;
+use super::dynamic::{join_dyn, narrows, open_dyn, DynCheck};
+use super::env::{Env, Provenance};
+use super::explain::*;
+use super::overload::{Candidate, OverloadSet};
+use super::report::*;
+use super::resolve::{const_optype, Resolution};
+use super::stack_algebra::{augment, augment_both, chain_with, padding};
+use super::strict::StrictOptions;
+use super::trace::{self, CheckStats, TraceEvent, Tracer};
+use super::type_env::TypeEnv;
 use super::types::*;
+use crate::analysis::call_graph::CallGraph;
+use crate::analysis::deprecated::{allows_deprecated, Deprecations};
+use crate::desugar::lists;
+use crate::diagnostics::fixes::{self, Fix};
+use crate::diagnostics::kind::DiagnosticKind;
 use crate::syntax::ast::*;
-use crate::syntax::module_wrapper::ModuleConstrMaps;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct InferenceError {
     pub span: Span,
     pub error: InferenceErrorMessage,
 }
 
-#[derive(Debug)]
+impl InferenceError {
+    /// See `InferenceErrorMessage::code`
+    pub fn code(&self) -> &str {
+        self.error.code()
+    }
+
+    pub fn kind(&self) -> DiagnosticKind {
+        DiagnosticKind::of(self.code())
+    }
+
+    /// Where the error is, the op or definition it is reported at
+    pub fn primary_span(&self) -> &Span {
+        &self.span
+    }
+
+    /// The message, its first line saying what is wrong
+    pub fn message(&self) -> String {
+        self.error.to_string()
+    }
+
+    /// Other places the error is about, none for most errors. The
+    /// definitions of the name for overload errors.
+    pub fn related_spans(&self) -> Vec<Span> {
+        match &self.error {
+            InferenceErrorMessage::OverloadsIndistinct { candidates, .. }
+            | InferenceErrorMessage::AmbiguousOverload { candidates, .. }
+            | InferenceErrorMessage::NoOverloadFits { candidates, .. }
+            | InferenceErrorMessage::OverloadNeedsContext { candidates, .. } => {
+                candidates.iter().map(|c| c.span.clone()).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Edits of the source that would make the error go away, best first
+    pub fn suggestions(&self, source: &str, module: &Module) -> Vec<Fix> {
+        fixes::suggest(source, module, self)
+    }
+}
+
+/// What checking fails with. Matching on the variants of another crate
+/// breaks as they change, see `diagnostics::kind` for what does not.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum InferenceErrorMessage {
-    AnnInfConflict { inf: OpType, ann: OpType },
-    UnificationError { t1: Type, t2: Type },
-    UnknownOp { name: String },
-    UnknownConstructor { name: String },
-    DuplicateConstructor { name: String },
+    #[non_exhaustive]
+    AnnInfConflict {
+        inf: OpType,
+        ann: OpType,
+    },
+    /// The body is empty, and so leaves the stack as it is, but the
+    /// annotation changes it
+    #[non_exhaustive]
+    EmptyBodyCannotSatisfy {
+        ann: OpType,
+    },
+    #[non_exhaustive]
+    UnificationError {
+        t1: Type,
+        t2: Type,
+    },
+    #[non_exhaustive]
+    UnknownOp {
+        name: String,
+    },
+    #[non_exhaustive]
+    UnknownConstructor {
+        name: String,
+    },
+    /// An extern literal whose tag no `Inference::with_extern_literal`
+    /// declared a type for
+    #[non_exhaustive]
+    UnknownLiteralKind {
+        tag: String,
+    },
+    #[non_exhaustive]
+    DuplicateConstructor {
+        name: String,
+    },
     NotAllConstructorsCovered,
-    TypeOrderErrorElem { general: Type, concrete: Type },
-    TypeOrderErrorOp { general: OpType, concrete: OpType },
-    OpPrePostLenNeq { general: OpType, concrete: OpType },
-    OccursCheck { name: String },
+    /// A case arm's type disagrees with the type of the arms before it
+    #[non_exhaustive]
+    CaseArmMismatch {
+        arms: OpType,
+        arm: OpType,
+    },
+    /// One side of a case reaches below the stack the other side uses and
+    /// changes values there, which the other side leaves in place. `slots`
+    /// holds, for each such value, its positions in the deeper side's pre
+    /// and post stacks and the types it has there
+    #[non_exhaustive]
+    ArmsDisagreeOnStackDepth {
+        arm: String,
+        arm_is_deeper: bool,
+        slots: Vec<(usize, usize, Type, Type)>,
+    },
+    /// The ops before a case leave a value of another type than the one its
+    /// arms match, `depth` 0 is the topmost value
+    #[non_exhaustive]
+    ScrutineeMismatch {
+        scrutinee: Type,
+        matched: Type,
+        depth: usize,
+    },
+    /// The ops before a case leave a value of a builtin type like `Int`,
+    /// which is not made of constructors and so cannot be matched
+    #[non_exhaustive]
+    CannotMatchBuiltin {
+        scrutinee_type: Type,
+        matched_type: Type,
+    },
+    /// The ops before a case leave a quote where the case matches a
+    /// constructor, `depth` values below the top. Quotes are opaque, what
+    /// one does can only be found out by running it.
+    #[non_exhaustive]
+    CannotMatchOnQuote {
+        quote: OpType,
+        depth: usize,
+    },
+    /// Constructor combinations no `case2` arm matches
+    #[non_exhaustive]
+    MissingCombinations {
+        missing: Vec<[Pattern; 2]>,
+    },
+    #[non_exhaustive]
+    OpPrePostLenNeq {
+        general: OpType,
+        concrete: OpType,
+    },
+    /// The variable would have to be `ty`, which contains it at the end of
+    /// `path`
+    #[non_exhaustive]
+    OccursCheck {
+        name: String,
+        ty: Type,
+        path: Vec<TypeStep>,
+    },
+    /// The type of the body grew past the most types a type may be built
+    /// of once `op` was chained to it, see `Inference::with_max_type_size`
+    #[non_exhaustive]
+    TypeTooLarge {
+        op: String,
+        size: usize,
+        max: usize,
+    },
+    /// An occurs check failed at a `fix` op, the quote's type would contain
+    /// itself
+    #[non_exhaustive]
+    InfiniteFix {
+        op: String,
+        name: String,
+        ty: Type,
+    },
+    /// `pick` or `roll` without an `Int` literal of at least 0 right before
+    /// it, the index its type depends on
+    #[non_exhaustive]
+    IndexNotLiteral {
+        op: String,
+    },
     ListMGULengthDifferent,
+    /// A quote given straight to `op` takes more values than `op` runs it
+    /// with, the `extra` deepest values of `quote.pre`
+    #[non_exhaustive]
+    QuoteNeedsInputs {
+        op: String,
+        quote: OpType,
+        expected: OpType,
+        extra: usize,
+    },
+    /// The constructor or prelude op pops more values than the annotation
+    /// and the ops before it leave, `needs` being the values it takes
+    #[non_exhaustive]
+    NotEnoughValues {
+        op: String,
+        kind: Provenance,
+        needs: Vec<Type>,
+        available: usize,
+    },
+    /// A name defined more than once with a definition not marked
+    /// `#[overload]`
+    #[non_exhaustive]
+    OverloadNotMarked {
+        name: String,
+    },
+    /// No position of the values the definitions of `name` take has a
+    /// different data type in each, see `OverloadSet::position`
+    #[non_exhaustive]
+    OverloadsIndistinct {
+        name: String,
+        candidates: Vec<Candidate>,
+    },
+    /// More than one definition of `name` fits the stack before the use
+    #[non_exhaustive]
+    AmbiguousOverload {
+        name: String,
+        candidates: Vec<Candidate>,
+    },
+    /// No definition of `name` fits the stack before the use, which has
+    /// `found` on top down to the resolving position
+    #[non_exhaustive]
+    NoOverloadFits {
+        name: String,
+        found: Vec<Type>,
+        candidates: Vec<Candidate>,
+    },
+    /// The value at the resolving position of `name` is not on the stack
+    /// before the use, it comes from before the sentence, as it does in a
+    /// quote
+    #[non_exhaustive]
+    OverloadNeedsContext {
+        name: String,
+        position: usize,
+        candidates: Vec<Candidate>,
+    },
+    /// Strict mode: the annotation takes away `pinned` type variables
+    #[non_exhaustive]
+    AnnotationTooSpecific {
+        inf: OpType,
+        ann: OpType,
+        pinned: usize,
+    },
+    /// Strict mode: unchecked ops are not trusted
+    #[non_exhaustive]
+    UncheckedOp {
+        name: String,
+    },
+    /// Strict mode: an op or constructor is named like a prelude op
+    #[non_exhaustive]
+    ShadowsPrelude {
+        name: String,
+    },
+    /// Strict mode: the annotation of `op` gives a value of type `var` and
+    /// takes none, see `OpType::output_only_vars`
+    #[non_exhaustive]
+    OutputOnlyVar {
+        op: String,
+        var: String,
+    },
+    /// Strict mode: the arm is covered by the arms before it
+    UnreachableArm,
+    /// Strict mode: the op or constructor is marked `#[deprecated]`, with
+    /// the attribute's message
+    #[non_exhaustive]
+    Deprecated {
+        name: String,
+        message: Option<String>,
+    },
+    /// The initializer of constant `name` uses `op`, which is not a pure op
+    /// of the prelude, a constructor or another constant
+    #[non_exhaustive]
+    ImpureConstant {
+        name: String,
+        op: String,
+    },
+    /// The annotation of op or constant `name`, which has a body, or a
+    /// field of constructor `name` has a `?`
+    #[non_exhaustive]
+    DynInAnnotation {
+        name: String,
+    },
+    /// The ops before a stack effect comment do not have the effect it says
+    #[non_exhaustive]
+    CheckpointMismatch {
+        inferred: OpType,
+        asserted: OpType,
+    },
+    /// The inferred type differs from an `expect-type` pragma
+    #[non_exhaustive]
+    ExpectTypeMismatch {
+        inf: OpType,
+        expected: OpType,
+    },
+    /// Checking did not fail with the error of an `expect-error` pragma,
+    /// `found` is the error it failed with instead
+    #[non_exhaustive]
+    ExpectErrorMismatch {
+        expected: String,
+        found: Option<Box<InferenceErrorMessage>>,
+    },
+    /// Checking was stopped, never returned by `typecheck_with` or `report_with`
+    Interrupted(Interrupt),
+    /// An error of an earlier run, read back from a `TypecheckCache` with
+    /// its code and message as they were
+    #[non_exhaustive]
+    Cached {
+        code: String,
+        message: String,
+    },
+}
+
+impl InferenceErrorMessage {
+    /// Stable name of the kind of error, as used by `expect-error` pragmas
+    pub fn code(&self) -> &str {
+        match self {
+            InferenceErrorMessage::AnnInfConflict { .. } => "AnnInfConflict",
+            InferenceErrorMessage::EmptyBodyCannotSatisfy { .. } => "EmptyBodyCannotSatisfy",
+            InferenceErrorMessage::UnificationError { .. } => "UnificationError",
+            InferenceErrorMessage::UnknownOp { .. } => "UnknownOp",
+            InferenceErrorMessage::UnknownConstructor { .. } => "UnknownConstructor",
+            InferenceErrorMessage::UnknownLiteralKind { .. } => "UnknownLiteralKind",
+            InferenceErrorMessage::DuplicateConstructor { .. } => "DuplicateConstructor",
+            InferenceErrorMessage::NotAllConstructorsCovered => "NotAllConstructorsCovered",
+            InferenceErrorMessage::CaseArmMismatch { .. } => "CaseArmMismatch",
+            InferenceErrorMessage::ArmsDisagreeOnStackDepth { .. } => "ArmsDisagreeOnStackDepth",
+            InferenceErrorMessage::ScrutineeMismatch { .. } => "ScrutineeMismatch",
+            InferenceErrorMessage::CannotMatchBuiltin { .. } => "CannotMatchBuiltin",
+            InferenceErrorMessage::CannotMatchOnQuote { .. } => "CannotMatchOnQuote",
+            InferenceErrorMessage::MissingCombinations { .. } => "MissingCombinations",
+            InferenceErrorMessage::OpPrePostLenNeq { .. } => "OpPrePostLenNeq",
+            InferenceErrorMessage::OccursCheck { .. } => "OccursCheck",
+            InferenceErrorMessage::TypeTooLarge { .. } => "TypeTooLarge",
+            InferenceErrorMessage::InfiniteFix { .. } => "InfiniteFix",
+            InferenceErrorMessage::IndexNotLiteral { .. } => "IndexNotLiteral",
+            InferenceErrorMessage::ListMGULengthDifferent => "ListMGULengthDifferent",
+            InferenceErrorMessage::QuoteNeedsInputs { .. } => "QuoteNeedsInputs",
+            InferenceErrorMessage::NotEnoughValues { .. } => "NotEnoughValues",
+            InferenceErrorMessage::OverloadNotMarked { .. } => "OverloadNotMarked",
+            InferenceErrorMessage::OverloadsIndistinct { .. } => "OverloadsIndistinct",
+            InferenceErrorMessage::AmbiguousOverload { .. } => "AmbiguousOverload",
+            InferenceErrorMessage::NoOverloadFits { .. } => "NoOverloadFits",
+            InferenceErrorMessage::OverloadNeedsContext { .. } => "OverloadNeedsContext",
+            InferenceErrorMessage::AnnotationTooSpecific { .. } => "AnnotationTooSpecific",
+            InferenceErrorMessage::UncheckedOp { .. } => "UncheckedOp",
+            InferenceErrorMessage::ShadowsPrelude { .. } => "ShadowsPrelude",
+            InferenceErrorMessage::OutputOnlyVar { .. } => "OutputOnlyVar",
+            InferenceErrorMessage::UnreachableArm => "UnreachableArm",
+            InferenceErrorMessage::Deprecated { .. } => "Deprecated",
+            InferenceErrorMessage::ImpureConstant { .. } => "ImpureConstant",
+            InferenceErrorMessage::DynInAnnotation { .. } => "DynInAnnotation",
+            InferenceErrorMessage::CheckpointMismatch { .. } => "CheckpointMismatch",
+            InferenceErrorMessage::ExpectTypeMismatch { .. } => "ExpectTypeMismatch",
+            InferenceErrorMessage::ExpectErrorMismatch { .. } => "ExpectErrorMismatch",
+            InferenceErrorMessage::Interrupted(_) => "Interrupted",
+            InferenceErrorMessage::Cached { code, .. } => code,
+        }
+    }
+
+    /// The error with the annotation's generated variables taken back to
+    /// the ones written, `back` as `instantiate_with_mapping` returns it
+    fn named_back(self, back: &Subst) -> Self {
+        match self {
+            InferenceErrorMessage::AnnInfConflict { inf, ann } => {
+                InferenceErrorMessage::AnnInfConflict {
+                    inf,
+                    ann: ann.apply(back),
+                }
+            }
+            InferenceErrorMessage::AnnotationTooSpecific { inf, ann, pinned } => {
+                InferenceErrorMessage::AnnotationTooSpecific {
+                    inf,
+                    ann: ann.apply(back),
+                    pinned,
+                }
+            }
+            error => error,
+        }
+    }
+}
+
+/// Ops marked `#[unchecked]` are trusted to match their annotation, as are
+/// ops prefixed with `noc` from before there were attributes
+pub(crate) fn is_unchecked(op_name: &str, op_def: &OpDef) -> bool {
+    op_def.has_attribute("unchecked") || op_name.starts_with("noc")
+}
+
+/// The ordinal of the index, "1st" for 0
+fn nth(i: usize) -> String {
+    let suffix = match ((i + 1) % 10, (i + 1) % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", i + 1, suffix)
+}
+
+/// The definitions of an overloaded name, a line each
+fn candidate_lines(candidates: &[Candidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| format!("\n  {}", candidate))
+        .collect()
+}
+
+/// Where a step goes, like "the 2nd argument of `Pair`"
+fn describe(step: &TypeStep, n: &mut Normalizer) -> String {
+    match step {
+        TypeStep::Arg { head, args: 1, .. } => format!("the argument of `{}`", n.ty(head)),
+        TypeStep::Arg { head, index, .. } => {
+            format!("the {} argument of `{}`", nth(*index), n.ty(head))
+        }
+        TypeStep::Head => "the type applied".to_owned(),
+        TypeStep::Input(i) => format!("the {} value a quote takes", nth(*i)),
+        TypeStep::Output(i) => format!("the {} value a quote leaves", nth(*i)),
+    }
+}
+
+impl fmt::Display for InferenceErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // variables are renamed jointly so that the types stay comparable
+        let mut n = Normalizer::default();
+        match self {
+            InferenceErrorMessage::AnnInfConflict { inf, ann } => {
+                let mut n = inf.naming_after(ann);
+                write!(
+                    f,
+                    "the inferred type {} does not match the annotation {}\n{}",
+                    n.optype(inf),
+                    n.optype(ann),
+                    inf.diff_naming_after(ann)
+                        .render("inferred type", "annotation")
+                )
+            }
+            InferenceErrorMessage::EmptyBodyCannotSatisfy { ann } => write!(
+                f,
+                "the body is empty, which leaves the stack as it is, but the annotation {} \
+                 changes it\n\
+                 an empty body is usually a stub left to fill in: write the body, mark the op \
+                 `#[unchecked]` to trust the annotation, or end the definition after its \
+                 annotation, leaving out the `:`, to declare an op a host function implements",
+                n.optype(ann)
+            ),
+            InferenceErrorMessage::CaseArmMismatch { arms, arm } => write!(
+                f,
+                "the arm has type {} but the arms before it have type {}\n{}",
+                n.optype(arm),
+                n.optype(arms),
+                arms.diff(arm).render("arms before", "arm")
+            ),
+            InferenceErrorMessage::ArmsDisagreeOnStackDepth {
+                arm,
+                arm_is_deeper,
+                slots,
+            } => {
+                if *arm_is_deeper {
+                    write!(
+                        f,
+                        "the arm `{}` changes values below the ones the arms before it use, \
+                         which they leave in place",
+                        arm
+                    )?;
+                } else {
+                    write!(
+                        f,
+                        "the arms before `{}` change values below the ones it uses, \
+                         which it leaves in place",
+                        arm
+                    )?;
+                }
+                for (pre, post, taken, left) in slots {
+                    write!(
+                        f,
+                        "\n  {} at pre {} is left as {} at post {}",
+                        n.ty(taken),
+                        pre,
+                        n.ty(left),
+                        post
+                    )?;
+                }
+                Ok(())
+            }
+            InferenceErrorMessage::ScrutineeMismatch {
+                scrutinee,
+                matched,
+                depth,
+            } => write!(
+                f,
+                "the case matches {} but the ops before it leave {} {}",
+                n.ty(matched),
+                n.ty(scrutinee),
+                match depth {
+                    0 => "on top of the stack",
+                    _ => "below the top of the stack",
+                }
+            ),
+            InferenceErrorMessage::CannotMatchBuiltin {
+                scrutinee_type,
+                matched_type,
+            } => write!(
+                f,
+                "the case matches {} but the ops before it leave {}, which has no constructors
+                 only values of data types can be taken apart by a case",
+                n.ty(matched_type),
+                n.ty(scrutinee_type),
+            ),
+            InferenceErrorMessage::CannotMatchOnQuote { quote, depth } => write!(
+                f,
+                "the case takes apart the quote {} {}, but quotes have no constructors\n\
+                 what a quote does cannot be looked at, only run with an `exec` op: \
+                 match on a data value instead, one that says which quote to push or \
+                 whether to run it",
+                n.optype(quote),
+                match depth {
+                    0 => "on top of the stack",
+                    _ => "below the top of the stack",
+                }
+            ),
+            InferenceErrorMessage::UnificationError { t1, t2 } => {
+                write!(f, "cannot unify {} with {}", n.ty(t1), n.ty(t2))
+            }
+            InferenceErrorMessage::UnknownOp { name } => write!(f, "unknown op `{}`", name),
+            InferenceErrorMessage::UnknownConstructor { name } => {
+                write!(f, "unknown constructor `{}`", name)
+            }
+            InferenceErrorMessage::UnknownLiteralKind { tag } => {
+                write!(f, "no type is declared for `{}` literals", tag)
+            }
+            InferenceErrorMessage::DuplicateConstructor { name } => {
+                write!(f, "constructor `{}` is defined more than once", name)
+            }
+            InferenceErrorMessage::NotAllConstructorsCovered => {
+                write!(f, "not all constructors are covered")
+            }
+            InferenceErrorMessage::MissingCombinations { missing } => {
+                let missing: Vec<_> = missing
+                    .iter()
+                    .map(|[p1, p2]| format!("`{}`/`{}`", p1, p2))
+                    .collect();
+                write!(f, "{} not covered", missing.join(", "))
+            }
+            InferenceErrorMessage::OpPrePostLenNeq { general, concrete } => write!(
+                f,
+                "{} and {} have different stack lengths\n{}",
+                n.optype(general),
+                n.optype(concrete),
+                general.diff(concrete).render("general", "concrete")
+            ),
+            InferenceErrorMessage::OccursCheck { name, ty, path } => {
+                let var = n.ty(&Type::Poly(name.to_owned()));
+                write!(
+                    f,
+                    "type variable {} occurs in {}, the type it is unified with",
+                    var,
+                    n.ty(ty)
+                )?;
+                if !path.is_empty() {
+                    let steps: Vec<_> = path
+                        .iter()
+                        .rev()
+                        .map(|step| describe(step, &mut n))
+                        .collect();
+                    write!(f, "\n{} is {}", var, steps.join(", which is "))?;
+                }
+                Ok(())
+            }
+            InferenceErrorMessage::TypeTooLarge { op, size, max } => write!(
+                f,
+                "the type of the body is built of {} types after `{}`, more than the {} a type \
+                 may be built of\n\
+                 a type doubling at every op, like that of a value paired with itself \
+                 again and again, soon gets too large to check",
+                size, op, max
+            ),
+            InferenceErrorMessage::InfiniteFix { op, name, ty } => write!(
+                f,
+                "the quote given to `{}` would have an infinite type, {} would be {}\n\
+                 the quote receives a quote running it again on top of its inputs, \
+                 which it can execute or drop but not return",
+                op,
+                n.ty(&Type::Poly(name.to_owned())),
+                n.ty(ty)
+            ),
+            InferenceErrorMessage::IndexNotLiteral { op } => write!(
+                f,
+                "`{}` requires a literal index\n\
+                 what it leaves depends on the index, which only a non-negative `Int` \
+                 literal written right before it gives",
+                op
+            ),
+            InferenceErrorMessage::ListMGULengthDifferent => {
+                write!(f, "stacks of different lengths cannot be unified")
+            }
+            InferenceErrorMessage::QuoteNeedsInputs {
+                op,
+                quote,
+                expected,
+                extra,
+            } => {
+                let expected = n.optype(expected);
+                let quote = n.optype(quote);
+                let inputs: Vec<_> = quote.pre[quote.pre.len() - extra..]
+                    .iter()
+                    .map(|t| format!("`{}`", t))
+                    .collect();
+                write!(
+                    f,
+                    "this quote needs {} {} input{}, `{}` runs it as {} but it is {}\n\
+                     did you mean to put a value into it? `quote` makes a quote of a value \
+                     and `comp-*` joins two quotes",
+                    if *extra == 1 { "an extra" } else { "extra" },
+                    inputs.join(", "),
+                    if *extra == 1 { "" } else { "s" },
+                    op,
+                    expected,
+                    quote
+                )
+            }
+            InferenceErrorMessage::AnnotationTooSpecific { inf, ann, pinned } => {
+                let mut n = inf.naming_after(ann);
+                write!(
+                    f,
+                    "the annotation {} takes away {} type variables of the inferred type {}\n{}",
+                    n.optype(ann),
+                    pinned,
+                    n.optype(inf),
+                    inf.diff_naming_after(ann)
+                        .render("inferred type", "annotation")
+                )
+            }
+            InferenceErrorMessage::UncheckedOp { name } => {
+                write!(f, "unchecked op `{}` is not allowed", name)
+            }
+            InferenceErrorMessage::OverloadNotMarked { name } => write!(
+                f,
+                "`{}` is defined more than once, but this definition is not marked `#[overload]`",
+                name
+            ),
+            InferenceErrorMessage::OverloadsIndistinct { name, candidates } => write!(
+                f,
+                "no value the definitions of `{}` take tells them apart, they have to take \
+                 values of different data types at some position:{}",
+                name,
+                candidate_lines(candidates)
+            ),
+            InferenceErrorMessage::AmbiguousOverload { name, candidates } => write!(
+                f,
+                "more than one definition of `{}` fits the stack here:{}",
+                name,
+                candidate_lines(candidates)
+            ),
+            InferenceErrorMessage::NoOverloadFits {
+                name,
+                found,
+                candidates,
+            } => {
+                let found: Vec<_> = n
+                    .optype(&OpType {
+                        pre: vec![],
+                        post: found.clone(),
+                    })
+                    .post
+                    .iter()
+                    .map(|t| format!("`{}`", t))
+                    .collect();
+                write!(
+                    f,
+                    "no definition of `{}` fits the stack here, which has {} on top:{}",
+                    name,
+                    found.join(", "),
+                    candidate_lines(candidates)
+                )
+            }
+            InferenceErrorMessage::OverloadNeedsContext {
+                name,
+                position,
+                candidates,
+            } => write!(
+                f,
+                "insufficient type information to resolve `{}`: the {} value it takes, which \
+                 tells its definitions apart, is not on the stack before it{}",
+                name,
+                nth(*position),
+                candidate_lines(candidates)
+            ),
+            InferenceErrorMessage::NotEnoughValues {
+                op,
+                kind,
+                needs,
+                available,
+            } => {
+                let needs_list: Vec<_> = n
+                    .optype(&OpType {
+                        pre: needs.clone(),
+                        post: vec![],
+                    })
+                    .pre
+                    .iter()
+                    .map(|t| format!("`{}`", t))
+                    .collect();
+                let values = |n| if n == 1 { "value" } else { "values" };
+                let available = match available {
+                    0 => "none are".to_owned(),
+                    1 => "only 1 is".to_owned(),
+                    n => format!("only {} are", n),
+                };
+                write!(
+                    f,
+                    "{} `{}` needs {} {} ({}) but {} available here",
+                    kind,
+                    op,
+                    needs.len(),
+                    values(needs.len()),
+                    needs_list.join(", "),
+                    available
+                )
+            }
+            InferenceErrorMessage::ShadowsPrelude { name } => {
+                write!(f, "`{}` shadows the prelude op of the same name", name)
+            }
+            InferenceErrorMessage::OutputOnlyVar { op, var } => write!(
+                f,
+                "the annotation of `{}` gives a value of type `{}` but takes none\n\
+                 `{}` is in no value the op takes and in no quote type, so the op would make \
+                 a value of every type from nothing, which only an op that never returns does",
+                op, var, var
+            ),
+            InferenceErrorMessage::UnreachableArm => {
+                write!(f, "this arm is covered by the arms before it")
+            }
+            InferenceErrorMessage::Deprecated { name, message } => {
+                write!(f, "`{}` is deprecated", name)?;
+                match message {
+                    Some(message) => write!(f, ": {}", message),
+                    None => Ok(()),
+                }
+            }
+            InferenceErrorMessage::ImpureConstant { name, op } => write!(
+                f,
+                "constant `{}` cannot use `{}`\n\
+                 a constant is computed once, from literals, constructors, other constants \
+                 and the prelude ops that only shuffle values",
+                name, op
+            ),
+            InferenceErrorMessage::DynInAnnotation { name } => write!(
+                f,
+                "`{}` cannot have a `?` in its type\n\
+                 only ops declared without a body take or leave values whose type is known \
+                 when the program runs, the checker has nothing to check a body against",
+                name
+            ),
+            InferenceErrorMessage::CheckpointMismatch { inferred, asserted } => {
+                let mut n = inferred.naming_after(asserted);
+                write!(
+                    f,
+                    "the ops before the stack effect comment have type {}, not {}\n{}",
+                    n.optype(inferred),
+                    n.optype(asserted),
+                    inferred
+                        .diff_naming_after(asserted)
+                        .render("inferred type", "comment")
+                )
+            }
+            // each side on its own, the types are only equal up to renaming
+            InferenceErrorMessage::ExpectTypeMismatch { inf, expected } => write!(
+                f,
+                "the inferred type {} does not match the expected type {}\n{}",
+                inf.canonical(),
+                expected.canonical(),
+                inf.diff(expected).render("inferred type", "expected type")
+            ),
+            InferenceErrorMessage::ExpectErrorMismatch {
+                expected,
+                found: None,
+            } => write!(f, "expected error `{}`, but the op typechecks", expected),
+            InferenceErrorMessage::ExpectErrorMismatch {
+                expected,
+                found: Some(found),
+            } => write!(
+                f,
+                "expected error `{}`, found `{}`: {}",
+                expected,
+                found.code(),
+                found
+            ),
+            InferenceErrorMessage::Interrupted(Interrupt::Cancelled) => {
+                write!(f, "checking was cancelled")
+            }
+            InferenceErrorMessage::Interrupted(Interrupt::TimedOut) => {
+                write!(f, "checking ran out of time")
+            }
+            InferenceErrorMessage::Cached { message, .. } => write!(f, "{}", message),
+        }
+    }
 }
 
-type Subst = HashMap<String, Type>;
+pub(crate) type Subst = HashMap<String, Type>;
+
+/// The type of ops, and whether running them never returns, as a `panic`
+/// does. What a diverging sentence leaves stands for whatever is wanted of
+/// it, the ops after the one diverging never run and are not typed.
+#[derive(Debug, Clone)]
+struct Inferred {
+    optype: OpType,
+    diverges: bool,
+}
 
-fn compose(s1: Subst, s2: Subst) -> Subst {
+pub(crate) fn compose(s1: Subst, s2: Subst) -> Subst {
     let mut s: Subst = s1.into_iter().map(|(v, t)| (v, t.apply(&s2))).collect();
     s.extend(s2);
     s
 }
 
-trait Typeable {
+pub(crate) trait Typeable: Sized {
     fn ftv(&self) -> HashSet<String>;
     fn apply(&self, subst: &Subst) -> Self;
-    fn mgu(t1: &Self, t2: &Self) -> Result<Subst, InferenceErrorMessage>;
+    /// Unifies, recording the steps into the collector when there is one
+    fn mgu_with(
+        t1: &Self,
+        t2: &Self,
+        c: Option<&mut Collector>,
+    ) -> Result<Subst, InferenceErrorMessage>;
+
+    fn mgu(t1: &Self, t2: &Self) -> Result<Subst, InferenceErrorMessage> {
+        Self::mgu_with(t1, t2, None)
+    }
+}
+
+/// The rule `Type::mgu_with` is going to apply, only computed when tracing
+fn type_rule(t1: &Type, t2: &Type) -> UnifyRule {
+    match (t1, t2) {
+        (Type::Mono(name1), Type::Mono(name2)) if name1 == name2 => UnifyRule::Equal,
+        (Type::Poly(name1), Type::Poly(name2)) if name1 == name2 => UnifyRule::Equal,
+        (Type::Poly(v), t) | (t, Type::Poly(v)) if t.ftv().contains(v) => UnifyRule::Occurs,
+        (Type::Poly(v), _) | (_, Type::Poly(v)) => UnifyRule::Bind { var: v.to_owned() },
+        (Type::Dyn, _) | (_, Type::Dyn) => UnifyRule::Dyn,
+        (Type::App(..), Type::App(..)) => UnifyRule::App,
+        (Type::Op(_), Type::Op(_)) => UnifyRule::Op,
+        (_, _) => UnifyRule::Mismatch,
+    }
+}
+
+/// Unifies the pre stacks, then the post stacks, of two op types
+fn mgu_op_stacks(
+    t1: &OpType,
+    t2: &OpType,
+    mut c: Option<&mut Collector>,
+) -> Result<Subst, InferenceErrorMessage> {
+    let s1 = Vec::mgu_with(&t1.pre, &t2.pre, c.as_deref_mut())?;
+    let t1 = t1.post.apply(&s1);
+    let t2 = t2.post.apply(&s1);
+    let s2 = Vec::mgu_with(&t1, &t2, c)?;
+    Ok(compose(s1, s2))
 }
 
 impl Typeable for Type {
     fn ftv(&self) -> HashSet<String> {
         match self {
-            Type::Mono(_) => HashSet::new(),
+            Type::Mono(_) | Type::Dyn => HashSet::new(),
             Type::Poly(v) => HashSet::from([v.clone()]),
             Type::Op(op_type) => op_type.ftv(),
             Type::App(t1, t2) => {
@@ -60,7 +888,7 @@ impl Typeable for Type {
 
     fn apply(&self, subst: &Subst) -> Self {
         match self {
-            Type::Mono(_) => self.clone(),
+            Type::Mono(_) | Type::Dyn => self.clone(),
             Type::Poly(v) => match subst.get(v) {
                 Some(t) => t.clone(),
                 None => Type::Poly(v.to_owned()),
@@ -70,29 +898,55 @@ impl Typeable for Type {
         }
     }
 
-    fn mgu(t1: &Self, t2: &Self) -> Result<Subst, InferenceErrorMessage> {
-        match (t1, t2) {
+    fn mgu_with(
+        t1: &Self,
+        t2: &Self,
+        mut c: Option<&mut Collector>,
+    ) -> Result<Subst, InferenceErrorMessage> {
+        if let Some(c) = c.as_deref_mut() {
+            c.enter(
+                Term::Type(t1.clone()),
+                Term::Type(t2.clone()),
+                type_rule(t1, t2),
+            );
+        }
+        let result = match (t1, t2) {
             (Type::Mono(name1), Type::Mono(name2)) if name1 == name2 => Ok(Subst::new()),
             (Type::Poly(name1), Type::Poly(name2)) if name1 == name2 => Ok(Subst::new()),
             (Type::Poly(v), t) | (t, Type::Poly(v)) => {
                 if t.ftv().contains(v) {
-                    return Err(InferenceErrorMessage::OccursCheck { name: v.to_owned() });
+                    Err(InferenceErrorMessage::OccursCheck {
+                        name: v.to_owned(),
+                        ty: t.to_owned(),
+                        path: t.path_to(v).unwrap_or_default(),
+                    })
+                } else {
+                    Ok(HashMap::from([(v.to_owned(), t.to_owned())]))
                 }
-                Ok(HashMap::from([(v.to_owned(), t.to_owned())]))
+            }
+            // checked when the program runs, see `DynCheck`. What is built
+            // around a `?` is only known then too.
+            (Type::Dyn, t) | (t, Type::Dyn) => {
+                Ok(t.ftv().into_iter().map(|v| (v, Type::Dyn)).collect())
             }
             (Type::App(lhs1, rhs1), Type::App(lhs2, rhs2)) => {
-                let s1 = Type::mgu(lhs1, lhs2)?;
-                let rhs1 = rhs1.apply(&s1);
-                let rhs2 = rhs2.apply(&s1);
-                let s2 = Type::mgu(&rhs1, &rhs2)?;
-                Ok(compose(s1, s2))
+                Type::mgu_with(lhs1, lhs2, c.as_deref_mut()).and_then(|s1| {
+                    let rhs1 = rhs1.apply(&s1);
+                    let rhs2 = rhs2.apply(&s1);
+                    let s2 = Type::mgu_with(&rhs1, &rhs2, c.as_deref_mut())?;
+                    Ok(compose(s1, s2))
+                })
             }
-            (Type::Op(o1), Type::Op(o2)) => Typeable::mgu(o1, o2),
+            (Type::Op(o1), Type::Op(o2)) => mgu_op_stacks(o1, o2, c.as_deref_mut()),
             (_, _) => Err(InferenceErrorMessage::UnificationError {
                 t1: t1.clone(),
                 t2: t2.clone(),
             }),
+        };
+        if let Some(c) = c {
+            c.leave(result.is_ok());
         }
+        result
     }
 }
 
@@ -111,325 +965,2365 @@ impl Typeable for OpType {
         OpType { pre, post }
     }
 
-    fn mgu(t1: &Self, t2: &Self) -> Result<Subst, InferenceErrorMessage> {
-        let s1 = Typeable::mgu(&t1.pre, &t2.pre)?;
-        let t1 = t1.post.apply(&s1);
-        let t2 = t2.post.apply(&s1);
-        let s2 = Typeable::mgu(&t1, &t2)?;
-        Ok(compose(s1, s2))
+    fn mgu_with(
+        t1: &Self,
+        t2: &Self,
+        mut c: Option<&mut Collector>,
+    ) -> Result<Subst, InferenceErrorMessage> {
+        if let Some(c) = c.as_deref_mut() {
+            c.enter(
+                Term::Type(Type::Op(t1.clone())),
+                Term::Type(Type::Op(t2.clone())),
+                UnifyRule::Op,
+            );
+        }
+        let result = mgu_op_stacks(t1, t2, c.as_deref_mut());
+        if let Some(c) = c {
+            c.leave(result.is_ok());
+        }
+        result
     }
 }
 
-impl<T> Typeable for Vec<T>
-where
-    T: Typeable + Clone,
-{
+impl Typeable for Vec<Type> {
     fn ftv(&self) -> HashSet<String> {
-        self.into_iter().flat_map(Typeable::ftv).collect()
+        self.iter().flat_map(Typeable::ftv).collect()
     }
 
     fn apply(&self, subst: &Subst) -> Self {
         self.iter().map(|x| x.apply(subst)).collect()
     }
 
-    fn mgu(t1: &Self, t2: &Self) -> Result<Subst, InferenceErrorMessage> {
-        if t1.len() != t2.len() {
-            return Err(InferenceErrorMessage::ListMGULengthDifferent);
+    fn mgu_with(
+        t1: &Self,
+        t2: &Self,
+        mut c: Option<&mut Collector>,
+    ) -> Result<Subst, InferenceErrorMessage> {
+        let same_len = t1.len() == t2.len();
+        if let Some(c) = c.as_deref_mut() {
+            let rule = if same_len {
+                UnifyRule::Stack
+            } else {
+                UnifyRule::Length
+            };
+            c.enter(Term::Stack(t1.clone()), Term::Stack(t2.clone()), rule);
         }
-        let mut s = Subst::new();
-        for (x, y) in zip(t1.into_iter(), t2.into_iter()) {
-            let x = x.apply(&s);
-            let y = y.apply(&s);
-            let ss = Typeable::mgu(&x, &y)?;
-            s = compose(s, ss);
+        let result = if same_len {
+            zip(t1, t2).try_fold(Subst::new(), |s, (x, y)| {
+                let x = x.apply(&s);
+                let y = y.apply(&s);
+                let ss = Type::mgu_with(&x, &y, c.as_deref_mut())?;
+                Ok(compose(s, ss))
+            })
+        } else {
+            Err(InferenceErrorMessage::ListMGULengthDifferent)
+        };
+        if let Some(c) = c {
+            c.leave(result.is_ok());
         }
-        Ok(s)
+        result
     }
 }
 
-struct ModuleConstrOpTypeMap<'m> {
-    pub constr_to_optype_map: HashMap<&'m str, OpType>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SearchHitKind {
+    Op,
+    Constant,
+    Constructor,
+    Extern,
+    Prelude,
 }
 
-impl<'m> ModuleConstrOpTypeMap<'m> {
-    pub fn new(module: &'m Module) -> Self {
-        let mut constr_to_optype_map = HashMap::new();
-        for (data_name, data_def) in module.data_defs.iter() {
-            for (constr_name, constr_def) in data_def.constrs.iter() {
-                let constructed_type = data_def
-                    .params
-                    .iter()
-                    .map(|p| Type::Poly(p.to_owned()))
-                    .fold(Type::Mono(data_name.to_owned()), |a, x| {
-                        Type::App(Box::new(a), Box::new(x))
-                    });
-                let optype = OpType {
-                    pre: constr_def.params.clone(),
-                    post: vec![constructed_type],
-                };
-                constr_to_optype_map.insert(constr_name.as_str(), optype);
-            }
-        }
-        ModuleConstrOpTypeMap {
-            constr_to_optype_map,
+/// A call of a user op, see `Inference::calls_at`
+#[derive(Debug, Clone)]
+pub struct CallType {
+    pub callee: String,
+    pub span: Span,
+    /// The callee's annotation as the call instantiates it
+    pub optype: OpType,
+}
+
+/// The calls inference met and the substitutions it made since it started
+/// recording, which applied in order give each call's final type
+#[derive(Default)]
+struct CallRecorder {
+    calls: Vec<CallType>,
+    substs: Vec<Subst>,
+}
+
+/// An op of a body that made a variable of the annotation less general,
+/// see `Inference::blame`
+#[derive(Debug, Clone)]
+pub struct BlameSite {
+    /// The op, constructs by their keyword
+    pub op: String,
+    pub span: Span,
+    /// What the variable stands for after the op, in the names of the
+    /// annotation, the other variable when the two were merged
+    pub became: Type,
+}
+
+/// An op found by `Inference::search`
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub name: String,
+    pub kind: SearchHitKind,
+    pub op_type: OpType,
+    /// Values added below both stacks of the op or of the query to line them
+    /// up
+    pub augmentation: usize,
+    /// Type variables of either side set to a concrete type or merged with
+    /// another variable
+    pub instantiation: usize,
+    /// The query, augmented, is an instance of the op's type: the op stands
+    /// in for it without making any of the query's variables more specific
+    pub general: bool,
+}
+
+impl SearchHit {
+    fn rank(&self) -> (usize, bool, usize, SearchHitKind, &str) {
+        (
+            self.augmentation + self.instantiation,
+            !self.general,
+            self.augmentation,
+            self.kind,
+            &self.name,
+        )
+    }
+}
+
+/// The most values on any stack of the op type, nested ones included
+fn longest_stack(t: &OpType) -> usize {
+    fn in_type(t: &Type) -> usize {
+        match t {
+            Type::Mono(_) | Type::Poly(_) | Type::Dyn => 0,
+            Type::Op(o) => longest_stack(o),
+            Type::App(t1, t2) => in_type(t1).max(in_type(t2)),
         }
     }
+    t.pre
+        .iter()
+        .chain(&t.post)
+        .map(in_type)
+        .fold(t.pre.len().max(t.post.len()), usize::max)
+}
+
+/// The op as traces and blame name it, constructs by their keyword
+fn op_label(op: &Op) -> String {
+    match op {
+        Op::Name { value, .. } => value.to_owned(),
+        Op::Literal { value, .. } => value.to_string(),
+        Op::Quote { .. } => "quote".to_owned(),
+        Op::Case { .. } => "case".to_owned(),
+        Op::Case2 { .. } => "case2".to_owned(),
+        Op::List { .. } => "list".to_owned(),
+    }
+}
+
+/// The special form `op` uses and its name, if it names one of the prelude
+fn special_form<'o>(env: &Env, op: &'o Op) -> Option<(SpecialForm, &'o str)> {
+    let Op::Name { value: name, .. } = op else {
+        return None;
+    };
+    if env.lookup(name)?.provenance != Provenance::Prelude {
+        return None;
+    }
+    let form = env.prelude()?.get_info(name)?.special_form?;
+    Some((form, name))
+}
+
+/// Whether `op` names `pick` or `roll` of the prelude
+fn indexed_form(env: &Env, op: Option<&Op>) -> bool {
+    op.and_then(|op| special_form(env, op))
+        .is_some_and(|(form, _)| form == SpecialForm::Indexed)
+}
+
+/// The index an `Int` literal gives the `pick` or `roll` after it
+fn index_of(op: &Op) -> Option<usize> {
+    match op {
+        Op::Literal {
+            value: Literal::Int(n),
+            ..
+        } => usize::try_from(*n).ok(),
+        _ => None,
+    }
+}
+
+/// How often the `infer` loop looks at the cancel token and the deadline
+const INTERRUPT_CHECK_INTERVAL: usize = 64;
+
+/// The most types the type of a body may be built of unless set with
+/// `Inference::with_max_type_size`, far more than any type written by hand
+pub const DEFAULT_MAX_TYPE_SIZE: usize = 10_000;
+
+#[derive(Default)]
+struct Watch {
+    cancel: Option<CancelToken>,
+    #[cfg(feature = "os")]
+    deadline: Option<Instant>,
+    ops_seen: usize,
+}
+
+/// What checking looks names up in, the same for every check against a
+/// module and shared between them
+#[derive(Clone)]
+pub(crate) struct Tables {
+    /// The op type of each constructor
+    constr_optypes: HashMap<String, OpType>,
+    /// The data definition of each constructor
+    constr_data: HashMap<String, Arc<DataDef>>,
+    /// The names op bodies can use, see `Env::base`
+    env: Env,
+    deprecations: Deprecations,
+    strict: StrictOptions,
+    /// The type of the extern literals of each tag
+    extern_literals: HashMap<String, Type>,
+    /// The most types the type of a body may be built of
+    max_type_size: Option<usize>,
+    /// The definitions of each overloaded name
+    overloads: HashMap<String, OverloadSet>,
+}
+
+/// The state of one check: the supply of fresh type variables, which
+/// starts over for every context so that the same check names its
+/// variables the same, and what the check collects. Cheap to make, there
+/// is one for each request against a shared `TypeEnv`.
+#[derive(Default)]
+pub struct InferCtx {
+    supply: NameSupply,
+    collector: RefCell<Option<Collector>>,
+    calls: RefCell<Option<CallRecorder>>,
+    watch: RefCell<Watch>,
+    /// The steps of the op being checked, see `trace`
+    stats: RefCell<CheckStats>,
+    tracer: Option<Tracer>,
+    /// The key of the definition each use of an overloaded name resolved
+    /// to, by the start of the use's span
+    resolved: RefCell<BTreeMap<usize, String>>,
+    /// The values each op has to check, by the start of its span
+    dyn_checks: RefCell<BTreeMap<OpKey, Vec<DynCheck>>>,
+}
+
+impl InferCtx {
+    pub fn new() -> Self {
+        InferCtx::default()
+    }
+
+    /// Has the tracer called with every step of checking, see `trace`
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
 }
 
+/// Checks a module. Neither `Send` nor `Sync`, the context of the check
+/// is in cells and the tracer is any closure: checks on other threads each
+/// start from a `TypeEnv`, see `freeze`.
 pub struct Inference<'m> {
     module: &'m Module,
-    constr_maps: ModuleConstrMaps<'m>,
-    optype_maps: ModuleConstrOpTypeMap<'m>,
-    counter: AtomicUsize,
+    tables: Arc<Tables>,
+    ctx: InferCtx,
 }
 
 impl<'m> Inference<'m> {
     pub fn new(module: &'m Module) -> Self {
-        let constr_maps = ModuleConstrMaps::new(module);
-        let optype_maps = ModuleConstrOpTypeMap::new(module);
+        Inference::with_resolution(module, Resolution::new(module))
+    }
+
+    /// Checks the module against tables built elsewhere, which may know more
+    /// names than the module defines
+    pub(crate) fn with_resolution(module: &'m Module, resolution: Resolution) -> Self {
+        let Resolution {
+            constr_optypes,
+            constr_data,
+            env,
+            deprecations,
+            overloads,
+        } = resolution;
+        let tables = Tables {
+            constr_optypes,
+            constr_data,
+            env,
+            deprecations,
+            strict: StrictOptions::default(),
+            extern_literals: HashMap::new(),
+            max_type_size: Some(DEFAULT_MAX_TYPE_SIZE),
+            overloads,
+        };
+        Inference::with_tables(module, Arc::new(tables), InferCtx::new())
+    }
+
+    pub(crate) fn with_tables(module: &'m Module, tables: Arc<Tables>, ctx: InferCtx) -> Self {
         Inference {
             module,
-            constr_maps,
-            optype_maps,
-            counter: AtomicUsize::new(0),
+            tables,
+            ctx,
         }
     }
 
-    pub fn typecheck(&self) -> Result<(), InferenceError> {
-        for (op_name, op_def) in self.module.op_defs.iter() {
-            if op_name.starts_with("noc") {
-                continue;
-            }
-            let inf = self.infer(&op_def.body)?;
-            let ann_inst = self.instantiate_op(op_def.ann.clone());
-            self.inf_vs_ann(inf, &ann_inst)
-                .map_err(|error| InferenceError {
-                    error,
-                    span: op_def.span.clone(),
-                })?;
+    /// The tables back, to check more definitions with them
+    pub(crate) fn into_resolution(self) -> Resolution {
+        let tables = Arc::unwrap_or_clone(self.tables);
+        Resolution {
+            constr_optypes: tables.constr_optypes,
+            constr_data: tables.constr_data,
+            env: tables.env,
+            deprecations: tables.deprecations,
+            overloads: tables.overloads,
         }
-        Ok(())
     }
 
-    fn inf_vs_ann(&self, inf: OpType, ann: &OpType) -> Result<(), InferenceErrorMessage> {
-        // augment stacks toward the annotation
-        let inf = self.augment_op_ow(inf, ann);
-        let s = OpType::mgu(&inf, ann)?;
-        // ann matches the inf when all subs associated with ftv of annotation are poly
-        for v in ann.ftv().iter().filter_map(|t| s.get(t)) {
-            match v {
-                Type::Poly(_) => (),
-                _ => Err(InferenceErrorMessage::AnnInfConflict {
-                    inf: inf.clone(),
-                    ann: ann.clone(),
-                })?,
-            }
-        }
-        Ok(())
+    /// The module and everything the checks look up, as they are now, to
+    /// share between threads. The module is copied once.
+    pub fn freeze(&self) -> TypeEnv {
+        TypeEnv::new(Arc::new(self.module.clone()), self.tables.clone())
     }
 
-    fn gen_name(&self) -> Type {
-        let n = self
-            .counter
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        let name = format!("_gen_{}", n);
-        Type::Poly(name)
+    /// What the checks look up, shared with the inference
+    pub(crate) fn tables(&self) -> Arc<Tables> {
+        self.tables.clone()
     }
 
-    fn instantiate_op(&self, op: OpType) -> OpType {
-        let new_var_subst = op.ftv().into_iter().map(|v| (v, self.gen_name())).collect();
-        op.apply(&new_var_subst)
+    /// The tables to change, copied first if a `TypeEnv` shares them
+    fn tables_mut(&mut self) -> &mut Tables {
+        Arc::make_mut(&mut self.tables)
     }
 
-    /// Augments the first argument's pre and post stacks towards the target
-    fn augment_op_ow(&self, mut general: OpType, concrete: &OpType) -> OpType {
-        while general.pre.len() < concrete.pre.len() && general.post.len() < concrete.post.len() {
-            let new_var = self.gen_name();
-            general.pre.push(new_var.clone());
-            general.post.push(new_var.clone());
+    /// Declares an op implemented outside of the module, e.g. a host function
+    pub fn with_extern_op(mut self, name: &str, optype: OpType) -> Self {
+        if let Some(externs) = self.tables_mut().env.scope_mut(Provenance::Extern) {
+            externs.insert(name.to_owned(), Scheme::generalize(optype));
         }
-        general
+        self
     }
 
-    /// augment both optypes aso that both optypes have the same stacks lengths
-    fn augment_op_bw(&self, o1: OpType, o2: OpType) -> (OpType, OpType) {
-        let o1 = self.augment_op_ow(o1, &o2);
-        let o2 = self.augment_op_ow(o2, &o1);
-        (o1, o2)
+    /// Declares the type of the extern literals tagged `tag`, see
+    /// `LiteralTable`
+    pub fn with_extern_literal(mut self, tag: &str, ty: Type) -> Self {
+        self.tables_mut().extern_literals.insert(tag.to_owned(), ty);
+        self
     }
 
-    fn lit_optype(&self, lit: &Literal) -> OpType {
-        let lit_type = match lit {
-            Literal::Int(_) => Type::Mono("Int".to_owned()),
-        };
-        OpType {
-            pre: vec![],
-            post: vec![lit_type],
-        }
+    /// Has the tracer called with every step of checking, see `trace`
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.ctx.tracer = Some(tracer);
+        self
     }
 
-    fn make_destr(constr: &OpType) -> OpType {
-        OpType {
-            pre: constr.post.clone(),
-            post: constr.pre.clone(),
+    fn trace(&self, event: TraceEvent) {
+        if let Some(tracer) = &self.ctx.tracer {
+            tracer(&event);
         }
     }
 
-    fn lookup_constructor_optype(&self, name: &str) -> Option<&OpType> {
-        self.optype_maps.constr_to_optype_map.get(name)
+    /// The names op bodies can use and what each refers to
+    pub fn env(&self) -> &Env {
+        &self.tables.env
     }
 
-    fn lookup_constructor_data_def(&self, name: &str) -> Option<&&DataDef> {
-        self.constr_maps
-            .constr_to_data_map
-            .get(name)
-            .map(|(_data_name, data_def)| data_def)
+    pub fn module(&self) -> &'m Module {
+        self.module
     }
 
-    fn infer_case_arm(&self, arm: &CaseArm) -> Result<OpType, InferenceError> {
-        let constr_ot = self
-            .lookup_constructor_optype(arm.constr.as_str())
-            .cloned()
-            .ok_or_else(|| InferenceError {
-                error: InferenceErrorMessage::UnknownConstructor {
-                    name: arm.constr.to_owned(),
-                },
-                span: arm.span.to_owned(),
-            })?;
-        let body_optype = self.infer(&arm.body)?;
-        // create a destructor from the constructor op type and instantiate it
-        let destr = Self::make_destr(&constr_ot);
-        let inst_destr = self.instantiate_op(destr);
-        // chain the destructor with the arm body to get the complete op type
-        self.chain(inst_destr, body_optype)
-            .map_err(|error| InferenceError {
-                error,
-                span: arm.span.to_owned(),
-            })
+    pub fn strict(&self) -> &StrictOptions {
+        &self.tables.strict
     }
 
-    fn get_prelude_optype(&self, name: &str) -> Option<OpType> {
-        prelude_types::get(name)
+    fn prelude(&self) -> &Prelude {
+        self.tables
+            .env
+            .prelude()
+            .expect("the base environment has a prelude")
     }
 
-    fn get_constr_optype(&self, name: &str) -> Option<OpType> {
-        self.optype_maps.constr_to_optype_map.get(name).cloned()
+    fn prelude_mut(&mut self) -> &mut Prelude {
+        self.tables_mut()
+            .env
+            .prelude_mut()
+            .expect("the base environment has a prelude")
     }
 
-    fn get_user_optype(&self, name: &str) -> Option<OpType> {
-        self.module
-            .op_defs
-            .get(name)
-            .map(|op_def| &op_def.ann)
-            .cloned()
+    pub fn with_strict(mut self, strict: StrictOptions) -> Self {
+        self.tables_mut().strict = strict;
+        self
     }
 
-    fn lookup_op_optype(&self, name: &str) -> Option<OpType> {
-        // lookup the prelude, constructors, user defined
-        self.get_prelude_optype(name)
-            .or_else(|| self.get_constr_optype(name))
-            .or_else(|| self.get_user_optype(name))
-    }
-
-    /// Chain two operator types through unification. This includes overflow and underflow chain.
-    fn chain(&self, ot1: OpType, ot2: OpType) -> Result<OpType, InferenceErrorMessage> {
-        let OpType {
-            pre: alpha,
-            post: beta,
-        } = ot1;
-        let OpType {
-            pre: gamma,
-            post: delta,
-        } = ot2;
-        let l = usize::min(beta.len(), gamma.len());
-        let s = Vec::mgu(&beta[..l].into(), &gamma[..l].into())?;
-        if beta.len() >= gamma.len() {
-            // overflow chain
-            let beta_skip_gamma = beta.into_iter().skip(gamma.len());
-            let pre = alpha.into_iter().collect();
-            let post = delta.into_iter().chain(beta_skip_gamma).collect();
-            Ok(OpType { pre, post }.apply(&s))
-        } else {
-            // underflow chain
-            let gamma_skip_beta = gamma.into_iter().skip(beta.len());
-            let pre = alpha.into_iter().chain(gamma_skip_beta).collect();
-            let post = delta.into_iter().collect();
-            Ok(OpType { pre, post }.apply(&s))
-        }
+    /// Fails bodies whose type gets built of more than `max` types with
+    /// `TypeTooLarge`, `DEFAULT_MAX_TYPE_SIZE` if not set, never if `None`.
+    /// A type can double in size at every op, checking it soon takes all
+    /// the time and memory there is.
+    pub fn with_max_type_size(mut self, max: Option<usize>) -> Self {
+        self.tables_mut().max_type_size = max;
+        self
     }
 
-    fn infer_op(&self, op: &Op) -> Result<OpType, InferenceError> {
-        match op {
-            Op::Literal { value, .. } => Ok(self.lit_optype(value)),
-            Op::Name { value: name, span } => self
-                .lookup_op_optype(name)
-                .map(|op| self.instantiate_op(op))
-                .ok_or_else(|| InferenceErrorMessage::UnknownOp {
-                    name: name.to_owned(),
-                })
-                .map_err(|error| InferenceError {
-                    error,
-                    span: span.to_owned(),
-                }),
-            Op::Quote { value, .. } => {
-                let quoted_optype = self.infer(value)?;
-                Ok(OpType {
-                    pre: vec![],
-                    post: vec![Type::Op(quoted_optype)],
-                })
-            }
-            Op::Case {
-                head_arm,
-                arms,
-                span,
-            } => {
-                let matched_data_type = self
-                    .lookup_constructor_data_def(&head_arm.constr)
-                    .ok_or_else(|| InferenceError {
-                        error: InferenceErrorMessage::UnknownConstructor {
-                            name: head_arm.constr.to_owned(),
-                        },
-                        span: span.to_owned(),
-                    })?;
-
-                let matched_data_type_constr_names: HashSet<_> =
-                    matched_data_type.constrs.keys().collect();
-                let covered_constr_names: HashSet<_> = once(&head_arm.constr)
-                    .chain(arms.iter().map(|arm| &arm.constr))
-                    .collect();
+    /// Checks without any prelude op, `override_prelude_op` can add single
+    /// ones back
+    pub fn without_prelude(mut self) -> Self {
+        self.prelude_mut().remove_all();
+        self
+    }
 
-                if matched_data_type_constr_names != covered_constr_names {
-                    return Err(InferenceError {
-                        error: InferenceErrorMessage::NotAllConstructorsCovered,
-                        span: span.to_owned(),
-                    });
-                }
+    pub fn without_prelude_op(mut self, name: &str) -> Self {
+        self.prelude_mut().remove(name);
+        self
+    }
 
-                let mut head_ot = self.infer_case_arm(head_arm)?;
-                for arm in arms {
-                    let mut arm_ot = self.infer_case_arm(arm)?;
-                    (head_ot, arm_ot) = self.augment_op_bw(head_ot, arm_ot);
-                    let s = OpType::mgu(&head_ot, &arm_ot).map_err(|error| InferenceError {
-                        error,
-                        span: span.to_owned(),
-                    })?;
-                    head_ot = head_ot.apply(&s);
-                }
+    /// Checks uses of the prelude op against another type. Only the checker
+    /// is affected, evaluation still runs the prelude's implementation.
+    pub fn override_prelude_op(
+        mut self,
+        name: &str,
+        optype: OpType,
+    ) -> Result<Self, UnknownPreludeOp> {
+        self.prelude_mut().override_op(name, optype)?;
+        Ok(self)
+    }
 
-                Ok(head_ot)
+    pub fn typecheck(&self) -> Result<(), InferenceError> {
+        self.check_data_defs()?;
+        for (const_name, const_def) in self.module.const_defs_in_source_order() {
+            self.check_const_def(const_name, const_def)?;
+        }
+        for (op_name, op_def) in self.module.op_defs_in_source_order() {
+            self.trace(TraceEvent::CheckOp { name: op_name });
+            self.check_op_name(op_name, op_def)?;
+            if op_def.declaration || is_unchecked(op_name, op_def) {
+                continue;
             }
+            // failing the way an `expect-error` pragma asks for is fine
+            let _ = self.check_op_def_pragmas(op_def)?;
         }
+        Ok(())
     }
 
-    fn infer(&self, ops: &[Op]) -> Result<OpType, InferenceError> {
-        let mut acc = OpType::empty();
-        for op in ops {
-            let t = self.infer_op(op)?;
-            acc = self.chain(acc, t).map_err(|error| InferenceError {
-                error,
-                span: op.get_span().clone(),
-            })?;
+    /// Like `typecheck`, but stops early once the token is cancelled
+    pub fn typecheck_with(&self, token: &CancelToken) -> Result<Completion, InferenceError> {
+        self.ctx.watch.borrow_mut().cancel = Some(token.clone());
+        let result = self.typecheck();
+        self.ctx.watch.replace(Watch::default());
+        match result {
+            Ok(()) => Ok(Completion::Finished),
+            Err(InferenceError {
+                error: InferenceErrorMessage::Interrupted(_),
+                ..
+            }) => Ok(Completion::Cancelled),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Checks every definition instead of stopping at the first error
+    pub fn report(&self) -> TypecheckReport {
+        self.report_with(&CheckOptions::default())
+    }
+
+    /// Like `report`, ops not checked because of cancellation or running
+    /// over the budget get their own outcomes
+    pub fn report_with(&self, opts: &CheckOptions) -> TypecheckReport {
+        self.report_reusing(opts, &|_, _| None)
+    }
+
+    /// Like `report_with`, but an op `reuse` gives an outcome for is not
+    /// checked again and gets that outcome, marked as from the cache
+    pub fn report_reusing(
+        &self,
+        opts: &CheckOptions,
+        reuse: &dyn Fn(&str, &OpDef) -> Option<OpOutcome>,
+    ) -> TypecheckReport {
+        self.report_ops(opts, reuse, &|_| true)
+    }
+
+    /// Like `report`, but only infers the ops the entries call, directly
+    /// or not, a name inside a quote counting as a call. The other ops are
+    /// `Skipped`. Data and constant definitions are checked as usual, as
+    /// are the names of all ops.
+    pub fn typecheck_reachable(&self, entries: &[&str]) -> TypecheckReport {
+        let reachable = CallGraph::new(self.module).reachable(entries, true);
+        self.report_ops(&CheckOptions::default(), &|_, _| None, &|name| {
+            reachable.contains(name)
+        })
+    }
+
+    fn report_ops(
+        &self,
+        opts: &CheckOptions,
+        reuse: &dyn Fn(&str, &OpDef) -> Option<OpOutcome>,
+        wanted: &dyn Fn(&str) -> bool,
+    ) -> TypecheckReport {
+        self.ctx.watch.borrow_mut().cancel = opts.cancel.clone();
+        let errors = self
+            .check_data_defs()
+            .err()
+            .into_iter()
+            .chain(
+                self.module
+                    .const_defs_in_source_order()
+                    .into_iter()
+                    .filter_map(|(name, const_def)| self.check_const_def(name, const_def).err()),
+            )
+            .collect();
+        let ops = self
+            .module
+            .op_defs_in_source_order()
+            .into_iter()
+            .map(|(op_name, op_def)| {
+                if let Some(outcome) = reuse(op_name, op_def) {
+                    return OpReport {
+                        name: op_name.to_owned(),
+                        span: op_def.span.clone(),
+                        outcome,
+                        from_cache: true,
+                        stats: CheckStats::default(),
+                        elapsed: None,
+                    };
+                }
+                self.ctx.stats.take();
+                let given = self.ctx.supply.given();
+                #[cfg(feature = "os")]
+                let start = Instant::now();
+                self.trace(TraceEvent::CheckOp { name: op_name });
+                let outcome = if let Err(err) = self.check_op_name(op_name, op_def) {
+                    OpOutcome::Failed(err)
+                } else if op_def.declaration {
+                    OpOutcome::Declared
+                } else if is_unchecked(op_name, op_def) {
+                    OpOutcome::Unchecked
+                } else if !wanted(op_name) {
+                    OpOutcome::Skipped(SkipReason::Unreachable)
+                } else {
+                    #[cfg(feature = "os")]
+                    {
+                        self.ctx.watch.borrow_mut().deadline =
+                            opts.op_budget.map(|budget| Instant::now() + budget);
+                    }
+                    match self.check_op_def_pragmas(op_def) {
+                        Ok(Ok(inferred)) => OpOutcome::Inferred(inferred),
+                        Ok(Err(err)) => OpOutcome::ExpectedError(err),
+                        Err(InferenceError {
+                            error: InferenceErrorMessage::Interrupted(interrupt),
+                            ..
+                        }) => match interrupt {
+                            Interrupt::Cancelled => OpOutcome::Cancelled,
+                            Interrupt::TimedOut => OpOutcome::TimedOut,
+                        },
+                        Err(err) => OpOutcome::Failed(err),
+                    }
+                };
+                #[cfg(feature = "os")]
+                let elapsed = Some(start.elapsed());
+                #[cfg(not(feature = "os"))]
+                let elapsed = None;
+                OpReport {
+                    name: op_name.to_owned(),
+                    span: op_def.span.clone(),
+                    outcome,
+                    from_cache: false,
+                    stats: CheckStats {
+                        fresh_vars: self.ctx.supply.given() - given,
+                        ..self.ctx.stats.take()
+                    },
+                    elapsed,
+                }
+            })
+            .collect();
+        self.ctx.watch.replace(Watch::default());
+        TypecheckReport {
+            errors,
+            ops,
+            prelude_used: self.prelude_used(),
+            overloads: self.ctx.resolved.take(),
+            dyn_checks: self.ctx.dyn_checks.take(),
+            timings: vec![],
+        }
+    }
+
+    /// The prelude ops named anywhere in the module, sorted
+    fn prelude_used(&self) -> Vec<String> {
+        fn names<'o>(ops: &'o [Op], out: &mut BTreeSet<&'o str>) {
+            for op in ops {
+                match op {
+                    Op::Literal { .. } => (),
+                    Op::Name { value, .. } => {
+                        out.insert(value);
+                    }
+                    Op::Quote { value, .. } => names(value, out),
+                    Op::Case { head_arm, arms, .. } => {
+                        for arm in once(head_arm).chain(arms) {
+                            names(&arm.body, out);
+                        }
+                    }
+                    Op::Case2 { arms, .. } => {
+                        for arm in arms {
+                            names(&arm.body, out);
+                        }
+                    }
+                    Op::List { items, .. } => {
+                        for item in items {
+                            names(item, out);
+                        }
+                    }
+                }
+            }
+        }
+        let mut used = BTreeSet::new();
+        for op_def in self.module.op_defs.values() {
+            names(&op_def.body, &mut used);
+        }
+        for const_def in self.module.const_defs.values() {
+            names(&const_def.body, &mut used);
+        }
+        used.into_iter()
+            .filter(|name| self.prelude().get(name).is_some())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Checks a single op again, recording every unification on the way
+    pub fn explain_op(&self, name: &str) -> UnifyTrace {
+        *self.ctx.collector.borrow_mut() = Some(Collector::default());
+        let error = self
+            .module
+            .op_defs
+            .get(name)
+            .and_then(|op_def| self.check_op_def(op_def).err());
+        let collector = self.ctx.collector.borrow_mut().take().unwrap_or_default();
+        UnifyTrace {
+            op: name.to_owned(),
+            obligations: collector.obligations,
+            error,
+            names: collector.names,
+        }
+    }
+
+    /// The type of an op's body on its own, ignoring the annotation. `None`
+    /// when no op is named `name` or it is declared without a body.
+    pub fn infer_body(&self, name: &str) -> Option<Result<OpType, InferenceError>> {
+        let op_def = self
+            .module
+            .op_defs
+            .get(name)
+            .filter(|op_def| !op_def.declaration)?;
+        Some(self.infer(&self.tables.env, &op_def.body))
+    }
+
+    /// The calls of user ops in the body of `name`, quotes and case arms
+    /// included, each with the type it is made at when the op is used at
+    /// `at`, an instance of its annotation. `None` when no op is named
+    /// `name`.
+    pub fn calls_at(
+        &self,
+        name: &str,
+        at: &OpType,
+    ) -> Option<Result<Vec<CallType>, InferenceError>> {
+        let op_def = self.module.op_defs.get(name)?;
+        *self.ctx.calls.borrow_mut() = Some(CallRecorder::default());
+        let result = self.infer(&self.tables.env, &op_def.body).and_then(|inf| {
+            let origin = || Origin {
+                kind: ObligationKind::Annotation,
+                span: op_def.span.clone(),
+                subject: "annotation".to_owned(),
+            };
+            self.match_ann(inf, at, origin)
+                .map_err(|error| InferenceError {
+                    error,
+                    span: op_def.span.clone(),
+                })
+        });
+        let recorder = self.ctx.calls.borrow_mut().take().unwrap_or_default();
+        Some(result.map(|_| {
+            let CallRecorder { calls, substs } = recorder;
+            calls
+                .into_iter()
+                .map(|call| CallType {
+                    optype: substs.iter().fold(call.optype, |t, s| t.apply(s)),
+                    ..call
+                })
+                .collect()
+        }))
+    }
+
+    /// The ops of the body of `op_name` that made the variable `var` of its
+    /// annotation less general, in the order the body runs them. The first
+    /// one gave it a type that is not a variable, or the variable another
+    /// of the annotation's stands for, the others made that type more
+    /// specific. The body runs on the annotation's inputs, a value left for
+    /// the annotation's outputs is blamed on the op that pushed it. Stops
+    /// at the first op that does not chain, empty when no op is named
+    /// `op_name` or it has no `var`. Only blaming tracks what each chain
+    /// substituted, checking does not.
+    pub fn blame(&self, op_name: &str, var: &str) -> Vec<BlameSite> {
+        let Some(op_def) = self.module.op_defs.get(op_name) else {
+            return vec![];
+        };
+        let (ann, back) = self.instantiate_with_mapping(&Scheme::generalize(op_def.ann.clone()));
+        let Some(blamed) = back
+            .iter()
+            .find(|(_, t)| **t == Type::Poly(var.to_owned()))
+            .map(|(generated, _)| generated.clone())
+        else {
+            return vec![];
+        };
+        let mut names = Normalizer::naming(back.iter().filter_map(|(generated, t)| match t {
+            Type::Poly(v) => Some((generated.clone(), v.clone())),
+            _ => None,
+        }));
+        // what each variable of the annotation stands for so far
+        let mut images: Subst = back
+            .keys()
+            .map(|v| (v.clone(), Type::Poly(v.clone())))
+            .collect();
+        // the variable of the annotation the blamed one was merged with
+        let merged_with = |images: &Subst| {
+            let image = &images[&blamed];
+            let others = images.iter().filter(|(v, t)| **v != blamed && *t == image);
+            others.map(|(v, _)| back[v].clone()).min()
+        };
+        let mut sites = vec![];
+        let mut step = |images: &mut Subst, s: &Subst, op: Option<&Op>| {
+            let before = images[&blamed].clone();
+            let was_lost = !matches!(before, Type::Poly(_)) || merged_with(images).is_some();
+            for t in images.values_mut() {
+                *t = t.apply(s);
+            }
+            let became = match &images[&blamed] {
+                Type::Poly(_) => merged_with(images),
+                after => Some(names.ty(after)),
+            };
+            let changed = images[&blamed] != before || !was_lost;
+            if let (Some(op), Some(became), true) = (op, became, changed) {
+                sites.push(BlameSite {
+                    op: op_label(op),
+                    span: op.get_span().clone(),
+                    became,
+                });
+            }
+        };
+        let mut acc = OpType {
+            pre: vec![],
+            post: ann.pre.clone(),
+        };
+        // the op that pushed each value of `acc.post`, none for the inputs
+        let mut pushers: Vec<Option<&Op>> = vec![None; ann.pre.len()];
+        for op in op_def.body.iter() {
+            let Ok(t) = self.infer_op(&self.tables.env, op) else {
+                return sites;
+            };
+            let (popped, pushed) = (t.pre.len(), t.post.len());
+            let origin = || Origin {
+                kind: ObligationKind::Chain,
+                span: op.get_span().clone(),
+                subject: op.to_string(),
+            };
+            let Ok((chained, s)) = self.chain_subst(acc, t, origin) else {
+                return sites;
+            };
+            acc = chained;
+            pushers = std::iter::repeat_n(Some(op), pushed)
+                .chain(pushers.into_iter().skip(popped))
+                .collect();
+            step(&mut images, &s, Some(op));
+        }
+        let mut s_outputs = Subst::new();
+        for (i, (found, wanted)) in zip(&acc.post, &ann.post).enumerate() {
+            let wanted = wanted.apply(&images).apply(&s_outputs);
+            let Ok(s) = Type::mgu(&found.apply(&s_outputs), &wanted) else {
+                break;
+            };
+            step(&mut images, &s, pushers[i]);
+            s_outputs = compose(s_outputs, s);
+        }
+        sites
+    }
+
+    fn record_call(&self, name: &str, span: &Span, optype: &OpType) {
+        if let Some(recorder) = self.ctx.calls.borrow_mut().as_mut() {
+            recorder.calls.push(CallType {
+                callee: name.to_owned(),
+                span: span.clone(),
+                optype: optype.clone(),
+            });
+        }
+    }
+
+    /// The type of ops on their own, as the body of an op of the module
+    /// would have ignoring its annotation
+    pub fn infer_ops(&self, ops: &[Op]) -> Result<OpType, InferenceError> {
+        self.infer(&self.tables.env, ops)
+    }
+
+    /// The type of one op of a body on its own, as it is chained with the
+    /// ops around it
+    pub fn infer_op_alone(&self, op: &Op) -> Result<OpType, InferenceError> {
+        self.infer_op(&self.tables.env, op)
+    }
+
+    /// Whether the op is the prelude's `pick` or `roll`, which is only
+    /// typed together with the literal index before it
+    pub fn takes_index(&self, op: &Op) -> bool {
+        indexed_form(&self.tables.env, Some(op))
+    }
+
+    /// Ops that can stand in for the query, i.e. whose types unify with it
+    /// once both are augmented to the same stack lengths, closest matches
+    /// first. Parametric prelude ops are tried with parameters up to the
+    /// longest stack in the query.
+    pub fn search(&self, query: &OpType) -> Vec<SearchHit> {
+        let is_shadowed = |name: &str| {
+            self.tables
+                .env
+                .lookup(name)
+                .is_some_and(|binding| binding.provenance != Provenance::User)
+        };
+        let prelude = self
+            .prelude()
+            .names(longest_stack(query))
+            .into_iter()
+            // `panic` would match every query pushing a value without ever
+            // pushing it, the types of `pick` and `roll` are for one index
+            .filter(|name| !["panic", "pick", "roll"].contains(&name.as_str()))
+            .filter_map(|name| {
+                let scheme = Scheme::generalize(self.prelude().get(&name)?);
+                Some((SearchHitKind::Prelude, scheme, name))
+            });
+        let externs = self
+            .tables
+            .env
+            .names(Provenance::Extern)
+            .map(|(name, t)| (SearchHitKind::Extern, t.clone(), name.to_owned()));
+        let constrs = self.tables.constr_optypes.iter().map(|(name, t)| {
+            let scheme = Scheme::generalize(t.clone());
+            (SearchHitKind::Constructor, scheme, name.to_owned())
+        });
+        let ops = self
+            .module
+            .op_defs
+            .iter()
+            .filter(|(name, _)| !is_shadowed(name))
+            .map(|(name, op_def)| {
+                let scheme = Scheme::generalize(op_def.ann.clone());
+                (SearchHitKind::Op, scheme, name.to_owned())
+            });
+        let consts = self
+            .tables
+            .env
+            .names(Provenance::Constant)
+            .filter(|(name, _)| {
+                self.tables
+                    .env
+                    .lookup(name)
+                    .is_some_and(|binding| binding.provenance == Provenance::Constant)
+            })
+            .map(|(name, t)| (SearchHitKind::Constant, t.clone(), name.to_owned()));
+        // the fresh variables never leave the search, rewinding the supply
+        // keeps the names generated afterwards independent of searches
+        let mark = self.ctx.supply.mark();
+        let mut hits: Vec<_> = prelude
+            .chain(externs)
+            .chain(constrs)
+            .chain(ops)
+            .chain(consts)
+            .filter_map(|(kind, scheme, name)| {
+                let (augmentation, instantiation, general) = self.match_query(query, &scheme)?;
+                Some(SearchHit {
+                    name,
+                    kind,
+                    op_type: scheme.canonical(),
+                    augmentation,
+                    instantiation,
+                    general,
+                })
+            })
+            .collect();
+        self.ctx.supply.rewind(mark);
+        hits.sort_by(|h1, h2| h1.rank().cmp(&h2.rank()));
+        hits
+    }
+
+    /// How many slots augmentation added, how far the unifier is from
+    /// renaming the variables of either side and whether the candidate is
+    /// at least as general as the query, when the types unify
+    fn match_query(&self, query: &OpType, candidate: &Scheme) -> Option<(usize, usize, bool)> {
+        let query = self.instantiate(&Scheme::generalize(query.clone()));
+        let candidate = self.instantiate(candidate);
+        let slots = |t: &OpType| t.pre.len() + t.post.len();
+        let before = slots(&query) + slots(&candidate);
+        let (query, candidate) = augment_both(query, candidate, &self.ctx.supply);
+        let augmentation = (slots(&query) + slots(&candidate) - before) / 2;
+        let subst = OpType::mgu(&query, &candidate).ok()?;
+        // variables set to a concrete type or to the same type as another
+        let lost = |t: &OpType| {
+            let mut images = HashSet::new();
+            t.ftv()
+                .into_iter()
+                .filter(|v| match subst.get(v) {
+                    Some(Type::Poly(w)) => !images.insert(w.to_owned()),
+                    Some(_) => true,
+                    None => !images.insert(v.to_owned()),
+                })
+                .count()
+        };
+        let instantiation = lost(&query) + lost(&candidate);
+        let general = OpType::instance_of(&candidate, &query).is_ok();
+        Some((augmentation, instantiation, general))
+    }
+
+    /// Unifies, attributing the unification to `origin` when tracing
+    fn unify<T: Typeable>(
+        &self,
+        t1: &T,
+        t2: &T,
+        origin: impl FnOnce() -> Origin,
+    ) -> Result<Subst, InferenceErrorMessage> {
+        let mut collector = self.ctx.collector.borrow_mut();
+        let result = match collector.as_mut() {
+            Some(c) => {
+                let result = T::mgu_with(t1, t2, Some(c));
+                c.obligation(origin());
+                result
+            }
+            None => T::mgu(t1, t2),
+        };
+        let mut stats = self.ctx.stats.borrow_mut();
+        stats.unifications += 1;
+        if let Ok(s) = &result {
+            stats.max_subst_len = stats.max_subst_len.max(s.len());
+            if let Some(recorder) = self.ctx.calls.borrow_mut().as_mut() {
+                recorder.substs.push(s.clone());
+            }
+        }
+        result
+    }
+
+    /// A constructor name may only be defined by a single data definition
+    fn check_data_defs(&self) -> Result<(), InferenceError> {
+        let mut seen = HashSet::new();
+        for (_data_name, data_def) in self.module.data_defs_in_source_order() {
+            for constr_name in data_def.constrs.keys() {
+                if self.tables.strict.reject_prelude_shadowing
+                    && self.prelude().get(constr_name).is_some()
+                {
+                    return Err(InferenceError {
+                        error: InferenceErrorMessage::ShadowsPrelude {
+                            name: constr_name.to_owned(),
+                        },
+                        span: data_def.span.clone(),
+                    });
+                }
+                if data_def.constrs[constr_name]
+                    .params
+                    .iter()
+                    .any(Type::has_dyn)
+                {
+                    return Err(InferenceError {
+                        error: InferenceErrorMessage::DynInAnnotation {
+                            name: constr_name.to_owned(),
+                        },
+                        span: data_def.span.clone(),
+                    });
+                }
+                if !seen.insert(constr_name) {
+                    return Err(InferenceError {
+                        error: InferenceErrorMessage::DuplicateConstructor {
+                            name: constr_name.to_owned(),
+                        },
+                        span: data_def.span.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the initializer only uses pure ops and has type `[][T]`
+    fn check_const_def(&self, name: &str, const_def: &ConstDef) -> Result<(), InferenceError> {
+        if const_def.ty.has_dyn() {
+            return Err(InferenceError {
+                error: InferenceErrorMessage::DynInAnnotation {
+                    name: name.to_owned(),
+                },
+                span: const_def.span.clone(),
+            });
+        }
+        self.check_deprecated_uses(name, &const_def.body)?;
+        if let Some((op, span)) = self.impure_op(&const_def.body) {
+            return Err(InferenceError {
+                error: InferenceErrorMessage::ImpureConstant {
+                    name: name.to_owned(),
+                    op: op.to_owned(),
+                },
+                span: span.clone(),
+            });
+        }
+        let inf = self.infer(&self.tables.env, &const_def.body)?;
+        let ann = Scheme::generalize(const_optype(const_def));
+        self.inf_vs_ann(inf, &ann, &const_def.span)
+            .map_err(|error| InferenceError {
+                error,
+                span: const_def.span.clone(),
+            })
+    }
+
+    /// Whether the ops only run pure prelude ops, constructors and
+    /// constants, see `impure_op`
+    pub fn is_pure(&self, ops: &[Op]) -> bool {
+        self.impure_op(ops).is_none()
+    }
+
+    /// The first name the ops run that is not a pure prelude op, a
+    /// constructor or a constant. The ops in quotes are not run, only
+    /// pushed, and running a quote takes an impure op.
+    fn impure_op<'o>(&self, ops: &'o [Op]) -> Option<(&'o str, &'o Span)> {
+        ops.iter().find_map(|op| match op {
+            Op::Name { value, span } => {
+                let pure = match self.tables.env.lookup(value)?.provenance {
+                    Provenance::Constructor | Provenance::Constant => true,
+                    Provenance::Prelude => {
+                        self.prelude().get_info(value).is_some_and(|info| info.pure)
+                    }
+                    Provenance::User | Provenance::Extern | Provenance::Local => false,
+                };
+                (!pure).then_some((value.as_str(), span))
+            }
+            Op::Case { head_arm, arms, .. } => once(head_arm)
+                .chain(arms)
+                .find_map(|arm| self.impure_op(&arm.body)),
+            Op::Case2 { arms, .. } => arms.iter().find_map(|arm| self.impure_op(&arm.body)),
+            Op::List { items, .. } => items.iter().find_map(|item| self.impure_op(item)),
+            Op::Literal { .. } | Op::Quote { .. } => None,
+        })
+    }
+
+    /// The strict mode checks on the name and annotation of an op definition
+    /// and on the deprecated names its body uses
+    fn check_op_name(&self, op_name: &str, op_def: &OpDef) -> Result<(), InferenceError> {
+        let output_only = match self.tables.strict.reject_output_only_vars {
+            true => op_def.ann.output_only_vars().first().copied(),
+            false => None,
+        };
+        let error = if self.tables.strict.reject_prelude_shadowing
+            && self.prelude().get(op_name).is_some()
+        {
+            InferenceErrorMessage::ShadowsPrelude {
+                name: op_name.to_owned(),
+            }
+        } else if !op_def.declaration && op_def.ann.has_dyn() {
+            InferenceErrorMessage::DynInAnnotation {
+                name: op_name.to_owned(),
+            }
+        } else if let Some(error) = self.check_overload(op_name, op_def) {
+            error
+        } else if self.tables.strict.reject_unchecked && is_unchecked(op_name, op_def) {
+            InferenceErrorMessage::UncheckedOp {
+                name: op_name.to_owned(),
+            }
+        } else if let Some((i, var)) = output_only {
+            // at the value rather than the whole definition
+            return Err(InferenceError {
+                error: InferenceErrorMessage::OutputOnlyVar {
+                    op: op_name.to_owned(),
+                    var: var.to_owned(),
+                },
+                span: op_def.post_spans[i].clone(),
+            });
+        } else if allows_deprecated(op_def) {
+            return Ok(());
+        } else {
+            return self.check_deprecated_uses(op_name, &op_def.body);
+        };
+        Err(InferenceError {
+            error,
+            span: op_def.span.clone(),
+        })
+    }
+
+    /// A definition of a name defined more than once has to be marked
+    /// `#[overload]`, and the definitions told apart by what they take.
+    /// The first definition does not report the latter, the ones added to
+    /// it do.
+    fn check_overload(&self, key: &str, op_def: &OpDef) -> Option<InferenceErrorMessage> {
+        let name = overloaded_name(key);
+        let set = self.tables.overloads.get(name)?;
+        if !set.is_overloaded() {
+            return None;
+        }
+        if !op_def.has_attribute("overload") {
+            let name = name.to_owned();
+            return Some(InferenceErrorMessage::OverloadNotMarked { name });
+        }
+        let first = set.candidates.first().is_some_and(|c| c.key == key);
+        (set.position().is_none() && !first).then(|| InferenceErrorMessage::OverloadsIndistinct {
+            name: name.to_owned(),
+            candidates: set.candidates.clone(),
+        })
+    }
+
+    /// Strict mode: the first use of a deprecated name by the definition
+    fn check_deprecated_uses(&self, name: &str, body: &[Op]) -> Result<(), InferenceError> {
+        if !self.tables.strict.reject_deprecated {
+            return Ok(());
+        }
+        let uses = self.tables.deprecations.uses(&self.tables.env, name, body);
+        match uses.into_iter().next() {
+            Some(first) => Err(InferenceError {
+                error: InferenceErrorMessage::Deprecated {
+                    name: first.name,
+                    message: first.message,
+                },
+                span: first.span,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Infers the body and checks it against the annotation, returns the
+    /// inferred type of the body
+    fn check_op_def(&self, op_def: &OpDef) -> Result<OpType, InferenceError> {
+        self.check_interrupt().map_err(|error| InferenceError {
+            error,
+            span: op_def.span.clone(),
+        })?;
+        // only an annotation leaving each value as it takes it, `[a, Int]
+        // [a, Int]` alike, agrees with an empty body
+        if op_def.body.is_empty() && op_def.ann.pre != op_def.ann.post {
+            return Err(InferenceError {
+                error: InferenceErrorMessage::EmptyBodyCannotSatisfy {
+                    ann: op_def.ann.clone(),
+                },
+                span: op_def.span.clone(),
+            });
+        }
+        let ann = Scheme::generalize(op_def.ann.clone());
+        // what the annotation gives tells the overloads used apart, the
+        // body still has to be as general as the annotation
+        let start = if self.uses_overloads(&self.tables.env, &op_def.body) {
+            let given = self.instantiate(&ann).pre;
+            OpType {
+                pre: given.clone(),
+                post: given,
+            }
+        } else {
+            OpType::empty()
+        };
+        let inferred = self.infer_marked(&self.tables.env, start, &op_def.body)?;
+        let inf = inferred.optype;
+        let checked = if inferred.diverges {
+            self.diverged_toward(&inf, &op_def.ann)
+        } else {
+            inf.clone()
+        };
+        self.inf_vs_ann(checked, &ann, &op_def.span)
+            .map_err(|error| {
+                let takes_more = inf.pre.len() > op_def.ann.pre.len();
+                match error {
+                    InferenceErrorMessage::AnnInfConflict { .. } if takes_more => {
+                        self.missing_values(&op_def.body, op_def.ann.pre.len())
+                    }
+                    InferenceErrorMessage::AnnInfConflict { .. } => self.check_inputs(op_def),
+                    _ => None,
+                }
+                .unwrap_or(InferenceError {
+                    error,
+                    span: op_def.span.clone(),
+                })
+            })?;
+        Ok(inf)
+    }
+
+    /// The type of a body that never returns as it is checked against the
+    /// annotation: the values it takes, padded to the ones the annotation
+    /// gives, then whatever the annotation leaves. A body taking more than
+    /// it is given still conflicts.
+    fn diverged_toward(&self, inf: &OpType, ann: &OpType) -> OpType {
+        if inf.pre.len() > ann.pre.len() {
+            return inf.clone();
+        }
+        let padding = ann.pre.len() - inf.pre.len();
+        OpType {
+            pre: (inf.pre.iter().cloned())
+                .chain((0..padding).map(|_| self.gen_name()))
+                .collect(),
+            post: ann.post.iter().map(|_| self.gen_name()).collect(),
+        }
+    }
+
+    /// Checking mode, for a body that does not agree with its annotation:
+    /// the body inferred again on the values the annotation gives it, so
+    /// that the first op taking one of them as a type it is not is blamed,
+    /// rather than the whole definition. The annotation's variables stand
+    /// for any type, so they are kept rigid, named as written, for the body
+    /// not to pin them to what it makes of them. A context of its own keeps
+    /// the calls and steps of the first inference as they are.
+    fn check_inputs(&self, op_def: &OpDef) -> Option<InferenceError> {
+        let rigid: Subst = op_def
+            .ann
+            .ftv()
+            .into_iter()
+            .map(|v| (v.clone(), Type::Mono(v)))
+            .collect();
+        let given = op_def.ann.apply(&rigid).pre;
+        let start = OpType {
+            pre: given.clone(),
+            post: given,
+        };
+        let checking = Inference::with_tables(self.module, self.tables.clone(), InferCtx::new());
+        match checking.infer_from(&self.tables.env, start, &op_def.body) {
+            // the comments describe the body alone
+            Err(err) if err.code() != "CheckpointMismatch" => Some(err),
+            _ => None,
+        }
+    }
+
+    /// The first op of the body that pops more values than there are when
+    /// the body runs on `given` values, if it is a constructor or a prelude
+    /// op, whose types are known to be what they were declared
+    fn missing_values(&self, body: &[Op], given: usize) -> Option<InferenceError> {
+        let mut depth = given;
+        for op in body {
+            let t = self.infer_op(&self.tables.env, op).ok()?;
+            if t.pre.len() <= depth {
+                depth = depth - t.pre.len() + t.post.len();
+                continue;
+            }
+            let Op::Name { value, span } = op else {
+                return None;
+            };
+            let binding = self.tables.env.lookup(value)?;
+            let (Provenance::Constructor | Provenance::Prelude) = binding.provenance else {
+                return None;
+            };
+            return Some(InferenceError {
+                error: InferenceErrorMessage::NotEnoughValues {
+                    op: value.to_owned(),
+                    kind: binding.provenance,
+                    needs: binding.scheme.canonical().pre,
+                    available: depth,
+                },
+                span: span.clone(),
+            });
+        }
+        None
+    }
+
+    /// Checks the op definition and then its pragmas. Failing with the error
+    /// an `expect-error` pragma asks for is a success, carrying that error.
+    fn check_op_def_pragmas(
+        &self,
+        op_def: &OpDef,
+    ) -> Result<Result<OpType, InferenceError>, InferenceError> {
+        let result = self.check_op_def(op_def);
+        if let Err(InferenceError {
+            error: InferenceErrorMessage::Interrupted(_),
+            ..
+        }) = result
+        {
+            return result.map(Ok);
+        }
+        for pragma in op_def.pragmas.iter() {
+            let error = match (&pragma.kind, &result) {
+                (PragmaKind::ExpectType(expected), Ok(inf))
+                    if inf.canonical() != expected.canonical() =>
+                {
+                    InferenceErrorMessage::ExpectTypeMismatch {
+                        inf: inf.clone(),
+                        expected: expected.clone(),
+                    }
+                }
+                (PragmaKind::ExpectError(expected), Ok(_)) => {
+                    InferenceErrorMessage::ExpectErrorMismatch {
+                        expected: expected.to_owned(),
+                        found: None,
+                    }
+                }
+                (PragmaKind::ExpectError(expected), Err(err)) if err.code() != expected => {
+                    InferenceErrorMessage::ExpectErrorMismatch {
+                        expected: expected.to_owned(),
+                        found: Some(Box::new(err.error.clone())),
+                    }
+                }
+                _ => continue,
+            };
+            return Err(InferenceError {
+                error,
+                span: pragma.span.clone(),
+            });
+        }
+        let expects_error = op_def
+            .pragmas
+            .iter()
+            .any(|pragma| matches!(pragma.kind, PragmaKind::ExpectError(_)));
+        match result {
+            Err(err) if !expects_error => Err(err),
+            result => Ok(result),
+        }
+    }
+
+    /// Whether the inferred type is at least as general as every op type
+    /// of the annotation's scheme, its errors in the annotation's names
+    fn inf_vs_ann(
+        &self,
+        inf: OpType,
+        ann: &Scheme,
+        span: &Span,
+    ) -> Result<(), InferenceErrorMessage> {
+        let (ann, back) = self.instantiate_with_mapping(ann);
+        self.record_names(&back);
+        let (inf, s) = self
+            .match_ann(inf, &ann, || Origin {
+                kind: ObligationKind::Annotation,
+                span: span.clone(),
+                subject: "annotation".to_owned(),
+            })
+            .map_err(|error| error.named_back(&back))?;
+        if let Some(max_pinned) = self.tables.strict.max_pinned_vars {
+            // variables of the body type that became concrete or were merged
+            let pinned = inf.ftv().len() - inf.apply(&s).ftv().len();
+            if pinned > max_pinned {
+                return Err(
+                    InferenceErrorMessage::AnnotationTooSpecific { inf, ann, pinned }
+                        .named_back(&back),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `inf`, augmented toward `ann`, is at least as general as
+    /// `ann`, an `AnnInfConflict` if not. Returns the augmented type and
+    /// what unifying it with `ann` substitutes.
+    fn match_ann(
+        &self,
+        inf: OpType,
+        ann: &OpType,
+        origin: impl FnOnce() -> Origin,
+    ) -> Result<(OpType, Subst), InferenceErrorMessage> {
+        // augment stacks toward the annotation
+        let inf = augment(inf, ann, &self.ctx.supply);
+        let s = self.unify(&inf, ann, origin).map_err(|error| match error {
+            // report the whole types rather than the slot that failed
+            InferenceErrorMessage::UnificationError { .. }
+            | InferenceErrorMessage::ListMGULengthDifferent => {
+                InferenceErrorMessage::AnnInfConflict {
+                    inf: inf.clone(),
+                    ann: ann.clone(),
+                }
+            }
+            error => error,
+        })?;
+        // unifying may also have made the annotation more specific, which
+        // only the annotation being an instance of the body's type rules out
+        if OpType::instance_of(&inf, ann).is_err() {
+            return Err(InferenceErrorMessage::AnnInfConflict {
+                inf,
+                ann: ann.clone(),
+            });
+        }
+        Ok((inf, s))
+    }
+
+    fn check_interrupt(&self) -> Result<(), InferenceErrorMessage> {
+        let watch = self.ctx.watch.borrow();
+        if watch.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Err(InferenceErrorMessage::Interrupted(Interrupt::Cancelled));
+        }
+        #[cfg(feature = "os")]
+        if watch
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(InferenceErrorMessage::Interrupted(Interrupt::TimedOut));
+        }
+        Ok(())
+    }
+
+    /// Counts an inferred op, every so often checking for interrupts
+    fn tick(&self) -> Result<(), InferenceErrorMessage> {
+        let ops_seen = {
+            let mut watch = self.ctx.watch.borrow_mut();
+            watch.ops_seen += 1;
+            watch.ops_seen
+        };
+        if ops_seen % INTERRUPT_CHECK_INTERVAL == 0 {
+            self.check_interrupt()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn gen_name(&self) -> Type {
+        Type::Poly(self.ctx.supply.fresh())
+    }
+
+    /// An op type of the scheme, with variables no other type has
+    fn instantiate(&self, scheme: &Scheme) -> OpType {
+        self.instantiate_with_mapping(scheme).0
+    }
+
+    /// Like `instantiate`, also returning the substitution taking each
+    /// generated variable back to the one it replaces, to report errors
+    /// about the type in the names it was written with
+    fn instantiate_with_mapping(&self, scheme: &Scheme) -> (OpType, Subst) {
+        self.ctx.stats.borrow_mut().instantiations += 1;
+        self.trace(TraceEvent::Instantiate {
+            vars: scheme.vars().len(),
+        });
+        let (optype, back) = scheme.instantiate_naming(&self.ctx.supply);
+        let back = back.into_iter().map(|(g, v)| (g, Type::Poly(v))).collect();
+        (optype, back)
+    }
+
+    /// Has the explanation being collected, if any, show the generated
+    /// variables in the names they replace
+    fn record_names(&self, back: &Subst) {
+        if let Some(collector) = self.ctx.collector.borrow_mut().as_mut() {
+            let names = back.iter().filter_map(|(generated, t)| match t {
+                Type::Poly(v) => Some((generated.clone(), v.clone())),
+                _ => None,
+            });
+            collector.names.extend(names);
+        }
+    }
+
+    fn lit_optype(&self, lit: &Literal, span: &Span) -> Result<OpType, InferenceError> {
+        let lit_type = match lit {
+            Literal::Int(_) => Type::Mono("Int".to_owned()),
+            Literal::Extern { tag, .. } => match self.tables.extern_literals.get(tag) {
+                Some(ty) => ty.clone(),
+                None => {
+                    return Err(InferenceError {
+                        span: span.clone(),
+                        error: InferenceErrorMessage::UnknownLiteralKind { tag: tag.clone() },
+                    })
+                }
+            },
+        };
+        Ok(OpType {
+            pre: vec![],
+            post: vec![lit_type],
+        })
+    }
+
+    /// The fields a constructor takes are pushed back in the same order, its
+    /// first field on top
+    fn make_destr(constr: &OpType) -> OpType {
+        OpType {
+            pre: constr.post.clone(),
+            post: constr.pre.clone(),
+        }
+    }
+
+    fn lookup_constructor_optype(&self, name: &str) -> Option<&OpType> {
+        self.tables.constr_optypes.get(name)
+    }
+
+    fn lookup_constructor_data_def(&self, name: &str) -> Option<&DataDef> {
+        self.tables
+            .constr_data
+            .get(name)
+            .map(|data_def| &**data_def)
+    }
+
+    /// The instantiated destructor of the constructor
+    fn destructor(&self, constr: &str, span: &Span) -> Result<OpType, InferenceError> {
+        let constr_ot = self
+            .lookup_constructor_optype(constr)
+            .ok_or_else(|| InferenceError {
+                error: InferenceErrorMessage::UnknownConstructor {
+                    name: constr.to_owned(),
+                },
+                span: span.to_owned(),
+            })?;
+        Ok(self.instantiate(&Scheme::generalize(Self::make_destr(constr_ot))))
+    }
+
+    fn infer_case_arm(&self, env: &Env, arm: &CaseArm) -> Result<Inferred, InferenceError> {
+        let inst_destr = self.destructor(&arm.constr, &arm.span)?;
+        // the fields tell the overloads the body uses apart
+        if self.uses_overloads(env, &arm.body) {
+            return self.infer_marked(env, inst_destr, &arm.body);
+        }
+        let body = self.infer_marked(env, OpType::empty(), &arm.body)?;
+        // chain the destructor with the arm body to get the complete op type
+        let origin = || Origin {
+            kind: ObligationKind::CaseArm,
+            span: arm.span.clone(),
+            subject: arm.constr.to_owned(),
+        };
+        let optype = self
+            .chain(inst_destr, body.optype, origin)
+            .map_err(|error| InferenceError {
+                error,
+                span: arm.span.to_owned(),
+            })?;
+        Ok(Inferred {
+            optype,
+            diverges: body.diverges,
+        })
+    }
+
+    /// A wildcard leaves the value in place
+    fn pattern_destructor(&self, pattern: &Pattern, span: &Span) -> Result<OpType, InferenceError> {
+        match pattern {
+            Pattern::Constr(constr) => self.destructor(constr, span),
+            Pattern::Wildcard => {
+                let t = self.gen_name();
+                Ok(OpType {
+                    pre: vec![t.clone()],
+                    post: vec![t],
+                })
+            }
+        }
+    }
+
+    /// Destructures the topmost value, then the one below it, the body
+    /// sees the fields of the topmost value on top
+    fn infer_case2_arm(&self, env: &Env, arm: &Case2Arm) -> Result<Inferred, InferenceError> {
+        let [p1, p2] = &arm.patterns;
+        let d1 = self.pattern_destructor(p1, &arm.span)?;
+        let d2 = self.pattern_destructor(p2, &arm.span)?;
+        let destr = OpType {
+            pre: d1.pre.into_iter().chain(d2.pre).collect(),
+            post: d1.post.into_iter().chain(d2.post).collect(),
+        };
+        let body = self.infer_marked(env, OpType::empty(), &arm.body)?;
+        let origin = || Origin {
+            kind: ObligationKind::CaseArm,
+            span: arm.span.clone(),
+            subject: format!("{} {}", p1, p2),
+        };
+        let optype = self
+            .chain(destr, body.optype, origin)
+            .map_err(|error| InferenceError {
+                error,
+                span: arm.span.to_owned(),
+            })?;
+        Ok(Inferred {
+            optype,
+            diverges: body.diverges,
+        })
+    }
+
+    /// Joins an arm with the arms before it, see `join_arms`. An arm that
+    /// never returns leaves what the others do, so only the values it
+    /// takes are unified with theirs, the others passing through the ones
+    /// only it takes. The case diverges when every arm does.
+    fn join(
+        &self,
+        arms: Inferred,
+        arm: Inferred,
+        subject: &str,
+        origin: impl Fn() -> Origin,
+    ) -> Result<Inferred, InferenceErrorMessage> {
+        if !arms.diverges && !arm.diverges {
+            let optype = self.join_arms(arms.optype, arm.optype, subject, origin)?;
+            return Ok(Inferred {
+                optype,
+                diverges: false,
+            });
+        }
+        let diverges = arms.diverges && arm.diverges;
+        let (mut joined, diverging) = if arms.diverges {
+            (arm.optype.clone(), &arms.optype)
+        } else {
+            (arms.optype.clone(), &arm.optype)
+        };
+        while joined.pre.len() < diverging.pre.len() {
+            let t = self.gen_name();
+            joined.pre.push(t.clone());
+            joined.post.push(t);
+        }
+        let taken = &joined.pre[..diverging.pre.len()];
+        let s = self
+            .unify::<Vec<_>>(&taken.into(), &diverging.pre, origin)
+            .map_err(|error| match error {
+                InferenceErrorMessage::UnificationError { .. }
+                | InferenceErrorMessage::ListMGULengthDifferent => {
+                    let (arms, arm) =
+                        augment_both(arms.optype.clone(), arm.optype.clone(), &self.ctx.supply);
+                    InferenceErrorMessage::CaseArmMismatch { arms, arm }
+                }
+                error => error,
+            })?;
+        let joined = joined.apply(&s);
+        self.ctx.stats.borrow_mut().arm_joins += 1;
+        self.trace(TraceEvent::JoinArms {
+            subject,
+            pre: joined.pre.len(),
+            post: joined.post.len(),
+        });
+        Ok(Inferred {
+            optype: joined,
+            diverges,
+        })
+    }
+
+    /// Joins the type of an arm with the type of the arms before it into the
+    /// most general type of which both are instances, see `padding` for the
+    /// order. The shallower side is padded with separate variables for its
+    /// pre and post slots, after unifying each pair has to be unifiable
+    /// again, or the deeper side changes a value the shallower side passes
+    /// through. A mismatch reports the whole types, padded as by
+    /// `augment_both`
+    fn join_arms(
+        &self,
+        arms: OpType,
+        arm: OpType,
+        subject: &str,
+        origin: impl Fn() -> Origin,
+    ) -> Result<OpType, InferenceErrorMessage> {
+        let arm_is_deeper = padding(&arms, &arm) > 0;
+        let (shallow, deep) = if arm_is_deeper {
+            (&arms, &arm)
+        } else {
+            (&arm, &arms)
+        };
+        let mut padded = shallow.clone();
+        let mut slots = vec![];
+        for k in 0..padding(shallow, deep) {
+            let (taken, left) = (self.gen_name(), self.gen_name());
+            padded.pre.push(taken.clone());
+            padded.post.push(left.clone());
+            slots.push((shallow.pre.len() + k, shallow.post.len() + k, taken, left));
+        }
+        let (joined_arms, joined_arm) = if arm_is_deeper {
+            (padded, arm.clone())
+        } else {
+            (arms.clone(), padded)
+        };
+        let mut s =
+            self.unify(&joined_arms, &joined_arm, &origin)
+                .map_err(|error| match error {
+                    InferenceErrorMessage::UnificationError { .. }
+                    | InferenceErrorMessage::ListMGULengthDifferent => {
+                        let (arms, arm) = augment_both(arms.clone(), arm.clone(), &self.ctx.supply);
+                        InferenceErrorMessage::CaseArmMismatch { arms, arm }
+                    }
+                    error => error,
+                })?;
+        let mut disagreeing = vec![];
+        for (pre, post, taken, left) in slots {
+            let (taken, left) = (taken.apply(&s), left.apply(&s));
+            match self.unify(&taken, &left, &origin) {
+                Ok(s2) => s = compose(s, s2),
+                Err(InferenceErrorMessage::UnificationError { .. }) => {
+                    disagreeing.push((pre, post, taken, left))
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        if !disagreeing.is_empty() {
+            return Err(InferenceErrorMessage::ArmsDisagreeOnStackDepth {
+                arm: subject.to_owned(),
+                arm_is_deeper,
+                slots: disagreeing,
+            });
+        }
+        let joined = join_dyn(&joined_arms.apply(&s), &joined_arm.apply(&s));
+        self.ctx.stats.borrow_mut().arm_joins += 1;
+        self.trace(TraceEvent::JoinArms {
+            subject,
+            pre: joined.pre.len(),
+            post: joined.post.len(),
+        });
+        Ok(joined)
+    }
+
+    fn infer_case2(
+        &self,
+        env: &Env,
+        arms: &[Case2Arm],
+        span: &Span,
+    ) -> Result<Inferred, InferenceError> {
+        let mut acc: Option<Inferred> = None;
+        for arm in arms {
+            let arm_ot = self.infer_case2_arm(env, arm)?;
+            acc = Some(match acc {
+                None => arm_ot,
+                Some(head_ot) => {
+                    let subject = format!("{} {}", arm.patterns[0], arm.patterns[1]);
+                    let origin = || Origin {
+                        kind: ObligationKind::CaseArms,
+                        span: arm.span.clone(),
+                        subject: subject.clone(),
+                    };
+                    self.join(head_ot, arm_ot, &subject, origin)
+                        .map_err(|error| InferenceError {
+                            error,
+                            span: arm.span.to_owned(),
+                        })?
+                }
+            });
+        }
+        self.check_case2_coverage(arms, span)?;
+        Ok(acc.expect("a covering case2 has an arm"))
+    }
+
+    /// The constructors of each column's data type, a column of only
+    /// wildcards counts as a single wildcard
+    fn case2_columns(&self, arms: &[Case2Arm]) -> [Vec<Pattern>; 2] {
+        let column = |i: usize| {
+            let data_def = arms.iter().find_map(|arm| match &arm.patterns[i] {
+                Pattern::Constr(constr) => self.lookup_constructor_data_def(constr),
+                Pattern::Wildcard => None,
+            });
+            match data_def {
+                Some(data_def) => data_def
+                    .constrs_in_source_order()
+                    .into_iter()
+                    .map(|(name, _)| Pattern::Constr(name.to_owned()))
+                    .collect(),
+                None => vec![Pattern::Wildcard],
+            }
+        };
+        [column(0), column(1)]
+    }
+
+    /// Every combination of the two columns' constructors has to be matched
+    /// by some arm, in strict mode every arm has to match one no earlier arm does
+    fn check_case2_coverage(&self, arms: &[Case2Arm], span: &Span) -> Result<(), InferenceError> {
+        let [first, second] = self.case2_columns(arms);
+        let combinations: Vec<_> = first
+            .iter()
+            .flat_map(|c1| second.iter().map(move |c2| [c1.clone(), c2.clone()]))
+            .collect();
+        let covers = |arm: &Case2Arm, [c1, c2]: &[Pattern; 2]| {
+            let [p1, p2] = &arm.patterns;
+            (*p1 == Pattern::Wildcard || p1 == c1) && (*p2 == Pattern::Wildcard || p2 == c2)
+        };
+        let missing: Vec<_> = combinations
+            .iter()
+            .filter(|combination| !arms.iter().any(|arm| covers(arm, combination)))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            return Err(InferenceError {
+                error: InferenceErrorMessage::MissingCombinations { missing },
+                span: span.to_owned(),
+            });
+        }
+        if self.tables.strict.reject_unreachable_arms {
+            for (i, arm) in arms.iter().enumerate() {
+                let reachable = combinations.iter().any(|combination| {
+                    covers(arm, combination) && !arms[..i].iter().any(|a| covers(a, combination))
+                });
+                if !reachable {
+                    return Err(InferenceError {
+                        error: InferenceErrorMessage::UnreachableArm,
+                        span: arm.span.to_owned(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `stack_algebra::chain`, the unification recorded into the
+    /// explanation and the stats like every other
+    fn chain(
+        &self,
+        ot1: OpType,
+        ot2: OpType,
+        origin: impl FnOnce() -> Origin,
+    ) -> Result<OpType, InferenceErrorMessage> {
+        self.chain_subst(ot1, ot2, origin).map(|(ot, _)| ot)
+    }
+
+    /// `chain`, also returning what the chain substituted
+    fn chain_subst(
+        &self,
+        ot1: OpType,
+        ot2: OpType,
+        origin: impl FnOnce() -> Origin,
+    ) -> Result<(OpType, Subst), InferenceErrorMessage> {
+        chain_with(ot1, ot2, |beta, gamma| self.unify(beta, gamma, origin))
+    }
+
+    fn infer_op(&self, env: &Env, op: &Op) -> Result<OpType, InferenceError> {
+        self.infer_marked_op(env, op)
+            .map(|inferred| inferred.optype)
+    }
+
+    /// The type of the op, and whether it never returns: a `panic`, or a
+    /// case every arm of which never returns
+    fn infer_marked_op(&self, env: &Env, op: &Op) -> Result<Inferred, InferenceError> {
+        let returns = |optype| Inferred {
+            optype,
+            diverges: false,
+        };
+        match op {
+            Op::Name { value, .. }
+                if value == "panic"
+                    && env.lookup(value).map(|b| b.provenance) == Some(Provenance::Prelude) =>
+            {
+                let optype = self.infer_op_type(env, op)?;
+                Ok(Inferred {
+                    optype,
+                    diverges: true,
+                })
+            }
+            Op::Case {
+                head_arm,
+                arms,
+                span,
+            } => self.infer_case(env, head_arm, arms, span),
+            Op::Case2 { arms, span } => self.infer_case2(env, arms, span),
+            op => self.infer_op_type(env, op).map(returns),
+        }
+    }
+
+    fn infer_op_type(&self, env: &Env, op: &Op) -> Result<OpType, InferenceError> {
+        match op {
+            Op::Literal { value, span } => self.lit_optype(value, span),
+            Op::Name { value: name, span } => match special_form(env, op) {
+                Some((SpecialForm::Macro, _)) => self.infer_macro(name, span),
+                // the literal index is taken in `infer`, alone it has none
+                Some((SpecialForm::Indexed, _)) => Err(InferenceError {
+                    error: InferenceErrorMessage::IndexNotLiteral {
+                        op: name.to_owned(),
+                    },
+                    span: span.to_owned(),
+                }),
+                _ => env
+                    .lookup(name)
+                    .map(|binding| {
+                        let optype = self.instantiate(&binding.scheme);
+                        if binding.provenance == Provenance::User {
+                            self.record_call(name, span, &optype);
+                        }
+                        optype
+                    })
+                    .ok_or_else(|| InferenceErrorMessage::UnknownOp {
+                        name: name.to_owned(),
+                    })
+                    .map_err(|error| InferenceError {
+                        error,
+                        span: span.to_owned(),
+                    }),
+            },
+            Op::Quote { value, .. } => {
+                let quoted_optype = self.infer(env, value)?;
+                Ok(OpType {
+                    pre: vec![],
+                    post: vec![Type::Op(quoted_optype)],
+                })
+            }
+            Op::Case { .. } | Op::Case2 { .. } => {
+                unreachable!("cases are typed with whether they return")
+            }
+            // a module not desugared, typed as it would be once it is
+            Op::List { items, span } => self.infer(env, &lists::expand(items.clone(), span)),
+        }
+    }
+
+    fn infer_case(
+        &self,
+        env: &Env,
+        head_arm: &CaseArm,
+        arms: &[CaseArm],
+        span: &Span,
+    ) -> Result<Inferred, InferenceError> {
+        let matched_data_type = self
+            .lookup_constructor_data_def(&head_arm.constr)
+            .ok_or_else(|| InferenceError {
+                error: InferenceErrorMessage::UnknownConstructor {
+                    name: head_arm.constr.to_owned(),
+                },
+                span: head_arm.span.to_owned(),
+            })?;
+
+        let matched_data_type_constr_names: HashSet<_> = matched_data_type.constrs.keys().collect();
+        let covered_constr_names: HashSet<_> = once(&head_arm.constr)
+            .chain(arms.iter().map(|arm| &arm.constr))
+            .collect();
+
+        if matched_data_type_constr_names != covered_constr_names {
+            return Err(InferenceError {
+                error: InferenceErrorMessage::NotAllConstructorsCovered,
+                span: span.to_owned(),
+            });
+        }
+        if self.tables.strict.reject_unreachable_arms {
+            let mut seen = HashSet::from([&head_arm.constr]);
+            if let Some(arm) = arms.iter().find(|arm| !seen.insert(&arm.constr)) {
+                return Err(InferenceError {
+                    error: InferenceErrorMessage::UnreachableArm,
+                    span: arm.span.to_owned(),
+                });
+            }
+        }
+
+        let mut head = self.infer_case_arm(env, head_arm)?;
+        for arm in arms {
+            let arm_inferred = self.infer_case_arm(env, arm)?;
+            let origin = || Origin {
+                kind: ObligationKind::CaseArms,
+                span: arm.span.clone(),
+                subject: arm.constr.to_owned(),
+            };
+            head = self
+                .join(head, arm_inferred, &arm.constr, origin)
+                .map_err(|error| InferenceError {
+                    error,
+                    span: arm.span.to_owned(),
+                })?;
+        }
+
+        Ok(head)
+    }
+
+    /// Whether values of the type are not made of constructors: `Int` and
+    /// the types of extern literals
+    fn is_builtin_type(&self, t: &Type) -> bool {
+        match t {
+            Type::Mono(name) => {
+                name == "Int" || self.tables.extern_literals.values().any(|ty| ty == t)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the case takes apart a quote the ops before it leave. Checked
+    /// before the case is inferred, since the arms of a case on a quote
+    /// name whatever constructors the author hoped a quote has.
+    fn check_quote_scrutinees(acc: &OpType, op: &Op) -> Result<(), InferenceError> {
+        let matched = match op {
+            Op::Case { .. } => vec![true],
+            // a column of wildcards leaves the value as it is
+            Op::Case2 { arms, .. } => (0..2)
+                .map(|i| {
+                    arms.iter()
+                        .any(|arm| matches!(arm.patterns[i], Pattern::Constr(_)))
+                })
+                .collect(),
+            _ => return Ok(()),
+        };
+        for (depth, (scrutinee, matched)) in zip(&acc.post, matched).enumerate() {
+            if let (Type::Op(quote), true) = (scrutinee, matched) {
+                return Err(InferenceError {
+                    error: InferenceErrorMessage::CannotMatchOnQuote {
+                        quote: quote.clone(),
+                        depth,
+                    },
+                    span: op.get_span().clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Unifies the values a case matches with the values the ops before it
+    /// leave, ahead of chaining the whole case, so that a mismatch is
+    /// reported as one of the whole scrutinee and not of a part of it
+    fn check_scrutinees(&self, acc: &OpType, case: &OpType, op: &Op) -> Result<(), InferenceError> {
+        let matched = match op {
+            Op::Case2 { .. } => 2,
+            _ => 1,
+        };
+        let mut s = Subst::new();
+        for (depth, (scrutinee, matched)) in zip(&acc.post, &case.pre).take(matched).enumerate() {
+            let (scrutinee, matched) = (scrutinee.apply(&s), matched.apply(&s));
+            match Type::mgu(&scrutinee, &matched) {
+                Ok(s2) => s = compose(s, s2),
+                Err(InferenceErrorMessage::UnificationError { .. })
+                    if self.is_builtin_type(&scrutinee) =>
+                {
+                    return Err(InferenceError {
+                        error: InferenceErrorMessage::CannotMatchBuiltin {
+                            scrutinee_type: scrutinee,
+                            matched_type: matched,
+                        },
+                        span: op.get_span().clone(),
+                    })
+                }
+                Err(InferenceErrorMessage::UnificationError { .. }) => {
+                    return Err(InferenceError {
+                        error: InferenceErrorMessage::ScrutineeMismatch {
+                            scrutinee,
+                            matched,
+                            depth,
+                        },
+                        span: op.get_span().clone(),
+                    })
+                }
+                // left to the chain to report
+                Err(_) => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    /// The type of the ops, their names looked up in `env`
+    fn infer(&self, env: &Env, ops: &[Op]) -> Result<OpType, InferenceError> {
+        self.infer_from(env, OpType::empty(), ops)
+    }
+
+    /// The type of `start` followed by the ops
+    fn infer_from(&self, env: &Env, start: OpType, ops: &[Op]) -> Result<OpType, InferenceError> {
+        self.infer_marked(env, start, ops)
+            .map(|inferred| inferred.optype)
+    }
+
+    /// `infer_from`, also saying whether the ops never return. Typing stops
+    /// at the first op that does not.
+    fn infer_marked(
+        &self,
+        env: &Env,
+        start: OpType,
+        ops: &[Op],
+    ) -> Result<Inferred, InferenceError> {
+        // the ops as desugaring leaves them, so that checkpoints after a
+        // list literal are checked with the ops before it
+        let expanded;
+        let ops = if ops.iter().any(|op| matches!(op, Op::List { .. })) {
+            expanded = lists::expand_in(ops);
+            &expanded[..]
+        } else {
+            ops
+        };
+        let mut acc = start;
+        for (i, op) in ops.iter().enumerate() {
+            self.tick().map_err(|error| InferenceError {
+                error,
+                span: op.get_span().clone(),
+            })?;
+            // an index goes with the `pick` or `roll` after it
+            if index_of(op).is_some() && indexed_form(env, ops.get(i + 1)) {
+                continue;
+            }
+            Self::check_quote_scrutinees(&acc, op)?;
+            let index = i.checked_sub(1).and_then(|j| index_of(&ops[j]));
+            let Inferred {
+                optype: t,
+                diverges,
+            } = match (special_form(env, op), index) {
+                (Some((SpecialForm::Indexed, name)), Some(n)) => prelude_types::indexed(name, n)
+                    .map(|t| Inferred {
+                        optype: self.instantiate(&Scheme::generalize(t)),
+                        diverges: false,
+                    })
+                    .ok_or_else(|| InferenceError {
+                        error: InferenceErrorMessage::UnknownOp {
+                            name: name.to_owned(),
+                        },
+                        span: op.get_span().clone(),
+                    })?,
+                _ => match self.overload_set(env, op) {
+                    Some((name, set)) => self.resolve_overload(name, set, &acc, op.get_span())?,
+                    None => self.infer_marked_op(env, op)?,
+                },
+            };
+            if let Op::Case { .. } | Op::Case2 { .. } = op {
+                self.check_scrutinees(&acc, &t, op)?;
+            }
+            self.record_dyn_checks(&acc, &t, op);
+            // a quote given straight to an op that runs it, kept to blame
+            // the quote if the two do not chain
+            let handed = match (i.checked_sub(1).map(|j| &ops[j]), op) {
+                (Some(quote @ Op::Quote { .. }), Op::Name { value, .. }) => {
+                    match (acc.post.first(), t.pre.first()) {
+                        (Some(Type::Op(found)), Some(Type::Op(expected))) => {
+                            Some((quote, value, found.clone(), expected.clone()))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            let origin = || Origin {
+                kind: ObligationKind::Chain,
+                span: op.get_span().clone(),
+                subject: op.to_string(),
+            };
+            acc = self
+                .chain(acc, t, origin)
+                .map_err(|error| match special_form(env, op) {
+                    Some((form, name)) => Self::special_form_error(form, name, error),
+                    None => error,
+                })
+                .map_err(|error| match handed {
+                    Some((quote, name, found, expected)) => Self::quote_needs_inputs(
+                        quote, name, found, expected,
+                    )
+                    .unwrap_or(InferenceError {
+                        error,
+                        span: op.get_span().clone(),
+                    }),
+                    None => InferenceError {
+                        error,
+                        span: op.get_span().clone(),
+                    },
+                })?;
+            self.count_chain(op, &acc);
+            if let Some(max) = self.tables.max_type_size {
+                let size = acc.size();
+                if size > max {
+                    return Err(InferenceError {
+                        error: InferenceErrorMessage::TypeTooLarge {
+                            op: op_label(op),
+                            size,
+                            max,
+                        },
+                        span: op.get_span().clone(),
+                    });
+                }
+            }
+            let end = op.get_span().end;
+            let checkpoints = self.module.checkpoints_after(end);
+            // desugared ops share the span of their construct, the comment
+            // comes after the last of them
+            if !checkpoints.is_empty() && !ops[i + 1..].iter().any(|op| op.get_span().end == end) {
+                for checkpoint in checkpoints {
+                    self.check_checkpoint(&acc, checkpoint)?;
+                }
+            }
+            if diverges {
+                return Ok(Inferred {
+                    optype: acc,
+                    diverges,
+                });
+            }
+        }
+        Ok(Inferred {
+            optype: acc,
+            diverges: false,
+        })
+    }
+
+    /// Records the values the op takes that the ops before it leave with a
+    /// `?` where the op takes a more specific type, which all the values
+    /// together decide. Chaining the two is left to report a mismatch.
+    fn record_dyn_checks(&self, acc: &OpType, t: &OpType, op: &Op) {
+        let found = &acc.post[..acc.post.len().min(t.pre.len())];
+        if !found.iter().any(Type::has_dyn) {
+            return;
+        }
+        let opened = found
+            .iter()
+            .map(|t| open_dyn(t, &self.ctx.supply))
+            .collect();
+        let Ok(s) = Vec::mgu(&opened, &t.pre[..found.len()].to_vec()) else {
+            return;
+        };
+        let checks: Vec<_> = (found.iter().zip(&t.pre))
+            .enumerate()
+            .filter_map(|(depth, (found, expected))| {
+                let expected = expected.apply(&s);
+                narrows(found, &expected).then_some(DynCheck { depth, expected })
+            })
+            .collect();
+        if checks.is_empty() {
+            return;
+        }
+        // an op typed more than once keeps every check it was recorded with
+        let mut dyn_checks = self.ctx.dyn_checks.borrow_mut();
+        let recorded = dyn_checks.entry(op.get_span().key()).or_default();
+        for check in checks {
+            if !recorded.contains(&check) {
+                recorded.push(check);
+            }
+        }
+    }
+
+    /// The name the op uses and its definitions, if it uses a name that
+    /// the module defines more than once
+    fn overload_set<'o>(&self, env: &Env, op: &'o Op) -> Option<(&'o str, &OverloadSet)> {
+        let Op::Name { value, .. } = op else {
+            return None;
+        };
+        let set = self.tables.overloads.get(value)?;
+        let binding = env.lookup(value)?;
+        (set.is_overloaded() && binding.provenance == Provenance::User).then_some((value, set))
+    }
+
+    /// Whether the ops use an overloaded name anywhere
+    fn uses_overloads(&self, env: &Env, ops: &[Op]) -> bool {
+        ops.iter().any(|op| match op {
+            Op::Literal { .. } => false,
+            Op::Name { .. } => self.overload_set(env, op).is_some(),
+            Op::Quote { value, .. } => self.uses_overloads(env, value),
+            Op::Case { head_arm, arms, .. } => once(head_arm)
+                .chain(arms)
+                .any(|arm| self.uses_overloads(env, &arm.body)),
+            Op::Case2 { arms, .. } => arms.iter().any(|arm| self.uses_overloads(env, &arm.body)),
+            Op::List { items, .. } => items.iter().any(|item| self.uses_overloads(env, item)),
+        })
+    }
+
+    /// The type of the definition of `name` that fits the values `acc`
+    /// leaves, the only one whose annotation unifies with them. The value
+    /// at the set's resolving position has to be on the stack already.
+    fn resolve_overload(
+        &self,
+        name: &str,
+        set: &OverloadSet,
+        acc: &OpType,
+        span: &Span,
+    ) -> Result<Inferred, InferenceError> {
+        let fail = |error| InferenceError {
+            error,
+            span: span.clone(),
+        };
+        let candidates = set.candidates.clone();
+        let Some(position) = set.position() else {
+            let name = name.to_owned();
+            return Err(fail(InferenceErrorMessage::OverloadsIndistinct {
+                name,
+                candidates,
+            }));
+        };
+        if acc.post.len() <= position {
+            return Err(fail(InferenceErrorMessage::OverloadNeedsContext {
+                name: name.to_owned(),
+                position,
+                candidates,
+            }));
+        }
+        let fitting: Vec<_> = set
+            .candidates
+            .iter()
+            .filter_map(|candidate| {
+                let t = self.instantiate(&Scheme::generalize(candidate.ann.clone()));
+                let l = t.pre.len().min(acc.post.len());
+                let fits = Vec::mgu(&acc.post[..l].to_vec(), &t.pre[..l].to_vec()).is_ok();
+                fits.then_some((candidate, t))
+            })
+            .collect();
+        match fitting.as_slice() {
+            [(candidate, optype)] => {
+                self.record_call(&candidate.key, span, optype);
+                (self.ctx.resolved.borrow_mut()).insert(span.start, candidate.key.clone());
+                Ok(Inferred {
+                    optype: optype.clone(),
+                    diverges: false,
+                })
+            }
+            [] => Err(fail(InferenceErrorMessage::NoOverloadFits {
+                name: name.to_owned(),
+                found: acc.post[..=position].to_vec(),
+                candidates,
+            })),
+            _ => Err(fail(InferenceErrorMessage::AmbiguousOverload {
+                name: name.to_owned(),
+                candidates: fitting.into_iter().map(|(c, _)| c.clone()).collect(),
+            })),
+        }
+    }
+
+    /// Counts the chain of the op of a body, which made `acc`
+    fn count_chain(&self, op: &Op, acc: &OpType) {
+        let depth = trace::depth(acc);
+        {
+            let mut stats = self.ctx.stats.borrow_mut();
+            stats.chains += 1;
+            stats.max_type_depth = stats.max_type_depth.max(depth);
+        }
+        if self.ctx.tracer.is_none() {
+            return;
+        }
+        self.trace(TraceEvent::Chain {
+            op: &op_label(op),
+            pre: acc.pre.len(),
+            post: acc.post.len(),
+            depth,
+        });
+    }
+
+    /// The quote takes values below the ones `op` runs it with, so it
+    /// cannot be what `op` expects whatever the values are. `None` when the
+    /// quote and `op` disagree otherwise.
+    fn quote_needs_inputs(
+        quote: &Op,
+        op: &str,
+        found: OpType,
+        expected: OpType,
+    ) -> Option<InferenceError> {
+        let extra = found.pre.len().checked_sub(expected.pre.len())?;
+        if extra == 0 {
+            return None;
+        }
+        Some(InferenceError {
+            error: InferenceErrorMessage::QuoteNeedsInputs {
+                op: op.to_owned(),
+                quote: found,
+                expected,
+                extra,
+            },
+            span: quote.get_span().clone(),
+        })
+    }
+
+    /// Checks the type of the ops so far against a stack effect comment
+    /// after them, like a body against its annotation
+    fn check_checkpoint(
+        &self,
+        acc: &OpType,
+        checkpoint: &Checkpoint,
+    ) -> Result<(), InferenceError> {
+        let (asserted, back) =
+            self.instantiate_with_mapping(&Scheme::generalize(checkpoint.effect.clone()));
+        self.record_names(&back);
+        let origin = || Origin {
+            kind: ObligationKind::Checkpoint,
+            span: checkpoint.span.clone(),
+            subject: "stack effect comment".to_owned(),
+        };
+        // the body's type is not changed by the comment, nor are its calls
+        let recorder = self.ctx.calls.borrow_mut().take();
+        let matched = self.match_ann(acc.clone(), &asserted, origin);
+        *self.ctx.calls.borrow_mut() = recorder;
+        matched
+            .map(|_| ())
+            .map_err(|error| match error {
+                InferenceErrorMessage::AnnInfConflict { inf, ann } => {
+                    InferenceErrorMessage::CheckpointMismatch {
+                        inferred: inf,
+                        asserted: ann.apply(&back),
+                    }
+                }
+                error => error,
+            })
+            .map_err(|error| InferenceError {
+                error,
+                span: checkpoint.span.clone(),
+            })
+    }
+
+    /// The type of the expansion of the macro op `name`, its ops typed as
+    /// the unconfigured prelude types them
+    fn infer_macro(&self, name: &str, span: &Span) -> Result<OpType, InferenceError> {
+        let mut acc = OpType::empty();
+        for op_name in prelude_types::expand(name).unwrap_or_default() {
+            let t = prelude_types::get(&op_name).map(|t| self.instantiate(&Scheme::generalize(t)));
+            let origin = || Origin {
+                kind: ObligationKind::Chain,
+                span: span.clone(),
+                subject: name.to_owned(),
+            };
+            acc = t
+                .ok_or(InferenceErrorMessage::UnknownOp { name: op_name })
+                .and_then(|t| self.chain(acc, t, origin))
+                .map_err(|error| InferenceError {
+                    error,
+                    span: span.clone(),
+                })?;
+        }
+        Ok(acc)
+    }
+
+    /// What chaining the special form `op` failing with `error` is reported as
+    fn special_form_error(
+        form: SpecialForm,
+        op: &str,
+        error: InferenceErrorMessage,
+    ) -> InferenceErrorMessage {
+        match (form, error) {
+            (SpecialForm::Fix, InferenceErrorMessage::OccursCheck { name, ty, .. }) => {
+                InferenceErrorMessage::InfiniteFix {
+                    op: op.to_owned(),
+                    name,
+                    ty,
+                }
+            }
+            (SpecialForm::Fix | SpecialForm::Macro | SpecialForm::Indexed, error) => error,
         }
-        Ok(acc)
     }
 }
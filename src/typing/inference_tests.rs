@@ -1,12 +1,20 @@
-use crate::syntax::parse;
+use crate::analysis::unreachable::check_unreachable;
+use crate::syntax::ast::{Literal, Span};
+use crate::syntax::literals::LiteralTable;
+use crate::syntax::{parse, parse_optype, parse_with_literals, LexingError};
+use crate::typing::cancel::*;
 use crate::typing::inference::*;
+use crate::typing::prelude_types::UnknownPreludeOp;
+use crate::typing::report::{OpOutcome, SkipReason};
+use crate::typing::strict::StrictOptions;
+use crate::typing::types::Type;
 
 #[test]
 fn sanity() {
     let input = "
         define [a, a] nocadd [a]: 1 2 3.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_ok());
 }
 
@@ -16,7 +24,7 @@ fn subst_int_ann_vs_inf() {
         data Alpha:.
         define [a, a] nocadd [a]:.
         define [Alpha, Alpha] intadd [Alpha]: nocadd.";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_ok());
 }
 
@@ -26,7 +34,7 @@ fn ann_ss_pre() {
         data Alpha: alpha.
         define [a, a] nocadd [a]:.
         define [a, Alpha] intadd [Alpha]: nocadd.";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_err());
 }
 
@@ -37,7 +45,7 @@ fn ann_ss_post() {
         define [a, a] nocadd [a]:.
         define [Alpha, a] intadd [Alpha]: nocadd.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_err());
 }
 
@@ -48,7 +56,7 @@ fn ann_ss_double() {
         define [a, a] nocadd [a]:.
         define [a, a] intadd [Alpha]: nocadd.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_err());
 }
 
@@ -60,7 +68,7 @@ fn ann_ss_trans_stack() {
         define [a] nocdup [a, a]:.
         define [Alpha] intadd [Beta, Beta]: nocadd.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_err());
 }
 
@@ -71,7 +79,7 @@ fn ann_ss_trans_stack_ok() {
         data Gamma:. define [a, b, c] nocfoobar [c, b, a]:.
         define [Alpha, Beta, Gamma] intadd [Gamma, Beta, Apha]: nocfoobar.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_err());
 }
 
@@ -81,7 +89,7 @@ fn uf_triple_add_ok() {
         define [a, a] nocadd [a]:.
         define [a, a, a] tripleadd [a]: nocadd nocadd.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_ok());
 }
 
@@ -91,7 +99,7 @@ fn uf_triple_add_ok_spec() {
         data Alpha:. data Beta:. define [a, a] nocadd [a]:.
         define [Alpha, Alpha, Alpha] tripleadd [Alpha]: nocadd nocadd.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_ok());
 }
 
@@ -101,7 +109,7 @@ fn uf_triple_add_ok_err1() {
         data Alpha:. data Beta:. define [a, a] nocadd [a]:.
         define [Alpha, Alpha, Alpha] tripleadd [a]: nocadd nocadd.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_err());
 }
 
@@ -111,7 +119,7 @@ fn uf_triple_add_ok_err2() {
         data Alpha:. data Beta:. define [a, a] nocadd [a]:.
         define [Alpha, Alpha, Beta] tripleadd [Alpha]: nocadd nocadd.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_err());
 }
 
@@ -122,7 +130,7 @@ fn of_triple1() {
         define [a] nocdup [a, a]:.
         define [a] tripledup [a, a, a]: nocdup nocdup.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_ok());
 }
 
@@ -134,7 +142,7 @@ fn op_triple2() {
         define [a] nocdup [a, a]:.
         define [a] tripledup [a, a, a]: nocdup nocdup nocadd nocdup.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_ok());
 }
 
@@ -144,7 +152,7 @@ fn int_add_uf_chain() {
         data Alpha: alpha. define [a, a] nocadd [a]:.
         define [Alpha] alphainc [Alpha]: alpha nocadd.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_ok());
 }
 
@@ -155,7 +163,7 @@ fn int_add_datapoly() {
         data Either a b: [a] left, [b] right.
         define [] inteithertest [Either Alpha b]: alpha left.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_ok());
 }
 
@@ -166,7 +174,7 @@ fn int_add_datapoly_err() {
         data Either a b: [a] left, [b] right.
         define [] inteithertest [Either a Alpha]: alpha left.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     assert!(Inference::new(&module).typecheck().is_err());
 }
 
@@ -177,7 +185,7 @@ fn case_maybe_nat() {
         data Maybe a: nothing, [a] just.
         define [Maybe Nat] incnatmaybe [Maybe Nat]: case { just { suc just }, nothing { nothing } }.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -195,7 +203,7 @@ fn case_maybe_nat_add_nop_expansion_err() {
         define [Maybe Nat, Nat] addnatmaybe [Maybe Nat, Nat]:
             case { just { nocswap nocdup nocdup nocrot nocnatadd just }, nothing { nothing } }.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -213,7 +221,7 @@ fn case_maybe_nat_add_nop_expansion() {
         define [Maybe Nat, Nat] addnatmaybe [Maybe Nat, Nat]:
             case { just { nocswap nocdup nocrot nocnatadd just }, nothing { nothing } }.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -231,7 +239,7 @@ fn case_maybe_nat_add_nop_expansion_arms_swapped() {
         define [Maybe Nat, Nat] addnatmaybe [Maybe Nat, Nat]:
             case { nothing { nothing }, just { nocswap nocdup nocrot nocnatadd just } }.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -250,7 +258,7 @@ fn case_maybe_nat_add_nop_expansion_wrong_constr() {
         define [Maybe Nat, Nat] addnatmaybe [Maybe Nat, Nat]:
             case { just { nocswap nocdup nocrot nocnatadd just }, left { nothing } }.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -264,7 +272,7 @@ fn case_totality() {
         define [Expr] foobar [Int]:
             case { int {1}, float {2}, string {3}, add {4}}.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -278,7 +286,7 @@ fn case_totality_err() {
         define [Expr] foobar [Int]:
             case { int {1}, float {2}, add {4}}.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -289,7 +297,7 @@ fn nop_unification_test() {
     let input = "
         define [] nop []:. define [a] foobar [a]: nop.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -300,7 +308,7 @@ fn nop_unification_test_err() {
     let input = "
         define [a] nocnop [a]:. define [] nop []: nocnop.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -312,7 +320,7 @@ fn nop_unification_test_err_poly() {
         define [a] nocnonop [a, a]:.
         define [] nop []: nocnonop.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -326,12 +334,54 @@ fn nop_unification_test_err_mono() {
         define [Alpha] nocnonop [Beta]:.
         define [] nop []: nocnonop.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
 }
 
+#[test]
+fn empty_bodies_only_satisfy_annotations_leaving_the_stack() {
+    let defs = "data Nat: zero, [Nat] suc.";
+    let check = |def: &str| {
+        let module = parse(&format!("{} {}", defs, def)).unwrap();
+        Inference::new(&module).typecheck()
+    };
+    for def in [
+        "define [a] id [a]:.",
+        "define [a, b] id2 [a, b]:.",
+        "define [Nat, a] keep [Nat, a]:.",
+        "define [] nothing []:.",
+    ] {
+        assert!(check(def).is_ok(), "{}", def);
+    }
+    for (def, ann) in [
+        ("define [Int] f [Nat]:.", "[Int][Nat]"),
+        ("define [] f [Int]:.", "[][Int]"),
+        ("define [a] f []:.", "[a][]"),
+        ("define [a, b] f [b, a]:.", "[a, b][b, a]"),
+    ] {
+        match check(def).unwrap_err().error {
+            InferenceErrorMessage::EmptyBodyCannotSatisfy { ann: found } => {
+                assert_eq!(found.to_string(), ann)
+            }
+            error => panic!("{}: {:?}", def, error),
+        }
+    }
+    let message = check("define [Int] f [Nat]:.")
+        .unwrap_err()
+        .error
+        .to_string();
+    assert!(message.starts_with(
+        "the body is empty, which leaves the stack as it is, but the annotation [Int][Nat] \
+         changes it\n"
+    ));
+    assert!(message.contains("`#[unchecked]`"));
+    // declared and unchecked ops are trusted
+    assert!(check("define [Int] f [Nat].").is_ok());
+    assert!(check("#[unchecked]\ndefine [Int] f [Nat]:.").is_ok());
+}
+
 #[test]
 fn nop_unification_test_complex() {
     let input = "
@@ -340,7 +390,7 @@ fn nop_unification_test_complex() {
         define [Maybe Nat] natnop [Maybe Nat]:.
         define [Maybe Nat, Nat] foobar [Maybe Nat, Nat]: natnop.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -351,7 +401,7 @@ fn op_quote_test() {
     let input = "
         define [] foobar [[][Int, Int, Int]]: (1 2 3).
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -364,7 +414,7 @@ fn prelude_br_test() {
         data Bar: bar.
         define [Bar, Foo, Foo, Foo] foobar [Foo, Foo, Foo, Bar]: br-3.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -377,7 +427,7 @@ fn prelude_br_test_err() {
         data Bar: bar.
         define [Bar, Foo, Foo, Foo] foobar [Foo, Foo, Foo, Bar]: br-2.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -390,7 +440,7 @@ fn prelude_dg_test() {
         data Bar: bar.
         define [Foo, Foo, Foo, Bar] foobar [Bar, Foo, Foo, Foo]: dg-3.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -403,7 +453,7 @@ fn prelude_dg_test_err() {
         data Bar: bar.
         define [Foo, Foo, Foo, Bar] foobar [Bar, Foo, Foo, Foo]: dg-2.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -415,7 +465,7 @@ fn special_comp_test_dup() {
         data Foo: foo.
         define [] foo [[][Foo, Foo]]: (foo) (dup) comp-0-1-1-2.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -427,7 +477,7 @@ fn special_comp_test_dup1() {
         data Foo: foo.
         define [] foo [[a][Foo, a, a]]: (dup) (foo) comp-1-2-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -439,7 +489,7 @@ fn todo_nop_postfix_ann_vs_inf_bad() {
         data Foo: foo.
         define [] foo [Foo]: dup pop foo.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -451,7 +501,7 @@ fn special_comp_test_err() {
         data Foo: foo.
         define [] foo [[][Foo, Foo]]: (dup) (foo) comp-1-2-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -464,7 +514,7 @@ fn special_comp_test_quote() {
         data Bar: bar.
         define [] foo [[][Bar, Foo]]: foo quote bar quote comp-0-1-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -477,7 +527,7 @@ fn special_comp_test_quote_err() {
         data Bar: bar.
         define [] foo [[][Foo, Bar]]: foo quote bar quote comp-0-1-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -491,7 +541,7 @@ fn special_comp_test2() {
         define [a] id [a]:.
         define [] foo [[Foo][Bar, Foo]]: (id) (bar) comp-1-1-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -505,7 +555,7 @@ fn special_comp_test3() {
         define [a] id [a]:.
         define [] foo [[][Bar]]: (bar) (id) comp-0-1-1-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -516,7 +566,7 @@ fn special_comp_of() {
     let input = "
         define [] foo [[a][a, a, a]]: (dup) (dup) comp-1-2-1-2.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -530,7 +580,7 @@ fn special_comp_of2() {
         define [a] id [a]:.
         define [] foo [[a][Bar, a, a]]: (dup) (bar) comp-1-2-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -543,7 +593,7 @@ fn special_comp_nei() {
         define [a] id [a]:.
         define [[a][a]] foo [[a][a]]: (id) comp-1-1-1-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -556,7 +606,7 @@ fn special_comp_nop1() {
         define [a] id [a]:.
         define [[a][a]] foobar [[a][Foo, a]]: (foo) comp-1-1-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -569,7 +619,7 @@ fn special_comp_nop2() {
         define [a] id [a]:.
         define [] foobar []: foo (id) comp-0-1-1-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -582,7 +632,7 @@ fn special_comp_nop3() {
         define [a] id [a]:.
         define [] foo []: (id) foobar comp-1-1-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -594,7 +644,7 @@ fn special_exec() {
         data Foo: foo.
         define [] foo [Foo, Foo, Foo, Foo]: foo (dup dup dup) exec-1-4.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -606,7 +656,7 @@ fn special_exec_nei() {
         data Foo: foo.
         define [Foo] foo [Foo, Foo, Foo, Foo]: (dup dup dup) exec-1-4.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -618,7 +668,7 @@ fn special_exec_nop() {
         data Foo: foo.
         define [] foo [Foo, Foo, Foo, Foo]: foo exec-0-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
@@ -630,7 +680,7 @@ fn prelude_name_lookup_false_positive() {
         define [a] foo-1 [a]:.
         define [b] foo [b]: foo-1.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -661,7 +711,7 @@ fn nat_list_sum_test() {
                  cons { br-2 dg-1 dup br-2 map br-2 exec-1-1 cons },
                }.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -692,7 +742,7 @@ fn nat_list_sum_test2() {
                  cons { dg-2 dup dg-2 br-1 exec-1-1 br-2 map dg-1 cons },
                }.
         ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -706,7 +756,7 @@ fn nested_case() {
             define [Maybe Nat] nestedcase [Maybe Nat]:
               case { just { case { zero { nothing }, suc { suc just } } }, nothing { nothing } }.
             ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -718,7 +768,7 @@ fn mut_rec_data() {
             data Foo: [Bar] foo.
             data Bar: [Foo] bar.
             ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_ok());
@@ -731,8 +781,1886 @@ fn occurs_check() {
             define [a] occurscheck [List a]:
                 dup cons.
             ";
-    let module = parse(&input).unwrap();
+    let module = parse(input).unwrap();
     let inferred = Inference::new(&module).typecheck();
     println!("{:?}", inferred);
     assert!(inferred.is_err());
 }
+
+fn slow_module(fast_ops: &str) -> String {
+    format!(
+        "define [Int, Int] nocadd [Int]:.\n{}\ndefine [Int] slow [Int]:{}.",
+        fast_ops,
+        " 1 nocadd".repeat(50000)
+    )
+}
+
+#[test]
+// `op_budget` is the other field, it only exists with the `os` feature
+#[cfg_attr(not(feature = "os"), allow(clippy::needless_update))]
+fn immediate_cancel() {
+    let module = parse(&slow_module("define [a] mydup [a, a]: dup.")).unwrap();
+    let inference = Inference::new(&module);
+    let token = CancelToken::new();
+    token.cancel();
+    assert_eq!(
+        inference.typecheck_with(&token).unwrap(),
+        Completion::Cancelled
+    );
+    let opts = CheckOptions {
+        cancel: Some(token),
+        ..CheckOptions::default()
+    };
+    let report = inference.report_with(&opts);
+    assert!(!report.is_complete());
+    let outcomes: Vec<_> = report.ops.iter().map(|op| &op.outcome).collect();
+    assert!(matches!(
+        outcomes[..],
+        [
+            OpOutcome::Unchecked,
+            OpOutcome::Cancelled,
+            OpOutcome::Cancelled
+        ]
+    ));
+}
+
+#[test]
+fn uncancelled_token() {
+    let token = CancelToken::new();
+    let module = parse("define [a] mydup [a, a]: dup.").unwrap();
+    let inference = Inference::new(&module);
+    assert_eq!(
+        inference.typecheck_with(&token).unwrap(),
+        Completion::Finished
+    );
+    let module = parse("define [a] mydup [a, a]: pop.").unwrap();
+    let inference = Inference::new(&module);
+    assert!(inference.typecheck_with(&token).is_err());
+}
+
+#[test]
+fn cancel_while_checking() {
+    let module = parse(&slow_module("")).unwrap();
+    let inference = Inference::new(&module);
+    let token = CancelToken::new();
+    let canceller = {
+        let token = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            token.cancel();
+        })
+    };
+    assert_eq!(
+        inference.typecheck_with(&token).unwrap(),
+        Completion::Cancelled
+    );
+    canceller.join().unwrap();
+    // the token is not kept around after the call
+    assert!(inference.typecheck().is_ok());
+}
+
+#[cfg(feature = "os")]
+#[test]
+fn op_budget() {
+    let module = parse(&slow_module("define [a] mydup [a, a]: dup.")).unwrap();
+    let inference = Inference::new(&module);
+    let opts = CheckOptions {
+        op_budget: Some(std::time::Duration::from_millis(20)),
+        ..CheckOptions::default()
+    };
+    let report = inference.report_with(&opts);
+    let outcomes: Vec<_> = report.ops.iter().map(|op| &op.outcome).collect();
+    assert!(matches!(
+        outcomes[..],
+        [
+            OpOutcome::Unchecked,
+            OpOutcome::Inferred(_),
+            OpOutcome::TimedOut
+        ]
+    ));
+    assert!(report.is_ok());
+    assert!(!report.is_complete());
+}
+
+fn strict_error(input: &str, strict: StrictOptions) -> Option<InferenceErrorMessage> {
+    let module = parse(input).unwrap();
+    let inference = Inference::new(&module);
+    assert!(
+        inference.typecheck().is_ok(),
+        "accepted without strict mode"
+    );
+    let inference = Inference::new(&module).with_strict(strict);
+    inference.typecheck().err().map(|err| err.error)
+}
+
+#[test]
+fn strict_pinned_vars() {
+    let input = "define [Int] intdup [Int, Int]: dup.";
+    let strict = |max| StrictOptions {
+        max_pinned_vars: Some(max),
+        ..StrictOptions::default()
+    };
+    assert!(matches!(
+        strict_error(input, strict(0)),
+        Some(InferenceErrorMessage::AnnotationTooSpecific { pinned: 1, .. })
+    ));
+    assert!(strict_error(input, strict(1)).is_none());
+    // merging two variables into one also takes one away
+    let input = "define [a, a] same [a, a]:.";
+    assert!(strict_error(input, strict(1)).is_none());
+    let input = "define [a, a] same [a, a]: br-1.";
+    assert!(matches!(
+        strict_error(input, strict(0)),
+        Some(InferenceErrorMessage::AnnotationTooSpecific { pinned: 1, .. })
+    ));
+    assert!(strict_error("define [a] mydup [a, a]: dup.", strict(0)).is_none());
+}
+
+#[test]
+fn strict_unchecked() {
+    let strict = StrictOptions {
+        reject_unchecked: true,
+        ..StrictOptions::default()
+    };
+    assert!(matches!(
+        strict_error("define [a] nocfoo [Int]:.", strict.clone()),
+        Some(InferenceErrorMessage::UncheckedOp { name }) if name == "nocfoo"
+    ));
+    assert!(matches!(
+        strict_error("#[unchecked] define [a] foo [Int]:.", strict),
+        Some(InferenceErrorMessage::UncheckedOp { name }) if name == "foo"
+    ));
+}
+
+#[test]
+fn unchecked_attribute() {
+    let input = "
+        #[unchecked]
+        define [a, a] add [a]: 1 2 3.
+        define [Int, Int] twice-add [Int]: add.
+        ";
+    let module = parse(input).unwrap();
+    let report = Inference::new(&module).report();
+    let outcomes: Vec<_> = report.ops.iter().map(|op| &op.outcome).collect();
+    assert!(matches!(
+        outcomes[..],
+        [OpOutcome::Unchecked, OpOutcome::Inferred(_)]
+    ));
+    let module = parse(&input.replace("#[unchecked]", "#[inline]")).unwrap();
+    assert!(Inference::new(&module).typecheck().is_err());
+}
+
+#[test]
+fn strict_prelude_shadowing() {
+    let strict = StrictOptions {
+        reject_prelude_shadowing: true,
+        ..StrictOptions::default()
+    };
+    assert!(matches!(
+        strict_error("define [a] dup [a, a]: dup.", strict.clone()),
+        Some(InferenceErrorMessage::ShadowsPrelude { name }) if name == "dup"
+    ));
+    assert!(matches!(
+        strict_error("data Foo: pop.", strict.clone()),
+        Some(InferenceErrorMessage::ShadowsPrelude { name }) if name == "pop"
+    ));
+    assert!(matches!(
+        strict_error("define [a, b] br-1 [b, a]: br-1.", strict),
+        Some(InferenceErrorMessage::ShadowsPrelude { .. })
+    ));
+}
+
+#[test]
+fn strict_deprecated() {
+    let strict = StrictOptions {
+        reject_deprecated: true,
+        ..StrictOptions::default()
+    };
+    let defs = "
+        #[deprecated(\"use count\")]
+        define [] size [Int]: 1.
+        data Res: #[deprecated] oops, fine.
+        ";
+    let deprecated = |body: &str| {
+        let input = format!("{}\n{}", defs, body);
+        match strict_error(&input, strict.clone()) {
+            Some(InferenceErrorMessage::Deprecated { name, message }) => {
+                Some((name, message.is_some()))
+            }
+            Some(err) => panic!("{:?}", err),
+            None => None,
+        }
+    };
+    let error = |name: &str, message| Some((name.to_owned(), message));
+    assert_eq!(
+        deprecated("define [] f [Int, Int]: size size."),
+        error("size", true)
+    );
+    assert_eq!(deprecated("define [] f [Res]: oops."), error("oops", false));
+    assert_eq!(
+        deprecated("define [Res] f [Int]: case { fine { 1 }, oops { 0 } }."),
+        error("oops", false)
+    );
+    assert_eq!(
+        deprecated("define [Res, Res] f [Int]: case2 { oops _ { pop 0 }, _ _ { pop pop 1 } }."),
+        error("oops", false)
+    );
+    assert_eq!(deprecated("const c [Res]: oops."), error("oops", false));
+    assert_eq!(
+        deprecated("#[allow(deprecated)]\ndefine [] f [Res]: oops."),
+        None
+    );
+    assert_eq!(deprecated("define [] f [Res]: fine."), None);
+}
+
+#[test]
+fn strict_output_only_vars() {
+    let strict = StrictOptions {
+        reject_output_only_vars: true,
+        ..StrictOptions::default()
+    };
+    let input = "define [Int] anything [Int, a]: pop anything.";
+    let module = parse(input).unwrap();
+    let err = Inference::new(&module)
+        .with_strict(strict.clone())
+        .typecheck()
+        .unwrap_err();
+    assert!(matches!(
+        &err.error,
+        InferenceErrorMessage::OutputOnlyVar { op, var } if op == "anything" && var == "a"
+    ));
+    assert_eq!(&input[err.span.start..err.span.end], "a");
+    // the variable comes from a field or a quote, or is in a taken value
+    let input = "
+        data List a: empty, [a, List a] cons.
+        define [] nil [List a]: empty.
+        define [] nils [List a, List b]: nil nil.
+        define [] swapper [[a, b][b, a]]: (br-1).
+        define [List a] first [a]: first.
+        ";
+    assert!(strict_error(input, strict).is_none());
+}
+
+#[test]
+fn strict_unreachable_arms() {
+    let strict = StrictOptions {
+        reject_unreachable_arms: true,
+        ..StrictOptions::default()
+    };
+    let input = "
+        data Bool: true, false.
+        define [Bool] not [Bool]: case { true { false }, false { true }, true { true } }.
+        ";
+    assert!(matches!(
+        strict_error(input, strict.clone()),
+        Some(InferenceErrorMessage::UnreachableArm)
+    ));
+    let input = "
+        data Bool: true, false.
+        define [Bool, Bool] and [Bool]:
+            case2 { _ _ { pop pop false }, true true { true } }.
+        ";
+    assert!(matches!(
+        strict_error(input, strict.clone()),
+        Some(InferenceErrorMessage::UnreachableArm)
+    ));
+    let input = "
+        data Bool: true, false.
+        define [Bool, Bool] and [Bool]:
+            case2 { true true { true }, _ _ { pop pop false } }.
+        ";
+    assert!(strict_error(input, strict).is_none());
+}
+
+#[test]
+fn strict_report() {
+    let module = parse("define [a] nocfoo [Int]:. define [a] mydup [a, a]: dup.").unwrap();
+    let report = Inference::new(&module)
+        .with_strict(StrictOptions::all())
+        .report();
+    let outcomes: Vec<_> = report.ops.iter().map(|op| &op.outcome).collect();
+    assert!(matches!(
+        outcomes[..],
+        [OpOutcome::Failed(_), OpOutcome::Inferred(_)]
+    ));
+}
+
+#[test]
+fn reachable_checking_skips_what_the_entries_never_call() {
+    let input = "
+        data Bool: true, false.
+        define [Bool] not [Bool]: case { true { false }, false { true } }.
+        define [Bool] bad [Int]: 1 not.
+        define [] quoted [[Bool][Bool]]: (not).
+        define [] main [Bool]: true quoted exec-1-1 helper.
+        define [Bool] helper [Bool]:.
+        define [Bool] unused [Bool]: not.
+        const c [Int]: true.
+        ";
+    let module = parse(input).unwrap();
+    let inference = Inference::new(&module);
+    let outcomes = |report: &crate::typing::report::TypecheckReport| -> Vec<_> {
+        report
+            .ops
+            .iter()
+            .map(|op| {
+                let outcome = match &op.outcome {
+                    OpOutcome::Inferred(_) => "inferred",
+                    OpOutcome::Failed(_) => "failed",
+                    OpOutcome::Skipped(SkipReason::Unreachable) => "skipped",
+                    outcome => panic!("{:?}", outcome),
+                };
+                (op.name.clone(), outcome)
+            })
+            .collect()
+    };
+    let full = inference.report();
+    let reachable = inference.typecheck_reachable(&["main"]);
+    assert_eq!(
+        outcomes(&full),
+        [
+            ("not".to_owned(), "inferred"),
+            ("bad".to_owned(), "failed"),
+            ("quoted".to_owned(), "inferred"),
+            ("main".to_owned(), "inferred"),
+            ("helper".to_owned(), "inferred"),
+            ("unused".to_owned(), "inferred"),
+        ]
+    );
+    // `not` is only reached through the quote in `quoted`
+    assert_eq!(
+        outcomes(&reachable),
+        [
+            ("not".to_owned(), "inferred"),
+            ("bad".to_owned(), "skipped"),
+            ("quoted".to_owned(), "inferred"),
+            ("main".to_owned(), "inferred"),
+            ("helper".to_owned(), "inferred"),
+            ("unused".to_owned(), "skipped"),
+        ]
+    );
+    assert!(!full.is_ok());
+    // the constant is checked either way
+    assert_eq!(reachable.errors.len(), 1);
+    assert!(reachable.is_complete());
+    let reachable = inference.typecheck_reachable(&["helper", "bad"]);
+    assert_eq!(
+        outcomes(&reachable)
+            .into_iter()
+            .filter(|(_, outcome)| *outcome != "skipped")
+            .collect::<Vec<_>>(),
+        [
+            ("not".to_owned(), "inferred"),
+            ("bad".to_owned(), "failed"),
+            ("helper".to_owned(), "inferred"),
+        ]
+    );
+}
+
+#[test]
+fn empty_annotations_need_bodies_without_net_effect() {
+    for ann in ["[] f []", "f --"] {
+        let check = |body: &str| {
+            let input = format!("define {}: {}.", ann, body);
+            Inference::new(&parse(&input).unwrap()).typecheck()
+        };
+        // a body that never returns leaves whatever it is annotated to
+        for body in [
+            "",
+            "1 pop",
+            "(2) pop",
+            "1 2 br-1 pop pop",
+            "panic",
+            "panic pop",
+        ] {
+            assert!(check(body).is_ok(), "{}: {}", ann, body);
+        }
+        // nothing below the annotation's empty stacks to leave or take
+        for body in [
+            "1",
+            "1 2 pop",
+            "(1)",
+            "pop panic",
+            "pop",
+            "br-1 br-1",
+            "dup pop",
+        ] {
+            assert!(check(body).is_err(), "{}: {}", ann, body);
+        }
+    }
+}
+
+fn search_names(source: &str, query: &str) -> Vec<String> {
+    let module = parse(source).unwrap();
+    let query = parse_optype(query).unwrap();
+    Inference::new(&module)
+        .search(&query)
+        .into_iter()
+        .map(|hit| hit.name)
+        .collect()
+}
+
+#[test]
+fn search_prelude() {
+    assert_eq!(search_names("", "Int -> Int, Int"), vec!["dup"]);
+    assert_eq!(search_names("", "Int ->"), vec!["pop"]);
+    assert_eq!(search_names("", "Int -> [][Int]"), vec!["quote"]);
+    // exact matches come before the ones only matching augmented or with
+    // variables merged
+    assert_eq!(
+        search_names("", "a, b -> b, a"),
+        vec!["br-1", "dg-1", "br-2", "dg-2"]
+    );
+    // the macro ops are searched like the others
+    assert_eq!(
+        search_names("", "[][a] -> a"),
+        vec!["exec-0-1", "dip-1-0-1"]
+    );
+}
+
+#[test]
+fn search_user_ops_and_constructors() {
+    let source = "
+        data Maybe a: nothing, [a] just.
+        data Bool: true, false.
+        define [Maybe a] is-just [Bool]: case { just { pop true }, nothing { false } }.
+        define [Int] is-zero [Bool]: pop true.
+        define [a] dup [a]:.
+        ";
+    // the user defined `dup` is shadowed by the prelude one, which matches
+    // with the query augmented
+    assert_eq!(
+        search_names(source, "-> Bool"),
+        vec!["false", "true", "dup"]
+    );
+    assert_eq!(search_names(source, "Int -> Bool"), vec!["is-zero"]);
+    assert_eq!(search_names(source, "Maybe Int -> Bool"), vec!["is-just"]);
+    assert_eq!(search_names(source, "Int -> Maybe Int"), vec!["just"]);
+}
+
+#[test]
+fn search_prefers_ops_the_query_is_an_instance_of() {
+    let source = "
+        define [Int] ints [Int]:.
+        #[unchecked]
+        define [b] any [c]:.
+        ";
+    // both need one variable set, but only `any` leaves the query's alone
+    let names = search_names(source, "a -> a");
+    assert_eq!(names[..2], ["any", "ints"]);
+    let module = parse(source).unwrap();
+    let hits = Inference::new(&module).search(&parse_optype("a -> a").unwrap());
+    let hit = |name: &str| hits.iter().find(|hit| hit.name == name).unwrap();
+    assert!(hit("any").general && !hit("ints").general);
+    assert_eq!(hit("any").instantiation, hit("ints").instantiation);
+}
+
+#[test]
+fn the_annotations_inputs_are_checked_op_by_op() {
+    let defs = "
+        data Bool: true, false.
+        data Nat: zero, [Nat] suc.
+        ";
+    let blamed = |def: &str| {
+        let input = format!("{}{}", defs, def);
+        let module = parse(&input).unwrap();
+        let err = Inference::new(&module).typecheck().unwrap_err();
+        let message = err.message();
+        let first_line = message.lines().next().unwrap().to_owned();
+        (first_line, input[err.span.start..err.span.end].to_owned())
+    };
+    let blame = |message: &str, at: &str| (message.to_owned(), at.to_owned());
+    // the first op taking a value the annotation gives as another type
+    assert_eq!(
+        blamed("define [Bool] f [Int]: suc pop 1."),
+        blame("cannot unify Bool with Nat", "suc")
+    );
+    assert_eq!(
+        blamed("define [Nat, Bool] f [Nat]: pop suc."),
+        blame("cannot unify Bool with Nat", "suc")
+    );
+    // a variable of the annotation is not pinned by the body
+    assert_eq!(
+        blamed("define [a] f [Nat]: suc."),
+        blame("cannot unify a with Nat", "suc")
+    );
+    // the values left are still compared at the end
+    let def = "define [Nat] f [Bool]: suc.";
+    assert_eq!(
+        blamed(def),
+        blame(
+            "the inferred type [Nat][Nat] does not match the annotation [Nat][Bool]",
+            def
+        )
+    );
+}
+
+#[test]
+fn annotations_cannot_split_variables_the_body_merges() {
+    let source = |ann: &str| {
+        format!(
+            "define [x, x] both [x]: pop.
+            define {}: both.",
+            ann
+        )
+    };
+    for ann in ["[a, a] f [a]", "[Int, Int] f [Int]"] {
+        let module = parse(&source(ann)).unwrap();
+        assert!(Inference::new(&module).typecheck().is_ok(), "{}", ann);
+    }
+    // `both` is given the annotation's values, which it cannot merge
+    for ann in ["[a, b] f [a]", "[a, b] f [b]", "[a, Int] f [a]"] {
+        assert!(
+            matches!(
+                first_error(&source(ann)),
+                InferenceErrorMessage::UnificationError { .. }
+            ),
+            "{}",
+            ann
+        );
+    }
+}
+
+#[test]
+fn search_is_stable_and_leaves_inference_alone() {
+    let source = "
+        data Maybe a: nothing, [a] just.
+        define [a] f [Maybe a]: just.
+        define [a, b] g [b, a]: br-1.
+        define [a] h [a, a]: dup.
+        ";
+    let module = parse(source).unwrap();
+    let inf = Inference::new(&module);
+    let query = parse_optype("a, b -> b, a").unwrap();
+    let first: Vec<_> = inf
+        .search(&query)
+        .into_iter()
+        .map(|hit| (hit.name, hit.op_type, hit.augmentation, hit.instantiation))
+        .collect();
+    assert_eq!(first[0].0, "g");
+    for _ in 0..3 {
+        let again: Vec<_> = inf
+            .search(&query)
+            .into_iter()
+            .map(|hit| (hit.name, hit.op_type, hit.augmentation, hit.instantiation))
+            .collect();
+        assert_eq!(again, first);
+    }
+    let types = |inf: &Inference| {
+        ["f", "h"].map(|name| format!("{}", inf.infer_body(name).unwrap().unwrap()))
+    };
+    assert_eq!(types(&inf), types(&Inference::new(&module)));
+}
+
+#[test]
+fn removed_prelude_ops_are_unknown() {
+    let input = "
+        define [a] f [a, a]: dup.
+        define [a] g []: pop.
+        ";
+    let module = parse(input).unwrap();
+    let report = Inference::new(&module).without_prelude_op("dup").report();
+    let outcomes: Vec<_> = report.ops.iter().map(|op| &op.outcome).collect();
+    assert!(matches!(
+        outcomes[..],
+        [OpOutcome::Failed(InferenceError { error: InferenceErrorMessage::UnknownOp { name }, span }), OpOutcome::Inferred(_)]
+            if name == "dup" && &input[span.start..span.end] == "dup"
+    ));
+    assert_eq!(report.prelude_used, vec!["pop"]);
+
+    let inf = Inference::new(&module).without_prelude();
+    assert!(matches!(
+        inf.typecheck(),
+        Err(InferenceError {
+            error: InferenceErrorMessage::UnknownOp { .. },
+            ..
+        })
+    ));
+    assert!(inf.report().prelude_used.is_empty());
+    // a removed op frees its name for the module
+    let module = parse("define [a] dup [a, a]: noc. define [a] noc [a, a]:.").unwrap();
+    assert!(Inference::new(&module).report().is_ok());
+}
+
+#[test]
+fn overridden_prelude_ops() {
+    let module = parse("define [a] f [a, a]: dup. define [Int] g [Int, Int]: dup.").unwrap();
+    let int_dup = parse_optype("Int -> Int, Int").unwrap();
+    let inf = Inference::new(&module)
+        .without_prelude()
+        .override_prelude_op("dup", int_dup.clone())
+        .unwrap();
+    let report = inf.report();
+    assert_eq!(report.prelude_used, vec!["dup"]);
+    let outcomes: Vec<_> = report.ops.iter().map(|op| &op.outcome).collect();
+    assert!(matches!(
+        outcomes[..],
+        [OpOutcome::Failed(_), OpOutcome::Inferred(_)]
+    ));
+    assert_eq!(
+        Inference::new(&module)
+            .override_prelude_op("dip", int_dup)
+            .err(),
+        Some(UnknownPreludeOp {
+            name: "dip".to_owned()
+        })
+    );
+}
+
+#[test]
+fn incompatible_overrides_fail_at_the_uses() {
+    let input = "
+        data Bool: true, false.
+        define [] f [Bool, Bool]: true dup.
+        define [] g [[][Bool, Bool]]: (true dup).
+        ";
+    let module = parse(input).unwrap();
+    let inf = Inference::new(&module)
+        .override_prelude_op("dup", parse_optype("Int -> Int, Int").unwrap())
+        .unwrap();
+    let report = inf.report();
+    assert_eq!(report.ops.len(), 2);
+    for op in report.ops {
+        let OpOutcome::Failed(err) = op.outcome else {
+            panic!("{} checks", op.name);
+        };
+        assert!(matches!(
+            err.error,
+            InferenceErrorMessage::UnificationError { .. }
+        ));
+        assert_eq!(&input[err.span.start..err.span.end], "dup");
+    }
+}
+
+fn first_error(input: &str) -> InferenceErrorMessage {
+    let module = parse(input).unwrap();
+    Inference::new(&module).typecheck().unwrap_err().error
+}
+
+#[test]
+fn pass_through_arm_joins_an_arm_that_keeps_the_type() {
+    // the arm `false` replaces the value below the `Bool`, the arm `true`
+    // leaves it, which only agrees when the value was an `Int` already
+    let input = "
+        data Bool: true, false.
+        define [Bool, Int] f [Int]: case { true { }, false { pop 1 } }.
+        define [Bool, Int] g [Int]: case { false { pop 1 }, true { } }.
+        ";
+    let module = parse(input).unwrap();
+    let report = Inference::new(&module).report();
+    let inferred: Vec<_> = report
+        .ops
+        .iter()
+        .map(|op| match &op.outcome {
+            OpOutcome::Inferred(inferred) => inferred.canonical(),
+            outcome => panic!("{} has {:?}", op.name, outcome),
+        })
+        .collect();
+    assert_eq!(
+        inferred,
+        [
+            parse_optype("Bool, Int -> Int").unwrap().canonical(),
+            parse_optype("Bool, Int -> Int").unwrap().canonical(),
+        ]
+    );
+}
+
+#[test]
+fn deeper_arm_changing_a_passed_value_is_rejected() {
+    let input = "
+        data Bool: true, false.
+        define [Bool, Bool] f [Int]: case { true { }, false { case { true { 1 }, false { 2 } } } }.
+        ";
+    let error = first_error(input);
+    let InferenceErrorMessage::ArmsDisagreeOnStackDepth {
+        arm,
+        arm_is_deeper,
+        slots,
+    } = &error
+    else {
+        panic!("{:?}", error);
+    };
+    assert_eq!(arm, "false");
+    assert!(arm_is_deeper);
+    assert_eq!(slots.len(), 1);
+    assert_eq!((slots[0].0, slots[0].1), (1, 0));
+    assert_eq!(
+        error.to_string(),
+        "the arm `false` changes values below the ones the arms before it use, \
+         which they leave in place\n  Bool at pre 1 is left as Int at post 0"
+    );
+}
+
+#[test]
+fn deeper_arms_before_a_pass_through_arm_are_rejected() {
+    let input = "
+        data Bool: true, false.
+        define [Bool, Bool] f [Int]: case { false { case { true { 1 }, false { 2 } } }, true { } }.
+        ";
+    assert!(matches!(
+        first_error(input),
+        InferenceErrorMessage::ArmsDisagreeOnStackDepth { arm, arm_is_deeper: false, .. }
+            if arm == "true"
+    ));
+}
+
+#[test]
+fn swapping_below_a_pass_through_arm_needs_equal_types() {
+    let swap = |ann: &str| {
+        format!(
+            "
+            data Bool: true, false.
+            data Nat: zero, [Nat] suc.
+            define [a, b] nocswap [b, a]:.
+            define {}: case {{ true {{ }}, false {{ nocswap }} }}.
+            ",
+            ann
+        )
+    };
+    let module = parse(&swap("[Bool, Nat, Nat] f [Nat, Nat]")).unwrap();
+    assert!(Inference::new(&module).typecheck().is_ok());
+    // the arms disagree on the values the annotation gives the case
+    assert!(matches!(
+        first_error(&swap("[Bool, Bool, Nat] f [Nat, Bool]")),
+        InferenceErrorMessage::UnificationError { .. }
+    ));
+}
+
+#[test]
+fn case2_arms_disagreeing_on_depth_are_rejected() {
+    let input = "
+        data Bool: true, false.
+        define [a, Bool] nocunder [a, Int]:.
+        define [Bool, Bool, Bool] f [Bool, Int]: case2 { true _ { }, false _ { nocunder } }.
+        ";
+    let error = first_error(input);
+    let InferenceErrorMessage::ArmsDisagreeOnStackDepth {
+        arm,
+        arm_is_deeper: true,
+        slots,
+    } = &error
+    else {
+        panic!("{:?}", error);
+    };
+    assert_eq!(arm, "false _");
+    assert_eq!((slots[0].0, slots[0].1), (2, 1));
+}
+
+#[test]
+fn scrutinee_mismatch_names_the_whole_types() {
+    let scrutinee = |input: &str| match first_error(input) {
+        InferenceErrorMessage::ScrutineeMismatch {
+            scrutinee,
+            matched,
+            depth,
+        } => (scrutinee.to_string(), matched.to_string(), depth),
+        error => panic!("{:?}", error),
+    };
+    let defs = "
+        data Nat: zero, [Nat] suc.
+        data Bool: true, false.
+        data Maybe a: nothing, [a] just.
+        ";
+    assert_eq!(
+        scrutinee(&format!(
+            "{} define [] f [Nat]: true case {{ zero {{ zero }}, suc {{ }} }}.",
+            defs
+        )),
+        ("Bool".to_owned(), "Nat".to_owned(), 0)
+    );
+    // the arms only pin the argument, which used to be the whole message
+    assert_eq!(
+        scrutinee(&format!(
+            "{} define [] f [Nat]: true just case {{ just {{ suc }}, nothing {{ zero }} }}.",
+            defs
+        )),
+        ("Maybe Bool".to_owned(), "Maybe Nat".to_owned(), 0)
+    );
+    assert_eq!(
+        scrutinee(&format!(
+            "{} define [] f [Bool]: true zero case2 {{ zero zero {{ true }}, _ _ {{ pop pop false }} }}.",
+            defs
+        )),
+        ("Bool".to_owned(), "Nat".to_owned(), 1)
+    );
+}
+
+#[test]
+fn matching_a_builtin_value_is_its_own_error() {
+    let defs = "
+        data Nat: zero, [Nat] suc.
+        data Maybe a: nothing, [a] just.
+        ";
+    let cannot_match = |error: InferenceErrorMessage| match error {
+        InferenceErrorMessage::CannotMatchBuiltin {
+            scrutinee_type,
+            matched_type,
+        } => (scrutinee_type.to_string(), matched_type.to_string()),
+        error => panic!("{:?}", error),
+    };
+    assert_eq!(
+        cannot_match(first_error(&format!(
+            "{} define [] f [Nat]: 3 case {{ zero {{ zero }}, suc {{ }} }}.",
+            defs
+        ))),
+        ("Int".to_owned(), "Nat".to_owned())
+    );
+    assert_eq!(
+        cannot_match(first_error(&format!(
+            "{} define [] f [Nat]: zero 3 case2 {{ zero zero {{ zero }}, _ _ {{ pop pop zero }} }}.",
+            defs
+        ))),
+        ("Int".to_owned(), "Nat".to_owned())
+    );
+    // the types of extern literals have no constructors either
+    fn seconds(text: &str, _: Span) -> Result<Literal, LexingError> {
+        Ok(Literal::Extern {
+            tag: "duration".to_owned(),
+            repr: text.to_owned(),
+        })
+    }
+    let literals = LiteralTable::new().with_suffix("s", seconds);
+    let source = format!(
+        "{} define [] f [Nat]: 5s case {{ zero {{ zero }}, suc {{ }} }}.",
+        defs
+    );
+    let module = parse_with_literals(&source, &literals).unwrap();
+    let error = Inference::new(&module)
+        .with_extern_literal("duration", Type::Mono("Duration".to_owned()))
+        .typecheck()
+        .unwrap_err();
+    assert_eq!(
+        cannot_match(error.error),
+        ("Duration".to_owned(), "Nat".to_owned())
+    );
+    // a variable could still be a data type, and data types mismatch as usual
+    assert!(matches!(
+        first_error(&format!(
+            "{} define [] f [Nat]: nothing case {{ zero {{ zero }}, suc {{ }} }}.",
+            defs
+        )),
+        InferenceErrorMessage::ScrutineeMismatch { .. }
+    ));
+}
+
+#[test]
+fn matching_a_quote_is_its_own_error() {
+    let defs = "
+        data Nat: zero, [Nat] suc.
+        define [] nop [[][]]: ().
+        ";
+    let on_quote = |body: &str| match first_error(&format!("{} {}", defs, body)) {
+        InferenceErrorMessage::CannotMatchOnQuote { quote, depth } => (quote.to_string(), depth),
+        error => panic!("{:?}", error),
+    };
+    // the arms name constructors no data type has
+    assert_eq!(
+        on_quote("define [] f [Nat]: (1) case { empty { zero }, full { pop zero } }."),
+        ("[][Int]".to_owned(), 0)
+    );
+    assert_eq!(
+        on_quote("define [] f [Nat]: nop case { zero { zero }, suc { } }."),
+        ("[][]".to_owned(), 0)
+    );
+    assert_eq!(
+        on_quote("define [] f [Nat]: nop zero case2 { zero zero { zero }, _ _ { pop pop zero } }."),
+        ("[][]".to_owned(), 1)
+    );
+    // a column of wildcards does not take its value apart
+    let input = format!(
+        "{} define [] f [Nat, [][]]: nop zero case2 {{ zero _ {{ zero }}, suc _ {{ }} }}.",
+        defs
+    );
+    assert!(Inference::new(&parse(&input).unwrap()).typecheck().is_ok());
+}
+
+#[test]
+fn literal_indices_type_pick_and_roll() {
+    let infer = |body: &str| {
+        let module = parse(&format!("define [] f []: {}.", body)).unwrap();
+        let inference = Inference::new(&module);
+        match inference.infer_ops(&module.op_defs["f"].body) {
+            Ok(optype) => optype.canonical().to_string(),
+            Err(err) => err.code().to_owned(),
+        }
+    };
+    let picks = [
+        "[a][a, a]",
+        "[a, b][b, a, b]",
+        "[a, b, c][c, a, b, c]",
+        "[a, b, c, d][d, a, b, c, d]",
+        "[a, b, c, d, e][e, a, b, c, d, e]",
+    ];
+    let rolls = [
+        "[a][a]",
+        "[a, b][b, a]",
+        "[a, b, c][c, a, b]",
+        "[a, b, c, d][d, a, b, c]",
+        "[a, b, c, d, e][e, a, b, c, d]",
+    ];
+    for (n, (pick, roll)) in picks.iter().zip(rolls).enumerate() {
+        assert_eq!(infer(&format!("{} pick", n)), *pick);
+        assert_eq!(infer(&format!("{} roll", n)), roll);
+    }
+    // the index is taken, other literals are pushed
+    assert_eq!(infer("1 2 3 2 pick"), "[][Int, Int, Int, Int]");
+    assert_eq!(infer("(0 pick) exec-1-2"), "[a][a, a]");
+
+    let input = "
+        data Nat: zero, [Nat] suc.
+        define [Int, Nat] under [Nat, Int, Nat]: 1 pick.
+        define [Int, Nat] over [Nat, Int]: 1 roll.
+        ";
+    assert!(Inference::new(&parse(input).unwrap()).typecheck().is_ok());
+}
+
+#[test]
+fn pick_and_roll_need_a_literal_index() {
+    let not_literal = |body: &str| {
+        let input = format!("define [a, b] f [a, b, a]: {}.", body);
+        match first_error(&input) {
+            InferenceErrorMessage::IndexNotLiteral { op } => op,
+            error => panic!("{:?}", error),
+        }
+    };
+    assert_eq!(not_literal("pick"), "pick");
+    assert_eq!(not_literal("0 dup pick"), "pick");
+    assert_eq!(not_literal("-1 pick"), "pick");
+    assert_eq!(not_literal("1 (roll) exec-0-0"), "roll");
+    assert_eq!(
+        first_error("define [a, b] f [a, b, a]: pick.").to_string(),
+        "`pick` requires a literal index\n\
+         what it leaves depends on the index, which only a non-negative `Int` literal \
+         written right before it gives"
+    );
+    // the prelude's op wins over the module's one of the same name
+    let input = "define [Int, Int] roll [Int]: pop. define [Int, Int] f [Int]: roll.";
+    assert!(matches!(
+        first_error(input),
+        InferenceErrorMessage::IndexNotLiteral { .. }
+    ));
+}
+
+#[test]
+fn polymorphic_scrutinees_take_the_matched_type() {
+    let input = "
+        data Nat: zero, [Nat] suc.
+        data Maybe a: nothing, [a] just.
+        define [] f [Nat]: nothing case { just { suc }, nothing { zero } }.
+        ";
+    let module = parse(input).unwrap();
+    assert!(Inference::new(&module).typecheck().is_ok());
+}
+
+#[test]
+fn quote_types_stay_linked_to_the_outer_stacks() {
+    // `a` is both the quote's input and the value below it, instantiating
+    // the parts of `apply` separately would let `g` through
+    let input = "
+        data Nat: zero, [Nat] suc.
+        data Bool: true, false.
+        define [Nat] is-zero [Bool]: case { zero { true }, suc { pop false } }.
+        define [[a -> b], a] apply [b]: exec-1-1.
+        define [[[a -> b] -> c], [a -> b]] call-with [c]: exec-1-1.
+        define [] f [Bool]: zero (is-zero) apply.
+        define [] h [Bool]: (is-zero) (zero dg-1 exec-1-1) call-with.
+        ";
+    let module = parse(input).unwrap();
+    let inference = Inference::new(&module);
+    assert!(inference.typecheck().is_ok());
+    let inferred = inference.infer_body("apply").unwrap().unwrap();
+    let ann = parse_optype("[a -> b], a -> b").unwrap();
+    assert_eq!(inferred.canonical(), ann.canonical());
+    let input = format!("{} define [] g [Bool]: true (is-zero) apply.", input);
+    assert!(matches!(
+        first_error(&input),
+        InferenceErrorMessage::UnificationError { .. }
+    ));
+}
+
+#[test]
+fn unifying_quote_types_binds_the_shared_variables() {
+    use crate::typing::unify::{apply_subst_optype, unify_optypes};
+    let general = parse_optype("[a -> b], a -> b, [b -> a]").unwrap();
+    let concrete = parse_optype("[Int -> c], d -> Bool, e").unwrap();
+    let s = unify_optypes(&general, &concrete).unwrap();
+    assert_eq!(
+        apply_subst_optype(&general, &s).to_string(),
+        "[[Int][Bool], Int][Bool, [Bool][Int]]"
+    );
+    let clash = parse_optype("[Int -> c], Bool -> c").unwrap();
+    assert!(unify_optypes(&parse_optype("[a -> b], a -> b").unwrap(), &clash).is_err());
+}
+
+#[test]
+fn the_env_says_what_names_refer_to() {
+    use crate::typing::env::Provenance;
+    let input = "
+        data Bool: true, false.
+        define [a] dup [a]:.
+        define [] true [Bool]: false.
+        define [] twice []:.
+        ";
+    let module = parse(input).unwrap();
+    let inference = Inference::new(&module).with_extern_op("twice", parse_optype("->").unwrap());
+    let env = inference.env();
+    let provenance = |name| env.lookup(name).map(|binding| binding.provenance);
+    assert_eq!(provenance("dup"), Some(Provenance::Prelude));
+    assert_eq!(provenance("true"), Some(Provenance::Constructor));
+    assert_eq!(provenance("twice"), Some(Provenance::Extern));
+    assert_eq!(
+        env.shadowing_note("true").unwrap(),
+        "`true` here refers to the constructor, shadowing the op defined in the module"
+    );
+    let inference = inference.without_prelude_op("dup");
+    assert_eq!(
+        inference.env().lookup("dup").unwrap().provenance,
+        Provenance::User
+    );
+}
+
+#[test]
+fn macro_ops_are_typed_by_their_expansion() {
+    use crate::typing::prelude_types::{expand, get, names};
+    for name in names(2).into_iter().filter(|name| expand(name).is_some()) {
+        let input = format!("define [] probe []: {}.", name);
+        let module = parse(&input).unwrap();
+        let inferred = Inference::new(&module)
+            .infer_body("probe")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            inferred.canonical(),
+            get(&name).unwrap().canonical(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn macro_ops_over_concrete_types() {
+    let input = "
+        data Nat: zero, [Nat] suc.
+        data Bool: true, false.
+        define [Nat] is-zero [Bool]: case { zero { true }, suc { pop false } }.
+        define [Nat] checked [Nat, Bool]: (is-zero) keep-1-1.
+        define [Nat] both [Nat, Bool]: (is-zero) (suc) bi-1-1.
+        define [Nat] all [Nat, Bool, Nat]: (suc suc) (is-zero) (suc) tri-1-1-1.
+        define [Bool, Nat] under [Bool, Bool]: (is-zero) dip-1-1-1.
+        ";
+    let module = parse(input).unwrap();
+    assert!(Inference::new(&module).typecheck().is_ok());
+    let input = "
+        data Nat: zero, [Nat] suc.
+        data Bool: true, false.
+        define [Nat] is-zero [Bool]: case { zero { true }, suc { pop false } }.
+        define [] under [Nat, Bool]: true zero (is-zero) dip-1-1-1.
+        ";
+    let module = parse(input).unwrap();
+    let err = Inference::new(&module).typecheck().unwrap_err();
+    // reported at the macro op as written, not at its expansion
+    assert_eq!(&input[err.span.start..err.span.end], "dip-1-1-1");
+}
+
+#[test]
+fn constants() {
+    let input = "
+        data Nat: zero, [Nat] suc.
+        data Pair a b: [a, b] pair.
+        const two [Nat]: zero suc suc.
+        const twos [Pair Nat Nat]: two dup pair.
+        const max-depth [Int]: 32.
+        define [] main [Int, Pair Nat Nat]: twos max-depth.
+        ";
+    let module = parse(input).unwrap();
+    assert!(Inference::new(&module).typecheck().is_ok());
+    let input = "
+        data Nat: zero, [Nat] suc.
+        const two [Int]: zero suc suc.
+        ";
+    let module = parse(input).unwrap();
+    let err = Inference::new(&module).typecheck().unwrap_err();
+    assert!(matches!(
+        err.error,
+        InferenceErrorMessage::AnnInfConflict { .. }
+    ));
+}
+
+#[test]
+fn impure_constants() {
+    let impure = |input: &str| {
+        let module = parse(input).unwrap();
+        let err = Inference::new(&module).typecheck().unwrap_err();
+        match err.error {
+            InferenceErrorMessage::ImpureConstant { name, op } => {
+                (name, op, input[err.span.start..err.span.end].to_owned())
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+    };
+    let (name, op, at) = impure("define [] one [Int]: 1.\nconst two [Int]: one one pop.");
+    assert_eq!((&name[..], &op[..], &at[..]), ("two", "one", "one"));
+    let (_, op, _) = impure("const one [Int]: (1) exec-0-1.");
+    assert_eq!(op, "exec-0-1");
+    // a quote is a value, what it would run is not run
+    let input = "define [] one [Int]: 1.\nconst later [[-> Int]]: (one).";
+    let module = parse(input).unwrap();
+    assert!(Inference::new(&module).typecheck().is_ok());
+}
+
+#[test]
+fn stack_effect_comments() {
+    let check = |input: &str| {
+        let module = parse(input).unwrap();
+        Inference::new(&module).typecheck()
+    };
+    let input = "
+        data Nat: zero, [Nat] suc.
+        define [Nat] twice [Nat, Nat]:
+            // ( Nat -- ) is not checked before the first op
+            dup // ( Nat -- Nat, Nat )
+            pop // ( a -- a )
+            (zero // ( -- Nat )
+             suc) // ( Bool -- [-> Nat], Bool )
+            pop dup.
+        define [Nat] pred [Nat]: case { zero { zero }, suc { dup pop // ( Nat -- Nat )
+        } }.
+        ";
+    assert!(check(input).is_ok());
+    let input = "
+        data Nat: zero, [Nat] suc.
+        define [] two [Nat]: zero suc // ( -- Int )
+            suc.
+        ";
+    let err = check(input).unwrap_err();
+    assert_eq!(&input[err.span.start..err.span.end], "// ( -- Int )");
+    match err.error {
+        InferenceErrorMessage::CheckpointMismatch { inferred, asserted } => {
+            assert_eq!(inferred.canonical().to_string(), "[][Nat]");
+            assert_eq!(asserted.canonical().to_string(), "[][Int]");
+        }
+        error => panic!("unexpected error: {}", error),
+    }
+    let input = "data Nat: zero.\ndefine [] nat [[-> Nat]]: (zero // ( -- Int )\n).";
+    let err = check(input).unwrap_err();
+    assert_eq!(&input[err.span.start..err.span.end], "// ( -- Int )");
+    // like an annotation, the comment may not be more general than the ops
+    let input = "define [] one [Int]: 1 // ( -- a )\n.";
+    assert!(matches!(
+        check(input).unwrap_err().error,
+        InferenceErrorMessage::CheckpointMismatch { .. }
+    ));
+}
+
+/// The inferred type or error code of every op, for each order of the
+/// definitions given
+fn in_every_order(defs: &[&str]) -> Vec<Vec<(String, String)>> {
+    let mut orders = vec![];
+    for rotation in 0..defs.len() {
+        let mut order = defs.to_vec();
+        order.rotate_left(rotation);
+        orders.push(order.clone());
+        order.reverse();
+        orders.push(order);
+    }
+    orders
+        .into_iter()
+        .map(|order| {
+            let module = parse(&order.join("\n")).unwrap();
+            let report = Inference::new(&module).report();
+            let mut outcomes: Vec<_> = report
+                .ops
+                .iter()
+                .map(|op| {
+                    let outcome = match &op.outcome {
+                        OpOutcome::Inferred(t) => t.canonical().to_string(),
+                        OpOutcome::Failed(err) => err.code().to_owned(),
+                        _ => "other".to_owned(),
+                    };
+                    (op.name.clone(), outcome)
+                })
+                .collect();
+            outcomes.sort();
+            outcomes
+        })
+        .collect()
+}
+
+#[test]
+fn definitions_in_any_order() {
+    let defs = [
+        // an op before the data type it constructs and destructs
+        "define [] some-tree [Tree Nat]: zero leaf zero leaf node.",
+        "define [Tree Nat] left [Tree Nat]: case { leaf { pop zero leaf }, node { pop } }.",
+        // a constructor field of a data type defined later
+        "data Tree a: [a] leaf, [Tree a, Tree a] node.",
+        "data Nat: zero, [Nat] suc.",
+        // an annotation and a constant mentioning later types
+        "const one [Nat]: zero suc.",
+        "define [] ones [Pair Nat Nat]: one one pair.",
+        "data Pair a b: [a, b] pair.",
+    ];
+    let outcomes = in_every_order(&defs);
+    assert!(outcomes.iter().all(|o| o == &outcomes[0]));
+    assert_eq!(
+        outcomes[0],
+        [
+            ("left".to_owned(), "[Tree Nat][Tree Nat]".to_owned()),
+            ("ones".to_owned(), "[][Pair Nat Nat]".to_owned()),
+            ("some-tree".to_owned(), "[][Tree Nat]".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn duplicate_constructors_resolve_to_their_first_definition() {
+    let input = "
+        data Nat: zero, [Nat] suc.
+        data Other: [Other, Other] suc.
+        define [] two [Nat]: zero suc suc.
+        ";
+    let module = parse(input).unwrap();
+    let report = Inference::new(&module).report();
+    let err = report.all_errors().next().unwrap();
+    assert!(matches!(
+        &err.error,
+        InferenceErrorMessage::DuplicateConstructor { name } if name == "suc"
+    ));
+    assert!(input[err.span.start..].starts_with("data Other"));
+    assert!(matches!(report.ops[0].outcome, OpOutcome::Inferred(_)));
+}
+
+/// Every sentence of up to `len` of the units
+fn sentences(units: &[&str], len: usize) -> Vec<String> {
+    let mut all: Vec<String> = vec![];
+    let mut last = vec![String::new()];
+    for _ in 0..len {
+        last = last
+            .iter()
+            .flat_map(|s| {
+                units
+                    .iter()
+                    .map(move |u| format!("{} {}", s, u).trim_start().to_owned())
+            })
+            .collect();
+        all.extend(last.iter().cloned());
+    }
+    all
+}
+
+/// The chained type of a sentence must say how deep a stack the
+/// interpreter needs to run it and where each value passed through ends
+/// up. The initial values are Ints numbered by the depth they start at,
+/// topmost 0, so a result that is a variable of `pre[j]` must be `j`.
+#[test]
+fn chained_types_predict_the_interpreter() {
+    use crate::evaluation::evaluator::Evaluator;
+    use crate::evaluation::types::{EvaluatorError, RuntimeErrorMessage, Value};
+    use crate::typing::types::{OpType, Type};
+
+    let units = [
+        "dup",
+        "pop",
+        "br-1",
+        "br-2",
+        "dg-2",
+        "(br-1) exec-2-2",
+        "pair",
+        "case { pair { br-1 } }",
+        "1",
+    ];
+    let mut checked = 0;
+    for ops in sentences(&units, 3) {
+        let source = format!("data Pair a b: [a, b] pair. define [] probe []: {}.", ops);
+        let module = parse(&source).unwrap();
+        let Ok(optype) = Inference::new(&module).infer_body("probe").unwrap() else {
+            continue;
+        };
+        let OpType { pre, post } = optype.canonical();
+        if !pre.iter().all(|t| matches!(t, Type::Poly(_))) {
+            continue;
+        }
+        let run = |depth: usize| {
+            let mut evaluator = Evaluator::new(&module);
+            evaluator.stack = (0..depth as i32).rev().map(Value::Int).collect();
+            evaluator.eval_op_def("probe").map(|_| evaluator.stack)
+        };
+        if !pre.is_empty() {
+            assert!(
+                matches!(
+                    run(pre.len() - 1),
+                    Err(EvaluatorError::Runtime(ref err))
+                        if matches!(err.error, RuntimeErrorMessage::StackUnderflow)
+                ),
+                "`{}` runs on fewer values than [{:?}]",
+                ops,
+                pre
+            );
+        }
+        let mut stack = run(pre.len()).unwrap_or_else(|e| panic!("`{}` failed: {:?}", ops, e));
+        stack.reverse();
+        assert_eq!(stack.len(), post.len(), "`{}` left {:?}", ops, stack);
+        for (value, t) in stack.iter().zip(post.iter()) {
+            if let Some(j) = pre.iter().position(|p| p == t) {
+                assert_eq!(value, &Value::Int(j as i32), "`{}`: {:?}", ops, post);
+            }
+        }
+        // the annotation parser reads the stacks the same way round
+        let stack = |types: &[Type]| {
+            let types: Vec<_> = types.iter().map(|t| t.to_string()).collect();
+            types.join(", ")
+        };
+        let annotated = format!(
+            "data Pair a b: [a, b] pair. define [{}] probe [{}]: {}.",
+            stack(&pre),
+            stack(&post),
+            ops
+        );
+        assert!(
+            Inference::new(&parse(&annotated).unwrap())
+                .typecheck()
+                .is_ok(),
+            "{}",
+            annotated
+        );
+        checked += 1;
+    }
+    assert!(checked > 300, "only {} sentences typecheck", checked);
+}
+
+#[test]
+fn quotes_needing_inputs_the_op_does_not_give() {
+    let source = "
+        data List a: empty, [a, List a] cons.
+        data Bool: true, false.
+        #[unchecked]
+        define [Int, Int] add [Int]:.
+        #[unchecked]
+        define [Int, Int] less [Bool]:.
+        #[unchecked]
+        define [[a][b], List a] map [List b]:.
+        #[unchecked]
+        define [[a][Bool], List a] filter [List a]:.
+        #[unchecked]
+        define [[][a], [][a], Bool] if [a]:.
+        define [List Int] bump [List Int]: (add) map.
+        define [List Int] small [List Int]: (less) filter.
+        define [Bool] choose [Int]: (1) (2 add) if.
+        define [List Int] pairs [List Int]: (dup) map.
+        define [Bool] wrong [Int]: (1) (true) if.
+        define [Bool] fine [Int]: (1) (2) if.
+        ";
+    let module = parse(source).unwrap();
+    let inference = Inference::new(&module);
+    let error = |name: &str| match inference.infer_body(name).unwrap() {
+        Ok(_) => None,
+        Err(err) => Some((err.error, &source[err.span.start..err.span.end])),
+    };
+    let needs = |name: &str| match error(name) {
+        Some((InferenceErrorMessage::QuoteNeedsInputs { op, extra, .. }, span)) => {
+            Some((op, extra, span))
+        }
+        _ => None,
+    };
+    assert_eq!(needs("bump"), Some(("map".to_owned(), 1, "(add)")));
+    assert_eq!(needs("small"), Some(("filter".to_owned(), 1, "(less)")));
+    assert_eq!(needs("choose"), Some(("if".to_owned(), 1, "(2 add)")));
+    let (message, _) = error("bump").unwrap();
+    assert!(message
+        .to_string()
+        .starts_with("this quote needs an extra `Int` input, `map` runs it as [a][b]"));
+    // a quote taking what the op gives but of another type, or leaving
+    // more, is not blamed for its inputs
+    assert!(needs("pairs").is_none() && error("pairs").is_some());
+    assert!(needs("wrong").is_none() && error("wrong").is_some());
+    assert!(error("fine").is_none());
+}
+
+#[test]
+fn constructors_and_prelude_ops_short_of_values() {
+    let source = "
+        data List a: empty, [a, List a] cons.
+        define [] single [List Int]: 1 cons.
+        define [Int, Int] pair [List Int]: empty br-1 cons br-1 cons br-1.
+        define [List Int] push [List Int]: 1 cons.
+        define [] pushed [List Int]: push.
+        define [] deep [Int]: 1 2 3 br-2 pop pop pop pop.
+        ";
+    let module = parse(source).unwrap();
+    let report = Inference::new(&module).report();
+    let short: Vec<_> = report
+        .all_errors()
+        .map(|err| match &err.error {
+            InferenceErrorMessage::NotEnoughValues {
+                op,
+                kind,
+                needs,
+                available,
+            } => Some((
+                op.as_str(),
+                kind.to_string(),
+                needs.len(),
+                *available,
+                &source[err.span.start..err.span.end],
+            )),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        short,
+        [
+            Some(("cons", "constructor".to_owned(), 2, 1, "cons")),
+            // the last `br-1`, with the list alone on the stack
+            Some(("br-1", "prelude op".to_owned(), 2, 1, "br-1")),
+            // an op of the module may be annotated wrong itself
+            None,
+            Some(("pop", "prelude op".to_owned(), 1, 0, "pop")),
+        ]
+    );
+}
+
+#[test]
+fn blame_names_the_ops_pinning_annotation_variables() {
+    let source = "
+        data List a: empty, [a, List a] cons.
+        #[unchecked]
+        define [Int, Int] add [Int]:.
+        #[unchecked]
+        define [List x] tail [List x]:.
+        #[unchecked]
+        define [List Int] sum-all [List Int]:.
+        define [a] inc [a]: 1 add.
+        define [a] sums [a]: tail sum-all.
+        define [a, b] same [a, b]: pop dup.
+        define [a] keep [a]: dup pop.
+        ";
+    let module = parse(source).unwrap();
+    let inference = Inference::new(&module);
+    let blame = |op: &str, var: &str| {
+        let sites = inference.blame(op, var);
+        sites
+            .into_iter()
+            .map(|site| {
+                let at = &source[site.span.start..site.span.end];
+                (site.op, at.to_owned(), site.became.to_string())
+            })
+            .collect::<Vec<_>>()
+    };
+    let site = |op: &str, became: &str| (op.to_owned(), op.to_owned(), became.to_owned());
+    assert_eq!(blame("inc", "a"), [site("add", "Int")]);
+    // made a list, then a list of `Int`s
+    assert_eq!(
+        blame("sums", "a"),
+        [site("tail", "List b"), site("sum-all", "List Int")]
+    );
+    // the output `a` is the input `b` that `dup` pushed
+    assert_eq!(blame("same", "a"), [site("dup", "b")]);
+    assert!(blame("keep", "a").is_empty());
+    assert!(blame("inc", "z").is_empty());
+    assert!(blame("nope", "a").is_empty());
+}
+
+#[test]
+fn panic_takes_the_type_the_arms_and_ops_around_it_need() {
+    let input = "
+        data Bool: true, false.
+        data Maybe a: nothing, [a] just.
+        define [Maybe a] unwrap-any [a]: case { nothing { panic }, just { } }.
+        define [Bool] pair [Int, Bool]: case { true { 1 false br-1 }, false { panic panic } }.
+        define [] later [Int]: panic 1 br-1 pop.
+        define [Bool, Int] checked [Int]: assert.
+        define [] never [a]: panic.
+        ";
+    let module = parse(input).unwrap();
+    let report = Inference::new(&module).report();
+    let types: Vec<_> = report
+        .ops
+        .iter()
+        .map(|op| match &op.outcome {
+            OpOutcome::Inferred(optype) => (op.name.as_str(), optype.canonical().to_string()),
+            outcome => panic!("{} failed: {:?}", op.name, outcome),
+        })
+        .collect();
+    assert_eq!(
+        types,
+        [
+            ("unwrap-any", "[Maybe a][a]".to_owned()),
+            ("pair", "[Bool][Int, Bool]".to_owned()),
+            // the ops after it never run and are not typed
+            ("later", "[][a]".to_owned()),
+            ("checked", "[Bool][]".to_owned()),
+            ("never", "[][a]".to_owned()),
+        ]
+    );
+
+    // an arm that never returns still has to agree on the values it takes
+    let input = "
+        data Bool: true, false.
+        define [Bool, Int] bad [Int]: case { true { }, false { assert panic } }.
+        define [Int] wrong []: assert.
+        const checked [Int]: true assert 1.
+        ";
+    let module = parse(input).unwrap();
+    let report = Inference::new(&module).report();
+    let codes: Vec<_> = report.all_errors().map(|err| err.code()).collect();
+    assert_eq!(codes.len(), 3, "{:?}", codes);
+    assert!(codes.contains(&"ImpureConstant"), "{:?}", codes);
+}
+
+#[test]
+fn declarations_are_typed_by_their_annotation_alone() {
+    let input = "
+        data Bool: true, false.
+        define [Int] is-even [Bool].
+        define [a] keep [a]: 1 is-even pop.
+        define [] main [Bool]: 2 is-even.
+        define [] bad [Int]: 2 is-even.
+        ";
+    let module = parse(input).unwrap();
+    let inference = Inference::new(&module);
+    assert_eq!(inference.infer_body("is-even").map(|r| r.is_ok()), None);
+    let outcomes = |report: &crate::typing::report::TypecheckReport| -> Vec<_> {
+        report
+            .ops
+            .iter()
+            .map(|op| {
+                let outcome = match &op.outcome {
+                    OpOutcome::Inferred(_) => "inferred",
+                    OpOutcome::Failed(_) => "failed",
+                    OpOutcome::Declared => "declared",
+                    OpOutcome::Skipped(SkipReason::Unreachable) => "skipped",
+                    outcome => panic!("{:?}", outcome),
+                };
+                (op.name.clone(), outcome)
+            })
+            .collect()
+    };
+    assert_eq!(
+        outcomes(&inference.report()),
+        [
+            ("is-even".to_owned(), "declared"),
+            ("keep".to_owned(), "inferred"),
+            ("main".to_owned(), "inferred"),
+            ("bad".to_owned(), "failed"),
+        ]
+    );
+    // a declaration is part of the API whatever the entries call
+    assert_eq!(
+        outcomes(&inference.typecheck_reachable(&["keep"])),
+        [
+            ("is-even".to_owned(), "declared"),
+            ("keep".to_owned(), "inferred"),
+            ("main".to_owned(), "skipped"),
+            ("bad".to_owned(), "skipped"),
+        ]
+    );
+    let module = parse("define [Int] is-even [Int]. define [] main [Int]: 1 is-even.").unwrap();
+    assert!(Inference::new(&module).typecheck().is_ok());
+}
+
+#[test]
+fn occurs_checks_show_where_the_variable_is() {
+    let deep = crate::syntax::parse_type("Pair Int (Maybe [Int, a][])").unwrap();
+    let error = Type::mgu(&Type::Poly("a".to_owned()), &deep).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "type variable a occurs in Pair Int (Maybe [Int, a][]), the type it is unified with\n\
+         a is the 2nd value a quote takes, which is the argument of `Maybe`, \
+         which is the 2nd argument of `Pair`"
+    );
+    let input = "
+        data List a: empty, [a, List a] cons.
+        define [a] selfcons [List a]: dup cons.
+        ";
+    assert!(matches!(
+        first_error(input),
+        InferenceErrorMessage::OccursCheck { path, .. } if path.len() == 1
+    ));
+}
+
+#[test]
+fn types_doubling_at_every_op_are_too_large() {
+    // the type of the value doubles with every `twice`
+    let input = format!(
+        "data Pair a b: [a, b] pair.
+        define [a] twice [Pair a a]: dup pair.
+        define [] huge []: 0{}.",
+        " twice".repeat(16)
+    );
+    let module = parse(&input).unwrap();
+    let size = |max| {
+        let inference = Inference::new(&module).with_max_type_size(max);
+        match inference.infer_body("huge").unwrap() {
+            Ok(optype) => Ok(optype.size()),
+            Err(err) => match err.error {
+                InferenceErrorMessage::TypeTooLarge { size, max, .. } => Err((size, max)),
+                error => panic!("{}", error),
+            },
+        }
+    };
+    assert_eq!(size(Some(DEFAULT_MAX_TYPE_SIZE)), Err((16381, 10_000)));
+    assert_eq!(size(Some(100)), Err((125, 100)));
+    assert_eq!(size(None), Ok(262141));
+    let err = Inference::new(&module)
+        .with_max_type_size(Some(100))
+        .infer_body("huge")
+        .unwrap()
+        .unwrap_err();
+    // after k of them the type is built of 2^(k+2) - 3 types, past 100
+    // at the 5th
+    let start = input.find("0 twice").unwrap() + 1 + 4 * 6 + 1;
+    assert_eq!(err.span.start, start);
+}
+
+#[test]
+fn arms_that_never_return_join_any_arm() {
+    let input = "
+        data Nat: zero, [Nat] suc.
+        data Three: one, two, three.
+        define [Three] f [Nat, Nat]: case { one { zero zero }, two { panic }, three { zero suc zero } }.
+        define [Nat] g [Nat, Nat]: case { zero { panic }, suc { zero } }.
+        define [Three, Nat, a] h [Nat, a]: case { one { }, two { pop pop panic }, three { suc } }.
+        define [Three, Nat] both [Int]: case { one { panic }, two { panic }, three { pop panic } }.
+        define [Nat] depth [Nat, Nat]: case { zero { panic }, suc { } } zero suc dup pop.
+        ";
+    let module = parse(input).unwrap();
+    let inference = Inference::new(&module);
+    assert!(inference.typecheck().is_ok());
+    let inferred = |name| {
+        let optype = inference.infer_body(name).unwrap().unwrap();
+        optype.canonical().to_string()
+    };
+    assert_eq!(inferred("f"), "[Three][Nat, Nat]");
+    assert_eq!(inferred("h"), "[Three, Nat, a][Nat, a]");
+    // every arm never returns, nor does the case
+    assert_eq!(inferred("both"), "[Three, a][b]");
+    assert_eq!(inferred("depth"), "[Nat][Nat, Nat]");
+}
+
+#[test]
+fn ops_after_a_panic_are_not_typed() {
+    let input = "
+        data Nat: zero, [Nat] suc.
+        define [] h [Nat]: zero panic pop pop suc (1 suc) nope 3.
+        define [Nat] k [Int, Int]: case { zero { panic pop }, suc { panic } } 1 suc.
+        define [] main []: h pop.
+        ";
+    let module = parse(input).unwrap();
+    assert!(Inference::new(&module).typecheck().is_ok());
+    let warnings: Vec<_> = check_unreachable(&module)
+        .into_iter()
+        .map(|warning| &input[warning.span.start..warning.span.end])
+        .collect();
+    assert_eq!(warnings, ["pop pop suc (1 suc) nope 3", "pop", "1 suc"]);
+    // the values it takes are checked still
+    let input = "define [] bad [Int]: pop panic.";
+    assert!(matches!(
+        first_error(input),
+        InferenceErrorMessage::AnnInfConflict { .. }
+            | InferenceErrorMessage::NotEnoughValues { .. }
+    ));
+}
+
+const OVERLOADS: &str = "
+    data Bool: false, true.
+    data Nat: zero, [Nat] suc.
+    #[overload]
+    define [Int] size [Int]:.
+    #[overload]
+    define [Bool] size [Int]: pop 1.
+    #[overload]
+    define [Nat, Int] size [Int]: pop.
+    ";
+
+#[test]
+fn overloads_resolve_by_the_value_that_tells_them_apart() {
+    let input = format!(
+        "{}
+        define [] sizes [Int, Int, Int]: 5 size true size 4 zero size.
+        define [Bool] given [Int]: size.
+        define [Nat] arm [Int]: case {{ zero {{ 0 }}, suc {{ pop true size }} }}.
+        define [] main [Int]: true given.
+        ",
+        OVERLOADS
+    );
+    let module = parse(&input).unwrap();
+    let report = Inference::new(&module).report();
+    assert_eq!(report.all_errors().count(), 0);
+    let resolved: Vec<_> = (report.overloads.iter())
+        .map(|(start, key)| (&input[*start..*start + 4], key.as_str()))
+        .collect();
+    let keys = ["size", "size#2", "size#3", "size#2", "size#2"];
+    assert_eq!(resolved.len(), keys.len());
+    assert!(resolved
+        .iter()
+        .zip(keys)
+        .all(|((n, k), key)| *n == "size" && *k == key));
+}
+
+#[test]
+fn overloads_only_resolve_to_one_definition() {
+    let error = |body: &str| first_error(&format!("{}define {}.", OVERLOADS, body));
+    assert!(matches!(
+        error("[a] any [Int]: size"),
+        InferenceErrorMessage::AmbiguousOverload { candidates, .. } if candidates.len() == 3
+    ));
+    assert!(matches!(
+        error("[] quoted [Int]: (1) size"),
+        InferenceErrorMessage::NoOverloadFits { found, .. } if found.len() == 1
+    ));
+    // the value the quote takes arrives when it runs, too late to pick one
+    assert!(matches!(
+        error("[] later [Int]: true (size) exec-1-1"),
+        InferenceErrorMessage::OverloadNeedsContext { position: 0, .. }
+    ));
+    assert!(matches!(
+        error("[] empty [Int]: size"),
+        InferenceErrorMessage::OverloadNeedsContext { .. }
+    ));
+}
+
+#[test]
+fn overloads_are_marked_and_told_apart() {
+    let input = "
+        data Bool: false, true.
+        define [Int] count [Int]:.
+        #[overload]
+        define [Bool] count [Int]: pop 1.
+        ";
+    assert!(matches!(
+        first_error(input),
+        InferenceErrorMessage::OverloadNotMarked { name } if name == "count"
+    ));
+    let input = "
+        #[overload]
+        define [Int] same [Int]:.
+        #[overload]
+        define [Int, Int] same [Int]: pop.
+        ";
+    assert!(matches!(
+        first_error(input),
+        InferenceErrorMessage::OverloadsIndistinct { candidates, .. } if candidates.len() == 2
+    ));
+    // without another definition the attribute changes nothing
+    let input = "#[overload]\ndefine [Int] single [Int]:.\ndefine [] main [Int]: 1 single.";
+    assert!(Inference::new(&parse(input).unwrap()).typecheck().is_ok());
+}
+
+#[test]
+fn overloads_run_the_definition_they_resolve_to() {
+    use crate::evaluation::evaluator::Evaluator;
+    use crate::evaluation::types::Value;
+    use crate::typing::overload::dispatch_overloads;
+    let input = format!(
+        "{}define [] main [Int, Int, Int]: 5 size true size 4 zero size.",
+        OVERLOADS
+    );
+    let module = parse(&input).unwrap();
+    // every definition is kept, the later ones under keys of their own
+    let mut keys: Vec<_> = module.op_defs.keys().collect();
+    keys.sort();
+    assert_eq!(keys, ["main", "size", "size#2", "size#3"]);
+    let report = Inference::new(&module).report();
+    let module = dispatch_overloads(&module, &report.overloads);
+    let mut evaluator = Evaluator::new(&module);
+    evaluator.eval_main().unwrap();
+    let stack: Vec<_> = (evaluator.stack.iter())
+        .map(|value| match value {
+            Value::Int(n) => *n,
+            value => panic!("not an `Int`: {:?}", value),
+        })
+        .collect();
+    assert_eq!(stack, [5, 1, 4]);
+}
+
+#[test]
+fn errors_are_read_through_their_accessors() {
+    use crate::diagnostics::kind::DiagnosticKind;
+    let input = format!("{}define [a] any [Int]: size.", OVERLOADS);
+    let module = parse(&input).unwrap();
+    let err = Inference::new(&module).typecheck().unwrap_err();
+    assert_eq!(err.code(), "AmbiguousOverload");
+    assert_eq!(err.kind(), DiagnosticKind::Overload);
+    let span = err.primary_span();
+    assert_eq!(&input[span.start..span.end], "size");
+    assert!(err
+        .message()
+        .starts_with("more than one definition of `size`"));
+    let defined: Vec<_> = (err.related_spans().iter())
+        .map(|span| &input[span.start..span.end])
+        .collect();
+    assert_eq!(defined, ["size", "size", "size"]);
+    assert!(err.suggestions(&input, &module).is_empty());
+}
+
+#[test]
+fn dyn_only_in_declarations() {
+    for input in [
+        "define [?] show [Int]: pop 0.",
+        "const c [?]: 1.",
+        "data Box: [?] box.",
+    ] {
+        assert!(
+            matches!(
+                first_error(input),
+                InferenceErrorMessage::DynInAnnotation { .. }
+            ),
+            "{}",
+            input
+        );
+    }
+    let module = parse("define [?] show [Int].").unwrap();
+    assert!(Inference::new(&module).typecheck().is_ok());
+    // a body leaving a `?` does not make it the annotation's type
+    let input = "define [] any [?].\ndefine [] bad [Int]: any.";
+    assert!(matches!(
+        first_error(input),
+        InferenceErrorMessage::AnnInfConflict { .. }
+    ));
+}
+
+#[test]
+fn dyn_checks_where_an_op_needs_a_type() {
+    const DECLARED: &str = "
+        data Bool: false, true.
+        define [] any [?].
+        define [Int] inc [Int]:.
+        define [a] id [a]:.
+        ";
+    let checked = |body: &str| {
+        let input = format!("{}define [Bool] main [Int]: {}.", DECLARED, body);
+        let module = parse(&input).unwrap();
+        let report = Inference::new(&module).report();
+        assert!(report.is_ok(), "{}: {:?}", body, report);
+        (report.dyn_checks.iter())
+            .map(|(key, checks)| {
+                let checks: Vec<_> = (checks.iter())
+                    .map(|check| (check.depth, check.expected.to_string()))
+                    .collect();
+                (
+                    input[key.start..]
+                        .split([' ', '.'])
+                        .next()
+                        .unwrap()
+                        .to_owned(),
+                    checks,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let int = |op: &str, depth| (op.to_owned(), vec![(depth, "Int".to_owned())]);
+    assert_eq!(checked("pop any inc"), [int("inc", 0)]);
+    // a variable a `?` fits is a `?` too
+    assert_eq!(checked("pop any id dup pop inc"), [int("inc", 0)]);
+    assert_eq!(checked("pop any pop 1 inc"), []);
+    // joined arms leave a `?` where either arm does
+    assert_eq!(
+        checked("case { false { 1 }, true { any } } inc"),
+        [int("inc", 0)]
+    );
+    assert_eq!(
+        checked("case { false { any }, true { 1 } } inc"),
+        [int("inc", 0)]
+    );
+}
@@ -0,0 +1,137 @@
+//! Ops defined more than once under one name, each definition marked
+//! `#[overload]`. The parser keeps all of them, the ones after the first
+//! under keys of their own, see `insert_op_def`. Checking resolves each
+//! use of the name to the one definition whose annotation fits the values
+//! on the stack before it, and the report records which that is, so that
+//! `dispatch_overloads` can have the evaluator and codegen run it.
+
+use super::types::{OpType, Type};
+use crate::syntax::ast::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::once;
+
+/// A definition of an overloaded name
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// Its key in `Module::op_defs`
+    pub key: String,
+    pub ann: OpType,
+    /// The name in its definition
+    pub span: Span,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stack = |stack: &[Type]| {
+            let stack: Vec<_> = stack.iter().map(Type::to_string).collect();
+            stack.join(", ")
+        };
+        write!(
+            f,
+            "[{}] {} [{}]",
+            stack(&self.ann.pre),
+            overloaded_name(&self.key),
+            stack(&self.ann.post)
+        )
+    }
+}
+
+/// The definitions of a name, in source order
+#[derive(Debug, Clone, Default)]
+pub struct OverloadSet {
+    pub candidates: Vec<Candidate>,
+}
+
+impl OverloadSet {
+    /// Adds the definition, replacing the one of the same key
+    pub fn insert(&mut self, key: &str, op_def: &OpDef) {
+        self.candidates.retain(|candidate| candidate.key != key);
+        self.candidates.push(Candidate {
+            key: key.to_owned(),
+            ann: op_def.ann.clone(),
+            span: op_def.name_span.clone(),
+        });
+        self.candidates
+            .sort_by_key(|candidate| candidate.span.start);
+    }
+
+    /// Whether there is more than one definition to choose from
+    pub fn is_overloaded(&self) -> bool {
+        self.candidates.len() > 1
+    }
+
+    /// The resolving position: the topmost value that every definition
+    /// takes, each as a type of its own made by a different data type or
+    /// `Int`. Values of different types only fit one of them there.
+    pub fn position(&self) -> Option<usize> {
+        let shortest = self.candidates.iter().map(|c| c.ann.pre.len()).min()?;
+        (0..shortest).find(|&i| {
+            let mut heads: Vec<_> = self
+                .candidates
+                .iter()
+                .map(|candidate| head(&candidate.ann.pre[i]))
+                .collect();
+            let len = heads.len();
+            heads.sort();
+            heads.dedup();
+            heads.len() == len && heads.iter().all(Option::is_some)
+        })
+    }
+}
+
+/// The name of the data type, or `Int`, that makes the type
+fn head(t: &Type) -> Option<&str> {
+    match t {
+        Type::Mono(name) => Some(name),
+        Type::App(t, _) => head(t),
+        Type::Poly(_) | Type::Op(_) | Type::Dyn => None,
+    }
+}
+
+/// Whether the module defines some name more than once
+pub fn has_overloads(module: &Module) -> bool {
+    module.op_defs.keys().any(|key| key.contains('#'))
+}
+
+/// The module with each use of an overloaded name that checking resolved,
+/// by the start of its span, renamed to the key of the definition it
+/// resolved to. Uses not resolved are left as they are.
+pub fn dispatch_overloads(module: &Module, resolved: &BTreeMap<usize, String>) -> Module {
+    fn rename(ops: &mut [Op], resolved: &BTreeMap<usize, String>) {
+        for op in ops {
+            match op {
+                Op::Literal { .. } => (),
+                Op::Name { value, span } => {
+                    if let Some(key) = resolved.get(&span.start) {
+                        *value = key.clone();
+                    }
+                }
+                Op::Quote { value, .. } => rename(value, resolved),
+                Op::Case { head_arm, arms, .. } => {
+                    for arm in once(head_arm).chain(arms) {
+                        rename(&mut arm.body, resolved);
+                    }
+                }
+                Op::Case2 { arms, .. } => {
+                    for arm in arms {
+                        rename(&mut arm.body, resolved);
+                    }
+                }
+                Op::List { items, .. } => {
+                    for item in items {
+                        rename(item, resolved);
+                    }
+                }
+            }
+        }
+    }
+    let mut module = module.clone();
+    for op_def in module.op_defs.values_mut() {
+        rename(&mut op_def.body, resolved);
+    }
+    for const_def in module.const_defs.values_mut() {
+        rename(&mut const_def.body, resolved);
+    }
+    module
+}
@@ -1,6 +1,12 @@
 use super::types::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::iter::once;
 
+/// Bumped whenever a prelude op is added, removed or changes its type or
+/// what it does, so that hashes of definitions tell the preludes apart
+pub const PRELUDE_VERSION: u32 = 2;
+
 fn gen_prelude_type(prefix: &str, i: usize) -> Type {
     Type::Poly(format!("_prelude_{}_{}", prefix, i))
 }
@@ -32,10 +38,49 @@ fn get_basic(s: &str) -> Option<OpType> {
                 post: vec![Type::Poly("a".to_owned())],
             })],
         }),
+        // fails unless the value is `true`, of the module's `Bool`
+        "assert" => Some(OpType {
+            pre: vec![Type::Mono("Bool".to_owned())],
+            post: vec![],
+        }),
+        // always fails, so the value it would push can be of any type.
+        // Checking a body knows it never returns and leaves the ops after
+        // it untyped, what it leaves stands for whatever the annotation or
+        // the other case arms say.
+        "panic" => Some(OpType {
+            pre: vec![],
+            post: vec![Type::Poly("a".to_owned())],
+        }),
+        // what `pick` and `roll` leave depends on their index, see
+        // `indexed`, these are their types for the index 0
+        "pick" => Some(OpType {
+            pre: vec![Type::Mono("Int".to_owned()), Type::Poly("a".to_owned())],
+            post: vec![Type::Poly("a".to_owned()), Type::Poly("a".to_owned())],
+        }),
+        "roll" => Some(OpType {
+            pre: vec![Type::Mono("Int".to_owned()), Type::Poly("a".to_owned())],
+            post: vec![Type::Poly("a".to_owned())],
+        }),
         _ => None,
     }
 }
 
+/// The type of `n pick` or `n roll`, the literal index `n` taken along
+/// with the op. `pick` pushes a copy of the value `n` below the top,
+/// `roll` moves that value to the top.
+pub fn indexed(s: &str, n: usize) -> Option<OpType> {
+    let taken = gen_prelude_types("x", n + 1);
+    let rest = match s {
+        "pick" => &taken[..],
+        "roll" => &taken[..n],
+        _ => return None,
+    };
+    Some(OpType {
+        pre: taken.clone(),
+        post: once(taken[n].clone()).chain(rest.iter().cloned()).collect(),
+    })
+}
+
 fn get_bury(s: &str) -> Option<OpType> {
     let [n] = parse_parametric("br-", s)?;
     let tau = gen_prelude_type("tau", 0);
@@ -145,13 +190,527 @@ fn get_exec(s: &str) -> Option<OpType> {
     })
 }
 
+/// Like `exec`, the quote receives a quote that runs it again on top of its
+/// inputs
+fn get_fix(s: &str) -> Option<OpType> {
+    let [pre_n, post_n] = parse_parametric("fix-", s)?;
+    let pre: Vec<_> = (0..pre_n).map(|i| gen_prelude_type("pre", i)).collect();
+    let post: Vec<_> = (0..post_n).map(|i| gen_prelude_type("post", i)).collect();
+    let fixed = Type::Op(OpType {
+        pre: pre.clone(),
+        post: post.clone(),
+    });
+    Some(OpType {
+        pre: once(Type::Op(OpType {
+            pre: once(fixed).chain(pre.clone()).collect(),
+            post: post.clone(),
+        }))
+        .chain(pre)
+        .collect(),
+        post,
+    })
+}
+
+fn gen_prelude_types(prefix: &str, n: usize) -> Vec<Type> {
+    (0..n).map(|i| gen_prelude_type(prefix, i)).collect()
+}
+
+fn quote_of(pre: &[Type], post: &[Type]) -> Type {
+    Type::Op(OpType {
+        pre: pre.to_vec(),
+        post: post.to_vec(),
+    })
+}
+
+/// `keep-P-Q` runs a quote taking P values and giving Q on the top P
+/// values, then pushes the topmost of them again
+fn get_keep(s: &str) -> Option<OpType> {
+    let [pre_n, post_n] = parse_parametric("keep-", s).filter(|[pre_n, _]| *pre_n > 0)?;
+    let pre = gen_prelude_types("pre", pre_n);
+    let post = gen_prelude_types("post", post_n);
+    Some(OpType {
+        pre: once(quote_of(&pre, &post)).chain(pre.clone()).collect(),
+        post: once(pre[0].clone()).chain(post).collect(),
+    })
+}
+
+/// `dip-N-P-Q` runs a quote taking P values and giving Q below the top N
+/// values
+fn get_dip(s: &str) -> Option<OpType> {
+    let [kept_n, pre_n, post_n] =
+        parse_parametric("dip-", s).filter(|[kept_n, _, _]| *kept_n > 0)?;
+    let kept = gen_prelude_types("kept", kept_n);
+    let pre = gen_prelude_types("pre", pre_n);
+    let post = gen_prelude_types("post", post_n);
+    Some(OpType {
+        pre: once(quote_of(&pre, &post))
+            .chain(kept.iter().cloned())
+            .chain(pre)
+            .collect(),
+        post: kept.into_iter().chain(post).collect(),
+    })
+}
+
+/// `bi-Q1-Q2` and `tri-Q1-Q2-Q3` run each of their quotes on the same
+/// value, the quote pushed first first, so that the last quote's results
+/// end up on top
+fn get_cleave(posts_n: &[usize]) -> OpType {
+    let x = gen_prelude_type("x", 0);
+    let posts: Vec<_> = posts_n
+        .iter()
+        .enumerate()
+        .map(|(i, n)| gen_prelude_types(&format!("post{}", i), *n))
+        .collect();
+    OpType {
+        pre: posts
+            .iter()
+            .rev()
+            .map(|post| quote_of(std::slice::from_ref(&x), post))
+            .chain(once(x.clone()))
+            .collect(),
+        post: posts.into_iter().rev().flatten().collect(),
+    }
+}
+
+fn get_macro(s: &str) -> Option<OpType> {
+    get_keep(s)
+        .or_else(|| get_dip(s))
+        .or_else(|| parse_parametric::<2>("bi-", s).map(|posts| get_cleave(&posts)))
+        .or_else(|| parse_parametric::<3>("tri-", s).map(|posts| get_cleave(&posts)))
+}
+
 fn get_parametric(s: &str) -> Option<OpType> {
     get_bury(s)
         .or_else(|| get_dig(s))
         .or_else(|| get_comp(s))
         .or_else(|| get_exec(s))
+        .or_else(|| get_fix(s))
+        .or_else(|| get_macro(s))
 }
 
 pub fn get(s: &str) -> Option<OpType> {
     get_basic(s).or_else(|| get_parametric(s))
 }
+
+/// `br-n` or `dg-n`, nothing for the `n` of 0 that would leave the stack
+/// as it is
+fn shuffle(prefix: &str, n: usize) -> Option<String> {
+    (n > 0).then(|| format!("{}{}", prefix, n))
+}
+
+fn expand_keep(pre_n: usize, post_n: usize) -> Vec<String> {
+    // a copy of the topmost input goes below the quote's other inputs
+    ["br-1".to_owned(), "dup".to_owned()]
+        .into_iter()
+        .chain(shuffle("br-", pre_n + 1))
+        .chain(["br-1".to_owned(), format!("exec-{}-{}", pre_n, post_n)])
+        .chain(shuffle("dg-", post_n))
+        .collect()
+}
+
+/// The ops a macro op of the prelude stands for, run one after the other.
+/// They are other prelude ops, no macro ops among them.
+pub fn expand(s: &str) -> Option<Vec<String>> {
+    if let Some([pre_n, post_n]) = parse_parametric("keep-", s).filter(|[pre_n, _]| *pre_n > 0) {
+        Some(expand_keep(pre_n, post_n))
+    } else if let Some([kept_n, pre_n, post_n]) =
+        parse_parametric("dip-", s).filter(|[kept_n, _, _]| *kept_n > 0)
+    {
+        // the kept values go below the quote's inputs, the first one lowest
+        let mut ops: Vec<_> = shuffle("br-", kept_n).into_iter().collect();
+        ops.extend((0..kept_n).map(|i| format!("br-{}", kept_n + pre_n - i)));
+        ops.push(format!("exec-{}-{}", pre_n, post_n));
+        ops.extend((0..kept_n).flat_map(|i| shuffle("dg-", post_n + i)));
+        Some(ops)
+    } else if let Some(posts) = parse_parametric::<2>("bi-", s)
+        .map(Vec::from)
+        .or_else(|| parse_parametric::<3>("tri-", s).map(Vec::from))
+    {
+        // each quote but the last one is kept below the value, and dug
+        // up once the quote before it ran
+        let (last, keeps) = posts.split_last()?;
+        let mut ops: Vec<_> = (2..=posts.len())
+            .rev()
+            .map(|n| format!("br-{}", n))
+            .collect();
+        let mut results = 0;
+        for post_n in keeps {
+            ops.extend(expand_keep(1, *post_n));
+            results += post_n;
+            ops.push(format!("dg-{}", results + 1));
+        }
+        ops.push(format!("exec-1-{}", last));
+        Some(ops)
+    } else {
+        None
+    }
+}
+
+/// A prelude op whose typing takes more than chaining its op type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialForm {
+    /// A `fix-*` whose quote cannot take the fixed quote is an infinite
+    /// type, reported as such rather than as a failed occurs check, in
+    /// `Inference::special_form_error`
+    Fix,
+    /// `keep-*`, `dip-*`, `bi-*` and `tri-*` stand for the ops `expand`
+    /// gives, which are typed and run in their place
+    Macro,
+    /// `pick` and `roll` take the `Int` literal right before them as their
+    /// index and are typed as `indexed` gives for it
+    Indexed,
+}
+
+impl SpecialForm {
+    pub const ALL: [SpecialForm; 3] = [SpecialForm::Fix, SpecialForm::Macro, SpecialForm::Indexed];
+}
+
+/// Facts about a prelude op beyond its op type
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreludeOpInfo {
+    pub name: String,
+    pub optype: OpType,
+    /// Running it has no effect besides the values it pushes, which only
+    /// depend on the values it pops
+    pub pure: bool,
+    /// It can fail at runtime even when the program typechecks
+    pub can_fail: bool,
+    /// It only moves, copies or drops the values it takes, see `moves`
+    pub shuffles: bool,
+    pub special_form: Option<SpecialForm>,
+}
+
+/// The facts shared by every op of a family, the family given by a name
+/// or, ending in `-`, by the prefix of its parametric names
+struct Family {
+    name: &'static str,
+    pure: bool,
+    can_fail: bool,
+    shuffles: bool,
+    special_form: Option<SpecialForm>,
+}
+
+const fn family(name: &'static str, pure: bool, special_form: Option<SpecialForm>) -> Family {
+    // only the ops running quotes, through the ops in the quote, and the
+    // ones failing on purpose are impure or can fail
+    Family {
+        name,
+        pure,
+        can_fail: !pure,
+        shuffles: false,
+        special_form,
+    }
+}
+
+const fn shuffler(name: &'static str) -> Family {
+    Family {
+        shuffles: true,
+        ..family(name, true, None)
+    }
+}
+
+const FAMILIES: [Family; 16] = [
+    shuffler("dup"),
+    shuffler("pop"),
+    family("quote", true, None),
+    family("assert", false, None),
+    family("panic", false, None),
+    shuffler("br-"),
+    shuffler("dg-"),
+    family("comp-", true, None),
+    family("exec-", false, None),
+    family("fix-", false, Some(SpecialForm::Fix)),
+    family("keep-", false, Some(SpecialForm::Macro)),
+    family("dip-", false, Some(SpecialForm::Macro)),
+    family("bi-", false, Some(SpecialForm::Macro)),
+    family("tri-", false, Some(SpecialForm::Macro)),
+    family("pick", true, Some(SpecialForm::Indexed)),
+    family("roll", true, Some(SpecialForm::Indexed)),
+];
+
+fn info_with_optype(name: &str, optype: OpType) -> Option<PreludeOpInfo> {
+    let family = FAMILIES
+        .iter()
+        .find(|family| match family.name.strip_suffix('-') {
+            Some(_) => name.starts_with(family.name),
+            None => name == family.name,
+        })?;
+    Some(PreludeOpInfo {
+        name: name.to_owned(),
+        optype,
+        pure: family.pure,
+        can_fail: family.can_fail,
+        shuffles: family.shuffles,
+        special_form: family.special_form,
+    })
+}
+
+/// For each value the prelude op leaves, topmost first, the value it takes
+/// that it leaves as it is, as a position among the values it takes,
+/// topmost first too. Every value a shuffler leaves is one it takes,
+/// `keep-*` and `dip-*` leave some of theirs besides what their quote
+/// gives. Told by the op's type: a value of a type variable that only one
+/// value taken has cannot be any other. Nothing for `pick` and `roll`,
+/// which move values by their index.
+pub fn moves(name: &str) -> Option<Vec<Option<usize>>> {
+    if get_info(name)?.special_form == Some(SpecialForm::Indexed) {
+        return None;
+    }
+    let OpType { pre, post } = get(name)?;
+    let taken = |t: &Type| {
+        let mut positions = pre
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| matches!(p, Type::Poly(_)) && *p == t)
+            .map(|(i, _)| i);
+        match (positions.next(), positions.next()) {
+            (Some(i), None) => Some(i),
+            _ => None,
+        }
+    };
+    Some(post.iter().map(taken).collect())
+}
+
+pub fn get_info(name: &str) -> Option<PreludeOpInfo> {
+    info_with_optype(name, get(name)?)
+}
+
+/// `get_info` of every op of `names(max)`
+pub fn all_info(max: usize) -> Vec<PreludeOpInfo> {
+    names(max)
+        .iter()
+        .filter_map(|name| get_info(name))
+        .collect()
+}
+
+/// Names of the prelude ops, the parametric ones with every parameter up to
+/// `max`
+pub fn names(max: usize) -> Vec<String> {
+    let mut names: Vec<_> = ["dup", "pop", "quote", "assert", "panic", "pick", "roll"]
+        .map(str::to_owned)
+        .into();
+    for n in 1..=max {
+        names.push(format!("br-{}", n));
+        names.push(format!("dg-{}", n));
+    }
+    for pre in 0..=max {
+        for post in 0..=max {
+            names.push(format!("exec-{}-{}", pre, post));
+            names.push(format!("fix-{}-{}", pre, post));
+        }
+    }
+    for a in 0..=max {
+        for b in 0..=max {
+            names.push(format!("bi-{}-{}", a, b));
+            if a > 0 {
+                names.push(format!("keep-{}-{}", a, b));
+            }
+            for c in 0..=max {
+                names.push(format!("tri-{}-{}-{}", a, b, c));
+                if a > 0 {
+                    names.push(format!("dip-{}-{}-{}", a, b, c));
+                }
+                for d in 0..=max {
+                    names.push(format!("comp-{}-{}-{}-{}", a, b, c, d));
+                }
+            }
+        }
+    }
+    names
+}
+
+/// The prelude as an embedder configured it, ops may be removed or given
+/// other types
+#[derive(Debug, Clone, Default)]
+pub struct Prelude {
+    without_all: bool,
+    removed: HashSet<String>,
+    overrides: HashMap<String, OpType>,
+}
+
+/// `Prelude::override_op` of an op the prelude does not have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPreludeOp {
+    pub name: String,
+}
+
+impl fmt::Display for UnknownPreludeOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the prelude has no op `{}` to override", self.name)
+    }
+}
+
+impl Prelude {
+    pub fn get(&self, name: &str) -> Option<OpType> {
+        if let Some(op_type) = self.overrides.get(name) {
+            return Some(op_type.clone());
+        }
+        if self.without_all || self.removed.contains(name) {
+            return None;
+        }
+        get(name)
+    }
+
+    /// Like `get_info`, with the op type given by `get`. An overridden op
+    /// is typed by its override alone and is no special form.
+    pub fn get_info(&self, name: &str) -> Option<PreludeOpInfo> {
+        let info = info_with_optype(name, self.get(name)?)?;
+        match self.overrides.contains_key(name) {
+            true => Some(PreludeOpInfo {
+                special_form: None,
+                ..info
+            }),
+            false => Some(info),
+        }
+    }
+
+    /// Removes every op, overrides made afterwards bring single ops back
+    pub fn remove_all(&mut self) {
+        self.without_all = true;
+        self.overrides.clear();
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.removed.insert(name.to_owned());
+        self.overrides.remove(name);
+    }
+
+    pub fn override_op(&mut self, name: &str, op_type: OpType) -> Result<(), UnknownPreludeOp> {
+        if get(name).is_none() {
+            return Err(UnknownPreludeOp {
+                name: name.to_owned(),
+            });
+        }
+        self.overrides.insert(name.to_owned(), op_type);
+        Ok(())
+    }
+
+    /// Like `names`, with the overridden ops and without the removed ones
+    pub fn names(&self, max: usize) -> Vec<String> {
+        let mut names: Vec<_> = names(max)
+            .into_iter()
+            .filter(|name| !self.overrides.contains_key(name) && self.get(name).is_some())
+            .collect();
+        let mut overridden: Vec<_> = self.overrides.keys().cloned().collect();
+        overridden.sort();
+        names.extend(overridden);
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_op_has_info() {
+        let info = all_info(2);
+        assert_eq!(info.len(), names(2).len());
+        let fix = get_info("fix-1-2").unwrap();
+        assert_eq!(fix.optype, get("fix-1-2").unwrap());
+        assert!(!fix.pure && fix.can_fail);
+        assert!(get_info("comp-1-0-2-1").unwrap().pure);
+        assert_eq!(get_info("exec-"), None);
+        assert_eq!(get_info("nope"), None);
+    }
+
+    #[test]
+    fn shufflers_only_move_values() {
+        for info in all_info(2) {
+            if info.special_form == Some(SpecialForm::Indexed) {
+                assert_eq!(moves(&info.name), None);
+                continue;
+            }
+            let moves = moves(&info.name).unwrap();
+            if info.shuffles {
+                assert!(moves.iter().all(Option::is_some), "{}", info.name);
+            }
+        }
+        assert_eq!(moves("dup").unwrap(), [Some(0), Some(0)]);
+        assert_eq!(moves("pop").unwrap(), []);
+        assert_eq!(moves("br-2").unwrap(), [Some(1), Some(2), Some(0)]);
+        assert_eq!(moves("dg-2").unwrap(), [Some(2), Some(0), Some(1)]);
+        assert_eq!(moves("dip-2-1-1").unwrap(), [Some(1), Some(2), None]);
+        assert_eq!(moves("keep-1-2").unwrap(), [Some(1), None, None]);
+        assert_eq!(moves("exec-1-1").unwrap(), [None]);
+        assert!(!get_info("exec-1-1").unwrap().shuffles);
+        assert_eq!(moves("nope"), None);
+    }
+
+    #[test]
+    fn special_forms_match_the_table() {
+        for form in SpecialForm::ALL {
+            assert!(
+                all_info(1)
+                    .iter()
+                    .any(|info| info.special_form == Some(form)),
+                "{:?} has no op",
+                form
+            );
+        }
+        for info in all_info(1) {
+            if let Some(form) = info.special_form {
+                assert!(SpecialForm::ALL.contains(&form), "{:?} is not listed", form);
+            }
+        }
+    }
+
+    #[test]
+    fn indexed_types() {
+        let typed = |name, n| indexed(name, n).unwrap().canonical().to_string();
+        assert_eq!(typed("pick", 0), "[a][a, a]");
+        assert_eq!(typed("pick", 2), "[a, b, c][c, a, b, c]");
+        assert_eq!(typed("roll", 0), "[a][a]");
+        assert_eq!(typed("roll", 1), "[a, b][b, a]");
+        assert_eq!(typed("roll", 3), "[a, b, c, d][d, a, b, c]");
+        assert_eq!(indexed("dup", 0), None);
+    }
+
+    #[test]
+    fn macro_expansions() {
+        let expansion = |name| expand(name).unwrap().join(" ");
+        assert_eq!(expansion("keep-1-1"), "br-1 dup br-2 br-1 exec-1-1 dg-1");
+        assert_eq!(expansion("keep-2-0"), "br-1 dup br-3 br-1 exec-2-0");
+        assert_eq!(expansion("dip-2-1-1"), "br-2 br-3 br-2 exec-1-1 dg-1 dg-2");
+        assert_eq!(
+            expansion("bi-2-1"),
+            "br-2 br-1 dup br-2 br-1 exec-1-2 dg-2 dg-3 exec-1-1"
+        );
+        assert_eq!(
+            expansion("tri-1-0-1"),
+            "br-3 br-2 br-1 dup br-2 br-1 exec-1-1 dg-1 dg-2 br-1 dup br-2 br-1 exec-1-0 dg-2 exec-1-1"
+        );
+        for name in names(2)
+            .into_iter()
+            .filter_map(|name| expand(&name))
+            .flatten()
+        {
+            assert!(get(&name).is_some() && expand(&name).is_none(), "{}", name);
+        }
+        assert_eq!(expand("keep-0-1"), None);
+        assert_eq!(expand("dip-0-1-1"), None);
+        assert_eq!(expand("exec-1-1"), None);
+    }
+
+    #[test]
+    fn configured_prelude_info() {
+        let mut prelude = Prelude::default();
+        let int_dup = OpType {
+            pre: vec![Type::Mono("Int".to_owned())],
+            post: vec![Type::Mono("Int".to_owned()); 2],
+        };
+        prelude.override_op("dup", int_dup.clone()).unwrap();
+        prelude.remove("pop");
+        let dup = prelude.get_info("dup").unwrap();
+        assert_eq!(dup.optype, int_dup);
+        assert!(dup.pure);
+        assert_eq!(prelude.get_info("pop"), None);
+        assert_eq!(
+            prelude.get_info("keep-1-1").unwrap().special_form,
+            Some(SpecialForm::Macro)
+        );
+        prelude.override_op("keep-1-1", int_dup).unwrap();
+        // an overridden macro is an ordinary op of the override's type
+        assert_eq!(prelude.get_info("keep-1-1").unwrap().special_form, None);
+    }
+}
@@ -0,0 +1,111 @@
+use super::dynamic::DynCheck;
+use super::inference::InferenceError;
+use super::trace::CheckStats;
+use super::types::OpType;
+use crate::analyze::Phase;
+use crate::syntax::ast::{OpKey, Span};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Outcome of checking every definition of a module
+#[derive(Debug)]
+pub struct TypecheckReport {
+    /// Errors not attributable to a single op definition
+    pub errors: Vec<InferenceError>,
+    /// One entry per op definition, in source order
+    pub ops: Vec<OpReport>,
+    /// The prelude ops the module names, sorted, to audit what an embedded
+    /// module relies on
+    pub prelude_used: Vec<String>,
+    /// The key of the definition each use of an overloaded name resolved
+    /// to, by the start of the use's span, see `dispatch_overloads`
+    pub overloads: BTreeMap<usize, String>,
+    /// The values each op has to check before it runs, by the key of its
+    /// span, see `Evaluator::with_dyn_checks`
+    pub dyn_checks: BTreeMap<OpKey, Vec<DynCheck>>,
+    /// How long each phase took up to and including checking, when the
+    /// report comes from `analyze`, see `AnalysisResult::timings`
+    pub timings: Vec<(Phase, Duration)>,
+}
+
+#[derive(Debug)]
+pub struct OpReport {
+    pub name: String,
+    pub span: Span,
+    pub outcome: OpOutcome,
+    /// Taken from a `TypecheckCache` instead of checked again
+    pub from_cache: bool,
+    /// The steps checking the op took, none when it was not checked
+    pub stats: CheckStats,
+    /// How long checking the op took, `None` when it was taken from the
+    /// cache or without the `os` feature, which the clock needs
+    pub elapsed: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub enum OpOutcome {
+    /// The body checks against the annotation, carries the body's type
+    Inferred(OpType),
+    Failed(InferenceError),
+    /// The op is trusted to match its annotation and was not inferred
+    Unchecked,
+    /// Checking was cancelled before the op was done
+    Cancelled,
+    /// Checking the op ran over its budget
+    TimedOut,
+    /// The op failed with the error its `expect-error` pragma asks for
+    ExpectedError(InferenceError),
+    /// The op was deliberately not checked
+    Skipped(SkipReason),
+    /// The op is declared without a body, callers use its annotation
+    Declared,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// No entry of a check of the reachable ops calls the op
+    Unreachable,
+}
+
+impl TypecheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+            && self
+                .ops
+                .iter()
+                .all(|op| !matches!(op.outcome, OpOutcome::Failed(_)))
+    }
+
+    /// Whether every op was checked, none was cancelled or timed out
+    pub fn is_complete(&self) -> bool {
+        self.ops
+            .iter()
+            .all(|op| !matches!(op.outcome, OpOutcome::Cancelled | OpOutcome::TimedOut))
+    }
+
+    /// The steps checking every op took
+    pub fn stats(&self) -> CheckStats {
+        self.ops
+            .iter()
+            .fold(CheckStats::default(), |total, op| total.add(&op.stats))
+    }
+
+    /// The `n` ops that took longest to check, slowest first, of the ones
+    /// that were timed
+    pub fn slowest(&self, n: usize) -> Vec<&OpReport> {
+        let mut timed: Vec<_> = self.ops.iter().filter(|op| op.elapsed.is_some()).collect();
+        timed.sort_by_key(|op| std::cmp::Reverse(op.elapsed));
+        timed.truncate(n);
+        timed
+    }
+
+    /// All errors, module level ones first, then per op in source order
+    pub fn all_errors(&self) -> impl Iterator<Item = &InferenceError> {
+        self.errors
+            .iter()
+            .chain(self.ops.iter().filter_map(|op| match &op.outcome {
+                OpOutcome::Failed(err) => Some(err),
+                _ => None,
+            }))
+    }
+}
@@ -0,0 +1,140 @@
+//! The resolution phase: what the names of a module refer to, built from
+//! the whole module before any inference runs. Definitions are taken in
+//! source order, so every table is the same however the module's maps
+//! iterate, and a definition can use the ones after it as well as the ones
+//! before.
+//!
+//! The tables can also grow one definition at a time, see
+//! `IncrementalCheck`.
+
+use super::env::{Env, Provenance};
+use super::overload::OverloadSet;
+use super::prelude_types::Prelude;
+use super::types::{OpType, Scheme, Type};
+use crate::analysis::deprecated::Deprecations;
+use crate::syntax::ast::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The op type a constant is looked up with, pushing its value
+pub(crate) fn const_optype(const_def: &ConstDef) -> OpType {
+    OpType {
+        pre: vec![],
+        post: vec![const_def.ty.clone()],
+    }
+}
+
+/// The op type of a constructor, taking its fields and pushing the data
+/// type applied to its parameters
+fn constr_optype(data_name: &str, data_def: &DataDef, constr_def: &DataConstr) -> OpType {
+    let constructed_type = data_def
+        .params
+        .iter()
+        .map(|p| Type::Poly(p.to_owned()))
+        .fold(Type::Mono(data_name.to_owned()), |a, x| {
+            Type::App(Box::new(a), Box::new(x))
+        });
+    OpType {
+        pre: constr_def.params.clone(),
+        post: vec![constructed_type],
+    }
+}
+
+/// Every table inference looks names up in
+pub(crate) struct Resolution {
+    /// The op type of each constructor, a constructor defined more than once
+    /// gets the op type of its first definition, checking reports the others
+    pub constr_optypes: HashMap<String, OpType>,
+    /// The data definition of each constructor, the first one as well
+    pub constr_data: HashMap<String, Arc<DataDef>>,
+    /// The base environment of the module, see `Env::base`
+    pub env: Env,
+    /// The ops and constructors marked `#[deprecated]`
+    pub deprecations: Deprecations,
+    /// The definitions of each name defined more than once, and of each
+    /// op marked `#[overload]`
+    pub overloads: HashMap<String, OverloadSet>,
+}
+
+impl Resolution {
+    /// The tables of a module without definitions
+    pub fn empty() -> Self {
+        Resolution {
+            constr_optypes: HashMap::new(),
+            constr_data: HashMap::new(),
+            env: Env::base(
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+                Prelude::default(),
+            ),
+            deprecations: Deprecations::default(),
+            overloads: HashMap::new(),
+        }
+    }
+
+    pub fn new(module: &Module) -> Self {
+        let mut resolution = Resolution::empty();
+        for (name, data_def) in module.data_defs_in_source_order() {
+            resolution.insert_data(name, data_def);
+        }
+        for (name, op_def) in &module.op_defs {
+            resolution.insert_op(name, op_def);
+        }
+        // the first definition of a name defined again, marked or not
+        for (name, set) in resolution.overloads.iter_mut() {
+            if let Some(first) = module.op_defs.get(name) {
+                set.insert(name, first);
+            }
+        }
+        for (name, const_def) in &module.const_defs {
+            resolution.insert_const(name, const_def);
+        }
+        resolution
+    }
+
+    /// Adds the constructors of a data definition, those already defined
+    /// keep their first definition
+    pub fn insert_data(&mut self, name: &str, data_def: &DataDef) {
+        let shared = Arc::new(data_def.clone());
+        for (constr_name, constr_def) in data_def.constrs_in_source_order() {
+            if self.constr_optypes.contains_key(constr_name) {
+                continue;
+            }
+            let optype = constr_optype(name, data_def, constr_def);
+            if let Some(constrs) = self.env.scope_mut(Provenance::Constructor) {
+                constrs.insert(constr_name.clone(), Scheme::generalize(optype.clone()));
+            }
+            self.constr_optypes.insert(constr_name.clone(), optype);
+            self.deprecations
+                .insert_constr(constr_name, data_def, constr_def);
+            self.constr_data
+                .insert(constr_name.clone(), Arc::clone(&shared));
+        }
+    }
+
+    /// Adds an op under its annotation, replacing an op of the same name.
+    /// An overload is also added to the set of its name.
+    pub fn insert_op(&mut self, name: &str, op_def: &OpDef) {
+        if let Some(user) = self.env.scope_mut(Provenance::User) {
+            user.insert(name.to_owned(), Scheme::generalize(op_def.ann.clone()));
+        }
+        self.deprecations.insert_op(name, op_def);
+        if op_def.has_attribute("overload") || name.contains('#') {
+            let set = self.overloads.entry(overloaded_name(name).to_owned());
+            set.or_default().insert(name, op_def);
+        }
+    }
+
+    /// Adds a constant, replacing a constant of the same name
+    pub fn insert_const(&mut self, name: &str, const_def: &ConstDef) {
+        if let Some(consts) = self.env.scope_mut(Provenance::Constant) {
+            consts.insert(name.to_owned(), Scheme::generalize(const_optype(const_def)));
+        }
+    }
+
+    /// Whether a name refers to something, a definition or a prelude op
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.env.lookup(name).is_some()
+    }
+}
@@ -0,0 +1,186 @@
+//! Composing op types, the core of checking: the type of ops run one after
+//! the other is `chain` of their types, and two types are compared after
+//! `augment` makes them as deep as each other. Neither needs an inference,
+//! only fresh variables for the values augmentation passes through, so
+//! search, analysis and codegen can use them on their own.
+//!
+//! The laws, each checked on generated op types by the tests below:
+//!
+//! - `OpType::empty()` is the identity of `chain`, on both sides
+//! - `chain` is associative up to renaming, for op types with no variable
+//!   in common, as types instantiated from schemes are: grouping either
+//!   way gives the same type or fails both ways
+//! - `chain` is monotone under substitution: the chain of two instances by
+//!   the same substitution, if there is one, is an instance of the chain
+//! - `augment` is idempotent, augmenting again towards the same target
+//!   adds nothing
+
+use super::inference::{Subst, Typeable};
+use super::types::{NameSupply, OpType, Type};
+use super::unify::UnifyError;
+
+/// How many slots `general` is padded with towards `target`. Padding orders
+/// op types by subsumption: `[a][b]` is more general than `[a, c][b, c]`,
+/// an op that does not reach a stack slot passes it through and so also has
+/// every type with that slot added to both stacks. Padding only ever adds
+/// slots to both stacks, the difference in stack lengths stays the same
+pub fn padding(general: &OpType, target: &OpType) -> usize {
+    let pre = target.pre.len().saturating_sub(general.pre.len());
+    let post = target.post.len().saturating_sub(general.post.len());
+    pre.min(post)
+}
+
+/// `general` passing fresh variables through below its values, as many as
+/// `padding` says
+pub fn augment(mut general: OpType, target: &OpType, supply: &NameSupply) -> OpType {
+    for _ in 0..padding(&general, target) {
+        general.augment(Type::Poly(supply.fresh()));
+    }
+    general
+}
+
+/// Both op types augmented towards each other
+pub fn augment_both(o1: OpType, o2: OpType, supply: &NameSupply) -> (OpType, OpType) {
+    let o1 = augment(o1, &o2, supply);
+    let o2 = augment(o2, &o1, supply);
+    (o1, o2)
+}
+
+/// The type of running ops of type `ot1`, then ops of type `ot2`. The
+/// topmost values `ot1` leaves are the topmost ones `ot2` takes. An
+/// overflow leaves what `ot2` does not take below what it pushes, an
+/// underflow takes what `ot1` did not push from below what `ot1` takes.
+pub fn chain(ot1: OpType, ot2: OpType) -> Result<OpType, UnifyError> {
+    chain_with(ot1, ot2, Vec::mgu)
+        .map(|(ot, _)| ot)
+        .map_err(UnifyError::from)
+}
+
+/// `chain`, unifying the values passed from one type to the other with
+/// `unify`, and also returning what the chain substituted
+pub(crate) fn chain_with<E>(
+    ot1: OpType,
+    ot2: OpType,
+    unify: impl FnOnce(&Vec<Type>, &Vec<Type>) -> Result<Subst, E>,
+) -> Result<(OpType, Subst), E> {
+    let OpType {
+        pre: alpha,
+        post: beta,
+    } = ot1;
+    let OpType {
+        pre: gamma,
+        post: delta,
+    } = ot2;
+    let l = usize::min(beta.len(), gamma.len());
+    let s = unify(&beta[..l].into(), &gamma[..l].into())?;
+    let (pre, post) = if beta.len() >= gamma.len() {
+        // overflow chain
+        let beta_skip_gamma = beta.into_iter().skip(gamma.len());
+        (alpha, delta.into_iter().chain(beta_skip_gamma).collect())
+    } else {
+        // underflow chain
+        let gamma_skip_beta = gamma.into_iter().skip(beta.len());
+        (alpha.into_iter().chain(gamma_skip_beta).collect(), delta)
+    };
+    Ok((OpType { pre, post }.apply(&s), s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    /// Three op types with no variable in common
+    fn disjoint(rng: &mut Rng) -> [OpType; 3] {
+        [
+            rng.optype(&["a1", "b1"], 2),
+            rng.optype(&["a2", "b2"], 2),
+            rng.optype(&["a3", "b3"], 2),
+        ]
+    }
+
+    const SAMPLES: usize = 3000;
+
+    #[test]
+    fn the_empty_type_is_the_identity() {
+        let mut rng = Rng(1);
+        for _ in 0..SAMPLES {
+            let o = rng.optype(&["a", "b"], 2);
+            assert_eq!(chain(OpType::empty(), o.clone()).unwrap(), o);
+            assert_eq!(chain(o.clone(), OpType::empty()).unwrap(), o);
+        }
+    }
+
+    #[test]
+    fn chaining_is_associative() {
+        let mut rng = Rng(2);
+        for _ in 0..SAMPLES {
+            let [o1, o2, o3] = disjoint(&mut rng);
+            let left = chain(o1.clone(), o2.clone()).and_then(|o| chain(o, o3.clone()));
+            let right = chain(o2.clone(), o3.clone()).and_then(|o| chain(o1.clone(), o));
+            match (left, right) {
+                (Ok(left), Ok(right)) => {
+                    assert_eq!(left.canonical(), right.canonical(), "{} {} {}", o1, o2, o3)
+                }
+                (Err(_), Err(_)) => (),
+                (left, right) => panic!("{} {} {}: {:?} {:?}", o1, o2, o3, left, right),
+            }
+        }
+    }
+
+    #[test]
+    fn chaining_is_monotone_under_substitution() {
+        let mut rng = Rng(3);
+        for _ in 0..SAMPLES {
+            let [o1, o2, _] = disjoint(&mut rng);
+            let mut s = Subst::new();
+            for var in ["a1", "b1", "a2", "b2"] {
+                if rng.below(2) == 0 {
+                    s.insert(var.to_owned(), rng.ty(&["c", "d"], 1));
+                }
+            }
+            let Ok(specific) = chain(o1.apply(&s), o2.apply(&s)) else {
+                continue;
+            };
+            let general = chain(o1.clone(), o2.clone())
+                .unwrap_or_else(|err| panic!("{} {}: {}", o1, o2, err));
+            assert!(
+                OpType::instance_of(&general, &specific).is_ok(),
+                "{} {}: {} is not an instance of {}",
+                o1,
+                o2,
+                specific,
+                general
+            );
+        }
+    }
+
+    #[test]
+    fn augmenting_is_idempotent() {
+        let mut rng = Rng(4);
+        let supply = NameSupply::default();
+        for _ in 0..SAMPLES {
+            let [general, target, _] = disjoint(&mut rng);
+            let once = augment(general.clone(), &target, &supply);
+            assert_eq!(padding(&once, &target), 0);
+            assert_eq!(augment(once.clone(), &target, &supply), once);
+            let (o1, o2) = augment_both(general, target, &supply);
+            assert_eq!((padding(&o1, &o2), padding(&o2, &o1)), (0, 0));
+        }
+    }
+
+    #[test]
+    fn chains_return_what_they_substitute() {
+        let ot1 = OpType {
+            pre: vec![],
+            post: vec![Type::Poly("a".to_owned())],
+        };
+        let ot2 = OpType {
+            pre: vec![Type::Mono("Int".to_owned())],
+            post: vec![],
+        };
+        let (ot, s) = chain_with(ot1, ot2, Vec::mgu).unwrap();
+        assert_eq!(ot, OpType::empty());
+        assert_eq!(s.get("a"), Some(&Type::Mono("Int".to_owned())));
+    }
+}
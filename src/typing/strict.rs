@@ -0,0 +1,34 @@
+/// Extra checks for `Inference`, everything is off by default
+#[derive(Debug, Clone, Default)]
+pub struct StrictOptions {
+    /// Rejects annotations that take away more than this many type
+    /// variables of the inferred body type
+    pub max_pinned_vars: Option<usize>,
+    /// Rejects unchecked ops instead of trusting their annotation
+    pub reject_unchecked: bool,
+    /// Rejects ops and constructors named like a prelude op
+    pub reject_prelude_shadowing: bool,
+    /// Rejects case arms that can never match because earlier arms
+    /// already cover them
+    pub reject_unreachable_arms: bool,
+    /// Rejects annotations giving a value of a type variable that they
+    /// take no value of, see `OpType::output_only_vars`
+    pub reject_output_only_vars: bool,
+    /// Rejects uses of deprecated ops and constructors, see
+    /// `analysis::deprecated`
+    pub reject_deprecated: bool,
+}
+
+impl StrictOptions {
+    /// Every check on, no polymorphism may be pinned by an annotation
+    pub fn all() -> Self {
+        StrictOptions {
+            max_pinned_vars: Some(0),
+            reject_unchecked: true,
+            reject_prelude_shadowing: true,
+            reject_unreachable_arms: true,
+            reject_output_only_vars: true,
+            reject_deprecated: true,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! What the checker does while it checks, to debug the checker itself. A
+//! tracer set with `Inference::with_tracer` gets an event for every step as
+//! it happens, and the report of every op counts the steps checking it
+//! took. Events carry names and sizes rather than types, which can be huge.
+
+use super::types::{OpType, Type};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent<'a> {
+    /// Checking the op definition starts, the events up to the next one are
+    /// of its body
+    CheckOp { name: &'a str },
+    /// The op, as written, chained to the ops before it, making a type of
+    /// the stack lengths and depth
+    Chain {
+        op: &'a str,
+        pre: usize,
+        post: usize,
+        depth: usize,
+    },
+    /// An op type given fresh variables for its `vars` variables
+    Instantiate { vars: usize },
+    /// An arm of the case joined to the arms before it
+    JoinArms {
+        subject: &'a str,
+        pre: usize,
+        post: usize,
+    },
+}
+
+pub type Tracer = Box<dyn Fn(&TraceEvent)>;
+
+/// The steps checking an op took
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckStats {
+    pub unifications: usize,
+    pub chains: usize,
+    pub instantiations: usize,
+    pub arm_joins: usize,
+    /// The most variables a single unification substituted
+    pub max_subst_len: usize,
+    /// The deepest type a chain made, see `depth`
+    pub max_type_depth: usize,
+    /// The type variables made, see `NameSupply::given`
+    pub fresh_vars: usize,
+}
+
+impl CheckStats {
+    /// The counts of both, the larger of the maximums
+    pub fn add(&self, other: &CheckStats) -> CheckStats {
+        CheckStats {
+            unifications: self.unifications + other.unifications,
+            chains: self.chains + other.chains,
+            instantiations: self.instantiations + other.instantiations,
+            arm_joins: self.arm_joins + other.arm_joins,
+            max_subst_len: self.max_subst_len.max(other.max_subst_len),
+            max_type_depth: self.max_type_depth.max(other.max_type_depth),
+            fresh_vars: self.fresh_vars + other.fresh_vars,
+        }
+    }
+
+    /// The step checking took the most of, `unifications` or
+    /// `instantiations`, which the time goes to
+    pub fn dominant_cost(&self) -> &'static str {
+        if self.instantiations > self.unifications {
+            "instantiations"
+        } else {
+            "unifications"
+        }
+    }
+}
+
+/// How deeply the types of the op type nest: a name is 1 deep, an applied
+/// type one deeper than its arguments and a quote type one deeper than the
+/// types on its stacks
+pub fn depth(optype: &OpType) -> usize {
+    fn in_type(t: &Type) -> usize {
+        match t {
+            Type::Mono(_) | Type::Poly(_) | Type::Dyn => 1,
+            Type::App(t1, t2) => in_type(t1).max(in_type(t2) + 1),
+            Type::Op(o) => depth(o) + 1,
+        }
+    }
+    optype
+        .pre
+        .iter()
+        .chain(&optype.post)
+        .map(in_type)
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+    use crate::typing::inference::Inference;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const SOURCE: &str = "
+        data Maybe a: nothing, [a] just.
+        define [Maybe a, a] or-else [a]: case { just { br-1 pop }, nothing { } }.
+        define [] main [Maybe (Maybe Int)]: 1 just just.
+        #[unchecked]
+        define [] trusted [Int]:.
+    ";
+
+    #[test]
+    fn reports_count_the_steps_of_each_op() {
+        let module = parse(SOURCE).unwrap();
+        let report = Inference::new(&module).report();
+        let stats: Vec<_> = report
+            .ops
+            .iter()
+            .map(|op| (&op.name[..], op.stats))
+            .collect();
+        assert_eq!(
+            stats,
+            [
+                (
+                    "or-else",
+                    CheckStats {
+                        unifications: 8,
+                        chains: 3,
+                        instantiations: 5,
+                        arm_joins: 1,
+                        max_subst_len: 3,
+                        max_type_depth: 2,
+                        fresh_vars: 8,
+                    }
+                ),
+                (
+                    "main",
+                    CheckStats {
+                        unifications: 4,
+                        chains: 3,
+                        instantiations: 3,
+                        arm_joins: 0,
+                        max_subst_len: 1,
+                        max_type_depth: 3,
+                        fresh_vars: 2,
+                    }
+                ),
+                ("trusted", CheckStats::default()),
+            ]
+        );
+        assert_eq!(report.stats().chains, 6);
+    }
+
+    #[test]
+    fn tracers_see_every_step() {
+        let module = parse(SOURCE).unwrap();
+        let events = Rc::new(RefCell::new(vec![]));
+        let seen = events.clone();
+        let inference = Inference::new(&module).with_tracer(Box::new(move |event| {
+            seen.borrow_mut().push(format!("{:?}", event))
+        }));
+        inference.report();
+        let events = events.borrow();
+        let main = events
+            .iter()
+            .position(|event| event == "CheckOp { name: \"main\" }")
+            .unwrap();
+        assert_eq!(
+            events[main..main + 7],
+            [
+                "CheckOp { name: \"main\" }",
+                "Chain { op: \"1\", pre: 0, post: 1, depth: 1 }",
+                "Instantiate { vars: 1 }",
+                "Chain { op: \"just\", pre: 0, post: 1, depth: 2 }",
+                "Instantiate { vars: 1 }",
+                "Chain { op: \"just\", pre: 0, post: 1, depth: 3 }",
+                // the annotation
+                "Instantiate { vars: 0 }",
+            ]
+        );
+        assert!(events.iter().any(|event| event.starts_with("JoinArms")));
+    }
+}
@@ -0,0 +1,158 @@
+//! Checking against one module from many threads. A `TypeEnv` holds what
+//! checks look up, the prelude, the constructors and the annotations of
+//! the ops, and never changes, so it is shared as it is. Each check gets an
+//! `Inference` of its own from it, whose `InferCtx` holds the fresh names
+//! and what the check collects.
+
+use super::inference::{InferCtx, Inference, InferenceError, Tables};
+use super::types::OpType;
+use crate::syntax::ast::*;
+use std::sync::Arc;
+
+/// A module and what checking it looks up, `Send` and `Sync`. Clones share
+/// everything.
+#[derive(Clone)]
+pub struct TypeEnv {
+    module: Arc<Module>,
+    tables: Arc<Tables>,
+}
+
+impl TypeEnv {
+    pub(crate) fn new(module: Arc<Module>, tables: Arc<Tables>) -> Self {
+        TypeEnv { module, tables }
+    }
+
+    /// The environment `Inference::new` checks the module in. To declare
+    /// extern ops or change the prelude, set up an `Inference` and
+    /// `Inference::freeze` it instead.
+    pub fn of(module: Module) -> Self {
+        let tables = Inference::new(&module).tables();
+        TypeEnv::new(Arc::new(module), tables)
+    }
+
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// A check of its own against the environment
+    pub fn inference(&self) -> Inference<'_> {
+        self.inference_with(InferCtx::new())
+    }
+
+    /// Same as `inference`, collecting into the given context
+    pub fn inference_with(&self, ctx: InferCtx) -> Inference<'_> {
+        Inference::with_tables(&self.module, self.tables.clone(), ctx)
+    }
+
+    /// The type of the ops as the body of an op of the module, ignoring
+    /// its annotation, with its variables named `a`, `b` and so on. The
+    /// same fragment gets the same type whatever ran before or alongside
+    /// it.
+    pub fn infer_fragment(&self, ops: &[Op]) -> Result<OpType, InferenceError> {
+        self.inference()
+            .infer_ops(ops)
+            .map(|optype| optype.canonical())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{parse, parse_optype};
+    use std::thread;
+
+    const MODULE: &str = "
+data Nat: zero, [Nat] suc.
+data Maybe a: nothing, [a] just.
+define [Nat] pred [Maybe Nat]: case { zero { nothing }, suc { just } }.
+define [a, b] swap [b, a]: br-1.
+";
+
+    /// The body of `define [] f []: <source>.`
+    fn fragment(source: &str) -> Vec<Op> {
+        let module = parse(&format!("define [] f []: {}.", source)).unwrap();
+        module.op_defs["f"].body.clone()
+    }
+
+    fn fragments() -> Vec<Vec<Op>> {
+        (0..32)
+            .map(|i| match i % 4 {
+                0 => fragment(&format!("zero{}", " suc".repeat(i))),
+                1 => fragment(&format!("pred{}", " swap".repeat(i / 4 + 1))),
+                2 => fragment(&format!("{}dup", "(just) ".repeat(i / 4))),
+                _ if i == 31 => fragment("nothing pred"),
+                _ => fragment(&format!("{} zero suc pred", i)),
+            })
+            .collect()
+    }
+
+    fn outcome(result: Result<OpType, InferenceError>) -> String {
+        match result {
+            Ok(optype) => optype.to_string(),
+            Err(err) => err.code().to_owned(),
+        }
+    }
+
+    #[test]
+    fn environments_are_shared_between_threads() {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<TypeEnv>();
+
+        let env = TypeEnv::of(parse(MODULE).unwrap());
+        let fragments = fragments();
+        let expected: Vec<_> = fragments
+            .iter()
+            .map(|ops| outcome(env.infer_fragment(ops)))
+            .collect();
+        assert!(expected.contains(&"UnificationError".to_owned()));
+
+        let results: Vec<Vec<String>> = thread::scope(|scope| {
+            let threads: Vec<_> = (0..32)
+                .map(|i| {
+                    let env = &env;
+                    let fragments = &fragments;
+                    scope.spawn(move || {
+                        // each thread starts at another fragment, in
+                        // another order than the others
+                        let mut outcomes: Vec<_> = (0..fragments.len())
+                            .map(|j| (i + j * 7) % fragments.len())
+                            .map(|k| (k, outcome(env.infer_fragment(&fragments[k]))))
+                            .collect();
+                        outcomes.sort();
+                        outcomes.into_iter().map(|(_, o)| o).collect()
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        for result in results {
+            assert_eq!(result, expected);
+        }
+
+        // and clones go to threads of their own
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let env = env.clone();
+                let ops = fragments[i].clone();
+                thread::spawn(move || outcome(env.infer_fragment(&ops)))
+            })
+            .collect();
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().unwrap(), expected[i]);
+        }
+    }
+
+    #[test]
+    fn frozen_inferences_keep_their_setup() {
+        let module = parse(MODULE).unwrap();
+        let env = Inference::new(&module)
+            .with_extern_op("size", parse_optype("[][Nat]").unwrap())
+            .freeze();
+        assert_eq!(
+            outcome(env.infer_fragment(&fragment("size pred"))),
+            "[][Maybe Nat]"
+        );
+        let env = TypeEnv::of(module);
+        assert_eq!(outcome(env.infer_fragment(&fragment("size"))), "UnknownOp");
+    }
+}
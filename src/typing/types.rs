@@ -1,12 +1,30 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Types are ordered by variant first, `Mono < Poly < Op < App < Dyn`,
+/// then by their contents. Variable names take part in the ordering, compare the
+/// canonical forms to order up to renaming.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Type {
     Mono(String),
     Poly(String),
     Op(OpType),
     App(Box<Type>, Box<Type>),
+    /// A value whose type is only known when it is there, written `?`. It
+    /// fits every type and binds no variable, so the evaluator checks it
+    /// where an op needs a value of a type, see `DynCheck`. Only allowed in
+    /// the annotations of ops without a body.
+    Dyn,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Both stacks list the topmost value first, as annotations are written:
+/// `pre[0]` is on top when the op runs and `post[0]` is on top after it.
+/// The values below the ones an op touches are at the ends, so that is
+/// where chaining and augmenting add the values passed through.
+///
+/// Op types are ordered by their pre stack, then by their post stack
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct OpType {
     pub pre: Vec<Type>,
     pub post: Vec<Type>,
@@ -20,8 +38,604 @@ impl OpType {
         }
     }
 
+    /// Whether the op takes and leaves nothing
+    pub fn is_empty(&self) -> bool {
+        self.pre.is_empty() && self.post.is_empty()
+    }
+
+    /// The type without the values it only passes through, those at the
+    /// bottom of both stacks that are the same variable occurring nowhere
+    /// else. Augmenting it with them gives the type back.
+    pub fn unaugmented(&self) -> OpType {
+        let mut unaugmented = self.clone();
+        while let (Some(Type::Poly(below)), Some(Type::Poly(above))) =
+            (unaugmented.pre.last(), unaugmented.post.last())
+        {
+            let occurrences: usize = (unaugmented.pre.iter())
+                .chain(&unaugmented.post)
+                .map(|t| t.occurrences(below))
+                .sum();
+            if below != above || occurrences != 2 {
+                break;
+            }
+            unaugmented.pre.pop();
+            unaugmented.post.pop();
+        }
+        unaugmented
+    }
+
+    /// The number of types the stacks are built of
+    pub fn size(&self) -> usize {
+        self.pre.iter().chain(&self.post).map(Type::size).sum()
+    }
+
+    /// Whether ops of the type leave every stack as they find it, like
+    /// `br-1 br-1` does
+    pub fn is_identity(&self) -> bool {
+        self.unaugmented().is_empty()
+    }
+
+    /// Whether a `?` occurs anywhere in the stacks
+    pub fn has_dyn(&self) -> bool {
+        self.pre.iter().chain(&self.post).any(Type::has_dyn)
+    }
+
+    /// Passes one more value through, below the others
     pub fn augment(&mut self, t: Type) {
         self.pre.push(t.clone());
         self.post.push(t.clone());
     }
+
+    /// Renames the type variables to `a`, `b`, ... in the order of their
+    /// first appearance, so that alpha-equivalent op types are equal
+    pub fn canonical(&self) -> OpType {
+        Normalizer::default().optype(self)
+    }
+
+    /// The type variables that are a whole value of `post` but occur
+    /// nowhere in `pre` and in no quote type, each with the position of the
+    /// first such value. An op of this type would make a value of every
+    /// type from nothing. A variable under a data type is fine, like the
+    /// `a` of an empty `List a`.
+    pub fn output_only_vars(&self) -> Vec<(usize, &str)> {
+        let in_pre = |var: &str| self.pre.iter().any(|t| t.mentions(var, false));
+        let in_quote = |var: &str| {
+            self.pre
+                .iter()
+                .chain(&self.post)
+                .any(|t| t.mentions(var, true))
+        };
+        let mut found: Vec<(usize, &str)> = vec![];
+        for (i, t) in self.post.iter().enumerate() {
+            let Type::Poly(var) = t else {
+                continue;
+            };
+            if !in_pre(var) && !in_quote(var) && found.iter().all(|(_, seen)| seen != var) {
+                found.push((i, var));
+            }
+        }
+        found
+    }
+}
+
+/// Where a type is in the type it is part of, see `Type::path_to`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeStep {
+    /// The `index`th of the `args` arguments `head` is applied to, the
+    /// first one 0
+    Arg {
+        head: Type,
+        index: usize,
+        args: usize,
+    },
+    /// The type applied to the arguments, when it is no type name
+    Head,
+    /// The `index`th value a quote takes, the topmost one 0
+    Input(usize),
+    /// The `index`th value a quote leaves
+    Output(usize),
+}
+
+/// An op type for every choice of types for its variables, the way the
+/// prelude ops, the annotations and the types inferred for bodies are
+/// meant. The variables belong to the scheme: an `a` of it is not the `a`
+/// of any other type, so an op type meets others only once `instantiate`
+/// named its variables apart.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Scheme {
+    /// In the order they first appear in the body
+    vars: Vec<String>,
+    body: OpType,
+}
+
+impl Scheme {
+    /// The op type with each of its variables quantified
+    pub fn generalize(body: OpType) -> Self {
+        let mut vars = vec![];
+        for t in body.pre.iter().chain(&body.post) {
+            t.collect_vars(&mut vars);
+        }
+        Scheme { vars, body }
+    }
+
+    pub fn vars(&self) -> &[String] {
+        &self.vars
+    }
+
+    /// An op type of the scheme, each variable a fresh one of the supply
+    pub fn instantiate(&self, supply: &NameSupply) -> OpType {
+        self.instantiate_naming(supply).0
+    }
+
+    /// Same as `instantiate`, also giving each fresh variable the variable
+    /// of the scheme it stands for, to tell about the op type in the names
+    /// it was written with
+    pub fn instantiate_naming(&self, supply: &NameSupply) -> (OpType, HashMap<String, String>) {
+        let fresh: HashMap<_, _> = self
+            .vars
+            .iter()
+            .map(|v| (v.clone(), supply.fresh()))
+            .collect();
+        let back = fresh.iter().map(|(v, g)| (g.clone(), v.clone())).collect();
+        let named = Normalizer::naming(fresh).optype(&self.body);
+        (named, back)
+    }
+
+    /// The body with its variables renamed to `a`, `b`, ... to be shown,
+    /// same as `OpType::canonical`. Two schemes have the same canonical
+    /// body when they are the same up to renaming.
+    pub fn canonical(&self) -> OpType {
+        self.body.canonical()
+    }
+}
+
+/// Written as its body, with the variables named as they were given
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.body)
+    }
+}
+
+/// Fresh type variables, `_gen_0`, `_gen_1` and so on, none of them a
+/// name a program can write
+#[derive(Debug, Default)]
+pub struct NameSupply {
+    next: AtomicUsize,
+    /// Every name given, the ones given again after a `rewind` included
+    given: AtomicUsize,
+}
+
+impl NameSupply {
+    pub fn fresh(&self) -> String {
+        self.given.fetch_add(1, Ordering::Relaxed);
+        format!("_gen_{}", self.next.fetch_add(1, Ordering::SeqCst))
+    }
+
+    /// How many names the supply gave so far, counting the ones given
+    /// again after a `rewind` each time
+    pub fn given(&self) -> usize {
+        self.given.load(Ordering::Relaxed)
+    }
+
+    /// Where the supply is, to `rewind` to
+    pub fn mark(&self) -> usize {
+        self.next.load(Ordering::SeqCst)
+    }
+
+    /// Gives the names given since `mark` again, for types that are
+    /// dropped before they meet any other
+    pub fn rewind(&self, mark: usize) {
+        self.next.store(mark, Ordering::SeqCst);
+    }
+}
+
+impl Type {
+    /// Same as `OpType::canonical`
+    pub fn canonical(&self) -> Type {
+        Normalizer::default().ty(self)
+    }
+
+    /// The number of types the type is built of, itself included
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Mono(_) | Type::Poly(_) | Type::Dyn => 1,
+            Type::Op(op_type) => 1 + op_type.size(),
+            Type::App(t1, t2) => 1 + t1.size() + t2.size(),
+        }
+    }
+
+    /// Whether a `?` occurs anywhere in the type
+    pub fn has_dyn(&self) -> bool {
+        match self {
+            Type::Dyn => true,
+            Type::Mono(_) | Type::Poly(_) => false,
+            Type::Op(op_type) => op_type.has_dyn(),
+            Type::App(t1, t2) => t1.has_dyn() || t2.has_dyn(),
+        }
+    }
+
+    /// The steps from the type down to the first occurrence of the
+    /// variable, outermost first, `None` if it does not occur. An
+    /// application is taken as a whole, `Pair a b` is `Pair` applied to
+    /// two arguments.
+    pub fn path_to(&self, var: &str) -> Option<Vec<TypeStep>> {
+        let (step, part) = match self {
+            Type::Mono(_) | Type::Dyn => return None,
+            Type::Poly(v) => return (v == var).then(Vec::new),
+            Type::Op(op_type) => {
+                let inputs = op_type
+                    .pre
+                    .iter()
+                    .enumerate()
+                    .map(|(i, t)| (TypeStep::Input(i), t));
+                let outputs = op_type
+                    .post
+                    .iter()
+                    .enumerate()
+                    .map(|(i, t)| (TypeStep::Output(i), t));
+                inputs
+                    .chain(outputs)
+                    .find(|(_, t)| t.mentions(var, false))?
+            }
+            Type::App(..) => {
+                let mut args = vec![];
+                let mut head = self;
+                while let Type::App(t1, t2) = head {
+                    args.push(&**t2);
+                    head = t1;
+                }
+                args.reverse();
+                match args.iter().position(|t| t.mentions(var, false)) {
+                    Some(index) => {
+                        let step = TypeStep::Arg {
+                            head: head.clone(),
+                            index,
+                            args: args.len(),
+                        };
+                        (step, args[index])
+                    }
+                    None => (TypeStep::Head, head),
+                }
+            }
+        };
+        let mut path = part.path_to(var)?;
+        path.insert(0, step);
+        Some(path)
+    }
+
+    /// Adds the variables of the type not in `vars` yet, in the order
+    /// they appear
+    fn collect_vars(&self, vars: &mut Vec<String>) {
+        match self {
+            Type::Mono(_) | Type::Dyn => (),
+            Type::Poly(v) => {
+                if !vars.contains(v) {
+                    vars.push(v.clone());
+                }
+            }
+            Type::Op(op_type) => {
+                for t in op_type.pre.iter().chain(&op_type.post) {
+                    t.collect_vars(vars);
+                }
+            }
+            Type::App(t1, t2) => {
+                t1.collect_vars(vars);
+                t2.collect_vars(vars);
+            }
+        }
+    }
+
+    fn occurrences(&self, var: &str) -> usize {
+        match self {
+            Type::Mono(_) | Type::Dyn => 0,
+            Type::Poly(v) => usize::from(v == var),
+            Type::Op(op_type) => op_type
+                .pre
+                .iter()
+                .chain(&op_type.post)
+                .map(|t| t.occurrences(var))
+                .sum(),
+            Type::App(t1, t2) => t1.occurrences(var) + t2.occurrences(var),
+        }
+    }
+
+    /// Whether the variable occurs in the type, counting only what is
+    /// inside quote types when `quoted`
+    fn mentions(&self, var: &str, quoted: bool) -> bool {
+        match self {
+            Type::Mono(_) | Type::Dyn => false,
+            Type::Poly(v) => !quoted && v == var,
+            Type::Op(op_type) => op_type
+                .pre
+                .iter()
+                .chain(&op_type.post)
+                .any(|t| t.mentions(var, false)),
+            Type::App(t1, t2) => t1.mentions(var, quoted) || t2.mentions(var, quoted),
+        }
+    }
+}
+
+/// Consistently renames type variables across several types, in the order
+/// they are first seen
+#[derive(Default)]
+pub struct Normalizer {
+    names: HashMap<String, String>,
+    /// Names given up front, which no other variable gets
+    taken: HashSet<String>,
+    next: usize,
+}
+
+fn nth_var_name(n: usize) -> String {
+    let letter = (b'a' + (n % 26) as u8) as char;
+    match n / 26 {
+        0 => letter.to_string(),
+        round => format!("{}{}", letter, round),
+    }
+}
+
+impl Normalizer {
+    /// Renames each variable paired with a name to that name, the others
+    /// to names none of them have
+    pub fn naming(names: impl IntoIterator<Item = (String, String)>) -> Self {
+        let names: HashMap<_, _> = names.into_iter().collect();
+        Normalizer {
+            taken: names.values().cloned().collect(),
+            names,
+            next: 0,
+        }
+    }
+
+    fn fresh_name(&mut self) -> String {
+        loop {
+            let name = nth_var_name(self.next);
+            self.next += 1;
+            if !self.taken.contains(&name) {
+                return name;
+            }
+        }
+    }
+
+    pub fn ty(&mut self, t: &Type) -> Type {
+        match t {
+            Type::Mono(_) | Type::Dyn => t.clone(),
+            Type::Poly(v) => {
+                if !self.names.contains_key(v) {
+                    let name = self.fresh_name();
+                    self.names.insert(v.clone(), name);
+                }
+                Type::Poly(self.names[v].clone())
+            }
+            Type::Op(op_type) => Type::Op(self.optype(op_type)),
+            Type::App(t1, t2) => Type::App(Box::new(self.ty(t1)), Box::new(self.ty(t2))),
+        }
+    }
+
+    pub fn optype(&mut self, op_type: &OpType) -> OpType {
+        let pre = op_type.pre.iter().map(|t| self.ty(t)).collect();
+        let post = op_type.post.iter().map(|t| self.ty(t)).collect();
+        OpType { pre, post }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Mono(name) | Type::Poly(name) => write!(f, "{}", name),
+            Type::Dyn => write!(f, "?"),
+            Type::Op(op_type) => write!(f, "{}", op_type),
+            // application is left associative, only the argument may need parens
+            Type::App(t1, t2) => match t2.as_ref() {
+                Type::App(..) => write!(f, "{} ({})", t1, t2),
+                _ => write!(f, "{} {}", t1, t2),
+            },
+        }
+    }
+}
+
+fn fmt_stack(stack: &[Type], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "[")?;
+    for (i, t) in stack.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", t)?;
+    }
+    write!(f, "]")
+}
+
+impl fmt::Display for OpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_stack(&self.pre, f)?;
+        fmt_stack(&self.post, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+
+    fn corpus() -> Vec<OpType> {
+        let mut rng = Rng(7);
+        (0..60).map(|_| rng.optype(&["x", "y", "z"], 2)).collect()
+    }
+
+    fn rename(t: &Type) -> Type {
+        match t {
+            Type::Poly(v) => Type::Poly(format!("renamed_{}", v)),
+            Type::Mono(_) | Type::Dyn => t.clone(),
+            Type::Op(o) => Type::Op(rename_op(o)),
+            Type::App(t1, t2) => Type::App(Box::new(rename(t1)), Box::new(rename(t2))),
+        }
+    }
+
+    fn rename_op(o: &OpType) -> OpType {
+        OpType {
+            pre: o.pre.iter().map(rename).collect(),
+            post: o.post.iter().map(rename).collect(),
+        }
+    }
+
+    fn hash(o: &OpType) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        o.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn canonical_equality_is_an_equivalence() {
+        let canonical: Vec<_> = corpus().iter().map(OpType::canonical).collect();
+        let equiv = |i: usize, j: usize| canonical[i] == canonical[j];
+        let n = canonical.len();
+        for a in 0..n {
+            assert!(equiv(a, a));
+            for b in 0..n {
+                assert_eq!(equiv(a, b), equiv(b, a));
+                for c in 0..n {
+                    if equiv(a, b) && equiv(b, c) {
+                        assert!(equiv(a, c));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_ignores_variable_names() {
+        for o in corpus() {
+            let renamed = rename_op(&o);
+            assert_eq!(o.canonical(), renamed.canonical());
+            assert_eq!(hash(&o.canonical()), hash(&renamed.canonical()));
+            assert_eq!(o.canonical().canonical(), o.canonical());
+        }
+    }
+
+    #[test]
+    fn canonical_keeps_structure() {
+        let swapped = |v1: &str, v2: &str| OpType {
+            pre: vec![Type::Poly(v1.to_owned()), Type::Poly(v2.to_owned())],
+            post: vec![Type::Poly(v1.to_owned())],
+        };
+        assert_eq!(swapped("x", "y").canonical(), swapped("q", "p").canonical());
+        let dup = OpType {
+            pre: vec![Type::Poly("x".to_owned())],
+            post: vec![Type::Poly("x".to_owned()), Type::Poly("x".to_owned())],
+        };
+        let not_dup = OpType {
+            pre: vec![Type::Poly("x".to_owned())],
+            post: vec![Type::Poly("x".to_owned()), Type::Poly("y".to_owned())],
+        };
+        assert_ne!(dup.canonical(), not_dup.canonical());
+    }
+
+    #[test]
+    fn unaugmenting_strips_only_what_is_passed_through() {
+        let unaugmented = |source: &str| {
+            let optype = crate::syntax::parse_optype(source).unwrap();
+            (optype.unaugmented().to_string(), optype.is_identity())
+        };
+        assert_eq!(
+            unaugmented("[Int, a][Bool, a]"),
+            ("[Int][Bool]".to_owned(), false)
+        );
+        assert_eq!(unaugmented("[a, b][a, b]"), ("[][]".to_owned(), true));
+        assert_eq!(unaugmented("--"), ("[][]".to_owned(), true));
+        // swapped, tied to another value, or not a variable
+        assert_eq!(
+            unaugmented("[a, b][b, a]"),
+            ("[a, b][b, a]".to_owned(), false)
+        );
+        assert_eq!(
+            unaugmented("[a, a][a, a]"),
+            ("[a, a][a, a]".to_owned(), false)
+        );
+        assert_eq!(
+            unaugmented("[[][a], a][a]"),
+            ("[[][a], a][a]".to_owned(), false)
+        );
+        assert_eq!(unaugmented("[Int][Int]"), ("[Int][Int]".to_owned(), false));
+        assert!(OpType::empty().is_empty());
+        assert!(!crate::syntax::parse_optype("[a][a]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn schemes_are_instantiated_apart() {
+        let scheme = Scheme::generalize(crate::syntax::parse_optype("[b, [][a]][a, b]").unwrap());
+        assert_eq!(scheme.vars(), ["b", "a"]);
+        assert_eq!(scheme.to_string(), "[b, [][a]][a, b]");
+        assert_eq!(scheme.canonical().to_string(), "[a, [][b]][b, a]");
+
+        let supply = NameSupply::default();
+        let (first, back) = scheme.instantiate_naming(&supply);
+        assert_eq!(first.to_string(), "[_gen_0, [][_gen_1]][_gen_1, _gen_0]");
+        assert_eq!(back["_gen_1"], "a");
+        // another instance shares no variable with the first, nor with the
+        // scheme's own
+        let second = scheme.instantiate(&supply);
+        assert_eq!(second.to_string(), "[_gen_2, [][_gen_3]][_gen_3, _gen_2]");
+        let mark = supply.mark();
+        scheme.instantiate(&supply);
+        supply.rewind(mark);
+        assert_eq!(supply.fresh(), "_gen_4");
+        assert!(Scheme::generalize(OpType::empty()).vars().is_empty());
+    }
+
+    #[test]
+    fn paths_to_variables() {
+        let path = |t: &str| crate::syntax::parse_type(t).unwrap().path_to("a");
+        let arg = |head: &str, index, args| TypeStep::Arg {
+            head: Type::Mono(head.to_owned()),
+            index,
+            args,
+        };
+        assert_eq!(path("a"), Some(vec![]));
+        assert_eq!(path("Int"), None);
+        assert_eq!(path("Pair b c"), None);
+        assert_eq!(
+            path("Pair (List b) (List a)"),
+            Some(vec![arg("Pair", 1, 2), arg("List", 0, 1)])
+        );
+        // the first occurrence, inputs before outputs
+        assert_eq!(
+            path("[Int, Maybe a][a]"),
+            Some(vec![TypeStep::Input(1), arg("Maybe", 0, 1)])
+        );
+        assert_eq!(path("[][b, a]"), Some(vec![TypeStep::Output(1)]));
+        // the parser has no variables applied to types, unification may
+        let applied = Type::App(
+            Box::new(Type::Poly("a".to_owned())),
+            Box::new(Type::Mono("Int".to_owned())),
+        );
+        assert_eq!(applied.path_to("a"), Some(vec![TypeStep::Head]));
+        // `Pair (List a)` is one of the types too
+        assert_eq!(
+            crate::syntax::parse_type("Pair (List a) Int")
+                .unwrap()
+                .size(),
+            7
+        );
+    }
+
+    #[test]
+    fn ordering_and_dedup() {
+        let int = Type::Mono("Int".to_owned());
+        let var = Type::Poly("a".to_owned());
+        let op = Type::Op(OpType::empty());
+        let app = Type::App(Box::new(int.clone()), Box::new(var.clone()));
+        let mut types = vec![app.clone(), op.clone(), var.clone(), int.clone()];
+        types.sort();
+        assert_eq!(types, vec![int, var, op, app]);
+
+        let corpus = corpus();
+        let distinct: HashSet<_> = corpus.iter().map(OpType::canonical).collect();
+        let renamed: HashSet<_> = corpus
+            .iter()
+            .map(|o| rename_op(o).canonical())
+            .chain(distinct.iter().cloned())
+            .collect();
+        assert_eq!(distinct, renamed);
+    }
 }
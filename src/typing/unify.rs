@@ -0,0 +1,437 @@
+//! Unification on its own, for tools that compare types without inferring
+//! a module. The substitutions and errors here are converted from the ones
+//! inference uses internally, which are free to change.
+
+use super::inference::{self, InferenceErrorMessage, Typeable};
+use super::types::{Normalizer, OpType, Type};
+use std::fmt;
+use std::iter::zip;
+
+/// A substitution of types for type variables
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Subst(inference::Subst);
+
+impl Subst {
+    /// The substitution that replaces nothing
+    pub fn new() -> Self {
+        Subst::default()
+    }
+
+    /// The type the variable is replaced with
+    pub fn get(&self, var: &str) -> Option<&Type> {
+        self.0.get(var)
+    }
+
+    /// The replaced variables and their types, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Type)> {
+        self.0.iter().map(|(var, ty)| (var.as_str(), ty))
+    }
+
+    /// The substitution applying `self`, then `other`
+    ///
+    /// ```
+    /// use iv::syntax::parse_type;
+    /// use iv::typing::unify::{apply_subst, unify_types};
+    ///
+    /// let s1 = unify_types(&parse_type("a").unwrap(), &parse_type("Maybe b").unwrap()).unwrap();
+    /// let s2 = unify_types(&parse_type("b").unwrap(), &parse_type("Int").unwrap()).unwrap();
+    /// let s = s1.compose(s2);
+    /// assert_eq!(apply_subst(&parse_type("a").unwrap(), &s), parse_type("Maybe Int").unwrap());
+    /// ```
+    pub fn compose(self, other: Subst) -> Subst {
+        Subst(inference::compose(self.0, other.0))
+    }
+}
+
+/// Why two types do not unify
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum UnifyError {
+    /// The types have different shapes or type names, `t1` and `t2` are
+    /// the parts that differ
+    Mismatch { t1: Type, t2: Type },
+    /// The variable would have to be replaced by a type containing it
+    Occurs { var: String, ty: Type },
+    /// Two stacks of an op type have different lengths
+    StackLengths,
+}
+
+impl From<InferenceErrorMessage> for UnifyError {
+    fn from(error: InferenceErrorMessage) -> Self {
+        match error {
+            InferenceErrorMessage::UnificationError { t1, t2 } => UnifyError::Mismatch { t1, t2 },
+            InferenceErrorMessage::OccursCheck { name, ty, .. } => {
+                UnifyError::Occurs { var: name, ty }
+            }
+            InferenceErrorMessage::ListMGULengthDifferent => UnifyError::StackLengths,
+            error => unreachable!("unification does not fail with {:?}", error),
+        }
+    }
+}
+
+impl fmt::Display for UnifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut n = Normalizer::default();
+        match self {
+            UnifyError::Mismatch { t1, t2 } => {
+                write!(f, "cannot unify {} with {}", n.ty(t1), n.ty(t2))
+            }
+            UnifyError::Occurs { var, ty } => write!(
+                f,
+                "type variable {} occurs in {}, the type it is unified with",
+                n.ty(&Type::Poly(var.to_owned())),
+                n.ty(ty)
+            ),
+            UnifyError::StackLengths => {
+                write!(f, "stacks of different lengths cannot be unified")
+            }
+        }
+    }
+}
+
+impl std::error::Error for UnifyError {}
+
+/// The most general substitution making both types equal
+///
+/// ```
+/// use iv::syntax::parse_type;
+/// use iv::typing::unify::{unify_types, UnifyError};
+///
+/// let s = unify_types(&parse_type("Pair a Int").unwrap(), &parse_type("Pair Bool b").unwrap()).unwrap();
+/// assert_eq!(s.get("a"), Some(&parse_type("Bool").unwrap()));
+/// assert_eq!(s.get("b"), Some(&parse_type("Int").unwrap()));
+///
+/// let err = unify_types(&parse_type("a").unwrap(), &parse_type("List a").unwrap()).unwrap_err();
+/// assert!(matches!(err, UnifyError::Occurs { .. }));
+/// ```
+pub fn unify_types(t1: &Type, t2: &Type) -> Result<Subst, UnifyError> {
+    Ok(Subst(Type::mgu(t1, t2)?))
+}
+
+/// The most general substitution making both op types equal, the stacks are
+/// not padded, so they have to have the same lengths
+///
+/// ```
+/// use iv::syntax::parse_optype;
+/// use iv::typing::unify::{apply_subst_optype, unify_optypes, UnifyError};
+///
+/// let t1 = parse_optype("a, a -> a").unwrap();
+/// let s = unify_optypes(&t1, &parse_optype("Int, b -> b").unwrap()).unwrap();
+/// assert_eq!(apply_subst_optype(&t1, &s), parse_optype("Int, Int -> Int").unwrap());
+///
+/// let err = unify_optypes(&t1, &parse_optype("a -> a").unwrap()).unwrap_err();
+/// assert_eq!(err, UnifyError::StackLengths);
+/// ```
+pub fn unify_optypes(t1: &OpType, t2: &OpType) -> Result<Subst, UnifyError> {
+    Ok(Subst(OpType::mgu(t1, t2)?))
+}
+
+/// Replaces the type variables of `ty` that `s` replaces
+pub fn apply_subst(ty: &Type, s: &Subst) -> Type {
+    ty.apply(&s.0)
+}
+
+/// Replaces the type variables of both stacks that `s` replaces
+pub fn apply_subst_optype(op_type: &OpType, s: &Subst) -> OpType {
+    op_type.apply(&s.0)
+}
+
+/// A position inside an op type, from the outside in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// The value of the pre stack at this depth, 0 is the topmost
+    Pre(usize),
+    Post(usize),
+    /// The type an application applies
+    Fun,
+    /// The type an application applies it to
+    Arg,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Pre(depth) => write!(f, "pre value {}", depth),
+            Step::Post(depth) => write!(f, "post value {}", depth),
+            Step::Fun => write!(f, "applied type"),
+            Step::Arg => write!(f, "type argument"),
+        }
+    }
+}
+
+/// Why a type is not an instance of another, at the position of the parts
+/// that differ. Positions in a plain type start from the type, the first
+/// step of a position in an op type is a stack value.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum TypeOrderError {
+    /// `general` has another type name or shape than `concrete` there, or
+    /// is a variable that already stands for another type
+    Elem {
+        general: Type,
+        concrete: Type,
+        at: Vec<Step>,
+    },
+    /// Quote types, or the op types themselves, with stacks of different
+    /// lengths
+    Op {
+        general: OpType,
+        concrete: OpType,
+        at: Vec<Step>,
+    },
+}
+
+fn fmt_position(at: &[Step]) -> String {
+    match at {
+        [] => String::new(),
+        _ => {
+            let steps: Vec<_> = at.iter().map(Step::to_string).collect();
+            format!(", at {}", steps.join(", "))
+        }
+    }
+}
+
+impl fmt::Display for TypeOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // a variable named the same on both sides is the same variable
+        let mut n = Normalizer::default();
+        match self {
+            TypeOrderError::Elem {
+                general,
+                concrete,
+                at,
+            } => write!(
+                f,
+                "{} is not more general than {}{}",
+                n.ty(general),
+                n.ty(concrete),
+                fmt_position(at)
+            ),
+            TypeOrderError::Op {
+                general,
+                concrete,
+                at,
+            } => write!(
+                f,
+                "{} and {} have stacks of different lengths{}",
+                n.optype(general),
+                n.optype(concrete),
+                fmt_position(at)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TypeOrderError {}
+
+/// Binds the variables of `general` so that it becomes `concrete`, which
+/// is not changed: its variables are as fixed as its type names
+fn match_type(
+    general: &Type,
+    concrete: &Type,
+    s: &mut inference::Subst,
+    at: &mut Vec<Step>,
+) -> Result<(), TypeOrderError> {
+    let mismatch = |at: &[Step]| TypeOrderError::Elem {
+        general: general.clone(),
+        concrete: concrete.clone(),
+        at: at.to_vec(),
+    };
+    match (general, concrete) {
+        (Type::Poly(v), _) => match s.get(v) {
+            Some(bound) if bound != concrete => Err(mismatch(at)),
+            Some(_) => Ok(()),
+            None => {
+                s.insert(v.clone(), concrete.clone());
+                Ok(())
+            }
+        },
+        (Type::Mono(name1), Type::Mono(name2)) if name1 == name2 => Ok(()),
+        (Type::Dyn, Type::Dyn) => Ok(()),
+        (Type::App(fun1, arg1), Type::App(fun2, arg2)) => {
+            for (step, t1, t2) in [(Step::Fun, fun1, fun2), (Step::Arg, arg1, arg2)] {
+                at.push(step);
+                match_type(t1, t2, s, at)?;
+                at.pop();
+            }
+            Ok(())
+        }
+        (Type::Op(o1), Type::Op(o2)) => match_optype(o1, o2, s, at),
+        _ => Err(mismatch(at)),
+    }
+}
+
+fn match_optype(
+    general: &OpType,
+    concrete: &OpType,
+    s: &mut inference::Subst,
+    at: &mut Vec<Step>,
+) -> Result<(), TypeOrderError> {
+    if general.pre.len() != concrete.pre.len() || general.post.len() != concrete.post.len() {
+        return Err(TypeOrderError::Op {
+            general: general.clone(),
+            concrete: concrete.clone(),
+            at: at.clone(),
+        });
+    }
+    let pre = zip(&general.pre, &concrete.pre)
+        .enumerate()
+        .map(|(depth, pair)| (Step::Pre(depth), pair));
+    let post = zip(&general.post, &concrete.post)
+        .enumerate()
+        .map(|(depth, pair)| (Step::Post(depth), pair));
+    for (step, (t1, t2)) in pre.chain(post) {
+        at.push(step);
+        match_type(t1, t2, s, at)?;
+        at.pop();
+    }
+    Ok(())
+}
+
+impl Type {
+    /// The substitution of the variables of `general` alone that turns it
+    /// into `concrete`, when `concrete` is an instance of `general`. Unlike
+    /// unifying, a variable of `concrete` only matches a variable, and two
+    /// of them never merge.
+    ///
+    /// ```
+    /// use iv::syntax::parse_type;
+    /// use iv::typing::types::Type;
+    ///
+    /// let general = parse_type("Pair a a").unwrap();
+    /// let s = Type::instance_of(&general, &parse_type("Pair b b").unwrap()).unwrap();
+    /// assert_eq!(s.get("a"), Some(&parse_type("b").unwrap()));
+    /// assert!(Type::instance_of(&general, &parse_type("Pair b c").unwrap()).is_err());
+    /// assert!(Type::instance_of(&parse_type("Pair b c").unwrap(), &general).is_ok());
+    /// ```
+    pub fn instance_of(general: &Type, concrete: &Type) -> Result<Subst, TypeOrderError> {
+        let mut s = inference::Subst::new();
+        match_type(general, concrete, &mut s, &mut vec![])?;
+        Ok(Subst(s))
+    }
+}
+
+impl OpType {
+    /// Same as `Type::instance_of`, the stacks are not padded, so they have
+    /// to have the same lengths
+    pub fn instance_of(general: &OpType, concrete: &OpType) -> Result<Subst, TypeOrderError> {
+        let mut s = inference::Subst::new();
+        match_optype(general, concrete, &mut s, &mut vec![])?;
+        Ok(Subst(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{parse_optype, parse_type};
+
+    fn order(general: &str, concrete: &str) -> Result<Subst, TypeOrderError> {
+        Type::instance_of(
+            &parse_type(general).unwrap(),
+            &parse_type(concrete).unwrap(),
+        )
+    }
+
+    #[test]
+    fn instances_of_types() {
+        let instances = [
+            ("a", "Int"),
+            ("a", "b"),
+            ("a", "a"),
+            ("a", "Maybe (List b)"),
+            ("Int", "Int"),
+            ("Maybe a", "Maybe Int"),
+            ("Pair a b", "Pair Int Int"),
+            ("Pair a b", "Pair c c"),
+            ("Pair a a", "Pair (List b) (List b)"),
+            ("Either (Maybe a) b", "Either (Maybe [][Int]) [Int][]"),
+            ("[a][a]", "[Int][Int]"),
+            ("[a, b][b]", "[c, c][c]"),
+            ("[[a][b]][b]", "[[Int][Maybe c]][Maybe c]"),
+            ("Maybe [a][a]", "Maybe [b][b]"),
+        ];
+        for (general, concrete) in instances {
+            assert!(order(general, concrete).is_ok(), "{} {}", general, concrete);
+        }
+        let s = order("Pair a (Maybe b)", "Pair Int (Maybe c)").unwrap();
+        assert_eq!(s.get("a"), Some(&parse_type("Int").unwrap()));
+        assert_eq!(s.get("b"), Some(&parse_type("c").unwrap()));
+        assert_eq!(s.iter().count(), 2);
+    }
+
+    #[test]
+    fn types_that_are_not_instances() {
+        let elem = |general, concrete, at: &[Step]| (general, concrete, at.to_vec());
+        let elems = [
+            // the concrete side is more general, or they are unrelated
+            ("Int", "a", elem("Int", "a", &[])),
+            ("Int", "Bool", elem("Int", "Bool", &[])),
+            ("Maybe a", "a", elem("Maybe a", "a", &[])),
+            ("Maybe a", "List Int", elem("Maybe", "List", &[Step::Fun])),
+            ("Pair a a", "Pair b c", elem("a", "c", &[Step::Arg])),
+            ("Pair a a", "Pair Int Bool", elem("a", "Bool", &[Step::Arg])),
+            // a concrete variable is not a type the general one can become
+            ("Pair a Int", "Pair b b", elem("Int", "b", &[Step::Arg])),
+            ("[a][a]", "[Int][Bool]", elem("a", "Bool", &[Step::Post(0)])),
+            ("[a, a][]", "[b, c][]", elem("a", "c", &[Step::Pre(1)])),
+            (
+                "Maybe [a][a]",
+                "Maybe [b][Int]",
+                elem("a", "Int", &[Step::Arg, Step::Post(0)]),
+            ),
+            (
+                "[[Int][a]][a]",
+                "[[b][c]][c]",
+                elem("Int", "b", &[Step::Pre(0), Step::Pre(0)]),
+            ),
+            ("[a][a]", "Int", elem("[a][a]", "Int", &[])),
+        ];
+        for (general, concrete, (g, c, at)) in elems {
+            let expected = TypeOrderError::Elem {
+                general: parse_type(g).unwrap(),
+                concrete: parse_type(c).unwrap(),
+                at,
+            };
+            assert_eq!(
+                order(general, concrete).unwrap_err(),
+                expected,
+                "{} {}",
+                general,
+                concrete
+            );
+        }
+        let ops = [
+            ("[a][a]", "[Int][]", vec![]),
+            ("[[a][a]][]", "[[a, b][a, b]][]", vec![Step::Pre(0)]),
+            ("List [a][]", "List [][]", vec![Step::Arg]),
+        ];
+        for (general, concrete, expected) in ops {
+            assert!(
+                matches!(order(general, concrete), Err(TypeOrderError::Op { at, .. }) if at == expected),
+                "{} {}",
+                general,
+                concrete
+            );
+        }
+    }
+
+    #[test]
+    fn op_types_are_not_padded() {
+        let general = parse_optype("a -> a").unwrap();
+        assert!(OpType::instance_of(&general, &parse_optype("Int -> Int").unwrap()).is_ok());
+        assert!(OpType::instance_of(&general, &parse_optype("Int, b -> Int, b").unwrap()).is_err());
+        assert_eq!(
+            OpType::instance_of(&general, &parse_optype("b -> Int").unwrap())
+                .unwrap_err()
+                .to_string(),
+            "a is not more general than Int, at post value 0"
+        );
+        assert_eq!(
+            OpType::instance_of(&general, &parse_optype("->").unwrap())
+                .unwrap_err()
+                .to_string(),
+            "[a][a] and [][] have stacks of different lengths"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Checking a module held in memory, for hosts without an OS such as the
+//! playground on wasm32-unknown-unknown. Nothing here reads files, spawns
+//! threads or looks at the clock. A `#[wasm_bindgen]` wrapper only has to
+//! hand the result of `check_source` to `JSON.parse` to get a `JsValue`.
+
+use crate::analyze::{analyze, AnalyzeOptions};
+use crate::diagnostics::json_string;
+use crate::typing::report::OpOutcome;
+
+/// Parses and typechecks `source`, returning a JSON object
+///
+/// `{"ok": bool, "diagnostics": [...], "ops": [{"name", "type", "outcome"}]}`
+/// where each diagnostic is `Diagnostic::to_json` and `type` is the inferred
+/// type of the op or `null`. Definitions that do not parse are left out of
+/// `ops`, a desugaring error leaves it empty.
+pub fn check_source(source: &str) -> String {
+    let result = analyze(source, AnalyzeOptions::default());
+    let diagnostics: Vec<_> = result
+        .diagnostics
+        .iter()
+        .map(|(_, diagnostic)| diagnostic.to_json(source))
+        .collect();
+    let ops: Vec<_> = result
+        .report
+        .iter()
+        .flat_map(|report| report.ops.iter())
+        .map(|op| {
+            let (ty, outcome) = match &op.outcome {
+                OpOutcome::Inferred(inferred) => {
+                    (json_string(&inferred.canonical().to_string()), "inferred")
+                }
+                OpOutcome::Failed(_) => ("null".to_owned(), "failed"),
+                OpOutcome::Unchecked => ("null".to_owned(), "unchecked"),
+                OpOutcome::Cancelled => ("null".to_owned(), "cancelled"),
+                OpOutcome::TimedOut => ("null".to_owned(), "timed out"),
+                OpOutcome::Skipped(_) => ("null".to_owned(), "skipped"),
+                OpOutcome::Declared => ("null".to_owned(), "declared"),
+                OpOutcome::ExpectedError(_) => ("null".to_owned(), "expected error"),
+            };
+            format!(
+                "{{\"name\":{},\"type\":{},\"outcome\":\"{}\"}}",
+                json_string(&op.name),
+                ty,
+                outcome
+            )
+        })
+        .collect();
+    format!(
+        "{{\"ok\":{},\"diagnostics\":[{}],\"ops\":[{}]}}",
+        result.is_ok(),
+        diagnostics.join(","),
+        ops.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_source;
+
+    #[test]
+    fn well_typed() {
+        assert_eq!(
+            check_source("define [a] twice [a, a]: dup. define [Int] nocx [Int]:."),
+            "{\"ok\":true,\"diagnostics\":[],\"ops\":[\
+             {\"name\":\"twice\",\"type\":\"[a][a, a]\",\"outcome\":\"inferred\"},\
+             {\"name\":\"nocx\",\"type\":null,\"outcome\":\"unchecked\"}]}"
+        );
+    }
+
+    #[test]
+    fn type_errors() {
+        let json = check_source("define [] main []: nope.");
+        assert!(json.starts_with("{\"ok\":false,\"diagnostics\":[{\"message\":"));
+        assert!(json.contains("\"start\":19,\"end\":23"));
+        assert!(
+            json.ends_with("\"ops\":[{\"name\":\"main\",\"type\":null,\"outcome\":\"failed\"}]}")
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let json = check_source("define main");
+        assert!(json.starts_with("{\"ok\":false,\"diagnostics\":[{\"message\":\"unexpected"));
+        assert!(json.ends_with("\"ops\":[]}"));
+    }
+
+    #[test]
+    fn definitions_after_a_parse_error() {
+        let json = check_source("define [] f [: 1. define [] one [Int]: 1.");
+        assert!(
+            json.starts_with("{\"ok\":false,\"diagnostics\":[{\"message\":\"`[` is never closed")
+        );
+        assert!(json.ends_with(
+            "\"ops\":[{\"name\":\"one\",\"type\":\"[][Int]\",\"outcome\":\"inferred\"}]}"
+        ));
+    }
+}
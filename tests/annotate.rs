@@ -0,0 +1,61 @@
+//! Takes the annotations away from `tests/annotate/annotated.iv`, runs
+//! `iv annotate --write` on it and checks that the annotations it writes
+//! are the ones taken away, up to the names of their variables.
+
+use iv::analyze::{analyze, AnalyzeOptions};
+use iv::syntax::parse;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+#[test]
+fn annotating_gives_back_the_annotations_taken_away() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/annotate/annotated.iv");
+    let source = fs::read_to_string(path).unwrap();
+    let original = parse(&source).unwrap();
+
+    // every checked op's annotation made `[] name []`
+    let mut stripped = source.clone();
+    let mut heads: Vec<_> = original
+        .op_defs
+        .iter()
+        .filter(|(_, op_def)| !op_def.has_attribute("unchecked"))
+        .map(|(name, op_def)| {
+            let colon = source[op_def.span.start..].find(':').unwrap();
+            (op_def.span.start, op_def.span.start + colon, name)
+        })
+        .collect();
+    heads.sort();
+    for (start, end, name) in heads.into_iter().rev() {
+        stripped.replace_range(start..end, &format!("define [] {} []", name));
+    }
+    assert!(!analyze(&stripped, AnalyzeOptions::default()).is_ok());
+
+    let file = env::temp_dir().join(format!("iv-annotate-{}.iv", process::id()));
+    fs::write(&file, &stripped).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_iv"))
+        .arg("annotate")
+        .arg("--write")
+        .arg(&file)
+        .output()
+        .unwrap();
+    let annotated = fs::read_to_string(&file).unwrap();
+    fs::remove_file(&file).unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stderr.is_empty(), "{:?}", output);
+
+    assert!(analyze(&annotated, AnalyzeOptions::default()).is_ok());
+    let module = parse(&annotated).unwrap();
+    for (name, op_def) in &original.op_defs {
+        assert_eq!(
+            module.op_defs[name].ann.canonical(),
+            op_def.ann.canonical(),
+            "{}",
+            name
+        );
+    }
+    // only the annotations changed
+    assert_eq!(annotated.lines().count(), source.lines().count());
+    assert!(annotated.starts_with("// every annotation is the type its body infers"));
+}
@@ -0,0 +1,150 @@
+//! Times the benchmark ops of `tests/bench/suite.iv` with a clock that only
+//! moves as the tests say, so the runs and the times are always the same.
+
+use iv::bench::{discover, parse_baseline, run, run_benches, BenchConfig, BenchError, Clock};
+use iv::desugar::desugar;
+use iv::evaluation::evaluator::Evaluator;
+use iv::evaluation::types::ExecLimits;
+use iv::syntax::ast::Module;
+use iv::syntax::parse;
+use iv::typing::inference::Inference;
+use std::cell::Cell;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+fn suite() -> (String, Module) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/bench/suite.iv");
+    let source = fs::read_to_string(path).unwrap();
+    let module = desugar(parse(&source).unwrap()).unwrap();
+    (source, module)
+}
+
+/// Gives the times in order, then the last one again and again
+struct FakeClock {
+    times: Vec<Duration>,
+    next: Cell<usize>,
+}
+
+impl FakeClock {
+    fn millis(times: impl IntoIterator<Item = u64>) -> Self {
+        FakeClock {
+            times: times.into_iter().map(Duration::from_millis).collect(),
+            next: Cell::new(0),
+        }
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Duration {
+        let next = self.next.get();
+        self.next.set(next + 1);
+        self.times[next.min(self.times.len() - 1)]
+    }
+}
+
+fn config() -> BenchConfig {
+    BenchConfig {
+        warmup: Duration::from_millis(5),
+        target: Duration::from_millis(20),
+        samples: 4,
+        ..BenchConfig::default()
+    }
+}
+
+#[test]
+fn runs_follow_from_the_warmup() {
+    let (_, module) = suite();
+    let mut evaluator = Evaluator::new(&module);
+    // the warmup takes 1ms a run, the samples 1, 1, 2 and 5ms for 5 runs
+    let clock = FakeClock::millis([0, 1, 2, 3, 4, 5, 10, 11, 20, 21, 30, 32, 40, 45]);
+    let result = run(&module, &mut evaluator, "bench-double", &config(), &clock).unwrap();
+    assert_eq!((result.warmup_runs, result.runs_per_sample), (5, 5));
+    let micros = |d: Duration| d.as_micros();
+    assert_eq!(
+        result
+            .samples
+            .iter()
+            .copied()
+            .map(micros)
+            .collect::<Vec<_>>(),
+        [200, 200, 400, 1000]
+    );
+    assert_eq!((micros(result.median), micros(result.mad)), (400, 200));
+    assert_eq!(result.steps, evaluator.steps());
+    assert!(evaluator.stack.is_empty());
+
+    // a clock too coarse to tell stops the warmup at the most runs
+    let clock = FakeClock::millis([0]);
+    let config = BenchConfig {
+        max_runs: 7,
+        ..config()
+    };
+    let result = run(&module, &mut evaluator, "bench-answer", &config, &clock).unwrap();
+    assert_eq!((result.warmup_runs, result.runs_per_sample), (7, 7));
+    assert_eq!(result.median, Duration::ZERO);
+}
+
+#[test]
+fn reports() {
+    let (source, module) = suite();
+    assert!(Inference::new(&module).typecheck().is_ok());
+    let names: Vec<_> = discover(&module, "")
+        .into_iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "bench-double",
+            "bench-answer",
+            "doubling-twice",
+            "bench-runs-forever",
+            "bench-takes-a-value"
+        ]
+    );
+    let mut evaluator = Evaluator::new(&module).with_limits(ExecLimits {
+        max_call_depth: Some(64),
+        ..ExecLimits::default()
+    });
+    let clock = FakeClock::millis(0..1000);
+    let report = run_benches(&module, &mut evaluator, "", &config(), &clock);
+    assert!(!report.is_ok());
+    let outcomes: Vec<_> = report
+        .outcomes
+        .iter()
+        .map(|outcome| match &outcome.result {
+            Ok(result) => format!("{:?} {}", result.median, result.steps),
+            Err(BenchError::Runtime(err)) => err.message(),
+            Err(BenchError::NotABench(ann)) => format!("not a bench, {}", ann),
+            Err(err) => panic!("{:?}", err),
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            "200µs 20",
+            "200µs 1",
+            "200µs 21",
+            "exceeded the limit on call depth",
+            "not a bench, [Nat][]",
+        ]
+    );
+
+    let baseline = parse_baseline(&report.baseline()).unwrap();
+    assert_eq!(baseline.len(), 3);
+    assert_eq!(baseline["bench-answer"], 200_000);
+    let mut halved = baseline.clone();
+    halved.insert("bench-double".to_owned(), 100_000);
+    halved.remove("bench-answer");
+    let rendered = report.render(&source, Some(&halved));
+    assert!(rendered.starts_with(
+        "bench bench-double ... 200µs/run ± 0ns, 4 samples of 5 runs, 20 steps, 2.00x baseline
+bench bench-answer ... 200µs/run ± 0ns, 4 samples of 5 runs, 1 steps, no baseline
+"
+    ));
+    assert!(rendered.contains("bench bench-runs-forever ... ERROR\n"));
+    assert!(rendered
+        .contains("`bench-takes-a-value` has type [Nat][], a benchmark has type [][] or [][Int]"));
+    assert_eq!(parse_baseline("{ \"a\": x }"), None);
+}
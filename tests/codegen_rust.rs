@@ -0,0 +1,62 @@
+//! Builds the Rust generated for `tests/codegen/sample.iv` in a scratch
+//! crate and compares its output to the interpreter's. Needs a working
+//! cargo, run with `cargo test --test codegen_rust -- --ignored`.
+
+use iv::codegen::rust::codegen_rust;
+use iv::evaluation::display::{display_stack, DisplayOptions};
+use iv::evaluation::evaluator::Evaluator;
+use iv::syntax::parse;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+const MAIN: &str = r#"mod generated;
+
+fn main() {
+    let stack = generated::run_main();
+    let values: Vec<_> = stack.iter().rev().map(|v| format!("{:?}", v)).collect();
+    println!("[{}]", values.join(", "));
+}
+"#;
+
+#[test]
+#[ignore]
+fn generated_code_builds_and_agrees_with_the_interpreter() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/codegen/sample.iv");
+    let source = fs::read_to_string(path).unwrap();
+    let module = parse(&source).unwrap();
+
+    let dir = env::temp_dir().join(format!("iv-codegen-{}", process::id()));
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"iv-codegen-check\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+    )
+    .unwrap();
+    fs::write(dir.join("src/generated.rs"), codegen_rust(&module)).unwrap();
+    fs::write(dir.join("src/main.rs"), MAIN).unwrap();
+
+    let output = Command::new(env!("CARGO"))
+        .args(["run", "--quiet", "--manifest-path"])
+        .arg(dir.join("Cargo.toml"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "generated code failed:\n{}",
+        stderr
+    );
+
+    let mut evaluator = Evaluator::new(&module);
+    evaluator.eval_main().unwrap();
+    // closures can't be shown, compare with the quote ops elided
+    let opts = DisplayOptions {
+        show_quote_ops: false,
+        ..DisplayOptions::default()
+    };
+    let expected = display_stack(&evaluator.stack, &opts);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), expected);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,65 @@
+//! Runs `tests/coverage/unreachable.iv`, which never takes some of its arms
+//! and never calls one of its ops, and checks that coverage reports them.
+
+use iv::desugar::desugar;
+use iv::evaluation::coverage::UncoveredItem;
+use iv::evaluation::evaluator::Evaluator;
+use iv::syntax::parse;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn unreachable_arms_and_ops_are_reported() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/coverage/unreachable.iv");
+    let source = fs::read_to_string(path).unwrap();
+    let module = desugar(parse(&source).unwrap()).unwrap();
+    let mut evaluator = Evaluator::new(&module).with_coverage();
+    evaluator.eval_main().unwrap();
+
+    let coverage = evaluator.coverage().unwrap();
+    assert!(coverage.op_ran("positive") && coverage.op_ran("both"));
+    let uncovered = coverage.uncovered(&module);
+    let items: Vec<_> = uncovered
+        .items
+        .iter()
+        .map(|item| match item {
+            UncoveredItem::Op { name, span } => {
+                format!("op {} at {}", name, &source[span.start..span.end])
+            }
+            UncoveredItem::Arm { op, patterns, span } => {
+                let text = &source[span.start..span.end];
+                format!("arm {} of {} at {}", patterns, op, text)
+            }
+        })
+        .collect();
+    assert_eq!(
+        items,
+        [
+            "arm zero of positive at zero { false }",
+            "arm _ _ of both at _ _ { pop pop false }",
+            "op unused at unused",
+        ]
+    );
+    assert_eq!((uncovered.op_count, uncovered.arm_count), (4, 4));
+    assert_eq!(
+        uncovered.render(&source).lines().collect::<Vec<_>>(),
+        [
+            "coverage: 3 of 4 ops, 2 of 4 arms of the ops that ran",
+            "8:9: arm `zero` of `positive` never ran",
+            "15:9: arm `_ _` of `both` never ran",
+            "18:14: op `unused` never ran",
+        ]
+    );
+    assert!(uncovered.to_json(&source).starts_with(
+        "{\"ops\":4,\"arms\":4,\"uncovered\":[{\"kind\":\"arm\",\"name\":\"zero\",\
+         \"op\":\"positive\",\"start\":"
+    ));
+}
+
+#[test]
+fn coverage_is_off_by_default() {
+    let module = parse("define [] main []: .").unwrap();
+    let mut evaluator = Evaluator::new(&module);
+    evaluator.eval_main().unwrap();
+    assert!(evaluator.coverage().is_none());
+}
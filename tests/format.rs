@@ -0,0 +1,77 @@
+//! Formats every `tests/cases/*.iv` fixture that parses with each of a
+//! grid of options, and checks that the output parses to the same
+//! definitions and that formatting it again changes nothing.
+
+use iv::format::{format_ranges_with, AnnotationStyle, CaseArmAlignment, FormatOptions};
+use iv::refactor::TextEdit;
+use iv::syntax::ast::Span;
+use iv::syntax::parse;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn grid() -> Vec<FormatOptions> {
+    let mut grid = vec![];
+    for max_width in [0, 24, 60, 100] {
+        for (indent, annotation_style) in [
+            (2, AnnotationStyle::NextLine),
+            (4, AnnotationStyle::SameLine),
+        ] {
+            for case_arm_alignment in [CaseArmAlignment::Unaligned, CaseArmAlignment::Braces] {
+                for (quote_break_threshold, trailing_newline) in [(0, false), (4, true)] {
+                    grid.push(FormatOptions {
+                        max_width,
+                        indent,
+                        annotation_style,
+                        case_arm_alignment,
+                        quote_break_threshold,
+                        trailing_newline,
+                    });
+                }
+            }
+        }
+    }
+    grid
+}
+
+fn format(src: &str, options: &FormatOptions) -> Vec<TextEdit> {
+    let module = parse(src).unwrap();
+    format_ranges_with(src, &module, &[Span::new(0, src.len())], options)
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "iv"))
+        .collect();
+    paths.sort();
+    let grid = grid();
+    for path in paths {
+        let src = fs::read_to_string(&path).unwrap();
+        let Ok(module) = parse(&src) else {
+            continue;
+        };
+        for options in &grid {
+            let formatted = TextEdit::apply_all(&src, &format(&src, options));
+            let reparsed = parse(&formatted)
+                .unwrap_or_else(|err| panic!("{}, {:?}: {:?}", path.display(), options, err));
+            assert_eq!(
+                reparsed.to_string(),
+                module.to_string(),
+                "{}, {:?}",
+                path.display(),
+                options
+            );
+            assert!(
+                format(&formatted, options).is_empty(),
+                "{}, {:?}:\n{}",
+                path.display(),
+                options,
+                formatted
+            );
+        }
+    }
+}
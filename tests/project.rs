@@ -0,0 +1,45 @@
+//! Checks the project in `tests/project`, whose manifest turns on every
+//! strict check and which does not pass them.
+
+use iv::project::Project;
+use std::path::{Path, PathBuf};
+
+fn project() -> Project {
+    Project::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/project")).unwrap()
+}
+
+#[test]
+fn files_are_checked_with_the_manifest_options() {
+    let mut project = project();
+    let paths: Vec<_> = project.files.iter().map(|file| file.path.clone()).collect();
+    assert_eq!(
+        paths,
+        [PathBuf::from("src/main.iv"), PathBuf::from("src/util.iv")]
+    );
+    assert!(project.options.strict);
+    let report = project.check();
+    assert_eq!(
+        report.render(&project),
+        "src/util.iv:4:1: unchecked op `pred` is not allowed\n\
+         define [Nat] pred [Nat]: case { zero { zero }, suc { } }.\n\
+         ^\n"
+    );
+    project.options.strict = false;
+    assert!(project.check().is_ok());
+}
+
+#[test]
+fn the_entry_op_is_checked_for_underflow() {
+    let mut project = project();
+    project.options.strict = false;
+    project.options.entry_op = "twice".to_owned();
+    assert!(project.check().render(&project).starts_with(
+        "src/main.iv:3:26: `dup` pops 1 value but only 0 are on the stack \
+         when `twice` runs from an empty stack\n"
+    ));
+    project.options.entry_op = "nope".to_owned();
+    assert!(project
+        .check()
+        .render(&project)
+        .starts_with("src/main.iv:1:1: the entry defines no `nope`\n"));
+}
@@ -0,0 +1,216 @@
+//! Runs programs through both `Evaluator` and the reference evaluator and
+//! checks that they agree: the same stack when both finish, the same error
+//! code when both fail. The programs are every op of every fixture, on an
+//! empty stack and on a few `Int`s, and generated sentences over a small
+//! module. A run hitting a limit of either evaluator tells nothing and is
+//! left out. Run with `cargo test --features reference --test reference`.
+
+use iv::desugar::desugar;
+use iv::diagnostics::kind::DiagnosticKind;
+use iv::evaluation::display::{display_stack, DisplayOptions};
+use iv::evaluation::evaluator::Evaluator;
+use iv::evaluation::reference::{self, Reference};
+use iv::evaluation::types::{EvaluatorError, ExecLimits, Value};
+use iv::syntax::ast::Module;
+use iv::syntax::parse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+const FIXTURES: [&str; 7] = [
+    "examples",
+    "tests/annotate",
+    "tests/cases",
+    "tests/codegen",
+    "tests/coverage",
+    "tests/project/src",
+    "tests/testing",
+];
+
+/// How a run ended, `None` if it hit a limit
+fn outcome(result: Result<(), EvaluatorError>, stack: &[Value]) -> Option<String> {
+    let options = DisplayOptions {
+        max_depth: usize::MAX,
+        max_nodes: usize::MAX,
+        show_quote_ops: true,
+    };
+    match result {
+        Ok(()) => Some(display_stack(stack, &options)),
+        Err(EvaluatorError::Runtime(err)) => match err.kind() {
+            DiagnosticKind::Limit => None,
+            _ => Some(err.code().to_owned()),
+        },
+        Err(err) => Some(format!("{:?}", err)),
+    }
+}
+
+/// Checks that both evaluators run the op on the stack the same, false if
+/// a limit left it open
+fn compare(module: &Module, op: &str, stack: &[Value], name: &str) -> bool {
+    let expected = {
+        let mut reference = Reference::new(module);
+        reference.stack = stack.to_vec();
+        let result = reference.eval_op_def(op);
+        match outcome(result, &reference.stack) {
+            Some(expected) => expected,
+            None => return false,
+        }
+    };
+    for tail_calls in [true, false] {
+        let mut evaluator = Evaluator::new(module)
+            .with_tail_calls(tail_calls)
+            .with_limits(ExecLimits {
+                max_steps: Some(reference::MAX_STEPS),
+                max_call_depth: Some(reference::MAX_DEPTH),
+                ..ExecLimits::default()
+            });
+        evaluator.stack = stack.to_vec();
+        let result = evaluator.eval_op_def(op);
+        let Some(found) = outcome(result, &evaluator.stack) else {
+            return false;
+        };
+        assert_eq!(
+            found, expected,
+            "{}: `{}` on {:?}, tail calls {}",
+            name, op, stack, tail_calls
+        );
+    }
+    true
+}
+
+fn stacks() -> [Vec<Value>; 2] {
+    [vec![], (0..3).rev().map(Value::Int).collect()]
+}
+
+fn fixture_paths() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths: Vec<_> = FIXTURES
+        .iter()
+        .flat_map(|dir| fs::read_dir(root.join(dir)).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "iv"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Runs the test on a thread whose stack takes the deepest recursion the
+/// reference evaluator allows
+fn deep(test: impl FnOnce() + Send + 'static) {
+    thread::Builder::new()
+        .stack_size(1 << 30)
+        .spawn(test)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn fixtures_run_the_same() {
+    deep(fixtures);
+}
+
+fn fixtures() {
+    let mut compared = 0;
+    for path in fixture_paths() {
+        let source = fs::read_to_string(&path).unwrap();
+        let Some(module) = parse(&source).ok().and_then(|m| desugar(m).ok()) else {
+            continue;
+        };
+        let name = path.display().to_string();
+        for (op, _) in module.op_defs_in_source_order() {
+            for stack in stacks() {
+                compared += compare(&module, op, &stack, &name) as usize;
+            }
+        }
+    }
+    assert!(compared > 300, "only {} runs compared", compared);
+}
+
+const GENERATED: &str = "
+data Nat: zero, [Nat] suc.
+data Bool: false, true.
+data Pair a b: [a, b] pair.
+const two [Nat]: zero suc suc.
+define [Nat] double [Nat]: case { zero { zero }, suc { double suc suc } }.
+define [Nat] count [Nat]: (case { zero { zero }, suc { br-1 exec-1-1 suc } }) fix-1-1.
+define [Int] nothing []: pop.
+";
+
+const UNITS: [&str; 32] = [
+    "dup",
+    "pop",
+    "br-1",
+    "br-2",
+    "dg-1",
+    "dg-2",
+    "0",
+    "1",
+    "2",
+    "-1",
+    "pick",
+    "roll",
+    "quote",
+    "zero",
+    "suc",
+    "pair",
+    "true",
+    "two",
+    "double",
+    "count",
+    "nothing",
+    "assert",
+    "(dup)",
+    "(suc)",
+    "(pop zero)",
+    "exec-1-1",
+    "comp-1-1-1-1",
+    "keep-1-1",
+    "dip-1-1-1",
+    "case { zero { true }, suc { pop false } }",
+    "case { pair { br-1 } }",
+    "case2 { zero zero { zero }, suc _ { pop }, _ _ { pop pop two } }",
+];
+
+/// Pseudorandom sentences of the units, the same every run
+fn sentences(n: usize) -> Vec<String> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+    (0..n)
+        .map(|_| {
+            let len = 1 + next(8);
+            let units: Vec<_> = (0..len).map(|_| UNITS[next(UNITS.len())]).collect();
+            units.join(" ")
+        })
+        .collect()
+}
+
+#[test]
+fn generated_sentences_run_the_same() {
+    deep(generated);
+}
+
+fn generated() {
+    let mut compared = 0;
+    let mut finished = 0;
+    for ops in sentences(2000) {
+        let source = format!("{}define [] probe []: {}.", GENERATED, ops);
+        let module = desugar(parse(&source).unwrap()).unwrap();
+        for stack in stacks() {
+            if compare(&module, "probe", &stack, &ops) {
+                compared += 1;
+                let mut reference = Reference::new(&module);
+                reference.stack = stack.clone();
+                finished += reference.eval_op_def("probe").is_ok() as usize;
+            }
+        }
+    }
+    assert!(compared > 3000, "only {} runs compared", compared);
+    // not only errors are compared
+    assert!(finished > 300, "only {} runs finished", finished);
+}
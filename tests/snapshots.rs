@@ -0,0 +1,213 @@
+//! Golden file tests. Every `tests/cases/*.iv` fixture is analyzed, and the
+//! outcome is compared against the `.snap` file next to it. A `// EXPECT: types` or `// EXPECT: diagnostics` line in the
+//! fixture chooses between snapshotting the per-op inferred types and the
+//! rendered diagnostics. Run with `IV_BLESS=1` to write the snapshots
+//! instead.
+
+use iv::analyze::{analyze, AnalysisResult, AnalyzeOptions, Phase};
+use iv::diagnostics::Diagnostic;
+use iv::syntax::ast::Module;
+use iv::typing::report::{OpOutcome, TypecheckReport};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+enum Mode {
+    Types,
+    Diagnostics,
+}
+
+fn mode(source: &str) -> Mode {
+    let marker = source
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("// EXPECT:"))
+        .expect("fixture has no `// EXPECT:` marker");
+    match marker.trim() {
+        "types" => Mode::Types,
+        "diagnostics" => Mode::Diagnostics,
+        other => panic!("unknown EXPECT mode `{}`", other),
+    }
+}
+
+fn cases() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let mut cases: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "iv"))
+        .collect();
+    cases.sort();
+    cases
+}
+
+/// The typechecked module, the definitions that parsed
+fn check(source: &str) -> (Module, TypecheckReport, AnalysisResult) {
+    let opts = AnalyzeOptions {
+        keep_ast: true,
+        ..AnalyzeOptions::default()
+    };
+    let mut result = analyze(source, opts);
+    let module = result.module.take().expect("fixture does not desugar");
+    let report = result.report.take().unwrap();
+    (module, report, result)
+}
+
+fn render(
+    source: &str,
+    (module, report, result): &(Module, TypecheckReport, AnalysisResult),
+) -> String {
+    let diagnostic = |err| Diagnostic::from_inference_error(source, module, err).render(source);
+    let mut out = String::new();
+    match mode(source) {
+        Mode::Types => {
+            for (phase, parse_error) in result.diagnostics.iter() {
+                if *phase == Phase::Parse {
+                    out.push_str(&parse_error.render(source));
+                }
+            }
+            for err in report.errors.iter() {
+                out.push_str(&diagnostic(err));
+            }
+            for op in report.ops.iter() {
+                let outcome = match &op.outcome {
+                    OpOutcome::Inferred(inferred) => inferred.canonical().to_string(),
+                    OpOutcome::Failed(err) => format!("error: {}", err.message()),
+                    OpOutcome::Unchecked => "unchecked".to_owned(),
+                    OpOutcome::Cancelled => "cancelled".to_owned(),
+                    OpOutcome::TimedOut => "timed out".to_owned(),
+                    OpOutcome::Skipped(_) => "skipped".to_owned(),
+                    OpOutcome::Declared => "declared".to_owned(),
+                    OpOutcome::ExpectedError(err) => format!("expected error: {}", err.message()),
+                };
+                out.push_str(&format!("{}: {}\n", op.name, outcome));
+            }
+        }
+        Mode::Diagnostics => {
+            for (_, diagnostic) in result.diagnostics.iter() {
+                out.push_str(&diagnostic.render(source));
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn snapshots() {
+    let bless = env::var_os("IV_BLESS").is_some();
+    let mut failures = vec![];
+    for case in cases() {
+        let source = fs::read_to_string(&case).unwrap();
+        let actual = render(&source, &check(&source));
+        let snap_path = case.with_extension("snap");
+        if bless {
+            fs::write(&snap_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&snap_path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!(
+                "{}:\n--- expected\n{}--- actual\n{}",
+                case.display(),
+                expected,
+                actual
+            )),
+            Err(_) => failures.push(format!("{}: missing snapshot", case.display())),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "snapshot mismatches (rerun with IV_BLESS=1 to accept):\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn deterministic() {
+    for case in cases() {
+        let source = fs::read_to_string(&case).unwrap();
+        let first = render(&source, &check(&source));
+        for _ in 0..4 {
+            assert_eq!(
+                first,
+                render(&source, &check(&source)),
+                "{}",
+                case.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn every_error_variant_is_covered() {
+    // OpPrePostLenNeq is not produced by the checker yet, Interrupted never
+    // leaves it
+    let expected: HashSet<_> = [
+        "AnnInfConflict",
+        "EmptyBodyCannotSatisfy",
+        "UnificationError",
+        "UnknownOp",
+        "UnknownConstructor",
+        "DuplicateConstructor",
+        "NotAllConstructorsCovered",
+        "CaseArmMismatch",
+        "ArmsDisagreeOnStackDepth",
+        "ScrutineeMismatch",
+        "CannotMatchBuiltin",
+        "CannotMatchOnQuote",
+        "MissingCombinations",
+        "OccursCheck",
+        "TypeTooLarge",
+        "InfiniteFix",
+        "IndexNotLiteral",
+        "ListMGULengthDifferent",
+        "QuoteNeedsInputs",
+        "NotEnoughValues",
+        "ImpureConstant",
+        "DynInAnnotation",
+        "CheckpointMismatch",
+        "ExpectTypeMismatch",
+        "ExpectErrorMismatch",
+        "OverloadNotMarked",
+        "OverloadsIndistinct",
+        "AmbiguousOverload",
+        "NoOverloadFits",
+        "OverloadNeedsContext",
+    ]
+    .into_iter()
+    .collect();
+    let mut covered = HashSet::new();
+    for case in cases() {
+        let source = fs::read_to_string(&case).unwrap();
+        for err in check(&source).1.all_errors() {
+            covered.insert(err.code().to_owned());
+        }
+    }
+    let missing: Vec<_> = expected.iter().filter(|v| !covered.contains(**v)).collect();
+    assert!(missing.is_empty(), "no fixture produces {:?}", missing);
+}
+
+#[test]
+fn suggested_fixes_remove_their_errors() {
+    for case in cases() {
+        let source = fs::read_to_string(&case).unwrap();
+        let (module, report, _) = check(&source);
+        for err in report.all_errors() {
+            let code = err.code();
+            let count =
+                |report: &TypecheckReport| report.all_errors().filter(|e| e.code() == code).count();
+            for fix in err.suggestions(&source, &module) {
+                let fixed = fix.apply(&source);
+                let (_, fixed_report, _) = check(&fixed);
+                assert!(
+                    count(&fixed_report) < count(&report),
+                    "{}: `{}` does not fix {}:\n{}",
+                    case.display(),
+                    fix.title,
+                    err.message(),
+                    fixed
+                );
+            }
+        }
+    }
+}
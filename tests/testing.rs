@@ -0,0 +1,87 @@
+//! Runs the `test-*` ops of `tests/testing/suite.iv`, which has passing,
+//! failing, erroring and mistyped tests.
+
+use iv::desugar::desugar;
+use iv::diagnostics::kind::DiagnosticKind;
+use iv::evaluation::evaluator::Evaluator;
+use iv::evaluation::types::ExecLimits;
+use iv::syntax::ast::Module;
+use iv::syntax::parse;
+use iv::testing::{discover, run_tests, TestOutcome};
+use iv::typing::inference::Inference;
+use std::fs;
+use std::path::Path;
+
+fn suite() -> (String, Module) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/testing/suite.iv");
+    let source = fs::read_to_string(path).unwrap();
+    let module = desugar(parse(&source).unwrap()).unwrap();
+    (source, module)
+}
+
+#[test]
+fn outcomes() {
+    let (source, module) = suite();
+    assert!(Inference::new(&module).typecheck().is_ok());
+    let mut evaluator = Evaluator::new(&module).with_limits(ExecLimits {
+        max_call_depth: Some(64),
+        ..ExecLimits::default()
+    });
+    let report = run_tests(&module, &mut evaluator, "");
+    let outcomes: Vec<_> = report
+        .results
+        .iter()
+        .map(|result| {
+            let outcome = match &result.outcome {
+                TestOutcome::Passed => "passed".to_owned(),
+                TestOutcome::Failed(top) => format!("failed with {}", top.as_ref().unwrap()),
+                TestOutcome::Errored(err) => {
+                    assert_eq!(err.kind(), DiagnosticKind::Limit);
+                    assert!(err.message().contains("call depth"));
+                    let span = err.primary_span();
+                    format!("errored at `{}`", &source[span.start..span.end])
+                }
+                TestOutcome::NotATest(ann) => format!("not a test, {}", ann),
+            };
+            (result.name.as_str(), outcome)
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("test-zero-is-zero", "passed".to_owned()),
+            ("test-one-is-zero", "failed with false".to_owned()),
+            ("test-pushes-and-pops", "passed".to_owned()),
+            ("test-runs-forever", "errored at `forever`".to_owned()),
+            ("test-takes-a-value", "not a test, [Nat][Bool]".to_owned()),
+        ]
+    );
+    assert_eq!((report.passed(), report.failed()), (2, 3));
+    assert!(!report.is_ok());
+    assert!(evaluator.stack.is_empty());
+
+    let rendered = report.render(&source);
+    assert!(
+        rendered.starts_with("test test-zero-is-zero ... ok\ntest test-one-is-zero ... FAILED\n")
+    );
+    assert!(rendered.contains("\n8:11: `test-one-is-zero` left `false` instead of `true`\n"));
+    assert!(rendered.contains(
+        "\n5:23: `test-runs-forever` hit a runtime error: exceeded the limit on call depth\n"
+    ));
+    assert!(rendered.ends_with("\n2 passed, 3 failed\n"));
+}
+
+#[test]
+fn filtering() {
+    let (_, module) = suite();
+    let names: Vec<_> = discover(&module, "-p")
+        .into_iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(names, ["test-pushes-and-pops"]);
+    let mut evaluator = Evaluator::new(&module);
+    let report = run_tests(&module, &mut evaluator, "zero-is");
+    assert_eq!(report.results.len(), 1);
+    assert!(report.is_ok());
+    assert!(report.render("").ends_with("\n1 passed, 0 failed\n"));
+}